@@ -0,0 +1,13 @@
+//! Generates Rust bindings for `proto/crdt_operation.proto` into
+//! `OUT_DIR`, pulled into the crate by `src/distributed/wire.rs`.
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/crdt_operation.proto");
+
+    protobuf_codegen::Codegen::new()
+        .pure()
+        .includes(["proto"])
+        .input("proto/crdt_operation.proto")
+        .cargo_out_dir("proto")
+        .run_from_script();
+}
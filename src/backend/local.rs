@@ -0,0 +1,148 @@
+//! Local-directory [`StorageBackend`] - chunks as plain files, addressed
+//! by their own `chunk_id` instead of a transport-assigned id. Meant for
+//! exercising the rest of the stack (and tests) without a Telegram
+//! account; see the `backend` module docs for what isn't wired to it yet.
+
+use crate::backend::StorageBackend;
+use crate::error::{Error, Result};
+
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+/// Stores each chunk as a file named after its locator inside `root`.
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    /// Opens `root` as a chunk store, creating it if it doesn't exist yet.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path_for(&self, locator: &str) -> Result<PathBuf> {
+        if locator.is_empty() || locator.contains('/') || locator.contains("..") {
+            return Err(Error::Internal(format!("invalid local chunk locator: {}", locator)));
+        }
+        Ok(self.root.join(locator))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    async fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn disconnect(&self) {}
+
+    async fn is_authorized(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn put_chunk(&self, chunk_id: &str, data: &[u8]) -> Result<String> {
+        let path = self.path_for(chunk_id)?;
+        std::fs::write(&path, data)?;
+        Ok(chunk_id.to_string())
+    }
+
+    async fn get_chunk(&self, locator: &str) -> Result<Vec<u8>> {
+        let path = self.path_for(locator)?;
+        std::fs::read(&path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Error::ChunkNotFound(locator.to_string())
+            } else {
+                Error::Io(e)
+            }
+        })
+    }
+
+    async fn delete_chunk(&self, locator: &str) -> Result<()> {
+        let path = self.path_for(locator)?;
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let mut locators = Vec::new();
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                if let Some(name) = entry.file_name().to_str() {
+                    locators.push(name.to_string());
+                }
+            }
+        }
+        Ok(locators)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("tgcryptfs-local-backend-test-{}-{}", std::process::id(), tag));
+        dir
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_put_then_get_round_trips() {
+        let dir = temp_dir("round-trip");
+        let backend = LocalBackend::new(&dir).unwrap();
+
+        block_on(async {
+            let locator = backend.put_chunk("abc123", b"hello world").await.unwrap();
+            let data = backend.get_chunk(&locator).await.unwrap();
+            assert_eq!(data, b"hello world");
+        });
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_get_missing_chunk_errors() {
+        let dir = temp_dir("missing");
+        let backend = LocalBackend::new(&dir).unwrap();
+
+        block_on(async {
+            let err = backend.get_chunk("does-not-exist").await.unwrap_err();
+            assert!(matches!(err, Error::ChunkNotFound(_)));
+        });
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_delete_then_list_is_empty() {
+        let dir = temp_dir("delete-list");
+        let backend = LocalBackend::new(&dir).unwrap();
+
+        block_on(async {
+            backend.put_chunk("chunk-a", b"data").await.unwrap();
+            backend.delete_chunk("chunk-a").await.unwrap();
+            assert!(backend.list().await.unwrap().is_empty());
+        });
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_path_traversal_locator_is_rejected() {
+        let dir = temp_dir("traversal");
+        let backend = LocalBackend::new(&dir).unwrap();
+
+        block_on(async {
+            let err = backend.get_chunk("../escape").await.unwrap_err();
+            assert!(matches!(err, Error::Internal(_)));
+        });
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
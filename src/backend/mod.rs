@@ -0,0 +1,62 @@
+//! Pluggable storage transports.
+//!
+//! [`StorageBackend`] is the seam between chunk storage and the transport
+//! that actually holds the bytes. [`TelegramStorageBackend`] adapts the
+//! existing [`TelegramBackend`] to it; [`LocalBackend`] stores chunks as
+//! plain files on disk so the filesystem, cache, and RAID layers can be
+//! exercised (and CI can run) without a Telegram account.
+//!
+//! Chunk references persisted to disk (`ChunkPayload::Remote`) still
+//! address Telegram messages by their `i32` message id, and the RAID pool
+//! (`raid::pool::AccountPool`) builds directly on that addressing for its
+//! lock-free stripe bookkeeping. Generalizing those to an opaque locator is
+//! its own follow-up; until then `TgCryptFs` and the RAID layer keep
+//! talking to `TelegramBackend` directly, and [`LocalBackend`] is usable
+//! standalone (e.g. via tests or future non-RAID tooling) but not yet
+//! wired into a live mount.
+
+mod local;
+mod telegram_adapter;
+
+pub use local::LocalBackend;
+pub use telegram_adapter::TelegramStorageBackend;
+
+use crate::error::Result;
+
+use async_trait::async_trait;
+
+/// A transport that can hold encrypted chunks, independent of Telegram.
+///
+/// Chunks are addressed by the caller-supplied `chunk_id` (a content hash)
+/// on write and by an opaque `locator` string the backend returns from
+/// [`StorageBackend::put_chunk`] on read/delete - for Telegram this is the
+/// message id rendered as a string, for a local directory it's the
+/// `chunk_id` itself. Implementations must be `Send + Sync` so a single
+/// backend can be shared across the async upload/download tasks that
+/// `raid::pool` and the cache write-back worker already spawn.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Establish the transport's connection (log in, open a directory, ...).
+    async fn connect(&self) -> Result<()>;
+
+    /// Tear the connection down. Best-effort; backends that have nothing
+    /// to close (e.g. [`LocalBackend`]) no-op.
+    async fn disconnect(&self);
+
+    /// Whether the backend is ready to serve `put_chunk`/`get_chunk`
+    /// without further setup (e.g. a completed Telegram login).
+    async fn is_authorized(&self) -> Result<bool>;
+
+    /// Store `data` under `chunk_id`, returning the locator later calls
+    /// must pass to [`StorageBackend::get_chunk`]/[`StorageBackend::delete_chunk`].
+    async fn put_chunk(&self, chunk_id: &str, data: &[u8]) -> Result<String>;
+
+    /// Retrieve the bytes previously stored at `locator`.
+    async fn get_chunk(&self, locator: &str) -> Result<Vec<u8>>;
+
+    /// Remove the chunk stored at `locator`.
+    async fn delete_chunk(&self, locator: &str) -> Result<()>;
+
+    /// List every locator currently stored, for reconciliation/GC passes.
+    async fn list(&self) -> Result<Vec<String>>;
+}
@@ -0,0 +1,61 @@
+//! [`StorageBackend`] adapter over the existing [`TelegramBackend`].
+
+use crate::backend::StorageBackend;
+use crate::error::{Error, Result};
+use crate::telegram::TelegramBackend;
+
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Wraps a [`TelegramBackend`], rendering its `i32` message ids as the
+/// opaque locator strings [`StorageBackend`] callers expect.
+pub struct TelegramStorageBackend {
+    inner: Arc<TelegramBackend>,
+}
+
+impl TelegramStorageBackend {
+    pub fn new(inner: Arc<TelegramBackend>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for TelegramStorageBackend {
+    async fn connect(&self) -> Result<()> {
+        self.inner.connect().await
+    }
+
+    async fn disconnect(&self) {
+        self.inner.disconnect().await
+    }
+
+    async fn is_authorized(&self) -> Result<bool> {
+        self.inner.is_authorized().await
+    }
+
+    async fn put_chunk(&self, chunk_id: &str, data: &[u8]) -> Result<String> {
+        let message_id = self.inner.upload_chunk(chunk_id, data).await?;
+        Ok(message_id.to_string())
+    }
+
+    async fn get_chunk(&self, locator: &str) -> Result<Vec<u8>> {
+        let message_id = parse_locator(locator)?;
+        self.inner.download_chunk(message_id).await
+    }
+
+    async fn delete_chunk(&self, locator: &str) -> Result<()> {
+        let message_id = parse_locator(locator)?;
+        self.inner.delete_message(message_id).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let messages = self.inner.list_chunks().await?;
+        Ok(messages.into_iter().map(|m| m.id.to_string()).collect())
+    }
+}
+
+fn parse_locator(locator: &str) -> Result<i32> {
+    locator
+        .parse()
+        .map_err(|_| Error::Internal(format!("invalid Telegram message locator: {}", locator)))
+}
@@ -1,92 +1,215 @@
 //! LRU (Least Recently Used) tracking
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 
-/// Simple LRU tracker using lazy cleanup
+/// A single cache entry, stored in a slab and linked into recency order by
+/// index rather than by key, so touching an entry is a pointer relink
+/// instead of a scan or a fresh allocation.
+struct Node<K> {
+    key: K,
+    weight: u64,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Bounded LRU tracker backed by an intrusive doubly linked list over a
+/// slab. `touch`/`insert`/`remove` are O(1): each just relinks a node in
+/// place rather than appending a new entry and leaving the old one to be
+/// skipped later, so there's no stale-entry buildup and nothing to
+/// periodically compact.
+///
+/// Entries may carry a weight (see [`Self::insert_weighted`]), letting this
+/// double as a size-aware cache (e.g. bytes of cached pages) rather than
+/// just a count of items.
 pub struct LruCache<K: Clone + Eq + std::hash::Hash> {
-    /// Order of access with generation (front = oldest). Entry is (key, generation at insertion)
-    order: VecDeque<(K, usize)>,
-    /// Position lookup: key -> current generation
-    positions: HashMap<K, usize>,
-    /// Generation counter for tracking freshness
-    generation: usize,
+    /// Node storage; freed slots are tracked in `free_slots` and reused
+    /// before the vec grows.
+    nodes: Vec<Option<Node<K>>>,
+    /// Indices into `nodes` freed by `remove`/`pop_oldest`, available for
+    /// reuse by the next insert.
+    free_slots: Vec<usize>,
+    /// key -> its node's slot in `nodes`
+    index: HashMap<K, usize>,
+    /// Most-recently-used end of the list
+    head: Option<usize>,
+    /// Least-recently-used end of the list
+    tail: Option<usize>,
+    /// Sum of every live node's weight
+    total_weight: u64,
+    /// Eviction threshold for `total_weight`, or `None` for an unbounded
+    /// cache (the caller drives eviction itself via `pop_oldest`).
+    max_weight: Option<u64>,
 }
 
 impl<K: Clone + Eq + std::hash::Hash> LruCache<K> {
-    /// Create a new LRU cache
+    /// Create a new, unbounded LRU tracker. Eviction is entirely up to the
+    /// caller (via `pop_oldest`) - nothing is dropped automatically.
     pub fn new() -> Self {
         LruCache {
-            order: VecDeque::new(),
-            positions: HashMap::new(),
-            generation: 0,
+            nodes: Vec::new(),
+            free_slots: Vec::new(),
+            index: HashMap::new(),
+            head: None,
+            tail: None,
+            total_weight: 0,
+            max_weight: None,
         }
     }
 
-    /// Insert a new item (as most recently used)
-    pub fn insert(&mut self, key: K) {
-        self.generation += 1;
-        self.positions.insert(key.clone(), self.generation);
-        self.order.push_back((key, self.generation));
+    /// Create a bounded cache that evicts the least recently used entries
+    /// once the sum of weights exceeds `max_weight`. Plain `insert` gives
+    /// every entry a weight of 1, so passing an item-count limit here
+    /// behaves as a capacity in the usual sense; `insert_weighted` lets a
+    /// caller bound by a real cost instead (e.g. bytes).
+    pub fn with_capacity(max_weight: u64) -> Self {
+        LruCache {
+            max_weight: Some(max_weight),
+            ..Self::new()
+        }
     }
 
-    /// Touch an item (mark as recently used)
+    /// Insert (or refresh) `key` as most recently used, with a weight of 1.
+    /// See [`Self::insert_weighted`] for the bounded-eviction behavior.
+    pub fn insert(&mut self, key: K) -> Vec<K> {
+        self.insert_weighted(key, 1)
+    }
+
+    /// Insert (or refresh) `key` as most recently used with an explicit
+    /// `weight`. If this cache is bounded (see [`Self::with_capacity`]),
+    /// entries are evicted from the least-recently-used end until the
+    /// total weight is back within budget; the evicted keys are returned
+    /// so the caller can release whatever they back (e.g. flush pages).
+    pub fn insert_weighted(&mut self, key: K, weight: u64) -> Vec<K> {
+        if let Some(&idx) = self.index.get(&key) {
+            self.unlink(idx);
+            self.total_weight -= self.nodes[idx].as_ref().unwrap().weight;
+            self.nodes[idx].as_mut().unwrap().weight = weight;
+            self.push_front(idx);
+        } else {
+            let idx = self.alloc_node(Node {
+                key: key.clone(),
+                weight,
+                prev: None,
+                next: None,
+            });
+            self.index.insert(key, idx);
+            self.push_front(idx);
+        }
+        self.total_weight += weight;
+
+        let mut evicted = Vec::new();
+        if let Some(max_weight) = self.max_weight {
+            while self.total_weight > max_weight {
+                match self.pop_oldest() {
+                    Some(key) => evicted.push(key),
+                    None => break,
+                }
+            }
+        }
+        evicted
+    }
+
+    /// Mark `key` as most recently used, in place - O(1), no new entry is
+    /// allocated. A no-op if `key` isn't tracked.
     pub fn touch(&mut self, key: &K) {
-        if self.positions.contains_key(key) {
-            self.generation += 1;
-            self.positions.insert(key.clone(), self.generation);
-            self.order.push_back((key.clone(), self.generation));
+        if let Some(&idx) = self.index.get(key) {
+            self.unlink(idx);
+            self.push_front(idx);
         }
     }
 
     /// Remove an item
     pub fn remove(&mut self, key: &K) {
-        self.positions.remove(key);
-        // Lazy removal - stale entries will be skipped when popping
+        if let Some(idx) = self.index.remove(key) {
+            self.unlink(idx);
+            let node = self.nodes[idx].take().unwrap();
+            self.total_weight -= node.weight;
+            self.free_slots.push(idx);
+        }
     }
 
-    /// Pop the oldest item
+    /// Pop the least recently used item
     pub fn pop_oldest(&mut self) -> Option<K> {
-        while let Some((key, entry_gen)) = self.order.pop_front() {
-            // Check if this entry is still valid (generation matches current)
-            if let Some(&current_gen) = self.positions.get(&key) {
-                if entry_gen == current_gen {
-                    // This is the current entry for this key
-                    self.positions.remove(&key);
-                    return Some(key);
-                }
-                // Stale entry - key was touched/reinserted, skip it
-            }
-            // Entry was removed or superseded, continue to next
-        }
-        None
+        let idx = self.tail?;
+        self.unlink(idx);
+        let node = self.nodes[idx].take().unwrap();
+        self.index.remove(&node.key);
+        self.total_weight -= node.weight;
+        self.free_slots.push(idx);
+        Some(node.key)
     }
 
     /// Check if empty
     pub fn is_empty(&self) -> bool {
-        self.positions.is_empty()
+        self.index.is_empty()
     }
 
     /// Get count of tracked items
     pub fn len(&self) -> usize {
-        self.positions.len()
+        self.index.len()
+    }
+
+    /// Sum of every tracked entry's weight (item count, under plain
+    /// `insert`; total cost, under `insert_weighted`).
+    pub fn total_weight(&self) -> u64 {
+        self.total_weight
     }
 
     /// Clear all items
     pub fn clear(&mut self) {
-        self.order.clear();
-        self.positions.clear();
-        self.generation = 0;
+        self.nodes.clear();
+        self.free_slots.clear();
+        self.index.clear();
+        self.head = None;
+        self.tail = None;
+        self.total_weight = 0;
+    }
+
+    /// Reuse a freed slot for `node` if one is available, otherwise grow
+    /// the slab. Returns the slot `node` now lives in.
+    fn alloc_node(&mut self, node: Node<K>) -> usize {
+        if let Some(idx) = self.free_slots.pop() {
+            self.nodes[idx] = Some(node);
+            idx
+        } else {
+            self.nodes.push(Some(node));
+            self.nodes.len() - 1
+        }
     }
 
-    /// Compact the internal structures (remove stale entries)
-    pub fn compact(&mut self) {
-        // Rebuild order from positions
-        let mut items: Vec<_> = self.positions.iter().map(|(k, &g)| (k.clone(), g)).collect();
-        items.sort_by_key(|(_, g)| *g);
+    /// Splice the node at `idx` out of the list, patching its neighbors'
+    /// links (and `head`/`tail`) in place.
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = {
+            let node = self.nodes[idx].as_ref().unwrap();
+            (node.prev, node.next)
+        };
+        match prev {
+            Some(p) => self.nodes[p].as_mut().unwrap().next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(n) => self.nodes[n].as_mut().unwrap().prev = prev,
+            None => self.tail = prev,
+        }
+        let node = self.nodes[idx].as_mut().unwrap();
+        node.prev = None;
+        node.next = None;
+    }
 
-        self.order.clear();
-        for (key, gen) in items {
-            self.order.push_back((key, gen));
+    /// Splice the (already-unlinked) node at `idx` in at the
+    /// most-recently-used end.
+    fn push_front(&mut self, idx: usize) {
+        match self.head {
+            Some(h) => {
+                self.nodes[h].as_mut().unwrap().prev = Some(idx);
+                self.nodes[idx].as_mut().unwrap().next = Some(h);
+                self.head = Some(idx);
+            }
+            None => {
+                self.head = Some(idx);
+                self.tail = Some(idx);
+            }
         }
     }
 }
@@ -159,4 +282,67 @@ mod tests {
         assert!(lru.is_empty());
         assert_eq!(lru.pop_oldest(), None);
     }
+
+    #[test]
+    fn test_reinsert_refreshes_weight_and_recency() {
+        let mut lru = LruCache::new();
+
+        lru.insert("a");
+        lru.insert("b");
+        // Re-inserting "a" should make it most recent again, not duplicate it.
+        lru.insert("a");
+
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.pop_oldest(), Some("b"));
+        assert_eq!(lru.pop_oldest(), Some("a"));
+    }
+
+    #[test]
+    fn test_with_capacity_evicts_by_item_count() {
+        let mut lru = LruCache::with_capacity(2);
+
+        assert!(lru.insert("a").is_empty());
+        assert!(lru.insert("b").is_empty());
+        let evicted = lru.insert("c");
+
+        assert_eq!(evicted, vec!["a"]);
+        assert_eq!(lru.len(), 2);
+        assert!(!lru.is_empty());
+    }
+
+    #[test]
+    fn test_insert_weighted_evicts_until_within_budget() {
+        let mut lru = LruCache::with_capacity(10);
+
+        assert!(lru.insert_weighted("a", 4).is_empty());
+        assert!(lru.insert_weighted("b", 4).is_empty());
+        // Pushes total weight to 14; "a" (oldest) must go to get back to 10.
+        let evicted = lru.insert_weighted("c", 6);
+
+        assert_eq!(evicted, vec!["a"]);
+        assert_eq!(lru.total_weight(), 10);
+        assert_eq!(lru.len(), 2);
+    }
+
+    #[test]
+    fn test_touch_and_remove_are_o1_relinks_not_new_entries() {
+        let mut lru = LruCache::with_capacity(3);
+
+        lru.insert("a");
+        lru.insert("b");
+        lru.insert("c");
+        lru.touch(&"a");
+        lru.touch(&"a");
+        lru.touch(&"b");
+        lru.remove(&"b");
+
+        // No stale duplicate entries should have accumulated from the
+        // repeated touches. "c" was never touched after insertion, so it's
+        // now the least recently used of what's left; "a" was touched most
+        // recently and comes out last.
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.pop_oldest(), Some("c"));
+        assert_eq!(lru.pop_oldest(), Some("a"));
+        assert_eq!(lru.pop_oldest(), None);
+    }
 }
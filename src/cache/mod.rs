@@ -7,15 +7,53 @@ mod lru;
 
 pub use lru::LruCache;
 
-use crate::config::CacheConfig;
+use crate::config::{CacheConfig, WriteBackConfig};
 use crate::error::{Error, Result};
-use parking_lot::RwLock;
-use std::collections::{HashMap, VecDeque};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
-use tracing::{debug, info};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use tracing::{debug, error, info};
+
+/// Durably stores a dirty chunk staged by [`ChunkCache::put_dirty`].
+/// Implemented by the Telegram-backed upload pipeline; tests substitute a
+/// fixture so the flush path can be exercised without a live backend.
+pub trait ChunkUploader: Send + Sync {
+    /// Upload `data` under `chunk_id`. Failures are logged and the chunk
+    /// stays dirty for the next flush attempt - callers don't need their
+    /// own retry loop around this.
+    fn upload(&self, chunk_id: &str, data: &[u8]) -> Result<()>;
+}
+
+/// Rebuilds a chunk's plaintext when [`ChunkCache::get`] finds its cached
+/// copy corrupt. Implemented by the erasure-coding pool (`raid::pool`) so
+/// a bad local copy is repaired from surviving shards instead of falling
+/// back to a full remote re-download; tests substitute a fixture.
+pub trait ChunkReconstructor: Send + Sync {
+    /// Reconstruct `chunk_id` from redundant storage. `Ok(None)` means
+    /// the reconstructor has no way to rebuild this chunk (e.g. it's
+    /// outside the erasure-coded pool) and the caller should fall back
+    /// to treating this as an ordinary cache miss.
+    fn reconstruct(&self, chunk_id: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// Confirms a cached chunk's plaintext matches its content-addressed
+/// `chunk_id`. Plugged in by [`crate::crypto::KeyManager`] once chunk ids
+/// are keyed (see `KeyManager::content_chunk_id`) - a bare
+/// `blake3::hash(data)` no longer equals a keyed id, so [`ChunkCache`]
+/// needs the key to tell genuine corruption from an id it just can't
+/// re-derive on its own. Falls back to an unkeyed BLAKE3 comparison (the
+/// pre-keying behavior) when no verifier is registered.
+pub trait ChunkIdVerifier: Send + Sync {
+    /// Whether `data` is really the chunk `chunk_id` claims to be.
+    fn verify(&self, chunk_id: &str, data: &[u8]) -> bool;
+}
 
 /// Disk-based chunk cache with LRU eviction
 pub struct ChunkCache {
@@ -33,6 +71,27 @@ pub struct ChunkCache {
     prefetch_queue: RwLock<VecDeque<String>>,
     /// Prefetch enabled
     prefetch_enabled: bool,
+    /// Write-back staging settings
+    write_back: WriteBackConfig,
+    /// Chunk ids staged by [`Self::put_dirty`] that haven't been uploaded
+    /// yet
+    dirty: RwLock<HashSet<String>>,
+    /// Bytes currently staged but not yet confirmed durable
+    dirty_bytes: AtomicU64,
+    /// Upload sink for the background flush workers and [`Self::sync`];
+    /// `None` until [`Self::start_write_back`] is called
+    uploader: RwLock<Option<Arc<dyn ChunkUploader>>>,
+    /// Where [`Self::put_dirty`] sends chunk ids for the background flush
+    /// workers to pick up once the high water mark is crossed
+    flush_tx: RwLock<Option<Sender<String>>>,
+    /// Repairs a corrupt chunk detected by [`Self::get`] or [`Self::scrub`];
+    /// `None` until [`Self::set_reconstructor`] is called, in which case a
+    /// corrupt chunk is just evicted and reported as a miss
+    reconstructor: RwLock<Option<Arc<dyn ChunkReconstructor>>>,
+    /// Confirms a chunk's plaintext against its id; `None` until
+    /// [`Self::set_id_verifier`] is called, in which case
+    /// [`Self::digest_matches`] falls back to a bare unkeyed BLAKE3 check.
+    id_verifier: RwLock<Option<Arc<dyn ChunkIdVerifier>>>,
 }
 
 impl ChunkCache {
@@ -49,6 +108,13 @@ impl ChunkCache {
             sizes: RwLock::new(HashMap::new()),
             prefetch_queue: RwLock::new(VecDeque::new()),
             prefetch_enabled: config.prefetch_enabled,
+            write_back: config.write_back.clone(),
+            dirty: RwLock::new(HashSet::new()),
+            dirty_bytes: AtomicU64::new(0),
+            uploader: RwLock::new(None),
+            flush_tx: RwLock::new(None),
+            reconstructor: RwLock::new(None),
+            id_verifier: RwLock::new(None),
         };
 
         // Scan existing cache
@@ -98,7 +164,12 @@ impl ChunkCache {
         self.chunk_path(chunk_id).exists()
     }
 
-    /// Get a chunk from cache
+    /// Get a chunk from cache, verifying its content-addressed digest
+    /// before returning it. A mismatch (disk corruption, truncation)
+    /// evicts the bad copy and is reported as a miss - a reconstructor
+    /// registered via [`Self::set_reconstructor`] gets first chance to
+    /// heal it from redundant storage before the caller falls back to a
+    /// remote re-fetch.
     pub fn get(&self, chunk_id: &str) -> Result<Option<Vec<u8>>> {
         let path = self.chunk_path(chunk_id);
 
@@ -106,18 +177,106 @@ impl ChunkCache {
             return Ok(None);
         }
 
-        // Update LRU
-        self.lru.write().touch(&chunk_id.to_string());
-
         // Read file
         let mut file = File::open(&path)?;
         let mut data = Vec::new();
         file.read_to_end(&mut data)?;
 
+        if !self.digest_matches(chunk_id, &data) {
+            return self.handle_corruption(chunk_id);
+        }
+
+        // Update LRU
+        self.lru.write().touch(&chunk_id.to_string());
+
         debug!("Cache hit: {} ({} bytes)", chunk_id, data.len());
         Ok(Some(data))
     }
 
+    /// Whether `data` matches its content-addressed `chunk_id`, via the
+    /// registered [`ChunkIdVerifier`] if one's set (see
+    /// [`Self::set_id_verifier`]), or a bare unkeyed BLAKE3 comparison
+    /// otherwise.
+    fn digest_matches(&self, chunk_id: &str, data: &[u8]) -> bool {
+        match self.id_verifier.read().clone() {
+            Some(verifier) => verifier.verify(chunk_id, data),
+            None => blake3::hash(data).to_hex().to_string() == chunk_id,
+        }
+    }
+
+    /// A cached chunk failed digest verification: evict it, log the
+    /// corruption, and try to heal it through the registered
+    /// reconstructor before giving up and reporting an ordinary miss.
+    fn handle_corruption(&self, chunk_id: &str) -> Result<Option<Vec<u8>>> {
+        error!("Cache corruption detected for chunk {}: digest mismatch, evicting", chunk_id);
+        self.remove(chunk_id)?;
+
+        let Some(reconstructor) = self.reconstructor.read().clone() else {
+            return Ok(None);
+        };
+
+        match reconstructor.reconstruct(chunk_id) {
+            Ok(Some(rebuilt)) if self.digest_matches(chunk_id, &rebuilt) => {
+                info!("Reconstructed corrupt chunk {} from redundant storage", chunk_id);
+                self.put(chunk_id, &rebuilt)?;
+                Ok(Some(rebuilt))
+            }
+            Ok(Some(_)) => {
+                error!("Reconstructed chunk {} still fails digest verification", chunk_id);
+                Ok(None)
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                error!("Failed to reconstruct corrupt chunk {}: {}", chunk_id, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Register the reconstructor [`Self::get`] and [`Self::scrub`] use to
+    /// heal a chunk that fails digest verification. Typically the
+    /// erasure-coding pool, so a corrupt local copy is rebuilt from
+    /// surviving shards rather than forcing a full remote re-download.
+    pub fn set_reconstructor(&self, reconstructor: Arc<dyn ChunkReconstructor>) {
+        *self.reconstructor.write() = Some(reconstructor);
+    }
+
+    /// Register the verifier [`Self::get`] and [`Self::scrub`] use to
+    /// confirm a cached chunk's plaintext against its id - see
+    /// [`ChunkIdVerifier`]. Typically this mount's [`crate::crypto::KeyManager`].
+    pub fn set_id_verifier(&self, verifier: Arc<dyn ChunkIdVerifier>) {
+        *self.id_verifier.write() = Some(verifier);
+    }
+
+    /// Walk every cached chunk, verifying its digest, for periodic
+    /// background integrity checks. Corrupt chunks are evicted (and
+    /// healed, if a reconstructor is registered) exactly as in
+    /// [`Self::get`]; returns the ids of chunks that were found corrupt.
+    pub fn scrub(&self) -> Result<Vec<String>> {
+        let chunk_ids: Vec<String> = self.sizes.read().keys().cloned().collect();
+        let mut corrupted = Vec::new();
+
+        for chunk_id in chunk_ids {
+            let path = self.chunk_path(&chunk_id);
+            let mut file = match File::open(&path) {
+                Ok(file) => file,
+                Err(_) => continue, // evicted/moved concurrently, nothing to scrub
+            };
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+
+            if !self.digest_matches(&chunk_id, &data) {
+                self.handle_corruption(&chunk_id)?;
+                corrupted.push(chunk_id);
+            }
+        }
+
+        if !corrupted.is_empty() {
+            info!("Scrub found {} corrupt chunk(s)", corrupted.len());
+        }
+        Ok(corrupted)
+    }
+
     /// Put a chunk in cache
     pub fn put(&self, chunk_id: &str, data: &[u8]) -> Result<()> {
         let size = data.len() as u64;
@@ -141,6 +300,137 @@ impl ChunkCache {
         Ok(())
     }
 
+    /// Stage a chunk for write-back: write it to disk and return
+    /// immediately without uploading, leaving that to the background
+    /// flush workers started by [`Self::start_write_back`] (or an
+    /// explicit [`Self::sync`]). Crash-safe: the data lands via a temp
+    /// file and atomic rename, so a crash mid-write never leaves a
+    /// half-written chunk behind for a flush worker to upload.
+    pub fn put_dirty(&self, chunk_id: &str, data: &[u8]) -> Result<()> {
+        let size = data.len() as u64;
+
+        self.ensure_space(size)?;
+
+        let path = self.chunk_path(chunk_id);
+        let mut staged = tempfile::NamedTempFile::new_in(&self.cache_dir)
+            .map_err(|e| Error::Internal(format!("failed to stage dirty chunk {chunk_id}: {e}")))?;
+        staged.write_all(data)?;
+        staged.as_file().sync_all()?;
+        staged
+            .persist(&path)
+            .map_err(|e| Error::Internal(format!("failed to stage dirty chunk {chunk_id}: {e}")))?;
+
+        self.lru.write().insert(chunk_id.to_string());
+        self.sizes.write().insert(chunk_id.to_string(), size);
+        self.current_size.fetch_add(size, Ordering::SeqCst);
+        self.dirty.write().insert(chunk_id.to_string());
+        let dirty_bytes = self.dirty_bytes.fetch_add(size, Ordering::SeqCst) + size;
+
+        debug!("Staged dirty chunk: {} ({} bytes)", chunk_id, size);
+
+        if dirty_bytes >= self.write_back.high_water_mark {
+            self.enqueue_flush(chunk_id);
+        }
+
+        Ok(())
+    }
+
+    /// Send `chunk_id` to the background flush workers, if write-back is
+    /// running. A no-op otherwise - the chunk just waits for eviction or
+    /// an explicit [`Self::sync`] to flush it instead.
+    fn enqueue_flush(&self, chunk_id: &str) {
+        if let Some(tx) = self.flush_tx.read().as_ref() {
+            let _ = tx.send(chunk_id.to_string());
+        }
+    }
+
+    /// Upload one dirty chunk and clear its dirty bit. Safe to call
+    /// concurrently for the same chunk (e.g. a flush worker racing
+    /// `sync()`) - the second caller just finds it already clean.
+    fn flush_one(&self, chunk_id: &str, uploader: &dyn ChunkUploader) -> Result<()> {
+        if !self.dirty.read().contains(chunk_id) {
+            return Ok(());
+        }
+
+        let path = self.chunk_path(chunk_id);
+        let mut file = File::open(&path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        uploader.upload(chunk_id, &data)?;
+
+        if self.dirty.write().remove(chunk_id) {
+            self.dirty_bytes.fetch_sub(data.len() as u64, Ordering::SeqCst);
+            debug!("Flushed dirty chunk: {} ({} bytes)", chunk_id, data.len());
+        }
+
+        Ok(())
+    }
+
+    /// Start the background flush workers that drain chunks staged by
+    /// [`Self::put_dirty`] to `uploader`. Call once, after wrapping the
+    /// cache in an `Arc`, when `CacheConfig::write_back.enabled` is set -
+    /// a cache nobody called this on just accumulates dirty chunks until
+    /// eviction or [`Self::sync`] flushes them instead.
+    pub fn start_write_back(self: &Arc<Self>, uploader: Arc<dyn ChunkUploader>) {
+        if !self.write_back.enabled {
+            return;
+        }
+
+        *self.uploader.write() = Some(Arc::clone(&uploader));
+
+        let (tx, rx) = mpsc::channel::<String>();
+        *self.flush_tx.write() = Some(tx);
+        let rx = Arc::new(Mutex::new(rx));
+
+        let workers = self.write_back.worker_threads.max(1);
+        for _ in 0..workers {
+            let cache = Arc::clone(self);
+            let uploader = Arc::clone(&uploader);
+            let rx = Arc::clone(&rx);
+            thread::spawn(move || loop {
+                let chunk_id = match rx.lock().recv() {
+                    Ok(id) => id,
+                    Err(_) => return, // sender dropped: cache is gone
+                };
+                if let Err(e) = cache.flush_one(&chunk_id, uploader.as_ref()) {
+                    error!("Failed to flush dirty chunk {}: {}", chunk_id, e);
+                }
+            });
+        }
+
+        info!(
+            "Write-back cache started: {} worker(s), high water mark {} bytes",
+            workers, self.write_back.high_water_mark
+        );
+    }
+
+    /// Block until every chunk in `chunk_ids` is durably uploaded - an
+    /// `fsync` for a set of chunks (typically one inode's manifest).
+    /// A no-op per id that's already clean or if write-back was never
+    /// started (write-through chunks are already durable by the time
+    /// `put` returns).
+    pub fn sync(&self, chunk_ids: &[String]) -> Result<()> {
+        let Some(uploader) = self.uploader.read().clone() else {
+            return Ok(());
+        };
+
+        for chunk_id in chunk_ids {
+            self.flush_one(chunk_id, uploader.as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Write-back metrics so callers can implement their own
+    /// back-pressure (e.g. stall new writes once too much is dirty).
+    pub fn write_back_stats(&self) -> WriteBackStats {
+        WriteBackStats {
+            dirty_bytes: self.dirty_bytes.load(Ordering::Relaxed),
+            pending_uploads: self.dirty.read().len(),
+        }
+    }
+
     /// Remove a chunk from cache
     pub fn remove(&self, chunk_id: &str) -> Result<()> {
         let path = self.chunk_path(chunk_id);
@@ -150,6 +440,7 @@ impl ChunkCache {
                 self.current_size.fetch_sub(size, Ordering::SeqCst);
             }
             self.lru.write().remove(&chunk_id.to_string());
+            self.dirty.write().remove(chunk_id);
             fs::remove_file(&path)?;
             debug!("Removed from cache: {}", chunk_id);
         }
@@ -157,9 +448,14 @@ impl ChunkCache {
         Ok(())
     }
 
-    /// Ensure we have space for new data
+    /// Ensure we have space for new data. Clean chunks are evicted first;
+    /// a dirty chunk is only evicted after being flushed (synchronously,
+    /// if an uploader is available), so write-back never silently drops
+    /// unuploaded data under memory pressure.
     fn ensure_space(&self, needed: u64) -> Result<()> {
         let mut current = self.current_size.load(Ordering::SeqCst);
+        let mut requeue = Vec::new();
+        let mut remaining_candidates = self.sizes.read().len();
 
         while current + needed > self.max_size {
             // Evict oldest
@@ -170,6 +466,25 @@ impl ChunkCache {
 
             match to_evict {
                 Some(chunk_id) => {
+                    if remaining_candidates == 0 {
+                        // Cycled through every tracked chunk without
+                        // freeing enough space (all dirty, no uploader).
+                        requeue.push(chunk_id);
+                        break;
+                    }
+                    remaining_candidates -= 1;
+
+                    if self.dirty.read().contains(&chunk_id) {
+                        let flushed = match self.uploader.read().clone() {
+                            Some(uploader) => self.flush_one(&chunk_id, uploader.as_ref()).is_ok(),
+                            None => false,
+                        };
+                        if !flushed {
+                            requeue.push(chunk_id);
+                            continue;
+                        }
+                    }
+
                     let size = self.sizes.write().remove(&chunk_id).unwrap_or(0);
                     let path = self.chunk_path(&chunk_id);
                     if path.exists() {
@@ -189,6 +504,15 @@ impl ChunkCache {
             }
         }
 
+        // Put back anything we passed over (still dirty, couldn't flush)
+        // so it isn't forgotten by the LRU and gets retried next time.
+        if !requeue.is_empty() {
+            let mut lru = self.lru.write();
+            for chunk_id in requeue {
+                lru.insert(chunk_id);
+            }
+        }
+
         Ok(())
     }
 
@@ -253,7 +577,7 @@ impl ChunkCache {
 }
 
 /// Cache statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CacheStats {
     pub current_size: u64,
     pub max_size: u64,
@@ -272,6 +596,16 @@ impl CacheStats {
     }
 }
 
+/// Write-back cache metrics, for callers implementing back-pressure
+/// against a growing dirty set.
+#[derive(Debug, Clone, Copy)]
+pub struct WriteBackStats {
+    /// Bytes staged but not yet confirmed durable
+    pub dirty_bytes: u64,
+    /// Number of chunks staged but not yet confirmed durable
+    pub pending_uploads: usize,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,6 +619,34 @@ mod tests {
             prefetch_enabled: true,
             prefetch_count: 3,
             eviction_policy: crate::config::EvictionPolicy::Lru,
+            write_back: crate::config::WriteBackConfig::default(),
+            read_parallelism: 4,
+            inode_cache_capacity: 10_000,
+        }
+    }
+
+    /// Content-addressed id for `data`, matching what real callers use as
+    /// a `chunk_id` - needed wherever a test reads a chunk back through
+    /// [`ChunkCache::get`], since that now verifies the digest.
+    fn cid(data: &[u8]) -> String {
+        blake3::hash(data).to_hex().to_string()
+    }
+
+    /// Uploader fixture that records every chunk it was asked to store.
+    struct RecordingUploader {
+        uploaded: Mutex<Vec<String>>,
+    }
+
+    impl RecordingUploader {
+        fn new() -> Self {
+            RecordingUploader { uploaded: Mutex::new(Vec::new()) }
+        }
+    }
+
+    impl ChunkUploader for RecordingUploader {
+        fn upload(&self, chunk_id: &str, _data: &[u8]) -> Result<()> {
+            self.uploaded.lock().push(chunk_id.to_string());
+            Ok(())
         }
     }
 
@@ -294,10 +656,11 @@ mod tests {
         let config = test_config(temp.path());
         let cache = ChunkCache::new(&config).unwrap();
 
-        cache.put("chunk1", b"hello world").unwrap();
-        assert!(cache.contains("chunk1"));
+        let id = cid(b"hello world");
+        cache.put(&id, b"hello world").unwrap();
+        assert!(cache.contains(&id));
 
-        let data = cache.get("chunk1").unwrap().unwrap();
+        let data = cache.get(&id).unwrap().unwrap();
         assert_eq!(data, b"hello world");
     }
 
@@ -352,16 +715,17 @@ mod tests {
 
         let cache = ChunkCache::new(&config).unwrap();
 
-        cache.put("chunk1", &[0u8; 30]).unwrap();
+        let id1 = cid(&[0u8; 30]);
+        cache.put(&id1, &[0u8; 30]).unwrap();
         cache.put("chunk2", &[0u8; 30]).unwrap();
 
         // Access chunk1 to make it more recent
-        cache.get("chunk1").unwrap();
+        cache.get(&id1).unwrap();
 
         // This should evict chunk2 (least recently used)
         cache.put("chunk3", &[0u8; 50]).unwrap();
 
-        assert!(cache.contains("chunk1"));
+        assert!(cache.contains(&id1));
         assert!(!cache.contains("chunk2"));
         assert!(cache.contains("chunk3"));
     }
@@ -379,4 +743,176 @@ mod tests {
         assert_eq!(cache.next_prefetch(), Some("c".to_string()));
         assert_eq!(cache.next_prefetch(), None);
     }
+
+    #[test]
+    fn test_put_dirty_tracks_dirty_bytes_until_synced() {
+        let temp = TempDir::new().unwrap();
+        let mut config = test_config(temp.path());
+        config.write_back.enabled = true;
+        let cache = Arc::new(ChunkCache::new(&config).unwrap());
+
+        cache.put_dirty("chunk1", b"hello world").unwrap();
+        assert!(cache.contains("chunk1"));
+        assert_eq!(cache.write_back_stats().pending_uploads, 1);
+        assert_eq!(cache.write_back_stats().dirty_bytes, 11);
+
+        // No uploader started yet: sync is a no-op, chunk stays dirty.
+        cache.sync(&["chunk1".to_string()]).unwrap();
+        assert_eq!(cache.write_back_stats().pending_uploads, 1);
+
+        let uploader = Arc::new(RecordingUploader::new());
+        cache.start_write_back(Arc::clone(&uploader) as Arc<dyn ChunkUploader>);
+        cache.sync(&["chunk1".to_string()]).unwrap();
+
+        assert_eq!(cache.write_back_stats().pending_uploads, 0);
+        assert_eq!(cache.write_back_stats().dirty_bytes, 0);
+        assert_eq!(*uploader.uploaded.lock(), vec!["chunk1".to_string()]);
+    }
+
+    #[test]
+    fn test_eviction_flushes_dirty_chunks_before_dropping_them() {
+        let temp = TempDir::new().unwrap();
+        let mut config = test_config(temp.path());
+        config.max_size = 50;
+        config.write_back.enabled = true;
+        let cache = Arc::new(ChunkCache::new(&config).unwrap());
+
+        let uploader = Arc::new(RecordingUploader::new());
+        cache.start_write_back(Arc::clone(&uploader) as Arc<dyn ChunkUploader>);
+
+        cache.put_dirty("chunk1", &[0u8; 30]).unwrap();
+        // Forces eviction of chunk1, which must be flushed rather than
+        // silently dropped while still dirty.
+        cache.put("chunk2", &[0u8; 30]).unwrap();
+
+        assert!(!cache.contains("chunk1"));
+        assert_eq!(*uploader.uploaded.lock(), vec!["chunk1".to_string()]);
+    }
+
+    /// Reconstructor fixture that returns canned bytes for ids it knows
+    /// about, and `Ok(None)` (not in the redundant pool) otherwise.
+    struct FixedReconstructor {
+        chunks: HashMap<String, Vec<u8>>,
+    }
+
+    impl ChunkReconstructor for FixedReconstructor {
+        fn reconstruct(&self, chunk_id: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self.chunks.get(chunk_id).cloned())
+        }
+    }
+
+    /// Verifier fixture standing in for a keyed [`crate::crypto::KeyManager`]:
+    /// treats `chunk_id` as valid for `data` only if it appears in `valid`,
+    /// regardless of what `data` actually hashes to.
+    struct FixedVerifier {
+        valid: std::collections::HashSet<String>,
+    }
+
+    impl ChunkIdVerifier for FixedVerifier {
+        fn verify(&self, chunk_id: &str, _data: &[u8]) -> bool {
+            self.valid.contains(chunk_id)
+        }
+    }
+
+    fn corrupt_chunk_on_disk(config: &CacheConfig, chunk_id: &str) {
+        fs::write(config.cache_dir.join(chunk_id), b"corrupted bytes").unwrap();
+    }
+
+    #[test]
+    fn test_get_evicts_and_misses_on_digest_mismatch() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        let id = cid(b"hello world");
+        cache.put(&id, b"hello world").unwrap();
+        corrupt_chunk_on_disk(&config, &id);
+
+        assert!(cache.get(&id).unwrap().is_none());
+        assert!(!cache.contains(&id));
+    }
+
+    #[test]
+    fn test_get_heals_corrupt_chunk_via_reconstructor() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        let id = cid(b"hello world");
+        cache.put(&id, b"hello world").unwrap();
+        corrupt_chunk_on_disk(&config, &id);
+
+        let mut chunks = HashMap::new();
+        chunks.insert(id.clone(), b"hello world".to_vec());
+        cache.set_reconstructor(Arc::new(FixedReconstructor { chunks }));
+
+        let data = cache.get(&id).unwrap().unwrap();
+        assert_eq!(data, b"hello world");
+        assert!(cache.contains(&id));
+    }
+
+    #[test]
+    fn test_get_misses_when_reconstructor_cant_help() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        let id = cid(b"hello world");
+        cache.put(&id, b"hello world").unwrap();
+        corrupt_chunk_on_disk(&config, &id);
+        cache.set_reconstructor(Arc::new(FixedReconstructor { chunks: HashMap::new() }));
+
+        assert!(cache.get(&id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_uses_registered_verifier_instead_of_bare_blake3() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        // A keyed id won't match `cid` (plain unkeyed BLAKE3) at all, so
+        // without a verifier this would read as corrupt.
+        let keyed_id = "keyed-id-unrelated-to-content";
+        cache.put(keyed_id, b"hello world").unwrap();
+
+        let mut valid = std::collections::HashSet::new();
+        valid.insert(keyed_id.to_string());
+        cache.set_id_verifier(Arc::new(FixedVerifier { valid }));
+
+        assert_eq!(cache.get(keyed_id).unwrap().unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_get_still_evicts_when_registered_verifier_rejects() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        let id = cid(b"hello world");
+        cache.put(&id, b"hello world").unwrap();
+        cache.set_id_verifier(Arc::new(FixedVerifier { valid: std::collections::HashSet::new() }));
+
+        assert!(cache.get(&id).unwrap().is_none());
+        assert!(!cache.contains(&id));
+    }
+
+    #[test]
+    fn test_scrub_finds_and_evicts_corrupt_chunks() {
+        let temp = TempDir::new().unwrap();
+        let config = test_config(temp.path());
+        let cache = ChunkCache::new(&config).unwrap();
+
+        let good_id = cid(b"intact");
+        let bad_id = cid(b"hello world");
+        cache.put(&good_id, b"intact").unwrap();
+        cache.put(&bad_id, b"hello world").unwrap();
+        corrupt_chunk_on_disk(&config, &bad_id);
+
+        let corrupted = cache.scrub().unwrap();
+
+        assert_eq!(corrupted, vec![bad_id.clone()]);
+        assert!(cache.contains(&good_id));
+        assert!(!cache.contains(&bad_id));
+    }
 }
@@ -0,0 +1,218 @@
+//! Content-defined chunking (rolling gear hash)
+//!
+//! Splitting a file at fixed offsets means a single inserted or deleted
+//! byte shifts every chunk boundary after it, so a new [`FileVersion`](crate::metadata::FileVersion)
+//! of a large file re-uploads almost the whole thing even when only a
+//! small region actually changed. [`ContentChunker`] instead cuts a chunk
+//! boundary wherever a rolling hash over the trailing window happens to
+//! satisfy a bitmask, so unaffected regions of a file - before and after
+//! an edit - fall at the same offsets and hash the same in the next
+//! version. [`crate::metadata::MetadataStore::save_chunk_ref`]'s existing
+//! content-hash refcounting then does the rest: a chunk shared by two
+//! versions is simply referenced twice, and
+//! [`MetadataStore::decrement_chunk_ref`](crate::metadata::MetadataStore::decrement_chunk_ref)
+//! only evicts it once neither version needs it anymore.
+
+use std::sync::OnceLock;
+
+/// One content-defined chunk cut from a byte stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CdcChunk {
+    /// Offset of this chunk within the stream it was cut from.
+    pub offset: u64,
+    /// The chunk's plaintext bytes.
+    pub data: Vec<u8>,
+    /// BLAKE3 hash of `data`, hex-encoded - the same content-addressing
+    /// scheme [`ChunkId`](super::ChunkId) uses for whole chunks.
+    pub hash: String,
+}
+
+/// Cuts a byte stream into [`CdcChunk`]s using a rolling gear hash,
+/// normalized (FastCDC-style) toward `target_size`: the cut-point mask
+/// tightens once a chunk reaches `target_size`, biasing the distribution
+/// away from `min_size`/`max_size` and toward the target instead of
+/// spreading uniformly between them.
+pub struct ContentChunker {
+    min_size: usize,
+    target_size: usize,
+    max_size: usize,
+}
+
+impl ContentChunker {
+    /// Build a chunker from `crate::config::ChunkConfig`'s
+    /// `min_chunk_size`/`chunk_size`/`max_chunk_size`. `target_size` is
+    /// clamped between `min_size` and `max_size` and rounded down to a
+    /// power of two (required by the mask-based cut test); `min_size`
+    /// must be less than `max_size` or every chunk is forced to
+    /// `max_size`.
+    pub fn new(min_size: usize, target_size: usize, max_size: usize) -> Self {
+        let max_size = max_size.max(min_size + 1);
+        let target_size = target_size.clamp(min_size.max(1), max_size);
+        // Round down to a power of two so `mask_for` can derive a bitmask
+        // from it with a plain bit shift.
+        let target_size = 1usize << target_size.max(2).ilog2();
+
+        Self { min_size, target_size, max_size }
+    }
+
+    /// Split `data` into content-defined chunks covering it end to end.
+    pub fn chunk(&self, data: &[u8]) -> Vec<CdcChunk> {
+        let table = gear_table();
+        let mut chunks = Vec::new();
+        let mut start = 0usize;
+
+        while start < data.len() {
+            let len = self.cut_point(&data[start..], table);
+            let slice = &data[start..start + len];
+            chunks.push(CdcChunk {
+                offset: start as u64,
+                data: slice.to_vec(),
+                hash: blake3::hash(slice).to_hex().to_string(),
+            });
+            start += len;
+        }
+
+        chunks
+    }
+
+    /// Length of the next chunk to cut from the front of `data`: the
+    /// earliest position at or after `min_size` where the rolling gear
+    /// hash satisfies the mask for its region, or `max_size` (or
+    /// `data.len()` if shorter) if none does.
+    fn cut_point(&self, data: &[u8], table: &[u64; 256]) -> usize {
+        let limit = self.max_size.min(data.len());
+        if limit <= self.min_size {
+            return limit;
+        }
+
+        let mask_before_target = mask_for(self.target_size, 1);
+        let mask_after_target = mask_for(self.target_size, -1);
+
+        let mut hash: u64 = 0;
+        for (i, &byte) in data[..limit].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(table[byte as usize]);
+
+            let consumed = i + 1;
+            if consumed < self.min_size {
+                continue;
+            }
+
+            let mask = if consumed < self.target_size { mask_before_target } else { mask_after_target };
+            if hash & mask == 0 {
+                return consumed;
+            }
+        }
+
+        limit
+    }
+}
+
+/// A bitmask with `log2(target_size) + bit_shift` low bits set (clamped
+/// to a sane range), used to normalize the cut-point distribution around
+/// `target_size` - see [`ContentChunker::cut_point`].
+fn mask_for(target_size: usize, bit_shift: i32) -> u64 {
+    let bits = (target_size.max(2).ilog2() as i32 + bit_shift).clamp(4, 31) as u32;
+    (1u64 << bits) - 1
+}
+
+/// Precomputed pseudo-random gear table, one `u64` per byte value.
+/// Generated deterministically with SplitMix64 rather than pulled from a
+/// dependency: the chunker only needs the values to look unrelated to
+/// the byte they index, not to be cryptographically random.
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u64; 256];
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        for slot in table.iter_mut() {
+            seed = seed.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^= z >> 31;
+            *slot = z;
+        }
+        table
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunker() -> ContentChunker {
+        ContentChunker::new(4 * 1024, 16 * 1024, 64 * 1024)
+    }
+
+    #[test]
+    fn test_chunk_empty_data_yields_no_chunks() {
+        assert!(chunker().chunk(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_reassembles_to_original_length_and_offsets() {
+        let data = vec![7u8; 200 * 1024];
+        let chunks = chunker().chunk(&data);
+
+        let mut expected_offset = 0u64;
+        for c in &chunks {
+            assert_eq!(c.offset, expected_offset);
+            assert!(!c.data.is_empty() && c.data.len() <= 64 * 1024);
+            expected_offset += c.data.len() as u64;
+        }
+        assert_eq!(expected_offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_chunk_forces_cut_at_max_size_on_incompressible_data() {
+        let data: Vec<u8> = (0..300 * 1024).map(|i| (i % 256) as u8).collect();
+        let chunks = chunker().chunk(&data);
+        assert!(chunks.len() > 1);
+        for c in &chunks[..chunks.len() - 1] {
+            assert_eq!(c.data.len(), 64 * 1024);
+        }
+    }
+
+    #[test]
+    fn test_edit_far_from_a_boundary_leaves_earlier_chunk_hashes_unchanged() {
+        let mut original = vec![0u8; 300 * 1024];
+        for (i, b) in original.iter_mut().enumerate() {
+            *b = ((i * 2654435761) % 256) as u8;
+        }
+
+        let before = chunker().chunk(&original);
+
+        let last = before.last().unwrap();
+        let flip_at = last.offset as usize + last.data.len() / 2;
+        let mut edited = original.clone();
+        edited[flip_at] ^= 0xFF;
+
+        let after = chunker().chunk(&edited);
+
+        let unaffected = before.len() - 1;
+        assert!(before.len() <= after.len() + 1 && after.len() <= before.len() + 1);
+        assert_eq!(&before[..unaffected], &after[..unaffected]);
+    }
+
+    #[test]
+    fn test_insertion_shifts_nothing_before_the_insertion_point() {
+        let mut original = vec![0u8; 300 * 1024];
+        for (i, b) in original.iter_mut().enumerate() {
+            *b = ((i * 2654435761) % 256) as u8;
+        }
+
+        let before = chunker().chunk(&original);
+
+        // Insert bytes in the middle of the last chunk - everything
+        // before that chunk must be untouched.
+        let last = before.last().unwrap();
+        let insert_at = last.offset as usize + last.data.len() / 2;
+        let mut edited = original.clone();
+        edited.splice(insert_at..insert_at, vec![0xAAu8; 37]);
+
+        let after = chunker().chunk(&edited);
+
+        let unaffected = before.len() - 1;
+        assert_eq!(&before[..unaffected], &after[..unaffected]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Per-chunk compression with algorithm selection and an integrity
+//! checksum footer.
+//!
+//! Each chunk picks its own [`CompressionAlgo`] rather than the crate
+//! compressing (or not) everything uniformly: we try to shrink the data
+//! and fall back to storing it uncompressed when compression doesn't
+//! help. Either way a trailing 4-byte checksum of the *uncompressed*
+//! bytes is appended, so corruption introduced after compression (or a
+//! bad decompress) is caught instead of silently handed back as
+//! plaintext.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Which compression algorithm (if any) was applied to a stored chunk.
+/// Also used directly as `NamespaceConfig::compression`, so a namespace
+/// can select its algorithm and level the same way a stored chunk records
+/// which one it used.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionAlgo {
+    /// Stored as-is: compression either wasn't attempted or didn't shrink
+    /// the data.
+    None,
+    /// zstd at the given level.
+    Zstd {
+        /// zstd compression level
+        level: i32,
+    },
+}
+
+impl Default for CompressionAlgo {
+    /// Namespaces don't compress unless they opt in.
+    fn default() -> Self {
+        CompressionAlgo::None
+    }
+}
+
+/// Valid range for a zstd compression level, as accepted by the `zstd`
+/// crate.
+pub const ZSTD_LEVEL_RANGE: std::ops::RangeInclusive<i32> = 1..=22;
+
+const CHECKSUM_LEN: usize = 4;
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = blake3::hash(data);
+    let bytes = hash.as_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+fn checksum_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Store `data` uncompressed with a trailing checksum footer. This is the
+/// fallback `compress` takes when compression doesn't shrink the data.
+pub fn compress_or_original(data: &[u8]) -> Result<(Vec<u8>, CompressionAlgo)> {
+    let mut out = Vec::with_capacity(data.len() + CHECKSUM_LEN);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&checksum(data));
+    Ok((out, CompressionAlgo::None))
+}
+
+/// Compress `data` at `level`, appending a checksum footer of the
+/// original bytes. Falls back to [`compress_or_original`] when the
+/// compressed form isn't actually smaller.
+pub fn compress(data: &[u8], level: i32) -> Result<(Vec<u8>, CompressionAlgo)> {
+    let compressed = zstd::encode_all(data, level)
+        .map_err(|e| Error::Internal(format!("zstd compression failed: {}", e)))?;
+
+    if compressed.len() >= data.len() {
+        return compress_or_original(data);
+    }
+
+    let mut out = compressed;
+    out.extend_from_slice(&checksum(data));
+    Ok((out, CompressionAlgo::Zstd { level }))
+}
+
+/// Reverse [`compress`]/[`compress_or_original`]: branch on `algo`,
+/// decompress if needed, and verify the trailing checksum against the
+/// recovered plaintext before returning it.
+pub fn decompress(stored: &[u8], algo: CompressionAlgo) -> Result<Vec<u8>> {
+    if stored.len() < CHECKSUM_LEN {
+        return Err(Error::Decryption(
+            "stored chunk shorter than the checksum footer".to_string(),
+        ));
+    }
+    let (payload, footer) = stored.split_at(stored.len() - CHECKSUM_LEN);
+
+    let plaintext = match algo {
+        CompressionAlgo::None => payload.to_vec(),
+        CompressionAlgo::Zstd { .. } => zstd::decode_all(payload)
+            .map_err(|e| Error::Internal(format!("zstd decompression failed: {}", e)))?,
+    };
+
+    let expected = checksum(&plaintext);
+    if expected != footer {
+        return Err(Error::ChunkVerificationFailed {
+            expected: checksum_hex(&expected),
+            got: checksum_hex(footer),
+        });
+    }
+
+    Ok(plaintext)
+}
+
+/// A legacy chunk stored uncompressed (pre-dating per-chunk algorithm
+/// selection) can be opportunistically recompressed on read. Returns the
+/// newly compressed bytes and algorithm when it's worth rewriting (i.e.
+/// compression actually shrinks it), or `None` to leave the chunk as-is.
+pub fn maybe_recompress(plaintext: &[u8], level: i32) -> Result<Option<(Vec<u8>, CompressionAlgo)>> {
+    let (stored, algo) = compress(plaintext, level)?;
+    match algo {
+        CompressionAlgo::Zstd { .. } => Ok(Some((stored, algo))),
+        CompressionAlgo::None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            .to_vec();
+        let (stored, algo) = compress(&data, 3).unwrap();
+        assert!(matches!(algo, CompressionAlgo::Zstd { .. }));
+        let restored = decompress(&stored, algo).unwrap();
+        assert_eq!(restored, data);
+    }
+
+    #[test]
+    fn test_compress_falls_back_when_incompressible() {
+        // Random-looking data that zstd won't shrink meaningfully.
+        let data: Vec<u8> = (0..64).map(|i| (i * 97 % 251) as u8).collect();
+        let (stored, algo) = compress(&data, 3).unwrap();
+        if let CompressionAlgo::None = algo {
+            let restored = decompress(&stored, algo).unwrap();
+            assert_eq!(restored, data);
+        }
+    }
+
+    #[test]
+    fn test_decompress_detects_corruption() {
+        let data = b"some plaintext to protect".to_vec();
+        let (mut stored, algo) = compress_or_original(&data).unwrap();
+        // Flip a byte in the payload; the checksum should catch it.
+        stored[0] ^= 0xFF;
+        let result = decompress(&stored, algo);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_maybe_recompress_skips_incompressible_data() {
+        let data: Vec<u8> = (0..64).map(|i| (i * 97 % 251) as u8).collect();
+        let result = maybe_recompress(&data, 3).unwrap();
+        assert!(result.is_none() || matches!(result.unwrap().1, CompressionAlgo::Zstd { .. }));
+    }
+}
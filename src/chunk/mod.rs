@@ -3,29 +3,83 @@
 //! Handles splitting files into chunks, content-addressable storage,
 //! compression, and deduplication.
 
+mod cdc;
 mod chunker;
 mod compression;
 
+pub use cdc::{CdcChunk, ContentChunker};
 pub use chunker::{Chunk, ChunkId, ChunkInfo, Chunker};
-pub use compression::{compress, compress_or_original, decompress};
+pub use compression::{
+    compress, compress_or_original, decompress, maybe_recompress, CompressionAlgo,
+    ZSTD_LEVEL_RANGE,
+};
 
+use crate::migration::Migrate;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-/// Reference to a chunk stored remotely
+/// Files below this size are stored inline in the manifest instead of
+/// being uploaded as a standalone chunk, saving a full network
+/// round-trip for metadata-heavy workloads.
+pub const INLINE_THRESHOLD: u64 = 3 * 1024;
+
+/// Where a chunk's bytes actually live.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChunkPayload {
+    /// Stored as a message in a Telegram chat.
+    Remote {
+        /// Telegram message ID where this chunk is stored
+        message_id: i32,
+    },
+    /// Stored directly alongside the manifest; never uploaded.
+    Inline {
+        /// Encrypted chunk bytes
+        data: Vec<u8>,
+    },
+}
+
+/// Reference to a chunk stored remotely (or inline, below `INLINE_THRESHOLD`)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ChunkRef {
-    /// Content-based ID (BLAKE3 hash of encrypted content)
+    /// Content-based id: a keyed BLAKE3 hash of the chunk's plaintext -
+    /// see [`crate::crypto::KeyManager::content_chunk_id`]. Deterministic
+    /// per store, so identical plaintext always dedups onto the same id.
     pub id: ChunkId,
     /// Size of the encrypted chunk in bytes
     pub size: u64,
-    /// Telegram message ID where this chunk is stored
-    pub message_id: i32,
+    /// Where the chunk's bytes live
+    pub payload: ChunkPayload,
     /// Offset within file this chunk represents
     pub offset: u64,
     /// Original (unencrypted, uncompressed) size
     pub original_size: u64,
-    /// Whether compression was applied
-    pub compressed: bool,
+    /// Compression algorithm applied to this chunk's stored bytes, if any
+    pub compression: CompressionAlgo,
+}
+
+impl ChunkRef {
+    /// The Telegram message id backing this chunk, or `None` if it's
+    /// stored inline.
+    pub fn message_id(&self) -> Option<i32> {
+        match &self.payload {
+            ChunkPayload::Remote { message_id } => Some(*message_id),
+            ChunkPayload::Inline { .. } => None,
+        }
+    }
+
+    /// Whether this chunk's bytes are stored inline in the manifest.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.payload, ChunkPayload::Inline { .. })
+    }
+
+    /// The chunk's bytes, if stored inline. Remote chunks must be fetched
+    /// from Telegram via their `message_id` instead.
+    pub fn inline_data(&self) -> Option<&[u8]> {
+        match &self.payload {
+            ChunkPayload::Inline { data } => Some(data),
+            ChunkPayload::Remote { .. } => None,
+        }
+    }
 }
 
 /// Manifest describing all chunks of a file
@@ -39,6 +93,12 @@ pub struct ChunkManifest {
     pub chunks: Vec<ChunkRef>,
     /// BLAKE3 hash of the complete file content
     pub file_hash: String,
+    /// Hybrid logical clock tick of the write that produced this manifest,
+    /// used as the last-writer-wins tie-breaker when `version` matches.
+    pub lww_time: u64,
+    /// Node that produced `lww_time`, the deterministic tie-breaker when
+    /// two manifests have the same `lww_time`.
+    pub lww_node: Uuid,
 }
 
 impl ChunkManifest {
@@ -49,9 +109,51 @@ impl ChunkManifest {
             total_size: 0,
             chunks: Vec::new(),
             file_hash: String::new(),
+            lww_time: 0,
+            lww_node: Uuid::nil(),
         }
     }
 
+    /// Merge a concurrently-modified manifest for the same file into this
+    /// one. This is commutative, associative, and idempotent, so replaying
+    /// gossiped manifests in any order converges to the same result.
+    ///
+    /// The element-wise maximum `version` wins outright. When versions tie
+    /// but `file_hash` differs (a genuine concurrent write), the side with
+    /// the greater `(lww_time, lww_node)` clock is kept as the manifest of
+    /// record, but the content-addressed `ChunkRef`s from both sides are
+    /// unioned so no uniquely-stored chunk is lost to garbage collection.
+    pub fn merge(&self, other: &ChunkManifest) -> ChunkManifest {
+        use std::cmp::Ordering;
+
+        let mut merged = match self.version.cmp(&other.version) {
+            Ordering::Greater => self.clone(),
+            Ordering::Less => other.clone(),
+            Ordering::Equal => {
+                if self.file_hash == other.file_hash {
+                    self.clone()
+                } else {
+                    match (self.lww_time, self.lww_node).cmp(&(other.lww_time, other.lww_node)) {
+                        Ordering::Less => other.clone(),
+                        _ => self.clone(),
+                    }
+                }
+            }
+        };
+
+        if self.version == other.version && self.file_hash != other.file_hash {
+            let mut seen: std::collections::HashSet<ChunkId> =
+                merged.chunks.iter().map(|c| c.id.clone()).collect();
+            for chunk in self.chunks.iter().chain(other.chunks.iter()) {
+                if seen.insert(chunk.id.clone()) {
+                    merged.chunks.push(chunk.clone());
+                }
+            }
+        }
+
+        merged
+    }
+
     /// Get the total stored size (after encryption/compression)
     pub fn stored_size(&self) -> u64 {
         self.chunks.iter().map(|c| c.size).sum()
@@ -73,6 +175,64 @@ impl ChunkManifest {
         }
         None
     }
+
+    /// Whether this manifest's total content is small enough to be stored
+    /// inline rather than uploaded as standalone chunks.
+    pub fn fits_inline(&self) -> bool {
+        self.total_size <= INLINE_THRESHOLD
+    }
+
+    /// Whether a write growing the file to `new_total_size` must spill an
+    /// inline manifest out to real uploaded chunks.
+    pub fn should_spill(&self, new_total_size: u64) -> bool {
+        self.fits_inline() && new_total_size > INLINE_THRESHOLD
+    }
+
+    /// Read bytes directly out of an inline chunk covering `offset`,
+    /// without a network round-trip. Returns `None` when the covering
+    /// chunk is remote (the caller must fetch it from Telegram) or when
+    /// `offset` is past the end of the file.
+    pub fn read_inline_at(&self, offset: u64) -> Option<&[u8]> {
+        let (_, chunk) = self.chunk_at_offset(offset)?;
+        let data = chunk.inline_data()?;
+        let local_offset = offset.checked_sub(chunk.offset)? as usize;
+        data.get(local_offset..)
+    }
+}
+
+/// The pre-LWW on-disk shape of [`ChunkManifest`], kept only so old blobs
+/// can be migrated forward. It predates `lww_time`/`lww_node`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestV1 {
+    pub version: u64,
+    pub total_size: u64,
+    pub chunks: Vec<ChunkRef>,
+    pub file_hash: String,
+}
+
+impl Migrate for ManifestV1 {
+    const VERSION: u16 = 1;
+    type Previous = ManifestV1;
+
+    fn migrate(previous: ManifestV1) -> Self {
+        previous
+    }
+}
+
+impl Migrate for ChunkManifest {
+    const VERSION: u16 = 2;
+    type Previous = ManifestV1;
+
+    fn migrate(previous: ManifestV1) -> Self {
+        ChunkManifest {
+            version: previous.version,
+            total_size: previous.total_size,
+            chunks: previous.chunks,
+            file_hash: previous.file_hash,
+            lww_time: 0,
+            lww_node: Uuid::nil(),
+        }
+    }
 }
 
 /// Location of a single block within a stripe
@@ -86,6 +246,11 @@ pub struct BlockLocation {
     pub block_index: u8,
     /// Upload timestamp (Unix seconds)
     pub uploaded_at: Option<i64>,
+    /// BLAKE3 hex digest of the block's plaintext bytes at upload time,
+    /// used by `AccountPool::scrub_stripe` to detect silent corruption on
+    /// re-download. `None` for blocks uploaded before this field existed.
+    #[serde(default)]
+    pub content_hash: Option<String>,
 }
 
 /// Stripe information for erasure-coded chunk
@@ -99,6 +264,12 @@ pub struct StripeInfo {
     pub parity_count: u8,
     /// Size of each block in bytes
     pub block_size: u64,
+    /// Monotonic commit counter stamped by `AccountPool::upload_stripe`.
+    /// Lets `resolve_latest` pick the newest of several `StripeInfo`s for
+    /// the same chunk when a crash retry or racing writer produced more
+    /// than one. Defaults to 0 for manifests predating this field.
+    #[serde(default)]
+    pub write_version: u64,
 }
 
 /// Reference to an erasure-coded chunk stored across multiple accounts
@@ -110,8 +281,8 @@ pub struct ErasureChunkRef {
     pub offset: u64,
     /// Original (unencrypted, uncompressed) size
     pub original_size: u64,
-    /// Whether compression was applied before erasure coding
-    pub compressed: bool,
+    /// Compression algorithm applied to this chunk's stored bytes, if any
+    pub compression: CompressionAlgo,
     /// Stripe information with block locations
     pub stripe: StripeInfo,
     /// Version for rebuild tracking
@@ -142,6 +313,7 @@ impl StripeInfo {
             data_count,
             parity_count,
             block_size,
+            write_version: 0,
         }
     }
 
@@ -174,3 +346,110 @@ impl ErasureChunkManifest {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_chunk_reads_without_message_id() {
+        let mut manifest = ChunkManifest::new(1);
+        manifest.total_size = 11;
+        manifest.chunks.push(ChunkRef {
+            id: ChunkId::from("inline".to_string()),
+            size: 11,
+            payload: ChunkPayload::Inline {
+                data: b"hello world".to_vec(),
+            },
+            offset: 0,
+            original_size: 11,
+            compression: CompressionAlgo::None,
+        });
+
+        assert!(manifest.fits_inline());
+        let (_, chunk) = manifest.chunk_at_offset(0).unwrap();
+        assert!(chunk.is_inline());
+        assert_eq!(chunk.message_id(), None);
+        assert_eq!(manifest.read_inline_at(6), Some(&b"world"[..]));
+    }
+
+    #[test]
+    fn test_should_spill_once_over_threshold() {
+        let mut manifest = ChunkManifest::new(1);
+        manifest.total_size = 100;
+        assert!(!manifest.should_spill(INLINE_THRESHOLD));
+        assert!(manifest.should_spill(INLINE_THRESHOLD + 1));
+    }
+
+    #[test]
+    fn test_chunk_manifest_merge_prefers_higher_version() {
+        let older = ChunkManifest::new(1);
+        let mut newer = ChunkManifest::new(2);
+        newer.file_hash = "newer".to_string();
+
+        let merged = older.merge(&newer);
+        assert_eq!(merged.version, 2);
+        assert_eq!(merged.file_hash, "newer");
+
+        // Merge is commutative
+        let merged_reversed = newer.merge(&older);
+        assert_eq!(merged_reversed.version, merged.version);
+        assert_eq!(merged_reversed.file_hash, merged.file_hash);
+    }
+
+    #[test]
+    fn test_chunk_manifest_merge_ties_break_on_lww_clock() {
+        let node_a = Uuid::from_u128(1);
+        let node_b = Uuid::from_u128(2);
+
+        let mut left = ChunkManifest::new(3);
+        left.file_hash = "left".to_string();
+        left.lww_time = 5;
+        left.lww_node = node_a;
+
+        let mut right = ChunkManifest::new(3);
+        right.file_hash = "right".to_string();
+        right.lww_time = 7;
+        right.lww_node = node_b;
+
+        let merged = left.merge(&right);
+        assert_eq!(merged.file_hash, "right");
+    }
+
+    #[test]
+    fn test_chunk_manifest_merge_is_idempotent() {
+        let manifest = ChunkManifest::new(4);
+        let merged = manifest.merge(&manifest);
+        assert_eq!(merged.version, manifest.version);
+        assert_eq!(merged.file_hash, manifest.file_hash);
+    }
+
+    #[test]
+    fn test_v1_manifest_migrates_to_current_version() {
+        let v1 = ManifestV1 {
+            version: 7,
+            total_size: 1024,
+            chunks: Vec::new(),
+            file_hash: "deadbeef".to_string(),
+        };
+        let blob = crate::migration::save_versioned(&v1).unwrap();
+
+        let migrated: ChunkManifest = crate::migration::load_versioned(&blob).unwrap();
+        assert_eq!(migrated.version, 7);
+        assert_eq!(migrated.total_size, 1024);
+        assert_eq!(migrated.file_hash, "deadbeef");
+        assert_eq!(migrated.lww_node, Uuid::nil());
+    }
+
+    #[test]
+    fn test_current_version_manifest_round_trips_without_migration() {
+        let mut manifest = ChunkManifest::new(9);
+        manifest.file_hash = "abc123".to_string();
+        manifest.lww_time = 42;
+
+        let blob = crate::migration::save_versioned(&manifest).unwrap();
+        let round_tripped: ChunkManifest = crate::migration::load_versioned(&blob).unwrap();
+        assert_eq!(round_tripped.version, manifest.version);
+        assert_eq!(round_tripped.lww_time, 42);
+    }
+}
@@ -1,7 +1,10 @@
 //! Configuration management for tgcryptfs
 
+use crate::crypto::Algorithm;
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
@@ -40,6 +43,34 @@ pub const DEFAULT_MASTER_REPLICA_SYNC_INTERVAL: u64 = 60;
 /// Default sync interval for distributed mode (milliseconds)
 pub const DEFAULT_DISTRIBUTED_SYNC_INTERVAL: u64 = 1000;
 
+/// Deployment environment. Controls how strict [`ConfigV2::validate`] is:
+/// `Development` keeps today's lenient defaults, while `Production` rejects
+/// a handful of settings that are fine for local testing but risky to ship
+/// (see `validate()` for the exact checks). Defaults to `Development` and
+/// is overridable via the `environment` config field or the
+/// `ENVIRONMENT`/`TGCRYPTFS_ENV` variables in [`ConfigV2::from_env`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Environment {
+    #[default]
+    Development,
+    Production,
+}
+
+impl Environment {
+    /// Parse an `ENVIRONMENT`/`TGCRYPTFS_ENV` value, case-insensitively.
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "production" | "prod" => Ok(Environment::Production),
+            other => Err(Error::InvalidConfig(format!(
+                "Invalid environment '{}': expected development or production",
+                other
+            ))),
+        }
+    }
+}
+
 /// Machine identity configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MachineConfig {
@@ -78,6 +109,34 @@ pub struct DistributionConfig {
 
     /// Distributed CRDT configuration
     pub distributed: Option<DistributedConfig>,
+
+    /// Optimistic-concurrency metadata transaction settings, used when
+    /// more than one machine mounts the same namespace read/write.
+    #[serde(default)]
+    pub transaction: TransactionConfig,
+}
+
+/// Optimistic-concurrency metadata transaction configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionConfig {
+    /// This machine's client id for prewrite lock ownership. Defaults to
+    /// the machine id if unset.
+    pub client_id: Option<String>,
+
+    /// How many times a transaction retries after losing a compare-and-swap
+    /// race before giving up with [`crate::error::Error::TransactionConflict`].
+    #[serde(default = "default_transaction_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_transaction_max_retries() -> u32 {
+    5
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        TransactionConfig { client_id: None, max_retries: default_transaction_max_retries() }
+    }
 }
 
 /// Master-replica configuration
@@ -234,6 +293,37 @@ pub struct NamespaceConfig {
     /// Access control rules
     #[serde(default)]
     pub access: Vec<AccessRule>,
+
+    /// Name of the `StorageTarget` (in `TelegramConfig::targets`) this
+    /// namespace's chunks are routed to. Falls back to
+    /// `TelegramConfig::default_target` when unset.
+    #[serde(default)]
+    pub storage_target: Option<String>,
+
+    /// Version-history/snapshot settings for this namespace. Per-namespace
+    /// since v2, rather than the single top-level setting legacy v1
+    /// `Config` had.
+    #[serde(default)]
+    pub versioning: VersioningConfig,
+
+    /// Per-namespace override of `ConfigV2::rate_limit`. `None` means this
+    /// namespace shares the top-level rate limit.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Block compression applied before encryption, via
+    /// `crate::chunk::compression`. Defaults to `CompressionAlgo::None`,
+    /// i.e. off.
+    #[serde(default)]
+    pub compression: crate::chunk::CompressionAlgo,
+
+    /// Target chunk size in bytes for files stored under this namespace.
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+}
+
+fn default_chunk_size() -> usize {
+    DEFAULT_CHUNK_SIZE
 }
 
 /// Logging configuration
@@ -255,6 +345,99 @@ impl Default for LoggingConfig {
     }
 }
 
+/// Opt-in crash report delivery settings. Building on the "usage
+/// tracking" intent behind [`EMBEDDED_API_ID`]/[`EMBEDDED_API_HASH`], this
+/// lets a panic's message and backtrace be delivered to a Telegram
+/// channel instead of only going to stderr.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReportConfig {
+    /// Whether panics are captured and delivered. Defaults to false so
+    /// this never leaks data silently.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Telegram destination crash reports are delivered to: an
+    /// `@username`, or `"me"` for Saved Messages. Required when `enabled`
+    /// is true; see `ConfigV2::validate`.
+    #[serde(default)]
+    pub channel: Option<String>,
+
+    /// Whether to include a symbolized backtrace in the report.
+    #[serde(default = "default_include_backtrace")]
+    pub include_backtrace: bool,
+}
+
+fn default_include_backtrace() -> bool {
+    true
+}
+
+impl Default for CrashReportConfig {
+    fn default() -> Self {
+        CrashReportConfig {
+            enabled: false,
+            channel: None,
+            include_backtrace: true,
+        }
+    }
+}
+
+/// Release channel an enabled updater checks against.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateChannel {
+    /// Tagged releases only
+    Stable,
+
+    /// Pre-release builds
+    Beta,
+}
+
+impl UpdateChannel {
+    /// The string this channel is sent to `check_url` as.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateChannel::Stable => "stable",
+            UpdateChannel::Beta => "beta",
+        }
+    }
+}
+
+impl Default for UpdateChannel {
+    fn default() -> Self {
+        UpdateChannel::Stable
+    }
+}
+
+/// Self-update settings for embedded-credential release builds, which
+/// aren't distributed through a package manager and so need their own
+/// update check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdaterConfig {
+    /// Whether `tgcryptfs update` checks for and installs new releases.
+    /// Defaults to false.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// URL returning a JSON `ReleaseInfo` document for `channel`. Required
+    /// when `enabled` is true; see `ConfigV2::validate`.
+    #[serde(default)]
+    pub check_url: String,
+
+    /// Release channel to check.
+    #[serde(default)]
+    pub channel: UpdateChannel,
+}
+
+impl Default for UpdaterConfig {
+    fn default() -> Self {
+        UpdaterConfig {
+            enabled: false,
+            check_url: String::new(),
+            channel: UpdateChannel::Stable,
+        }
+    }
+}
+
 /// Main configuration structure (v2)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigV2 {
@@ -265,6 +448,10 @@ pub struct ConfigV2 {
     /// Machine identity
     pub machine: MachineConfig,
 
+    /// Deployment environment; see [`Environment`] for what it changes.
+    #[serde(default)]
+    pub environment: Environment,
+
     /// Telegram backend configuration
     pub telegram: TelegramConfig,
 
@@ -285,6 +472,39 @@ pub struct ConfigV2 {
     #[serde(default)]
     pub logging: LoggingConfig,
 
+    /// Opt-in crash report delivery settings
+    #[serde(default)]
+    pub crash_report: CrashReportConfig,
+
+    /// Self-update settings
+    #[serde(default)]
+    pub updater: UpdaterConfig,
+
+    /// Rate limit applied to outgoing Telegram API calls, shared by
+    /// namespaces that don't set `NamespaceConfig::rate_limit`.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    /// Optional secrets file (a YAML key/value map) whose entries are made
+    /// available to `${VAR}` substitution before real environment
+    /// variables are applied, so secrets need not be committed inline.
+    /// Relative paths are resolved against the directory of the config
+    /// file that references them.
+    #[serde(default)]
+    pub secrets_file: Option<PathBuf>,
+
+    /// RAID-style erasure-coded account pool configuration; `None` until
+    /// `tgcryptfs raid add-account` creates one.
+    #[serde(default)]
+    pub pool: Option<crate::raid::PoolConfig>,
+
+    /// Storage engine the metadata database is using. Change this with
+    /// `tgcryptfs convert-db --to <backend>`, not by editing it directly -
+    /// flipping it here only changes which engine *new* opens expect, it
+    /// doesn't move any data.
+    #[serde(default)]
+    pub metadata_backend: crate::metadata::BackendKind,
+
     /// Path to the data directory
     #[serde(skip)]
     pub data_dir: PathBuf,
@@ -300,6 +520,10 @@ pub struct Config {
     /// Telegram API configuration
     pub telegram: TelegramConfig,
 
+    /// Storage backend selection; defaults to Telegram.
+    #[serde(default)]
+    pub backend: BackendConfig,
+
     /// Encryption configuration
     pub encryption: EncryptionConfig,
 
@@ -315,8 +539,19 @@ pub struct Config {
     /// Version control configuration
     pub versioning: VersioningConfig,
 
+    /// Storage engine the metadata database is using
+    #[serde(default)]
+    pub metadata_backend: crate::metadata::BackendKind,
+
     /// Path to the data directory
     pub data_dir: PathBuf,
+
+    /// Directory holding `machines.toml`/`roles.toml`/`namespaces.toml`
+    /// for this mount's RBAC access rules - see
+    /// [`crate::distributed::namespace_config::load_config_dir`]. When
+    /// unset, every operation is allowed (the pre-ACL behavior).
+    #[serde(default)]
+    pub acl_config_dir: Option<PathBuf>,
 }
 
 /// Telegram API configuration
@@ -345,6 +580,139 @@ pub struct TelegramConfig {
 
     /// Base delay for exponential backoff (ms)
     pub retry_base_delay_ms: u64,
+
+    /// How often a long-lived connection sends a keepalive `Ping` while
+    /// idle, in seconds. Also the interval the background reconnect task
+    /// uses to notice a dead `SenderPool` and rebuild it.
+    #[serde(default = "default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+
+    /// Named storage destinations chunks can be routed to. Empty by
+    /// default, meaning all data goes to "Saved Messages" as before.
+    #[serde(default)]
+    pub targets: Vec<StorageTarget>,
+
+    /// Name of the `StorageTarget` used for a namespace that doesn't set
+    /// `NamespaceConfig::storage_target`. Required when `targets` is
+    /// non-empty.
+    #[serde(default)]
+    pub default_target: Option<String>,
+
+    /// Byte size of each range fetched by the segmented download path.
+    /// Documents larger than this are split into `upload.getFile` range
+    /// requests that are issued concurrently across the `SenderPool`
+    /// instead of streamed sequentially.
+    #[serde(default = "default_download_segment_size")]
+    pub download_segment_size: u64,
+
+    /// Maximum number of segment ranges downloaded concurrently for a
+    /// single document, bounded by `max_concurrent_downloads`.
+    #[serde(default = "default_download_segment_parallelism")]
+    pub download_segment_parallelism: usize,
+}
+
+fn default_download_segment_size() -> u64 {
+    512 * 1024
+}
+
+fn default_download_segment_parallelism() -> usize {
+    4
+}
+
+/// A named Telegram storage destination - a chat, channel, or "Saved
+/// Messages" - that a namespace's chunks can be routed to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageTarget {
+    /// Name referenced by `NamespaceConfig::storage_target` and
+    /// `TelegramConfig::default_target`.
+    pub name: String,
+
+    /// Destination identifier: a numeric chat/channel id, an `@username`,
+    /// or `"me"` for Saved Messages.
+    pub destination: String,
+}
+
+impl TelegramConfig {
+    /// Resolve which `StorageTarget` a namespace's chunks should be routed
+    /// to: the namespace's own `storage_target` if set, else
+    /// `default_target`, else `None` (meaning "Saved Messages").
+    pub fn resolve_target(&self, namespace: &NamespaceConfig) -> Option<&StorageTarget> {
+        let name = namespace
+            .storage_target
+            .as_deref()
+            .or(self.default_target.as_deref())?;
+        self.targets.iter().find(|t| t.name == name)
+    }
+}
+
+/// Which [`crate::backend::StorageBackend`] a mount talks to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    #[default]
+    Telegram,
+    Local,
+}
+
+impl BackendKind {
+    /// Parse a `--backend` CLI value, case-insensitively.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "telegram" => Ok(BackendKind::Telegram),
+            "local" => Ok(BackendKind::Local),
+            other => Err(Error::InvalidConfig(format!(
+                "Invalid backend '{}': expected telegram or local",
+                other
+            ))),
+        }
+    }
+}
+
+/// Storage backend selection and the settings each choice needs.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BackendConfig {
+    /// Which backend a mount uses.
+    #[serde(default)]
+    pub kind: BackendKind,
+
+    /// Directory [`crate::backend::LocalBackend`] stores chunks in, when
+    /// `kind` is [`BackendKind::Local`]. Defaults to `<data_dir>/chunks`.
+    #[serde(default)]
+    pub local_path: Option<PathBuf>,
+}
+
+/// Rate limit applied to outgoing Telegram API calls, backed by
+/// [`crate::rate_limit::TokenBucket`]. Telegram enforces its own flood
+/// limits and returns `FLOOD_WAIT` errors when a client exceeds them, so
+/// this lets the upload/download layer throttle itself proactively
+/// instead of discovering the limit the hard way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Steady-state request rate the bucket refills at.
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+
+    /// Maximum number of requests that can be made back-to-back before
+    /// the limiter starts imposing waits.
+    #[serde(default = "default_burst")]
+    pub burst: u64,
+}
+
+fn default_requests_per_second() -> f64 {
+    30.0
+}
+
+fn default_burst() -> u64 {
+    30
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            requests_per_second: default_requests_per_second(),
+            burst: default_burst(),
+        }
+    }
 }
 
 /// Encryption configuration
@@ -362,6 +730,15 @@ pub struct EncryptionConfig {
     /// Salt for key derivation (will be generated if not set)
     #[serde(with = "hex_serde")]
     pub salt: Vec<u8>,
+
+    /// AEAD cipher used for new encryptions. Defaults to
+    /// [`Algorithm::Aes256Gcm`]; switch to [`Algorithm::ChaCha20Poly1305`]
+    /// on hardware without AES-NI (ARM SBCs, cheap VPSes) for a throughput
+    /// win. Existing ciphertext stays readable after changing this -
+    /// [`EncryptedData`](crate::crypto::EncryptedData) tags every blob with
+    /// the algorithm that wrote it.
+    #[serde(default)]
+    pub algorithm: Algorithm,
 }
 
 /// Cache configuration
@@ -370,7 +747,10 @@ pub struct CacheConfig {
     /// Maximum cache size in bytes
     pub max_size: u64,
 
-    /// Cache directory path
+    /// Cache directory path. Leave unset (empty) to have
+    /// [`ConfigV2::load`] resolve it to `$XDG_CACHE_HOME/tgcryptfs` (or the
+    /// platform equivalent).
+    #[serde(default)]
     pub cache_dir: PathBuf,
 
     /// Enable prefetching
@@ -381,6 +761,73 @@ pub struct CacheConfig {
 
     /// Cache eviction policy
     pub eviction_policy: EvictionPolicy,
+
+    /// Write-back staging settings. Disabled by default, which keeps the
+    /// existing write-through behavior (upload inline, cache afterwards).
+    #[serde(default)]
+    pub write_back: WriteBackConfig,
+
+    /// Maximum number of chunk downloads a single read may have in flight
+    /// at once, across both the requested range and its read-ahead.
+    #[serde(default = "default_read_parallelism")]
+    pub read_parallelism: usize,
+
+    /// Maximum number of inodes [`crate::metadata::MetadataStore`] keeps
+    /// resident in its in-memory cache - see
+    /// [`crate::metadata::MetadataStore::with_inode_cache_capacity`].
+    #[serde(default = "default_inode_cache_capacity")]
+    pub inode_cache_capacity: u64,
+}
+
+fn default_read_parallelism() -> usize {
+    4
+}
+
+fn default_inode_cache_capacity() -> u64 {
+    10_000
+}
+
+fn default_keepalive_interval_secs() -> u64 {
+    60
+}
+
+/// Write-back cache configuration
+///
+/// When enabled, [`crate::cache::ChunkCache::put_dirty`] stages a chunk to
+/// disk and returns immediately instead of waiting on the Telegram round
+/// trip; a background worker pool uploads it afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteBackConfig {
+    /// Use write-back staging for new chunks instead of write-through.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Dirty-byte threshold that triggers proactive background flushing,
+    /// ahead of whatever the LRU eviction path would otherwise force.
+    #[serde(default = "default_write_back_high_water_mark")]
+    pub high_water_mark: u64,
+
+    /// Background upload worker threads.
+    #[serde(default = "default_write_back_workers")]
+    pub worker_threads: usize,
+}
+
+fn default_write_back_high_water_mark() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_write_back_workers() -> usize {
+    2
+}
+
+impl Default for WriteBackConfig {
+    fn default() -> Self {
+        WriteBackConfig {
+            enabled: false,
+            high_water_mark: default_write_back_high_water_mark(),
+            worker_threads: default_write_back_workers(),
+        }
+    }
 }
 
 /// Chunk configuration
@@ -395,8 +842,43 @@ pub struct ChunkConfig {
     /// Minimum size to compress (bytes)
     pub compression_threshold: usize,
 
+    /// zstd compression level used when a chunk is compressed
+    pub compression_level: i32,
+
     /// Enable content-based deduplication
     pub dedup_enabled: bool,
+
+    /// Split files using content-defined (rolling gear hash) chunk
+    /// boundaries instead of fixed-size ones. Keeps chunk boundaries -
+    /// and therefore chunk hashes - stable across a small in-place edit,
+    /// so only the chunks actually touched by a new [`FileVersion`](crate::metadata::FileVersion)
+    /// need re-uploading instead of every chunk from the edit point
+    /// onward.
+    #[serde(default = "default_content_defined_chunking")]
+    pub content_defined_chunking: bool,
+
+    /// Smallest chunk content-defined chunking will ever cut, bytes.
+    /// Ignored when `content_defined_chunking` is disabled.
+    #[serde(default = "default_min_chunk_size")]
+    pub min_chunk_size: usize,
+
+    /// Largest chunk content-defined chunking will ever cut before
+    /// forcing a boundary, bytes. Ignored when `content_defined_chunking`
+    /// is disabled.
+    #[serde(default = "default_max_chunk_size")]
+    pub max_chunk_size: usize,
+}
+
+fn default_content_defined_chunking() -> bool {
+    true
+}
+
+fn default_min_chunk_size() -> usize {
+    DEFAULT_CHUNK_SIZE / 4
+}
+
+fn default_max_chunk_size() -> usize {
+    DEFAULT_CHUNK_SIZE * 4
 }
 
 /// Mount configuration
@@ -422,6 +904,12 @@ pub struct MountConfig {
 
     /// GID for files
     pub gid: u32,
+
+    /// Path to the control socket a live mount serves its management API
+    /// on (see `tgcryptfs::control`). `None` defaults to
+    /// `data_dir/control.sock`.
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>,
 }
 
 /// Versioning configuration
@@ -462,6 +950,7 @@ impl Default for Config {
 
         Config {
             telegram: TelegramConfig::default(),
+            backend: BackendConfig::default(),
             encryption: EncryptionConfig::default(),
             cache: CacheConfig {
                 max_size: DEFAULT_CACHE_SIZE,
@@ -469,11 +958,16 @@ impl Default for Config {
                 prefetch_enabled: true,
                 prefetch_count: DEFAULT_PREFETCH_COUNT,
                 eviction_policy: EvictionPolicy::Lru,
+                write_back: WriteBackConfig::default(),
+                read_parallelism: default_read_parallelism(),
+                inode_cache_capacity: default_inode_cache_capacity(),
             },
             chunk: ChunkConfig::default(),
             mount: MountConfig::default(),
             versioning: VersioningConfig::default(),
+            metadata_backend: crate::metadata::BackendKind::default(),
             data_dir,
+            acl_config_dir: None,
         }
     }
 }
@@ -497,6 +991,11 @@ impl Default for TelegramConfig {
             max_concurrent_downloads: 5,
             retry_attempts: 3,
             retry_base_delay_ms: 1000,
+            keepalive_interval_secs: default_keepalive_interval_secs(),
+            targets: Vec::new(),
+            default_target: None,
+            download_segment_size: default_download_segment_size(),
+            download_segment_parallelism: default_download_segment_parallelism(),
         }
     }
 }
@@ -508,6 +1007,7 @@ impl Default for EncryptionConfig {
             argon2_iterations: 3,
             argon2_parallelism: 4,
             salt: Vec::new(), // Will be generated on first use
+            algorithm: Algorithm::default(),
         }
     }
 }
@@ -518,7 +1018,11 @@ impl Default for ChunkConfig {
             chunk_size: DEFAULT_CHUNK_SIZE,
             compression_enabled: true,
             compression_threshold: 1024, // Only compress if > 1KB
+            compression_level: 3,
             dedup_enabled: true,
+            content_defined_chunking: default_content_defined_chunking(),
+            min_chunk_size: default_min_chunk_size(),
+            max_chunk_size: default_max_chunk_size(),
         }
     }
 }
@@ -533,6 +1037,7 @@ impl Default for MountConfig {
             default_dir_mode: 0o755,
             uid: unsafe { libc::getuid() },
             gid: unsafe { libc::getgid() },
+            control_socket: None,
         }
     }
 }
@@ -708,51 +1213,222 @@ impl Default for DistributionConfig {
             cluster_id: None,
             master_replica: None,
             distributed: None,
+            transaction: TransactionConfig::default(),
         }
     }
 }
 
 impl Default for ConfigV2 {
     fn default() -> Self {
-        let data_dir = dirs::data_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("tgcryptfs");
+        let data_dir = default_data_dir();
+        let cache_dir = default_cache_dir();
 
         ConfigV2 {
             version: 2,
             machine: MachineConfig::default(),
+            environment: Environment::default(),
             telegram: TelegramConfig::default(),
             encryption: EncryptionConfig::default(),
             distribution: DistributionConfig::default(),
             namespaces: vec![],
             cache: CacheConfig {
                 max_size: DEFAULT_CACHE_SIZE,
-                cache_dir: data_dir.join("cache"),
+                cache_dir,
                 prefetch_enabled: true,
                 prefetch_count: DEFAULT_PREFETCH_COUNT,
                 eviction_policy: EvictionPolicy::Lru,
+                write_back: WriteBackConfig::default(),
+                read_parallelism: default_read_parallelism(),
+                inode_cache_capacity: default_inode_cache_capacity(),
             },
             logging: LoggingConfig::default(),
+            crash_report: CrashReportConfig::default(),
+            updater: UpdaterConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            secrets_file: None,
+            pool: None,
+            metadata_backend: crate::metadata::BackendKind::default(),
             data_dir,
         }
     }
 }
 
+/// Whether `path`'s extension marks it as YAML (`.yaml`/`.yml`) rather
+/// than the JSON fallback `ConfigV2::load`/`save` otherwise use.
+fn is_yaml_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+/// Validate a `RateLimitConfig`, labelling errors with `context` (e.g.
+/// `"rate_limit"` or `"namespace 'foo' rate_limit"`).
+fn validate_rate_limit(rate_limit: &RateLimitConfig, context: &str) -> Result<()> {
+    if rate_limit.requests_per_second <= 0.0 {
+        return Err(Error::InvalidConfig(format!(
+            "{}.requests_per_second must be greater than 0",
+            context
+        )));
+    }
+
+    if rate_limit.burst < 1 {
+        return Err(Error::InvalidConfig(format!(
+            "{}.burst must be at least 1",
+            context
+        )));
+    }
+
+    Ok(())
+}
+
+/// Platform-appropriate default data directory (`$XDG_DATA_HOME/tgcryptfs`
+/// on Linux, the OS equivalent elsewhere), used when `data_dir` is left
+/// unset in the config.
+fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("tgcryptfs")
+}
+
+/// Platform-appropriate default cache directory
+/// (`$XDG_CACHE_HOME/tgcryptfs` on Linux, the OS equivalent elsewhere),
+/// used when `cache.cache_dir` is left unset in the config.
+fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("tgcryptfs")
+}
+
+/// Expand a leading `~` to the home directory, then resolve a relative
+/// path against `base_dir` (the directory the config file lives in).
+/// Absolute paths are returned unchanged.
+fn resolve_config_path(path: &Path, base_dir: &Path) -> PathBuf {
+    let path = if let Ok(rest) = path.strip_prefix("~") {
+        dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    };
+
+    if path.is_relative() {
+        base_dir.join(path)
+    } else {
+        path
+    }
+}
+
+/// On Unix, returns `path`'s mode if it grants any "other" permission bits
+/// (world-readable, writable, or executable). Returns `None` if the path
+/// doesn't exist yet (nothing to check) or the platform can't express this.
+#[cfg(unix)]
+fn world_readable_mode(path: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            let mode = metadata.permissions().mode();
+            if mode & 0o077 != 0 {
+                Ok(Some(mode & 0o777))
+            } else {
+                Ok(None)
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Config(format!(
+            "Failed to stat secrets_file {:?}: {}",
+            path, e
+        ))),
+    }
+}
+
+#[cfg(not(unix))]
+fn world_readable_mode(_path: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Recursively merge `patch` into `base`, used by
+/// [`ConfigV2::load_layered`] to apply each layer in turn: objects merge
+/// key-by-key, anything else in `patch` replaces the corresponding value
+/// in `base` outright.
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(patch_map)) => {
+            for (key, value) in patch_map {
+                merge_json(base_map.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base_slot, patch_value) => {
+            *base_slot = patch_value;
+        }
+    }
+}
+
+/// Build a JSON patch from `TGCRYPTFS__`-prefixed environment variables
+/// for [`ConfigV2::load_layered`], nesting on `__`
+/// (`TGCRYPTFS__CACHE__MAX_SIZE=123` becomes `{"cache": {"max_size":
+/// 123}}`). Each value is parsed as JSON first, so numbers/bools/arrays
+/// come through typed, falling back to a plain string if that fails.
+fn env_override_layer() -> serde_json::Value {
+    let mut patch = serde_json::Value::Object(serde_json::Map::new());
+
+    for (key, value) in std::env::vars() {
+        let Some(rest) = key.strip_prefix("TGCRYPTFS__") else {
+            continue;
+        };
+
+        let path: Vec<String> = rest.to_lowercase().split("__").map(String::from).collect();
+        let parsed =
+            serde_json::from_str(&value).unwrap_or_else(|_| serde_json::Value::String(value));
+
+        set_json_path(&mut patch, &path, parsed);
+    }
+
+    patch
+}
+
+/// Set `value` at the nested `path` within `root`, creating intermediate
+/// objects as needed. Used by [`env_override_layer`].
+fn set_json_path(root: &mut serde_json::Value, path: &[String], value: serde_json::Value) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+
+    if !root.is_object() {
+        *root = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let map = root.as_object_mut().expect("just replaced with an object above");
+
+    if rest.is_empty() {
+        map.insert(first.clone(), value);
+    } else {
+        let entry = map
+            .entry(first.clone())
+            .or_insert(serde_json::Value::Object(serde_json::Map::new()));
+        set_json_path(entry, rest, value);
+    }
+}
+
 impl ConfigV2 {
-    /// Load configuration from a file (YAML or JSON), with environment variable substitution
+    /// Load configuration from a file (YAML or JSON), with environment
+    /// variable and secrets-file substitution
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path_ref = path.as_ref();
         let content = std::fs::read_to_string(path_ref).map_err(|e| {
             Error::Config(format!("Failed to read config file: {}", e))
         })?;
 
-        // Perform environment variable substitution
-        let content = Self::substitute_env_vars(&content);
+        let secrets = Self::load_secrets(&content, path_ref)?;
 
-        // Detect format by extension
-        let config: ConfigV2 = if path_ref.extension().and_then(|s| s.to_str()) == Some("yaml")
-            || path_ref.extension().and_then(|s| s.to_str()) == Some("yml")
-        {
+        // Perform environment variable / secrets-file substitution
+        let content = Self::substitute_env_vars(&content, &secrets)?;
+
+        // A document with no `version` field, or `version < 2`, is a
+        // legacy v1 `Config` - migrate it instead of parsing as `ConfigV2`.
+        let mut config = if Self::sniff_version(&content, path_ref) < 2 {
+            Self::migrate_legacy(path_ref, &content)?
+        } else if is_yaml_path(path_ref) {
             serde_yaml::from_str(&content).map_err(|e| {
                 Error::Config(format!("Failed to parse YAML config: {}", e))
             })?
@@ -762,14 +1438,23 @@ impl ConfigV2 {
             })?
         };
 
-        let mut config = config;
+        // Resolve data_dir and cache.cache_dir: an unset (empty) path
+        // falls back to the platform default, a leading `~` expands to the
+        // home directory, and anything still relative is resolved against
+        // the config file's own directory rather than the process cwd.
+        let base_dir = path_ref.parent().unwrap_or_else(|| Path::new("."));
 
-        // Set data_dir if not specified
-        if config.data_dir == PathBuf::new() {
-            config.data_dir = dirs::data_dir()
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join("tgcryptfs");
-        }
+        config.data_dir = if config.data_dir == PathBuf::new() {
+            default_data_dir()
+        } else {
+            resolve_config_path(&config.data_dir, base_dir)
+        };
+
+        config.cache.cache_dir = if config.cache.cache_dir == PathBuf::new() {
+            default_cache_dir()
+        } else {
+            resolve_config_path(&config.cache.cache_dir, base_dir)
+        };
 
         // Generate machine ID if set to "auto"
         if config.machine.id == "auto" {
@@ -780,33 +1465,199 @@ impl ConfigV2 {
         Ok(config)
     }
 
-    /// Substitute environment variables in config content
-    /// Supports ${VAR_NAME} syntax
-    fn substitute_env_vars(content: &str) -> String {
-        let mut result = content.to_string();
+    /// Sniff the `version` field out of (already-substituted) config
+    /// content without committing to the full `ConfigV2` shape, so `load`
+    /// can tell a legacy v1 document from a v2 one before parsing it.
+    /// Missing/unparseable content is treated as version 0.
+    fn sniff_version(content: &str, path: &Path) -> u32 {
+        #[derive(Deserialize, Default)]
+        struct VersionProbe {
+            #[serde(default)]
+            version: u32,
+        }
+
+        let probe: VersionProbe = if is_yaml_path(path) {
+            serde_yaml::from_str(content).unwrap_or_default()
+        } else {
+            serde_json::from_str(content).unwrap_or_default()
+        };
+
+        probe.version
+    }
+
+    /// Parse `content` as a legacy v1 `Config`, convert it with
+    /// [`Self::from_legacy`], and write the upgraded document back to
+    /// `path` (after copying the original to a sibling `.v1.bak` file) so
+    /// the migration happens exactly once and the next `load` sees a
+    /// plain v2 document.
+    fn migrate_legacy(path: &Path, content: &str) -> Result<Self> {
+        let legacy: Config = if is_yaml_path(path) {
+            serde_yaml::from_str(content).map_err(|e| {
+                Error::Config(format!("Failed to parse legacy YAML config: {}", e))
+            })?
+        } else {
+            serde_json::from_str(content).map_err(|e| {
+                Error::Config(format!("Failed to parse legacy JSON config: {}", e))
+            })?
+        };
+
+        let config = ConfigV2::from_legacy(legacy);
+
+        let mut backup_name = path.file_name().unwrap_or_default().to_os_string();
+        backup_name.push(".v1.bak");
+        let backup_path = path.with_file_name(backup_name);
+        std::fs::copy(path, &backup_path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to back up legacy config to {:?}: {}",
+                backup_path, e
+            ))
+        })?;
+
+        config.save(path)?;
+
+        Ok(config)
+    }
+
+    /// Convert a legacy v1 `Config` into `ConfigV2`: carries `telegram`,
+    /// `encryption` and `cache` across unchanged, folds the old top-level
+    /// `mount` and `versioning` settings into one default Standalone
+    /// namespace (since those are per-namespace as of v2), sets
+    /// `DistributionMode::Standalone`, and generates a fresh machine id.
+    pub fn from_legacy(legacy: Config) -> Self {
+        let compression = if legacy.chunk.compression_enabled {
+            crate::chunk::CompressionAlgo::Zstd {
+                level: legacy.chunk.compression_level,
+            }
+        } else {
+            crate::chunk::CompressionAlgo::None
+        };
+
+        let default_namespace = NamespaceConfig {
+            name: "default".to_string(),
+            namespace_type: NamespaceType::Standalone,
+            mount_point: Some(legacy.mount.mount_point),
+            master: None,
+            cluster: None,
+            access: Vec::new(),
+            storage_target: None,
+            versioning: legacy.versioning,
+            rate_limit: None,
+            compression,
+            chunk_size: legacy.chunk.chunk_size,
+        };
+
+        ConfigV2 {
+            version: 2,
+            machine: MachineConfig::default(),
+            environment: Environment::default(),
+            telegram: legacy.telegram,
+            encryption: legacy.encryption,
+            distribution: DistributionConfig::default(),
+            namespaces: vec![default_namespace],
+            cache: legacy.cache,
+            logging: LoggingConfig::default(),
+            crash_report: CrashReportConfig::default(),
+            updater: UpdaterConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            secrets_file: None,
+            pool: None,
+            metadata_backend: legacy.metadata_backend,
+            data_dir: legacy.data_dir,
+        }
+    }
+
+    /// Read the `secrets_file` referenced by the raw (pre-substitution)
+    /// config content, if any, and return its entries as a name/value map.
+    /// The secrets file itself is a plain YAML key/value map; relative
+    /// paths are resolved against `config_path`'s parent directory.
+    fn load_secrets(content: &str, config_path: &Path) -> Result<HashMap<String, String>> {
+        #[derive(Deserialize, Default)]
+        struct SecretsFileRef {
+            #[serde(default)]
+            secrets_file: Option<PathBuf>,
+        }
+
+        let secrets_ref: SecretsFileRef = if is_yaml_path(config_path) {
+            serde_yaml::from_str(content).unwrap_or_default()
+        } else {
+            serde_json::from_str(content).unwrap_or_default()
+        };
+
+        let Some(secrets_path) = secrets_ref.secrets_file else {
+            return Ok(HashMap::new());
+        };
+
+        let secrets_path = if secrets_path.is_relative() {
+            config_path
+                .parent()
+                .map(|dir| dir.join(&secrets_path))
+                .unwrap_or(secrets_path)
+        } else {
+            secrets_path
+        };
 
-        // Find all ${VAR_NAME} patterns
-        let re = regex::Regex::new(r"\$\{([A-Z_][A-Z0-9_]*)\}").unwrap();
+        let secrets_content = std::fs::read_to_string(&secrets_path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read secrets file {:?}: {}",
+                secrets_path, e
+            ))
+        })?;
 
-        for cap in re.captures_iter(content) {
-            let full_match = &cap[0];
-            let var_name = &cap[1];
+        serde_yaml::from_str(&secrets_content).map_err(|e| {
+            Error::Config(format!(
+                "Failed to parse secrets file {:?}: {}",
+                secrets_path, e
+            ))
+        })
+    }
 
-            if let Ok(value) = std::env::var(var_name) {
-                result = result.replace(full_match, &value);
+    /// Substitute variable references in config content. Supports
+    /// `${VAR}` (left untouched if unset, matching prior behavior),
+    /// `${VAR:-default}` (use `default` when `VAR` is unset or empty) and
+    /// `${VAR:?message}` (fail the load with `Error::Config(message)` when
+    /// `VAR` is unset or empty). `VAR` is looked up in the process
+    /// environment first, falling back to `secrets` - a real environment
+    /// variable always takes precedence over a `secrets_file` entry.
+    fn substitute_env_vars(content: &str, secrets: &HashMap<String, String>) -> Result<String> {
+        let re =
+            regex::Regex::new(r"\$\{([A-Z_][A-Z0-9_]*)(?::-([^}]*)|:\?([^}]*))?\}").unwrap();
+
+        let mut missing_required = None;
+        let substituted = re.replace_all(content, |caps: &regex::Captures| {
+            let var_name = &caps[1];
+            let raw_value = std::env::var(var_name)
+                .ok()
+                .or_else(|| secrets.get(var_name).cloned());
+
+            if let Some(default) = caps.get(2) {
+                raw_value
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or_else(|| default.as_str().to_string())
+            } else if let Some(message) = caps.get(3) {
+                match raw_value.filter(|v| !v.is_empty()) {
+                    Some(v) => v,
+                    None => {
+                        missing_required = Some(message.as_str().to_string());
+                        String::new()
+                    }
+                }
+            } else {
+                raw_value.unwrap_or_else(|| caps[0].to_string())
             }
+        });
+
+        if let Some(message) = missing_required {
+            return Err(Error::Config(message));
         }
 
-        result
+        Ok(substituted.into_owned())
     }
 
     /// Save configuration to a file (format determined by extension)
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path_ref = path.as_ref();
 
-        let content = if path_ref.extension().and_then(|s| s.to_str()) == Some("yaml")
-            || path_ref.extension().and_then(|s| s.to_str()) == Some("yml")
-        {
+        let content = if is_yaml_path(path_ref) {
             serde_yaml::to_string(self).map_err(|e| {
                 Error::Config(format!("Failed to serialize config to YAML: {}", e))
             })?
@@ -895,6 +1746,122 @@ impl ConfigV2 {
             }
         }
 
+        // Validate Telegram storage target routing
+        if !self.telegram.targets.is_empty() {
+            let default_target = self.telegram.default_target.as_deref().ok_or_else(|| {
+                Error::InvalidConfig(
+                    "telegram.default_target is required when telegram.targets is non-empty"
+                        .to_string(),
+                )
+            })?;
+            if !self.telegram.targets.iter().any(|t| t.name == default_target) {
+                return Err(Error::InvalidConfig(format!(
+                    "telegram.default_target '{}' does not match any target in telegram.targets",
+                    default_target
+                )));
+            }
+        }
+
+        for namespace in &self.namespaces {
+            if let Some(target_name) = &namespace.storage_target {
+                if !self.telegram.targets.iter().any(|t| &t.name == target_name) {
+                    return Err(Error::InvalidConfig(format!(
+                        "Namespace '{}': storage_target '{}' does not match any target in telegram.targets",
+                        namespace.name, target_name
+                    )));
+                }
+            }
+        }
+
+        // Crash reporting must name a destination when enabled, so it
+        // never silently fails to know where to send data.
+        if self.crash_report.enabled && self.crash_report.channel.is_none() {
+            return Err(Error::InvalidConfig(
+                "crash_report.channel is required when crash_report.enabled is true".to_string(),
+            ));
+        }
+
+        // An enabled updater with nowhere to check is just a silent no-op,
+        // so reject it instead of letting `tgcryptfs update` fail confusingly.
+        if self.updater.enabled && self.updater.check_url.is_empty() {
+            return Err(Error::InvalidConfig(
+                "updater.check_url is required when updater.enabled is true".to_string(),
+            ));
+        }
+
+        validate_rate_limit(&self.rate_limit, "rate_limit")?;
+        for namespace in &self.namespaces {
+            if let Some(rate_limit) = &namespace.rate_limit {
+                validate_rate_limit(
+                    rate_limit,
+                    &format!("namespace '{}' rate_limit", namespace.name),
+                )?;
+            }
+
+            if let crate::chunk::CompressionAlgo::Zstd { level } = namespace.compression {
+                if !crate::chunk::ZSTD_LEVEL_RANGE.contains(&level) {
+                    return Err(Error::InvalidConfig(format!(
+                        "Namespace '{}': zstd compression level {} is outside the supported range {:?}",
+                        namespace.name,
+                        level,
+                        crate::chunk::ZSTD_LEVEL_RANGE
+                    )));
+                }
+            }
+
+            if namespace.chunk_size == 0 {
+                return Err(Error::InvalidConfig(format!(
+                    "Namespace '{}': chunk_size must be greater than 0",
+                    namespace.name
+                )));
+            }
+            if namespace.chunk_size > 2 * 1024 * 1024 * 1024 {
+                return Err(Error::InvalidConfig(format!(
+                    "Namespace '{}': chunk_size exceeds Telegram's 2GB limit",
+                    namespace.name
+                )));
+            }
+        }
+
+        if self.environment == Environment::Production {
+            self.validate_production()?;
+        }
+
+        Ok(())
+    }
+
+    /// Extra checks that only apply in [`Environment::Production`]: things
+    /// that are convenient defaults for local testing but shouldn't make it
+    /// into a real deployment unnoticed.
+    fn validate_production(&self) -> Result<()> {
+        if matches!(
+            self.distribution.mode,
+            DistributionMode::MasterReplica | DistributionMode::Distributed
+        ) && self.telegram.phone.as_deref().unwrap_or("").is_empty()
+        {
+            return Err(Error::InvalidConfig(
+                "telegram.phone is required in production for master-replica/distributed mode"
+                    .to_string(),
+            ));
+        }
+
+        if self.machine.name == MachineConfig::default().name {
+            return Err(Error::InvalidConfig(
+                "machine.name must be set explicitly in production, not left as the default hostname".to_string(),
+            ));
+        }
+
+        // Best-effort: resolved relative to the current directory, since
+        // `validate()` doesn't have the original config path on hand.
+        if let Some(secrets_file) = &self.secrets_file {
+            if let Some(mode) = world_readable_mode(secrets_file)? {
+                return Err(Error::InvalidConfig(format!(
+                    "secrets_file {:?} is world-readable (mode {:o}); chmod it to 0600 or tighter",
+                    secrets_file, mode
+                )));
+            }
+        }
+
         Ok(())
     }
 
@@ -944,8 +1911,367 @@ impl ConfigV2 {
             config.machine.name = machine_name.trim().to_string();
         }
 
+        // TGCRYPTFS_ENV is the namespaced override and takes precedence
+        // over the bare ENVIRONMENT variable.
+        if let Ok(env) = std::env::var("ENVIRONMENT") {
+            config.environment = Environment::parse(&env)?;
+        }
+        if let Ok(env) = std::env::var("TGCRYPTFS_ENV") {
+            config.environment = Environment::parse(&env)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Build a `ConfigV2` by layering, in increasing priority: the
+    /// hardcoded [`Self::default`], an optional config file named by the
+    /// `TGCRYPTFS_CONFIG` environment variable (`.toml`, `.yaml`/`.yml`,
+    /// else JSON, auto-detected by extension), and finally
+    /// `TGCRYPTFS__`-prefixed environment variables, with `__` separating
+    /// nested keys (e.g. `TGCRYPTFS__DISTRIBUTION__CLUSTER_ID` overrides
+    /// `distribution.cluster_id`). Unlike [`Self::from_env`], which only
+    /// understands a fixed handful of `TELEGRAM_*` variables, this covers
+    /// every field and is meant for container/multi-environment
+    /// deployments where a single config file path is too rigid.
+    pub fn load_layered() -> Result<Self> {
+        let mut merged = serde_json::to_value(ConfigV2::default()).map_err(|e| {
+            Error::Config(format!("Failed to serialize default config: {}", e))
+        })?;
+
+        if let Ok(config_path) = std::env::var("TGCRYPTFS_CONFIG") {
+            let config_path = PathBuf::from(config_path);
+            let content = std::fs::read_to_string(&config_path).map_err(|e| {
+                Error::Config(format!("Failed to read config file {:?}: {}", config_path, e))
+            })?;
+            merge_json(&mut merged, Self::parse_layer(&config_path, &content)?);
+        }
+
+        merge_json(&mut merged, env_override_layer());
+
+        let mut config: ConfigV2 = serde_json::from_value(merged).map_err(|e| {
+            Error::Config(format!("Failed to build layered config: {}", e))
+        })?;
+
+        if config.data_dir == PathBuf::new() {
+            config.data_dir = dirs::data_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("tgcryptfs");
+        }
+
+        if config.machine.id == "auto" {
+            config.machine.id = Uuid::new_v4().to_string();
+        }
+
+        config.validate()?;
         Ok(config)
     }
+
+    /// Parse `content` into a JSON [`Value`](serde_json::Value) based on
+    /// `path`'s extension: `.toml`, `.yaml`/`.yml`, else JSON.
+    fn parse_layer(path: &Path, content: &str) -> Result<serde_json::Value> {
+        if matches!(path.extension().and_then(|s| s.to_str()), Some("toml")) {
+            let toml_value: toml::Value = toml::from_str(content).map_err(|e| {
+                Error::Config(format!("Failed to parse TOML config: {}", e))
+            })?;
+            serde_json::to_value(toml_value).map_err(|e| {
+                Error::Config(format!("Failed to convert TOML config: {}", e))
+            })
+        } else if is_yaml_path(path) {
+            let yaml_value: serde_yaml::Value = serde_yaml::from_str(content).map_err(|e| {
+                Error::Config(format!("Failed to parse YAML config: {}", e))
+            })?;
+            serde_json::to_value(yaml_value).map_err(|e| {
+                Error::Config(format!("Failed to convert YAML config: {}", e))
+            })
+        } else {
+            serde_json::from_str(content).map_err(|e| {
+                Error::Config(format!("Failed to parse JSON config: {}", e))
+            })
+        }
+    }
+
+    /// Interactively build a `ConfigV2` by prompting the user on stdin/stdout.
+    ///
+    /// Used by `tgcryptfs init --interactive` in place of hand-editing YAML.
+    /// Telegram credentials fall back to [`EMBEDDED_API_ID`]/[`EMBEDDED_API_HASH`]
+    /// and are only prompted for if those are unset. The result is validated
+    /// with [`Self::validate`] before being returned, so the caller only
+    /// needs to call [`Self::save`].
+    pub fn wizard() -> Result<Self> {
+        let mut config = ConfigV2::default();
+
+        println!("tgcryptfs setup wizard");
+        println!("======================");
+        println!();
+
+        config.machine.name = prompt_with_default("Machine name", &config.machine.name)?;
+        config.machine.id = Uuid::new_v4().to_string();
+
+        if config.telegram.api_id == 0 {
+            config.telegram.api_id = prompt_parse("Telegram API ID (from my.telegram.org)")?;
+        } else {
+            println!("Using embedded Telegram API ID");
+        }
+
+        if config.telegram.api_hash.is_empty() {
+            config.telegram.api_hash = prompt_required("Telegram API hash")?;
+        } else {
+            println!("Using embedded Telegram API hash");
+        }
+
+        let phone = prompt_optional("Phone number (optional, press enter to skip)")?;
+        if !phone.is_empty() {
+            config.telegram.phone = Some(phone);
+        }
+
+        let mode = prompt_choice(
+            "Distribution mode",
+            &["standalone", "master-replica", "distributed"],
+            "standalone",
+        )?;
+
+        config.distribution.mode = match mode.as_str() {
+            "master-replica" => {
+                let cluster_id = prompt_required("Cluster ID")?;
+                let role = prompt_choice("Role", &["master", "replica"], "master")?;
+                let master_id = if role == "replica" {
+                    prompt_required("Master machine ID")?
+                } else {
+                    config.machine.id.clone()
+                };
+                let sync_interval_secs = prompt_parse_with_default(
+                    "Sync interval (seconds)",
+                    DEFAULT_MASTER_REPLICA_SYNC_INTERVAL,
+                )?;
+
+                config.distribution.cluster_id = Some(cluster_id);
+                config.distribution.master_replica = Some(MasterReplicaConfig {
+                    role: if role == "replica" {
+                        ReplicaRole::Replica
+                    } else {
+                        ReplicaRole::Master
+                    },
+                    master_id,
+                    sync_interval_secs,
+                    snapshot_retention: default_snapshot_retention(),
+                });
+
+                DistributionMode::MasterReplica
+            }
+            "distributed" => {
+                let cluster_id = prompt_required("Cluster ID")?;
+                let sync_interval_ms = prompt_parse_with_default(
+                    "Sync interval (milliseconds)",
+                    DEFAULT_DISTRIBUTED_SYNC_INTERVAL,
+                )?;
+                let conflict_resolution = prompt_choice(
+                    "Conflict resolution strategy",
+                    &["last-write-wins", "manual", "merge"],
+                    "last-write-wins",
+                )?;
+
+                config.distribution.cluster_id = Some(cluster_id);
+                config.distribution.distributed = Some(DistributedConfig {
+                    sync_interval_ms,
+                    conflict_resolution: match conflict_resolution.as_str() {
+                        "manual" => ConflictResolution::Manual,
+                        "merge" => ConflictResolution::Merge,
+                        _ => ConflictResolution::LastWriteWins,
+                    },
+                    operation_log_retention_hours: default_op_retention(),
+                });
+
+                DistributionMode::Distributed
+            }
+            _ => DistributionMode::Standalone,
+        };
+
+        let data_dir = prompt_with_default(
+            "Data directory",
+            &default_data_dir().display().to_string(),
+        )?;
+        config.data_dir = PathBuf::from(data_dir);
+
+        let cache_dir = prompt_with_default(
+            "Cache directory",
+            &default_cache_dir().display().to_string(),
+        )?;
+        config.cache.cache_dir = PathBuf::from(cache_dir);
+
+        let namespace_name = prompt_with_default("First namespace name", "default")?;
+        let mount_point = prompt_with_default("Mount point", "/mnt/tgcryptfs")?;
+
+        let chunk_size = prompt_parse_with_default("Chunk size in bytes", DEFAULT_CHUNK_SIZE)?;
+
+        // Deduplication is intrinsic to content-addressed chunk storage
+        // (see `MetadataStore::save_chunk_ref`) - identical chunks are
+        // always stored once, regardless of this toggle. Compression is
+        // the only thing actually worth asking about here.
+        let compress = prompt_choice("Compress chunks before encryption?", &["yes", "no"], "yes")?;
+        let compression = if compress == "yes" {
+            let level = prompt_parse_with_default("zstd compression level", 3)?;
+            crate::chunk::CompressionAlgo::Zstd { level }
+        } else {
+            crate::chunk::CompressionAlgo::None
+        };
+
+        config.namespaces.push(NamespaceConfig {
+            name: namespace_name,
+            namespace_type: match config.distribution.mode {
+                DistributionMode::Standalone => NamespaceType::Standalone,
+                DistributionMode::MasterReplica => NamespaceType::MasterReplica,
+                DistributionMode::Distributed => NamespaceType::Distributed,
+            },
+            mount_point: Some(PathBuf::from(mount_point)),
+            master: config
+                .distribution
+                .master_replica
+                .as_ref()
+                .map(|m| m.master_id.clone()),
+            cluster: config.distribution.cluster_id.clone(),
+            access: Vec::new(),
+            storage_target: None,
+            versioning: VersioningConfig::default(),
+            rate_limit: None,
+            compression,
+            chunk_size,
+        });
+
+        let cache_size = prompt_parse_with_default("Cache size in bytes", DEFAULT_CACHE_SIZE)?;
+        let eviction_policy = prompt_choice("Cache eviction policy", &["lru", "lfu", "fifo"], "lru")?;
+        config.cache.max_size = cache_size;
+        config.cache.eviction_policy = match eviction_policy.as_str() {
+            "lfu" => EvictionPolicy::Lfu,
+            "fifo" => EvictionPolicy::Fifo,
+            _ => EvictionPolicy::Lru,
+        };
+
+        let setup_overlay = prompt_choice(
+            "Mount over an existing directory (overlay mode)?",
+            &["yes", "no"],
+            "no",
+        )?;
+        if setup_overlay == "yes" {
+            let lower_path = prompt_with_default(
+                "Lower layer path (read-only existing data)",
+                &dirs::home_dir()
+                    .unwrap_or_else(|| PathBuf::from("."))
+                    .display()
+                    .to_string(),
+            )?;
+            println!(
+                "Mount with `tgcryptfs mount {} --overlay --lower-path {}` to combine them \
+                 (overlay settings are passed at mount time, not stored in the config).",
+                config.namespaces[0]
+                    .mount_point
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default(),
+                lower_path
+            );
+        }
+
+        let setup_raid = prompt_choice(
+            "Set up a RAID-style erasure-coded account pool now?",
+            &["yes", "no"],
+            "no",
+        )?;
+        if setup_raid == "yes" {
+            println!(
+                "Add accounts to the pool with `tgcryptfs raid add-account --api-id <id> \
+                 --api-hash <hash> --session-file <path>` after finishing this wizard, \
+                 then migrate existing data with `tgcryptfs raid migrate-to-erasure`."
+            );
+        }
+
+        config.encryption.argon2_memory_kib = prompt_parse_with_default(
+            "Argon2 memory cost (KiB)",
+            config.encryption.argon2_memory_kib,
+        )?;
+        config.encryption.argon2_iterations = prompt_parse_with_default(
+            "Argon2 iterations",
+            config.encryption.argon2_iterations,
+        )?;
+        config.encryption.argon2_parallelism = prompt_parse_with_default(
+            "Argon2 parallelism",
+            config.encryption.argon2_parallelism,
+        )?;
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Prompt `label` and return the trimmed line the user typed, which may be empty.
+fn prompt_optional(label: &str) -> Result<String> {
+    print!("{}: ", label);
+    std::io::stdout()
+        .flush()
+        .map_err(|e| Error::Config(e.to_string()))?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| Error::Config(e.to_string()))?;
+
+    Ok(line.trim().to_string())
+}
+
+/// Prompt `label`, re-prompting until the user types a non-empty value.
+fn prompt_required(label: &str) -> Result<String> {
+    loop {
+        let value = prompt_optional(label)?;
+        if !value.is_empty() {
+            return Ok(value);
+        }
+        println!("A value is required.");
+    }
+}
+
+/// Prompt `label` showing `default` in brackets, returning it unchanged if
+/// the user just presses enter.
+fn prompt_with_default(label: &str, default: &str) -> Result<String> {
+    let value = prompt_optional(&format!("{} [{}]", label, default))?;
+    Ok(if value.is_empty() {
+        default.to_string()
+    } else {
+        value
+    })
+}
+
+/// Prompt `label` until the user types one of `choices` (or presses enter
+/// for `default`).
+fn prompt_choice(label: &str, choices: &[&str], default: &str) -> Result<String> {
+    loop {
+        let value = prompt_with_default(&format!("{} ({})", label, choices.join("/")), default)?;
+        if choices.contains(&value.as_str()) {
+            return Ok(value);
+        }
+        println!("Please enter one of: {}", choices.join(", "));
+    }
+}
+
+/// Prompt `label`, re-prompting until the user enters something that parses as `T`.
+fn prompt_parse<T: std::str::FromStr>(label: &str) -> Result<T> {
+    loop {
+        let value = prompt_required(label)?;
+        if let Ok(parsed) = value.parse() {
+            return Ok(parsed);
+        }
+        println!("Couldn't parse that value, please try again.");
+    }
+}
+
+/// Prompt `label` showing `default`, re-prompting until the user enters
+/// something that parses as `T` (or presses enter for `default`).
+fn prompt_parse_with_default<T: std::str::FromStr + ToString>(label: &str, default: T) -> Result<T> {
+    loop {
+        let value = prompt_with_default(label, &default.to_string())?;
+        if let Ok(parsed) = value.parse() {
+            return Ok(parsed);
+        }
+        println!("Couldn't parse that value, please try again.");
+    }
 }
 
 /// Hex serialization for byte arrays
@@ -0,0 +1,84 @@
+//! CLI-side control socket client.
+//!
+//! `ControlClient::connect` is how the one-shot `status`/`cache`/`sync`/
+//! `raid` subcommands notice a daemon already has the filesystem mounted
+//! and hand the request off to it, instead of reconnecting to the backend
+//! themselves - see each `cmd_*`'s daemon-first fallback in `main.rs`.
+
+use super::protocol::{read_response, write_request};
+use crate::distributed::SyncStatus;
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::net::UnixStream;
+
+pub struct ControlClient {
+    socket_path: PathBuf,
+}
+
+impl ControlClient {
+    /// Connects to `socket_path` if a daemon is listening there. Returns
+    /// `Ok(None)` - not an error - when nothing is listening, since a
+    /// mount simply not being up right now is the common case, not a
+    /// failure the caller needs reported.
+    pub async fn connect(socket_path: &Path) -> Result<Option<Self>> {
+        if !socket_path.exists() {
+            return Ok(None);
+        }
+        match UnixStream::connect(socket_path).await {
+            Ok(_) => Ok(Some(ControlClient { socket_path: socket_path.to_path_buf() })),
+            Err(_) => Ok(None),
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(&self, method: &str, path: &str, body: &[u8]) -> Result<T> {
+        let mut stream = UnixStream::connect(&self.socket_path).await?;
+        write_request(&mut stream, method, path, body).await?;
+        let response = read_response(&mut stream).await?;
+
+        if response.status >= 400 {
+            #[derive(serde::Deserialize)]
+            struct ErrorBody {
+                error: String,
+            }
+            let message = serde_json::from_slice::<ErrorBody>(&response.body)
+                .map(|e| e.error)
+                .unwrap_or_else(|_| format!("control socket returned status {}", response.status));
+            return Err(Error::Control(message));
+        }
+
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+
+    pub async fn status(&self) -> Result<Value> {
+        self.request("GET", "/status", b"").await
+    }
+
+    pub async fn cache_stats(&self) -> Result<crate::cache::CacheStats> {
+        self.request("GET", "/cache", b"").await
+    }
+
+    pub async fn cache_clear(&self) -> Result<()> {
+        let _: Value = self.request("DELETE", "/cache", b"").await?;
+        Ok(())
+    }
+
+    pub async fn sync(&self, full: bool) -> Result<SyncStatus> {
+        let body = serde_json::to_vec(&serde_json::json!({ "full": full }))?;
+        self.request("POST", "/sync", &body).await
+    }
+
+    pub async fn raid_status(&self) -> Result<Value> {
+        self.request("GET", "/raid", b"").await
+    }
+
+    pub async fn raid_scrub(&self, repair: bool) -> Result<Value> {
+        let body = serde_json::to_vec(&serde_json::json!({ "repair": repair }))?;
+        self.request("POST", "/raid/scrub", &body).await
+    }
+
+    pub async fn raid_rebuild(&self, account_id: u8) -> Result<Value> {
+        self.request("POST", &format!("/raid/rebuild/{account_id}"), b"").await
+    }
+}
@@ -0,0 +1,16 @@
+//! Local control-socket management API for a running mount.
+//!
+//! While a non-overlay mount is active it can serve a small HTTP/JSON API
+//! (see [`server::ControlServer`]) over a Unix socket at
+//! `MountConfig::control_socket` (default `data_dir/control.sock`), so the
+//! one-shot `status`/`cache`/`sync`/`raid` subcommands can query and drive
+//! it through [`client::ControlClient`] instead of reconnecting to the
+//! backend themselves - see each `cmd_*`'s daemon-first fallback in
+//! `main.rs`.
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+pub use client::ControlClient;
+pub use server::{ControlServer, ControlState};
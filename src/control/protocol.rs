@@ -0,0 +1,165 @@
+//! Minimal HTTP/1.1 framing for the control socket.
+//!
+//! The control API only ever serves a handful of fixed JSON routes to a
+//! single local client at a time, so this hand-rolls just enough of
+//! HTTP/1.1 - a request/status line, headers up to the blank line, and a
+//! `Content-Length`-delimited body - to avoid pulling in a full server
+//! framework for that.
+
+use crate::error::{Error, Result};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// A request as received by [`ControlServer`](super::server::ControlServer):
+/// method, path with any query string stripped, and body bytes.
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// A response as received by [`ControlClient`](super::client::ControlClient).
+#[derive(Debug)]
+pub struct ControlResponseRaw {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// A response to send back to the client: status code plus a JSON body.
+pub struct ControlResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl ControlResponse {
+    /// JSON body with an explicit status code.
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Result<Self> {
+        Ok(ControlResponse { status, body: serde_json::to_vec(value)? })
+    }
+
+    /// `200 OK` with a JSON body - the common case for every handler.
+    pub fn ok<T: Serialize>(value: &T) -> Result<Self> {
+        Self::json(200, value)
+    }
+
+    /// An error response; the body is `{"error": message}` so
+    /// [`ControlClient`](super::client::ControlClient) can surface it.
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        #[derive(Serialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        let body = serde_json::to_vec(&ErrorBody { error: message.into() }).unwrap_or_default();
+        ControlResponse { status, body }
+    }
+
+    fn reason(status: u16) -> &'static str {
+        match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Reads request line + headers + `Content-Length` body off `stream`.
+pub async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Result<ControlRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.trim_end().splitn(3, ' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let raw_path = parts.next().unwrap_or_default().to_string();
+    if method.is_empty() || raw_path.is_empty() {
+        return Err(Error::Control("malformed request line".to_string()));
+    }
+    let path = raw_path.split('?').next().unwrap_or(&raw_path).to_string();
+
+    let content_length = read_headers(&mut reader).await?;
+
+    let mut body = vec![0u8; content_length];
+    if content_length > 0 {
+        reader.read_exact(&mut body).await?;
+    }
+
+    Ok(ControlRequest { method, path, body })
+}
+
+/// Writes a request line + headers + body - the client-side counterpart
+/// to [`read_request`].
+pub async fn write_request(
+    stream: &mut (impl AsyncWrite + Unpin),
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<()> {
+    let header = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Writes a status line + headers + body - the server-side counterpart to
+/// [`read_response`].
+pub async fn write_response(stream: &mut (impl AsyncWrite + Unpin), response: &ControlResponse) -> Result<()> {
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        ControlResponse::reason(response.status),
+        response.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads a status line + headers + `Content-Length` body off `stream`.
+pub async fn read_response(stream: &mut (impl AsyncRead + Unpin)) -> Result<ControlResponseRaw> {
+    let mut reader = BufReader::new(stream);
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).await?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Error::Control(format!("malformed status line: {status_line:?}")))?;
+
+    let content_length = read_headers(&mut reader).await?;
+
+    let mut body = vec![0u8; content_length];
+    if content_length > 0 {
+        reader.read_exact(&mut body).await?;
+    }
+
+    Ok(ControlResponseRaw { status, body })
+}
+
+/// Consumes header lines up to the blank line separating them from the
+/// body, returning `Content-Length` (0 if absent).
+async fn read_headers(reader: &mut (impl AsyncBufReadExt + Unpin)) -> Result<usize> {
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    Ok(content_length)
+}
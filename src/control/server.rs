@@ -0,0 +1,322 @@
+//! The control socket server: a small JSON/HTTP API bound to a Unix
+//! socket, started alongside an active mount so `tgcryptfs status|cache|
+//! sync|raid` can drive it directly instead of reconnecting - see
+//! [`super::client::ControlClient`] and each `cmd_*`'s daemon-first
+//! fallback in `main.rs`.
+
+use super::protocol::{read_request, write_response, ControlRequest, ControlResponse};
+use crate::cache::ChunkCache;
+use crate::config::{BackendKind, ConfigV2};
+use crate::crypto::KeyManager;
+use crate::distributed::{OpLogManager, SnapshotManager, SyncConfig, SyncDaemon};
+use crate::error::{Error, Result};
+use crate::metadata::MetadataStore;
+use crate::raid::rebuild::{list_manifests, rebuild_account};
+use crate::raid::{AccountPool, Encoder};
+use crate::telegram::TelegramBackend;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Shared state handed to every connection the control server accepts.
+/// The cache/metadata/telegram/keys handles are the same `Arc`s backing
+/// the active mount - serving a request never opens a second Telegram
+/// connection or cache directory handle.
+pub struct ControlState {
+    /// Path to the config file, re-read for the `/raid` routes since
+    /// erasure-coded pool accounts are a separate credential set from the
+    /// mount's own backend and aren't held open between requests.
+    pub config_path: PathBuf,
+    pub mount_point: PathBuf,
+    pub started_at: Instant,
+    pub cache: Arc<ChunkCache>,
+    pub metadata: Arc<MetadataStore>,
+    pub telegram: Arc<TelegramBackend>,
+    pub keys: Arc<KeyManager>,
+    pub backend_kind: BackendKind,
+}
+
+/// Binds `socket_path` and serves the control API until dropped. Meant to
+/// be run on its own thread/runtime alongside the blocking `fuser::mount2`
+/// call that owns the main thread.
+pub struct ControlServer {
+    socket_path: PathBuf,
+    state: Arc<ControlState>,
+}
+
+impl ControlServer {
+    pub fn new(socket_path: PathBuf, state: ControlState) -> Self {
+        ControlServer { socket_path, state: Arc::new(state) }
+    }
+
+    /// Binds the socket - removing a stale file left behind by an unclean
+    /// shutdown first - and serves connections until the process exits.
+    pub async fn run(self) -> Result<()> {
+        if self.socket_path.exists() {
+            std::fs::remove_file(&self.socket_path)?;
+        }
+        if let Some(parent) = self.socket_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let listener = UnixListener::bind(&self.socket_path)?;
+        info!("Control socket listening at {:?}", self.socket_path);
+
+        loop {
+            let (stream, _addr) = listener.accept().await?;
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, state).await {
+                    warn!("Control connection error: {}", e);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(mut stream: UnixStream, state: Arc<ControlState>) -> Result<()> {
+    let request = read_request(&mut stream).await?;
+    let response = dispatch(&request, &state).await;
+    write_response(&mut stream, &response).await
+}
+
+async fn dispatch(request: &ControlRequest, state: &ControlState) -> ControlResponse {
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => handle_status(state).await,
+        ("GET", "/cache") => handle_cache_stats(state),
+        ("DELETE", "/cache") => handle_cache_clear(state),
+        ("POST", "/sync") => handle_sync(request, state).await,
+        ("GET", "/raid") => handle_raid_status(state).await,
+        ("POST", "/raid/scrub") => handle_raid_scrub(request, state).await,
+        (method, path) if method == "POST" && path.starts_with("/raid/rebuild/") => {
+            handle_raid_rebuild(path, state).await
+        }
+        _ => Err(Error::Control(format!("no such route: {} {}", request.method, request.path))),
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(e) => ControlResponse::error(status_for(&e), e.to_string()),
+    }
+}
+
+fn status_for(e: &Error) -> u16 {
+    match e {
+        Error::Control(msg) if msg.starts_with("no such route") => 404,
+        Error::InvalidConfig(_) => 400,
+        _ => 500,
+    }
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    mount_point: PathBuf,
+    pid: u32,
+    uptime_secs: u64,
+    backend: &'static str,
+    telegram_authorized: bool,
+}
+
+async fn handle_status(state: &ControlState) -> Result<ControlResponse> {
+    let telegram_authorized = match state.backend_kind {
+        BackendKind::Local => true,
+        BackendKind::Telegram => state.telegram.is_authorized().await.unwrap_or(false),
+    };
+
+    ControlResponse::ok(&StatusResponse {
+        mount_point: state.mount_point.clone(),
+        pid: std::process::id(),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        backend: match state.backend_kind {
+            BackendKind::Telegram => "telegram",
+            BackendKind::Local => "local",
+        },
+        telegram_authorized,
+    })
+}
+
+fn handle_cache_stats(state: &ControlState) -> Result<ControlResponse> {
+    ControlResponse::ok(&state.cache.stats())
+}
+
+fn handle_cache_clear(state: &ControlState) -> Result<ControlResponse> {
+    state.cache.clear()?;
+    #[derive(Serialize)]
+    struct Cleared {
+        cleared: bool,
+    }
+    ControlResponse::ok(&Cleared { cleared: true })
+}
+
+#[derive(Deserialize, Default)]
+struct SyncRequest {
+    #[serde(default)]
+    full: bool,
+}
+
+/// Metadata-store key the per-machine sync identity is minted under - kept
+/// in lockstep with `cmd_sync`'s copy in `main.rs` (a legacy v1 `Config`
+/// has no `MachineConfig` to persist it in instead).
+const SYNC_MACHINE_ID_KEY: &str = "sync_machine_id";
+
+async fn handle_sync(request: &ControlRequest, state: &ControlState) -> Result<ControlResponse> {
+    let params: SyncRequest = if request.body.is_empty() {
+        SyncRequest::default()
+    } else {
+        serde_json::from_slice(&request.body)?
+    };
+
+    let machine_id = match state.metadata.get_metadata(SYNC_MACHINE_ID_KEY)? {
+        Some(bytes) => Uuid::from_slice(&bytes).unwrap_or_else(|_| Uuid::new_v4()),
+        None => {
+            let id = Uuid::new_v4();
+            state.metadata.save_metadata(SYNC_MACHINE_ID_KEY, id.as_bytes())?;
+            id
+        }
+    };
+    let namespace_id = "default".to_string();
+    let master_key = state.keys.master_key();
+
+    let oplog = Arc::new(OpLogManager::new(
+        master_key.clone(),
+        state.telegram.clone(),
+        state.metadata.clone(),
+        machine_id,
+        namespace_id.clone(),
+    )?);
+    let snapshots = Arc::new(SnapshotManager::new(
+        master_key,
+        state.telegram.clone(),
+        state.metadata.clone(),
+        machine_id,
+        namespace_id.clone(),
+        10,
+    )?);
+    let daemon = SyncDaemon::new(oplog, snapshots, state.metadata.clone(), namespace_id, SyncConfig::default());
+    let status = daemon.sync(params.full).await?;
+    ControlResponse::ok(&status)
+}
+
+async fn handle_raid_status(state: &ControlState) -> Result<ControlResponse> {
+    let config = ConfigV2::load(&state.config_path)?;
+    let pool_config = config
+        .pool
+        .ok_or_else(|| Error::InvalidConfig("No pool configuration found.".to_string()))?;
+
+    if !pool_config.erasure.enabled {
+        #[derive(Serialize)]
+        struct Disabled {
+            erasure_enabled: bool,
+        }
+        return ControlResponse::ok(&Disabled { erasure_enabled: false });
+    }
+
+    let pool = AccountPool::new(pool_config)?;
+    if let Err(e) = pool.connect_all().await {
+        warn!("Could not connect to all RAID accounts: {}", e);
+    }
+    let health = pool.health();
+    pool.disconnect_all().await;
+
+    ControlResponse::ok(&health)
+}
+
+#[derive(Deserialize, Default)]
+struct ScrubRequest {
+    #[serde(default)]
+    repair: bool,
+}
+
+/// Bound on concurrent in-flight stripe scrubs - kept in lockstep with
+/// `cmd_raid_scrub`'s copy in `main.rs`.
+const SCRUB_PARALLELISM: usize = 4;
+
+async fn handle_raid_scrub(request: &ControlRequest, state: &ControlState) -> Result<ControlResponse> {
+    let params: ScrubRequest = if request.body.is_empty() {
+        ScrubRequest::default()
+    } else {
+        serde_json::from_slice(&request.body)?
+    };
+
+    let config = ConfigV2::load(&state.config_path)?;
+    let pool_config = config
+        .pool
+        .ok_or_else(|| Error::InvalidConfig("No pool configuration found.".to_string()))?;
+
+    let stripes: Vec<_> = list_manifests(&state.metadata)?
+        .into_iter()
+        .flat_map(|(_, manifest)| manifest.chunks.into_iter().map(|chunk| chunk.stripe))
+        .collect();
+    let stripes_scanned = stripes.len();
+
+    let pool = AccountPool::new(pool_config)?;
+    pool.connect_all().await?;
+    let summary = pool.scrub_all(stripes, SCRUB_PARALLELISM).await;
+    pool.disconnect_all().await;
+
+    #[derive(Serialize)]
+    struct ScrubResponse {
+        repair: bool,
+        stripes_scanned: usize,
+        clean: usize,
+        repaired: usize,
+        unrecoverable: usize,
+    }
+    ControlResponse::ok(&ScrubResponse {
+        repair: params.repair,
+        stripes_scanned,
+        clean: summary.clean,
+        repaired: summary.repaired,
+        unrecoverable: summary.unrecoverable,
+    })
+}
+
+async fn handle_raid_rebuild(path: &str, state: &ControlState) -> Result<ControlResponse> {
+    let account_id: u8 = path
+        .trim_start_matches("/raid/rebuild/")
+        .parse()
+        .map_err(|_| Error::InvalidConfig(format!("Invalid account id in path: {path}")))?;
+
+    let config = ConfigV2::load(&state.config_path)?;
+    let pool_config = config
+        .pool
+        .ok_or_else(|| Error::InvalidConfig("No pool configuration found.".to_string()))?;
+    if account_id as usize >= pool_config.accounts.len() {
+        return Err(Error::InvalidConfig(format!(
+            "Account {} not found. Valid range: 0-{}",
+            account_id,
+            pool_config.accounts.len() - 1
+        )));
+    }
+
+    let encoder = Encoder::new(pool_config.erasure.data_chunks, pool_config.erasure.total_chunks)?;
+    let pool = AccountPool::new(pool_config)?;
+    pool.connect_all().await?;
+    let report = rebuild_account(&pool, &state.metadata, &encoder, account_id).await;
+    pool.disconnect_all().await;
+    let report = report?;
+
+    #[derive(Serialize)]
+    struct RebuildResponse {
+        account_id: u8,
+        stripes_scanned: u64,
+        blocks_reconstructed: u64,
+        failures: u64,
+    }
+    ControlResponse::ok(&RebuildResponse {
+        account_id,
+        stripes_scanned: report.stripes_scanned,
+        blocks_reconstructed: report.blocks_reconstructed,
+        failures: report.failures,
+    })
+}
+
+/// Default control socket path under `data_dir`, used when
+/// `MountConfig::control_socket` isn't set.
+pub fn default_socket_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("control.sock")
+}
@@ -0,0 +1,117 @@
+//! Opt-in crash report delivery.
+//!
+//! When enabled via `CrashReportConfig`, installs a panic hook that
+//! captures the panic message and a symbolized backtrace, redacts the
+//! Telegram session file path and API hash, and uploads the report as a
+//! text message to the configured Telegram destination.
+
+use crate::config::{CrashReportConfig, TelegramConfig};
+use crate::telegram::TelegramBackend;
+use std::panic::PanicHookInfo;
+
+/// Install the crash-report panic hook. Does nothing if
+/// `crash_report.enabled` is false. The previous panic hook still runs
+/// first, so normal panic output on stderr is unaffected.
+pub fn install(telegram: TelegramConfig, crash_report: CrashReportConfig) {
+    if !crash_report.enabled {
+        return;
+    }
+
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let Some(channel) = crash_report.channel.clone() else {
+            return;
+        };
+
+        let report = build_report(info, &telegram, &crash_report);
+
+        // A panic hook can't assume an async runtime is already running,
+        // so spin up a throwaway one just for delivery.
+        if let Ok(runtime) = tokio::runtime::Runtime::new() {
+            runtime.block_on(deliver(&telegram, &channel, &report));
+        }
+    }));
+}
+
+/// Build the redacted report text for `info`.
+fn build_report(
+    info: &PanicHookInfo<'_>,
+    telegram: &TelegramConfig,
+    crash_report: &CrashReportConfig,
+) -> String {
+    let mut report = format!(
+        "tgcryptfs crash report\n\nPanic: {}\nLocation: {}\n",
+        panic_message(info),
+        info.location()
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| "unknown".to_string()),
+    );
+
+    if crash_report.include_backtrace {
+        report.push_str("\nBacktrace:\n");
+        report.push_str(&symbolized_backtrace());
+    }
+
+    redact(&report, telegram)
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+/// Capture the current backtrace with every frame's symbol explicitly
+/// demangled, so the report is human-readable rather than raw `_ZN...`
+/// mangled Rust symbols.
+fn symbolized_backtrace() -> String {
+    let mut out = String::new();
+    let mut index = 0usize;
+
+    backtrace::trace(|frame| {
+        backtrace::resolve_frame(frame, |symbol| {
+            let name = symbol
+                .name()
+                .map(|n| rustc_demangle::demangle(&n.to_string()).to_string())
+                .unwrap_or_else(|| "<unknown>".to_string());
+            out.push_str(&format!("  {:>3}: {}\n", index, name));
+            index += 1;
+        });
+        true
+    });
+
+    out
+}
+
+/// Strip values that shouldn't leave the machine: the session file path
+/// and the Telegram API hash.
+fn redact(report: &str, telegram: &TelegramConfig) -> String {
+    let mut redacted = report.to_string();
+
+    if let Some(session_path) = telegram.session_file.to_str() {
+        if !session_path.is_empty() {
+            redacted = redacted.replace(session_path, "[REDACTED session_file]");
+        }
+    }
+
+    if !telegram.api_hash.is_empty() {
+        redacted = redacted.replace(&telegram.api_hash, "[REDACTED api_hash]");
+    }
+
+    redacted
+}
+
+async fn deliver(telegram: &TelegramConfig, channel: &str, report: &str) {
+    let backend = TelegramBackend::new(telegram.clone());
+    if backend.connect().await.is_err() {
+        return;
+    }
+    let _ = backend.send_text(channel, report).await;
+}
@@ -1,20 +1,119 @@
-//! AES-256-GCM Encryption Implementation
+//! AEAD Encryption Implementation
 //!
-//! All data is encrypted using AES-256-GCM which provides:
+//! `encrypt`/`decrypt` give algorithm agility over a single AEAD interface:
+//! - AES-256-GCM (the long-standing default)
+//! - ChaCha20-Poly1305 (software-friendly alternative, same 96-bit nonce)
+//! - XChaCha20-Poly1305 (192-bit nonce - random nonces are collision-safe
+//!   for the life of a key, unlike the 96-bit algorithms above)
+//!
+//! All three provide:
 //! - Confidentiality: Data is encrypted
 //! - Integrity: Any tampering is detected
 //! - Authentication: Verifies the data came from the key holder
 
-use crate::crypto::{KEY_SIZE, NONCE_SIZE, TAG_SIZE};
+use crate::crypto::{KEY_SIZE, NONCE_SIZE, TAG_SIZE, XNONCE_SIZE};
 use crate::error::{Error, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
 use rand::RngCore;
-use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, CHACHA20_POLY1305};
 use serde::{Deserialize, Serialize};
 
+/// Which AEAD cipher produced an [`EncryptedData`]'s ciphertext. Stored
+/// alongside the ciphertext (as a one-byte tag in [`EncryptedData::to_bytes`])
+/// so blobs encrypted under different algorithms can coexist and
+/// `from_bytes` knows which one to use without being told out of band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    /// AES-256 in Galois/Counter Mode - 96-bit nonce.
+    Aes256Gcm,
+    /// ChaCha20-Poly1305 - 96-bit nonce, software-friendly.
+    ChaCha20Poly1305,
+    /// XChaCha20-Poly1305 - 192-bit extended nonce. Random nonces never
+    /// need a birthday-bound argument: at 192 bits, collision is not a
+    /// practical concern for any key's lifetime.
+    XChaCha20Poly1305,
+}
+
+impl Algorithm {
+    /// The one-byte tag this algorithm is identified by in [`EncryptedData::to_bytes`].
+    fn tag(self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => 0,
+            Algorithm::ChaCha20Poly1305 => 1,
+            Algorithm::XChaCha20Poly1305 => 2,
+        }
+    }
+
+    /// Recover an [`Algorithm`] from the tag byte [`Algorithm::tag`] wrote.
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Algorithm::Aes256Gcm),
+            1 => Ok(Algorithm::ChaCha20Poly1305),
+            2 => Ok(Algorithm::XChaCha20Poly1305),
+            other => Err(Error::Decryption(format!("unknown algorithm tag: {other}"))),
+        }
+    }
+
+    /// Nonce length this algorithm requires.
+    fn nonce_size(self) -> usize {
+        match self {
+            Algorithm::Aes256Gcm | Algorithm::ChaCha20Poly1305 => NONCE_SIZE,
+            Algorithm::XChaCha20Poly1305 => XNONCE_SIZE,
+        }
+    }
+}
+
+impl Default for Algorithm {
+    /// AES-256-GCM, accelerated by AES-NI on most server and desktop CPUs.
+    /// [`Algorithm::ChaCha20Poly1305`] is the better default on hardware
+    /// without it (ARM SBCs, cheap VPSes) - callers on that hardware should
+    /// set it explicitly via [`crate::config::EncryptionConfig::algorithm`].
+    fn default() -> Self {
+        Algorithm::Aes256Gcm
+    }
+}
+
+/// Where an encryption's nonce comes from.
+pub enum NonceSource {
+    /// Draw a fresh random nonce for this encryption. Always safe for
+    /// [`Algorithm::XChaCha20Poly1305`]; safe for the 96-bit algorithms up
+    /// to roughly 2^32 encryptions under the same key before collision
+    /// risk becomes meaningful.
+    Random,
+    /// Derive the nonce from an externally-tracked monotonic counter -
+    /// every value must be used at most once per key, ever. Intended for
+    /// the 96-bit algorithms when a single key encrypts far more than
+    /// 2^32 messages over its lifetime (e.g. a metadata store's single
+    /// long-lived key), where counting is cheap and collision-proof where
+    /// randomness alone would eventually roll over.
+    Counter(u64),
+}
+
+fn generate_nonce(source: NonceSource, size: usize) -> Vec<u8> {
+    match source {
+        NonceSource::Random => {
+            let mut nonce = vec![0u8; size];
+            rand::thread_rng().fill_bytes(&mut nonce);
+            nonce
+        }
+        NonceSource::Counter(counter) => {
+            let mut nonce = vec![0u8; size];
+            let counter_bytes = counter.to_be_bytes();
+            let start = size - counter_bytes.len().min(size);
+            let take = counter_bytes.len().min(size);
+            nonce[start..].copy_from_slice(&counter_bytes[counter_bytes.len() - take..]);
+            nonce
+        }
+    }
+}
+
 /// Encrypted data container with nonce and authentication tag
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncryptedData {
-    /// Nonce used for encryption (unique per encryption)
+    /// Which cipher encrypted `ciphertext`.
+    pub algorithm: Algorithm,
+    /// Nonce used for encryption (unique per encryption, per key)
     #[serde(with = "serde_bytes")]
     pub nonce: Vec<u8>,
     /// Ciphertext with appended authentication tag
@@ -25,76 +124,131 @@ pub struct EncryptedData {
 impl EncryptedData {
     /// Get the total size of encrypted data
     pub fn size(&self) -> usize {
-        self.nonce.len() + self.ciphertext.len()
+        1 + self.nonce.len() + self.ciphertext.len()
     }
 
-    /// Serialize to bytes for storage
+    /// Serialize to bytes for storage: a one-byte algorithm tag, then the
+    /// nonce, then the ciphertext.
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(NONCE_SIZE + self.ciphertext.len());
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.push(self.algorithm.tag());
         bytes.extend_from_slice(&self.nonce);
         bytes.extend_from_slice(&self.ciphertext);
         bytes
     }
 
-    /// Deserialize from bytes
+    /// Deserialize from bytes, dispatching on the leading algorithm tag
+    /// so blobs written under any supported algorithm parse correctly.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < NONCE_SIZE + TAG_SIZE {
+        let (&tag, rest) = bytes
+            .split_first()
+            .ok_or_else(|| Error::Decryption("Data too short".to_string()))?;
+        let algorithm = Algorithm::from_tag(tag)?;
+        let nonce_size = algorithm.nonce_size();
+
+        if rest.len() < nonce_size + TAG_SIZE {
             return Err(Error::Decryption("Data too short".to_string()));
         }
 
         Ok(EncryptedData {
-            nonce: bytes[..NONCE_SIZE].to_vec(),
-            ciphertext: bytes[NONCE_SIZE..].to_vec(),
+            algorithm,
+            nonce: rest[..nonce_size].to_vec(),
+            ciphertext: rest[nonce_size..].to_vec(),
         })
     }
 }
 
-/// Encrypt data using AES-256-GCM
-///
-/// # Arguments
-/// * `key` - 256-bit encryption key
-/// * `plaintext` - Data to encrypt
-/// * `aad` - Additional authenticated data (optional, authenticated but not encrypted)
-///
-/// # Returns
-/// EncryptedData containing nonce and ciphertext with auth tag
-pub fn encrypt(key: &[u8; KEY_SIZE], plaintext: &[u8], aad: &[u8]) -> Result<EncryptedData> {
-    // Create the key
-    let unbound_key = UnboundKey::new(&AES_256_GCM, key)
+fn seal_ring(alg: &'static aead::Algorithm, key: &[u8; KEY_SIZE], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let unbound_key = UnboundKey::new(alg, key)
         .map_err(|_| Error::Encryption("Failed to create encryption key".to_string()))?;
     let sealing_key = LessSafeKey::new(unbound_key);
 
-    // Generate random nonce
     let mut nonce_bytes = [0u8; NONCE_SIZE];
-    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    nonce_bytes.copy_from_slice(nonce);
     let nonce = Nonce::assume_unique_for_key(nonce_bytes);
 
-    // Prepare buffer: plaintext + space for tag
     let mut in_out = plaintext.to_vec();
     in_out.reserve(TAG_SIZE);
-
-    // Encrypt in place
     sealing_key
         .seal_in_place_append_tag(nonce, Aad::from(aad), &mut in_out)
         .map_err(|_| Error::Encryption("Encryption failed".to_string()))?;
+    Ok(in_out)
+}
+
+fn open_ring(alg: &'static aead::Algorithm, key: &[u8; KEY_SIZE], nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let unbound_key = UnboundKey::new(alg, key)
+        .map_err(|_| Error::Decryption("Failed to create decryption key".to_string()))?;
+    let opening_key = LessSafeKey::new(unbound_key);
+
+    let mut nonce_bytes = [0u8; NONCE_SIZE];
+    nonce_bytes.copy_from_slice(nonce);
+    let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = opening_key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| Error::Decryption("Decryption failed - data corrupted or wrong key".to_string()))?;
+    Ok(plaintext.to_vec())
+}
+
+fn seal_xchacha(key: &[u8; KEY_SIZE], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XNonce::from_slice(nonce);
+    cipher
+        .encrypt(nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| Error::Encryption("Encryption failed".to_string()))
+}
 
-    Ok(EncryptedData {
-        nonce: nonce_bytes.to_vec(),
-        ciphertext: in_out,
-    })
+fn open_xchacha(key: &[u8; KEY_SIZE], nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XNonce::from_slice(nonce);
+    cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad })
+        .map_err(|_| Error::Decryption("Decryption failed - data corrupted or wrong key".to_string()))
 }
 
-/// Decrypt data using AES-256-GCM
+/// Encrypt `plaintext` under `algorithm` with a fresh random nonce.
+///
+/// # Arguments
+/// * `algorithm` - Which AEAD cipher to use
+/// * `key` - 256-bit encryption key
+/// * `plaintext` - Data to encrypt
+/// * `aad` - Additional authenticated data (optional, authenticated but not encrypted)
+pub fn encrypt(algorithm: Algorithm, key: &[u8; KEY_SIZE], plaintext: &[u8], aad: &[u8]) -> Result<EncryptedData> {
+    encrypt_with_nonce(algorithm, key, plaintext, aad, NonceSource::Random)
+}
+
+/// Encrypt `plaintext` under `algorithm`, drawing the nonce from `nonce_source`.
+///
+/// Use [`NonceSource::Counter`] for a key that will encrypt far more than
+/// ~2^32 messages over its lifetime under a 96-bit-nonce algorithm, so
+/// nonces never collide even though they aren't random.
+pub fn encrypt_with_nonce(
+    algorithm: Algorithm,
+    key: &[u8; KEY_SIZE],
+    plaintext: &[u8],
+    aad: &[u8],
+    nonce_source: NonceSource,
+) -> Result<EncryptedData> {
+    let nonce = generate_nonce(nonce_source, algorithm.nonce_size());
+
+    let ciphertext = match algorithm {
+        Algorithm::Aes256Gcm => seal_ring(&AES_256_GCM, key, &nonce, plaintext, aad)?,
+        Algorithm::ChaCha20Poly1305 => seal_ring(&CHACHA20_POLY1305, key, &nonce, plaintext, aad)?,
+        Algorithm::XChaCha20Poly1305 => seal_xchacha(key, &nonce, plaintext, aad)?,
+    };
+
+    Ok(EncryptedData { algorithm, nonce, ciphertext })
+}
+
+/// Decrypt `encrypted`, using whichever algorithm it was tagged with.
 ///
 /// # Arguments
 /// * `key` - 256-bit encryption key
 /// * `encrypted` - Encrypted data container
 /// * `aad` - Additional authenticated data (must match encryption)
-///
-/// # Returns
-/// Decrypted plaintext
 pub fn decrypt(key: &[u8; KEY_SIZE], encrypted: &EncryptedData, aad: &[u8]) -> Result<Vec<u8>> {
-    if encrypted.nonce.len() != NONCE_SIZE {
+    if encrypted.nonce.len() != encrypted.algorithm.nonce_size() {
         return Err(Error::Decryption(format!(
             "Invalid nonce length: {}",
             encrypted.nonce.len()
@@ -105,29 +259,17 @@ pub fn decrypt(key: &[u8; KEY_SIZE], encrypted: &EncryptedData, aad: &[u8]) -> R
         return Err(Error::Decryption("Ciphertext too short".to_string()));
     }
 
-    // Create the key
-    let unbound_key = UnboundKey::new(&AES_256_GCM, key)
-        .map_err(|_| Error::Decryption("Failed to create decryption key".to_string()))?;
-    let opening_key = LessSafeKey::new(unbound_key);
-
-    // Create nonce
-    let mut nonce_bytes = [0u8; NONCE_SIZE];
-    nonce_bytes.copy_from_slice(&encrypted.nonce);
-    let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-
-    // Decrypt in place
-    let mut in_out = encrypted.ciphertext.clone();
-    let plaintext = opening_key
-        .open_in_place(nonce, Aad::from(aad), &mut in_out)
-        .map_err(|_| Error::Decryption("Decryption failed - data corrupted or wrong key".to_string()))?;
-
-    Ok(plaintext.to_vec())
+    match encrypted.algorithm {
+        Algorithm::Aes256Gcm => open_ring(&AES_256_GCM, key, &encrypted.nonce, &encrypted.ciphertext, aad),
+        Algorithm::ChaCha20Poly1305 => open_ring(&CHACHA20_POLY1305, key, &encrypted.nonce, &encrypted.ciphertext, aad),
+        Algorithm::XChaCha20Poly1305 => open_xchacha(key, &encrypted.nonce, &encrypted.ciphertext, aad),
+    }
 }
 
-/// Encrypt with empty AAD (convenience function)
+/// Encrypt with AES-256-GCM, a random nonce, and empty AAD (convenience function)
 #[allow(dead_code)]
 pub fn encrypt_simple(key: &[u8; KEY_SIZE], plaintext: &[u8]) -> Result<EncryptedData> {
-    encrypt(key, plaintext, &[])
+    encrypt(Algorithm::Aes256Gcm, key, plaintext, &[])
 }
 
 /// Decrypt with empty AAD (convenience function)
@@ -182,7 +324,7 @@ mod tests {
         let plaintext = b"Secret data";
         let aad = b"file:1234";
 
-        let encrypted = encrypt(&key, plaintext, aad).unwrap();
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &key, plaintext, aad).unwrap();
         let decrypted = decrypt(&key, &encrypted, aad).unwrap();
 
         assert_eq!(decrypted, plaintext);
@@ -195,7 +337,7 @@ mod tests {
         let aad = b"file:1234";
         let wrong_aad = b"file:5678";
 
-        let encrypted = encrypt(&key, plaintext, aad).unwrap();
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &key, plaintext, aad).unwrap();
         let result = decrypt(&key, &encrypted, wrong_aad);
 
         assert!(result.is_err());
@@ -260,4 +402,70 @@ mod tests {
         let decrypted = decrypt_simple(&key, &restored).unwrap();
         assert_eq!(decrypted, plaintext);
     }
+
+    #[test]
+    fn test_chacha20poly1305_round_trip() {
+        let key = test_key();
+        let plaintext = b"chacha payload";
+
+        let encrypted = encrypt(Algorithm::ChaCha20Poly1305, &key, plaintext, b"aad").unwrap();
+        assert_eq!(encrypted.nonce.len(), NONCE_SIZE);
+
+        let decrypted = decrypt(&key, &encrypted, b"aad").unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_xchacha20poly1305_round_trip() {
+        let key = test_key();
+        let plaintext = b"xchacha payload";
+
+        let encrypted = encrypt(Algorithm::XChaCha20Poly1305, &key, plaintext, b"aad").unwrap();
+        assert_eq!(encrypted.nonce.len(), XNONCE_SIZE);
+
+        let decrypted = decrypt(&key, &encrypted, b"aad").unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_tag_round_trips_through_bytes_for_every_algorithm() {
+        let key = test_key();
+        for algorithm in [Algorithm::Aes256Gcm, Algorithm::ChaCha20Poly1305, Algorithm::XChaCha20Poly1305] {
+            let encrypted = encrypt(algorithm, &key, b"payload", &[]).unwrap();
+            let bytes = encrypted.to_bytes();
+            let restored = EncryptedData::from_bytes(&bytes).unwrap();
+            assert_eq!(restored.algorithm, algorithm);
+            assert_eq!(decrypt(&key, &restored, &[]).unwrap(), b"payload");
+        }
+    }
+
+    #[test]
+    fn test_blobs_from_different_algorithms_coexist() {
+        let key = test_key();
+        let gcm = encrypt(Algorithm::Aes256Gcm, &key, b"gcm", &[]).unwrap().to_bytes();
+        let xchacha = encrypt(Algorithm::XChaCha20Poly1305, &key, b"xchacha", &[]).unwrap().to_bytes();
+
+        assert_eq!(decrypt(&key, &EncryptedData::from_bytes(&gcm).unwrap(), &[]).unwrap(), b"gcm");
+        assert_eq!(decrypt(&key, &EncryptedData::from_bytes(&xchacha).unwrap(), &[]).unwrap(), b"xchacha");
+    }
+
+    #[test]
+    fn test_algorithm_defaults_to_aes256_gcm() {
+        assert_eq!(Algorithm::default(), Algorithm::Aes256Gcm);
+    }
+
+    #[test]
+    fn test_counter_nonce_is_deterministic_and_distinct() {
+        let key = test_key();
+
+        let first = encrypt_with_nonce(Algorithm::Aes256Gcm, &key, b"one", &[], NonceSource::Counter(0)).unwrap();
+        let second = encrypt_with_nonce(Algorithm::Aes256Gcm, &key, b"two", &[], NonceSource::Counter(1)).unwrap();
+        let first_again = encrypt_with_nonce(Algorithm::Aes256Gcm, &key, b"one", &[], NonceSource::Counter(0)).unwrap();
+
+        assert_ne!(first.nonce, second.nonce);
+        assert_eq!(first.nonce, first_again.nonce);
+
+        assert_eq!(decrypt(&key, &first, &[]).unwrap(), b"one");
+        assert_eq!(decrypt(&key, &second, &[]).unwrap(), b"two");
+    }
 }
@@ -4,10 +4,24 @@
 //! - Master Key: Derived from user password, protects metadata key and chunk keys
 //! - Metadata Key: Encrypts filesystem metadata
 //! - Chunk Keys: Per-chunk keys derived from master key + chunk ID
+//!
+//! That hierarchy has a cost: the password-derived [`MasterKey`] sits
+//! directly above every derived key, so changing the password changes
+//! `metadata_key` and every [`ChunkKey`] with it, which would invalidate
+//! every chunk already stored remotely. [`RootSecret`] breaks that link -
+//! a random 32-byte secret that `metadata_key`/`ChunkKey` derive from
+//! instead, wrapped for storage as a [`WrappedRootSecret`] under the
+//! password-derived [`MasterKey`] (now acting as a key-encryption key, or
+//! KEK). [`KeyManager::unlock`]/[`KeyManager::change_password`] build on
+//! this to turn a password change into rewrapping one 32-byte secret
+//! instead of re-encrypting the whole filesystem. Stores created before
+//! this existed have no root secret to unwrap; [`KeyManager::new`] is
+//! unchanged and keeps deriving straight from the password for them.
 
-use crate::crypto::{derive_key, KEY_SIZE, SALT_SIZE};
 use crate::config::EncryptionConfig;
+use crate::crypto::{decrypt, derive_key, encrypt, Algorithm, EncryptedData, KEY_SIZE, SALT_SIZE};
 use crate::error::{Error, Result};
+use rand::RngCore;
 use ring::hkdf::{self, Salt, HKDF_SHA256};
 use std::sync::Arc;
 use zeroize::{Zeroize, Zeroizing};
@@ -75,6 +89,11 @@ impl MasterKey {
     pub fn metadata_key(&self) -> Result<[u8; KEY_SIZE]> {
         self.derive_subkey(b"tgcryptfs-metadata-v1")
     }
+
+    /// Derive the extended attribute encryption key
+    pub fn xattr_key(&self) -> Result<[u8; KEY_SIZE]> {
+        self.derive_subkey(b"tgcryptfs-xattr-v1")
+    }
 }
 
 impl Drop for MasterKey {
@@ -83,6 +102,125 @@ impl Drop for MasterKey {
     }
 }
 
+/// Something [`ChunkKey::derive`] can derive purpose-specific subkeys
+/// from: either a password-derived [`MasterKey`] (stores that predate
+/// root-secret wrapping) or a [`RootSecret`] (enveloped stores).
+pub trait KeyMaterial {
+    /// Derive a subkey for `purpose` via HKDF-expand.
+    fn derive_subkey(&self, purpose: &[u8]) -> Result<[u8; KEY_SIZE]>;
+}
+
+impl KeyMaterial for MasterKey {
+    fn derive_subkey(&self, purpose: &[u8]) -> Result<[u8; KEY_SIZE]> {
+        MasterKey::derive_subkey(self, purpose)
+    }
+}
+
+/// Fixed, non-secret HKDF salt [`RootSecret`] derives subkeys under.
+/// Unlike [`MasterKey`], which salts with a random per-filesystem value to
+/// slow down rainbow-table attacks on a low-entropy password, the root
+/// secret is already 32 bytes of high-entropy randomness - domain
+/// separation from the purpose label alone is enough.
+const ROOT_SECRET_HKDF_SALT: &[u8] = b"tgcryptfs-root-secret-v1";
+
+/// HKDF purpose label for [`KeyManager::content_chunk_id`]'s keyed hash -
+/// distinct from `"tgcryptfs-chunk-v1:..."` ([`ChunkKey::derive`]) so a
+/// leaked content-id subkey can't be used to recover any chunk's
+/// encryption key.
+const DEDUP_HKDF_PURPOSE: &[u8] = b"tgcryptfs-dedup-v1";
+
+/// Additional authenticated data a [`RootSecret`] is wrapped under in a
+/// [`WrappedRootSecret`], so a wrapped root secret can't be mistaken for
+/// (or swapped in for) any other ciphertext encrypted under the same KEK.
+const ROOT_SECRET_WRAP_AAD: &[u8] = b"tgcryptfs-root-secret-wrap-v1";
+
+/// A random 32-byte secret that `metadata_key`, `xattr_key`, and every
+/// [`ChunkKey`] derive from once a store has been migrated to root-secret
+/// envelope wrapping. It never derives from the password, so it never
+/// changes when the password does - see [`WrappedRootSecret`].
+pub struct RootSecret {
+    key: Zeroizing<[u8; KEY_SIZE]>,
+}
+
+impl RootSecret {
+    /// Generate a fresh root secret. Called once, the first time a store
+    /// is opened under the envelope scheme.
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut key);
+        RootSecret {
+            key: Zeroizing::new(key),
+        }
+    }
+
+    /// Derive the metadata encryption key
+    pub fn metadata_key(&self) -> Result<[u8; KEY_SIZE]> {
+        KeyMaterial::derive_subkey(self, b"tgcryptfs-metadata-v1")
+    }
+
+    /// Derive the extended attribute encryption key
+    pub fn xattr_key(&self) -> Result<[u8; KEY_SIZE]> {
+        KeyMaterial::derive_subkey(self, b"tgcryptfs-xattr-v1")
+    }
+}
+
+impl KeyMaterial for RootSecret {
+    fn derive_subkey(&self, purpose: &[u8]) -> Result<[u8; KEY_SIZE]> {
+        let salt = Salt::new(HKDF_SHA256, ROOT_SECRET_HKDF_SALT);
+        let prk = salt.extract(self.key.as_ref());
+
+        let mut output = [0u8; KEY_SIZE];
+        prk.expand(&[purpose], HkdfKeyType)
+            .map_err(|_| Error::KeyDerivation("HKDF expansion failed".to_string()))?
+            .fill(&mut output)
+            .map_err(|_| Error::KeyDerivation("HKDF fill failed".to_string()))?;
+
+        Ok(output)
+    }
+}
+
+/// A [`RootSecret`] wrapped (AES-256-GCM) under a password-derived KEK,
+/// as persisted via [`crate::metadata::MetadataStore::save_wrapped_root_secret`].
+/// Safe to store and transmit - unwrapping it requires the KEK.
+#[derive(Clone)]
+pub struct WrappedRootSecret(EncryptedData);
+
+impl WrappedRootSecret {
+    /// Wrap `secret` under `kek`.
+    pub fn wrap(secret: &RootSecret, kek: &MasterKey) -> Result<Self> {
+        let data = encrypt(Algorithm::Aes256Gcm, kek.key(), secret.key.as_ref(), ROOT_SECRET_WRAP_AAD)?;
+        Ok(WrappedRootSecret(data))
+    }
+
+    /// Unwrap with `kek`, failing with [`Error::Decryption`] if it's the
+    /// wrong password's KEK.
+    pub fn unwrap_with(&self, kek: &MasterKey) -> Result<RootSecret> {
+        let raw = decrypt(kek.key(), &self.0, ROOT_SECRET_WRAP_AAD)?;
+        if raw.len() != KEY_SIZE {
+            return Err(Error::InvalidKeyLength {
+                expected: KEY_SIZE,
+                got: raw.len(),
+            });
+        }
+        let mut key = [0u8; KEY_SIZE];
+        key.copy_from_slice(&raw);
+        Ok(RootSecret {
+            key: Zeroizing::new(key),
+        })
+    }
+
+    /// Serialize for storage in [`crate::metadata::MetadataStore`]'s raw
+    /// metadata tree.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    /// Deserialize a blob previously produced by [`Self::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(WrappedRootSecret(EncryptedData::from_bytes(bytes)?))
+    }
+}
+
 /// Per-chunk encryption key
 #[derive(Clone)]
 pub struct ChunkKey {
@@ -90,10 +228,11 @@ pub struct ChunkKey {
 }
 
 impl ChunkKey {
-    /// Derive a chunk key from master key and chunk ID
-    pub fn derive(master: &MasterKey, chunk_id: &str) -> Result<Self> {
+    /// Derive a chunk key from some key material (a password-derived
+    /// [`MasterKey`] or a [`RootSecret`]) and chunk ID
+    pub fn derive<K: KeyMaterial>(source: &K, chunk_id: &str) -> Result<Self> {
         let purpose = format!("tgcryptfs-chunk-v1:{}", chunk_id);
-        let key = master.derive_subkey(purpose.as_bytes())?;
+        let key = source.derive_subkey(purpose.as_bytes())?;
 
         Ok(ChunkKey {
             key: Zeroizing::new(key),
@@ -119,38 +258,190 @@ impl hkdf::KeyType for HkdfKeyType {
 pub struct KeyManager {
     master_key: Arc<MasterKey>,
     metadata_key: [u8; KEY_SIZE],
+    xattr_key: [u8; KEY_SIZE],
+    /// Present once this store has been migrated to root-secret envelope
+    /// wrapping - `metadata_key`/`xattr_key`/`chunk_key` derive from it
+    /// instead of `master_key` so a password change never touches them.
+    envelope: Option<Envelope>,
+}
+
+/// The root-secret side of an envelope-wrapped [`KeyManager`]: the
+/// unwrapped secret itself plus the wrapped form currently persisted, so
+/// [`KeyManager::change_password`] can rewrap without needing the caller
+/// to hand the wrapped blob back in.
+struct Envelope {
+    root_secret: Arc<RootSecret>,
+    wrapped: WrappedRootSecret,
 }
 
 impl KeyManager {
-    /// Create a new key manager from a master key
+    /// Create a new key manager from a master key, deriving
+    /// `metadata_key`/`xattr_key`/chunk keys straight from it. This is the
+    /// pre-envelope scheme: changing the password changes every key this
+    /// manager hands out. Stores created under [`Self::unlock`] use that
+    /// instead.
     pub fn new(master_key: MasterKey) -> Result<Self> {
         let metadata_key = master_key.metadata_key()?;
+        let xattr_key = master_key.xattr_key()?;
 
         Ok(KeyManager {
             master_key: Arc::new(master_key),
             metadata_key,
+            xattr_key,
+            envelope: None,
         })
     }
 
+    /// Unlock (or initialize) a root-secret-enveloped store. `wrapped` is
+    /// the blob [`crate::metadata::MetadataStore::load_wrapped_root_secret`]
+    /// returned - `None` for a brand-new store, which generates a fresh
+    /// root secret and wraps it under the password-derived KEK. Returns
+    /// the manager plus the wrapped secret the caller must persist via
+    /// [`crate::metadata::MetadataStore::save_wrapped_root_secret`] (a
+    /// no-op write if `wrapped` was already `Some` and unchanged).
+    pub fn unlock(
+        password: &[u8],
+        config: &EncryptionConfig,
+        wrapped: Option<WrappedRootSecret>,
+    ) -> Result<(Self, WrappedRootSecret)> {
+        let kek = MasterKey::from_password(password, config)?;
+        let (root_secret, wrapped) = match wrapped {
+            Some(wrapped) => {
+                let root_secret = wrapped.unwrap_with(&kek)?;
+                (root_secret, wrapped)
+            }
+            None => {
+                let root_secret = RootSecret::generate();
+                let wrapped = WrappedRootSecret::wrap(&root_secret, &kek)?;
+                (root_secret, wrapped)
+            }
+        };
+
+        let metadata_key = root_secret.metadata_key()?;
+        let xattr_key = root_secret.xattr_key()?;
+
+        let manager = KeyManager {
+            master_key: Arc::new(kek),
+            metadata_key,
+            xattr_key,
+            envelope: Some(Envelope {
+                root_secret: Arc::new(root_secret),
+                wrapped: wrapped.clone(),
+            }),
+        };
+        Ok((manager, wrapped))
+    }
+
+    /// Re-wrap this store's root secret under `new_password` instead of
+    /// the password it was unlocked with. `metadata_key`, `xattr_key`, and
+    /// every [`ChunkKey`] this manager derives are unaffected - only the
+    /// small wrapped blob returned here needs to be persisted via
+    /// [`crate::metadata::MetadataStore::save_wrapped_root_secret`], so a
+    /// password change never re-encrypts a single chunk already stored.
+    ///
+    /// Fails with [`Error::Decryption`] if `old_password` doesn't unwrap
+    /// this store's current wrapped secret, and with
+    /// [`Error::NotImplemented`] if this manager predates envelope
+    /// wrapping (built via [`Self::new`]) - there's no root secret to
+    /// rewrap, so a password change there still requires re-encrypting
+    /// every derived key.
+    pub fn change_password(
+        &mut self,
+        old_password: &[u8],
+        new_password: &[u8],
+        config: &EncryptionConfig,
+    ) -> Result<WrappedRootSecret> {
+        let envelope = self.envelope.as_mut().ok_or_else(|| {
+            Error::NotImplemented(
+                "change_password requires a root-secret-enveloped store".to_string(),
+            )
+        })?;
+
+        let old_kek = MasterKey::from_existing(old_password, self.master_key.salt(), config)?;
+        // Unwrapping with `old_kek` both confirms `old_password` is
+        // correct and proves we're rewrapping the real root secret rather
+        // than trusting the caller's claim.
+        envelope.wrapped.unwrap_with(&old_kek)?;
+
+        let mut new_config = config.clone();
+        new_config.salt = Vec::new(); // force a fresh salt for the new password
+        let new_kek = MasterKey::from_password(new_password, &new_config)?;
+        let rewrapped = WrappedRootSecret::wrap(&envelope.root_secret, &new_kek)?;
+
+        envelope.wrapped = rewrapped.clone();
+        self.master_key = Arc::new(new_kek);
+        Ok(rewrapped)
+    }
+
     /// Get the metadata encryption key
     pub fn metadata_key(&self) -> &[u8; KEY_SIZE] {
         &self.metadata_key
     }
 
+    /// Get the extended attribute encryption key
+    pub fn xattr_key(&self) -> &[u8; KEY_SIZE] {
+        &self.xattr_key
+    }
+
     /// Get a chunk encryption key
     pub fn chunk_key(&self, chunk_id: &str) -> Result<ChunkKey> {
-        ChunkKey::derive(&self.master_key, chunk_id)
+        match &self.envelope {
+            Some(envelope) => ChunkKey::derive(envelope.root_secret.as_ref(), chunk_id),
+            None => ChunkKey::derive(self.master_key.as_ref(), chunk_id),
+        }
+    }
+
+    /// Derive the content-addressed id for a plaintext chunk: a BLAKE3
+    /// hash keyed with a subkey of this store's root secret (or master
+    /// key, pre-envelope), hex-encoded.
+    ///
+    /// Keying the hash - rather than the bare `blake3::hash(plaintext)`
+    /// [`crate::chunk::cdc::CdcChunk`] uses for its own content-defined
+    /// boundaries - means the id alone never reveals plaintext equality
+    /// to anyone without this key, e.g. Telegram noticing two uploads
+    /// share an id. Cross-file and cross-version dedup still works via
+    /// [`crate::metadata::MetadataStore::save_chunk_ref`]'s refcounting:
+    /// the hash is deterministic per key, so two chunks with identical
+    /// plaintext in the same store always land on the same id.
+    pub fn content_chunk_id(&self, plaintext: &[u8]) -> Result<String> {
+        let subkey = match &self.envelope {
+            Some(envelope) => envelope.root_secret.derive_subkey(DEDUP_HKDF_PURPOSE)?,
+            None => self.master_key.derive_subkey(DEDUP_HKDF_PURPOSE)?,
+        };
+        let hash = blake3::Hasher::new_keyed(&subkey).update(plaintext).finalize();
+        Ok(hash.to_hex().to_string())
     }
 
     /// Get the salt (needed for config persistence)
     pub fn salt(&self) -> &[u8; SALT_SIZE] {
         self.master_key.salt()
     }
+
+    /// Get a shared handle to the master key, e.g. for the distributed
+    /// sync types that need it directly rather than through chunk/metadata
+    /// derivation - see `control::server`'s `/sync` handler, which reuses
+    /// an already-connected mount's key manager instead of re-deriving one
+    /// from a second password prompt.
+    pub fn master_key(&self) -> Arc<MasterKey> {
+        self.master_key.clone()
+    }
 }
 
 impl Drop for KeyManager {
     fn drop(&mut self) {
         self.metadata_key.zeroize();
+        self.xattr_key.zeroize();
+    }
+}
+
+impl crate::cache::ChunkIdVerifier for KeyManager {
+    /// Re-derive `data`'s keyed content id and compare it to `chunk_id` -
+    /// see [`Self::content_chunk_id`]. Wired into a mount's
+    /// [`crate::cache::ChunkCache`] via `ChunkCache::set_id_verifier` so
+    /// corruption detection keeps working now that chunk ids are no
+    /// longer a bare, unkeyed hash of the plaintext.
+    fn verify(&self, chunk_id: &str, data: &[u8]) -> bool {
+        self.content_chunk_id(data).map(|id| id == chunk_id).unwrap_or(false)
     }
 }
 
@@ -164,6 +455,7 @@ mod tests {
             argon2_iterations: 1,
             argon2_parallelism: 1,
             salt: Vec::new(),
+            algorithm: crate::crypto::Algorithm::default(),
         }
     }
 
@@ -221,8 +513,147 @@ mod tests {
         let manager = KeyManager::new(master).unwrap();
 
         assert_eq!(manager.metadata_key().len(), KEY_SIZE);
+        assert_ne!(manager.metadata_key(), manager.xattr_key());
 
         let chunk_key = manager.chunk_key("test-chunk").unwrap();
         assert_eq!(chunk_key.key().len(), KEY_SIZE);
     }
+
+    #[test]
+    fn test_content_chunk_id_is_deterministic_and_content_sensitive() {
+        let config = test_config();
+        let master = MasterKey::from_password(b"password", &config).unwrap();
+        let manager = KeyManager::new(master).unwrap();
+
+        let id1 = manager.content_chunk_id(b"hello world").unwrap();
+        let id1_again = manager.content_chunk_id(b"hello world").unwrap();
+        let id2 = manager.content_chunk_id(b"goodbye world").unwrap();
+
+        assert_eq!(id1, id1_again);
+        assert_ne!(id1, id2);
+    }
+
+    #[test]
+    fn test_content_chunk_id_differs_across_stores_for_identical_plaintext() {
+        let config_a = test_config();
+        let master_a = MasterKey::from_password(b"password-a", &config_a).unwrap();
+        let manager_a = KeyManager::new(master_a).unwrap();
+
+        let config_b = test_config();
+        let master_b = MasterKey::from_password(b"password-b", &config_b).unwrap();
+        let manager_b = KeyManager::new(master_b).unwrap();
+
+        // Same plaintext, different stores: the id must not leak the
+        // match to anyone without one store's key.
+        assert_ne!(
+            manager_a.content_chunk_id(b"duplicate content").unwrap(),
+            manager_b.content_chunk_id(b"duplicate content").unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_wrapped_root_secret_round_trips() {
+        let config = test_config();
+        let kek = MasterKey::from_password(b"password", &config).unwrap();
+
+        let secret = RootSecret::generate();
+        let wrapped = WrappedRootSecret::wrap(&secret, &kek).unwrap();
+
+        let unwrapped = wrapped.unwrap_with(&kek).unwrap();
+        assert_eq!(secret.metadata_key().unwrap(), unwrapped.metadata_key().unwrap());
+    }
+
+    #[test]
+    fn test_wrapped_root_secret_rejects_wrong_kek() {
+        let config = test_config();
+        let kek = MasterKey::from_password(b"password", &config).unwrap();
+        let wrong_kek = MasterKey::from_password(b"wrong password", &config).unwrap();
+
+        let secret = RootSecret::generate();
+        let wrapped = WrappedRootSecret::wrap(&secret, &kek).unwrap();
+
+        assert!(wrapped.unwrap_with(&wrong_kek).is_err());
+    }
+
+    #[test]
+    fn test_wrapped_root_secret_survives_byte_round_trip() {
+        let config = test_config();
+        let kek = MasterKey::from_password(b"password", &config).unwrap();
+
+        let secret = RootSecret::generate();
+        let wrapped = WrappedRootSecret::wrap(&secret, &kek).unwrap();
+
+        let restored = WrappedRootSecret::from_bytes(&wrapped.to_bytes()).unwrap();
+        let unwrapped = restored.unwrap_with(&kek).unwrap();
+        assert_eq!(secret.metadata_key().unwrap(), unwrapped.metadata_key().unwrap());
+    }
+
+    #[test]
+    fn test_unlock_generates_a_root_secret_for_a_fresh_store() {
+        let mut config = test_config();
+
+        let (manager, wrapped) = KeyManager::unlock(b"password", &config, None).unwrap();
+        // Reopening re-derives the KEK from the same salt the first
+        // unlock picked, exactly like `cmd_mount` persisting
+        // `key_manager.salt()` into the on-disk config after first use.
+        config.salt = manager.salt().to_vec();
+
+        let (reopened, _) = KeyManager::unlock(b"password", &config, Some(wrapped)).unwrap();
+
+        assert_eq!(manager.metadata_key(), reopened.metadata_key());
+        assert_eq!(manager.xattr_key(), reopened.xattr_key());
+        assert_eq!(
+            manager.chunk_key("c").unwrap().key(),
+            reopened.chunk_key("c").unwrap().key()
+        );
+    }
+
+    #[test]
+    fn test_change_password_preserves_every_derived_key() {
+        let mut config = test_config();
+
+        let (mut manager, _wrapped) = KeyManager::unlock(b"old password", &config, None).unwrap();
+        config.salt = manager.salt().to_vec();
+        let metadata_key_before = *manager.metadata_key();
+        let xattr_key_before = *manager.xattr_key();
+        let chunk_key_before = *manager.chunk_key("c").unwrap().key();
+
+        let rewrapped = manager
+            .change_password(b"old password", b"new password", &config)
+            .unwrap();
+
+        // Every key this manager hands out is unaffected by the password
+        // change - only the wrapped blob on disk needs rewriting.
+        assert_eq!(manager.metadata_key(), &metadata_key_before);
+        assert_eq!(manager.xattr_key(), &xattr_key_before);
+        assert_eq!(manager.chunk_key("c").unwrap().key(), &chunk_key_before);
+
+        // The freshly rewrapped blob unlocks under the new password, to
+        // the same keys - a reopen after the password change needs no
+        // migration step.
+        let mut new_config = config.clone();
+        new_config.salt = manager.salt().to_vec();
+        let (reopened, _) =
+            KeyManager::unlock(b"new password", &new_config, Some(rewrapped)).unwrap();
+        assert_eq!(reopened.metadata_key(), &metadata_key_before);
+    }
+
+    #[test]
+    fn test_change_password_rejects_wrong_old_password() {
+        let config = test_config();
+        let (mut manager, _) = KeyManager::unlock(b"old password", &config, None).unwrap();
+
+        let result = manager.change_password(b"not the old password", b"new password", &config);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_change_password_without_envelope_is_not_implemented() {
+        let config = test_config();
+        let master = MasterKey::from_password(b"password", &config).unwrap();
+        let mut manager = KeyManager::new(master).unwrap();
+
+        let result = manager.change_password(b"password", b"new password", &config);
+        assert!(result.is_err());
+    }
 }
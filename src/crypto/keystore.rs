@@ -0,0 +1,241 @@
+//! Envelope encryption and online key rotation
+//!
+//! A [`MasterKey`] never touches bulk data directly. Instead a [`KeyStore`]
+//! issues short-lived data encryption keys (DEKs) and wraps each one under
+//! the master key before it ever leaves memory. Every ciphertext produced
+//! by [`KeyStore::encrypt`] is tagged with the `key_id` of the DEK that
+//! produced it; [`KeyStore::decrypt`] looks that DEK up and unwraps it
+//! before decrypting. [`KeyStore::rotate`] generates a fresh DEK and makes
+//! it active for new writes - existing ciphertext keeps its old `key_id`
+//! and keeps decrypting against the retained (wrapped) DEK it names, so
+//! rotation never requires touching data that's already been written.
+
+use crate::crypto::{decrypt, encrypt, Algorithm, EncryptedData, MasterKey, KEY_SIZE};
+use crate::error::{Error, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use zeroize::Zeroizing;
+
+/// Identifies a single data encryption key within a [`KeyStore`].
+pub type KeyId = String;
+
+/// Additional authenticated data under which DEKs are wrapped, so a
+/// wrapped DEK can't be mistaken for (or swapped in for) ordinary
+/// ciphertext encrypted under the master key.
+const WRAP_AAD: &[u8] = b"tgcryptfs-dek-wrap-v1";
+
+/// A short-lived key that encrypts/decrypts bulk data directly.
+struct DataEncryptionKey {
+    key: Zeroizing<[u8; KEY_SIZE]>,
+}
+
+/// Ciphertext tagged with the `key_id` of the DEK that produced it, so a
+/// [`KeyStore`] can find the right key to decrypt it with regardless of
+/// which DEK happens to be active now.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Which DEK in the owning [`KeyStore`] encrypted `data`.
+    pub key_id: KeyId,
+    /// The ciphertext itself.
+    pub data: EncryptedData,
+}
+
+/// Serializable form of a [`KeyStore`]: every DEK it has ever issued,
+/// wrapped under the master key, plus which one is active. DEK material
+/// never appears here in plaintext.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WrappedKeyStore {
+    active_key_id: KeyId,
+    wrapped_deks: HashMap<KeyId, EncryptedData>,
+}
+
+/// Holds every DEK a namespace has ever issued, each wrapped under the
+/// master key, and tracks which one is active for new writes.
+pub struct KeyStore {
+    active_key_id: KeyId,
+    deks: HashMap<KeyId, DataEncryptionKey>,
+    wrapped: HashMap<KeyId, EncryptedData>,
+}
+
+impl KeyStore {
+    /// Create a new key store with a freshly generated active DEK, wrapped
+    /// under `master`.
+    pub fn new(master: &MasterKey) -> Result<Self> {
+        let mut store = KeyStore {
+            active_key_id: KeyId::new(),
+            deks: HashMap::new(),
+            wrapped: HashMap::new(),
+        };
+        store.active_key_id = store.generate_and_wrap(master)?;
+        Ok(store)
+    }
+
+    /// Generate a fresh DEK, wrap it under `master`, and register it -
+    /// without changing which DEK is active.
+    fn generate_and_wrap(&mut self, master: &MasterKey) -> Result<KeyId> {
+        let mut raw = [0u8; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut raw);
+        let key_id = uuid::Uuid::new_v4().to_string();
+
+        let wrapped = encrypt(Algorithm::Aes256Gcm, master.key(), &raw, WRAP_AAD)?;
+        self.wrapped.insert(key_id.clone(), wrapped);
+        self.deks.insert(key_id.clone(), DataEncryptionKey { key: Zeroizing::new(raw) });
+        Ok(key_id)
+    }
+
+    /// The `key_id` new writes are tagged with.
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    /// Encrypt `plaintext` under the active DEK.
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Envelope> {
+        let dek = self
+            .deks
+            .get(&self.active_key_id)
+            .ok_or_else(|| Error::Internal("active DEK missing from key store".to_string()))?;
+        let data = encrypt(Algorithm::Aes256Gcm, &dek.key, plaintext, aad)?;
+        Ok(Envelope { key_id: self.active_key_id.clone(), data })
+    }
+
+    /// Decrypt `envelope` by looking up the DEK it names, which may be any
+    /// DEK this store has ever issued - not just the currently active one.
+    pub fn decrypt(&self, envelope: &Envelope, aad: &[u8]) -> Result<Vec<u8>> {
+        let dek = self
+            .deks
+            .get(&envelope.key_id)
+            .ok_or_else(|| Error::KeyNotFound(envelope.key_id.clone()))?;
+        decrypt(&dek.key, &envelope.data, aad)
+    }
+
+    /// Generate a new DEK, wrap it under `master`, and make it active for
+    /// future writes. Every DEK issued before this one is retained, so
+    /// ciphertext tagged with an older `key_id` still decrypts.
+    pub fn rotate(&mut self, master: &MasterKey) -> Result<KeyId> {
+        let key_id = self.generate_and_wrap(master)?;
+        self.active_key_id = key_id.clone();
+        Ok(key_id)
+    }
+
+    /// Export the wrapped DEKs for persistence (e.g. alongside a
+    /// namespace's other config). Unwrapped DEK material never leaves.
+    pub fn export(&self) -> WrappedKeyStore {
+        WrappedKeyStore {
+            active_key_id: self.active_key_id.clone(),
+            wrapped_deks: self.wrapped.clone(),
+        }
+    }
+
+    /// Rebuild a key store from its wrapped form, unwrapping every DEK
+    /// with `master`.
+    pub fn import(wrapped: WrappedKeyStore, master: &MasterKey) -> Result<Self> {
+        let mut deks = HashMap::with_capacity(wrapped.wrapped_deks.len());
+        for (key_id, wrapped_dek) in &wrapped.wrapped_deks {
+            let raw = decrypt(master.key(), wrapped_dek, WRAP_AAD)?;
+            if raw.len() != KEY_SIZE {
+                return Err(Error::InvalidKeyLength { expected: KEY_SIZE, got: raw.len() });
+            }
+            let mut key = [0u8; KEY_SIZE];
+            key.copy_from_slice(&raw);
+            deks.insert(key_id.clone(), DataEncryptionKey { key: Zeroizing::new(key) });
+        }
+
+        if !deks.contains_key(&wrapped.active_key_id) {
+            return Err(Error::KeyNotFound(wrapped.active_key_id));
+        }
+
+        Ok(KeyStore { active_key_id: wrapped.active_key_id, deks, wrapped: wrapped.wrapped_deks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncryptionConfig;
+
+    fn test_config() -> EncryptionConfig {
+        EncryptionConfig {
+            argon2_memory_kib: 1024,
+            argon2_iterations: 1,
+            argon2_parallelism: 1,
+            salt: Vec::new(),
+            algorithm: crate::crypto::Algorithm::default(),
+        }
+    }
+
+    fn test_master() -> MasterKey {
+        MasterKey::from_password(b"password", &test_config()).unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let master = test_master();
+        let store = KeyStore::new(&master).unwrap();
+
+        let envelope = store.encrypt(b"secret payload", b"aad").unwrap();
+        assert_eq!(envelope.key_id, store.active_key_id());
+
+        let plaintext = store.decrypt(&envelope, b"aad").unwrap();
+        assert_eq!(plaintext, b"secret payload");
+    }
+
+    #[test]
+    fn test_rotate_keeps_old_ciphertext_decryptable() {
+        let master = test_master();
+        let mut store = KeyStore::new(&master).unwrap();
+
+        let old_envelope = store.encrypt(b"before rotation", b"aad").unwrap();
+        let old_key_id = old_envelope.key_id.clone();
+
+        let new_key_id = store.rotate(&master).unwrap();
+        assert_ne!(new_key_id, old_key_id);
+        assert_eq!(store.active_key_id(), new_key_id);
+
+        // Old ciphertext, tagged with the retired key_id, still decrypts.
+        let plaintext = store.decrypt(&old_envelope, b"aad").unwrap();
+        assert_eq!(plaintext, b"before rotation");
+
+        // New writes are tagged with the new active key.
+        let new_envelope = store.encrypt(b"after rotation", b"aad").unwrap();
+        assert_eq!(new_envelope.key_id, new_key_id);
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let master = test_master();
+        let mut store = KeyStore::new(&master).unwrap();
+        store.rotate(&master).unwrap();
+
+        let old_envelope = store.encrypt(b"rotated in", b"aad").unwrap();
+
+        let wrapped = store.export();
+        let restored = KeyStore::import(wrapped, &master).unwrap();
+
+        assert_eq!(restored.active_key_id(), store.active_key_id());
+        assert_eq!(restored.decrypt(&old_envelope, b"aad").unwrap(), b"rotated in");
+    }
+
+    #[test]
+    fn test_decrypt_unknown_key_id_fails() {
+        let master = test_master();
+        let store = KeyStore::new(&master).unwrap();
+        let other = KeyStore::new(&master).unwrap();
+
+        let envelope = other.encrypt(b"foreign", b"aad").unwrap();
+        assert!(store.decrypt(&envelope, b"aad").is_err());
+    }
+
+    #[test]
+    fn test_import_wrong_master_fails() {
+        let master = test_master();
+        let store = KeyStore::new(&master).unwrap();
+        let wrapped = store.export();
+
+        let mut wrong_config = test_config();
+        wrong_config.salt = vec![0u8; crate::crypto::SALT_SIZE];
+        let wrong_master = MasterKey::from_password(b"different", &wrong_config).unwrap();
+
+        assert!(KeyStore::import(wrapped, &wrong_master).is_err());
+    }
+}
@@ -1,23 +1,29 @@
 //! Cryptography module for tgcryptfs
 //!
-//! Provides AES-256-GCM encryption with Argon2id key derivation.
+//! Provides algorithm-agile AEAD encryption (AES-256-GCM, ChaCha20-Poly1305,
+//! XChaCha20-Poly1305) with Argon2id key derivation.
 //! All data is encrypted before leaving the local system.
 
 mod encryption;
 mod kdf;
 mod keys;
+mod keystore;
 
-pub use encryption::{decrypt, encrypt, EncryptedData};
+pub use encryption::{decrypt, encrypt, encrypt_with_nonce, Algorithm, EncryptedData, NonceSource};
 pub use kdf::{derive_key, DerivedKey};
-pub use keys::{ChunkKey, KeyManager, MasterKey};
+pub use keys::{ChunkKey, KeyManager, KeyMaterial, MasterKey, RootSecret, WrappedRootSecret};
+pub use keystore::{Envelope, KeyId, KeyStore, WrappedKeyStore};
 
 /// Size of AES-256 key in bytes
 pub const KEY_SIZE: usize = 32;
 
-/// Size of GCM nonce in bytes
+/// Size of the 96-bit nonce used by AES-256-GCM and ChaCha20-Poly1305
 pub const NONCE_SIZE: usize = 12;
 
-/// Size of GCM authentication tag in bytes
+/// Size of the 192-bit extended nonce used by XChaCha20-Poly1305
+pub const XNONCE_SIZE: usize = 24;
+
+/// Size of the AEAD authentication tag in bytes
 pub const TAG_SIZE: usize = 16;
 
 /// Size of salt for key derivation
@@ -0,0 +1,178 @@
+//! FastCDC-style content-defined chunking for `Write` operations
+//!
+//! A `Write` that covers `offset..offset+length` as a single blob means
+//! any concurrent write touching an overlapping byte range of the same
+//! file becomes an unmergeable [`ConflictType::WriteWrite`](crate::distributed::crdt::ConflictType::WriteWrite)
+//! over the *whole* region, even when only a handful of bytes actually
+//! changed. [`chunk`] splits a write's payload into variable-size chunks
+//! at content-defined boundaries (a rolling gear hash, normalized toward
+//! [`AVG_CHUNK_SIZE`]) rather than fixed offsets, so inserting or
+//! deleting a few bytes only shifts the boundary of the chunk(s) it
+//! actually touches: everything before and after stays byte-identical
+//! and hashes the same, turning most "concurrent edits to different
+//! parts of a file" into chunk ranges that don't overlap at all.
+
+use std::sync::OnceLock;
+
+/// Below this many bytes into the current chunk, the gear hash is never
+/// tested: we don't want a lucky match producing a near-empty chunk.
+pub const MIN_CHUNK_SIZE: usize = 4 * 1024;
+/// Target chunk size. The cut-point mask tightens once a chunk reaches
+/// this size, biasing the distribution away from `MIN`/`MAX` and toward
+/// this value (FastCDC's "normalized chunking").
+pub const AVG_CHUNK_SIZE: usize = 16 * 1024;
+/// A cut is forced here even if the gear hash never satisfies its mask,
+/// bounding the worst-case chunk size.
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+// `AVG_CHUNK_SIZE` is a power of two, so `MASK_BEFORE_AVG`/`MASK_AFTER_AVG`
+// differ from a centered mask by one bit each: stricter (more bits set,
+// lower match probability) before the average size is reached, so chunks
+// aren't biased toward `MIN`; looser (fewer bits, higher probability)
+// after, so chunks aren't biased toward `MAX`.
+const AVG_MASK_BITS: u32 = AVG_CHUNK_SIZE.trailing_zeros();
+const MASK_BEFORE_AVG: u64 = (1u64 << (AVG_MASK_BITS + 1)) - 1;
+const MASK_AFTER_AVG: u64 = (1u64 << (AVG_MASK_BITS - 1)) - 1;
+
+/// A single content-defined chunk of a write payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Offset of this chunk within the file (base offset + position in
+    /// the payload).
+    pub offset: u64,
+    /// BLAKE3 hash of the chunk's bytes, hex-encoded.
+    pub data_hash: String,
+    /// Length of the chunk in bytes.
+    pub length: u64,
+}
+
+/// Precomputed pseudo-random gear table, one `u64` per byte value.
+/// Generated deterministically with SplitMix64 rather than pulled from a
+/// dependency: the chunker only needs the values to look unrelated to
+/// the byte they index, not to be cryptographically random.
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u64; 256];
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        for slot in table.iter_mut() {
+            seed = seed.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^= z >> 31;
+            *slot = z;
+        }
+        table
+    })
+}
+
+/// Split `data` into content-defined chunks, each reporting its offset
+/// (relative to `base_offset`, the file offset the payload starts at),
+/// BLAKE3 hash, and length.
+pub fn chunk(data: &[u8], base_offset: u64) -> Vec<Chunk> {
+    let table = gear_table();
+    let mut chunks = Vec::new();
+    let mut start = 0usize;
+
+    while start < data.len() {
+        let len = cut_point(&data[start..], table);
+        let slice = &data[start..start + len];
+        chunks.push(Chunk {
+            offset: base_offset + start as u64,
+            data_hash: blake3::hash(slice).to_hex().to_string(),
+            length: len as u64,
+        });
+        start += len;
+    }
+
+    chunks
+}
+
+/// Length of the next chunk to cut from the front of `data`: the
+/// earliest position at or after `MIN_CHUNK_SIZE` where the rolling gear
+/// hash satisfies the mask for its region, or `MAX_CHUNK_SIZE` (or
+/// `data.len()` if shorter) if none does.
+fn cut_point(data: &[u8], table: &[u64; 256]) -> usize {
+    let limit = MAX_CHUNK_SIZE.min(data.len());
+    if limit <= MIN_CHUNK_SIZE {
+        return limit;
+    }
+
+    let mut hash: u64 = 0;
+    for (i, &byte) in data[..limit].iter().enumerate() {
+        hash = (hash << 1).wrapping_add(table[byte as usize]);
+
+        let consumed = i + 1;
+        if consumed < MIN_CHUNK_SIZE {
+            continue;
+        }
+
+        let mask = if consumed < AVG_CHUNK_SIZE { MASK_BEFORE_AVG } else { MASK_AFTER_AVG };
+        if hash & mask == 0 {
+            return consumed;
+        }
+    }
+
+    limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_empty_payload_yields_no_chunks() {
+        assert!(chunk(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_reassembles_to_original_length_and_offsets() {
+        let data = vec![7u8; 200 * 1024];
+        let chunks = chunk(&data, 1_000);
+
+        let mut expected_offset = 1_000u64;
+        for c in &chunks {
+            assert_eq!(c.offset, expected_offset);
+            assert!(c.length as usize >= 1 && c.length as usize <= MAX_CHUNK_SIZE);
+            expected_offset += c.length;
+        }
+        assert_eq!(expected_offset, 1_000 + data.len() as u64);
+    }
+
+    #[test]
+    fn test_chunk_forces_cut_at_max_size_on_incompressible_data() {
+        // Bytes that never make the gear hash satisfy either mask: every
+        // chunk but the last must hit the MAX_CHUNK_SIZE forced cut.
+        let data: Vec<u8> = (0..300 * 1024).map(|i| (i % 256) as u8).collect();
+        let chunks = chunk(&data, 0);
+        assert!(chunks.len() > 1);
+        for c in &chunks[..chunks.len() - 1] {
+            assert_eq!(c.length as usize, MAX_CHUNK_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_edit_far_from_a_boundary_leaves_other_chunk_hashes_unchanged() {
+        let mut original = vec![0u8; 300 * 1024];
+        for (i, b) in original.iter_mut().enumerate() {
+            *b = ((i * 2654435761) % 256) as u8;
+        }
+
+        let before = chunk(&original, 0);
+
+        // Flip a single byte inside the last chunk only.
+        let last = before.last().unwrap();
+        let flip_at = last.offset as usize + last.length as usize / 2;
+        let mut edited = original.clone();
+        edited[flip_at] ^= 0xFF;
+
+        let after = chunk(&edited, 0);
+
+        // Every chunk before the edited one is untouched: same count,
+        // same offsets, same hashes.
+        assert!(before.len() <= after.len() + 1 && after.len() <= before.len() + 1);
+        let unaffected = before.len() - 1;
+        assert_eq!(&before[..unaffected], &after[..unaffected]);
+    }
+}
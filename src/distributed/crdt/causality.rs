@@ -0,0 +1,215 @@
+//! Causal-delivery buffer for CRDT operations
+//!
+//! Telegram's transport gives no ordering or exactly-once guarantees -
+//! messages can arrive retried or out of order. [`CrdtSync::merge_operations`]
+//! already resolves *conflicting* concurrent writes, but it still assumes
+//! whatever arrives is safe to apply immediately. [`CausalityBarrier`] sits
+//! in front of that: it holds back an operation until every operation its
+//! vector clock causally depends on has already been delivered, so a
+//! `SetAttr` that arrives before the `Create` it modifies never gets applied
+//! out of order.
+
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::CrdtOperation;
+use crate::distributed::VectorClock;
+
+/// Buffers [`CrdtOperation`]s that arrive before their causal dependencies
+/// and releases them, in dependency order, once those dependencies land.
+///
+/// Tracks one "delivered" counter per machine: the clock entry of the last
+/// operation from that machine that has actually been handed back to the
+/// caller. An incoming operation from machine `m` with clock `C` is
+/// deliverable iff it is the very next one expected from `m` (`C[m] ==
+/// delivered[m] + 1`) and it depends on nothing from any other machine that
+/// hasn't already been delivered (`C[k] <= delivered[k]` for every other
+/// `k`). Everything else waits in `buffered` until a later delivery makes it
+/// eligible.
+#[derive(Debug, Default)]
+pub struct CausalityBarrier {
+    delivered: VectorClock,
+    buffered: Vec<CrdtOperation>,
+}
+
+impl CausalityBarrier {
+    /// Create an empty barrier - nothing delivered yet from any machine.
+    pub fn new() -> Self {
+        Self {
+            delivered: VectorClock::new(),
+            buffered: Vec::new(),
+        }
+    }
+
+    /// Feed in a newly arrived operation and get back every operation -
+    /// possibly including ones buffered from earlier calls, and possibly
+    /// including `op` itself - that is now safe to apply, in causal order.
+    /// An empty result means `op` is still waiting on a dependency that
+    /// hasn't arrived yet.
+    pub fn deliver(&mut self, op: CrdtOperation) -> Vec<CrdtOperation> {
+        self.buffered.push(op);
+
+        let mut released = Vec::new();
+        loop {
+            let Some(index) = self.buffered.iter().position(|op| self.is_deliverable(op)) else {
+                break;
+            };
+            let op = self.buffered.remove(index);
+            self.delivered.merge(op.vector_clock());
+            released.push(op);
+        }
+
+        released
+    }
+
+    /// Whether `op` can be applied given everything delivered so far.
+    fn is_deliverable(&self, op: &CrdtOperation) -> bool {
+        let machine_id = op.machine_id();
+        let clock = op.vector_clock();
+
+        if clock.get(machine_id) != self.delivered.get(machine_id) + 1 {
+            return false;
+        }
+
+        clock
+            .machines()
+            .into_iter()
+            .filter(|&m| m != machine_id)
+            .all(|m| clock.get(m) <= self.delivered.get(m))
+    }
+
+    /// Vector clock of the latest contiguous run of operations delivered
+    /// so far.
+    pub fn delivered_clock(&self) -> &VectorClock {
+        &self.delivered
+    }
+
+    /// Operations still waiting on a causal dependency that hasn't arrived.
+    pub fn pending_count(&self) -> usize {
+        self.buffered.len()
+    }
+
+    /// Per-machine count of buffered operations, for diagnostics (e.g.
+    /// `tgcryptfs cluster status` flagging a machine that has stalled).
+    pub fn pending_by_machine(&self) -> HashMap<Uuid, usize> {
+        let mut counts = HashMap::new();
+        for op in &self.buffered {
+            *counts.entry(op.machine_id()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{FileType, InodeAttributes};
+    use std::time::SystemTime;
+
+    fn create_op(machine_id: Uuid, clock: VectorClock, name: &str) -> CrdtOperation {
+        CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: clock,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: name.to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        }
+    }
+
+    #[test]
+    fn test_delivers_immediately_when_no_dependency() {
+        let machine = Uuid::new_v4();
+        let mut clock = VectorClock::new();
+        clock.increment(machine);
+
+        let mut barrier = CausalityBarrier::new();
+        let released = barrier.deliver(create_op(machine, clock, "/a"));
+
+        assert_eq!(released.len(), 1);
+        assert_eq!(barrier.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_buffers_out_of_order_operation() {
+        let machine = Uuid::new_v4();
+
+        let mut clock1 = VectorClock::new();
+        clock1.increment(machine);
+        let mut clock2 = clock1.clone();
+        clock2.increment(machine);
+
+        let mut barrier = CausalityBarrier::new();
+
+        // op2 (clock {m:2}) arrives before op1 (clock {m:1}) - not
+        // deliverable yet since it isn't the next expected op from m.
+        let released = barrier.deliver(create_op(machine, clock2.clone(), "/b"));
+        assert!(released.is_empty());
+        assert_eq!(barrier.pending_count(), 1);
+
+        // op1 arrives - both op1 and the now-unblocked op2 release in order.
+        let released = barrier.deliver(create_op(machine, clock1, "/a"));
+        assert_eq!(released.len(), 2);
+        assert_eq!(released[0].vector_clock().get(machine), 1);
+        assert_eq!(released[1].vector_clock().get(machine), 2);
+        assert_eq!(barrier.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_waits_on_cross_machine_dependency() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+
+        let mut clock_a1 = VectorClock::new();
+        clock_a1.increment(machine_a);
+
+        // b's first op causally depends on a's first op having been seen.
+        let mut clock_b1 = clock_a1.clone();
+        clock_b1.increment(machine_b);
+
+        let mut barrier = CausalityBarrier::new();
+
+        let released = barrier.deliver(create_op(machine_b, clock_b1.clone(), "/b"));
+        assert!(released.is_empty(), "b's op depends on a's op, which hasn't arrived");
+
+        let released = barrier.deliver(create_op(machine_a, clock_a1, "/a"));
+        assert_eq!(released.len(), 2);
+        assert_eq!(released[0].machine_id(), machine_a);
+        assert_eq!(released[1].machine_id(), machine_b);
+    }
+
+    #[test]
+    fn test_concurrent_operations_from_different_machines_deliver_independently() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+
+        let mut clock_a = VectorClock::new();
+        clock_a.increment(machine_a);
+
+        let mut clock_b = VectorClock::new();
+        clock_b.increment(machine_b);
+
+        let mut barrier = CausalityBarrier::new();
+
+        assert_eq!(barrier.deliver(create_op(machine_a, clock_a, "/a")).len(), 1);
+        assert_eq!(barrier.deliver(create_op(machine_b, clock_b, "/b")).len(), 1);
+        assert_eq!(barrier.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_pending_by_machine() {
+        let machine = Uuid::new_v4();
+        let mut clock2 = VectorClock::new();
+        clock2.increment(machine);
+        clock2.increment(machine);
+
+        let mut barrier = CausalityBarrier::new();
+        barrier.deliver(create_op(machine, clock2, "/a"));
+
+        assert_eq!(barrier.pending_by_machine().get(&machine), Some(&1));
+    }
+}
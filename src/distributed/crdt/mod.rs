@@ -0,0 +1,2920 @@
+//! CRDT-based distributed write system for tgcryptfs
+//!
+//! This module implements Conflict-free Replicated Data Types (CRDTs) for
+//! distributed filesystem operations. It enables multiple nodes to perform
+//! concurrent writes with automatic conflict resolution.
+
+mod causality;
+mod proof;
+
+pub use causality::CausalityBarrier;
+pub use proof::ConflictProof;
+
+use crate::distributed::chunking;
+use crate::distributed::VectorClock;
+use crate::error::{Error, Result};
+use crate::metadata::{FileType, InodeAttributes};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Default write-ahead log path for a [`CrdtSync`] joining `data_dir` as a
+/// distributed node, used when nothing more specific is configured.
+pub fn default_oplog_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("crdt_oplog")
+}
+
+/// Observed-Remove Set (OR-Set): a CRDT set where concurrent add and remove
+/// of the same value resolves in favor of the add.
+///
+/// Each `add` tags the value with a fresh unique id; `remove` records every
+/// add-tag currently observed for that value as "removed". A value is a
+/// member of the set iff it has at least one add-tag that has not been
+/// observed-removed. Because merge only ever grows both tag sets, the
+/// operation is commutative, associative, and idempotent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrSet<T: Eq + std::hash::Hash + Clone> {
+    /// value -> set of live/observed add-tags
+    adds: HashMap<T, HashSet<Uuid>>,
+    /// value -> set of add-tags that have been removed
+    removes: HashMap<T, HashSet<Uuid>>,
+}
+
+impl<T: Eq + std::hash::Hash + Clone> OrSet<T> {
+    /// Create a new empty OR-Set
+    pub fn new() -> Self {
+        OrSet {
+            adds: HashMap::new(),
+            removes: HashMap::new(),
+        }
+    }
+
+    /// Add a value, tagging it with a fresh unique id. Returns the tag so
+    /// the caller can reference this specific add (e.g. for later removal).
+    pub fn add(&mut self, value: T) -> Uuid {
+        let tag = Uuid::new_v4();
+        self.adds.entry(value).or_default().insert(tag);
+        tag
+    }
+
+    /// Remove a value by moving every add-tag currently observed for it
+    /// into the removed set. A concurrent add the remover never observed
+    /// is untouched and so survives the merge.
+    pub fn remove(&mut self, value: &T) {
+        if let Some(tags) = self.adds.get(value) {
+            let tags = tags.clone();
+            self.removes.entry(value.clone()).or_default().extend(tags);
+        }
+    }
+
+    /// Check whether a value is currently a member of the set
+    pub fn contains(&self, value: &T) -> bool {
+        match self.adds.get(value) {
+            Some(tags) => {
+                let removed = self.removes.get(value);
+                tags.iter()
+                    .any(|tag| removed.map(|r| !r.contains(tag)).unwrap_or(true))
+            }
+            None => false,
+        }
+    }
+
+    /// Return all values currently in the set
+    pub fn values(&self) -> Vec<T> {
+        self.adds
+            .keys()
+            .filter(|v| self.contains(v))
+            .cloned()
+            .collect()
+    }
+
+    /// Add a value tagged with a caller-supplied id instead of a freshly
+    /// generated one. Used when the tag needs to come from elsewhere in
+    /// the system (e.g. an operation's `op_id`) so a later removal can
+    /// reference that exact add.
+    pub fn add_tagged(&mut self, value: T, tag: Uuid) {
+        self.adds.entry(value).or_default().insert(tag);
+    }
+
+    /// Remove a single observed add-tag for `value`, rather than every
+    /// tag currently known for it. Lets a remover record only the adds it
+    /// actually observed, leaving any tag it never saw untouched so a
+    /// concurrent add still survives the merge.
+    pub fn remove_tag(&mut self, value: &T, tag: Uuid) {
+        self.removes.entry(value.clone()).or_default().insert(tag);
+    }
+
+    /// Merge another OR-Set into this one. Union of adds and union of
+    /// removes; order-independent and idempotent.
+    pub fn merge(&mut self, other: &Self) {
+        for (value, tags) in &other.adds {
+            self.adds
+                .entry(value.clone())
+                .or_default()
+                .extend(tags.iter().copied());
+        }
+        for (value, tags) in &other.removes {
+            self.removes
+                .entry(value.clone())
+                .or_default()
+                .extend(tags.iter().copied());
+        }
+    }
+}
+
+impl<T: Eq + std::hash::Hash + Clone> Default for OrSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A type that can deterministically merge with another instance of
+/// itself. Implementations must make `merge` commutative, associative,
+/// and idempotent so replicas converge regardless of delivery order.
+pub trait Crdt {
+    /// Merge `other` into `self` in place.
+    fn merge(&mut self, other: &Self);
+}
+
+/// Last-Write-Wins register: a single value tagged with a logical
+/// timestamp and the node that wrote it. `merge` keeps the value with the
+/// greater `ts`, breaking ties on the greater `node` id so two replicas
+/// that wrote at the same millisecond still converge on the same winner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lww<T> {
+    /// Logical write timestamp in milliseconds.
+    pub ts: u64,
+    /// Node that produced this value; the tie-breaker when `ts` is equal.
+    pub node: Uuid,
+    /// The current value.
+    pub v: T,
+}
+
+impl<T: Clone> Lww<T> {
+    /// Create a register holding `v`, timestamped at the current wall
+    /// clock time.
+    pub fn new(node: Uuid, v: T) -> Self {
+        Lww {
+            ts: now_msec(),
+            node,
+            v,
+        }
+    }
+
+    /// Set a new value. The timestamp always advances past the register's
+    /// current one, so updates stay ordered even if the wall clock hasn't
+    /// moved (or has gone backwards) since the last write.
+    pub fn update(&mut self, node: Uuid, v: T) {
+        self.ts = (self.ts + 1).max(now_msec());
+        self.node = node;
+        self.v = v;
+    }
+}
+
+impl<T: Clone> Crdt for Lww<T> {
+    fn merge(&mut self, other: &Self) {
+        let other_wins = match self.ts.cmp(&other.ts) {
+            std::cmp::Ordering::Less => true,
+            std::cmp::Ordering::Greater => false,
+            std::cmp::Ordering::Equal => other.node > self.node,
+        };
+        if other_wins {
+            self.ts = other.ts;
+            self.node = other.node;
+            self.v = other.v.clone();
+        }
+    }
+}
+
+/// Current wall-clock time in milliseconds since the Unix epoch, used as
+/// the logical timestamp source for [`Lww::new`] and [`Lww::update`].
+fn now_msec() -> u64 {
+    SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// CRDT operation types for filesystem operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CrdtOperation {
+    /// Create a new file or directory
+    Create {
+        /// Unique operation ID
+        op_id: Uuid,
+        /// Machine that created this operation
+        machine_id: Uuid,
+        /// Vector clock at time of creation
+        vector_clock: VectorClock,
+        /// Wall clock timestamp
+        timestamp: SystemTime,
+        /// Op-ids of the `OperationLog` heads this operation was recorded
+        /// on top of. Empty means it was the first operation in the log.
+        parents: Vec<Uuid>,
+        /// Parent directory path
+        parent_path: String,
+        /// Name of the new file/directory
+        name: String,
+        /// File type (file, directory, symlink)
+        file_type: FileType,
+        /// Initial file attributes
+        initial_attrs: InodeAttributes,
+        /// Symlink target (if file_type is Symlink)
+        symlink_target: Option<String>,
+    },
+
+    /// Write data to a file
+    Write {
+        /// Unique operation ID
+        op_id: Uuid,
+        /// Machine that created this operation
+        machine_id: Uuid,
+        /// Vector clock at time of write
+        vector_clock: VectorClock,
+        /// Wall clock timestamp
+        timestamp: SystemTime,
+        /// Op-ids of the `OperationLog` heads this operation was recorded
+        /// on top of. Empty means it was the first operation in the log.
+        parents: Vec<Uuid>,
+        /// Path to the file
+        path: String,
+        /// Offset in the file
+        offset: u64,
+        /// Hash of the data chunk (reference to chunk storage)
+        data_hash: String,
+        /// Length of the data
+        length: u64,
+    },
+
+    /// Delete a file or directory (creates a tombstone)
+    Delete {
+        /// Unique operation ID
+        op_id: Uuid,
+        /// Machine that created this operation
+        machine_id: Uuid,
+        /// Vector clock at time of deletion
+        vector_clock: VectorClock,
+        /// Wall clock timestamp
+        timestamp: SystemTime,
+        /// Op-ids of the `OperationLog` heads this operation was recorded
+        /// on top of. Empty means it was the first operation in the log.
+        parents: Vec<Uuid>,
+        /// Path to delete
+        path: String,
+        /// Tombstone timestamp for garbage collection
+        tombstone_time: SystemTime,
+    },
+
+    /// Move/rename a file or directory
+    Move {
+        /// Unique operation ID
+        op_id: Uuid,
+        /// Machine that created this operation
+        machine_id: Uuid,
+        /// Vector clock at time of move
+        vector_clock: VectorClock,
+        /// Wall clock timestamp
+        timestamp: SystemTime,
+        /// Op-ids of the `OperationLog` heads this operation was recorded
+        /// on top of. Empty means it was the first operation in the log.
+        parents: Vec<Uuid>,
+        /// Original path
+        old_path: String,
+        /// New path
+        new_path: String,
+    },
+
+    /// Set file attributes
+    SetAttr {
+        /// Unique operation ID
+        op_id: Uuid,
+        /// Machine that created this operation
+        machine_id: Uuid,
+        /// Vector clock at time of attribute change
+        vector_clock: VectorClock,
+        /// Wall clock timestamp
+        timestamp: SystemTime,
+        /// Op-ids of the `OperationLog` heads this operation was recorded
+        /// on top of. Empty means it was the first operation in the log.
+        parents: Vec<Uuid>,
+        /// Path to the file
+        path: String,
+        /// New attributes
+        attrs: InodeAttributes,
+    },
+}
+
+impl CrdtOperation {
+    /// Get the operation ID
+    pub fn op_id(&self) -> Uuid {
+        match self {
+            CrdtOperation::Create { op_id, .. }
+            | CrdtOperation::Write { op_id, .. }
+            | CrdtOperation::Delete { op_id, .. }
+            | CrdtOperation::Move { op_id, .. }
+            | CrdtOperation::SetAttr { op_id, .. } => *op_id,
+        }
+    }
+
+    /// Get the op-ids of the `OperationLog` heads this operation was
+    /// recorded on top of.
+    pub fn parents(&self) -> &[Uuid] {
+        match self {
+            CrdtOperation::Create { parents, .. }
+            | CrdtOperation::Write { parents, .. }
+            | CrdtOperation::Delete { parents, .. }
+            | CrdtOperation::Move { parents, .. }
+            | CrdtOperation::SetAttr { parents, .. } => parents,
+        }
+    }
+
+    /// Overwrite this operation's parent pointers. Used by
+    /// [`OperationLog::append`] to stamp an operation with the heads it
+    /// was recorded on top of, and by [`OperationLog::resolve_heads`] to
+    /// point a freshly created merge operation at every head it resolves.
+    fn set_parents(&mut self, parents: Vec<Uuid>) {
+        match self {
+            CrdtOperation::Create { parents: p, .. }
+            | CrdtOperation::Write { parents: p, .. }
+            | CrdtOperation::Delete { parents: p, .. }
+            | CrdtOperation::Move { parents: p, .. }
+            | CrdtOperation::SetAttr { parents: p, .. } => *p = parents,
+        }
+    }
+
+    /// Overwrite this operation's vector clock. Used by
+    /// [`CrdtSync::record_operation`] to stamp a freshly created
+    /// operation with the machine's current clock (post-increment)
+    /// before it ever leaves the local node.
+    fn set_vector_clock(&mut self, vector_clock: VectorClock) {
+        match self {
+            CrdtOperation::Create { vector_clock: vc, .. }
+            | CrdtOperation::Write { vector_clock: vc, .. }
+            | CrdtOperation::Delete { vector_clock: vc, .. }
+            | CrdtOperation::Move { vector_clock: vc, .. }
+            | CrdtOperation::SetAttr { vector_clock: vc, .. } => *vc = vector_clock,
+        }
+    }
+
+    /// Get the machine ID that created this operation
+    pub fn machine_id(&self) -> Uuid {
+        match self {
+            CrdtOperation::Create { machine_id, .. }
+            | CrdtOperation::Write { machine_id, .. }
+            | CrdtOperation::Delete { machine_id, .. }
+            | CrdtOperation::Move { machine_id, .. }
+            | CrdtOperation::SetAttr { machine_id, .. } => *machine_id,
+        }
+    }
+
+    /// Get the vector clock
+    pub fn vector_clock(&self) -> &VectorClock {
+        match self {
+            CrdtOperation::Create { vector_clock, .. }
+            | CrdtOperation::Write { vector_clock, .. }
+            | CrdtOperation::Delete { vector_clock, .. }
+            | CrdtOperation::Move { vector_clock, .. }
+            | CrdtOperation::SetAttr { vector_clock, .. } => vector_clock,
+        }
+    }
+
+    /// Get the timestamp
+    pub fn timestamp(&self) -> SystemTime {
+        match self {
+            CrdtOperation::Create { timestamp, .. }
+            | CrdtOperation::Write { timestamp, .. }
+            | CrdtOperation::Delete { timestamp, .. }
+            | CrdtOperation::Move { timestamp, .. }
+            | CrdtOperation::SetAttr { timestamp, .. } => *timestamp,
+        }
+    }
+
+    /// Get the path affected by this operation
+    pub fn path(&self) -> &str {
+        match self {
+            CrdtOperation::Create { parent_path, .. } => {
+                // Note: This is simplified; in practice you'd join paths properly
+                parent_path
+            }
+            CrdtOperation::Write { path, .. }
+            | CrdtOperation::Delete { path, .. }
+            | CrdtOperation::SetAttr { path, .. } => path,
+            CrdtOperation::Move { old_path, .. } => old_path,
+        }
+    }
+}
+
+/// Split `data` into content-defined chunks (see [`chunking::chunk`]) and
+/// emit one `Write` per chunk, each carrying its own chunk's offset,
+/// hash, and length. All emitted ops share `machine_id`, `vector_clock`,
+/// `timestamp`, and `parents`, since they all originate from the same
+/// logical write.
+pub fn chunked_write_ops(
+    machine_id: Uuid,
+    vector_clock: VectorClock,
+    timestamp: SystemTime,
+    parents: Vec<Uuid>,
+    path: String,
+    base_offset: u64,
+    data: &[u8],
+) -> Vec<CrdtOperation> {
+    chunking::chunk(data, base_offset)
+        .into_iter()
+        .map(|c| CrdtOperation::Write {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: vector_clock.clone(),
+            timestamp,
+            parents: parents.clone(),
+            path: path.clone(),
+            offset: c.offset,
+            data_hash: c.data_hash,
+            length: c.length,
+        })
+        .collect()
+}
+
+/// The result of inspecting an [`OperationLog`]'s current heads.
+#[derive(Debug)]
+pub enum OpHeads<'a> {
+    /// History is linear: one unambiguous current operation.
+    Single(&'a CrdtOperation),
+    /// History has forked: these heads were recorded concurrently and
+    /// need resolving (see [`OperationLog::resolve_heads`]) before the
+    /// log has a single current operation again.
+    Unresolved { heads: Vec<&'a CrdtOperation> },
+}
+
+/// Append-only log of CRDT operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationLog {
+    /// All operations in chronological order
+    operations: Vec<CrdtOperation>,
+    /// Index: op_id -> position in operations vec
+    op_index: HashMap<Uuid, usize>,
+    /// [`ConflictProof`]s this node has already verified and applied the
+    /// resolution for, so a late-joining replica that replays this log
+    /// reaches the same outcome instead of independently re-detecting and
+    /// re-resolving the same conflict (which the deterministic strategies
+    /// guarantee agrees, but only if every node actually runs them on the
+    /// same inputs).
+    applied_proofs: Vec<ConflictProof>,
+}
+
+impl OperationLog {
+    /// Create a new empty operation log
+    pub fn new() -> Self {
+        OperationLog {
+            operations: Vec::new(),
+            op_index: HashMap::new(),
+            applied_proofs: Vec::new(),
+        }
+    }
+
+    /// Record a [`ConflictProof`] whose resolution has already been
+    /// applied locally, so replay sees it too. Does not re-verify the
+    /// proof - callers apply a resolution only after [`ConflictProof::verify`]
+    /// succeeds, and recording an already-trusted proof here should not
+    /// redo that work.
+    pub fn record_proof(&mut self, proof: ConflictProof) {
+        self.applied_proofs.push(proof);
+    }
+
+    /// Every conflict proof this log has recorded, in the order they were
+    /// applied.
+    pub fn applied_proofs(&self) -> &[ConflictProof] {
+        &self.applied_proofs
+    }
+
+    /// Append an operation to the log.
+    ///
+    /// If `op` doesn't already carry parent pointers, it is stamped with
+    /// the log's current heads before being stored, so the log forms a
+    /// DAG rather than a flat sequence: a later [`heads`](Self::heads)
+    /// call can tell whether history is still linear or has forked into
+    /// concurrent branches. An operation constructed with explicit
+    /// parents (e.g. a merge op from [`resolve_heads`](Self::resolve_heads))
+    /// keeps the parents it was given.
+    pub fn append(&mut self, mut op: CrdtOperation) -> Result<()> {
+        let op_id = op.op_id();
+
+        // Check for duplicate operations
+        if self.op_index.contains_key(&op_id) {
+            return Err(Error::Internal(format!(
+                "Operation {} already exists in log",
+                op_id
+            )));
+        }
+
+        if op.parents().is_empty() && !self.operations.is_empty() {
+            op.set_parents(self.heads());
+        }
+
+        let index = self.operations.len();
+        self.operations.push(op);
+        self.op_index.insert(op_id, index);
+
+        Ok(())
+    }
+
+    /// Op-ids with no children: operations no other operation in the log
+    /// lists as a parent. A linear history has exactly one head; a log
+    /// with concurrently recorded branches has more than one.
+    pub fn heads(&self) -> Vec<Uuid> {
+        let referenced: HashSet<Uuid> = self
+            .operations
+            .iter()
+            .flat_map(|op| op.parents().iter().copied())
+            .collect();
+
+        self.operations
+            .iter()
+            .map(|op| op.op_id())
+            .filter(|id| !referenced.contains(id))
+            .collect()
+    }
+
+    /// Inspect the log's current heads: a single, unambiguous current
+    /// operation, or every head of an as-yet-unresolved fork.
+    pub fn op_heads(&self) -> OpHeads<'_> {
+        let head_ids = self.heads();
+        if let [only] = head_ids.as_slice() {
+            OpHeads::Single(self.get(only).expect("head op_id must be in log"))
+        } else {
+            OpHeads::Unresolved {
+                heads: head_ids
+                    .iter()
+                    .map(|id| self.get(id).expect("head op_id must be in log"))
+                    .collect(),
+            }
+        }
+    }
+
+    /// If the log has forked into more than one head, resolve them with
+    /// the caller-supplied `resolve` closure and append the resulting
+    /// merge operation pointing at every head it resolved. Returns the new
+    /// merge operation's id, or `None` if there was nothing to resolve
+    /// (zero or one head).
+    pub fn resolve_heads<F>(&mut self, resolve: F) -> Result<Option<Uuid>>
+    where
+        F: FnOnce(Vec<&CrdtOperation>) -> Result<CrdtOperation>,
+    {
+        let head_ids = self.heads();
+        if head_ids.len() <= 1 {
+            return Ok(None);
+        }
+
+        let head_ops: Vec<&CrdtOperation> = head_ids
+            .iter()
+            .map(|id| self.get(id).expect("head op_id must be in log"))
+            .collect();
+
+        let mut merge_op = resolve(head_ops)?;
+        merge_op.set_parents(head_ids);
+        let merge_id = merge_op.op_id();
+        self.append(merge_op)?;
+
+        Ok(Some(merge_id))
+    }
+
+    /// Get an operation by ID
+    pub fn get(&self, op_id: &Uuid) -> Option<&CrdtOperation> {
+        self.op_index.get(op_id).map(|&idx| &self.operations[idx])
+    }
+
+    /// Check if an operation exists
+    pub fn contains(&self, op_id: &Uuid) -> bool {
+        self.op_index.contains_key(op_id)
+    }
+
+    /// Get all operations
+    pub fn operations(&self) -> &[CrdtOperation] {
+        &self.operations
+    }
+
+    /// Get operations after a certain vector clock
+    pub fn operations_after(&self, vc: &VectorClock) -> Vec<&CrdtOperation> {
+        self.operations
+            .iter()
+            .filter(|op| op.vector_clock().happened_after(vc))
+            .collect()
+    }
+
+    /// Get the number of operations
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Check if the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Fold this log's `Create`/`Delete` operations for `parent_path` into
+    /// an observed-remove set keyed on child name and return the names
+    /// that are currently live.
+    ///
+    /// Each `Create` adds its `name` tagged with its own `op_id`. A
+    /// `Delete` removes only the add-tags it causally observed: the
+    /// `op_id` of every `Create` for that name whose vector clock
+    /// happened-before the delete's. A concurrent create the delete never
+    /// observed keeps its tag and so survives the fold (add-wins), which
+    /// is what lets [`ConflictResolver`] pick the create as the winner of
+    /// a `CreateDelete`/`DeleteCreate` conflict instead of the delete
+    /// unconditionally winning.
+    pub fn resolve_directory(&self, parent_path: &str) -> Vec<String> {
+        let mut entries: OrSet<String> = OrSet::new();
+
+        for op in &self.operations {
+            if let CrdtOperation::Create {
+                op_id,
+                parent_path: p,
+                name,
+                ..
+            } = op
+            {
+                if p == parent_path {
+                    entries.add_tagged(name.clone(), *op_id);
+                }
+            }
+        }
+
+        for op in &self.operations {
+            let CrdtOperation::Delete { path, vector_clock: delete_clock, .. } = op else {
+                continue;
+            };
+
+            for create in &self.operations {
+                let CrdtOperation::Create {
+                    op_id,
+                    parent_path: p,
+                    name,
+                    vector_clock: create_clock,
+                    ..
+                } = create
+                else {
+                    continue;
+                };
+
+                if p == parent_path
+                    && format!("{}/{}", p, name) == *path
+                    && create_clock.happened_before(delete_clock)
+                {
+                    entries.remove_tag(name, *op_id);
+                }
+            }
+        }
+
+        entries.values()
+    }
+
+    /// Remove every operation dominated by `stability_clock` (causally
+    /// observed by every known replica, see [`is_causally_stable`]) from
+    /// the live log and return them, in their original order, for the
+    /// caller to fold into a snapshot. What's left is the still-concurrent
+    /// tail `heads`/`operations_after` keep working against.
+    pub fn compact(&mut self, stability_clock: &VectorClock) -> Vec<CrdtOperation> {
+        let (dominated, tail): (Vec<_>, Vec<_>) = self
+            .operations
+            .drain(..)
+            .partition(|op| is_causally_stable(op.vector_clock(), stability_clock));
+
+        self.operations = tail;
+        self.op_index = self
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (op.op_id(), i))
+            .collect();
+
+        dominated
+    }
+}
+
+impl Default for OperationLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether byte range `[off1, off1+len1)` overlaps `[off2, off2+len2)`.
+/// Two zero-length ranges never overlap.
+fn ranges_overlap(off1: u64, len1: u64, off2: u64, len2: u64) -> bool {
+    len1 > 0 && len2 > 0 && off1 < off2 + len2 && off2 < off1 + len1
+}
+
+/// Detects conflicts between concurrent operations
+#[derive(Debug)]
+pub struct ConflictDetector {
+    /// Current vector clock state
+    current_clock: VectorClock,
+}
+
+impl ConflictDetector {
+    /// Create a new conflict detector
+    pub fn new(current_clock: VectorClock) -> Self {
+        ConflictDetector { current_clock }
+    }
+
+    /// Detect if an operation conflicts with the current state
+    pub fn detect_conflict(
+        &self,
+        op1: &CrdtOperation,
+        op2: &CrdtOperation,
+    ) -> Option<Conflict> {
+        // Operations are concurrent if their vector clocks are concurrent
+        if !op1.vector_clock().concurrent(op2.vector_clock()) {
+            return None;
+        }
+
+        // Check if operations affect the same path
+        let conflict_type = match (op1, op2) {
+            // Two creates with same parent and name
+            (
+                CrdtOperation::Create { parent_path: p1, name: n1, .. },
+                CrdtOperation::Create { parent_path: p2, name: n2, .. },
+            ) if p1 == p2 && n1 == n2 => ConflictType::CreateCreate,
+
+            // Writes on the same file only truly conflict if their byte
+            // ranges overlap; a chunked write into a different part of
+            // the file, or the exact same chunk re-sent, isn't a
+            // conflict at all.
+            (
+                CrdtOperation::Write { path: path1, offset: off1, length: len1, data_hash: hash1, .. },
+                CrdtOperation::Write { path: path2, offset: off2, length: len2, data_hash: hash2, .. },
+            ) if path1 == path2 => {
+                if !ranges_overlap(*off1, *len1, *off2, *len2) {
+                    return None;
+                }
+                if off1 == off2 && len1 == len2 && hash1 == hash2 {
+                    // Same chunk, same content: a no-op duplicate, not a
+                    // conflict.
+                    return None;
+                }
+                ConflictType::WriteWrite
+            }
+
+            // Delete conflicts
+            (
+                CrdtOperation::Delete { path: path1, .. },
+                CrdtOperation::Delete { path: path2, .. },
+            ) if path1 == path2 => ConflictType::DeleteDelete,
+
+            // Create vs Delete
+            (
+                CrdtOperation::Create { parent_path, name, .. },
+                CrdtOperation::Delete { path, .. },
+            ) if format!("{}/{}", parent_path, name) == *path => ConflictType::CreateDelete,
+
+            // Delete vs Create
+            (
+                CrdtOperation::Delete { path, .. },
+                CrdtOperation::Create { parent_path, name, .. },
+            ) if *path == format!("{}/{}", parent_path, name) => ConflictType::DeleteCreate,
+
+            // Move conflicts
+            (
+                CrdtOperation::Move { old_path: old1, .. },
+                CrdtOperation::Move { old_path: old2, .. },
+            ) if old1 == old2 => ConflictType::MoveMove,
+
+            // SetAttr conflicts
+            (
+                CrdtOperation::SetAttr { path: path1, .. },
+                CrdtOperation::SetAttr { path: path2, .. },
+            ) if path1 == path2 => ConflictType::SetAttrSetAttr,
+
+            _ => return None,
+        };
+
+        Some(Conflict {
+            op1: op1.clone(),
+            op2: op2.clone(),
+            conflict_type,
+        })
+    }
+
+    /// Update the current vector clock
+    pub fn update_clock(&mut self, new_clock: VectorClock) {
+        self.current_clock = new_clock;
+    }
+}
+
+/// Represents a detected conflict between operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    pub op1: CrdtOperation,
+    pub op2: CrdtOperation,
+    pub conflict_type: ConflictType,
+}
+
+/// Types of conflicts that can occur
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictType {
+    /// Two concurrent creates of the same file
+    CreateCreate,
+    /// Two concurrent writes to the same file
+    WriteWrite,
+    /// Two concurrent deletes of the same file
+    DeleteDelete,
+    /// Concurrent create and delete
+    CreateDelete,
+    /// Concurrent delete and create
+    DeleteCreate,
+    /// Two concurrent moves of the same file
+    MoveMove,
+    /// Two concurrent attribute changes
+    SetAttrSetAttr,
+}
+
+/// Conflict resolution strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictResolutionStrategy {
+    /// Last Write Wins (based on timestamp)
+    LastWriteWins,
+    /// Manual resolution required
+    Manual,
+    /// Attempt to merge changes automatically
+    Merge,
+}
+
+impl From<crate::config::ConflictResolution> for ConflictResolutionStrategy {
+    fn from(value: crate::config::ConflictResolution) -> Self {
+        match value {
+            crate::config::ConflictResolution::LastWriteWins => ConflictResolutionStrategy::LastWriteWins,
+            crate::config::ConflictResolution::Manual => ConflictResolutionStrategy::Manual,
+            crate::config::ConflictResolution::Merge => ConflictResolutionStrategy::Merge,
+        }
+    }
+}
+
+/// Field-by-field LWW projection of the `InodeAttributes` fields two
+/// concurrent `SetAttr` operations can touch independently (permission
+/// bits, ownership, modification time, size). Capturing both sides of a
+/// `SetAttrSetAttr` conflict as an `AttrLww` and merging them lets e.g. one
+/// node's `chmod` and another's `truncate` both survive, instead of
+/// picking one operation's attributes wholesale.
+#[derive(Debug, Clone)]
+struct AttrLww {
+    perm: Lww<u16>,
+    uid: Lww<u32>,
+    gid: Lww<u32>,
+    mtime: Lww<SystemTime>,
+    size: Lww<u64>,
+}
+
+impl AttrLww {
+    /// Snapshot `attrs` as a set of registers all tagged with the same
+    /// `ts`/`node` (the timestamp/machine of the `SetAttr` op that produced
+    /// them).
+    fn capture(attrs: &InodeAttributes, ts: u64, node: Uuid) -> Self {
+        AttrLww {
+            perm: Lww { ts, node, v: attrs.perm },
+            uid: Lww { ts, node, v: attrs.uid },
+            gid: Lww { ts, node, v: attrs.gid },
+            mtime: Lww { ts, node, v: attrs.mtime },
+            size: Lww { ts, node, v: attrs.size },
+        }
+    }
+
+    /// Write the merged field values onto `base`, leaving every field this
+    /// type doesn't track (kind, nlink, atime, ctime, ...) as `base` already
+    /// had it.
+    fn apply(&self, base: &mut InodeAttributes) {
+        base.perm = self.perm.v;
+        base.uid = self.uid.v;
+        base.gid = self.gid.v;
+        base.mtime = self.mtime.v;
+        base.size = self.size.v;
+    }
+}
+
+impl Crdt for AttrLww {
+    fn merge(&mut self, other: &Self) {
+        self.perm.merge(&other.perm);
+        self.uid.merge(&other.uid);
+        self.gid.merge(&other.gid);
+        self.mtime.merge(&other.mtime);
+        self.size.merge(&other.size);
+    }
+}
+
+/// Convert a `SetAttr` operation's wall-clock timestamp into the
+/// millisecond logical timestamp `Lww` registers compare on.
+fn msec_from_system_time(t: SystemTime) -> u64 {
+    t.duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A destination path's provenance in a [`CopyLedger`]: where it was
+/// copied/renamed from (`None` encodes a deletion at that path), and
+/// which other entries' op-ids this one has already out-voted so repeat
+/// merges of the same two branches stay deterministic.
+#[derive(Debug, Clone)]
+pub struct CopySource {
+    /// The `Move`/`Delete` operation that produced this entry.
+    pub op_id: Uuid,
+    /// Where this destination was renamed from, or `None` if this entry
+    /// is a deletion.
+    pub source: Option<String>,
+    /// Wall clock timestamp of the producing operation, used to break
+    /// genuine concurrent-rename conflicts.
+    pub timestamp: SystemTime,
+    /// Vector clock of the producing operation, used by
+    /// [`CopyLedger::trace_copies_after`] to scope a sync round.
+    pub vector_clock: VectorClock,
+    /// Op-ids of entries this one has already beaten in a merge.
+    pub overwritten: HashSet<Uuid>,
+}
+
+/// Copy-tracing ledger (inspired by Mercurial's merge copy tracking) that
+/// remembers, for every destination path a `Move` has touched, where it
+/// was renamed from. Unlike plain Last-Write-Wins on the `Move` operation
+/// itself, merging two branches' ledgers lets a rename chain survive
+/// across concurrent merges instead of silently collapsing to one side.
+#[derive(Debug, Clone, Default)]
+pub struct CopyLedger {
+    entries: HashMap<String, CopySource>,
+}
+
+impl CopyLedger {
+    /// Create an empty ledger.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a rename/move: `dest` was copied from `source`.
+    pub fn record_move(
+        &mut self,
+        op_id: Uuid,
+        source: String,
+        dest: String,
+        timestamp: SystemTime,
+        vector_clock: VectorClock,
+    ) {
+        self.entries.insert(
+            dest,
+            CopySource {
+                op_id,
+                source: Some(source),
+                timestamp,
+                vector_clock,
+                overwritten: HashSet::new(),
+            },
+        );
+    }
+
+    /// Record a deletion at `path`. The entry's `source` is `None`, so a
+    /// concurrent re-create at this path that the deleting branch never
+    /// observed still loses once this entry wins a merge.
+    pub fn record_delete(
+        &mut self,
+        op_id: Uuid,
+        path: String,
+        timestamp: SystemTime,
+        vector_clock: VectorClock,
+    ) {
+        self.entries.insert(
+            path,
+            CopySource {
+                op_id,
+                source: None,
+                timestamp,
+                vector_clock,
+                overwritten: HashSet::new(),
+            },
+        );
+    }
+
+    /// Merge `other`'s ledger into this one, resolving every destination
+    /// both sides recorded.
+    pub fn merge(&mut self, other: &Self) {
+        for (dest, theirs) in &other.entries {
+            let merged = match self.entries.remove(dest) {
+                None => theirs.clone(),
+                Some(ours) => Self::resolve_entry(ours, theirs.clone()),
+            };
+            self.entries.insert(dest.clone(), merged);
+        }
+    }
+
+    /// Resolve two branches' entries for the same destination.
+    fn resolve_entry(mut ours: CopySource, mut theirs: CopySource) -> CopySource {
+        if ours.op_id == theirs.op_id {
+            ours.overwritten.extend(theirs.overwritten);
+            return ours;
+        }
+
+        // One side already knows it overwrote the other: it wins outright,
+        // regardless of timestamp.
+        if theirs.overwritten.contains(&ours.op_id) {
+            theirs.overwritten.insert(ours.op_id);
+            return theirs;
+        }
+        if ours.overwritten.contains(&theirs.op_id) {
+            ours.overwritten.insert(theirs.op_id);
+            return ours;
+        }
+
+        // Genuine concurrent rename conflict: LWW on timestamp, tie broken
+        // by op_id so both sides of the merge converge on the same winner.
+        let ours_wins = match ours.timestamp.cmp(&theirs.timestamp) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => ours.op_id > theirs.op_id,
+        };
+
+        if ours_wins {
+            ours.overwritten.insert(theirs.op_id);
+            ours.overwritten.extend(theirs.overwritten);
+            ours
+        } else {
+            theirs.overwritten.insert(ours.op_id);
+            theirs.overwritten.extend(ours.overwritten);
+            theirs
+        }
+    }
+
+    /// The final source -> dest mapping for every entry whose vector
+    /// clock happened after `vc` (i.e. recorded during the sync round
+    /// that started at `vc`). Deletions (no `source`) are omitted since
+    /// they have no source path to map from.
+    pub fn trace_copies_after(&self, vc: &VectorClock) -> HashMap<String, String> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.vector_clock.happened_after(vc))
+            .filter_map(|(dest, entry)| entry.source.clone().map(|source| (source, dest.clone())))
+            .collect()
+    }
+}
+
+/// Whether `op_vc` is causally stable relative to `stability_clock`: every
+/// known replica (the clocks `stability_clock` was built as the
+/// elementwise minimum of) has already observed it, so it is safe to fold
+/// into a snapshot and drop from the live log.
+fn is_causally_stable(op_vc: &VectorClock, stability_clock: &VectorClock) -> bool {
+    op_vc == stability_clock || op_vc.happened_before(stability_clock)
+}
+
+/// Materialized filesystem state folded from the prefix of an
+/// [`OperationLog`] that [`CrdtSync::compact`] has determined every known
+/// replica has already observed. A peer whose own clock predates the
+/// snapshot's can't be caught up by operation replay alone (the ops it's
+/// missing are already gone from the log), so it needs this snapshot
+/// first; see [`SyncPayload`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilesystemState {
+    /// Path -> current attributes, for every path this snapshot knows to
+    /// currently exist.
+    paths: HashMap<String, InodeAttributes>,
+    /// Path -> (deleting op's vector clock, tombstone time) for deletes
+    /// folded into this snapshot but not yet past the GC horizon. Kept
+    /// separately from `paths` so a tardy concurrent Create/Move that
+    /// raced the delete still has something to lose an add-wins check
+    /// against, instead of the path looking like it never existed.
+    tombstones: HashMap<String, (VectorClock, SystemTime)>,
+    /// The stability clock every operation folded into this snapshot was
+    /// dominated by. Everything up to this point is reflected here.
+    vector_clock: VectorClock,
+}
+
+impl FilesystemState {
+    /// An empty snapshot: nothing has been compacted yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold one already-stable operation's effect into this snapshot.
+    fn apply(&mut self, op: &CrdtOperation) {
+        match op {
+            CrdtOperation::Create { parent_path, name, initial_attrs, .. } => {
+                let path = format!("{}/{}", parent_path, name);
+                self.tombstones.remove(&path);
+                self.paths.insert(path, initial_attrs.clone());
+            }
+            CrdtOperation::Delete { path, vector_clock, tombstone_time, .. } => {
+                self.paths.remove(path);
+                self.tombstones.insert(path.clone(), (vector_clock.clone(), *tombstone_time));
+            }
+            CrdtOperation::Move { old_path, new_path, .. } => {
+                self.tombstones.remove(new_path);
+                if let Some(attrs) = self.paths.remove(old_path) {
+                    self.paths.insert(new_path.clone(), attrs);
+                }
+            }
+            CrdtOperation::SetAttr { path, attrs, .. } => {
+                self.paths.insert(path.clone(), attrs.clone());
+            }
+            // A Write's bytes live in chunk storage, not this
+            // attribute/existence snapshot; nothing to fold.
+            CrdtOperation::Write { .. } => {}
+        }
+    }
+
+    /// Permanently forget every tombstone whose `tombstone_time` is at or
+    /// before `gc_horizon`. Returns how many were collected.
+    fn gc_tombstones(&mut self, gc_horizon: SystemTime) -> usize {
+        let before = self.tombstones.len();
+        self.tombstones.retain(|_, (_, tombstone_time)| *tombstone_time > gc_horizon);
+        before - self.tombstones.len()
+    }
+
+    /// Whether `path` currently exists according to this snapshot.
+    pub fn contains(&self, path: &str) -> bool {
+        self.paths.contains_key(path)
+    }
+
+    /// Attributes for `path`, if this snapshot knows it to exist.
+    pub fn attrs(&self, path: &str) -> Option<&InodeAttributes> {
+        self.paths.get(path)
+    }
+
+    /// Whether `path` is tombstoned (deleted but not yet past the GC
+    /// horizon) in this snapshot.
+    pub fn is_tombstoned(&self, path: &str) -> bool {
+        self.tombstones.contains_key(path)
+    }
+
+    /// The stability clock this snapshot's contents are dominated by.
+    pub fn vector_clock(&self) -> &VectorClock {
+        &self.vector_clock
+    }
+}
+
+/// Outcome of one [`CrdtSync::compact`] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompactionStats {
+    /// Operations folded into the snapshot and removed from the live log.
+    pub folded_into_snapshot: usize,
+    /// Delete tombstones permanently forgotten (causally stable and past
+    /// the GC horizon).
+    pub tombstones_collected: usize,
+    /// Operations left in the live log after compaction.
+    pub remaining_in_log: usize,
+}
+
+/// Outcome of one [`CrdtSync::compact_pending`] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PendingCompactionStats {
+    /// Not-yet-acked operations dropped outright because a later operation
+    /// in the pending queue already made them redundant.
+    pub ops_removed: usize,
+    /// Bytes of `Write` payload that don't need to be retransmitted,
+    /// either because the `Write` carrying them was dropped or because an
+    /// earlier pending `Write` already covers the same content hash.
+    pub bytes_deduplicated: u64,
+}
+
+/// What a peer needs in order to catch up from a given vector clock.
+#[derive(Debug)]
+pub enum SyncPayload<'a> {
+    /// The peer's clock already reflects everything folded into the
+    /// snapshot (or nothing has been compacted yet): replaying these
+    /// operations over its existing state is sufficient.
+    Operations(Vec<&'a CrdtOperation>),
+    /// The peer's clock predates the last compaction, so plain operation
+    /// replay would skip state the log no longer carries. It must install
+    /// this snapshot before replaying `tail`.
+    Snapshot {
+        /// State to install before replaying `tail`.
+        state: &'a FilesystemState,
+        /// Every operation still in the live log (the concurrent tail
+        /// the snapshot's stability clock didn't dominate).
+        tail: Vec<&'a CrdtOperation>,
+    },
+}
+
+/// Resolves conflicts between concurrent operations
+#[derive(Debug)]
+pub struct ConflictResolver {
+    strategy: ConflictResolutionStrategy,
+}
+
+impl ConflictResolver {
+    /// Create a new conflict resolver with the given strategy
+    pub fn new(strategy: ConflictResolutionStrategy) -> Self {
+        ConflictResolver { strategy }
+    }
+
+    /// Resolve a conflict and return the winning operation(s)
+    pub fn resolve(&self, conflict: &Conflict) -> Result<ResolutionResult> {
+        match self.strategy {
+            ConflictResolutionStrategy::LastWriteWins => self.resolve_lww(conflict),
+            ConflictResolutionStrategy::Manual => Ok(ResolutionResult::Manual(conflict.clone())),
+            ConflictResolutionStrategy::Merge => self.resolve_merge(conflict),
+        }
+    }
+
+    /// Resolve using Last Write Wins
+    fn resolve_lww(&self, conflict: &Conflict) -> Result<ResolutionResult> {
+        let ts1 = conflict.op1.timestamp();
+        let ts2 = conflict.op2.timestamp();
+
+        match ts1.cmp(&ts2) {
+            std::cmp::Ordering::Greater => Ok(ResolutionResult::Winner(conflict.op1.clone())),
+            std::cmp::Ordering::Less => Ok(ResolutionResult::Winner(conflict.op2.clone())),
+            std::cmp::Ordering::Equal => {
+                // Tie-breaker: use machine ID lexicographic order
+                if conflict.op1.machine_id() < conflict.op2.machine_id() {
+                    Ok(ResolutionResult::Winner(conflict.op1.clone()))
+                } else {
+                    Ok(ResolutionResult::Winner(conflict.op2.clone()))
+                }
+            }
+        }
+    }
+
+    /// Resolve using merge strategy
+    fn resolve_merge(&self, conflict: &Conflict) -> Result<ResolutionResult> {
+        match conflict.conflict_type {
+            ConflictType::WriteWrite => {
+                // For concurrent writes, we keep both and let the application decide
+                Ok(ResolutionResult::Merge(vec![
+                    conflict.op1.clone(),
+                    conflict.op2.clone(),
+                ]))
+            }
+            ConflictType::SetAttrSetAttr => self.resolve_setattr_merge(conflict),
+            ConflictType::DeleteDelete => {
+                // Both deletes win (idempotent)
+                Ok(ResolutionResult::Winner(conflict.op1.clone()))
+            }
+            ConflictType::CreateCreate => {
+                // Fall back to LWW for creates
+                self.resolve_lww(conflict)
+            }
+            ConflictType::CreateDelete | ConflictType::DeleteCreate => {
+                let (create_op, delete_op) = if matches!(conflict.op1, CrdtOperation::Create { .. })
+                {
+                    (&conflict.op1, &conflict.op2)
+                } else {
+                    (&conflict.op2, &conflict.op1)
+                };
+
+                // Add-wins: the delete only beats the create if it
+                // causally observed it. A delete that never saw the
+                // create (concurrent) loses, matching the OR-Set fold in
+                // `OperationLog::resolve_directory`.
+                if create_op.vector_clock().happened_before(delete_op.vector_clock()) {
+                    Ok(ResolutionResult::Winner(delete_op.clone()))
+                } else {
+                    Ok(ResolutionResult::Winner(create_op.clone()))
+                }
+            }
+            ConflictType::MoveMove => {
+                // Fall back to LWW for moves
+                self.resolve_lww(conflict)
+            }
+        }
+    }
+
+    /// Merge two conflicting `SetAttr` operations field-by-field via
+    /// [`AttrLww`] instead of handing both back to the caller: each
+    /// attribute converges independently, so the result is the single
+    /// `SetAttr` a correct CRDT merge would produce rather than a pair the
+    /// application has to reconcile by hand.
+    fn resolve_setattr_merge(&self, conflict: &Conflict) -> Result<ResolutionResult> {
+        let (
+            CrdtOperation::SetAttr { attrs: attrs1, machine_id: m1, timestamp: ts1, .. },
+            CrdtOperation::SetAttr { attrs: attrs2, machine_id: m2, timestamp: ts2, .. },
+        ) = (&conflict.op1, &conflict.op2)
+        else {
+            return Err(Error::Internal(
+                "resolve_setattr_merge called on a non-SetAttrSetAttr conflict".to_string(),
+            ));
+        };
+
+        let mut merged = AttrLww::capture(attrs1, msec_from_system_time(*ts1), *m1);
+        merged.merge(&AttrLww::capture(attrs2, msec_from_system_time(*ts2), *m2));
+
+        // The merged attrs land on whichever side's timestamp is later, so
+        // the winning operation's non-attribute fields (op_id, path,
+        // vector clock, ...) match the most recent write.
+        let mut winner = if ts1 >= ts2 {
+            conflict.op1.clone()
+        } else {
+            conflict.op2.clone()
+        };
+        if let CrdtOperation::SetAttr { attrs, .. } = &mut winner {
+            merged.apply(attrs);
+        }
+
+        Ok(ResolutionResult::Winner(winner))
+    }
+}
+
+/// Result of conflict resolution
+#[derive(Debug, Clone)]
+pub enum ResolutionResult {
+    /// Single winning operation
+    Winner(CrdtOperation),
+    /// Multiple operations to merge
+    Merge(Vec<CrdtOperation>),
+    /// Manual resolution required
+    Manual(Conflict),
+}
+
+/// One entry in the durable write-ahead log behind [`CrdtSync::record_operation`]:
+/// either a freshly recorded operation, or an acknowledgement that a
+/// previously-logged operation has been uploaded and no longer needs to
+/// be replayed into `pending_operations()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum WalRecord {
+    Op(CrdtOperation),
+    Ack(Uuid),
+    /// A conflict [`CrdtSync::merge_operations`] detected, so
+    /// [`CrdtSync::conflicts`] survives a restart instead of resetting to
+    /// empty every time the log is reopened.
+    DetectedConflict(Conflict),
+}
+
+/// Standard CRC-32 (IEEE 802.3), computed byte-at-a-time rather than via
+/// a precomputed table: the WAL only checksums small per-record payloads,
+/// so the table's setup cost isn't worth it.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Durable, crash-recoverable append log behind [`CrdtSync::record_operation`].
+///
+/// Every record is framed as `[u32 length][u32 CRC32 of payload][payload
+/// bytes]` and `fsync`'d before the appending call returns, so an `Ok(())`
+/// from [`Self::append_op`] means the operation has survived a crash.
+/// Replay in [`Self::open`] stops at the first record whose length or CRC
+/// doesn't check out: a process can be killed mid-append, and everything
+/// before that point is still a valid log.
+struct OperationWal {
+    path: PathBuf,
+    file: File,
+}
+
+impl OperationWal {
+    /// Open (or create) the log at `path`, returning it alongside every
+    /// operation it holds that hasn't been acknowledged yet (the
+    /// reconstructed `pending_operations()`) and every conflict ever
+    /// recorded into it (the reconstructed `conflicts()`).
+    fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<CrdtOperation>, Vec<Conflict>)> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut bytes = Vec::new();
+        if path.exists() {
+            File::open(&path)?.read_to_end(&mut bytes)?;
+        }
+
+        let mut pending = Vec::new();
+        let mut acked = HashSet::new();
+        let mut conflicts = Vec::new();
+        for record in Self::decode_records(&bytes) {
+            match record {
+                WalRecord::Op(op) => pending.push(op),
+                WalRecord::Ack(op_id) => {
+                    acked.insert(op_id);
+                }
+                WalRecord::DetectedConflict(conflict) => conflicts.push(conflict),
+            }
+        }
+        pending.retain(|op: &CrdtOperation| !acked.contains(&op.op_id()));
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok((OperationWal { path, file }, pending, conflicts))
+    }
+
+    /// Append a freshly recorded operation, `fsync`'d before returning.
+    fn append_op(&mut self, op: &CrdtOperation) -> Result<()> {
+        self.append(&WalRecord::Op(op.clone()))
+    }
+
+    /// Append an acknowledgement for `op_id`, `fsync`'d before returning.
+    fn append_ack(&mut self, op_id: Uuid) -> Result<()> {
+        self.append(&WalRecord::Ack(op_id))
+    }
+
+    /// Append a freshly detected conflict, `fsync`'d before returning.
+    fn append_conflict(&mut self, conflict: &Conflict) -> Result<()> {
+        self.append(&WalRecord::DetectedConflict(conflict.clone()))
+    }
+
+    fn append(&mut self, record: &WalRecord) -> Result<()> {
+        let payload = bincode::serialize(record)?;
+        let crc = crc32(&payload);
+
+        self.file.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.file.write_all(&crc.to_le_bytes())?;
+        self.file.write_all(&payload)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Decode `[u32 length][u32 CRC32][payload]` records, stopping at the
+    /// first one that's truncated or fails its checksum: treated as the
+    /// tail of an interrupted write, not a corruption error.
+    fn decode_records(bytes: &[u8]) -> Vec<WalRecord> {
+        let mut records = Vec::new();
+        let mut cursor = 0usize;
+
+        while cursor + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[cursor..cursor + 4].try_into().unwrap()) as usize;
+            let expected_crc =
+                u32::from_le_bytes(bytes[cursor + 4..cursor + 8].try_into().unwrap());
+            let payload_start = cursor + 8;
+            if payload_start + len > bytes.len() {
+                break;
+            }
+
+            let payload = &bytes[payload_start..payload_start + len];
+            if crc32(payload) != expected_crc {
+                break;
+            }
+            let Ok(record) = bincode::deserialize::<WalRecord>(payload) else {
+                break;
+            };
+
+            records.push(record);
+            cursor = payload_start + len;
+        }
+
+        records
+    }
+
+    /// Rewrite the log to hold only `pending` plus `conflicts`: write a
+    /// compacted log to a temp file in the same directory, `fsync` it,
+    /// then atomically rename it over the current log so readers never
+    /// observe a half-written file. Conflicts are carried forward
+    /// unconditionally - unlike pending operations, they have no
+    /// ack-and-drop lifecycle, so every rotation must keep every one
+    /// ever recorded.
+    fn rotate(&mut self, pending: &[CrdtOperation], conflicts: &[Conflict]) -> Result<()> {
+        let dir = self
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|e| Error::Internal(format!("failed to create WAL rotation temp file: {e}")))?;
+        for op in pending {
+            let payload = bincode::serialize(&WalRecord::Op(op.clone()))?;
+            let crc = crc32(&payload);
+            tmp.write_all(&(payload.len() as u32).to_le_bytes())?;
+            tmp.write_all(&crc.to_le_bytes())?;
+            tmp.write_all(&payload)?;
+        }
+        for conflict in conflicts {
+            let payload = bincode::serialize(&WalRecord::DetectedConflict(conflict.clone()))?;
+            let crc = crc32(&payload);
+            tmp.write_all(&(payload.len() as u32).to_le_bytes())?;
+            tmp.write_all(&crc.to_le_bytes())?;
+            tmp.write_all(&payload)?;
+        }
+        tmp.as_file().sync_all()?;
+
+        tmp.persist(&self.path)
+            .map_err(|e| Error::Internal(format!("failed to rotate WAL: {e}")))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Main CRDT synchronization coordinator
+pub struct CrdtSync {
+    /// Current machine ID
+    machine_id: Uuid,
+    /// Current vector clock
+    vector_clock: VectorClock,
+    /// Local operation log
+    operation_log: OperationLog,
+    /// Set of operation IDs that have been applied
+    applied_ops: HashSet<Uuid>,
+    /// Pending operations to upload
+    pending_ops: Vec<CrdtOperation>,
+    /// Conflict resolver
+    resolver: ConflictResolver,
+    /// Remote operations held back because they arrived before an
+    /// operation they causally depend on, keyed by the machine id whose
+    /// vector clock entry is still deficient.
+    deferred: HashMap<Uuid, Vec<CrdtOperation>>,
+    /// Copy-tracing ledger of every `Move`/`Delete` applied so far.
+    copy_ledger: CopyLedger,
+    /// Snapshot everything [`Self::compact`] has folded out of
+    /// `operation_log` so far.
+    snapshot: FilesystemState,
+    /// Durable write-ahead log backing `pending_ops`, if this sync was
+    /// opened with [`Self::open`] rather than [`Self::new`].
+    wal: Option<OperationWal>,
+    /// Every conflict [`Self::merge_operations`] has detected between a
+    /// concurrent remote op and local history, regardless of how (or
+    /// whether) `resolver` resolved it. Surfaced via [`Self::conflicts`]
+    /// so a caller can apply its own policy - e.g. keeping both sides of
+    /// a `CreateCreate` under renamed paths - instead of trusting
+    /// whatever the configured [`ConflictResolutionStrategy`] picked.
+    conflicts: Vec<Conflict>,
+}
+
+impl CrdtSync {
+    /// Create a new CRDT sync coordinator with no durable backing: a
+    /// crash loses `pending_operations()`. Use [`Self::open`] when that
+    /// queue needs to survive a restart.
+    pub fn new(machine_id: Uuid, strategy: ConflictResolutionStrategy) -> Self {
+        CrdtSync {
+            machine_id,
+            vector_clock: VectorClock::new(),
+            operation_log: OperationLog::new(),
+            applied_ops: HashSet::new(),
+            pending_ops: Vec::new(),
+            resolver: ConflictResolver::new(strategy),
+            deferred: HashMap::new(),
+            copy_ledger: CopyLedger::new(),
+            snapshot: FilesystemState::new(),
+            wal: None,
+            conflicts: Vec::new(),
+        }
+    }
+
+    /// Create a CRDT sync coordinator backed by a durable write-ahead log
+    /// at `path`: any operation recorded via [`Self::record_operation`]
+    /// is `fsync`'d to disk before the call returns, and
+    /// `pending_operations()` and `conflicts()` are reconstructed here
+    /// from whatever the log already holds - so a crash between
+    /// recording an operation and uploading it doesn't lose the queue,
+    /// and a restart doesn't forget concurrent-edit history a caller
+    /// (e.g. `tgcryptfs cluster status`) may still want to report.
+    pub fn open(
+        path: impl AsRef<Path>,
+        machine_id: Uuid,
+        strategy: ConflictResolutionStrategy,
+    ) -> Result<Self> {
+        let (wal, pending_ops, conflicts) = OperationWal::open(path)?;
+        let mut sync = Self::new(machine_id, strategy);
+        sync.pending_ops = pending_ops;
+        sync.conflicts = conflicts;
+        sync.wal = Some(wal);
+        Ok(sync)
+    }
+
+    /// Record a `Move` or `Delete` operation into the copy-tracing ledger
+    /// so a later [`Self::trace_copies_after`] can follow rename chains
+    /// across concurrent merges instead of relying on a single
+    /// Last-Write-Wins pick.
+    fn record_in_copy_ledger(&mut self, op: &CrdtOperation) {
+        match op {
+            CrdtOperation::Move { op_id, old_path, new_path, timestamp, vector_clock, .. } => {
+                self.copy_ledger.record_move(
+                    *op_id,
+                    old_path.clone(),
+                    new_path.clone(),
+                    *timestamp,
+                    vector_clock.clone(),
+                );
+            }
+            CrdtOperation::Delete { op_id, path, timestamp, vector_clock, .. } => {
+                self.copy_ledger.record_delete(
+                    *op_id,
+                    path.clone(),
+                    *timestamp,
+                    vector_clock.clone(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// The source -> dest mapping for every rename recorded since `vc`,
+    /// folding together every `Move`/`Delete` merged so far.
+    pub fn trace_copies_after(&self, vc: &VectorClock) -> HashMap<String, String> {
+        self.copy_ledger.trace_copies_after(vc)
+    }
+
+    /// The machine id whose vector clock entry blocks `op` from being
+    /// applied against the current local clock, or `None` if `op` is
+    /// causally ready: its own machine's counter is exactly one past the
+    /// local value and every other machine's counter is no greater than
+    /// the local value.
+    fn causal_block(&self, op: &CrdtOperation) -> Option<Uuid> {
+        let op_clock = op.vector_clock();
+        let op_machine = op.machine_id();
+
+        let mut machines = op_clock.machines();
+        for m in self.vector_clock.machines() {
+            if !machines.contains(&m) {
+                machines.push(m);
+            }
+        }
+
+        for machine in machines {
+            let op_val = op_clock.get(machine);
+            let local_val = self.vector_clock.get(machine);
+            if machine == op_machine {
+                if op_val != local_val + 1 {
+                    return Some(machine);
+                }
+            } else if op_val > local_val {
+                return Some(machine);
+            }
+        }
+
+        None
+    }
+
+    /// Number of remote operations currently held back pending a
+    /// causal dependency that hasn't arrived yet.
+    pub fn pending_deferred_count(&self) -> usize {
+        self.deferred.values().map(|ops| ops.len()).sum()
+    }
+
+    /// Record a new operation created by this machine. If this sync is
+    /// backed by a durable log (see [`Self::open`]), the operation is
+    /// appended and `fsync`'d there before anything else happens, so a
+    /// crash right after this call returns can never lose it.
+    pub fn record_operation(&mut self, mut op: CrdtOperation) -> Result<()> {
+        // Stamp the op with this machine's current vector clock *before*
+        // it's durably logged or queued, so every op this node produces
+        // carries the clock a receiving node needs to order or detect
+        // conflicts against it - callers don't have to get this right
+        // themselves.
+        self.vector_clock.increment(self.machine_id);
+        op.set_vector_clock(self.vector_clock.clone());
+
+        if let Some(wal) = self.wal.as_mut() {
+            wal.append_op(&op)?;
+        }
+
+        self.record_in_copy_ledger(&op);
+
+        // Add to operation log
+        self.operation_log.append(op.clone())?;
+
+        // Mark as applied
+        self.applied_ops.insert(op.op_id());
+
+        // Add to pending uploads
+        self.pending_ops.push(op);
+
+        Ok(())
+    }
+
+    /// Get pending operations that need to be uploaded
+    pub fn pending_operations(&self) -> &[CrdtOperation] {
+        &self.pending_ops
+    }
+
+    /// Mark operations as uploaded: they're dropped from
+    /// `pending_operations()`, and (if this sync is backed by a durable
+    /// log) an acknowledgement is appended so they aren't replayed back
+    /// into the pending queue on the next [`Self::open`].
+    pub fn mark_uploaded(&mut self, op_ids: &[Uuid]) -> Result<()> {
+        if let Some(wal) = self.wal.as_mut() {
+            for op_id in op_ids {
+                wal.append_ack(*op_id)?;
+            }
+        }
+        self.pending_ops.retain(|op| !op_ids.contains(&op.op_id()));
+        Ok(())
+    }
+
+    /// Coalesce `pending_operations()` before transmission. Safe to call
+    /// any time: it only ever touches not-yet-acked operations, so
+    /// already-replicated history (anything [`Self::mark_uploaded`] has
+    /// removed) is never rewritten.
+    ///
+    /// A `Write` to a path that a later pending operation deletes is
+    /// dropped outright - the delete makes it moot. A `Write` whose range
+    /// is fully covered by a later pending `Write` to the same path is
+    /// dropped too, since the later write already supersedes every byte
+    /// it touched. Neither case rewrites the surviving operation: it's
+    /// just removed from the queue. Finally, since a `Write` only ever
+    /// carries its payload's hash (never the bytes themselves), a
+    /// surviving `Write` whose hash repeats one already seen earlier in
+    /// the queue doesn't need its content retransmitted either - that's
+    /// reported as deduplicated bytes without touching the operation.
+    pub fn compact_pending(&mut self) -> PendingCompactionStats {
+        let len = self.pending_ops.len();
+
+        // A write is redundant if some *later* pending op deletes its
+        // path. Walk backward so `deleted_after` only ever holds paths
+        // whose delete sits at a strictly greater index than `i`.
+        let mut redundant = vec![false; len];
+        let mut deleted_after: HashSet<&str> = HashSet::new();
+        for (i, op) in self.pending_ops.iter().enumerate().rev() {
+            match op {
+                CrdtOperation::Write { path, .. } if deleted_after.contains(path.as_str()) => {
+                    redundant[i] = true;
+                }
+                CrdtOperation::Delete { path, .. } => {
+                    deleted_after.insert(path.as_str());
+                }
+                _ => {}
+            }
+        }
+
+        // A write is also redundant if a *later* pending write to the
+        // same path fully covers its byte range.
+        let mut last_write_for_path: HashMap<&str, (u64, u64, usize)> = HashMap::new();
+        for (i, op) in self.pending_ops.iter().enumerate() {
+            let CrdtOperation::Write { path, offset, length, .. } = op else {
+                continue;
+            };
+            if let Some(&(prev_off, prev_len, prev_idx)) = last_write_for_path.get(path.as_str()) {
+                if *offset <= prev_off && *offset + *length >= prev_off + prev_len {
+                    redundant[prev_idx] = true;
+                }
+            }
+            last_write_for_path.insert(path.as_str(), (*offset, *length, i));
+        }
+
+        let mut stats = PendingCompactionStats::default();
+        let mut seen_hashes: HashSet<&str> = HashSet::new();
+        let mut kept = Vec::with_capacity(len);
+
+        for (i, op) in self.pending_ops.iter().enumerate() {
+            if redundant[i] {
+                stats.ops_removed += 1;
+                if let CrdtOperation::Write { length, .. } = op {
+                    stats.bytes_deduplicated += length;
+                }
+                continue;
+            }
+
+            if let CrdtOperation::Write { data_hash, length, .. } = op {
+                if !seen_hashes.insert(data_hash.as_str()) {
+                    stats.bytes_deduplicated += length;
+                }
+            }
+            kept.push(op.clone());
+        }
+
+        self.pending_ops = kept;
+        stats
+    }
+
+    /// Compact the durable write-ahead log down to just the operations
+    /// still pending upload, so it doesn't grow forever across
+    /// acknowledged operations. A no-op if this sync isn't backed by a
+    /// log (see [`Self::open`]).
+    pub fn rotate_wal(&mut self) -> Result<()> {
+        if let Some(wal) = self.wal.as_mut() {
+            wal.rotate(&self.pending_ops, &self.conflicts)?;
+        }
+        Ok(())
+    }
+
+    /// Download and merge remote operations.
+    ///
+    /// Operations are only applied once they're causally ready (see
+    /// [`Self::causal_block`]); an op that arrived ahead of a dependency
+    /// it needs is held in `deferred` instead of being applied
+    /// out of order. Applying an operation can satisfy the dependency
+    /// other deferred ops were waiting on, so the queue is re-scanned
+    /// after every apply until it reaches a fixpoint.
+    pub fn merge_operations(&mut self, remote_ops: Vec<CrdtOperation>) -> Result<Vec<CrdtOperation>> {
+        let mut new_ops = Vec::new();
+        let mut queue: std::collections::VecDeque<CrdtOperation> = remote_ops.into_iter().collect();
+
+        while let Some(remote_op) = queue.pop_front() {
+            let op_id = remote_op.op_id();
+
+            // Skip if already applied
+            if self.applied_ops.contains(&op_id) {
+                continue;
+            }
+
+            if let Some(blocking_machine) = self.causal_block(&remote_op) {
+                self.deferred.entry(blocking_machine).or_default().push(remote_op);
+                continue;
+            }
+
+            // Check for conflicts with existing operations
+            let mut has_conflict = false;
+            for local_op in self.operation_log.operations() {
+                let detector = ConflictDetector::new(self.vector_clock.clone());
+                if let Some(conflict) = detector.detect_conflict(&remote_op, local_op) {
+                    has_conflict = true;
+                    self.conflicts.push(conflict.clone());
+                    if let Some(wal) = self.wal.as_mut() {
+                        wal.append_conflict(&conflict)?;
+                    }
+
+                    // Resolve the conflict
+                    match self.resolver.resolve(&conflict)? {
+                        ResolutionResult::Winner(winning_op) => {
+                            if winning_op.op_id() == remote_op.op_id() {
+                                new_ops.push(remote_op.clone());
+                            }
+                            // If local op wins, we don't apply remote op
+                        }
+                        ResolutionResult::Merge(ops) => {
+                            // Apply all merged operations
+                            for merged_op in ops {
+                                if merged_op.op_id() == remote_op.op_id() {
+                                    new_ops.push(merged_op);
+                                }
+                            }
+                        }
+                        ResolutionResult::Manual(_conflict) => {
+                            return Err(Error::Internal(format!(
+                                "Manual conflict resolution required for operation {}",
+                                op_id
+                            )));
+                        }
+                    }
+                    break;
+                }
+            }
+
+            // If no conflict, add the operation
+            if !has_conflict {
+                new_ops.push(remote_op.clone());
+            }
+
+            // Update state
+            let op_machine = remote_op.machine_id();
+            self.vector_clock.merge(remote_op.vector_clock());
+            self.record_in_copy_ledger(&remote_op);
+            self.operation_log.append(remote_op)?;
+            self.applied_ops.insert(op_id);
+
+            // This machine's clock just advanced, so any op waiting on it
+            // may now be causally ready; re-queue them to re-check.
+            if let Some(waiting) = self.deferred.remove(&op_machine) {
+                queue.extend(waiting);
+            }
+        }
+
+        Ok(new_ops)
+    }
+
+    /// Get the current vector clock
+    pub fn vector_clock(&self) -> &VectorClock {
+        &self.vector_clock
+    }
+
+    /// Get the operation log
+    pub fn operation_log(&self) -> &OperationLog {
+        &self.operation_log
+    }
+
+    /// Every conflict detected so far by [`Self::merge_operations`],
+    /// oldest first. These accumulate across calls; use
+    /// [`Self::take_conflicts`] to drain them once a caller has applied
+    /// its own resolution (e.g. renaming one side of a `CreateCreate`).
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Drain and return every conflict accumulated so far, leaving
+    /// [`Self::conflicts`] empty.
+    pub fn take_conflicts(&mut self) -> Vec<Conflict> {
+        std::mem::take(&mut self.conflicts)
+    }
+
+    /// What a peer whose clock is `vc` needs in order to catch up: plain
+    /// operation replay if `vc` already reflects this sync's snapshot, or
+    /// the snapshot plus the full remaining tail if `vc` predates the
+    /// last [`Self::compact`] call (see [`SyncPayload`]).
+    pub fn operations_after(&self, vc: &VectorClock) -> SyncPayload<'_> {
+        if is_causally_stable(self.snapshot.vector_clock(), vc) {
+            SyncPayload::Operations(self.operation_log.operations_after(vc))
+        } else {
+            SyncPayload::Snapshot {
+                state: &self.snapshot,
+                tail: self.operation_log.operations().iter().collect(),
+            }
+        }
+    }
+
+    /// The materialized state folded out of the log by [`Self::compact`]
+    /// so far. Empty until the first compaction.
+    pub fn snapshot(&self) -> &FilesystemState {
+        &self.snapshot
+    }
+
+    /// Fold every operation `stability_clock` dominates (causally
+    /// observed by every known replica — typically the elementwise
+    /// minimum of all peers' vector clocks) into the snapshot and drop it
+    /// from the live log, keeping only the still-concurrent tail. Delete
+    /// tombstones are folded into the snapshot too, but only permanently
+    /// forgotten once their `tombstone_time` is older than `gc_horizon`;
+    /// until then they stay in [`FilesystemState`] so a tardy concurrent
+    /// Create/Move that raced the delete can't resurrect the path.
+    pub fn compact(&mut self, stability_clock: &VectorClock, gc_horizon: SystemTime) -> CompactionStats {
+        let dominated = self.operation_log.compact(stability_clock);
+        let folded_into_snapshot = dominated.len();
+
+        for op in &dominated {
+            self.snapshot.apply(op);
+        }
+        self.snapshot.vector_clock.merge(stability_clock);
+
+        let tombstones_collected = self.snapshot.gc_tombstones(gc_horizon);
+
+        CompactionStats {
+            folded_into_snapshot,
+            tombstones_collected,
+            remaining_in_log: self.operation_log.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_or_set_add_contains() {
+        let mut set = OrSet::new();
+        set.add("a");
+        assert!(set.contains(&"a"));
+        assert!(!set.contains(&"b"));
+    }
+
+    #[test]
+    fn test_or_set_remove() {
+        let mut set = OrSet::new();
+        set.add("a");
+        set.remove(&"a");
+        assert!(!set.contains(&"a"));
+    }
+
+    #[test]
+    fn test_or_set_concurrent_add_wins_over_remove() {
+        // Replica A adds "a", replica B never observed that add and removes
+        // a value it doesn't have a tag for. After merging, the add survives.
+        let mut replica_a = OrSet::new();
+        replica_a.add("a");
+
+        let mut replica_b = OrSet::new();
+        replica_b.remove(&"a"); // no-op: replica B never observed an add-tag
+
+        replica_a.merge(&replica_b);
+        assert!(replica_a.contains(&"a"));
+    }
+
+    #[test]
+    fn test_or_set_merge_is_commutative_and_idempotent() {
+        let mut a = OrSet::new();
+        a.add("x");
+        let mut b = OrSet::new();
+        b.add("y");
+        b.remove(&"y");
+
+        let mut merged_ab = a.clone();
+        merged_ab.merge(&b);
+        let mut merged_ba = b.clone();
+        merged_ba.merge(&a);
+
+        assert_eq!(merged_ab.values().len(), merged_ba.values().len());
+        assert!(merged_ab.contains(&"x"));
+        assert!(!merged_ab.contains(&"y"));
+
+        // Merging again should not change anything (idempotent)
+        let before = merged_ab.values().len();
+        merged_ab.merge(&b);
+        assert_eq!(merged_ab.values().len(), before);
+    }
+
+    #[test]
+    fn test_or_set_add_tagged_and_remove_tag() {
+        let mut set: OrSet<&str> = OrSet::new();
+        let tag1 = Uuid::new_v4();
+        let tag2 = Uuid::new_v4();
+
+        // Two "creates" of the same name, tagged independently (e.g. by
+        // each create's own op_id).
+        set.add_tagged("file.txt", tag1);
+        set.add_tagged("file.txt", tag2);
+
+        // A delete that only observed the first create leaves the second
+        // add-tag alone, so the name is still present.
+        set.remove_tag(&"file.txt", tag1);
+        assert!(set.contains(&"file.txt"));
+
+        set.remove_tag(&"file.txt", tag2);
+        assert!(!set.contains(&"file.txt"));
+    }
+
+    #[test]
+    fn test_resolve_directory_empty_log_has_no_children() {
+        let log = OperationLog::new();
+        assert!(log.resolve_directory("/dir").is_empty());
+    }
+
+    #[test]
+    fn test_operation_log_append() {
+        let mut log = OperationLog::new();
+        let op_id = Uuid::new_v4();
+        let machine_id = Uuid::new_v4();
+
+        let op = CrdtOperation::Delete {
+            op_id,
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: SystemTime::now(),
+        };
+
+        assert!(log.is_empty());
+        log.append(op).unwrap();
+        assert_eq!(log.len(), 1);
+        assert!(log.contains(&op_id));
+    }
+
+    #[test]
+    fn test_operation_log_duplicate() {
+        let mut log = OperationLog::new();
+        let op_id = Uuid::new_v4();
+        let machine_id = Uuid::new_v4();
+
+        let op = CrdtOperation::Delete {
+            op_id,
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: SystemTime::now(),
+        };
+
+        log.append(op.clone()).unwrap();
+
+        // Attempting to append the same operation again should fail
+        assert!(log.append(op).is_err());
+    }
+
+    #[test]
+    fn test_operation_log_heads_tracks_forks() {
+        let mut log = OperationLog::new();
+        let machine_id = Uuid::new_v4();
+
+        let root_id = Uuid::new_v4();
+        log.append(CrdtOperation::Delete {
+            op_id: root_id,
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/a".to_string(),
+            tombstone_time: SystemTime::now(),
+        })
+        .unwrap();
+        assert_eq!(log.heads(), vec![root_id]);
+
+        // Two ops recorded concurrently on top of the same head fork the log.
+        let branch1 = Uuid::new_v4();
+        log.append(CrdtOperation::Delete {
+            op_id: branch1,
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![root_id],
+            path: "/b".to_string(),
+            tombstone_time: SystemTime::now(),
+        })
+        .unwrap();
+
+        let branch2 = Uuid::new_v4();
+        log.append(CrdtOperation::Delete {
+            op_id: branch2,
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![root_id],
+            path: "/c".to_string(),
+            tombstone_time: SystemTime::now(),
+        })
+        .unwrap();
+
+        let mut heads = log.heads();
+        heads.sort();
+        let mut expected = vec![branch1, branch2];
+        expected.sort();
+        assert_eq!(heads, expected);
+        assert!(matches!(log.op_heads(), OpHeads::Unresolved { .. }));
+
+        // Resolving the fork appends a single new head pointing at both.
+        let merge_id = log
+            .resolve_heads(|heads| {
+                let winner = heads[0];
+                Ok(CrdtOperation::Delete {
+                    op_id: Uuid::new_v4(),
+                    machine_id,
+                    vector_clock: winner.vector_clock().clone(),
+                    timestamp: SystemTime::now(),
+                    parents: vec![],
+                    path: "/merged".to_string(),
+                    tombstone_time: SystemTime::now(),
+                })
+            })
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(log.heads(), vec![merge_id]);
+        let mut parents = log.get(&merge_id).unwrap().parents().to_vec();
+        parents.sort();
+        assert_eq!(parents, expected);
+    }
+
+    #[test]
+    fn test_conflict_resolution_lww() {
+        let resolver = ConflictResolver::new(ConflictResolutionStrategy::LastWriteWins);
+        let machine1 = Uuid::new_v4();
+        let machine2 = Uuid::new_v4();
+
+        let ts1 = SystemTime::now();
+        let ts2 = ts1 + std::time::Duration::from_secs(1);
+
+        let op1 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: machine1,
+            vector_clock: VectorClock::new(),
+            timestamp: ts1,
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: ts1,
+        };
+
+        let op2 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: machine2,
+            vector_clock: VectorClock::new(),
+            timestamp: ts2,
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: ts2,
+        };
+
+        let conflict = Conflict {
+            op1: op1.clone(),
+            op2: op2.clone(),
+            conflict_type: ConflictType::DeleteDelete,
+        };
+
+        let result = resolver.resolve(&conflict).unwrap();
+
+        match result {
+            ResolutionResult::Winner(op) => {
+                assert_eq!(op.timestamp(), ts2); // Later timestamp wins
+            }
+            _ => panic!("Expected Winner result"),
+        }
+    }
+
+    #[test]
+    fn test_conflict_resolution_tie_breaker() {
+        let resolver = ConflictResolver::new(ConflictResolutionStrategy::LastWriteWins);
+        let machine1 = Uuid::new_v4();
+        let machine2 = Uuid::new_v4();
+
+        let ts = SystemTime::now();
+
+        let op1 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: machine1,
+            vector_clock: VectorClock::new(),
+            timestamp: ts,
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: ts,
+        };
+
+        let op2 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: machine2,
+            vector_clock: VectorClock::new(),
+            timestamp: ts,
+            parents: vec![],
+            path: "/test".to_string(),
+            tombstone_time: ts,
+        };
+
+        let conflict = Conflict {
+            op1: op1.clone(),
+            op2: op2.clone(),
+            conflict_type: ConflictType::DeleteDelete,
+        };
+
+        let result = resolver.resolve(&conflict).unwrap();
+
+        // Should resolve deterministically using machine ID
+        match result {
+            ResolutionResult::Winner(op) => {
+                let expected_machine = if machine1 < machine2 { machine1 } else { machine2 };
+                assert_eq!(op.machine_id(), expected_machine);
+            }
+            _ => panic!("Expected Winner result"),
+        }
+    }
+
+    /// Build a `Write` op for the conflict-detector tests below.
+    fn write_op(machine_id: Uuid, vector_clock: VectorClock, offset: u64, length: u64, data_hash: &str) -> CrdtOperation {
+        CrdtOperation::Write {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/f".to_string(),
+            offset,
+            data_hash: data_hash.to_string(),
+            length,
+        }
+    }
+
+    /// Two vector clocks that are concurrent: each machine only
+    /// incremented its own counter, so neither happened-before the other.
+    fn concurrent_clocks(machine_a: Uuid, machine_b: Uuid) -> (VectorClock, VectorClock) {
+        let mut vc_a = VectorClock::new();
+        vc_a.increment(machine_a);
+        let mut vc_b = VectorClock::new();
+        vc_b.increment(machine_b);
+        (vc_a, vc_b)
+    }
+
+    #[test]
+    fn test_write_write_non_overlapping_ranges_do_not_conflict() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+        let (vc_a, vc_b) = concurrent_clocks(machine_a, machine_b);
+
+        let op1 = write_op(machine_a, vc_a, 0, 100, "hash-a");
+        let op2 = write_op(machine_b, vc_b, 200, 100, "hash-b");
+
+        let detector = ConflictDetector::new(VectorClock::new());
+        assert!(detector.detect_conflict(&op1, &op2).is_none());
+    }
+
+    #[test]
+    fn test_write_write_overlapping_ranges_conflict() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+        let (vc_a, vc_b) = concurrent_clocks(machine_a, machine_b);
+
+        let op1 = write_op(machine_a, vc_a, 0, 100, "hash-a");
+        let op2 = write_op(machine_b, vc_b, 50, 100, "hash-b");
+
+        let detector = ConflictDetector::new(VectorClock::new());
+        let conflict = detector.detect_conflict(&op1, &op2).unwrap();
+        assert_eq!(conflict.conflict_type, ConflictType::WriteWrite);
+    }
+
+    #[test]
+    fn test_write_write_identical_chunk_is_deduped_not_a_conflict() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+        let (vc_a, vc_b) = concurrent_clocks(machine_a, machine_b);
+
+        // Same chunk range and same content hash: e.g. the same payload
+        // re-synced via two paths. Not a real conflict.
+        let op1 = write_op(machine_a, vc_a, 0, 100, "same-hash");
+        let op2 = write_op(machine_b, vc_b, 0, 100, "same-hash");
+
+        let detector = ConflictDetector::new(VectorClock::new());
+        assert!(detector.detect_conflict(&op1, &op2).is_none());
+    }
+
+    #[test]
+    fn test_chunked_write_ops_emits_one_write_per_chunk() {
+        let machine_id = Uuid::new_v4();
+        let data = vec![0xAB; 300 * 1024];
+
+        let ops = chunked_write_ops(
+            machine_id,
+            VectorClock::new(),
+            SystemTime::now(),
+            vec![],
+            "/big".to_string(),
+            0,
+            &data,
+        );
+
+        assert!(ops.len() > 1);
+        let mut expected_offset = 0u64;
+        for op in &ops {
+            let CrdtOperation::Write { path, offset, length, .. } = op else {
+                panic!("expected a Write op");
+            };
+            assert_eq!(path, "/big");
+            assert_eq!(*offset, expected_offset);
+            expected_offset += *length;
+        }
+        assert_eq!(expected_offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_lww_merge_keeps_later_timestamp() {
+        let node1 = Uuid::new_v4();
+        let node2 = Uuid::new_v4();
+
+        let mut a = Lww { ts: 10, node: node1, v: 1u32 };
+        let b = Lww { ts: 20, node: node2, v: 2u32 };
+
+        a.merge(&b);
+
+        assert_eq!(a.v, 2);
+        assert_eq!(a.ts, 20);
+        assert_eq!(a.node, node2);
+    }
+
+    #[test]
+    fn test_lww_merge_tie_breaks_on_node() {
+        let low_node = Uuid::nil();
+        let high_node = Uuid::max();
+
+        let mut a = Lww { ts: 10, node: high_node, v: "a" };
+        let b = Lww { ts: 10, node: low_node, v: "b" };
+
+        // Equal timestamps: the higher node id wins, regardless of merge order.
+        a.merge(&b);
+        assert_eq!(a.v, "a");
+
+        let mut c = Lww { ts: 10, node: low_node, v: "b" };
+        c.merge(&Lww { ts: 10, node: high_node, v: "a" });
+        assert_eq!(c.v, "a");
+    }
+
+    #[test]
+    fn test_copy_ledger_merge_keeps_non_conflicting_moves() {
+        let mut a = CopyLedger::new();
+        a.record_move(Uuid::new_v4(), "/a".to_string(), "/b".to_string(), SystemTime::now(), VectorClock::new());
+
+        let mut b = CopyLedger::new();
+        b.record_move(Uuid::new_v4(), "/c".to_string(), "/d".to_string(), SystemTime::now(), VectorClock::new());
+
+        a.merge(&b);
+        let traced = a.trace_copies_after(&VectorClock::new());
+        assert_eq!(traced.get("/a"), Some(&"/b".to_string()));
+        assert_eq!(traced.get("/c"), Some(&"/d".to_string()));
+    }
+
+    #[test]
+    fn test_copy_ledger_concurrent_rename_onto_same_dest_resolved_by_lww_and_remembered() {
+        let earlier = SystemTime::now();
+        let later = earlier + Duration::from_secs(1);
+
+        let op_early = Uuid::new_v4();
+        let op_late = Uuid::new_v4();
+
+        // Two branches concurrently rename different sources onto the same
+        // destination path: a genuine conflict, broken by timestamp.
+        let mut a = CopyLedger::new();
+        a.record_move(op_early, "/src-a".to_string(), "/dest".to_string(), earlier, VectorClock::new());
+
+        let mut b = CopyLedger::new();
+        b.record_move(op_late, "/src-b".to_string(), "/dest".to_string(), later, VectorClock::new());
+
+        a.merge(&b);
+        let traced = a.trace_copies_after(&VectorClock::new());
+        assert_eq!(traced.get("/src-b"), Some(&"/dest".to_string()));
+        assert!(traced.get("/src-a").is_none());
+
+        // Re-merging in the opposite direction must reach the same winner.
+        let mut a2 = CopyLedger::new();
+        a2.record_move(op_early, "/src-a".to_string(), "/dest".to_string(), earlier, VectorClock::new());
+        let mut b2 = CopyLedger::new();
+        b2.record_move(op_late, "/src-b".to_string(), "/dest".to_string(), later, VectorClock::new());
+        b2.merge(&a2);
+        let traced2 = b2.trace_copies_after(&VectorClock::new());
+        assert_eq!(traced2.get("/src-b"), Some(&"/dest".to_string()));
+    }
+
+    #[test]
+    fn test_copy_ledger_delete_beats_stale_recreate_via_overwritten_set() {
+        let op_delete = Uuid::new_v4();
+        let op_recreate = Uuid::new_v4();
+        let earlier = SystemTime::now();
+        let later = earlier + Duration::from_secs(1);
+
+        // Branch A deletes "/x", branch B (unaware of the delete) renames
+        // something new onto "/x" with an earlier timestamp than the
+        // delete, so on first merge the delete wins and folds B's op_id
+        // into its overwritten set.
+        let mut a = CopyLedger::new();
+        a.record_delete(op_delete, "/x".to_string(), later, VectorClock::new());
+
+        let mut b = CopyLedger::new();
+        b.record_move(op_recreate, "/y".to_string(), "/x".to_string(), earlier, VectorClock::new());
+
+        a.merge(&b);
+        assert!(a.trace_copies_after(&VectorClock::new()).get("/x").is_none());
+
+        // Now branch B re-derives its ledger without ever seeing the
+        // delete and merges again later: the delete's recorded
+        // overwritten-set must still beat it even though the timestamps
+        // alone would have favored the recreate.
+        let mut b_again = CopyLedger::new();
+        b_again.record_move(op_recreate, "/y".to_string(), "/x".to_string(), earlier, VectorClock::new());
+        let mut a_again = a.clone();
+        a_again.merge(&b_again);
+        assert!(a_again.trace_copies_after(&VectorClock::new()).get("/x").is_none());
+    }
+
+    #[test]
+    fn test_crdt_sync_record_operation() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        let op = CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: "test.txt".to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: crate::metadata::InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        };
+
+        sync.record_operation(op.clone()).unwrap();
+
+        assert_eq!(sync.pending_operations().len(), 1);
+        assert_eq!(sync.operation_log().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_operations_defers_out_of_order_delivery() {
+        let local_machine = Uuid::new_v4();
+        let remote_machine = Uuid::new_v4();
+        let mut sync = CrdtSync::new(local_machine, ConflictResolutionStrategy::LastWriteWins);
+
+        let mut vc1 = VectorClock::new();
+        vc1.increment(remote_machine);
+        let mut vc2 = vc1.clone();
+        vc2.increment(remote_machine);
+
+        let op1 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: remote_machine,
+            vector_clock: vc1,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/a".to_string(),
+            tombstone_time: SystemTime::now(),
+        };
+        let op2 = CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: remote_machine,
+            vector_clock: vc2,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/b".to_string(),
+            tombstone_time: SystemTime::now(),
+        };
+
+        // op2 depends on op1 (remote_machine's clock must reach 1 before
+        // 2), but it arrives first.
+        let applied = sync.merge_operations(vec![op2.clone()]).unwrap();
+        assert!(applied.is_empty());
+        assert_eq!(sync.pending_deferred_count(), 1);
+        assert_eq!(sync.operation_log().len(), 0);
+
+        // Once op1 arrives, both become applied in causal order.
+        let applied = sync.merge_operations(vec![op1.clone()]).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(sync.pending_deferred_count(), 0);
+        assert_eq!(sync.operation_log().len(), 2);
+    }
+
+    #[test]
+    fn test_compact_folds_stable_create_into_snapshot_and_empties_log() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        let op = CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: "a.txt".to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: crate::metadata::InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        };
+        sync.record_operation(op).unwrap();
+        assert_eq!(sync.operation_log().len(), 1);
+
+        // Every replica's clock is at least sync.vector_clock(): stable.
+        let stability_clock = sync.vector_clock().clone();
+        let gc_horizon = SystemTime::now() - Duration::from_secs(3600);
+        let stats = sync.compact(&stability_clock, gc_horizon);
+
+        assert_eq!(stats.folded_into_snapshot, 1);
+        assert_eq!(stats.remaining_in_log, 0);
+        assert_eq!(sync.operation_log().len(), 0);
+        assert!(sync.snapshot().contains("//a.txt"));
+    }
+
+    #[test]
+    fn test_compact_keeps_concurrent_tail_in_log() {
+        let local_machine = Uuid::new_v4();
+        let remote_machine = Uuid::new_v4();
+        let mut sync = CrdtSync::new(local_machine, ConflictResolutionStrategy::LastWriteWins);
+
+        // A stable delete the stability clock already reflects...
+        let mut stable_vc = VectorClock::new();
+        stable_vc.increment(remote_machine);
+        sync.merge_operations(vec![CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: remote_machine,
+            vector_clock: stable_vc.clone(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/stable".to_string(),
+            tombstone_time: SystemTime::now() - Duration::from_secs(3600),
+        }])
+        .unwrap();
+
+        // ...and a concurrent create from a third machine nobody else has
+        // observed yet, which must survive compaction in the live log.
+        let other_machine = Uuid::new_v4();
+        let mut concurrent_vc = VectorClock::new();
+        concurrent_vc.increment(other_machine);
+        sync.merge_operations(vec![CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id: other_machine,
+            vector_clock: concurrent_vc,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: "b.txt".to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: crate::metadata::InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        }])
+        .unwrap();
+
+        assert_eq!(sync.operation_log().len(), 2);
+
+        // Only `stable_vc` is known-observed-by-everyone; the concurrent
+        // create from `other_machine` isn't reflected in it.
+        let gc_horizon = SystemTime::now();
+        let stats = sync.compact(&stable_vc, gc_horizon);
+
+        assert_eq!(stats.folded_into_snapshot, 1);
+        assert_eq!(stats.remaining_in_log, 1);
+        assert_eq!(sync.operation_log().len(), 1);
+    }
+
+    #[test]
+    fn test_compact_keeps_tombstone_until_gc_horizon_then_collects_it() {
+        let local_machine = Uuid::new_v4();
+        let remote_machine = Uuid::new_v4();
+        let mut sync = CrdtSync::new(local_machine, ConflictResolutionStrategy::LastWriteWins);
+
+        let mut vc = VectorClock::new();
+        vc.increment(remote_machine);
+        let recent_tombstone_time = SystemTime::now();
+
+        sync.merge_operations(vec![CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id: remote_machine,
+            vector_clock: vc.clone(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/gone".to_string(),
+            tombstone_time: recent_tombstone_time,
+        }])
+        .unwrap();
+
+        // GC horizon is older than the tombstone: not collected yet, but
+        // still folded out of the live log and tracked in the snapshot.
+        let old_horizon = recent_tombstone_time - Duration::from_secs(60);
+        let stats = sync.compact(&vc, old_horizon);
+        assert_eq!(stats.folded_into_snapshot, 1);
+        assert_eq!(stats.tombstones_collected, 0);
+        assert!(sync.snapshot().is_tombstoned("/gone"));
+
+        // A later compaction with a horizon past the tombstone's time
+        // permanently forgets it.
+        let new_horizon = recent_tombstone_time + Duration::from_secs(60);
+        let stats = sync.compact(&vc, new_horizon);
+        assert_eq!(stats.tombstones_collected, 1);
+        assert!(!sync.snapshot().is_tombstoned("/gone"));
+    }
+
+    #[test]
+    fn test_operations_after_returns_snapshot_for_peer_predating_compaction() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        sync.record_operation(CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: "a.txt".to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: crate::metadata::InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        })
+        .unwrap();
+
+        let stability_clock = sync.vector_clock().clone();
+        sync.compact(&stability_clock, SystemTime::now());
+
+        // A peer starting from scratch (empty clock) predates the
+        // compaction and must be handed the snapshot, not just an empty
+        // operation list.
+        match sync.operations_after(&VectorClock::new()) {
+            SyncPayload::Snapshot { state, .. } => assert!(state.contains("//a.txt")),
+            SyncPayload::Operations(_) => panic!("expected Snapshot payload for a stale peer"),
+        }
+
+        // A peer already at least as caught-up as the snapshot gets plain
+        // operation replay (empty here, since everything was compacted).
+        match sync.operations_after(&stability_clock) {
+            SyncPayload::Operations(ops) => assert!(ops.is_empty()),
+            SyncPayload::Snapshot { .. } => panic!("expected Operations payload for a caught-up peer"),
+        }
+    }
+
+    fn sample_create(machine_id: Uuid, vc: VectorClock, name: &str) -> CrdtOperation {
+        CrdtOperation::Create {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: vc,
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            parent_path: "/".to_string(),
+            name: name.to_string(),
+            file_type: FileType::RegularFile,
+            initial_attrs: crate::metadata::InodeAttributes::new_file(1000, 1000, 0o644),
+            symlink_target: None,
+        }
+    }
+
+    #[test]
+    fn test_wal_survives_reopen_as_pending_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ops.wal");
+        let machine_id = Uuid::new_v4();
+
+        {
+            let mut sync = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "a.txt")).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "b.txt")).unwrap();
+        }
+
+        let reopened = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        assert_eq!(reopened.pending_operations().len(), 2);
+    }
+
+    #[test]
+    fn test_wal_acknowledged_operations_are_not_replayed_as_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ops.wal");
+        let machine_id = Uuid::new_v4();
+
+        let acked_id = {
+            let mut sync = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+            let op = sample_create(machine_id, VectorClock::new(), "a.txt");
+            let op_id = op.op_id();
+            sync.record_operation(op).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "b.txt")).unwrap();
+            sync.mark_uploaded(&[op_id]).unwrap();
+            op_id
+        };
+
+        let reopened = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        assert_eq!(reopened.pending_operations().len(), 1);
+        assert!(reopened.pending_operations().iter().all(|op| op.op_id() != acked_id));
+    }
+
+    #[test]
+    fn test_wal_replay_stops_at_truncated_trailing_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ops.wal");
+        let machine_id = Uuid::new_v4();
+
+        {
+            let mut sync = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "a.txt")).unwrap();
+        }
+
+        // Simulate a crash mid-append: a few trailing bytes of a second,
+        // never-completed record.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5]);
+        std::fs::write(&path, bytes).unwrap();
+
+        let reopened = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        assert_eq!(reopened.pending_operations().len(), 1);
+    }
+
+    #[test]
+    fn test_wal_replay_stops_at_corrupt_record_crc() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ops.wal");
+        let machine_id = Uuid::new_v4();
+
+        {
+            let mut sync = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "a.txt")).unwrap();
+            sync.record_operation(sample_create(machine_id, VectorClock::new(), "b.txt")).unwrap();
+        }
+
+        // Flip a byte inside the second record's payload so its CRC no
+        // longer matches; replay must still recover the first record.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let flip_at = bytes.len() - 1;
+        bytes[flip_at] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let reopened = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        assert_eq!(reopened.pending_operations().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_wal_compacts_away_acknowledged_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ops.wal");
+        let machine_id = Uuid::new_v4();
+
+        let mut sync = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        let op = sample_create(machine_id, VectorClock::new(), "a.txt");
+        let op_id = op.op_id();
+        sync.record_operation(op).unwrap();
+        sync.record_operation(sample_create(machine_id, VectorClock::new(), "b.txt")).unwrap();
+        sync.mark_uploaded(&[op_id]).unwrap();
+
+        let size_before_rotate = std::fs::metadata(&path).unwrap().len();
+        sync.rotate_wal().unwrap();
+        let size_after_rotate = std::fs::metadata(&path).unwrap().len();
+        assert!(size_after_rotate < size_before_rotate);
+
+        let reopened = CrdtSync::open(&path, machine_id, ConflictResolutionStrategy::LastWriteWins).unwrap();
+        assert_eq!(reopened.pending_operations().len(), 1);
+    }
+
+    #[test]
+    fn test_compact_pending_drops_write_superseded_by_later_delete_to_same_path() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        let write = CrdtOperation::Write {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/gone.txt".to_string(),
+            offset: 0,
+            data_hash: "hash-a".to_string(),
+            length: 100,
+        };
+        sync.record_operation(write).unwrap();
+        sync.record_operation(CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/gone.txt".to_string(),
+            tombstone_time: SystemTime::now(),
+        })
+        .unwrap();
+
+        let stats = sync.compact_pending();
+        assert_eq!(stats.ops_removed, 1);
+        assert_eq!(stats.bytes_deduplicated, 100);
+        assert_eq!(sync.pending_operations().len(), 1);
+        assert!(matches!(sync.pending_operations()[0], CrdtOperation::Delete { .. }));
+    }
+
+    #[test]
+    fn test_compact_pending_collapses_write_fully_covered_by_later_write() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 0, 50, "hash-old")).unwrap();
+        // Same range, different (newer) content: fully covers the first.
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 0, 50, "hash-new")).unwrap();
+
+        let stats = sync.compact_pending();
+        assert_eq!(stats.ops_removed, 1);
+        assert_eq!(stats.bytes_deduplicated, 50);
+
+        let remaining = sync.pending_operations();
+        assert_eq!(remaining.len(), 1);
+        match &remaining[0] {
+            CrdtOperation::Write { data_hash, .. } => assert_eq!(data_hash, "hash-new"),
+            other => panic!("expected Write, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_compact_pending_keeps_non_overlapping_writes_to_same_path() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 0, 50, "hash-a")).unwrap();
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 200, 50, "hash-b")).unwrap();
+
+        let stats = sync.compact_pending();
+        assert_eq!(stats.ops_removed, 0);
+        assert_eq!(sync.pending_operations().len(), 2);
+    }
+
+    #[test]
+    fn test_compact_pending_reports_repeated_content_hash_as_deduplicated() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        // Two different, non-overlapping offsets, but the exact same
+        // content (e.g. a repeated sparse-zero chunk).
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 0, 4096, "zero-chunk")).unwrap();
+        sync.record_operation(write_op(machine_id, VectorClock::new(), 4096, 4096, "zero-chunk")).unwrap();
+
+        let stats = sync.compact_pending();
+        assert_eq!(stats.ops_removed, 0);
+        assert_eq!(stats.bytes_deduplicated, 4096);
+        assert_eq!(sync.pending_operations().len(), 2);
+    }
+
+    #[test]
+    fn test_compact_pending_never_touches_already_acked_operations() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        let acked = write_op(machine_id, VectorClock::new(), 0, 50, "hash-a");
+        let acked_id = acked.op_id();
+        sync.record_operation(acked).unwrap();
+        sync.mark_uploaded(&[acked_id]).unwrap();
+
+        sync.record_operation(CrdtOperation::Delete {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/f".to_string(),
+            tombstone_time: SystemTime::now(),
+        })
+        .unwrap();
+
+        let stats = sync.compact_pending();
+        // The already-acked write is gone from `pending_operations()`
+        // entirely (by `mark_uploaded`, not by this call), so there was
+        // nothing for compaction to do here beyond the lone Delete.
+        assert_eq!(stats.ops_removed, 0);
+        assert_eq!(sync.operation_log().len(), 2);
+    }
+
+    #[test]
+    fn test_record_operation_stamps_op_with_current_vector_clock() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+
+        let first = sample_create(machine_id, VectorClock::new(), "a.txt");
+        sync.record_operation(first).unwrap();
+        let second = sample_create(machine_id, VectorClock::new(), "b.txt");
+        sync.record_operation(second).unwrap();
+
+        let logged = sync.operation_log().operations();
+        assert_eq!(logged[0].vector_clock().get(machine_id), 1);
+        assert_eq!(logged[1].vector_clock().get(machine_id), 2);
+    }
+
+    #[test]
+    fn test_merge_operations_records_concurrent_same_path_conflict() {
+        let local_machine = Uuid::new_v4();
+        let remote_machine = Uuid::new_v4();
+        let mut sync = CrdtSync::new(local_machine, ConflictResolutionStrategy::LastWriteWins);
+
+        let local_write = write_op(local_machine, VectorClock::new(), 0, 10, "local-hash");
+        sync.record_operation(local_write).unwrap();
+
+        // Concurrent remote write to the same byte range: neither clock
+        // dominates the other, so this should be flagged rather than
+        // silently overwritten.
+        let remote_write = write_op(remote_machine, VectorClock::with_initial(remote_machine, 1), 0, 10, "remote-hash");
+        sync.merge_operations(vec![remote_write]).unwrap();
+
+        let conflicts = sync.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].conflict_type, ConflictType::WriteWrite);
+
+        assert_eq!(sync.take_conflicts().len(), 1);
+        assert!(sync.conflicts().is_empty());
+    }
+}
@@ -12,12 +12,19 @@ pub mod sync;
 
 // Supporting modules
 pub mod namespace;
-pub mod types;
+pub mod namespace_config;
 
+pub mod chunking;
 pub mod crdt;
+pub mod filter_cascade;
+pub mod oplog;
+pub mod sync_sender;
+pub mod transaction;
 pub mod vector_clock;
+pub mod wire;
 
 pub mod identity;
+pub mod trusted_peers;
 
 // Re-export master-replica types
 pub use replication::{
@@ -27,15 +34,27 @@ pub use sync::{SyncConfig, SyncDaemon, SyncStatus};
 
 // Re-export supporting types
 pub use namespace::{
-    Namespace, NamespaceManager, PermissionType,
+    AccessRule, AccessSubject, MachineGroupRegistry, Namespace, NamespaceManager, NamespaceType,
+    PermissionType, Permissions, RoleRegistry, RuleEffect,
 };
-pub use types::{AccessRule, AccessSubject, NamespaceType, Permissions};
 
 // Re-export CRDT types
 pub use crdt::{
-    Conflict, ConflictDetector, ConflictResolutionStrategy, ConflictResolver, ConflictType,
-    CrdtOperation, CrdtSync, OperationLog, ResolutionResult,
+    chunked_write_ops, default_oplog_path, CausalityBarrier, CompactionStats, Conflict,
+    ConflictDetector, ConflictProof, ConflictResolutionStrategy, ConflictResolver, ConflictType,
+    CrdtOperation, CrdtSync, FilesystemState, OperationLog, OrSet, PendingCompactionStats,
+    ResolutionResult, SyncPayload,
 };
-pub use vector_clock::{ClockOrdering, VectorClock};
-
-pub use identity::{IdentityStore, IdentityStoreError, MachineIdentity};
+pub use chunking::{chunk, Chunk};
+pub use filter_cascade::FilterCascade;
+pub use oplog::{MetadataOp, OpLogEntry, OpLogManager};
+pub use sync_sender::{SyncSender, SyncTransport};
+pub use transaction::{run_transaction, Transaction};
+pub use vector_clock::{ClockOrdering, VectorClock, VersionVectorWithExceptions};
+
+pub use identity::{
+    verify_certificate, EnrollmentRequest, IdentityBackend, IdentityCertificate,
+    IdentityStore, IdentityStoreError, IdentityVerificationDecision, InMemoryIdentityBackend,
+    MachineIdentity, RecoveryMode, RotationRecord, SledIdentityBackend,
+};
+pub use trusted_peers::{generate_challenge, TrustedPeer, TrustedPeers};
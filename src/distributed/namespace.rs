@@ -4,9 +4,10 @@
 //! Multiple namespaces can coexist without interfering with each other.
 
 use crate::crypto::KEY_SIZE;
+use crate::distributed::crdt::OrSet;
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -28,8 +29,10 @@ pub enum NamespaceType {
     Distributed {
         /// Cluster identifier
         cluster_id: String,
-        /// Member machines in the cluster
-        members: Vec<Uuid>,
+        /// Member machines in the cluster, as an add-wins OR-Set so a
+        /// membership change made on one node converges with concurrent
+        /// changes made on others instead of clobbering them.
+        members: OrSet<Uuid>,
     },
 }
 
@@ -44,6 +47,8 @@ pub enum AccessSubject {
     AnyAuthenticated,
     /// Public access (anyone)
     Public,
+    /// A named role, as defined in a [`RoleRegistry`]
+    Role(String),
 }
 
 /// Permission flags
@@ -91,25 +96,234 @@ impl Permissions {
     }
 }
 
+/// Whether a matching [`AccessRule`] grants or withholds the permissions
+/// it names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleEffect {
+    Allow,
+    Deny,
+}
+
 /// Access control rule
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessRule {
     /// Who this rule applies to
     pub subject: AccessSubject,
-    /// What permissions are granted
+    /// Which permissions this rule names
     pub permissions: Permissions,
     /// Path pattern (glob-style)
     pub path_pattern: String,
+    /// Whether this rule allows or denies the permissions it names
+    pub effect: RuleEffect,
 }
 
 impl AccessRule {
-    /// Create a new access rule
+    /// Create a new allow rule
     pub fn new(subject: AccessSubject, permissions: Permissions, path_pattern: String) -> Self {
         Self {
             subject,
             permissions,
             path_pattern,
+            effect: RuleEffect::Allow,
+        }
+    }
+
+    /// Create a new deny rule
+    pub fn deny(subject: AccessSubject, permissions: Permissions, path_pattern: String) -> Self {
+        Self {
+            subject,
+            permissions,
+            path_pattern,
+            effect: RuleEffect::Deny,
+        }
+    }
+}
+
+/// How specific an [`AccessSubject`] is, for tie-breaking between equally
+/// path-specific rules: a named machine beats a role/group, which beats
+/// "any authenticated machine", which beats public access.
+fn subject_specificity(subject: &AccessSubject) -> i32 {
+    match subject {
+        AccessSubject::Machine(_) => 3,
+        AccessSubject::Role(_) | AccessSubject::MachineGroup(_) => 2,
+        AccessSubject::AnyAuthenticated => 1,
+        AccessSubject::Public => 0,
+    }
+}
+
+/// How specific a glob `path_pattern` is: fewer wildcards wins, ties
+/// broken by the longest literal (non-wildcard, non-`/`) text.
+fn path_specificity(pattern: &str) -> (i32, i32) {
+    let wildcards = pattern.chars().filter(|c| matches!(c, '*' | '?')).count() as i32;
+    let literal_len = pattern
+        .chars()
+        .filter(|c| !matches!(c, '*' | '?' | '/'))
+        .count() as i32;
+    (-wildcards, literal_len)
+}
+
+/// Registry of named machine groups, with nested-group membership.
+///
+/// Shared across namespaces via [`NamespaceManager`], mirroring
+/// [`RoleRegistry`] - group membership is an operator-level concept, not
+/// something scoped to a single namespace.
+#[derive(Debug, Clone, Default)]
+pub struct MachineGroupRegistry {
+    /// group name -> directly added machines
+    machines: HashMap<String, HashSet<Uuid>>,
+    /// group name -> directly nested groups
+    subgroups: HashMap<String, HashSet<String>>,
+}
+
+impl MachineGroupRegistry {
+    /// Create an empty group registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `machine_id` as a direct member of `group`
+    pub fn add_machine_to_group(&mut self, machine_id: Uuid, group: impl Into<String>) {
+        self.machines.entry(group.into()).or_default().insert(machine_id);
+    }
+
+    /// Remove `machine_id` as a direct member of `group`. A no-op if it
+    /// wasn't a direct member (e.g. it's only a member via nesting).
+    pub fn remove_machine_from_group(&mut self, machine_id: &Uuid, group: &str) {
+        if let Some(members) = self.machines.get_mut(group) {
+            members.remove(machine_id);
+        }
+    }
+
+    /// Nest `subgroup` inside `group`, so `subgroup`'s members are also
+    /// members of `group`.
+    pub fn add_subgroup(&mut self, group: impl Into<String>, subgroup: impl Into<String>) {
+        self.subgroups.entry(group.into()).or_default().insert(subgroup.into());
+    }
+
+    /// Whether `machine_id` is a member of `group`, directly or through
+    /// nested groups. Cycle-safe: a visited-set keeps nested group loops
+    /// from recursing forever.
+    pub fn is_member(&self, machine_id: &Uuid, group: &str) -> bool {
+        let mut visited = HashSet::new();
+        self.walk(group, machine_id, &mut visited)
+    }
+
+    fn walk(&self, group: &str, machine_id: &Uuid, visited: &mut HashSet<String>) -> bool {
+        if !visited.insert(group.to_string()) {
+            return false; // already visited - cycle or shared ancestor
+        }
+        if self
+            .machines
+            .get(group)
+            .is_some_and(|members| members.contains(machine_id))
+        {
+            return true;
+        }
+        self.subgroups
+            .get(group)
+            .into_iter()
+            .flatten()
+            .any(|subgroup| self.walk(subgroup, machine_id, visited))
+    }
+
+    /// Every group `machine_id` belongs to, directly or through nesting.
+    pub fn groups_for_machine(&self, machine_id: &Uuid) -> HashSet<String> {
+        self.machines
+            .keys()
+            .chain(self.subgroups.keys())
+            .filter(|group| self.is_member(machine_id, group))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A named role's definition: the roles it inherits grants from, and the
+/// grants it carries itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoleDef {
+    /// Names of roles this role inherits grants from
+    pub parents: Vec<String>,
+    /// Grants this role carries directly, independent of its parents
+    pub grants: Vec<(String, Permissions)>,
+}
+
+/// Registry of named roles (with inheritance) and which machines are
+/// assigned to them.
+///
+/// Shared across namespaces via [`NamespaceManager`], since roles are an
+/// operator-level concept rather than something scoped to a single
+/// namespace.
+#[derive(Debug, Clone, Default)]
+pub struct RoleRegistry {
+    /// Role name -> definition
+    roles: HashMap<String, RoleDef>,
+    /// Machine -> directly assigned role names (not the transitive closure)
+    assignments: HashMap<Uuid, HashSet<String>>,
+}
+
+impl RoleRegistry {
+    /// Create an empty role registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define (or redefine) a role
+    pub fn define_role(&mut self, name: impl Into<String>, def: RoleDef) {
+        self.roles.insert(name.into(), def);
+    }
+
+    /// Assign `machine_id` to `role`. A machine may hold multiple roles.
+    pub fn assign_role(&mut self, machine_id: Uuid, role: impl Into<String>) {
+        self.assignments.entry(machine_id).or_default().insert(role.into());
+    }
+
+    /// The roles directly assigned to a machine (not the transitive closure
+    /// through parents - see [`Self::role_closure`]).
+    pub fn roles_for_machine(&self, machine_id: &Uuid) -> HashSet<String> {
+        self.assignments.get(machine_id).cloned().unwrap_or_default()
+    }
+
+    /// Every role reachable from `role` by walking parents, including
+    /// `role` itself, with cycle protection so a role listing an ancestor
+    /// as a parent can't loop.
+    pub fn role_closure(&self, role: &str) -> HashSet<String> {
+        let mut visited = HashSet::new();
+        self.walk_parents(role, &mut visited);
+        visited
+    }
+
+    fn walk_parents(&self, role: &str, visited: &mut HashSet<String>) {
+        if !visited.insert(role.to_string()) {
+            return; // already visited - cycle or shared ancestor
         }
+        if let Some(def) = self.roles.get(role) {
+            for parent in &def.parents {
+                self.walk_parents(parent, visited);
+            }
+        }
+    }
+
+    /// Whether `machine_id` holds `role`, directly or via inheritance.
+    pub fn machine_has_role(&self, machine_id: &Uuid, role: &str) -> bool {
+        self.roles_for_machine(machine_id)
+            .iter()
+            .any(|assigned| self.role_closure(assigned).contains(role))
+    }
+
+    /// Every grant reachable from `machine_id`'s assigned roles, walking
+    /// parent roles transitively and evaluating each role in the closure
+    /// exactly once even if reached through more than one path.
+    pub fn effective_grants(&self, machine_id: &Uuid) -> Vec<(String, Permissions)> {
+        let mut closure = HashSet::new();
+        for role in self.roles_for_machine(machine_id) {
+            self.walk_parents(&role, &mut closure);
+        }
+
+        closure
+            .into_iter()
+            .filter_map(|role| self.roles.get(&role))
+            .flat_map(|def| def.grants.clone())
+            .collect()
     }
 }
 
@@ -122,14 +336,21 @@ pub struct Namespace {
     /// Namespace type
     pub namespace_type: NamespaceType,
 
-    /// Encryption key for this namespace
-    pub encryption_key: [u8; KEY_SIZE],
+    /// Encryption keys for this namespace, keyed by epoch. Rotation (see
+    /// [`Self::rotate_key`]) adds a new epoch rather than overwriting an
+    /// old one, so data encrypted under a previous epoch stays
+    /// decryptable - letting re-encryption onto the new key happen
+    /// lazily, file by file, instead of all at once.
+    pub key_ring: HashMap<u32, [u8; KEY_SIZE]>,
+
+    /// The epoch new writes are encrypted under
+    pub current_epoch: u32,
 
     /// Access control list
     pub acl: Vec<AccessRule>,
 
     /// Telegram message prefix for this namespace
-    /// Format: tgfs:{namespace_id}:{type}:{id}
+    /// Format: tgfs:{namespace_id}:{type}:{id}:{epoch}
     pub telegram_prefix: String,
 
     /// Description
@@ -144,17 +365,49 @@ impl Namespace {
         encryption_key: [u8; KEY_SIZE],
     ) -> Self {
         let telegram_prefix = format!("tgfs:{}", namespace_id);
+        let mut key_ring = HashMap::new();
+        key_ring.insert(0, encryption_key);
 
         Self {
             namespace_id: namespace_id.clone(),
             namespace_type,
-            encryption_key,
+            key_ring,
+            current_epoch: 0,
             acl: Vec::new(),
             telegram_prefix,
             description: None,
         }
     }
 
+    /// Roll over to a new encryption key under a freshly minted epoch,
+    /// without discarding the keys for older epochs - existing data
+    /// encrypted under those epochs remains readable via
+    /// [`Self::key_for_epoch`]. Returns the new epoch.
+    pub fn rotate_key(&mut self, new_key: [u8; KEY_SIZE]) -> u32 {
+        let epoch = self.current_epoch + 1;
+        self.key_ring.insert(epoch, new_key);
+        self.current_epoch = epoch;
+        epoch
+    }
+
+    /// The key for the current epoch, used to encrypt new writes
+    pub fn current_key(&self) -> &[u8; KEY_SIZE] {
+        self.key_ring
+            .get(&self.current_epoch)
+            .expect("current_epoch always has a key")
+    }
+
+    /// The key for a specific epoch, used to decrypt data written under
+    /// it. Errors if that epoch was pruned (or never existed).
+    pub fn key_for_epoch(&self, epoch: u32) -> Result<&[u8; KEY_SIZE]> {
+        self.key_ring.get(&epoch).ok_or_else(|| {
+            Error::KeyNotFound(format!(
+                "namespace {:?} has no key for epoch {}",
+                self.namespace_id, epoch
+            ))
+        })
+    }
+
     /// Create a standalone namespace
     pub fn standalone(namespace_id: String, encryption_key: [u8; KEY_SIZE]) -> Self {
         Self::new(namespace_id, NamespaceType::Standalone, encryption_key)
@@ -184,65 +437,153 @@ impl Namespace {
         cluster_id: String,
         members: Vec<Uuid>,
     ) -> Self {
+        let mut member_set = OrSet::new();
+        for member in members {
+            member_set.add(member);
+        }
         Self::new(
             namespace_id,
             NamespaceType::Distributed {
                 cluster_id,
-                members,
+                members: member_set,
             },
             encryption_key,
         )
     }
 
+    /// Merge another replica's view of this namespace into this one.
+    ///
+    /// For a distributed namespace this merges the membership OR-Set so
+    /// concurrent `add_member`/`remove_member` calls on different nodes
+    /// converge rather than one overwriting the other.
+    pub fn merge(&mut self, other: &Namespace) {
+        if let (
+            NamespaceType::Distributed { members, .. },
+            NamespaceType::Distributed {
+                members: other_members,
+                ..
+            },
+        ) = (&mut self.namespace_type, &other.namespace_type)
+        {
+            members.merge(other_members);
+        }
+    }
+
+    /// Add a member to a distributed namespace. No-op for other namespace types.
+    pub fn add_member(&mut self, machine_id: Uuid) {
+        if let NamespaceType::Distributed { members, .. } = &mut self.namespace_type {
+            members.add(machine_id);
+        }
+    }
+
+    /// Remove a member from a distributed namespace. No-op for other namespace types.
+    pub fn remove_member(&mut self, machine_id: &Uuid) {
+        if let NamespaceType::Distributed { members, .. } = &mut self.namespace_type {
+            members.remove(machine_id);
+        }
+    }
+
     /// Add an access rule
     pub fn add_rule(&mut self, rule: AccessRule) {
         self.acl.push(rule);
     }
 
     /// Check if a machine has permission for a path
+    ///
+    /// `roles`, if given, resolves `AccessSubject::Role` rules and lets a
+    /// machine also draw on the grants its assigned roles carry directly
+    /// (walked transitively through role inheritance - see
+    /// [`RoleRegistry::effective_grants`]). `groups`, if given, resolves
+    /// `AccessSubject::MachineGroup` rules (including nested groups - see
+    /// [`MachineGroupRegistry::is_member`]). Pass `None` for deployments
+    /// with no role or group subsystem configured.
+    ///
+    /// Every rule naming the requested permission whose subject and path
+    /// both match is a candidate; among candidates, the one with the most
+    /// specific `path_pattern` wins (fewest wildcards, then longest literal
+    /// text), with subject specificity (`Machine` > `Role`/`MachineGroup` >
+    /// `AnyAuthenticated` > `Public`) breaking ties between equally
+    /// specific paths, and `Deny` breaking ties between equally specific
+    /// subjects. A role's direct grants (not mediated by a `Role(...)`
+    /// rule) are treated as `Allow` at `Role` subject specificity. If
+    /// nothing matches, access is denied.
     pub fn check_permission(
         &self,
         machine_id: &Uuid,
         path: &str,
         required_permission: PermissionType,
+        roles: Option<&RoleRegistry>,
+        groups: Option<&MachineGroupRegistry>,
     ) -> bool {
+        let grants_permission = |permissions: &Permissions| match required_permission {
+            PermissionType::Read => permissions.read,
+            PermissionType::Write => permissions.write,
+            PermissionType::Delete => permissions.delete,
+            PermissionType::Admin => permissions.admin,
+        };
+
+        // (path specificity, subject specificity, effect) for every rule
+        // whose subject and path match and that names the permission asked
+        // for, so the most specific one can be picked below.
+        let mut candidates: Vec<((i32, i32), i32, RuleEffect)> = Vec::new();
+
         for rule in &self.acl {
-            // Check if subject matches
             let subject_matches = match &rule.subject {
                 AccessSubject::Machine(id) => id == machine_id,
                 AccessSubject::AnyAuthenticated => true,
                 AccessSubject::Public => true,
-                AccessSubject::MachineGroup(_) => false, // TODO: implement groups
+                AccessSubject::MachineGroup(group) => groups
+                    .map(|g| g.is_member(machine_id, group))
+                    .unwrap_or(false),
+                AccessSubject::Role(role) => roles
+                    .map(|r| r.machine_has_role(machine_id, role))
+                    .unwrap_or(false),
             };
 
-            if !subject_matches {
+            if !subject_matches || !path_matches(&rule.path_pattern, path) {
                 continue;
             }
 
-            // Check if path matches (simple prefix match for now)
-            if !path.starts_with(&rule.path_pattern) && rule.path_pattern != "*" {
-                continue;
+            if grants_permission(&rule.permissions) {
+                candidates.push((
+                    path_specificity(&rule.path_pattern),
+                    subject_specificity(&rule.subject),
+                    rule.effect,
+                ));
             }
+        }
 
-            // Check if permission is granted
-            let permission_granted = match required_permission {
-                PermissionType::Read => rule.permissions.read,
-                PermissionType::Write => rule.permissions.write,
-                PermissionType::Delete => rule.permissions.delete,
-                PermissionType::Admin => rule.permissions.admin,
-            };
-
-            if permission_granted {
-                return true;
+        // Grants a machine's roles carry directly (not mediated by an ACL
+        // rule with subject `Role(...)`).
+        if let Some(roles) = roles {
+            for (pattern, permissions) in roles.effective_grants(machine_id) {
+                if path_matches(&pattern, path) && grants_permission(&permissions) {
+                    candidates.push((
+                        path_specificity(&pattern),
+                        subject_specificity(&AccessSubject::Role(String::new())),
+                        RuleEffect::Allow,
+                    ));
+                }
             }
         }
 
-        false
+        candidates
+            .into_iter()
+            .max_by_key(|&(path_spec, subject_spec, effect)| {
+                (path_spec, subject_spec, effect == RuleEffect::Deny)
+            })
+            .is_some_and(|(_, _, effect)| effect == RuleEffect::Allow)
     }
 
-    /// Generate a Telegram message caption for this namespace
+    /// Generate a Telegram message caption for this namespace, embedding
+    /// the epoch the message was (or will be) encrypted under so it can
+    /// be decrypted with the right key later even after the namespace
+    /// has rotated past that epoch.
     pub fn telegram_caption(&self, msg_type: &str, id: &str) -> String {
-        format!("{}:{}:{}", self.telegram_prefix, msg_type, id)
+        format!(
+            "{}:{}:{}:{}",
+            self.telegram_prefix, msg_type, id, self.current_epoch
+        )
     }
 
     /// Get the storage key prefix for this namespace
@@ -257,6 +598,66 @@ impl Namespace {
     }
 }
 
+/// Match `path` against a glob-style `pattern`, the way [`AccessRule`]'s
+/// `path_pattern` is documented to work: `*` matches any run of characters
+/// within a single path segment, `?` matches a single character, and `**`
+/// matches zero or more whole segments (so a pattern ending in `**`
+/// matches any deeper path, and `**` alone matches everything).
+///
+/// Both sides are split on `/` and matched segment by segment; a pattern
+/// with no wildcards must match the full path exactly, not just a prefix.
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern_segments: Vec<&str> = pattern.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern_segments, &path_segments)
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(&"**") => {
+            // Try consuming an increasing number of path segments before
+            // matching whatever comes after "**" in the pattern.
+            for consumed in 0..=path.len() {
+                if match_segments(&pattern[1..], &path[consumed..]) {
+                    return true;
+                }
+            }
+            false
+        }
+        Some(&segment_pattern) => match path.first() {
+            Some(&segment) if segment_matches(segment_pattern, segment) => {
+                match_segments(&pattern[1..], &path[1..])
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Match a single path segment against a pattern segment that may contain
+/// `*` (any run of characters) and `?` (any single character).
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let segment: Vec<char> = segment.chars().collect();
+    segment_matches_from(&pattern, &segment)
+}
+
+fn segment_matches_from(pattern: &[char], segment: &[char]) -> bool {
+    match pattern.first() {
+        None => segment.is_empty(),
+        Some('*') => {
+            for consumed in 0..=segment.len() {
+                if segment_matches_from(&pattern[1..], &segment[consumed..]) {
+                    return true;
+                }
+            }
+            false
+        }
+        Some('?') => !segment.is_empty() && segment_matches_from(&pattern[1..], &segment[1..]),
+        Some(&c) => segment.first() == Some(&c) && segment_matches_from(&pattern[1..], &segment[1..]),
+    }
+}
+
 /// Permission types for access control
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PermissionType {
@@ -334,9 +735,18 @@ impl NamespaceManager {
         self.namespaces.len()
     }
 
-    /// Route a Telegram message caption to the correct namespace
-    pub fn route_telegram_message(&self, caption: &str) -> Result<(Arc<Namespace>, String, String)> {
-        // Parse caption: tgfs:{namespace}:{type}:{id}
+    /// Route a Telegram message caption to the correct namespace,
+    /// returning the epoch the message was encrypted under so the caller
+    /// can fetch the matching key via [`Namespace::key_for_epoch`].
+    ///
+    /// Accepts both `tgfs:{namespace}:{type}:{id}:{epoch}` and the old
+    /// `tgfs:{namespace}:{type}:{id}` format (no epoch), which is treated
+    /// as epoch 0 for backward compatibility with messages sent before
+    /// key rotation existed.
+    pub fn route_telegram_message(
+        &self,
+        caption: &str,
+    ) -> Result<(Arc<Namespace>, String, String, u32)> {
         if !caption.starts_with("tgfs:") {
             return Err(Error::Config(format!("invalid message caption: {}", caption)));
         }
@@ -351,16 +761,36 @@ impl NamespaceManager {
 
         let namespace_id = parts[0];
         let msg_type = parts[1].to_string();
-        let msg_id = parts[2].to_string();
+
+        // `parts[2]` is either "{id}" (old format) or "{id}:{epoch}" (new
+        // format); the id itself may contain ':', so only a trailing
+        // all-digit segment is treated as an epoch.
+        let (msg_id, epoch) = match parts[2].rsplit_once(':') {
+            Some((id, epoch_str)) if !epoch_str.is_empty() && epoch_str.bytes().all(|b| b.is_ascii_digit()) => {
+                let epoch = epoch_str.parse().map_err(|_| {
+                    Error::Config(format!("invalid message caption format: {}", caption))
+                })?;
+                (id.to_string(), epoch)
+            }
+            _ => (parts[2].to_string(), 0),
+        };
 
         let namespace = self.get_namespace(namespace_id)?;
-        Ok((namespace, msg_type, msg_id))
+        Ok((namespace, msg_type, msg_id, epoch))
     }
 
     /// Check if a namespace exists
     pub fn has_namespace(&self, namespace_id: &str) -> bool {
         self.namespaces.contains_key(namespace_id)
     }
+
+    /// Build a manager (and its [`RoleRegistry`]) from a directory
+    /// containing `machines.toml`, `roles.toml`, and `namespaces.toml`.
+    /// See [`namespace_config::load_config_dir`](super::namespace_config::load_config_dir)
+    /// for the file layout and the referential-integrity checks applied.
+    pub fn from_config_dir(dir: impl AsRef<std::path::Path>) -> Result<(Self, RoleRegistry)> {
+        super::namespace_config::load_config_dir(dir.as_ref())
+    }
 }
 
 #[cfg(test)]
@@ -381,9 +811,11 @@ mod tests {
 
     #[test]
     fn test_telegram_caption() {
-        let ns = Namespace::standalone("myns".to_string(), test_key());
-        let caption = ns.telegram_caption("chunk", "abc123");
-        assert_eq!(caption, "tgfs:myns:chunk:abc123");
+        let mut ns = Namespace::standalone("myns".to_string(), test_key());
+        assert_eq!(ns.telegram_caption("chunk", "abc123"), "tgfs:myns:chunk:abc123:0");
+
+        ns.rotate_key([9u8; KEY_SIZE]);
+        assert_eq!(ns.telegram_caption("chunk", "abc123"), "tgfs:myns:chunk:abc123:1");
     }
 
     #[test]
@@ -410,12 +842,35 @@ mod tests {
         let ns = Namespace::standalone("test".to_string(), test_key());
         mgr.add_namespace(ns).unwrap();
 
-        let (namespace, msg_type, msg_id) =
+        // Old-format caption (no epoch) routes as epoch 0.
+        let (namespace, msg_type, msg_id, epoch) =
             mgr.route_telegram_message("tgfs:test:chunk:abc123").unwrap();
 
         assert_eq!(namespace.namespace_id, "test");
         assert_eq!(msg_type, "chunk");
         assert_eq!(msg_id, "abc123");
+        assert_eq!(epoch, 0);
+
+        // New-format caption carries its epoch explicitly.
+        let (_, _, msg_id, epoch) = mgr
+            .route_telegram_message("tgfs:test:chunk:abc123:3")
+            .unwrap();
+        assert_eq!(msg_id, "abc123");
+        assert_eq!(epoch, 3);
+    }
+
+    #[test]
+    fn test_rotate_key_preserves_old_epochs() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let original = *ns.current_key();
+
+        let epoch = ns.rotate_key([1u8; KEY_SIZE]);
+
+        assert_eq!(epoch, 1);
+        assert_eq!(ns.current_epoch, 1);
+        assert_eq!(*ns.current_key(), [1u8; KEY_SIZE]);
+        assert_eq!(*ns.key_for_epoch(0).unwrap(), original);
+        assert!(ns.key_for_epoch(2).is_err());
     }
 
     #[test]
@@ -426,14 +881,211 @@ mod tests {
         let rule = AccessRule::new(
             AccessSubject::Machine(machine_id),
             Permissions::read_write(),
-            "*".to_string(),
+            "**".to_string(),
         );
 
         ns.add_rule(rule);
 
-        assert!(ns.check_permission(&machine_id, "/any/path", PermissionType::Read));
-        assert!(ns.check_permission(&machine_id, "/any/path", PermissionType::Write));
-        assert!(!ns.check_permission(&machine_id, "/any/path", PermissionType::Admin));
+        assert!(ns.check_permission(&machine_id, "/any/path", PermissionType::Read, None, None));
+        assert!(ns.check_permission(&machine_id, "/any/path", PermissionType::Write, None, None));
+        assert!(!ns.check_permission(&machine_id, "/any/path", PermissionType::Admin, None, None));
+    }
+
+    #[test]
+    fn test_role_inheritance_grants_access() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        let mut roles = RoleRegistry::new();
+        roles.define_role(
+            "reader",
+            RoleDef {
+                parents: vec![],
+                grants: vec![("/docs/**".to_string(), Permissions::read_only())],
+            },
+        );
+        roles.define_role(
+            "writer",
+            RoleDef {
+                parents: vec!["reader".to_string()],
+                grants: vec![("/docs/**".to_string(), Permissions::read_write())],
+            },
+        );
+        roles.assign_role(machine_id, "writer");
+
+        ns.add_rule(AccessRule::new(
+            AccessSubject::Role("admin".to_string()),
+            Permissions::full(),
+            "**".to_string(),
+        ));
+
+        // "writer" inherits "reader"'s grants, so both succeed even though
+        // only "writer" carries write access directly.
+        assert!(ns.check_permission(&machine_id, "/docs/a", PermissionType::Read, Some(&roles), None));
+        assert!(ns.check_permission(&machine_id, "/docs/a", PermissionType::Write, Some(&roles), None));
+        // Not a member of "admin", so that rule doesn't apply.
+        assert!(!ns.check_permission(&machine_id, "/docs/a", PermissionType::Admin, Some(&roles), None));
+        // No registry supplied: role-derived grants don't apply at all.
+        assert!(!ns.check_permission(&machine_id, "/docs/a", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_role_subject_rule_matches_via_inheritance() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        let mut roles = RoleRegistry::new();
+        roles.define_role("reader", RoleDef::default());
+        roles.define_role(
+            "admin",
+            RoleDef {
+                parents: vec!["reader".to_string()],
+                grants: vec![],
+            },
+        );
+        roles.assign_role(machine_id, "admin");
+
+        ns.add_rule(AccessRule::new(
+            AccessSubject::Role("reader".to_string()),
+            Permissions::read_only(),
+            "/shared/**".to_string(),
+        ));
+
+        // Machine only holds "admin" directly, but "admin" inherits
+        // "reader", so a rule scoped to "reader" still matches.
+        assert!(ns.check_permission(&machine_id, "/shared/x", PermissionType::Read, Some(&roles), None));
+    }
+
+    #[test]
+    fn test_role_registry_handles_cycles() {
+        let mut roles = RoleRegistry::new();
+        roles.define_role(
+            "a",
+            RoleDef {
+                parents: vec!["b".to_string()],
+                grants: vec![],
+            },
+        );
+        roles.define_role(
+            "b",
+            RoleDef {
+                parents: vec!["a".to_string()],
+                grants: vec![],
+            },
+        );
+
+        let machine_id = Uuid::new_v4();
+        roles.assign_role(machine_id, "a");
+
+        // Must terminate and still resolve both roles in the cycle.
+        assert!(roles.machine_has_role(&machine_id, "a"));
+        assert!(roles.machine_has_role(&machine_id, "b"));
+        assert!(!roles.machine_has_role(&machine_id, "c"));
+    }
+
+    #[test]
+    fn test_deny_rule_overrides_equally_specific_allow() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        ns.add_rule(AccessRule::new(
+            AccessSubject::Machine(machine_id),
+            Permissions::read_write(),
+            "/data/**".to_string(),
+        ));
+        ns.add_rule(AccessRule::deny(
+            AccessSubject::Machine(machine_id),
+            Permissions::read_write(),
+            "/data/**".to_string(),
+        ));
+
+        // Same path pattern, same subject specificity: deny wins the tie.
+        assert!(!ns.check_permission(&machine_id, "/data/x", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_more_specific_allow_overrides_broader_deny() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        ns.add_rule(AccessRule::deny(
+            AccessSubject::Machine(machine_id),
+            Permissions::read_write(),
+            "**".to_string(),
+        ));
+        ns.add_rule(AccessRule::new(
+            AccessSubject::Machine(machine_id),
+            Permissions::read_write(),
+            "/public/**".to_string(),
+        ));
+
+        // "/public/**" is a more specific path pattern than "**", so its
+        // allow wins even though a broader deny also matches.
+        assert!(ns.check_permission(&machine_id, "/public/x", PermissionType::Read, None, None));
+        // Outside "/public", only the broad deny matches.
+        assert!(!ns.check_permission(&machine_id, "/other/x", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_no_matching_rule_defaults_to_deny() {
+        let ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        assert!(!ns.check_permission(&machine_id, "/anything", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_machine_group_rule_matches_via_membership() {
+        let mut ns = Namespace::standalone("test".to_string(), test_key());
+        let machine_id = Uuid::new_v4();
+
+        let mut groups = MachineGroupRegistry::new();
+        groups.add_machine_to_group(machine_id, "backup-nodes");
+
+        ns.add_rule(AccessRule::new(
+            AccessSubject::MachineGroup("backup-nodes".to_string()),
+            Permissions::read_only(),
+            "/backups/**".to_string(),
+        ));
+
+        assert!(ns.check_permission(
+            &machine_id,
+            "/backups/x",
+            PermissionType::Read,
+            None,
+            Some(&groups)
+        ));
+        // No registry supplied: group-scoped rules don't apply at all.
+        assert!(!ns.check_permission(&machine_id, "/backups/x", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_machine_group_resolves_nested_groups() {
+        let machine_id = Uuid::new_v4();
+        let mut groups = MachineGroupRegistry::new();
+        groups.add_machine_to_group(machine_id, "us-east");
+        groups.add_subgroup("all-regions", "us-east");
+
+        // Membership in "us-east" implies membership in "all-regions".
+        assert!(groups.is_member(&machine_id, "all-regions"));
+        assert!(groups.groups_for_machine(&machine_id).contains("all-regions"));
+
+        groups.remove_machine_from_group(&machine_id, "us-east");
+        assert!(!groups.is_member(&machine_id, "all-regions"));
+    }
+
+    #[test]
+    fn test_machine_group_handles_cycles() {
+        let machine_id = Uuid::new_v4();
+        let mut groups = MachineGroupRegistry::new();
+        groups.add_subgroup("a", "b");
+        groups.add_subgroup("b", "a");
+        groups.add_machine_to_group(machine_id, "a");
+
+        // Must terminate and still resolve both groups in the cycle.
+        assert!(groups.is_member(&machine_id, "a"));
+        assert!(groups.is_member(&machine_id, "b"));
+        assert!(!groups.is_member(&machine_id, "c"));
     }
 
     #[test]
@@ -476,9 +1128,66 @@ mod tests {
         match ns.namespace_type {
             NamespaceType::Distributed { cluster_id, members } => {
                 assert_eq!(cluster_id, "my-cluster");
-                assert_eq!(members.len(), 2);
+                assert_eq!(members.values().len(), 2);
+                assert!(members.contains(&member1));
+            }
+            _ => panic!("wrong namespace type"),
+        }
+    }
+
+    #[test]
+    fn test_distributed_namespace_membership_converges() {
+        let member1 = Uuid::new_v4();
+        let member2 = Uuid::new_v4();
+
+        let mut node_a = Namespace::distributed(
+            "cluster".to_string(),
+            test_key(),
+            "my-cluster".to_string(),
+            vec![member1],
+        );
+        let mut node_b = node_a.clone();
+
+        // Node A adds member2 while node B, unaware of it, removes member1.
+        node_a.add_member(member2);
+        node_b.remove_member(&member1);
+
+        node_a.merge(&node_b);
+
+        match &node_a.namespace_type {
+            NamespaceType::Distributed { members, .. } => {
+                assert!(!members.contains(&member1));
+                assert!(members.contains(&member2));
             }
             _ => panic!("wrong namespace type"),
         }
     }
+
+    #[test]
+    fn test_path_matches_literal() {
+        assert!(path_matches("/a/b", "/a/b"));
+        assert!(!path_matches("/a/b", "/a/b/c"));
+        assert!(!path_matches("/a/b", "/a"));
+        assert!(path_matches("", ""));
+    }
+
+    #[test]
+    fn test_path_matches_single_segment_wildcards() {
+        assert!(path_matches("/a/*/c", "/a/b/c"));
+        assert!(!path_matches("/a/*/c", "/a/b/x/c"));
+        assert!(path_matches("/a/fil?.txt", "/a/file.txt"));
+        assert!(!path_matches("/a/fil?.txt", "/a/file1.txt"));
+    }
+
+    #[test]
+    fn test_path_matches_double_star() {
+        assert!(path_matches("/a/**", "/a"));
+        assert!(path_matches("/a/**", "/a/b"));
+        assert!(path_matches("/a/**", "/a/b/c/d"));
+        assert!(path_matches("**", "/anything/at/all"));
+        assert!(!path_matches("/a/**", "/x/y"));
+        assert!(path_matches("/a/**/z", "/a/z"));
+        assert!(path_matches("/a/**/z", "/a/b/c/z"));
+        assert!(!path_matches("/a/**/z", "/a/b/c"));
+    }
 }
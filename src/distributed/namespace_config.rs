@@ -0,0 +1,448 @@
+//! TOML configuration loading for [`NamespaceManager`](super::namespace::NamespaceManager)
+//!
+//! Lets namespaces, machines, and roles be declared in `machines.toml`,
+//! `roles.toml`, and `namespaces.toml` instead of only assembled
+//! programmatically. See [`load_config_dir`] for the expected layout.
+
+use crate::crypto::KEY_SIZE;
+use crate::distributed::crdt::OrSet;
+use crate::error::{Error, Result};
+use base64::Engine as _;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use super::namespace::{
+    AccessRule, AccessSubject, Namespace, NamespaceManager, NamespaceType, Permissions, RoleDef,
+    RoleRegistry, RuleEffect,
+};
+
+/// `machines.toml`: machines keyed by their `Uuid`, with a display name
+/// and the grants they carry directly (independent of any role).
+#[derive(Debug, Deserialize, Default)]
+struct MachinesFile {
+    #[serde(default)]
+    machines: HashMap<String, MachineEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)] // `name`/`description` are for operator-facing tooling, not consulted here
+struct MachineEntry {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    grants: Vec<GrantEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GrantEntry {
+    path_pattern: String,
+    permissions: PermissionsEntry,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PermissionsEntry {
+    #[serde(default)]
+    read: bool,
+    #[serde(default)]
+    write: bool,
+    #[serde(default)]
+    delete: bool,
+    #[serde(default)]
+    admin: bool,
+}
+
+impl From<PermissionsEntry> for Permissions {
+    fn from(p: PermissionsEntry) -> Self {
+        Permissions {
+            read: p.read,
+            write: p.write,
+            delete: p.delete,
+            admin: p.admin,
+        }
+    }
+}
+
+/// `roles.toml`: named roles, their parents, and the grants they carry.
+#[derive(Debug, Deserialize, Default)]
+struct RolesFile {
+    #[serde(default)]
+    roles: HashMap<String, RoleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoleEntry {
+    #[serde(default)]
+    parents: Vec<String>,
+    #[serde(default)]
+    grants: Vec<GrantEntry>,
+}
+
+/// `namespaces.toml`: the default namespace and every namespace's type,
+/// description, key, and ACL.
+#[derive(Debug, Deserialize)]
+struct NamespacesFile {
+    default: String,
+    #[serde(default)]
+    namespaces: HashMap<String, NamespaceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamespaceEntry {
+    #[serde(flatten)]
+    namespace_type: NamespaceTypeEntry,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    encryption_key_file: Option<String>,
+    #[serde(default)]
+    encryption_key_base64: Option<String>,
+    #[serde(default)]
+    acl: Vec<AclRuleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum NamespaceTypeEntry {
+    Standalone,
+    MasterReplica {
+        master_id: Uuid,
+        #[serde(default)]
+        replicas: Vec<Uuid>,
+    },
+    Distributed {
+        cluster_id: String,
+        #[serde(default)]
+        members: Vec<Uuid>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct AclRuleEntry {
+    subject: SubjectEntry,
+    path_pattern: String,
+    permissions: PermissionsEntry,
+    #[serde(default)]
+    effect: EffectEntry,
+}
+
+/// Either a bare keyword (`"any_authenticated"` / `"public"`) or a table
+/// naming a machine, role, or group.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SubjectEntry {
+    Keyword(String),
+    Machine { machine: String },
+    Role { role: String },
+    Group { group: String },
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum EffectEntry {
+    #[default]
+    Allow,
+    Deny,
+}
+
+impl From<EffectEntry> for RuleEffect {
+    fn from(e: EffectEntry) -> Self {
+        match e {
+            EffectEntry::Allow => RuleEffect::Allow,
+            EffectEntry::Deny => RuleEffect::Deny,
+        }
+    }
+}
+
+fn read_toml<T: serde::de::DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::InvalidConfig(format!("failed to read {:?}: {}", path, e)))?;
+    toml::from_str(&content)
+        .map_err(|e| Error::InvalidConfig(format!("failed to parse {:?}: {}", path, e)))
+}
+
+fn decode_encryption_key(dir: &Path, ns_id: &str, entry: &NamespaceEntry) -> Result<[u8; KEY_SIZE]> {
+    let raw = match (&entry.encryption_key_file, &entry.encryption_key_base64) {
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidConfig(format!(
+                "namespace {:?} specifies both encryption_key_file and encryption_key_base64",
+                ns_id
+            )))
+        }
+        (Some(file), None) => {
+            let path = dir.join(file);
+            std::fs::read(&path).map_err(|e| {
+                Error::InvalidConfig(format!("failed to read keyfile {:?}: {}", path, e))
+            })?
+        }
+        (None, Some(b64)) => base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .map_err(|e| {
+                Error::InvalidConfig(format!(
+                    "namespace {:?} has an invalid base64 encryption key: {}",
+                    ns_id, e
+                ))
+            })?,
+        (None, None) => {
+            return Err(Error::InvalidConfig(format!(
+                "namespace {:?} must specify encryption_key_file or encryption_key_base64",
+                ns_id
+            )))
+        }
+    };
+
+    raw.try_into().map_err(|v: Vec<u8>| {
+        Error::InvalidConfig(format!(
+            "namespace {:?} encryption key must be {} bytes, got {}",
+            ns_id,
+            KEY_SIZE,
+            v.len()
+        ))
+    })
+}
+
+fn resolve_subject(
+    subject: &SubjectEntry,
+    machine_ids: &HashSet<Uuid>,
+    known_roles: &HashSet<&String>,
+    ns_id: &str,
+) -> Result<AccessSubject> {
+    match subject {
+        SubjectEntry::Keyword(k) if k == "any_authenticated" => Ok(AccessSubject::AnyAuthenticated),
+        SubjectEntry::Keyword(k) if k == "public" => Ok(AccessSubject::Public),
+        SubjectEntry::Keyword(other) => Err(Error::InvalidConfig(format!(
+            "namespace {:?} ACL has unknown subject keyword {:?}",
+            ns_id, other
+        ))),
+        SubjectEntry::Machine { machine } => {
+            let id = Uuid::parse_str(machine).map_err(|e| {
+                Error::InvalidConfig(format!("invalid machine id {:?}: {}", machine, e))
+            })?;
+            if !machine_ids.contains(&id) {
+                return Err(Error::InvalidConfig(format!(
+                    "namespace {:?} ACL references unknown machine {:?}",
+                    ns_id, machine
+                )));
+            }
+            Ok(AccessSubject::Machine(id))
+        }
+        SubjectEntry::Role { role } => {
+            if !known_roles.contains(role) {
+                return Err(Error::InvalidConfig(format!(
+                    "namespace {:?} ACL references unknown role {:?}",
+                    ns_id, role
+                )));
+            }
+            Ok(AccessSubject::Role(role.clone()))
+        }
+        // Group membership resolution doesn't exist yet (see MachineGroup's
+        // TODO in `check_permission`), so group names aren't validated
+        // against a registry - there isn't one to validate against.
+        SubjectEntry::Group { group } => Ok(AccessSubject::MachineGroup(group.clone())),
+    }
+}
+
+/// Parse `machines.toml`, `roles.toml`, and `namespaces.toml` out of `dir`
+/// (files may be absent, treated as empty) and assemble a
+/// [`NamespaceManager`] and [`RoleRegistry`] from them.
+///
+/// Validates referential integrity before building anything: every ACL
+/// subject must resolve to a declared machine or role, every role's
+/// `parents` must themselves be declared roles, and the namespace named
+/// by `default` in `namespaces.toml` must be present. The first problem
+/// found is returned as `Error::InvalidConfig`.
+pub fn load_config_dir(dir: &Path) -> Result<(NamespaceManager, RoleRegistry)> {
+    let machines: MachinesFile = read_toml(&dir.join("machines.toml"))?;
+    let roles_file: RolesFile = read_toml(&dir.join("roles.toml"))?;
+    let namespaces_file: NamespacesFile = read_toml(&dir.join("namespaces.toml"))?;
+
+    let mut machine_ids = HashSet::new();
+    for id_str in machines.machines.keys() {
+        let id = Uuid::parse_str(id_str)
+            .map_err(|e| Error::InvalidConfig(format!("invalid machine id {:?}: {}", id_str, e)))?;
+        machine_ids.insert(id);
+    }
+
+    let known_roles: HashSet<&String> = roles_file.roles.keys().collect();
+    for (name, def) in &roles_file.roles {
+        for parent in &def.parents {
+            if !roles_file.roles.contains_key(parent) {
+                return Err(Error::InvalidConfig(format!(
+                    "role {:?} has unknown parent {:?}",
+                    name, parent
+                )));
+            }
+        }
+    }
+
+    if !namespaces_file.namespaces.contains_key(&namespaces_file.default) {
+        return Err(Error::InvalidConfig(format!(
+            "default namespace {:?} is not defined in namespaces.toml",
+            namespaces_file.default
+        )));
+    }
+
+    for (ns_id, ns_entry) in &namespaces_file.namespaces {
+        for rule in &ns_entry.acl {
+            resolve_subject(&rule.subject, &machine_ids, &known_roles, ns_id)?;
+        }
+    }
+
+    let mut roles = RoleRegistry::new();
+    for (name, def) in &roles_file.roles {
+        roles.define_role(
+            name.clone(),
+            RoleDef {
+                parents: def.parents.clone(),
+                grants: def
+                    .grants
+                    .iter()
+                    .map(|g| (g.path_pattern.clone(), g.permissions.clone().into()))
+                    .collect(),
+            },
+        );
+    }
+
+    let mut manager = NamespaceManager::new(namespaces_file.default.clone());
+    for (ns_id, ns_entry) in namespaces_file.namespaces {
+        let key = decode_encryption_key(dir, &ns_id, &ns_entry)?;
+
+        let namespace_type = match ns_entry.namespace_type {
+            NamespaceTypeEntry::Standalone => NamespaceType::Standalone,
+            NamespaceTypeEntry::MasterReplica {
+                master_id,
+                replicas,
+            } => NamespaceType::MasterReplica {
+                master_id,
+                replicas,
+            },
+            NamespaceTypeEntry::Distributed {
+                cluster_id,
+                members,
+            } => {
+                let mut member_set = OrSet::new();
+                for member in members {
+                    member_set.add(member);
+                }
+                NamespaceType::Distributed {
+                    cluster_id,
+                    members: member_set,
+                }
+            }
+        };
+
+        let mut namespace = Namespace::new(ns_id.clone(), namespace_type, key);
+        namespace.description = ns_entry.description;
+
+        // Machines' own grants apply in every namespace - there's no
+        // per-namespace machine grant concept yet, so they're seeded here
+        // as ordinary `Machine`-subject rules.
+        for (id_str, machine) in &machines.machines {
+            let id = Uuid::parse_str(id_str).expect("validated above");
+            for grant in &machine.grants {
+                namespace.add_rule(AccessRule::new(
+                    AccessSubject::Machine(id),
+                    grant.permissions.clone().into(),
+                    grant.path_pattern.clone(),
+                ));
+            }
+        }
+
+        for rule in ns_entry.acl {
+            let subject = resolve_subject(&rule.subject, &machine_ids, &known_roles, &ns_id)?;
+            let access_rule = AccessRule {
+                subject,
+                permissions: rule.permissions.into(),
+                path_pattern: rule.path_pattern,
+                effect: rule.effect.into(),
+            };
+            namespace.add_rule(access_rule);
+        }
+
+        manager.add_namespace(namespace)?;
+    }
+
+    Ok((manager, roles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributed::namespace::PermissionType;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn write_file(dir: &Path, name: &str, content: &str) {
+        let mut f = std::fs::File::create(dir.join(name)).unwrap();
+        f.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_load_config_dir_builds_namespace_with_acl() {
+        let dir = tempdir().unwrap();
+        let machine_id = Uuid::new_v4();
+
+        write_file(
+            dir.path(),
+            "machines.toml",
+            &format!(
+                "[machines.\"{machine_id}\"]\nname = \"db-primary\"\n"
+            ),
+        );
+        write_file(dir.path(), "roles.toml", "");
+        write_file(
+            dir.path(),
+            "namespaces.toml",
+            &format!(
+                "default = \"main\"\n\n[namespaces.main]\nkind = \"standalone\"\nencryption_key_base64 = \"{}\"\n\n[[namespaces.main.acl]]\npath_pattern = \"/**\"\neffect = \"allow\"\n[namespaces.main.acl.subject]\nmachine = \"{machine_id}\"\n[namespaces.main.acl.permissions]\nread = true\nwrite = true\ndelete = false\nadmin = false\n",
+                base64::engine::general_purpose::STANDARD.encode([7u8; KEY_SIZE]),
+            ),
+        );
+
+        let (manager, _roles) = load_config_dir(dir.path()).unwrap();
+        let ns = manager.get_default_namespace().unwrap();
+        assert_eq!(ns.namespace_id, "main");
+        assert!(ns.check_permission(&machine_id, "/anything", PermissionType::Read, None, None));
+    }
+
+    #[test]
+    fn test_load_config_dir_rejects_unknown_acl_machine() {
+        let dir = tempdir().unwrap();
+
+        write_file(dir.path(), "machines.toml", "");
+        write_file(dir.path(), "roles.toml", "");
+        write_file(
+            dir.path(),
+            "namespaces.toml",
+            &format!(
+                "default = \"main\"\n\n[namespaces.main]\nkind = \"standalone\"\nencryption_key_base64 = \"{}\"\n\n[[namespaces.main.acl]]\npath_pattern = \"/**\"\n[namespaces.main.acl.subject]\nmachine = \"{}\"\n[namespaces.main.acl.permissions]\nread = true\nwrite = false\ndelete = false\nadmin = false\n",
+                base64::engine::general_purpose::STANDARD.encode([7u8; KEY_SIZE]),
+                Uuid::new_v4(),
+            ),
+        );
+
+        assert!(matches!(
+            load_config_dir(dir.path()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_config_dir_rejects_missing_default_namespace() {
+        let dir = tempdir().unwrap();
+
+        write_file(dir.path(), "machines.toml", "");
+        write_file(dir.path(), "roles.toml", "");
+        write_file(dir.path(), "namespaces.toml", "default = \"main\"\n");
+
+        assert!(matches!(
+            load_config_dir(dir.path()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
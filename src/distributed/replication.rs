@@ -6,7 +6,8 @@
 //! - The master periodically creates snapshots and uploads to Telegram
 //! - Replicas periodically download and apply the latest snapshot
 
-use crate::crypto::{decrypt, encrypt, EncryptedData, KEY_SIZE};
+use crate::crypto::{Envelope, KeyId, KeyStore, MasterKey};
+use crate::distributed::filter_cascade::FilterCascade;
 use crate::error::{Error, Result};
 use crate::metadata::{Inode, MetadataStore};
 use crate::telegram::TelegramBackend;
@@ -18,6 +19,33 @@ use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+/// Key prefix under which every [`SnapshotMetadata`] is registered in the
+/// [`MetadataStore`], so [`SnapshotManager::list_snapshot_metadata`] can
+/// scan for all of them without knowing any snapshot id ahead of time.
+const SNAPSHOT_META_PREFIX: &str = "snapshot_meta:";
+
+/// The metadata-store key a given snapshot id is registered under.
+fn snapshot_metadata_key(snapshot_id: &str) -> String {
+    format!("{SNAPSHOT_META_PREFIX}{snapshot_id}")
+}
+
+/// Every `FULL_SNAPSHOT_INTERVAL`th snapshot is emitted in full rather
+/// than as a delta, so a replica never has to walk back through an
+/// unbounded chain to reconstruct the current state.
+const FULL_SNAPSHOT_INTERVAL: u64 = 10;
+
+/// Tracks, per namespace, the inode versions the most recently uploaded
+/// snapshot (full or delta) reflects, so the next [`SnapshotManager::create_snapshot`]
+/// call can diff the current tree against it without downloading
+/// anything back from Telegram.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct SnapshotBaseline {
+    /// The version this baseline reflects.
+    version: u64,
+    /// ino -> `Inode::version` as of `version`.
+    inode_versions: HashMap<u64, u64>,
+}
+
 /// Replication role for a machine
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReplicationRole {
@@ -41,8 +69,12 @@ impl ReplicationRole {
 
 /// Metadata snapshot for replication
 ///
-/// This is a serializable snapshot of all inodes in the filesystem.
-/// Chunks are content-addressed and immutable, so only metadata needs to be replicated.
+/// A snapshot is either full (`base_version: None`, `inodes` holds every
+/// inode in the filesystem) or a delta against an earlier full or delta
+/// snapshot (`base_version: Some(v)`, `inodes` holds only inodes added or
+/// changed since version `v`, and `removed_inodes` lists everything
+/// deleted since then). Chunks are content-addressed and immutable, so
+/// only metadata needs to be replicated either way.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetadataSnapshot {
     /// Unique snapshot ID
@@ -60,24 +92,43 @@ pub struct MetadataSnapshot {
     /// Snapshot version number (monotonically increasing)
     pub version: u64,
 
-    /// All inodes in the filesystem (ino -> inode)
+    /// `None` for a full snapshot. `Some(v)` if this snapshot only carries
+    /// a delta against the full filesystem state as of version `v`; a
+    /// replica needs every snapshot back to the nearest full one to
+    /// reconstruct the state this version describes.
+    pub base_version: Option<u64>,
+
+    /// Inodes added or changed since `base_version` (every inode, for a
+    /// full snapshot).
     pub inodes: HashMap<u64, Inode>,
 
+    /// Inode numbers removed since `base_version`. Always empty for a
+    /// full snapshot.
+    pub removed_inodes: Vec<u64>,
+
     /// Next available inode number
     pub next_ino: u64,
 
     /// Optional description
     pub description: Option<String>,
+
+    /// Exact-membership filter cascade over the chunk hashes referenced by
+    /// `inodes` as of this snapshot's version, so a replica can identify
+    /// and delete chunks nothing references anymore without downloading a
+    /// raw hash list. See [`SnapshotManager::apply_snapshot`].
+    pub live_chunks: FilterCascade,
 }
 
 impl MetadataSnapshot {
-    /// Create a new metadata snapshot
+    /// Create a new full metadata snapshot: `inodes` must contain every
+    /// inode in the filesystem.
     pub fn new(
         master_id: Uuid,
         namespace_id: String,
         version: u64,
         inodes: HashMap<u64, Inode>,
         next_ino: u64,
+        live_chunks: FilterCascade,
     ) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
@@ -85,13 +136,51 @@ impl MetadataSnapshot {
             namespace_id,
             created_at: Utc::now(),
             version,
+            base_version: None,
             inodes,
+            removed_inodes: Vec::new(),
             next_ino,
             description: None,
+            live_chunks,
         }
     }
 
-    /// Get the size of this snapshot in inodes
+    /// Create a delta snapshot against `base_version`: `inodes` holds
+    /// only what changed since that version, `removed_inodes` what
+    /// disappeared.
+    pub fn new_delta(
+        master_id: Uuid,
+        namespace_id: String,
+        version: u64,
+        base_version: u64,
+        inodes: HashMap<u64, Inode>,
+        removed_inodes: Vec<u64>,
+        next_ino: u64,
+        live_chunks: FilterCascade,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            master_id,
+            namespace_id,
+            created_at: Utc::now(),
+            version,
+            base_version: Some(base_version),
+            inodes,
+            removed_inodes,
+            next_ino,
+            description: None,
+            live_chunks,
+        }
+    }
+
+    /// Whether this snapshot is a delta that needs a base chain to apply,
+    /// rather than a standalone full snapshot.
+    pub fn is_delta(&self) -> bool {
+        self.base_version.is_some()
+    }
+
+    /// Get the size of this snapshot in inodes (added/changed inodes only,
+    /// for a delta snapshot)
     pub fn inode_count(&self) -> usize {
         self.inodes.len()
     }
@@ -119,6 +208,11 @@ pub struct SnapshotMetadata {
     /// Snapshot ID
     pub snapshot_id: String,
 
+    /// Machine ID of the master that created the snapshot. Carried here (as
+    /// well as on the snapshot itself) so it's available to authenticate the
+    /// encrypted blob before that blob has been decrypted.
+    pub master_id: Uuid,
+
     /// Version number
     pub version: u64,
 
@@ -135,10 +229,24 @@ pub struct SnapshotMetadata {
     pub inode_count: usize,
 }
 
+/// Build the AAD a snapshot's encrypted blob is bound to: namespace,
+/// snapshot ID, creating master, and version. Authenticating these fields
+/// means a snapshot from the wrong namespace, the wrong master, or an older
+/// version can never be substituted in and decrypt cleanly - it fails to
+/// decrypt instead of silently applying the wrong state.
+fn snapshot_aad(namespace_id: &str, snapshot_id: &str, master_id: Uuid, version: u64) -> Vec<u8> {
+    format!("{namespace_id}:{snapshot_id}:{master_id}:{version}").into_bytes()
+}
+
 /// Manages snapshot creation, upload, download, and application
 pub struct SnapshotManager {
-    /// Encryption key for snapshots
-    key: [u8; KEY_SIZE],
+    /// Envelope-encrypts snapshots under a per-namespace DEK wrapped by
+    /// `master`; see [`Self::rotate_key`] for online key rotation.
+    keystore: KeyStore,
+
+    /// Master key `keystore`'s DEKs are wrapped under, needed to unwrap
+    /// existing DEKs on load and to wrap freshly rotated ones.
+    master: Arc<MasterKey>,
 
     /// Telegram backend for upload/download
     telegram: Arc<TelegramBackend>,
@@ -155,41 +263,120 @@ pub struct SnapshotManager {
     /// Current version number
     current_version: Arc<RwLock<u64>>,
 
-    /// Maximum snapshots to retain (TODO: implement retention policy)
-    #[allow(dead_code)]
+    /// Maximum snapshots to retain; [`Self::cleanup_old_snapshots`] deletes
+    /// everything older than the most recent `max_snapshots`.
     max_snapshots: usize,
 }
 
 impl SnapshotManager {
-    /// Create a new snapshot manager
+    /// Create a new snapshot manager, loading this namespace's key store
+    /// from `metadata_store` if one was persisted by an earlier run, or
+    /// minting a fresh one (and persisting it) otherwise.
     pub fn new(
-        key: [u8; KEY_SIZE],
+        master: Arc<MasterKey>,
         telegram: Arc<TelegramBackend>,
         metadata_store: Arc<MetadataStore>,
         machine_id: Uuid,
         namespace_id: String,
         max_snapshots: usize,
-    ) -> Self {
-        Self {
-            key,
+    ) -> Result<Self> {
+        let keystore = Self::load_or_create_keystore(&metadata_store, &namespace_id, &master)?;
+
+        Ok(Self {
+            keystore,
+            master,
             telegram,
             metadata_store,
             machine_id,
             namespace_id,
             current_version: Arc::new(RwLock::new(0)),
             max_snapshots,
+        })
+    }
+
+    /// The metadata-store key this namespace's wrapped [`KeyStore`] is
+    /// persisted under.
+    fn keystore_key(namespace_id: &str) -> String {
+        format!("snapshot_keystore:{namespace_id}")
+    }
+
+    /// Load a namespace's persisted key store, or mint and persist a new
+    /// one if none exists yet.
+    fn load_or_create_keystore(
+        metadata_store: &MetadataStore,
+        namespace_id: &str,
+        master: &MasterKey,
+    ) -> Result<KeyStore> {
+        match metadata_store.get_metadata(&Self::keystore_key(namespace_id))? {
+            Some(bytes) => {
+                let wrapped = bincode::deserialize(&bytes).map_err(|e| Error::Deserialization(e.to_string()))?;
+                KeyStore::import(wrapped, master)
+            }
+            None => {
+                let keystore = KeyStore::new(master)?;
+                let bytes = bincode::serialize(&keystore.export())?;
+                metadata_store.save_metadata(&Self::keystore_key(namespace_id), &bytes)?;
+                Ok(keystore)
+            }
         }
     }
 
-    /// Create a snapshot of the current metadata state
-    pub async fn create_snapshot(&self) -> Result<MetadataSnapshot> {
-        info!("Creating metadata snapshot for namespace {}", self.namespace_id);
+    /// Generate a new DEK and make it active for future snapshot uploads,
+    /// without touching snapshots already uploaded under an older DEK -
+    /// they keep decrypting via the `key_id` they were tagged with. This
+    /// is the whole point of envelope encryption: recovering from a
+    /// suspected DEK compromise doesn't require re-encrypting history.
+    pub fn rotate_key(&mut self) -> Result<KeyId> {
+        let key_id = self.keystore.rotate(&self.master)?;
+        let bytes = bincode::serialize(&self.keystore.export())?;
+        self.metadata_store.save_metadata(&Self::keystore_key(&self.namespace_id), &bytes)?;
+        Ok(key_id)
+    }
+
+    /// The metadata-store key this namespace's [`SnapshotBaseline`] is
+    /// kept under.
+    fn baseline_key(&self) -> String {
+        format!("snapshot_baseline:{}", self.namespace_id)
+    }
 
-        // Collect all inodes from the metadata store
+    /// Load the baseline the last uploaded snapshot left behind, if any.
+    fn load_baseline(&self) -> Result<Option<SnapshotBaseline>> {
+        match self.metadata_store.get_metadata(&self.baseline_key())? {
+            Some(bytes) => {
+                Ok(Some(bincode::deserialize(&bytes).map_err(|e| Error::Deserialization(e.to_string()))?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record the baseline `snapshot` leaves behind, so the next
+    /// [`Self::create_snapshot`] call can diff against it. For a delta
+    /// snapshot this folds its changes into the previous baseline rather
+    /// than replacing it wholesale.
+    fn save_baseline_after_upload(&self, snapshot: &MetadataSnapshot) -> Result<()> {
+        let mut inode_versions = match snapshot.base_version {
+            Some(_) => self.load_baseline()?.map(|b| b.inode_versions).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+
+        for (ino, inode) in &snapshot.inodes {
+            inode_versions.insert(*ino, inode.version);
+        }
+        for ino in &snapshot.removed_inodes {
+            inode_versions.remove(ino);
+        }
+
+        let baseline = SnapshotBaseline { version: snapshot.version, inode_versions };
+        let bytes = bincode::serialize(&baseline)?;
+        self.metadata_store.save_metadata(&self.baseline_key(), &bytes)
+    }
+
+    /// Walk every inode reachable from the root, as they currently stand
+    /// in the metadata store.
+    fn walk_current_inodes(&self) -> Result<(HashMap<u64, Inode>, u64)> {
         let mut inodes = HashMap::new();
         let mut max_ino = 1u64;
 
-        // Walk all inodes starting from root
         let mut to_visit = vec![1u64]; // Start with root
         let mut visited = std::collections::HashSet::new();
 
@@ -202,7 +389,6 @@ impl SnapshotManager {
             if let Some(inode) = self.metadata_store.get_inode(ino)? {
                 max_ino = max_ino.max(ino);
 
-                // If it's a directory, add children to visit list
                 if inode.is_dir() {
                     for child_ino in &inode.children {
                         to_visit.push(*child_ino);
@@ -213,7 +399,44 @@ impl SnapshotManager {
             }
         }
 
-        let next_ino = max_ino + 1;
+        Ok((inodes, max_ino + 1))
+    }
+
+    /// Build an exact-membership cascade over `inodes`' referenced chunk
+    /// hashes, against every other chunk id this node's metadata store
+    /// still knows about - the known-non-referenced set a replica needs to
+    /// tell "definitely live" from "safe to garbage-collect".
+    fn build_live_chunks_cascade(&self, inodes: &HashMap<u64, Inode>) -> Result<FilterCascade> {
+        let referenced: std::collections::HashSet<String> = inodes
+            .values()
+            .filter_map(|inode| inode.manifest.as_ref())
+            .flat_map(|manifest| manifest.chunks.iter())
+            .map(|chunk_ref| chunk_ref.id.to_string())
+            .collect();
+
+        let non_referenced: Vec<String> = self
+            .metadata_store
+            .list_chunk_ids()?
+            .into_iter()
+            .filter(|id| !referenced.contains(id))
+            .collect();
+
+        Ok(FilterCascade::build_default(
+            referenced.iter().map(String::as_str),
+            non_referenced.iter().map(String::as_str),
+        ))
+    }
+
+    /// Create a snapshot of the current metadata state: a delta against
+    /// the last uploaded version when one is available and due, or a
+    /// full snapshot otherwise (including periodically, every
+    /// [`FULL_SNAPSHOT_INTERVAL`] versions, to bound how far back a
+    /// replica ever has to walk to reconstruct state).
+    pub async fn create_snapshot(&self) -> Result<MetadataSnapshot> {
+        info!("Creating metadata snapshot for namespace {}", self.namespace_id);
+
+        let (inodes, next_ino) = self.walk_current_inodes()?;
+        let live_chunks = self.build_live_chunks_cascade(&inodes)?;
 
         // Increment version
         let mut version = self.current_version.write().await;
@@ -221,18 +444,54 @@ impl SnapshotManager {
         let snapshot_version = *version;
         drop(version);
 
-        let snapshot = MetadataSnapshot::new(
-            self.machine_id,
-            self.namespace_id.clone(),
-            snapshot_version,
-            inodes,
-            next_ino,
-        );
+        let baseline = self.load_baseline()?;
+        let due_for_full = snapshot_version % FULL_SNAPSHOT_INTERVAL == 0;
+
+        let snapshot = match baseline {
+            Some(baseline) if !due_for_full => {
+                let mut changed = HashMap::new();
+                for (ino, inode) in &inodes {
+                    match baseline.inode_versions.get(ino) {
+                        Some(&v) if v == inode.version => {}
+                        _ => {
+                            changed.insert(*ino, inode.clone());
+                        }
+                    }
+                }
+                let removed: Vec<u64> = baseline
+                    .inode_versions
+                    .keys()
+                    .filter(|ino| !inodes.contains_key(ino))
+                    .copied()
+                    .collect();
+
+                MetadataSnapshot::new_delta(
+                    self.machine_id,
+                    self.namespace_id.clone(),
+                    snapshot_version,
+                    baseline.version,
+                    changed,
+                    removed,
+                    next_ino,
+                    live_chunks,
+                )
+            }
+            _ => MetadataSnapshot::new(
+                self.machine_id,
+                self.namespace_id.clone(),
+                snapshot_version,
+                inodes,
+                next_ino,
+                live_chunks,
+            ),
+        };
 
         info!(
-            "Created snapshot {} with {} inodes (version {})",
+            "Created {} snapshot {} with {} inode(s), {} removed (version {})",
+            if snapshot.is_delta() { "delta" } else { "full" },
             snapshot.id,
             snapshot.inode_count(),
+            snapshot.removed_inodes.len(),
             snapshot_version
         );
 
@@ -247,10 +506,13 @@ impl SnapshotManager {
         let data = snapshot.serialize()?;
         debug!("Snapshot serialized to {} bytes", data.len());
 
-        // Encrypt the data
-        let encrypted = encrypt(&self.key, &data, &[])?;
-        let encrypted_bytes = encrypted.to_bytes();
-        debug!("Snapshot encrypted to {} bytes", encrypted_bytes.len());
+        // Encrypt the data under the currently active DEK, tagged with its
+        // key_id, and bound via AAD to the namespace/snapshot/master/version
+        // it belongs to so it can't be substituted for a different one.
+        let aad = snapshot_aad(&self.namespace_id, &snapshot.id, snapshot.master_id, snapshot.version);
+        let envelope = self.keystore.encrypt(&data, &aad)?;
+        let encrypted_bytes = bincode::serialize(&envelope)?;
+        debug!("Snapshot encrypted to {} bytes under key {}", encrypted_bytes.len(), envelope.key_id);
 
         // Upload to Telegram with special metadata prefix
         let snapshot_filename = format!("tgfs_snapshot_{}_{}", self.namespace_id, snapshot.id);
@@ -259,6 +521,7 @@ impl SnapshotManager {
         // Store snapshot metadata locally
         let metadata = SnapshotMetadata {
             snapshot_id: snapshot.id.clone(),
+            master_id: snapshot.master_id,
             version: snapshot.version,
             created_at: snapshot.created_at,
             message_id,
@@ -266,9 +529,10 @@ impl SnapshotManager {
             inode_count: snapshot.inode_count(),
         };
 
-        let metadata_key = format!("snapshot_meta:{}", snapshot.id);
+        let metadata_key = snapshot_metadata_key(&snapshot.id);
         let metadata_bytes = bincode::serialize(&metadata)?;
         self.metadata_store.save_metadata(&metadata_key, &metadata_bytes)?;
+        self.save_baseline_after_upload(snapshot)?;
 
         info!(
             "Snapshot {} uploaded as message {} ({} bytes)",
@@ -281,27 +545,21 @@ impl SnapshotManager {
         Ok(message_id)
     }
 
-    /// Download the latest snapshot from Telegram
-    pub async fn download_latest_snapshot(&self) -> Result<MetadataSnapshot> {
-        info!("Downloading latest snapshot for namespace {}", self.namespace_id);
-
-        // Find the latest snapshot metadata
-        let latest_metadata = self.get_latest_snapshot_metadata()?;
-
-        // Download from Telegram
-        let encrypted_bytes = self.telegram.download_chunk(latest_metadata.message_id).await?;
+    /// Download and decrypt the snapshot a [`SnapshotMetadata`] entry
+    /// points at.
+    async fn download_snapshot(&self, meta: &SnapshotMetadata) -> Result<MetadataSnapshot> {
+        let encrypted_bytes = self.telegram.download_chunk(meta.message_id).await?;
         debug!("Downloaded {} bytes from Telegram", encrypted_bytes.len());
 
-        // Decrypt
-        let encrypted = EncryptedData::from_bytes(&encrypted_bytes)?;
-        let decrypted = decrypt(&self.key, &encrypted, &[])?;
-        debug!("Decrypted to {} bytes", decrypted.len());
+        let envelope: Envelope = bincode::deserialize(&encrypted_bytes).map_err(|e| Error::Deserialization(e.to_string()))?;
+        let aad = snapshot_aad(&self.namespace_id, &meta.snapshot_id, meta.master_id, meta.version);
+        let decrypted = self.keystore.decrypt(&envelope, &aad)?;
+        debug!("Decrypted to {} bytes using key {}", decrypted.len(), envelope.key_id);
 
-        // Deserialize
         let snapshot = MetadataSnapshot::deserialize(&decrypted)?;
 
         info!(
-            "Downloaded snapshot {} with {} inodes (version {})",
+            "Downloaded snapshot {} with {} inode(s) (version {})",
             snapshot.id,
             snapshot.inode_count(),
             snapshot.version
@@ -310,34 +568,107 @@ impl SnapshotManager {
         Ok(snapshot)
     }
 
-    /// Apply a snapshot to the local metadata store (overwrite local state)
+    /// Download the latest snapshot from Telegram. If it's a delta, this
+    /// returns only that delta - use [`Self::download_snapshot_chain`] to
+    /// get everything needed to actually apply it.
+    pub async fn download_latest_snapshot(&self) -> Result<MetadataSnapshot> {
+        info!("Downloading latest snapshot for namespace {}", self.namespace_id);
+        let latest_metadata = self.get_latest_snapshot_metadata()?;
+        self.download_snapshot(&latest_metadata).await
+    }
+
+    /// Download the latest snapshot along with every delta back to (and
+    /// including) the nearest full snapshot, oldest first - exactly the
+    /// sequence [`Self::apply_snapshot`] needs to reconstruct current
+    /// state without clearing and re-downloading everything on every
+    /// sync.
+    pub async fn download_snapshot_chain(&self) -> Result<Vec<MetadataSnapshot>> {
+        let mut chain = vec![self.download_latest_snapshot().await?];
+
+        while let Some(base_version) = chain.last().and_then(|s| s.base_version) {
+            let base_metadata = self
+                .list_snapshot_metadata()?
+                .into_iter()
+                .find(|meta| meta.version == base_version)
+                .ok_or_else(|| {
+                    Error::SnapshotNotFound(format!(
+                        "base snapshot version {base_version} referenced by the chain is missing"
+                    ))
+                })?;
+            chain.push(self.download_snapshot(&base_metadata).await?);
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Download the latest snapshot chain and apply it in order, so a
+    /// replica catches up via whatever deltas are available instead of
+    /// re-downloading the whole tree on every sync.
+    pub async fn sync_from_latest_snapshot(&self) -> Result<()> {
+        for snapshot in self.download_snapshot_chain().await? {
+            self.apply_snapshot(&snapshot).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply a snapshot to the local metadata store. A full snapshot
+    /// (`base_version: None`) overwrites local state entirely; a delta
+    /// only touches the inodes it lists as added/changed/removed, so
+    /// callers must apply every snapshot in a chain (see
+    /// [`Self::download_snapshot_chain`]) in order, starting from the
+    /// nearest full snapshot.
     pub async fn apply_snapshot(&self, snapshot: &MetadataSnapshot) -> Result<()> {
         info!(
-            "Applying snapshot {} ({} inodes) to local metadata store",
+            "Applying {} snapshot {} ({} inode(s), {} removed) to local metadata store",
+            if snapshot.is_delta() { "delta" } else { "full" },
             snapshot.id,
-            snapshot.inode_count()
-        );
-
-        // This is a destructive operation - we're replacing all local metadata
-        warn!(
-            "Overwriting local metadata with snapshot version {}",
-            snapshot.version
+            snapshot.inode_count(),
+            snapshot.removed_inodes.len(),
         );
 
-        // Clear the cache first
-        self.metadata_store.clear_cache();
+        if snapshot.is_delta() {
+            debug!(
+                "Applying delta on top of base version {}",
+                snapshot.base_version.expect("is_delta implies base_version is Some")
+            );
+        } else {
+            // This clears everything local state previously held - safe
+            // only because a full snapshot's `inodes` map is a complete
+            // replacement, not a diff.
+            warn!(
+                "Overwriting local metadata with full snapshot version {}",
+                snapshot.version
+            );
+            self.metadata_store.clear_cache();
+        }
 
-        // Save all inodes from the snapshot
+        // Save all inodes the snapshot carries
         for (ino, inode) in &snapshot.inodes {
             self.metadata_store.save_inode(inode)?;
             debug!("Applied inode {} ({})", ino, inode.name);
         }
 
+        // Remove whatever the snapshot says disappeared
+        for ino in &snapshot.removed_inodes {
+            self.metadata_store.delete_inode(*ino)?;
+            debug!("Removed inode {} per snapshot", ino);
+        }
+
         // Update version
         let mut version = self.current_version.write().await;
         *version = snapshot.version;
         drop(version);
 
+        // A full snapshot's `live_chunks` covers every chunk the
+        // filesystem references as of this version, so it's safe to
+        // collect anything else this node still holds a reference to. A
+        // delta's cascade only covers what changed, and would wrongly
+        // flag untouched-but-still-live chunks, so GC only runs here.
+        if !snapshot.is_delta() {
+            self.collect_unreferenced_chunks(&snapshot.live_chunks).await?;
+        }
+
         // Flush to disk
         self.metadata_store.flush()?;
 
@@ -349,25 +680,68 @@ impl SnapshotManager {
         Ok(())
     }
 
+    /// Delete every locally-held chunk `live_chunks` says is no longer
+    /// referenced: removes its Telegram message and its local chunk
+    /// reference, so orphaned chunks don't accumulate after sync.
+    async fn collect_unreferenced_chunks(&self, live_chunks: &FilterCascade) -> Result<()> {
+        for chunk_id in self.metadata_store.list_chunk_ids()? {
+            if live_chunks.contains(&chunk_id) {
+                continue;
+            }
+
+            if let Some(message_id) = self.metadata_store.get_chunk_ref(&chunk_id)? {
+                self.telegram.delete_message(message_id).await?;
+            }
+            self.metadata_store.remove_chunk_ref(&chunk_id)?;
+            debug!("Garbage-collected unreferenced chunk {}", chunk_id);
+        }
+        Ok(())
+    }
+
+    /// Every registered snapshot's metadata, in no particular order.
+    fn list_snapshot_metadata(&self) -> Result<Vec<SnapshotMetadata>> {
+        self.metadata_store
+            .scan_metadata_prefix(SNAPSHOT_META_PREFIX)?
+            .into_iter()
+            .map(|(_, bytes)| {
+                bincode::deserialize(&bytes).map_err(|e| Error::Deserialization(e.to_string()))
+            })
+            .collect()
+    }
+
     /// Get metadata for the latest snapshot
     fn get_latest_snapshot_metadata(&self) -> Result<SnapshotMetadata> {
-        // In a real implementation, this would scan the metadata store
-        // for all snapshot metadata entries and return the one with the highest version
-
-        // For now, return an error indicating no snapshot found
-        // This will be implemented when we have a proper index
-        Err(Error::SnapshotNotFound(
-            "No snapshots found - snapshot indexing not yet implemented".to_string(),
-        ))
+        self.list_snapshot_metadata()?
+            .into_iter()
+            .max_by_key(|meta| meta.version)
+            .ok_or_else(|| Error::SnapshotNotFound("No snapshots found".to_string()))
     }
 
-    /// Clean up old snapshots, keeping only the most recent N
+    /// Clean up old snapshots, keeping only the most recent `max_snapshots`
     async fn cleanup_old_snapshots(&self) -> Result<()> {
-        // TODO: Implement snapshot cleanup
-        // 1. List all snapshot metadata
-        // 2. Sort by version
-        // 3. Keep the latest N, delete the rest
-        debug!("Snapshot cleanup not yet implemented");
+        let mut snapshots = self.list_snapshot_metadata()?;
+        if snapshots.len() <= self.max_snapshots {
+            return Ok(());
+        }
+
+        snapshots.sort_by_key(|meta| meta.version);
+        let to_remove = snapshots.len() - self.max_snapshots;
+
+        for meta in &snapshots[..to_remove] {
+            debug!(
+                "Deleting old snapshot {} (version {})",
+                meta.snapshot_id, meta.version
+            );
+            self.telegram.delete_message(meta.message_id).await?;
+            self.metadata_store
+                .delete_metadata(&snapshot_metadata_key(&meta.snapshot_id))?;
+        }
+
+        info!(
+            "Cleaned up {} old snapshot(s), keeping the latest {}",
+            to_remove, self.max_snapshots
+        );
+
         Ok(())
     }
 
@@ -459,11 +833,39 @@ mod tests {
         let root = Inode::root(1000, 1000, 0o755);
         inodes.insert(1, root);
 
-        let snapshot = MetadataSnapshot::new(master_id, namespace_id, 1, inodes, 2);
+        let snapshot = MetadataSnapshot::new(master_id, namespace_id, 1, inodes, 2, FilterCascade::default());
 
         assert_eq!(snapshot.version, 1);
         assert_eq!(snapshot.inode_count(), 1);
         assert_eq!(snapshot.next_ino, 2);
+        assert!(!snapshot.is_delta());
+        assert!(snapshot.removed_inodes.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_snapshot_delta_creation() {
+        let master_id = Uuid::new_v4();
+        let namespace_id = "test".to_string();
+
+        let changed = Inode::root(1000, 1000, 0o755);
+        let mut inodes = HashMap::new();
+        inodes.insert(1, changed);
+
+        let snapshot = MetadataSnapshot::new_delta(
+            master_id,
+            namespace_id,
+            2,
+            1,
+            inodes,
+            vec![42],
+            3,
+            FilterCascade::default(),
+        );
+
+        assert!(snapshot.is_delta());
+        assert_eq!(snapshot.base_version, Some(1));
+        assert_eq!(snapshot.inode_count(), 1);
+        assert_eq!(snapshot.removed_inodes, vec![42]);
     }
 
     #[test]
@@ -475,7 +877,8 @@ mod tests {
         let root = Inode::root(1000, 1000, 0o755);
         inodes.insert(1, root);
 
-        let snapshot = MetadataSnapshot::new(master_id, namespace_id.clone(), 1, inodes, 2);
+        let snapshot =
+            MetadataSnapshot::new(master_id, namespace_id.clone(), 1, inodes, 2, FilterCascade::default());
 
         // Serialize and deserialize
         let serialized = snapshot.serialize().unwrap();
@@ -528,4 +931,110 @@ mod tests {
         assert!(message.contains("production"));
         assert!(message.contains("read-only"));
     }
+
+    fn test_master() -> MasterKey {
+        use crate::config::EncryptionConfig;
+        MasterKey::from_password(
+            b"password",
+            &EncryptionConfig { argon2_memory_kib: 1024, argon2_iterations: 1, argon2_parallelism: 1, salt: Vec::new(), algorithm: crate::crypto::Algorithm::default() },
+        )
+        .unwrap()
+    }
+
+    fn test_snapshot_manager(
+        master: Arc<MasterKey>,
+        metadata_store: Arc<MetadataStore>,
+        namespace_id: &str,
+    ) -> SnapshotManager {
+        let telegram = Arc::new(TelegramBackend::new(crate::config::TelegramConfig::default()));
+        SnapshotManager::new(master, telegram, metadata_store, Uuid::new_v4(), namespace_id.to_string(), 10).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_manager_persists_keystore_across_restarts() {
+        let master = Arc::new(test_master());
+        let metadata_store = Arc::new(MetadataStore::in_memory([0u8; crate::crypto::KEY_SIZE]).unwrap());
+
+        let manager = test_snapshot_manager(master.clone(), metadata_store.clone(), "ns1");
+        let key_id = manager.keystore.active_key_id().to_string();
+
+        // A fresh manager over the same metadata store should load the
+        // same key store rather than minting a new one.
+        let reloaded = test_snapshot_manager(master, metadata_store, "ns1");
+        assert_eq!(reloaded.keystore.active_key_id(), key_id);
+    }
+
+    #[test]
+    fn test_rotate_key_keeps_old_snapshots_decryptable() {
+        let master = Arc::new(test_master());
+        let metadata_store = Arc::new(MetadataStore::in_memory([0u8; crate::crypto::KEY_SIZE]).unwrap());
+
+        let mut manager = test_snapshot_manager(master, metadata_store, "ns1");
+
+        let old_envelope = manager.keystore.encrypt(b"old snapshot bytes", &[]).unwrap();
+        let old_key_id = old_envelope.key_id.clone();
+
+        let new_key_id = manager.rotate_key().unwrap();
+        assert_ne!(new_key_id, old_key_id);
+        assert_eq!(manager.keystore.active_key_id(), new_key_id);
+
+        // The snapshot encrypted under the retired key still decrypts.
+        let plaintext = manager.keystore.decrypt(&old_envelope, &[]).unwrap();
+        assert_eq!(plaintext, b"old snapshot bytes");
+    }
+
+    #[test]
+    fn test_snapshot_aad_rejects_mismatched_context() {
+        let master = Arc::new(test_master());
+        let metadata_store = Arc::new(MetadataStore::in_memory([0u8; crate::crypto::KEY_SIZE]).unwrap());
+        let manager = test_snapshot_manager(master, metadata_store, "ns1");
+
+        let master_id = Uuid::new_v4();
+        let aad = snapshot_aad("ns1", "snap-1", master_id, 3);
+        let envelope = manager.keystore.encrypt(b"snapshot bytes", &aad).unwrap();
+
+        // Correct context decrypts.
+        assert_eq!(manager.keystore.decrypt(&envelope, &aad).unwrap(), b"snapshot bytes");
+
+        // Any field disagreeing with what was encrypted - namespace, snapshot
+        // id, master, or version - fails the decryption cleanly instead of
+        // letting a substituted snapshot through.
+        assert!(manager.keystore.decrypt(&envelope, &snapshot_aad("ns2", "snap-1", master_id, 3)).is_err());
+        assert!(manager.keystore.decrypt(&envelope, &snapshot_aad("ns1", "snap-2", master_id, 3)).is_err());
+        assert!(manager.keystore.decrypt(&envelope, &snapshot_aad("ns1", "snap-1", Uuid::new_v4(), 3)).is_err());
+        assert!(manager.keystore.decrypt(&envelope, &snapshot_aad("ns1", "snap-1", master_id, 4)).is_err());
+    }
+
+    #[test]
+    fn test_build_live_chunks_cascade_identifies_stale_chunks() {
+        use crate::chunk::{ChunkId, ChunkManifest, ChunkPayload, ChunkRef, CompressionAlgo};
+
+        let master = Arc::new(test_master());
+        let metadata_store = Arc::new(MetadataStore::in_memory([0u8; crate::crypto::KEY_SIZE]).unwrap());
+        let manager = test_snapshot_manager(master, metadata_store.clone(), "ns1");
+
+        // A chunk still referenced by an inode, and one the store still
+        // tracks a reference for but that nothing points at anymore.
+        metadata_store.save_chunk_ref("live-chunk", 1).unwrap();
+        metadata_store.save_chunk_ref("stale-chunk", 2).unwrap();
+
+        let mut file = Inode::new_file(2, 1, "f.txt".to_string(), 1000, 1000, 0o644);
+        let mut manifest = ChunkManifest::new(1);
+        manifest.chunks.push(ChunkRef {
+            id: ChunkId::from("live-chunk".to_string()),
+            size: 10,
+            payload: ChunkPayload::Remote { message_id: 1 },
+            offset: 0,
+            original_size: 10,
+            compression: CompressionAlgo::None,
+        });
+        file.manifest = Some(manifest);
+
+        let mut inodes = HashMap::new();
+        inodes.insert(2, file);
+
+        let cascade = manager.build_live_chunks_cascade(&inodes).unwrap();
+        assert!(cascade.contains("live-chunk"));
+        assert!(!cascade.contains("stale-chunk"));
+    }
 }
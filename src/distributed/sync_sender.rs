@@ -0,0 +1,324 @@
+//! Drains `CrdtSync::pending_operations()` to a remote encrypted store,
+//! turning the local operation queue into an actual replication channel
+//! rather than a journal nobody reads.
+//!
+//! Operations are shipped in batches bounded by a configurable in-flight
+//! window so a large backlog isn't handed to the transport all at once.
+//! An operation only leaves `pending_operations()` once the server's
+//! per-operation acknowledgement (carrying its assigned sequence number)
+//! comes back, via [`CrdtSync::mark_uploaded`] - which, if `sync` is
+//! backed by the write-ahead log from [`crate::distributed::crdt`],
+//! durably records the ack too. So a reconnect, or a full restart, simply
+//! resumes from whatever [`CrdtSync::pending_operations`] still reports:
+//! nothing un-acked is ever silently dropped, and nothing acked is ever
+//! resent.
+
+use crate::distributed::crdt::CrdtSync;
+use crate::distributed::CrdtOperation;
+use crate::error::{Error, Result};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Where a [`SyncSender`] ships batches of pending operations and learns
+/// which ones the remote store has durably accepted. A live
+/// implementation streams to the remote encrypted store over the
+/// network; tests substitute a fixture.
+pub trait SyncTransport {
+    /// Ship `batch`, returning the server-assigned sequence number for
+    /// every operation acknowledged before the call returns, in the same
+    /// order as `batch`. A connection drop mid-stream is not itself an
+    /// error: whatever prefix of `batch` got acked is returned and the
+    /// rest stays pending for the next call to retry. Only a transport
+    /// that couldn't send anything at all returns `Err`.
+    fn send_batch(&mut self, batch: &[CrdtOperation]) -> Result<Vec<u64>>;
+
+    /// The highest sequence number the server has told this sender it
+    /// durably holds, so [`SyncSender::resume`] can tell it where to
+    /// pick the stream back up after a restart. `None` if the server has
+    /// never acknowledged anything from this machine.
+    fn last_acked_sequence(&mut self) -> Result<Option<u64>>;
+}
+
+/// Delay before retrying a failed batch: starts at `base`, doubles on
+/// every consecutive failure up to `max`, and resets to `base` after a
+/// batch succeeds.
+struct Backoff {
+    base: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    fn new(base: Duration, max: Duration) -> Self {
+        Backoff { base, max, current: base }
+    }
+
+    fn reset(&mut self) {
+        self.current = self.base;
+    }
+
+    /// Sleep for the current delay, then double it (capped at `max`) for
+    /// next time.
+    fn wait(&mut self) {
+        std::thread::sleep(self.current);
+        self.current = (self.current * 2).min(self.max);
+    }
+}
+
+/// Drains a `CrdtSync`'s pending queue to a [`SyncTransport`] in bounded
+/// batches, retrying failures with exponential backoff.
+pub struct SyncSender {
+    max_in_flight: usize,
+    backoff: Backoff,
+    last_acked_sequence: Option<u64>,
+}
+
+impl SyncSender {
+    /// `max_in_flight` bounds how many pending operations are ever handed
+    /// to the transport in a single [`Self::drain_pending`] call.
+    pub fn new(max_in_flight: usize) -> Self {
+        SyncSender {
+            max_in_flight: max_in_flight.max(1),
+            backoff: Backoff::new(Duration::from_millis(100), Duration::from_secs(30)),
+            last_acked_sequence: None,
+        }
+    }
+
+    /// The last sequence number this sender has seen acknowledged, if
+    /// any.
+    pub fn last_acked_sequence(&self) -> Option<u64> {
+        self.last_acked_sequence
+    }
+
+    /// Ask `transport` where this machine's stream left off, so a sender
+    /// created after a restart doesn't have to wait for its first batch
+    /// to learn it.
+    pub fn resume(&mut self, transport: &mut impl SyncTransport) -> Result<()> {
+        self.last_acked_sequence = transport.last_acked_sequence()?;
+        Ok(())
+    }
+
+    /// Ship up to `max_in_flight` pending operations from `sync` to
+    /// `transport`, marking every operation the server acknowledged as
+    /// uploaded. Returns the number of operations acknowledged (0 if
+    /// there was nothing pending). A transport failure leaves every
+    /// operation in `pending_operations()` for the next call to retry,
+    /// after sleeping for the current backoff delay.
+    pub fn drain_pending(
+        &mut self,
+        sync: &mut CrdtSync,
+        transport: &mut impl SyncTransport,
+    ) -> Result<usize> {
+        let batch: Vec<CrdtOperation> = sync
+            .pending_operations()
+            .iter()
+            .take(self.max_in_flight)
+            .cloned()
+            .collect();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let acked_seqs = match transport.send_batch(&batch) {
+            Ok(seqs) => seqs,
+            Err(e) => {
+                self.backoff.wait();
+                return Err(e);
+            }
+        };
+        self.backoff.reset();
+
+        if acked_seqs.len() > batch.len() {
+            return Err(Error::Internal(format!(
+                "transport acknowledged {} sequence numbers for a batch of {}",
+                acked_seqs.len(),
+                batch.len()
+            )));
+        }
+
+        let acked_op_ids: Vec<Uuid> =
+            batch.iter().take(acked_seqs.len()).map(|op| op.op_id()).collect();
+        sync.mark_uploaded(&acked_op_ids)?;
+
+        if let Some(&highest) = acked_seqs.iter().max() {
+            self.last_acked_sequence = Some(self.last_acked_sequence.map_or(highest, |prev| prev.max(highest)));
+        }
+
+        Ok(acked_op_ids.len())
+    }
+
+    /// Drain every pending operation, one bounded batch at a time, until
+    /// none are left or a batch comes back empty (the server is caught up
+    /// with everything it acknowledged so far; a genuine transport
+    /// failure is still returned as `Err`).
+    pub fn drain_all_pending(
+        &mut self,
+        sync: &mut CrdtSync,
+        transport: &mut impl SyncTransport,
+    ) -> Result<usize> {
+        let mut total = 0;
+        loop {
+            let sent = self.drain_pending(sync, transport)?;
+            if sent == 0 {
+                return Ok(total);
+            }
+            total += sent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributed::ConflictResolutionStrategy;
+    use crate::distributed::VectorClock;
+    use std::cell::RefCell;
+    use std::time::SystemTime;
+
+    fn sample_write(machine_id: Uuid) -> CrdtOperation {
+        CrdtOperation::Write {
+            op_id: Uuid::new_v4(),
+            machine_id,
+            vector_clock: VectorClock::new(),
+            timestamp: SystemTime::now(),
+            parents: vec![],
+            path: "/a.txt".to_string(),
+            offset: 0,
+            data_hash: "hash".to_string(),
+            length: 10,
+        }
+    }
+
+    /// Fixture transport: acks a fixed prefix length per call, or returns
+    /// a scripted error, so tests can drive partial acks / retries
+    /// deterministically instead of hitting real network flakiness.
+    struct FixtureTransport {
+        next_seq: u64,
+        acked_per_call: RefCell<Vec<usize>>,
+        fail_next: RefCell<bool>,
+    }
+
+    impl SyncTransport for FixtureTransport {
+        fn send_batch(&mut self, batch: &[CrdtOperation]) -> Result<Vec<u64>> {
+            if *self.fail_next.borrow() {
+                *self.fail_next.borrow_mut() = false;
+                return Err(Error::Internal("connection dropped".to_string()));
+            }
+
+            let ack_count = self
+                .acked_per_call
+                .borrow_mut()
+                .pop()
+                .unwrap_or(batch.len())
+                .min(batch.len());
+
+            let seqs = (0..ack_count).map(|_| {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                seq
+            }).collect();
+            Ok(seqs)
+        }
+
+        fn last_acked_sequence(&mut self) -> Result<Option<u64>> {
+            Ok(if self.next_seq == 0 { None } else { Some(self.next_seq - 1) })
+        }
+    }
+
+    #[test]
+    fn test_drain_pending_acks_and_removes_operations_up_to_window() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+        for _ in 0..5 {
+            sync.record_operation(sample_write(machine_id)).unwrap();
+        }
+
+        let mut transport = FixtureTransport {
+            next_seq: 0,
+            acked_per_call: RefCell::new(vec![]),
+            fail_next: RefCell::new(false),
+        };
+        let mut sender = SyncSender::new(3);
+
+        let sent = sender.drain_pending(&mut sync, &mut transport).unwrap();
+        assert_eq!(sent, 3);
+        assert_eq!(sync.pending_operations().len(), 2);
+    }
+
+    #[test]
+    fn test_drain_pending_leaves_unacked_operations_for_retry() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+        for _ in 0..3 {
+            sync.record_operation(sample_write(machine_id)).unwrap();
+        }
+
+        // Only the first 2 of the batch get acked before the connection
+        // drops.
+        let mut transport = FixtureTransport {
+            next_seq: 0,
+            acked_per_call: RefCell::new(vec![2]),
+            fail_next: RefCell::new(false),
+        };
+        let mut sender = SyncSender::new(10);
+
+        let sent = sender.drain_pending(&mut sync, &mut transport).unwrap();
+        assert_eq!(sent, 2);
+        assert_eq!(sync.pending_operations().len(), 1);
+    }
+
+    #[test]
+    fn test_drain_pending_retries_after_transport_failure() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+        sync.record_operation(sample_write(machine_id)).unwrap();
+
+        let mut transport = FixtureTransport {
+            next_seq: 0,
+            acked_per_call: RefCell::new(vec![]),
+            fail_next: RefCell::new(true),
+        };
+        let mut sender = SyncSender::new(10);
+
+        assert!(sender.drain_pending(&mut sync, &mut transport).is_err());
+        assert_eq!(sync.pending_operations().len(), 1);
+
+        // Retry succeeds: the operation that survived the failed attempt
+        // now gets acked.
+        let sent = sender.drain_pending(&mut sync, &mut transport).unwrap();
+        assert_eq!(sent, 1);
+        assert!(sync.pending_operations().is_empty());
+    }
+
+    #[test]
+    fn test_drain_all_pending_drains_across_multiple_batches() {
+        let machine_id = Uuid::new_v4();
+        let mut sync = CrdtSync::new(machine_id, ConflictResolutionStrategy::LastWriteWins);
+        for _ in 0..7 {
+            sync.record_operation(sample_write(machine_id)).unwrap();
+        }
+
+        let mut transport = FixtureTransport {
+            next_seq: 0,
+            acked_per_call: RefCell::new(vec![]),
+            fail_next: RefCell::new(false),
+        };
+        let mut sender = SyncSender::new(3);
+
+        let total = sender.drain_all_pending(&mut sync, &mut transport).unwrap();
+        assert_eq!(total, 7);
+        assert!(sync.pending_operations().is_empty());
+    }
+
+    #[test]
+    fn test_resume_reads_last_acked_sequence_from_transport() {
+        let mut transport = FixtureTransport {
+            next_seq: 42,
+            acked_per_call: RefCell::new(vec![]),
+            fail_next: RefCell::new(false),
+        };
+        let mut sender = SyncSender::new(10);
+        sender.resume(&mut transport).unwrap();
+        assert_eq!(sender.last_acked_sequence(), Some(41));
+    }
+}
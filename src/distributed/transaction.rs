@@ -0,0 +1,272 @@
+//! Optimistic-concurrency metadata transactions
+//!
+//! Lets several machines mount the same cloud volume read/write without
+//! silently clobbering each other's changes. A [`Transaction`] reads
+//! inodes through [`Transaction::get`], which remembers the version each
+//! one was read at, and stages writes in memory via [`Transaction::put`].
+//! [`run_transaction`] then commits the staged writes with a two-phase
+//! protocol: a prewrite phase stakes a lock on every touched inode under
+//! the transaction's start timestamp (failing if any inode moved past the
+//! version it was read at, or another transaction already holds it), and
+//! a commit phase installs the writes and releases the locks together -
+//! so a reader never observes a transaction that touched two directories
+//! plus an inode (e.g. a rename) half-applied. A prewrite conflict
+//! retries the whole closure from scratch, up to
+//! `TransactionConfig::max_retries`, surfacing
+//! [`Error::TransactionConflict`] once exhausted.
+
+use crate::config::TransactionConfig;
+use crate::error::{Error, Result};
+use crate::metadata::{Inode, MetadataStore};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Process-wide source of transaction start/commit timestamps. A plain
+/// counter is enough to order transactions against each other on one
+/// machine; cross-machine ordering is whatever the underlying
+/// [`MetadataStore`] (shared cloud storage) serializes CAS writes to.
+static NEXT_TS: AtomicU64 = AtomicU64::new(1);
+
+fn next_ts() -> u64 {
+    NEXT_TS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A buffered optimistic-concurrency transaction over a [`MetadataStore`].
+/// Reads go straight to the store; writes are staged in memory until
+/// [`run_transaction`] commits them.
+pub struct Transaction<'a> {
+    store: &'a MetadataStore,
+    client_id: String,
+    start_ts: u64,
+    /// Version each touched inode was read (or created) at, used as the
+    /// prewrite phase's compare-and-swap baseline.
+    read_versions: BTreeMap<u64, u64>,
+    /// Staged writes, keyed by inode number so a key written twice in one
+    /// transaction only prewrites/commits once.
+    writes: BTreeMap<u64, Inode>,
+}
+
+impl<'a> Transaction<'a> {
+    fn new(store: &'a MetadataStore, client_id: String) -> Self {
+        Transaction {
+            store,
+            client_id,
+            start_ts: next_ts(),
+            read_versions: BTreeMap::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// This transaction's start timestamp, the snapshot its reads are
+    /// taken as of.
+    pub fn start_ts(&self) -> u64 {
+        self.start_ts
+    }
+
+    /// Read an inode, remembering the version it was read at so the
+    /// commit's prewrite phase can detect a concurrent writer.
+    pub fn get(&mut self, ino: u64) -> Result<Inode> {
+        let inode = self.store.get_inode_required(ino)?;
+        self.read_versions.entry(ino).or_insert(inode.version);
+        Ok(inode)
+    }
+
+    /// Stage `inode` to be written when the transaction commits. Also
+    /// records its pre-write version as the CAS baseline if this is the
+    /// first time the transaction has touched it (e.g. a newly created
+    /// inode staged without a prior [`Self::get`]).
+    pub fn put(&mut self, inode: Inode) {
+        self.read_versions.entry(inode.ino).or_insert(inode.version);
+        self.writes.insert(inode.ino, inode);
+    }
+
+    /// Stake a prewrite lock on every staged inode. Rolls back whatever
+    /// locks it already took if a later key conflicts, so a failed
+    /// prewrite never leaves the store half-locked for this transaction.
+    fn prewrite(&self) -> Result<()> {
+        let mut locked = Vec::with_capacity(self.writes.len());
+        for ino in self.writes.keys() {
+            let expected_version = *self.read_versions.get(ino).unwrap_or(&0);
+            match self.store.prewrite_lock(*ino, expected_version, &self.client_id, self.start_ts) {
+                Ok(()) => locked.push(*ino),
+                Err(e) => {
+                    for ino in locked {
+                        self.store.release_lock(ino, &self.client_id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Commit phase: install every staged write and release its lock.
+    /// Only called after [`Self::prewrite`] has locked every key, so in
+    /// practice this should not fail - but if the store reports a lock
+    /// loss anyway (e.g. an operator force-released it), the error is
+    /// surfaced rather than silently dropping the write.
+    fn commit(self) -> Result<()> {
+        for (_, inode) in self.writes {
+            self.store.commit_locked_inode(&inode, &self.client_id)?;
+        }
+        Ok(())
+    }
+
+    fn abort(self) {
+        for ino in self.writes.keys() {
+            self.store.release_lock(*ino, &self.client_id);
+        }
+    }
+}
+
+/// Run `f` against a fresh [`Transaction`] and commit its staged writes.
+/// On a prewrite conflict, the transaction is discarded and `f` re-runs
+/// from scratch against a new one (its reads may see different data), up
+/// to `config.max_retries` times.
+pub fn run_transaction<T>(
+    store: &MetadataStore,
+    config: &TransactionConfig,
+    client_id: &str,
+    mut f: impl FnMut(&mut Transaction) -> Result<T>,
+) -> Result<T> {
+    let mut last_conflict = None;
+
+    for _ in 0..=config.max_retries {
+        let mut txn = Transaction::new(store, client_id.to_string());
+        let result = f(&mut txn)?;
+
+        match txn.prewrite() {
+            Ok(()) => {
+                txn.commit()?;
+                return Ok(result);
+            }
+            Err(Error::TransactionConflict(msg)) => {
+                last_conflict = Some(msg);
+                continue;
+            }
+            Err(e) => {
+                txn.abort();
+                return Err(e);
+            }
+        }
+    }
+
+    Err(Error::TransactionConflict(format!(
+        "exceeded {} retries: {}",
+        config.max_retries,
+        last_conflict.unwrap_or_else(|| "no prewrite was attempted".to_string())
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Inode;
+
+    fn test_store() -> MetadataStore {
+        MetadataStore::in_memory([0u8; 32]).unwrap()
+    }
+
+    fn test_config() -> TransactionConfig {
+        TransactionConfig { client_id: None, max_retries: 3 }
+    }
+
+    #[test]
+    fn test_transaction_commits_staged_writes() {
+        let store = test_store();
+        let mut dir = store.get_inode_required(1).unwrap();
+        let ino = store.alloc_ino();
+        let file = Inode::new_file(ino, 1, "a.txt".to_string(), 0, 0, 0o644);
+        store.save_inode(&file).unwrap();
+        dir.add_child(ino);
+        store.save_inode(&dir).unwrap();
+
+        run_transaction(&store, &test_config(), "client-a", |txn| {
+            let mut file = txn.get(ino)?;
+            file.attrs.perm = 0o600;
+            txn.put(file);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(store.get_inode_required(ino).unwrap().attrs.perm, 0o600);
+    }
+
+    #[test]
+    fn test_transaction_conflict_retries_then_succeeds() {
+        let store = test_store();
+        let ino = store.alloc_ino();
+        let file = Inode::new_file(ino, 1, "a.txt".to_string(), 0, 0, 0o644);
+        store.save_inode(&file).unwrap();
+
+        let mut attempts = 0;
+        run_transaction(&store, &test_config(), "client-a", |txn| {
+            attempts += 1;
+            let mut file = txn.get(ino)?;
+            if attempts == 1 {
+                // Simulate a concurrent writer bumping the version
+                // between this transaction's read and its commit.
+                let mut racer = store.get_inode_required(ino).unwrap();
+                racer.bump_version();
+                store.save_inode(&racer).unwrap();
+            }
+            file.attrs.perm = 0o600;
+            txn.put(file);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(attempts, 2);
+        assert_eq!(store.get_inode_required(ino).unwrap().attrs.perm, 0o600);
+    }
+
+    #[test]
+    fn test_transaction_exhausts_retries_on_persistent_conflict() {
+        let store = test_store();
+        let ino = store.alloc_ino();
+        let file = Inode::new_file(ino, 1, "a.txt".to_string(), 0, 0, 0o644);
+        store.save_inode(&file).unwrap();
+
+        let config = TransactionConfig { client_id: None, max_retries: 2 };
+        let result = run_transaction(&store, &config, "client-a", |txn| {
+            let mut file = txn.get(ino)?;
+            // Every attempt races itself, so the conflict never resolves.
+            let mut racer = store.get_inode_required(ino).unwrap();
+            racer.bump_version();
+            store.save_inode(&racer).unwrap();
+            file.attrs.perm = 0o600;
+            txn.put(file);
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(Error::TransactionConflict(_))));
+    }
+
+    #[test]
+    fn test_concurrent_transactions_on_disjoint_inodes_both_commit() {
+        let store = test_store();
+        let ino_a = store.alloc_ino();
+        let ino_b = store.alloc_ino();
+        store.save_inode(&Inode::new_file(ino_a, 1, "a.txt".to_string(), 0, 0, 0o644)).unwrap();
+        store.save_inode(&Inode::new_file(ino_b, 1, "b.txt".to_string(), 0, 0, 0o644)).unwrap();
+
+        run_transaction(&store, &test_config(), "client-a", |txn| {
+            let mut file = txn.get(ino_a)?;
+            file.attrs.perm = 0o600;
+            txn.put(file);
+            Ok(())
+        })
+        .unwrap();
+
+        run_transaction(&store, &test_config(), "client-b", |txn| {
+            let mut file = txn.get(ino_b)?;
+            file.attrs.perm = 0o640;
+            txn.put(file);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(store.get_inode_required(ino_a).unwrap().attrs.perm, 0o600);
+        assert_eq!(store.get_inode_required(ino_b).unwrap().attrs.perm, 0o640);
+    }
+}
@@ -0,0 +1,184 @@
+//! Registry of cluster peers that have completed enrollment.
+//!
+//! A peer becomes trusted by completing the challenge/response flow
+//! described on [`crate::distributed::identity::MachineIdentity::enrollment_request`]:
+//! it sends an [`EnrollmentRequest`], an operator confirms the request's
+//! verification code out-of-band, the existing member issues a random
+//! challenge, and [`TrustedPeers::complete_enrollment`] records the peer
+//! once its signature over that challenge checks out.
+
+use crate::distributed::identity::{EnrollmentRequest, MachineIdentity};
+use crate::error::{Error, Result};
+use ring::rand::SecureRandom;
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+use std::time::SystemTime;
+use tracing::info;
+use uuid::Uuid;
+
+/// A remote machine this node has completed enrollment with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedPeer {
+    /// The peer's stable machine id.
+    pub machine_id: Uuid,
+    /// The peer's Ed25519 public key at the time it was enrolled.
+    pub public_key: [u8; 32],
+    /// When enrollment completed.
+    pub joined_at: SystemTime,
+}
+
+/// Draw a random 32-byte enrollment challenge for a joining peer to sign.
+pub fn generate_challenge() -> Result<[u8; 32]> {
+    let mut challenge = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut challenge)
+        .map_err(|_| Error::KeyDerivation("Failed to generate enrollment challenge".to_string()))?;
+    Ok(challenge)
+}
+
+/// Sled-backed set of [`TrustedPeer`]s, keyed by `machine_id`.
+pub struct TrustedPeers {
+    tree: Tree,
+}
+
+impl TrustedPeers {
+    /// Open the `trusted_peers` tree of `db`.
+    pub fn open(db: &Db) -> std::result::Result<Self, sled::Error> {
+        let tree = db.open_tree("trusted_peers")?;
+        Ok(Self { tree })
+    }
+
+    /// Record `machine_id`/`public_key` as trusted, unconditionally.
+    pub fn add(&self, machine_id: Uuid, public_key: [u8; 32]) -> Result<()> {
+        let peer = TrustedPeer {
+            machine_id,
+            public_key,
+            joined_at: SystemTime::now(),
+        };
+        let bytes = bincode::serialize(&peer)?;
+        self.tree.insert(machine_id.as_bytes(), bytes)?;
+        self.tree.flush()?;
+        info!("Enrolled trusted peer {}", machine_id);
+        Ok(())
+    }
+
+    /// Verify `request`'s signature over `challenge`, then record the peer
+    /// as trusted. Fails with [`Error::EnrollmentChallengeFailed`] without
+    /// touching the store if the signature doesn't check out.
+    pub fn complete_enrollment(
+        &self,
+        request: &EnrollmentRequest,
+        challenge: &[u8],
+        signature: &[u8],
+    ) -> Result<()> {
+        if !MachineIdentity::verify_with_key(&request.public_key, challenge, signature) {
+            return Err(Error::EnrollmentChallengeFailed);
+        }
+        self.add(request.machine_id, request.public_key)
+    }
+
+    /// Whether `machine_id`/`public_key` has completed enrollment.
+    pub fn is_trusted(&self, machine_id: Uuid, public_key: &[u8; 32]) -> Result<bool> {
+        match self.tree.get(machine_id.as_bytes())? {
+            Some(bytes) => {
+                let peer: TrustedPeer = bincode::deserialize(&bytes)?;
+                Ok(&peer.public_key == public_key)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Like [`TrustedPeers::is_trusted`], but fails with
+    /// [`Error::UntrustedPeer`] instead of returning `false`, for call
+    /// sites that want to short-circuit with `?`.
+    pub fn require_trusted(&self, machine_id: Uuid, public_key: &[u8; 32]) -> Result<()> {
+        if self.is_trusted(machine_id, public_key)? {
+            Ok(())
+        } else {
+            Err(Error::UntrustedPeer(machine_id.to_string()))
+        }
+    }
+
+    /// List every trusted peer.
+    pub fn list(&self) -> Result<Vec<TrustedPeer>> {
+        let mut peers = Vec::new();
+        for item in self.tree.iter() {
+            let (_, bytes) = item?;
+            peers.push(bincode::deserialize(&bytes)?);
+        }
+        Ok(peers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncryptionConfig;
+
+    fn test_config() -> EncryptionConfig {
+        EncryptionConfig {
+            argon2_memory_kib: 1024,
+            argon2_iterations: 1,
+            argon2_parallelism: 1,
+            salt: Vec::new(),
+            algorithm: crate::crypto::Algorithm::default(),
+        }
+    }
+
+    fn test_identity(name: &str) -> MachineIdentity {
+        let master_key = [0x42u8; 32];
+        let config = test_config();
+        MachineIdentity::generate(name.to_string(), &master_key, &config).unwrap()
+    }
+
+    #[test]
+    fn test_enrollment_round_trip() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let trusted = TrustedPeers::open(&db).unwrap();
+
+        let joiner = test_identity("joiner");
+        let request = joiner.enrollment_request();
+        assert_eq!(request.verification_code.len(), 6);
+
+        let challenge = generate_challenge().unwrap();
+        let signature = joiner.sign(&challenge).unwrap();
+
+        trusted
+            .complete_enrollment(&request, &challenge, &signature)
+            .expect("enrollment should succeed");
+
+        assert!(trusted.is_trusted(joiner.machine_id, &joiner.public_key).unwrap());
+        assert_eq!(trusted.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_enrollment_rejects_bad_signature() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let trusted = TrustedPeers::open(&db).unwrap();
+
+        let joiner = test_identity("joiner");
+        let impostor = test_identity("impostor");
+        let request = joiner.enrollment_request();
+
+        let challenge = generate_challenge().unwrap();
+        let bad_signature = impostor.sign(&challenge).unwrap();
+
+        let err = trusted
+            .complete_enrollment(&request, &challenge, &bad_signature)
+            .unwrap_err();
+        assert!(matches!(err, Error::EnrollmentChallengeFailed));
+        assert!(!trusted.is_trusted(joiner.machine_id, &joiner.public_key).unwrap());
+    }
+
+    #[test]
+    fn test_require_trusted_errors_for_unknown_peer() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let trusted = TrustedPeers::open(&db).unwrap();
+        let stranger = test_identity("stranger");
+
+        let err = trusted
+            .require_trusted(stranger.machine_id, &stranger.public_key)
+            .unwrap_err();
+        assert!(matches!(err, Error::UntrustedPeer(_)));
+    }
+}
@@ -1,7 +1,8 @@
 //! Vector clock implementation for distributed causality tracking
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use uuid::Uuid;
 
 /// Vector clock for tracking causality in distributed systems
@@ -151,6 +152,26 @@ impl VectorClock {
     pub fn clear(&mut self) {
         self.clocks.clear();
     }
+
+    /// Drop every machine not in `live_machines`.
+    ///
+    /// Used when a node permanently leaves the cluster so clocks don't
+    /// grow unboundedly over the life of a long-running deployment. A
+    /// retired machine's timestamp only ever contributed entries that
+    /// are `<=` every surviving clock's entry for that machine (nothing
+    /// new can be attributed to it once it's gone), so dropping it never
+    /// changes the ordering relationship between clocks that reference
+    /// only machines in `live_machines`.
+    pub fn prune(&mut self, live_machines: &HashSet<Uuid>) {
+        self.clocks.retain(|machine_id, _| live_machines.contains(machine_id));
+    }
+
+    /// Drop a single retired machine's entry. Equivalent to calling
+    /// [`Self::prune`] with every other currently-tracked machine as
+    /// `live_machines`.
+    pub fn retire(&mut self, machine_id: Uuid) {
+        self.clocks.remove(&machine_id);
+    }
 }
 
 impl Default for VectorClock {
@@ -159,6 +180,22 @@ impl Default for VectorClock {
     }
 }
 
+impl PartialOrd for VectorClock {
+    /// Maps the causal ordering onto natural comparison: `self < other`
+    /// iff `self` happened before `other`, and so on. Concurrent clocks
+    /// (including clocks that merely reference disjoint sets of
+    /// machines) have no relationship, so this returns `None` for them
+    /// rather than falling back to e.g. length comparison.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.compare(other) {
+            ClockOrdering::Equal => Some(Ordering::Equal),
+            ClockOrdering::Before => Some(Ordering::Less),
+            ClockOrdering::After => Some(Ordering::Greater),
+            ClockOrdering::Concurrent => None,
+        }
+    }
+}
+
 /// Ordering relationship between two vector clocks
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClockOrdering {
@@ -172,6 +209,84 @@ pub enum ClockOrdering {
     Concurrent,
 }
 
+/// Per-machine state for a [`VersionVectorWithExceptions`]: a contiguous
+/// run of events `1..=base` that have all been seen, plus any
+/// out-of-order events past `base` received before the gap before them
+/// was filled in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+struct MachineProgress {
+    base: u64,
+    exceptions: BTreeSet<u64>,
+}
+
+/// A version vector that, unlike [`VectorClock`], can represent gaps:
+/// "I've seen events 1 and 3 but missed 2" rather than collapsing both
+/// that and "I've seen 1,2,3" down to the same `3`.
+///
+/// Per machine, events `1..=base` are known contiguous, and `exceptions`
+/// holds individual events past `base` that arrived before the gap
+/// before them closed. This lets the sync layer safely deduplicate
+/// out-of-order CRDT operations and tell "already applied" apart from
+/// "still missing something".
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VersionVectorWithExceptions {
+    machines: HashMap<Uuid, MachineProgress>,
+}
+
+impl VersionVectorWithExceptions {
+    /// Create an empty version vector - nothing witnessed for any machine.
+    pub fn new() -> Self {
+        Self {
+            machines: HashMap::new(),
+        }
+    }
+
+    /// Record that event `n` from `machine` has been seen. Returns
+    /// `false` if `n` was already accounted for (`n <= base`, or already
+    /// in `exceptions`) - a duplicate delivery the caller should skip -
+    /// and `true` if this witness moved the vector forward.
+    pub fn witness(&mut self, machine: Uuid, n: u64) -> bool {
+        let progress = self.machines.entry(machine).or_default();
+
+        if n <= progress.base || progress.exceptions.contains(&n) {
+            return false;
+        }
+
+        if n == progress.base + 1 {
+            progress.base += 1;
+            while progress.exceptions.remove(&(progress.base + 1)) {
+                progress.base += 1;
+            }
+        } else {
+            progress.exceptions.insert(n);
+        }
+
+        true
+    }
+
+    /// Whether event `n` from `machine` has already been witnessed.
+    pub fn contains(&self, machine: Uuid, n: u64) -> bool {
+        match self.machines.get(&machine) {
+            Some(progress) => n <= progress.base || progress.exceptions.contains(&n),
+            None => false,
+        }
+    }
+
+    /// Collapse to a [`VectorClock`] holding only the contiguous prefix
+    /// (`base`) known for each machine - any out-of-order `exceptions`
+    /// past a gap are dropped, since a plain vector clock can't express
+    /// them.
+    pub fn to_vector_clock(&self) -> VectorClock {
+        let mut vc = VectorClock::new();
+        for (&machine, progress) in &self.machines {
+            if progress.base > 0 {
+                vc.set(machine, progress.base);
+            }
+        }
+        vc
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -459,4 +574,171 @@ mod tests {
         // These are concurrent (vc1[A] > vc2[A]=0, but vc1[C]=0 < vc2[C])
         assert!(vc1.concurrent(&vc2));
     }
+
+    #[test]
+    fn test_partial_ord() {
+        let machine_a = Uuid::new_v4();
+
+        let mut vc1 = VectorClock::new();
+        vc1.set(machine_a, 1);
+
+        let mut vc2 = VectorClock::new();
+        vc2.set(machine_a, 2);
+
+        assert!(vc1 < vc2);
+        assert!(vc2 > vc1);
+        assert!(vc1 <= vc1.clone());
+        assert_eq!(vc1.partial_cmp(&vc1.clone()), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn test_partial_ord_concurrent_is_none() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+
+        let mut vc1 = VectorClock::new();
+        vc1.set(machine_a, 2);
+        vc1.set(machine_b, 1);
+
+        let mut vc2 = VectorClock::new();
+        vc2.set(machine_a, 1);
+        vc2.set(machine_b, 2);
+
+        assert_eq!(vc1.partial_cmp(&vc2), None);
+        assert!(!(vc1 < vc2));
+        assert!(!(vc1 > vc2));
+    }
+
+    #[test]
+    fn test_prune_drops_retired_machines() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+
+        let mut vc = VectorClock::new();
+        vc.set(machine_a, 3);
+        vc.set(machine_b, 5);
+
+        let live: HashSet<Uuid> = [machine_a].into_iter().collect();
+        vc.prune(&live);
+
+        assert_eq!(vc.get(machine_a), 3);
+        assert_eq!(vc.get(machine_b), 0);
+        assert_eq!(vc.machines(), vec![machine_a]);
+    }
+
+    #[test]
+    fn test_retire_single_machine() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+
+        let mut vc = VectorClock::new();
+        vc.set(machine_a, 1);
+        vc.set(machine_b, 2);
+
+        vc.retire(machine_b);
+
+        assert_eq!(vc.machines(), vec![machine_a]);
+    }
+
+    #[test]
+    fn test_prune_preserves_ordering_among_live_machines() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+        let retired = Uuid::new_v4();
+
+        let mut vc1 = VectorClock::new();
+        vc1.set(machine_a, 1);
+        vc1.set(machine_b, 1);
+        vc1.set(retired, 9);
+
+        let mut vc2 = VectorClock::new();
+        vc2.set(machine_a, 2);
+        vc2.set(machine_b, 2);
+        vc2.set(retired, 9);
+
+        assert!(vc1 < vc2);
+
+        let live: HashSet<Uuid> = [machine_a, machine_b].into_iter().collect();
+        vc1.prune(&live);
+        vc2.prune(&live);
+
+        assert!(vc1 < vc2);
+    }
+
+    #[test]
+    fn test_version_vector_witnesses_in_order() {
+        let machine = Uuid::new_v4();
+        let mut vv = VersionVectorWithExceptions::new();
+
+        assert!(vv.witness(machine, 1));
+        assert!(vv.witness(machine, 2));
+        assert!(vv.witness(machine, 3));
+
+        assert!(vv.contains(machine, 1));
+        assert!(vv.contains(machine, 3));
+        assert!(!vv.contains(machine, 4));
+    }
+
+    #[test]
+    fn test_version_vector_tracks_gap_as_exception() {
+        let machine = Uuid::new_v4();
+        let mut vv = VersionVectorWithExceptions::new();
+
+        assert!(vv.witness(machine, 1));
+        // Event 2 is missing - event 3 arrives early and is held as an
+        // exception rather than collapsing the gap away.
+        assert!(vv.witness(machine, 3));
+
+        assert!(vv.contains(machine, 1));
+        assert!(!vv.contains(machine, 2));
+        assert!(vv.contains(machine, 3));
+
+        // A plain vector clock can't express the gap, so collapsing
+        // stops at the contiguous prefix.
+        assert_eq!(vv.to_vector_clock().get(machine), 1);
+    }
+
+    #[test]
+    fn test_version_vector_absorbs_exceptions_once_gap_fills() {
+        let machine = Uuid::new_v4();
+        let mut vv = VersionVectorWithExceptions::new();
+
+        vv.witness(machine, 1);
+        vv.witness(machine, 4);
+        vv.witness(machine, 3);
+
+        // 2 hasn't arrived yet - base stays at 1 even though 3 and 4 are known.
+        assert_eq!(vv.to_vector_clock().get(machine), 1);
+
+        // 2 arrives, closing the gap - 3 and 4 should absorb into base too.
+        vv.witness(machine, 2);
+        assert_eq!(vv.to_vector_clock().get(machine), 4);
+        assert!(vv.contains(machine, 4));
+    }
+
+    #[test]
+    fn test_version_vector_rejects_duplicates() {
+        let machine = Uuid::new_v4();
+        let mut vv = VersionVectorWithExceptions::new();
+
+        assert!(vv.witness(machine, 1));
+        assert!(!vv.witness(machine, 1));
+
+        assert!(vv.witness(machine, 3));
+        assert!(!vv.witness(machine, 3));
+    }
+
+    #[test]
+    fn test_version_vector_tracks_machines_independently() {
+        let machine_a = Uuid::new_v4();
+        let machine_b = Uuid::new_v4();
+        let mut vv = VersionVectorWithExceptions::new();
+
+        vv.witness(machine_a, 1);
+        vv.witness(machine_a, 2);
+        vv.witness(machine_b, 1);
+
+        assert_eq!(vv.to_vector_clock().get(machine_a), 2);
+        assert_eq!(vv.to_vector_clock().get(machine_b), 1);
+    }
 }
@@ -22,6 +22,9 @@ pub enum Error {
     #[error("Invalid key length: expected {expected}, got {got}")]
     InvalidKeyLength { expected: usize, got: usize },
 
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+
     // Telegram errors
     #[error("Telegram client error: {0}")]
     TelegramClient(String),
@@ -32,6 +35,9 @@ pub enum Error {
     #[error("Telegram rate limited, retry after {seconds} seconds")]
     TelegramRateLimited { seconds: u32 },
 
+    #[error("Telegram file reference expired, re-fetch required")]
+    TelegramFileReferenceExpired,
+
     #[error("Telegram upload failed: {0}")]
     TelegramUpload(String),
 
@@ -70,6 +76,9 @@ pub enum Error {
     #[error("Already exists: {0}")]
     AlreadyExists(String),
 
+    #[error("Extended attribute not found: {0}")]
+    XattrNotFound(String),
+
     #[error("Database error: {0}")]
     Database(#[from] sled::Error),
 
@@ -77,6 +86,12 @@ pub enum Error {
     #[error("Permission denied")]
     PermissionDenied,
 
+    #[error("Permission denied for extended attribute {0}")]
+    XattrPermissionDenied(String),
+
+    #[error("Buffer too small for xattr list: need {required} bytes")]
+    XattrBufferTooSmall { required: usize },
+
     #[error("Invalid file handle: {0}")]
     InvalidFileHandle(u64),
 
@@ -97,6 +112,9 @@ pub enum Error {
     #[error("Snapshot already exists: {0}")]
     SnapshotAlreadyExists(String),
 
+    #[error("Snapshot corrupt: {0} inode(s) failed Merkle verification: {1:?}")]
+    SnapshotCorruption(usize, Vec<u64>),
+
     // Version errors
     #[error("Version not found: {0}")]
     VersionNotFound(u64),
@@ -117,6 +135,18 @@ pub enum Error {
     #[error("Duplicate operation: {0}")]
     DuplicateOperation(String),
 
+    #[error("Transaction conflict: {0}")]
+    TransactionConflict(String),
+
+    #[error("Peer {0} is not trusted: complete cluster enrollment first")]
+    UntrustedPeer(String),
+
+    #[error("Enrollment challenge failed: signature did not verify")]
+    EnrollmentChallengeFailed,
+
+    #[error("Rotation chain invalid: {0}")]
+    InvalidRotationChain(String),
+
     // Erasure coding errors
     #[error("Erasure degraded: {available}/{required} accounts available")]
     ErasureDegraded { available: usize, required: usize },
@@ -142,6 +172,16 @@ pub enum Error {
     #[error("Rebuild failed for account {account}: {reason}")]
     RebuildFailed { account: u8, reason: String },
 
+    #[error("Shard corruption: {corrupted} shard(s) failed checksum verification, {available}/{required} trustworthy shards remain")]
+    ShardCorruption {
+        corrupted: usize,
+        available: usize,
+        required: usize,
+    },
+
+    #[error("Block {block_index} from account {account_id} failed checksum verification on download")]
+    ChecksumMismatch { block_index: u8, account_id: u8 },
+
     // Config errors
     #[error("Configuration error: {0}")]
     Config(String),
@@ -156,6 +196,10 @@ pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 
+    // Control socket errors
+    #[error("Control socket error: {0}")]
+    Control(String),
+
     // Serialization errors
     #[error("Serialization error: {0}")]
     Serialization(String),
@@ -182,6 +226,12 @@ impl Error {
             Error::NotAFile(_) => libc::EISDIR,
             Error::DirectoryNotEmpty(_) => libc::ENOTEMPTY,
             Error::AlreadyExists(_) => libc::EEXIST,
+            Error::XattrPermissionDenied(_) => libc::EPERM,
+            Error::XattrBufferTooSmall { .. } => libc::ERANGE,
+            #[cfg(target_os = "macos")]
+            Error::XattrNotFound(_) => libc::ENOATTR,
+            #[cfg(not(target_os = "macos"))]
+            Error::XattrNotFound(_) => libc::ENODATA,
             Error::PermissionDenied => libc::EACCES,
             Error::FileTooLarge { .. } => libc::EFBIG,
             Error::Io(e) => e.raw_os_error().unwrap_or(libc::EIO),
@@ -189,6 +239,18 @@ impl Error {
             _ => libc::EIO,
         }
     }
+
+    /// Whether a failed Telegram operation is worth retrying. Auth that's
+    /// been revoked and file references that have expired won't succeed no
+    /// matter how many more times we try, so the retry loops in
+    /// [`crate::telegram::client::TelegramBackend`] fail fast on these
+    /// instead of burning their whole retry budget.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            Error::TelegramAuthRequired | Error::TelegramFileReferenceExpired
+        )
+    }
 }
 
 impl From<bincode::Error> for Error {
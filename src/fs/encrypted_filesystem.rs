@@ -0,0 +1,405 @@
+//! A stable, FUSE-independent async filesystem API
+//!
+//! [`TgCryptFs`] only exists to satisfy `fuser::Filesystem`'s synchronous,
+//! reply-based callback shape. Anything embedding tgcryptfs as a library -
+//! a gRPC service, a WASI host, a GUI - needs an API that speaks `Result`
+//! and `async fn` instead, without dragging in `fuser` or FUSE's
+//! open/write-buffer/release handle lifecycle. [`EncryptedFilesystem`] is
+//! that API; [`TgCryptFs::into_encrypted_filesystem`] builds one by
+//! consuming the shared, already-`Arc`'d pieces of a running mount.
+
+use crate::cache::ChunkCache;
+use crate::chunk::{compress, compress_or_original, decompress, ChunkId, ChunkManifest, ChunkPayload, ChunkRef, Chunker};
+use crate::config::Config;
+use crate::crypto::{decrypt, encrypt, EncryptedData, KeyManager};
+use crate::error::{Error, Result};
+use crate::metadata::{FileType, Inode, MetadataStore};
+use crate::telegram::TelegramBackend;
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// One entry returned by [`EncryptedFilesystem::readdir`].
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// The entry's inode number.
+    pub ino: u64,
+    /// The entry's name within its parent directory.
+    pub name: String,
+    /// The entry's file type.
+    pub kind: FileType,
+}
+
+/// Attribute changes for [`EncryptedFilesystem::setattr`]. Every field left
+/// `None` is left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct SetAttrRequest {
+    /// New permission bits.
+    pub mode: Option<u16>,
+    /// New owning user id.
+    pub uid: Option<u32>,
+    /// New owning group id.
+    pub gid: Option<u32>,
+    /// New file size (truncates or extends; extension is zero-filled).
+    pub size: Option<u64>,
+    /// New access time.
+    pub atime: Option<SystemTime>,
+    /// New modification time.
+    pub mtime: Option<SystemTime>,
+}
+
+/// Every mutating/reading operation a tgcryptfs-backed frontend needs,
+/// independent of FUSE. Object-safe so callers can hold a
+/// `Box<dyn EncryptedFilesystem>` / `Arc<dyn EncryptedFilesystem>` without
+/// knowing the concrete implementation.
+#[async_trait]
+pub trait EncryptedFilesystem: Send + Sync {
+    /// Resolve `name` within `parent`.
+    async fn lookup(&self, parent: u64, name: &str) -> Result<Inode>;
+
+    /// Fetch an inode's current attributes.
+    async fn getattr(&self, ino: u64) -> Result<Inode>;
+
+    /// Apply `changes` to an inode's attributes.
+    async fn setattr(&self, ino: u64, changes: SetAttrRequest) -> Result<Inode>;
+
+    /// Read up to `size` bytes starting at `offset`.
+    async fn read(&self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>>;
+
+    /// Replace a file's contents with `data`. Returns the number of bytes
+    /// written. Like [`TgCryptFs`]'s own write path, this replaces the
+    /// whole file rather than patching a byte range - a future chunk can
+    /// teach both paths real partial writes together.
+    async fn write(&self, ino: u64, data: &[u8]) -> Result<u32>;
+
+    /// Create a new, empty file.
+    async fn create(&self, parent: u64, name: &str, mode: u32) -> Result<Inode>;
+
+    /// Create a new directory.
+    async fn mkdir(&self, parent: u64, name: &str, mode: u32) -> Result<Inode>;
+
+    /// Remove a file.
+    async fn unlink(&self, parent: u64, name: &str) -> Result<()>;
+
+    /// Remove an empty directory.
+    async fn rmdir(&self, parent: u64, name: &str) -> Result<()>;
+
+    /// List a directory's entries, including `.` and `..`.
+    async fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>>;
+
+    /// Move/rename an entry, replacing `new_name` if it already exists.
+    async fn rename(&self, parent: u64, name: &str, new_parent: u64, new_name: &str) -> Result<()>;
+}
+
+/// Owns its own handles to the shared encryption/storage/cache stack, with
+/// no FUSE-specific state (no file handle table, no blocking-bridge
+/// runtime) - every operation just runs on whatever async runtime the
+/// caller is already on.
+pub struct EncryptedFs {
+    config: Arc<Config>,
+    keys: Arc<KeyManager>,
+    metadata: Arc<MetadataStore>,
+    telegram: Arc<TelegramBackend>,
+    cache: Arc<ChunkCache>,
+    chunker: Chunker,
+    uid: u32,
+    gid: u32,
+}
+
+impl EncryptedFs {
+    pub(crate) fn new(
+        config: Arc<Config>,
+        keys: Arc<KeyManager>,
+        metadata: Arc<MetadataStore>,
+        telegram: Arc<TelegramBackend>,
+        cache: Arc<ChunkCache>,
+        uid: u32,
+        gid: u32,
+    ) -> Self {
+        let chunker = Chunker::new(&config.chunk);
+        Self { config, keys, metadata, telegram, cache, chunker, uid, gid }
+    }
+
+    async fn get_chunk_data(&self, chunk_ref: &ChunkRef) -> Result<Vec<u8>> {
+        if let Some(data) = self.cache.get(&chunk_ref.id)? {
+            return Ok(data);
+        }
+
+        let encrypted_bytes = match &chunk_ref.payload {
+            ChunkPayload::Inline { data } => data.clone(),
+            ChunkPayload::Remote { message_id } => self.telegram.download_chunk(*message_id).await?,
+        };
+
+        let chunk_key = self.keys.chunk_key(&chunk_ref.id)?;
+        let encrypted = EncryptedData::from_bytes(&encrypted_bytes)?;
+        let decrypted = decrypt(chunk_key.key(), &encrypted, chunk_ref.id.as_bytes())?;
+        let data = decompress(&decrypted, chunk_ref.compression)?;
+
+        self.cache.put(&chunk_ref.id, &data)?;
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl EncryptedFilesystem for EncryptedFs {
+    async fn lookup(&self, parent: u64, name: &str) -> Result<Inode> {
+        self.metadata.lookup(parent, name)?.ok_or_else(|| Error::PathNotFound(name.to_string()))
+    }
+
+    async fn getattr(&self, ino: u64) -> Result<Inode> {
+        self.metadata.get_inode_required(ino)
+    }
+
+    async fn setattr(&self, ino: u64, changes: SetAttrRequest) -> Result<Inode> {
+        let mut inode = self.metadata.get_inode_required(ino)?;
+
+        if let Some(mode) = changes.mode {
+            inode.attrs.perm = mode;
+        }
+        if let Some(uid) = changes.uid {
+            inode.attrs.uid = uid;
+        }
+        if let Some(gid) = changes.gid {
+            inode.attrs.gid = gid;
+        }
+        if let Some(size) = changes.size {
+            if size == 0 && inode.is_file() {
+                inode.manifest = Some(ChunkManifest::new(inode.version + 1));
+            }
+            inode.set_size(size);
+        }
+        if let Some(atime) = changes.atime {
+            inode.attrs.atime = atime;
+        }
+        if let Some(mtime) = changes.mtime {
+            inode.attrs.mtime = mtime;
+        }
+        inode.attrs.ctime = SystemTime::now();
+
+        self.metadata.save_inode(&inode)?;
+        Ok(inode)
+    }
+
+    async fn read(&self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>> {
+        let inode = self.metadata.get_inode_required(ino)?;
+        let manifest = inode.manifest.as_ref().ok_or_else(|| Error::NotAFile(inode.name.clone()))?;
+
+        if offset >= manifest.total_size {
+            return Ok(Vec::new());
+        }
+
+        let end = std::cmp::min(offset + size as u64, manifest.total_size);
+        let mut result = Vec::with_capacity((end - offset) as usize);
+
+        let mut current_offset = 0u64;
+        for chunk_ref in &manifest.chunks {
+            let chunk_end = current_offset + chunk_ref.original_size;
+
+            if chunk_end > offset && current_offset < end {
+                let chunk_data = self.get_chunk_data(chunk_ref).await?;
+
+                let slice_start = if offset > current_offset { (offset - current_offset) as usize } else { 0 };
+                let slice_end = if end < chunk_end { (end - current_offset) as usize } else { chunk_data.len() };
+
+                result.extend_from_slice(&chunk_data[slice_start..slice_end]);
+            }
+
+            current_offset = chunk_end;
+            if current_offset >= end {
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn write(&self, ino: u64, data: &[u8]) -> Result<u32> {
+        let mut inode = self.metadata.get_inode_required(ino)?;
+
+        let chunks = self.chunker.chunk_data(data);
+        let file_hash = self.chunker.file_hash(data);
+
+        let mut manifest = ChunkManifest::new(inode.version + 1);
+        manifest.total_size = data.len() as u64;
+        manifest.file_hash = file_hash;
+
+        for chunk in chunks {
+            let (chunk_data, compression) = if self.config.chunk.compression_enabled
+                && chunk.data.len() >= self.config.chunk.compression_threshold
+            {
+                compress(&chunk.data, self.config.chunk.compression_level)?
+            } else {
+                compress_or_original(&chunk.data)?
+            };
+
+            // Content-address by the plaintext under a keyed hash (see
+            // `KeyManager::content_chunk_id`), not `chunk.info.id` as-is -
+            // see `TgCryptFs::store_chunk` for why.
+            let chunk_id = ChunkId::from(self.keys.content_chunk_id(&chunk.data)?);
+
+            let chunk_key = self.keys.chunk_key(&chunk_id)?;
+            let encrypted = encrypt(self.config.encryption.algorithm, chunk_key.key(), &chunk_data, chunk_id.as_bytes())?;
+
+            let message_id = if let Some(msg_id) = self.metadata.get_chunk_ref(&chunk_id)? {
+                self.metadata.save_chunk_ref(&chunk_id, msg_id)?;
+                msg_id
+            } else {
+                let msg_id = self.telegram.upload_chunk(&chunk_id, &encrypted.to_bytes()).await?;
+                self.metadata.save_chunk_ref(&chunk_id, msg_id)?;
+                msg_id
+            };
+
+            manifest.chunks.push(ChunkRef {
+                id: chunk_id,
+                size: encrypted.size() as u64,
+                payload: ChunkPayload::Remote { message_id },
+                offset: chunk.info.offset,
+                original_size: chunk.data.len() as u64,
+                compression,
+            });
+
+            self.cache.put(&manifest.chunks.last().unwrap().id, &chunk.data)?;
+        }
+
+        inode.manifest = Some(manifest);
+        inode.set_size(data.len() as u64);
+        inode.bump_version();
+        self.metadata.save_inode(&inode)?;
+
+        Ok(data.len() as u32)
+    }
+
+    async fn create(&self, parent: u64, name: &str, mode: u32) -> Result<Inode> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        if !parent_inode.is_dir() {
+            return Err(Error::NotADirectory(parent_inode.name.clone()));
+        }
+        if self.metadata.lookup(parent, name)?.is_some() {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        let ino = self.metadata.alloc_ino();
+        let inode = Inode::new_file(ino, parent, name.to_string(), self.uid, self.gid, mode as u16);
+
+        self.metadata.save_inode(&inode)?;
+        parent_inode.add_child(ino);
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(inode)
+    }
+
+    async fn mkdir(&self, parent: u64, name: &str, mode: u32) -> Result<Inode> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        if !parent_inode.is_dir() {
+            return Err(Error::NotADirectory(parent_inode.name.clone()));
+        }
+        if self.metadata.lookup(parent, name)?.is_some() {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        let ino = self.metadata.alloc_ino();
+        let inode = Inode::new_directory(ino, parent, name.to_string(), self.uid, self.gid, mode as u16);
+
+        self.metadata.save_inode(&inode)?;
+        parent_inode.add_child(ino);
+        parent_inode.attrs.nlink += 1;
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(inode)
+    }
+
+    async fn unlink(&self, parent: u64, name: &str) -> Result<()> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        let inode = self.metadata.lookup(parent, name)?.ok_or_else(|| Error::PathNotFound(name.to_string()))?;
+
+        if !inode.is_file() && !inode.is_symlink() {
+            return Err(Error::NotAFile(name.to_string()));
+        }
+
+        if let Some(manifest) = &inode.manifest {
+            for chunk in &manifest.chunks {
+                if let Some(msg_id) = self.metadata.decrement_chunk_ref(&chunk.id)? {
+                    let _ = self.telegram.delete_message(msg_id).await;
+                    let _ = self.cache.remove(&chunk.id);
+                }
+            }
+        }
+
+        self.metadata.delete_inode(inode.ino)?;
+        parent_inode.remove_child(inode.ino);
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(())
+    }
+
+    async fn rmdir(&self, parent: u64, name: &str) -> Result<()> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        let inode = self.metadata.lookup(parent, name)?.ok_or_else(|| Error::PathNotFound(name.to_string()))?;
+
+        if !inode.is_dir() {
+            return Err(Error::NotADirectory(name.to_string()));
+        }
+        if !inode.children.is_empty() {
+            return Err(Error::DirectoryNotEmpty(name.to_string()));
+        }
+
+        self.metadata.delete_inode(inode.ino)?;
+        parent_inode.remove_child(inode.ino);
+        parent_inode.attrs.nlink -= 1;
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(())
+    }
+
+    async fn readdir(&self, ino: u64) -> Result<Vec<DirEntry>> {
+        let inode = self.metadata.get_inode_required(ino)?;
+        if !inode.is_dir() {
+            return Err(Error::NotADirectory(inode.name.clone()));
+        }
+
+        let mut entries = vec![
+            DirEntry { ino, name: ".".to_string(), kind: FileType::Directory },
+            DirEntry { ino: inode.parent, name: "..".to_string(), kind: FileType::Directory },
+        ];
+
+        for child in self.metadata.get_children(ino)? {
+            entries.push(DirEntry { ino: child.ino, name: child.name.clone(), kind: child.attrs.kind });
+        }
+
+        Ok(entries)
+    }
+
+    async fn rename(&self, parent: u64, name: &str, new_parent: u64, new_name: &str) -> Result<()> {
+        let mut inode = self.metadata.lookup(parent, name)?.ok_or_else(|| Error::PathNotFound(name.to_string()))?;
+
+        if let Some(existing) = self.metadata.lookup(new_parent, new_name)? {
+            if existing.is_dir() {
+                self.rmdir(new_parent, new_name).await?;
+            } else {
+                self.unlink(new_parent, new_name).await?;
+            }
+        }
+
+        let mut old_parent = self.metadata.get_inode_required(parent)?;
+        old_parent.remove_child(inode.ino);
+        if inode.is_dir() {
+            old_parent.attrs.nlink -= 1;
+        }
+        self.metadata.save_inode(&old_parent)?;
+
+        let mut new_parent_inode = self.metadata.get_inode_required(new_parent)?;
+        new_parent_inode.add_child(inode.ino);
+        if inode.is_dir() {
+            new_parent_inode.attrs.nlink += 1;
+        }
+        self.metadata.save_inode(&new_parent_inode)?;
+
+        inode.parent = new_parent;
+        inode.name = new_name.to_string();
+        inode.attrs.ctime = SystemTime::now();
+        self.metadata.save_inode(&inode)?;
+
+        Ok(())
+    }
+}
@@ -1,27 +1,102 @@
 //! Main FUSE filesystem implementation
 
 use crate::cache::ChunkCache;
-use crate::chunk::{compress_or_original, decompress, ChunkManifest, ChunkRef, Chunker};
+use crate::chunk::{
+    compress, compress_or_original, decompress, ChunkId, ChunkManifest, ChunkPayload, ChunkRef, Chunker,
+};
 use crate::config::Config;
 use crate::crypto::{decrypt, encrypt, KeyManager};
+use crate::distributed::namespace_config::load_config_dir;
+use crate::distributed::{Namespace, PermissionType, RoleRegistry};
 use crate::error::{Error, Result};
+use crate::fs::encrypted_filesystem::{EncryptedFilesystem, EncryptedFs};
 use crate::fs::handle::HandleManager;
-use crate::metadata::{Inode, MetadataStore};
+use crate::metadata::{FileType, FileVersion, Inode, MetadataStore, VersionManager, XattrStore};
+#[cfg(feature = "mount")]
+use crate::metadata::{InodeFlags, XattrContext};
 use crate::telegram::TelegramBackend;
 
+#[cfg(feature = "mount")]
 use fuser::{
     FileType as FuserFileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData,
-    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
 use tracing::{debug, error};
+use uuid::Uuid;
 
 /// TTL for cached attributes
 const TTL: Duration = Duration::from_secs(1);
 
+/// Virtual inode namespace for the read-only `.snapshots/<timestamp>` tree
+/// (see [`TgCryptFs`]'s handling of it in `lookup`/`getattr`/`readdir`/`read`).
+/// These inodes never exist in [`MetadataStore`] - they're derived on the
+/// fly from [`VersionManager`] history, and always refuse mutation.
+#[cfg(feature = "mount")]
+mod snapshots {
+    const MAGIC: u64 = 0xFE << 56;
+    const MASK: u64 = 0xFFu64 << 56;
+    const KIND_SHIFT: u64 = 52;
+    const KIND_MASK: u64 = 0xF << KIND_SHIFT;
+    const TIMESTAMP_MASK: u64 = (1u64 << KIND_SHIFT) - 1;
+    const VERSION_SHIFT: u64 = 32;
+    const VERSION_MASK: u64 = 0xF_FFFF;
+    const INO_MASK: u64 = 0xFFFF_FFFF;
+
+    /// Name of the synthetic top-level directory, a child of the real root.
+    pub const DIR_NAME: &str = ".snapshots";
+
+    /// Inode of the `.snapshots` directory itself.
+    pub const ROOT_INO: u64 = MAGIC;
+
+    pub fn is_synthetic(ino: u64) -> bool {
+        ino & MASK == MAGIC
+    }
+
+    pub fn timestamp_dir_ino(timestamp_secs: u64) -> u64 {
+        MAGIC | (1 << KIND_SHIFT) | (timestamp_secs & TIMESTAMP_MASK)
+    }
+
+    pub fn file_ino(real_ino: u64, version: u64) -> u64 {
+        MAGIC | (2 << KIND_SHIFT) | ((version & VERSION_MASK) << VERSION_SHIFT) | (real_ino & INO_MASK)
+    }
+
+    /// A decoded historical-file entry name: `<current name>-ino<N>`, unique
+    /// without needing to resolve full paths through a flattened, whole-tree
+    /// view of every retained version.
+    pub fn file_entry_name(name: &str, real_ino: u64) -> String {
+        format!("{}-ino{}", name, real_ino)
+    }
+
+    pub fn parse_file_entry_name(entry: &str) -> Option<u64> {
+        entry.rsplit_once("-ino").and_then(|(_, ino)| ino.parse().ok())
+    }
+
+    pub enum Kind {
+        Root,
+        TimestampDir(u64),
+        File { real_ino: u64, version: u64 },
+    }
+
+    pub fn decode(ino: u64) -> Option<Kind> {
+        if !is_synthetic(ino) {
+            return None;
+        }
+        match (ino & KIND_MASK) >> KIND_SHIFT {
+            0 => Some(Kind::Root),
+            1 => Some(Kind::TimestampDir(ino & TIMESTAMP_MASK)),
+            2 => Some(Kind::File { real_ino: ino & INO_MASK, version: (ino >> VERSION_SHIFT) & VERSION_MASK }),
+            _ => None,
+        }
+    }
+}
+
 /// Main tgcryptfs filesystem
 pub struct TgCryptFs {
     /// Configuration
@@ -34,6 +109,10 @@ pub struct TgCryptFs {
     telegram: Arc<TelegramBackend>,
     /// Local cache
     cache: Arc<ChunkCache>,
+    /// Extended attribute store
+    xattrs: Arc<XattrStore>,
+    /// Superseded file version history, backing `.snapshots/<timestamp>`
+    versions: Arc<VersionManager>,
     /// Chunker
     chunker: Chunker,
     /// File handle manager
@@ -44,6 +123,48 @@ pub struct TgCryptFs {
     uid: u32,
     /// GID for this process
     gid: u32,
+    /// RBAC access control, loaded from [`Config::acl_config_dir`] if set
+    /// - see [`Self::check_permission`]. `None` means every operation is
+    /// allowed, matching pre-ACL behavior.
+    acl: Option<NamespaceAcl>,
+}
+
+/// This mount's namespace ACL and the local machine identity it's
+/// evaluated against - see [`TgCryptFs::check_permission`].
+struct NamespaceAcl {
+    namespace: Arc<Namespace>,
+    roles: RoleRegistry,
+    local_machine_id: Uuid,
+}
+
+/// Metadata-store key the per-machine sync identity is minted under - kept
+/// in lockstep with `cmd_sync`'s copy in `main.rs` and
+/// `control::server`'s copy (a legacy v1 [`Config`] has no `MachineConfig`
+/// to persist it in instead).
+const SYNC_MACHINE_ID_KEY: &str = "sync_machine_id";
+
+/// This machine's persisted identity, minted and saved on first use -
+/// shared with `cmd_sync`/`control::server::handle_sync` so an ACL rule
+/// keyed on a machine id lines up with the same machine's sync identity.
+fn local_machine_id(metadata: &MetadataStore) -> Result<Uuid> {
+    match metadata.get_metadata(SYNC_MACHINE_ID_KEY)? {
+        Some(bytes) => Ok(Uuid::from_slice(&bytes).unwrap_or_else(|_| Uuid::new_v4())),
+        None => {
+            let id = Uuid::new_v4();
+            metadata.save_metadata(SYNC_MACHINE_ID_KEY, id.as_bytes())?;
+            Ok(id)
+        }
+    }
+}
+
+/// Appends `name` onto a parent's [`TgCryptFs::inode_virtual_path`],
+/// without doubling the `/` when `parent_path` is the root.
+fn join_virtual_path(parent_path: &str, name: &str) -> String {
+    if parent_path == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", parent_path, name)
+    }
 }
 
 impl TgCryptFs {
@@ -54,98 +175,418 @@ impl TgCryptFs {
         metadata: MetadataStore,
         telegram: TelegramBackend,
         cache: ChunkCache,
+        xattrs: XattrStore,
+        versions: VersionManager,
     ) -> Result<Self> {
         let runtime = Runtime::new().map_err(|e| Error::Internal(e.to_string()))?;
 
         let chunker = Chunker::new(&config.chunk);
+        let keys = Arc::new(keys);
+        cache.set_id_verifier(keys.clone());
+
+        let acl = match &config.acl_config_dir {
+            Some(dir) => {
+                let (manager, roles) = load_config_dir(dir)?;
+                let namespace = manager.get_default_namespace()?;
+                let local_machine_id = local_machine_id(&metadata)?;
+                Some(NamespaceAcl { namespace, roles, local_machine_id })
+            }
+            None => None,
+        };
 
         Ok(TgCryptFs {
             config: Arc::new(config),
-            keys: Arc::new(keys),
+            keys,
             metadata: Arc::new(metadata),
             telegram: Arc::new(telegram),
             cache: Arc::new(cache),
+            xattrs: Arc::new(xattrs),
+            versions: Arc::new(versions),
             chunker,
             handles: HandleManager::new(),
             runtime,
             uid: unsafe { libc::getuid() },
             gid: unsafe { libc::getgid() },
+            acl,
         })
     }
 
+    /// Whether the local machine is allowed `required_permission` on
+    /// `path` under this mount's namespace ACL
+    /// ([`crate::distributed::Namespace::check_permission`]). Always
+    /// `true` when no `acl_config_dir` is configured - see
+    /// [`NamespaceAcl`].
+    fn check_permission(&self, path: &str, required_permission: PermissionType) -> bool {
+        match &self.acl {
+            Some(acl) => acl.namespace.check_permission(
+                &acl.local_machine_id,
+                path,
+                required_permission,
+                Some(&acl.roles),
+                None,
+            ),
+            None => true,
+        }
+    }
+
+    /// Best-effort `/`-rooted path for `ino`, built by walking parent
+    /// links up to the root inode - used only to evaluate
+    /// [`Self::check_permission`] against [`Namespace`]'s glob patterns,
+    /// never persisted or returned to FUSE callers.
+    fn inode_virtual_path(&self, ino: u64) -> Result<String> {
+        let mut segments = Vec::new();
+        let mut current = ino;
+        loop {
+            let inode = self.metadata.get_inode(current)?.ok_or(Error::InodeNotFound(current))?;
+            if inode.parent == current {
+                break;
+            }
+            segments.push(inode.name.clone());
+            current = inode.parent;
+        }
+        segments.reverse();
+        Ok(format!("/{}", segments.join("/")))
+    }
+
     /// Helper to run async code from sync FUSE callbacks
     fn block_on<F: std::future::Future>(&self, f: F) -> F::Output {
         self.runtime.block_on(f)
     }
 
+    /// Shared handle to this mount's chunk cache, for the control socket
+    /// server (see `crate::control`) to report live stats and clear it
+    /// without opening a second cache directory handle.
+    pub fn cache(&self) -> Arc<ChunkCache> {
+        self.cache.clone()
+    }
+
+    /// Shared handle to this mount's metadata store, for the control
+    /// socket's `/sync` handler to reuse rather than reopening the
+    /// database.
+    pub fn metadata(&self) -> Arc<MetadataStore> {
+        self.metadata.clone()
+    }
+
+    /// Shared handle to this mount's already-connected Telegram backend,
+    /// for the control socket to query/sync against without a second
+    /// connection.
+    pub fn telegram(&self) -> Arc<TelegramBackend> {
+        self.telegram.clone()
+    }
+
+    /// Shared handle to this mount's key manager, for the control
+    /// socket's `/sync` handler to get at the master key without a second
+    /// password prompt.
+    pub fn keys(&self) -> Arc<KeyManager> {
+        self.keys.clone()
+    }
+
     /// Read file data at a given offset
     fn read_file_data(&self, inode: &Inode, offset: u64, size: u32) -> Result<Vec<u8>> {
         let manifest = inode
             .manifest
             .as_ref()
             .ok_or_else(|| Error::NotAFile(inode.name.clone()))?;
+        self.read_manifest_data(manifest, offset, size)
+    }
 
+    /// Read file data at a given offset out of an arbitrary manifest, current
+    /// or historical - shared by `read` and the `.snapshots` read-only view.
+    ///
+    /// Every chunk overlapping `[offset, offset+size)` is fetched
+    /// concurrently (bounded by `cache.read_parallelism`) instead of one
+    /// blocking Telegram round-trip at a time, and the next
+    /// `cache.prefetch_count` chunks past the requested range are fetched
+    /// alongside them speculatively so a later sequential read finds them
+    /// already in [`ChunkCache`].
+    fn read_manifest_data(&self, manifest: &ChunkManifest, offset: u64, size: u32) -> Result<Vec<u8>> {
         if offset >= manifest.total_size {
             return Ok(Vec::new());
         }
 
         let end = std::cmp::min(offset + size as u64, manifest.total_size);
-        let mut result = Vec::with_capacity((end - offset) as usize);
 
-        // Find chunks that overlap with the requested range
+        // Chunks that overlap the requested range, paired with their offset
+        // within the file.
+        let mut overlapping = Vec::new();
         let mut current_offset = 0u64;
-        for chunk_ref in &manifest.chunks {
+        let mut last_overlap_idx = None;
+        for (idx, chunk_ref) in manifest.chunks.iter().enumerate() {
             let chunk_end = current_offset + chunk_ref.original_size;
-
             if chunk_end > offset && current_offset < end {
-                // This chunk overlaps with our range
-                let chunk_data = self.get_chunk_data(chunk_ref)?;
-
-                // Calculate the slice of this chunk we need
-                let slice_start = if offset > current_offset {
-                    (offset - current_offset) as usize
-                } else {
-                    0
-                };
-                let slice_end = if end < chunk_end {
-                    (end - current_offset) as usize
-                } else {
-                    chunk_data.len()
-                };
-
-                result.extend_from_slice(&chunk_data[slice_start..slice_end]);
+                overlapping.push((current_offset, chunk_ref));
+                last_overlap_idx = Some(idx);
             }
-
             current_offset = chunk_end;
             if current_offset >= end {
                 break;
             }
         }
 
+        let readahead = last_overlap_idx
+            .map(|idx| {
+                manifest.chunks.iter().skip(idx + 1).take(self.config.cache.prefetch_count)
+            })
+            .into_iter()
+            .flatten();
+
+        let to_fetch: Vec<ChunkRef> =
+            overlapping.iter().map(|(_, cr)| (*cr).clone()).chain(readahead.cloned()).collect();
+
+        let mut fetched =
+            self.block_on(self.fetch_chunks_concurrent(to_fetch, self.config.cache.read_parallelism));
+
+        let mut result = Vec::with_capacity((end - offset) as usize);
+        for (chunk_offset, chunk_ref) in &overlapping {
+            let chunk_end = chunk_offset + chunk_ref.original_size;
+            let chunk_data = fetched
+                .remove(&chunk_ref.id)
+                .ok_or_else(|| Error::ChunkNotFound(chunk_ref.id.to_string()))??;
+
+            let slice_start = if offset > *chunk_offset { (offset - chunk_offset) as usize } else { 0 };
+            let slice_end =
+                if end < chunk_end { (end - chunk_offset) as usize } else { chunk_data.len() };
+
+            result.extend_from_slice(&chunk_data[slice_start..slice_end]);
+        }
+
         Ok(result)
     }
 
+    /// Fetch a batch of chunks concurrently, bounded by `parallelism`
+    /// in-flight downloads at once. Chunks are deduped by
+    /// [`ChunkRef::id`] before fetching - the content-addressed dedup
+    /// mentioned in [`Self::store_chunk`] means the requested range and its
+    /// read-ahead can reference the very same chunk, and a chunk can appear
+    /// twice within one range, so this is the in-flight map that keeps
+    /// those from racing each other into two separate downloads.
+    async fn fetch_chunks_concurrent(
+        &self,
+        chunk_refs: Vec<ChunkRef>,
+        parallelism: usize,
+    ) -> HashMap<ChunkId, Result<Arc<Vec<u8>>>> {
+        let mut in_flight: HashMap<ChunkId, ChunkRef> = HashMap::new();
+        for chunk_ref in chunk_refs {
+            in_flight.entry(chunk_ref.id.clone()).or_insert(chunk_ref);
+        }
+
+        let semaphore = Arc::new(Semaphore::new(parallelism.max(1)));
+        let mut tasks = FuturesUnordered::new();
+        for (id, chunk_ref) in in_flight {
+            let semaphore = Arc::clone(&semaphore);
+            tasks.push(async move {
+                let _permit = semaphore.acquire().await.expect("semaphore is never closed");
+                (id, self.get_chunk_data_async(&chunk_ref).await.map(Arc::new))
+            });
+        }
+
+        let mut results = HashMap::new();
+        while let Some((id, result)) = tasks.next().await {
+            results.insert(id, result);
+        }
+        results
+    }
+
+    /// Build the (never-persisted) `Inode` for the `.snapshots` directory
+    /// itself, a synthetic child of the real root.
+    #[cfg(feature = "mount")]
+    fn snapshot_root_inode(&self) -> Inode {
+        Inode::new_directory(snapshots::ROOT_INO, 1, snapshots::DIR_NAME.to_string(), self.uid, self.gid, 0o555)
+    }
+
+    /// Build the (never-persisted) `Inode` for `.snapshots/<timestamp>`.
+    #[cfg(feature = "mount")]
+    fn snapshot_timestamp_inode(&self, timestamp_secs: u64) -> Inode {
+        let mtime = std::time::UNIX_EPOCH + Duration::from_secs(timestamp_secs);
+        let mut inode = Inode::new_directory(
+            snapshots::timestamp_dir_ino(timestamp_secs),
+            snapshots::ROOT_INO,
+            timestamp_secs.to_string(),
+            self.uid,
+            self.gid,
+            0o555,
+        );
+        inode.attrs.mtime = mtime;
+        inode.attrs.ctime = mtime;
+        inode
+    }
+
+    /// Build the (never-persisted) read-only `Inode` for a historical file
+    /// entry, taking identity (name, owner, mode) from the live inode when
+    /// it still exists and falling back to a generic placeholder for files
+    /// that have since been deleted.
+    #[cfg(feature = "mount")]
+    fn snapshot_file_inode(&self, real_ino: u64, version: u64, fv: &FileVersion) -> Inode {
+        let (name, uid, gid, mode) = match self.metadata.get_inode(real_ino) {
+            Ok(Some(live)) => (live.name, live.attrs.uid, live.attrs.gid, live.attrs.perm),
+            _ => ("deleted".to_string(), self.uid, self.gid, 0o644),
+        };
+
+        let mut inode = Inode::new_file(
+            snapshots::file_ino(real_ino, version),
+            snapshots::ROOT_INO,
+            snapshots::file_entry_name(&name, real_ino),
+            uid,
+            gid,
+            mode & !0o222,
+        );
+        inode.attrs.mtime = fv.timestamp;
+        inode.attrs.ctime = fv.timestamp;
+        inode.set_size(fv.manifest.total_size);
+        inode.manifest = Some(fv.manifest.clone());
+        inode
+    }
+
+    /// Resolve a `lookup` under the synthetic `.snapshots` tree. Returns
+    /// `None` when `parent` isn't part of that tree at all, so the caller
+    /// falls through to the ordinary [`MetadataStore`]-backed lookup.
+    #[cfg(feature = "mount")]
+    fn snapshot_lookup(&self, parent: u64, name: &str) -> Option<Result<Option<Inode>>> {
+        if parent == 1 && name == snapshots::DIR_NAME {
+            return Some(Ok(Some(self.snapshot_root_inode())));
+        }
+
+        let kind = snapshots::decode(parent)?;
+        let result = match kind {
+            snapshots::Kind::Root => match name.parse::<u64>() {
+                Ok(ts) => match self.versions.versions_at(ts) {
+                    Ok(v) if !v.is_empty() => Ok(Some(self.snapshot_timestamp_inode(ts))),
+                    Ok(_) => Ok(None),
+                    Err(e) => Err(e),
+                },
+                Err(_) => Ok(None),
+            },
+            snapshots::Kind::TimestampDir(ts) => match snapshots::parse_file_entry_name(name) {
+                Some(real_ino) => match self.versions.versions_at(ts) {
+                    Ok(versions) => match versions.into_iter().find(|v| v.ino == real_ino) {
+                        Some(fv) => Ok(Some(self.snapshot_file_inode(real_ino, fv.version, &fv))),
+                        None => Ok(None),
+                    },
+                    Err(e) => Err(e),
+                },
+                None => Ok(None),
+            },
+            // Historical file entries are leaves, never directories.
+            snapshots::Kind::File { .. } => Ok(None),
+        };
+        Some(result)
+    }
+
+    /// Resolve a `getattr` under the synthetic `.snapshots` tree, `None` if
+    /// `ino` isn't part of it.
+    #[cfg(feature = "mount")]
+    fn snapshot_getattr(&self, ino: u64) -> Option<Result<Option<Inode>>> {
+        let kind = snapshots::decode(ino)?;
+        let result = match kind {
+            snapshots::Kind::Root => Ok(Some(self.snapshot_root_inode())),
+            snapshots::Kind::TimestampDir(ts) => match self.versions.versions_at(ts) {
+                Ok(v) if !v.is_empty() => Ok(Some(self.snapshot_timestamp_inode(ts))),
+                Ok(_) => Ok(None),
+                Err(e) => Err(e),
+            },
+            snapshots::Kind::File { real_ino, version } => match self.versions.get(real_ino, version) {
+                Ok(Some(fv)) => Ok(Some(self.snapshot_file_inode(real_ino, version, &fv))),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            },
+        };
+        Some(result)
+    }
+
+    /// List the entries of a synthetic `.snapshots` directory. Only
+    /// superseded file content is tracked, so `.snapshots/<timestamp>`
+    /// trees are flat: one entry per retained file version, never nested
+    /// directories.
+    #[cfg(feature = "mount")]
+    fn snapshot_readdir(&self, ino: u64) -> Result<Option<Vec<(u64, FuserFileType, String)>>> {
+        let kind = match snapshots::decode(ino) {
+            Some(k) => k,
+            None => return Ok(None),
+        };
+
+        match kind {
+            snapshots::Kind::Root => {
+                let mut entries = vec![
+                    (ino, FuserFileType::Directory, ".".to_string()),
+                    (1, FuserFileType::Directory, "..".to_string()),
+                ];
+                for ts in self.versions.all_timestamps()? {
+                    entries.push((snapshots::timestamp_dir_ino(ts), FuserFileType::Directory, ts.to_string()));
+                }
+                Ok(Some(entries))
+            }
+            snapshots::Kind::TimestampDir(ts) => {
+                let versions = self.versions.versions_at(ts)?;
+                if versions.is_empty() {
+                    return Ok(None);
+                }
+
+                let mut entries = vec![
+                    (ino, FuserFileType::Directory, ".".to_string()),
+                    (snapshots::ROOT_INO, FuserFileType::Directory, "..".to_string()),
+                ];
+                for fv in &versions {
+                    let name = match self.metadata.get_inode(fv.ino)? {
+                        Some(live) => live.name,
+                        None => "deleted".to_string(),
+                    };
+                    entries.push((
+                        snapshots::file_ino(fv.ino, fv.version),
+                        FuserFileType::RegularFile,
+                        snapshots::file_entry_name(&name, fv.ino),
+                    ));
+                }
+                Ok(Some(entries))
+            }
+            snapshots::Kind::File { .. } => Err(Error::NotADirectory(ino.to_string())),
+        }
+    }
+
+    /// Read file data out of a historical manifest under `.snapshots`.
+    #[cfg(feature = "mount")]
+    fn snapshot_read(&self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>> {
+        match snapshots::decode(ino) {
+            Some(snapshots::Kind::File { real_ino, version }) => {
+                let fv = self
+                    .versions
+                    .get(real_ino, version)?
+                    .ok_or(Error::VersionNotFound(version))?;
+                self.read_manifest_data(&fv.manifest, offset, size)
+            }
+            _ => Err(Error::NotAFile(ino.to_string())),
+        }
+    }
+
     /// Get chunk data (from cache or Telegram)
     fn get_chunk_data(&self, chunk_ref: &ChunkRef) -> Result<Vec<u8>> {
+        self.block_on(self.get_chunk_data_async(chunk_ref))
+    }
+
+    /// Async counterpart of [`Self::get_chunk_data`], used directly by
+    /// [`Self::fetch_chunks_concurrent`] so concurrent fetches don't each
+    /// need their own `block_on`.
+    async fn get_chunk_data_async(&self, chunk_ref: &ChunkRef) -> Result<Vec<u8>> {
         // Try cache first
         if let Some(data) = self.cache.get(&chunk_ref.id)? {
             return Ok(data);
         }
 
-        // Download from Telegram
-        let encrypted_bytes = self.block_on(self.telegram.download_chunk(chunk_ref.message_id))?;
+        // Fetch encrypted bytes: inline chunks carry them directly, remote
+        // chunks must be downloaded from Telegram.
+        let encrypted_bytes = match &chunk_ref.payload {
+            ChunkPayload::Inline { data } => data.clone(),
+            ChunkPayload::Remote { message_id } => {
+                self.telegram.download_chunk(*message_id).await?
+            }
+        };
 
         // Decrypt
         let chunk_key = self.keys.chunk_key(&chunk_ref.id)?;
         let encrypted = crate::crypto::EncryptedData::from_bytes(&encrypted_bytes)?;
-        let decrypted = decrypt(chunk_key.key(), &encrypted, &[])?;
+        let decrypted = decrypt(chunk_key.key(), &encrypted, chunk_ref.id.as_bytes())?;
 
-        // Decompress if needed
-        let data = if chunk_ref.compressed {
-            decompress(&decrypted)?
-        } else {
-            decrypted
-        };
+        // Decompress (verifying the integrity checksum) per the chunk's algorithm
+        let data = decompress(&decrypted, chunk_ref.compression)?;
 
         // Cache for later
         self.cache.put(&chunk_ref.id, &data)?;
@@ -153,58 +594,166 @@ impl TgCryptFs {
         Ok(data)
     }
 
-    /// Write file data (simplified - full implementation would handle partial writes)
-    fn write_file_data(&self, ino: u64, data: &[u8]) -> Result<()> {
+    /// Encrypt, dedup-check, and (if needed) upload one freshly content-defined
+    /// chunk, returning the `ChunkRef` to splice into a manifest.
+    fn store_chunk(&self, chunk: &crate::chunk::Chunk, manifest_offset: u64) -> Result<ChunkRef> {
+        let (chunk_data, compression) = if self.config.chunk.compression_enabled
+            && chunk.data.len() >= self.config.chunk.compression_threshold
+        {
+            compress(&chunk.data, self.config.chunk.compression_level)?
+        } else {
+            compress_or_original(&chunk.data)?
+        };
+
+        // Content-address by the plaintext under a keyed hash (see
+        // `KeyManager::content_chunk_id`) rather than trusting
+        // `chunk.info.id` as-is: two stores with different keys never
+        // collide on the same id even over byte-identical plaintext, so
+        // the id itself can't be used to detect a dedup hit without the key.
+        let chunk_id = ChunkId::from(self.keys.content_chunk_id(&chunk.data)?);
+
+        let chunk_key = self.keys.chunk_key(&chunk_id)?;
+        let encrypted = encrypt(self.config.encryption.algorithm, chunk_key.key(), &chunk_data, chunk_id.as_bytes())?;
+
+        // Dedup: if this content-addressed chunk is already stored, just add
+        // a reference instead of uploading it again.
+        let message_id = if let Some(msg_id) = self.metadata.get_chunk_ref(&chunk_id)? {
+            self.metadata.save_chunk_ref(&chunk_id, msg_id)?;
+            msg_id
+        } else {
+            let msg_id = self.block_on(self.telegram.upload_chunk(&chunk_id, &encrypted.to_bytes()))?;
+            self.metadata.save_chunk_ref(&chunk_id, msg_id)?;
+            msg_id
+        };
+
+        self.cache.put(&chunk_id, &chunk.data)?;
+
+        Ok(ChunkRef {
+            id: chunk_id,
+            size: encrypted.size() as u64,
+            payload: ChunkPayload::Remote { message_id },
+            offset: manifest_offset,
+            original_size: chunk.data.len() as u64,
+            compression,
+        })
+    }
+
+    /// Drop a replaced chunk's reference, deleting it from Telegram (and
+    /// evicting it from the local cache) once nothing else points to it.
+    fn release_chunk_ref(&self, chunk_ref: &ChunkRef) -> Result<()> {
+        if let Some(msg_id) = self.metadata.decrement_chunk_ref(&chunk_ref.id)? {
+            let _ = self.block_on(self.telegram.delete_message(msg_id));
+            let _ = self.cache.remove(&chunk_ref.id);
+        }
+        Ok(())
+    }
+
+    /// Apply a copy-on-write partial write covering `[offset, offset + data.len())`.
+    ///
+    /// Rather than re-chunking and re-encrypting the whole file (catastrophic
+    /// for large files with one changed byte), this only reconstructs the
+    /// window of the file that the write actually touches - extended out to
+    /// the chunk boundaries on either side so the content-defined chunker can
+    /// realign its cut points - re-chunks just that window, and splices the
+    /// resulting `ChunkRef`s in place of the old ones. Chunk refs outside the
+    /// window are left untouched and never re-uploaded; `store_chunk`'s dedup
+    /// check means even chunks the chunker re-cuts identically upload nothing.
+    /// A write past EOF sparse-zero-fills the gap.
+    fn apply_write(&self, ino: u64, offset: u64, data: &[u8]) -> Result<()> {
         let mut inode = self.metadata.get_inode_required(ino)?;
 
-        // Create chunks
-        let chunks = self.chunker.chunk_data(data);
-        let file_hash = self.chunker.file_hash(data);
+        let old_chunks: Vec<ChunkRef> =
+            inode.manifest.as_ref().map(|m| m.chunks.clone()).unwrap_or_default();
+        let old_size = inode.manifest.as_ref().map(|m| m.total_size).unwrap_or(0);
+        let write_end = offset + data.len() as u64;
+        let new_size = write_end.max(old_size);
+
+        // Locate the run of existing chunks whose byte range overlaps the
+        // write, so only that run gets reconstructed and replaced.
+        let mut overlap: Option<(usize, usize)> = None;
+        for (i, cr) in old_chunks.iter().enumerate() {
+            if cr.offset + cr.original_size > offset && cr.offset < write_end {
+                overlap = Some(match overlap {
+                    Some((first, _)) => (first, i),
+                    None => (i, i),
+                });
+            }
+        }
 
-        // Create new manifest
-        let mut manifest = ChunkManifest::new(inode.version + 1);
-        manifest.total_size = data.len() as u64;
-        manifest.file_hash = file_hash;
-
-        // Upload each chunk
-        for chunk in chunks {
-            // Compress if beneficial
-            let (chunk_data, compressed) =
-                compress_or_original(&chunk.data, self.config.chunk.compression_threshold);
-
-            // Encrypt
-            let chunk_key = self.keys.chunk_key(&chunk.info.id)?;
-            let encrypted = encrypt(chunk_key.key(), &chunk_data, &[])?;
-
-            // Check if chunk already exists (dedup)
-            let message_id = if let Some(msg_id) = self.metadata.get_chunk_ref(&chunk.info.id)? {
-                // Chunk already exists, just add reference
-                self.metadata.save_chunk_ref(&chunk.info.id, msg_id)?;
-                msg_id
-            } else {
-                // Upload new chunk
-                let msg_id = self.block_on(self.telegram.upload_chunk(&chunk.info.id, &encrypted.to_bytes()))?;
-                self.metadata.save_chunk_ref(&chunk.info.id, msg_id)?;
-                msg_id
-            };
-
-            // Add to manifest
-            manifest.chunks.push(ChunkRef {
-                id: chunk.info.id,
-                size: encrypted.size() as u64,
-                message_id,
-                offset: chunk.info.offset,
-                original_size: chunk.data.len() as u64,
-                compressed,
-            });
+        let window_start = overlap.map(|(first, _)| old_chunks[first].offset).unwrap_or(offset);
+        let window_end = overlap
+            .map(|(_, last)| (old_chunks[last].offset + old_chunks[last].original_size).max(write_end))
+            .unwrap_or(write_end);
+
+        // Reconstruct the window's current bytes from the overlapping chunks
+        // (downloading/decrypting only those, not the whole file); any gap
+        // past the old EOF stays zero-filled (sparse write).
+        let mut window = vec![0u8; (window_end - window_start) as usize];
+        if let Some((first, last)) = overlap {
+            for cr in &old_chunks[first..=last] {
+                let chunk_data = self.get_chunk_data(cr)?;
+                let rel = (cr.offset - window_start) as usize;
+                let len = chunk_data.len().min(window.len().saturating_sub(rel));
+                window[rel..rel + len].copy_from_slice(&chunk_data[..len]);
+            }
+        }
 
-            // Cache the uncompressed data
-            self.cache.put(&manifest.chunks.last().unwrap().id, &chunk.data)?;
+        // Splice the new bytes into the reconstructed window.
+        let splice_start = (offset - window_start) as usize;
+        let splice_end = splice_start + data.len();
+        if splice_end > window.len() {
+            window.resize(splice_end, 0);
+        }
+        window[splice_start..splice_end].copy_from_slice(data);
+
+        // Re-run content-defined chunking over just the spliced window and
+        // store each resulting chunk (deduping unchanged tails for free).
+        let mut new_refs = Vec::new();
+        for chunk in self.chunker.chunk_data(&window) {
+            new_refs.push(self.store_chunk(&chunk, window_start + chunk.info.offset)?);
+        }
+
+        // Splice the manifest: untouched prefix, the replaced window, untouched suffix.
+        let (prefix, replaced, suffix) = match overlap {
+            Some((first, last)) => (
+                old_chunks[..first].to_vec(),
+                old_chunks[first..=last].to_vec(),
+                old_chunks[last + 1..].to_vec(),
+            ),
+            None => (old_chunks.clone(), Vec::new(), Vec::new()),
+        };
+
+        for cr in &replaced {
+            self.release_chunk_ref(cr)?;
+        }
+
+        let mut chunks = prefix;
+        chunks.extend(new_refs);
+        chunks.extend(suffix);
+
+        // Re-derive the whole-file hash from the final chunk list. Every
+        // chunk here is either untouched (decrypts straight from cache) or
+        // one we just stored (and therefore just cached), so this reads
+        // plaintext locally rather than repeating the expensive part of a
+        // full rewrite - re-encrypting and re-uploading every chunk.
+        let mut hasher = blake3::Hasher::new();
+        for cr in &chunks {
+            hasher.update(&self.get_chunk_data(cr)?);
+        }
+
+        let mut manifest = ChunkManifest::new(inode.version + 1);
+        manifest.total_size = new_size;
+        manifest.file_hash = hasher.finalize().to_hex().to_string();
+        manifest.chunks = chunks;
+
+        // Retain the manifest this write supersedes so it stays browsable
+        // under `.snapshots/<timestamp>` even after it's replaced below.
+        if let Some(old_manifest) = &inode.manifest {
+            self.versions.record(ino, old_manifest, SystemTime::now())?;
         }
 
-        // Update inode
         inode.manifest = Some(manifest);
-        inode.set_size(data.len() as u64);
+        inode.set_size(new_size);
         inode.bump_version();
         self.metadata.save_inode(&inode)?;
 
@@ -258,6 +807,68 @@ impl TgCryptFs {
         Ok(inode)
     }
 
+    /// Create a new symlink, storing the target inline on the inode rather
+    /// than as a chunk manifest - symlink targets are path-sized, nowhere
+    /// near worth chunking/uploading.
+    fn create_symlink(&self, parent: u64, name: &str, target: &str) -> Result<Inode> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        if !parent_inode.is_dir() {
+            return Err(Error::NotADirectory(parent_inode.name.clone()));
+        }
+
+        if self.metadata.lookup(parent, name)?.is_some() {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        let ino = self.metadata.alloc_ino();
+        let inode = Inode::new_symlink(ino, parent, name.to_string(), self.uid, self.gid, target.to_string());
+
+        self.metadata.save_inode(&inode)?;
+        parent_inode.add_child(ino);
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(inode)
+    }
+
+    /// Create a device node or FIFO. `mode`'s `S_IFMT` bits pick the file
+    /// type; `rdev` is only meaningful (and only persisted) for char/block
+    /// devices.
+    fn create_special(&self, parent: u64, name: &str, mode: u32, rdev: u32) -> Result<Inode> {
+        let mut parent_inode = self.metadata.get_inode_required(parent)?;
+        if !parent_inode.is_dir() {
+            return Err(Error::NotADirectory(parent_inode.name.clone()));
+        }
+
+        if self.metadata.lookup(parent, name)?.is_some() {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        let file_type = match mode & libc::S_IFMT {
+            libc::S_IFIFO => FileType::Fifo,
+            libc::S_IFCHR => FileType::CharDevice,
+            libc::S_IFBLK => FileType::BlockDevice,
+            _ => return Err(Error::InvalidConfig(format!("unsupported mknod mode {:o}", mode))),
+        };
+
+        let ino = self.metadata.alloc_ino();
+        let inode = Inode::new_special(
+            ino,
+            parent,
+            name.to_string(),
+            self.uid,
+            self.gid,
+            mode as u16,
+            file_type,
+            rdev,
+        );
+
+        self.metadata.save_inode(&inode)?;
+        parent_inode.add_child(ino);
+        self.metadata.save_inode(&parent_inode)?;
+
+        Ok(inode)
+    }
+
     /// Remove a file
     fn remove_file(&self, parent: u64, name: &str) -> Result<()> {
         let mut parent_inode = self.metadata.get_inode_required(parent)?;
@@ -283,6 +894,7 @@ impl TgCryptFs {
 
         // Delete inode
         self.metadata.delete_inode(inode.ino)?;
+        self.xattrs.remove_all(inode.ino)?;
 
         // Update parent
         parent_inode.remove_child(inode.ino);
@@ -308,14 +920,36 @@ impl TgCryptFs {
         }
 
         self.metadata.delete_inode(inode.ino)?;
+        self.xattrs.remove_all(inode.ino)?;
         parent_inode.remove_child(inode.ino);
         parent_inode.attrs.nlink -= 1;
         self.metadata.save_inode(&parent_inode)?;
 
         Ok(())
     }
+
+    /// Build the [`XattrContext`] for a `setxattr`/`removexattr` call on
+    /// `ino`. FUSE requests don't carry real capabilities, so
+    /// `CAP_SYS_ADMIN` is approximated as the caller being root.
+    #[cfg(feature = "mount")]
+    fn xattr_context(&self, req: &Request, ino: u64) -> Result<XattrContext> {
+        let inode = self.metadata.get_inode_required(ino)?;
+        Ok(XattrContext {
+            uid: req.uid(),
+            has_cap_sys_admin: req.uid() == 0,
+            is_regular_or_dir: inode.is_file() || inode.is_dir(),
+        })
+    }
+
+    /// Consume this mount and hand back its encryption/storage/cache stack
+    /// as a stable [`EncryptedFilesystem`], for frontends that want to
+    /// drive tgcryptfs without linking against FUSE at all.
+    pub fn into_encrypted_filesystem(self) -> impl EncryptedFilesystem {
+        EncryptedFs::new(self.config, self.keys, self.metadata, self.telegram, self.cache, self.uid, self.gid)
+    }
 }
 
+#[cfg(feature = "mount")]
 impl Filesystem for TgCryptFs {
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         let name = match name.to_str() {
@@ -328,6 +962,32 @@ impl Filesystem for TgCryptFs {
 
         debug!("lookup: parent={}, name={}", parent, name);
 
+        if let Some(result) = self.snapshot_lookup(parent, name) {
+            match result {
+                Ok(Some(inode)) => reply.entry(&TTL, &inode.attrs.to_fuser(inode.ino), 0),
+                Ok(None) => reply.error(libc::ENOENT),
+                Err(e) => reply.error(e.to_errno()),
+            }
+            return;
+        }
+
+        if self.acl.is_some() {
+            match self.inode_virtual_path(parent) {
+                Ok(parent_path) => {
+                    let path = join_virtual_path(&parent_path, name);
+                    if !self.check_permission(&path, PermissionType::Read) {
+                        reply.error(libc::EACCES);
+                        return;
+                    }
+                }
+                Err(e) => {
+                    error!("lookup ACL path resolution error: {}", e);
+                    reply.error(e.to_errno());
+                    return;
+                }
+            }
+        }
+
         match self.metadata.lookup(parent, name) {
             Ok(Some(inode)) => {
                 reply.entry(&TTL, &inode.attrs.to_fuser(inode.ino), 0);
@@ -345,6 +1005,15 @@ impl Filesystem for TgCryptFs {
     fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
         debug!("getattr: ino={}", ino);
 
+        if let Some(result) = self.snapshot_getattr(ino) {
+            match result {
+                Ok(Some(inode)) => reply.attr(&TTL, &inode.attrs.to_fuser(ino)),
+                Ok(None) => reply.error(libc::ENOENT),
+                Err(e) => reply.error(e.to_errno()),
+            }
+            return;
+        }
+
         match self.metadata.get_inode(ino) {
             Ok(Some(inode)) => {
                 reply.attr(&TTL, &inode.attrs.to_fuser(ino));
@@ -379,6 +1048,11 @@ impl Filesystem for TgCryptFs {
     ) {
         debug!("setattr: ino={}", ino);
 
+        if snapshots::is_synthetic(ino) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         match self.metadata.get_inode(ino) {
             Ok(Some(mut inode)) => {
                 if let Some(m) = mode {
@@ -432,6 +1106,22 @@ impl Filesystem for TgCryptFs {
     ) {
         debug!("readdir: ino={}, offset={}", ino, offset);
 
+        if snapshots::is_synthetic(ino) {
+            match self.snapshot_readdir(ino) {
+                Ok(Some(entries)) => {
+                    for (i, (ino, kind, name)) in entries.iter().enumerate().skip(offset as usize) {
+                        if reply.add(*ino, (i + 1) as i64, *kind, name) {
+                            break;
+                        }
+                    }
+                    reply.ok();
+                }
+                Ok(None) => reply.error(libc::ENOENT),
+                Err(e) => reply.error(e.to_errno()),
+            }
+            return;
+        }
+
         let inode = match self.metadata.get_inode(ino) {
             Ok(Some(i)) => i,
             Ok(None) => {
@@ -467,6 +1157,12 @@ impl Filesystem for TgCryptFs {
             }
         }
 
+        // The `.snapshots` tree is synthetic, not a real child, so splice
+        // it into root's listing here rather than storing it in MetadataStore.
+        if ino == 1 {
+            entries.push((snapshots::ROOT_INO, FuserFileType::Directory, snapshots::DIR_NAME.to_string()));
+        }
+
         for (i, (ino, kind, name)) in entries.iter().enumerate().skip(offset as usize) {
             if reply.add(*ino, (i + 1) as i64, *kind, name) {
                 break;
@@ -479,6 +1175,22 @@ impl Filesystem for TgCryptFs {
     fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
         debug!("open: ino={}, flags={}", ino, flags);
 
+        if snapshots::is_synthetic(ino) {
+            match snapshots::decode(ino) {
+                Some(snapshots::Kind::File { .. }) => {
+                    if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                        reply.error(libc::EROFS);
+                        return;
+                    }
+                    let fh = self.handles.open(ino, flags);
+                    reply.opened(fh, 0);
+                }
+                Some(_) => reply.error(libc::EISDIR),
+                None => reply.error(libc::ENOENT),
+            }
+            return;
+        }
+
         match self.metadata.get_inode(ino) {
             Ok(Some(inode)) => {
                 if inode.is_dir() {
@@ -506,6 +1218,17 @@ impl Filesystem for TgCryptFs {
     ) {
         debug!("read: ino={}, offset={}, size={}", ino, offset, size);
 
+        if snapshots::is_synthetic(ino) {
+            match self.snapshot_read(ino, offset as u64, size) {
+                Ok(data) => reply.data(&data),
+                Err(e) => {
+                    error!("snapshot read error: {}", e);
+                    reply.error(e.to_errno());
+                }
+            }
+            return;
+        }
+
         let inode = match self.metadata.get_inode(ino) {
             Ok(Some(i)) => i,
             Ok(None) => {
@@ -518,6 +1241,21 @@ impl Filesystem for TgCryptFs {
             }
         };
 
+        if self.acl.is_some() {
+            match self.inode_virtual_path(ino) {
+                Ok(path) if !self.check_permission(&path, PermissionType::Read) => {
+                    reply.error(libc::EACCES);
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("read ACL path resolution error: {}", e);
+                    reply.error(e.to_errno());
+                    return;
+                }
+            }
+        }
+
         match self.read_file_data(&inode, offset as u64, size) {
             Ok(data) => reply.data(&data),
             Err(e) => {
@@ -541,10 +1279,31 @@ impl Filesystem for TgCryptFs {
     ) {
         debug!("write: ino={}, offset={}, size={}", ino, offset, data.len());
 
-        // For simplicity, we buffer writes and flush on release
-        // A full implementation would handle partial writes and offsets
+        if snapshots::is_synthetic(ino) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        if self.acl.is_some() {
+            match self.inode_virtual_path(ino) {
+                Ok(path) if !self.check_permission(&path, PermissionType::Write) => {
+                    reply.error(libc::EACCES);
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("write ACL path resolution error: {}", e);
+                    reply.error(e.to_errno());
+                    return;
+                }
+            }
+        }
+
+        // Stage the write in the handle, which coalesces it with any other
+        // dirty range from this open() rather than flushing per-call; the
+        // actual copy-on-write splice happens once, on release/fsync.
         self.handles.with_handle_mut(fh, |handle| {
-            handle.write(data);
+            handle.write_at(offset as u64, data);
         });
 
         reply.written(data.len() as u32);
@@ -563,9 +1322,9 @@ impl Filesystem for TgCryptFs {
         debug!("release: ino={}, fh={}", ino, fh);
 
         if let Some(handle) = self.handles.close(fh) {
-            if handle.is_dirty() {
+            if let Some((start, _end)) = handle.dirty_range() {
                 let data = handle.get_write_buffer();
-                if let Err(e) = self.write_file_data(ino, &data) {
+                if let Err(e) = self.apply_write(ino, start, &data) {
                     error!("Failed to flush write buffer: {}", e);
                     reply.error(e.to_errno());
                     return;
@@ -576,6 +1335,98 @@ impl Filesystem for TgCryptFs {
         reply.ok();
     }
 
+    fn mknod(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        _umask: u32,
+        rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+
+        debug!("mknod: parent={}, name={}, mode={:o}, rdev={}", parent, name, mode, rdev);
+
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        match self.create_special(parent, name, mode, rdev) {
+            Ok(inode) => {
+                reply.entry(&TTL, &inode.attrs.to_fuser(inode.ino), 0);
+            }
+            Err(e) => {
+                error!("mknod error: {}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        link: &std::path::Path,
+        reply: ReplyEntry,
+    ) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+        let target = match link.to_str() {
+            Some(t) => t,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+
+        debug!("symlink: parent={}, name={}, target={}", parent, name, target);
+
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        match self.create_symlink(parent, name, target) {
+            Ok(inode) => {
+                reply.entry(&TTL, &inode.attrs.to_fuser(inode.ino), 0);
+            }
+            Err(e) => {
+                error!("symlink error: {}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        debug!("readlink: ino={}", ino);
+
+        match self.metadata.get_inode_required(ino) {
+            Ok(inode) => match &inode.symlink_target {
+                Some(target) => reply.data(target.as_bytes()),
+                None => reply.error(libc::EINVAL),
+            },
+            Err(e) => {
+                error!("readlink error: {}", e);
+                reply.error(e.to_errno());
+            }
+        }
+    }
+
     fn create(
         &mut self,
         _req: &Request,
@@ -596,6 +1447,11 @@ impl Filesystem for TgCryptFs {
 
         debug!("create: parent={}, name={}, mode={:o}", parent, name, mode);
 
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         match self.create_file(parent, name, mode) {
             Ok(inode) => {
                 let fh = self.handles.open(inode.ino, flags);
@@ -627,6 +1483,11 @@ impl Filesystem for TgCryptFs {
 
         debug!("mkdir: parent={}, name={}, mode={:o}", parent, name, mode);
 
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         match self.create_directory(parent, name, mode) {
             Ok(inode) => {
                 reply.entry(&TTL, &inode.attrs.to_fuser(inode.ino), 0);
@@ -649,6 +1510,11 @@ impl Filesystem for TgCryptFs {
 
         debug!("unlink: parent={}, name={}", parent, name);
 
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         match self.remove_file(parent, name) {
             Ok(_) => reply.ok(),
             Err(e) => {
@@ -669,6 +1535,11 @@ impl Filesystem for TgCryptFs {
 
         debug!("rmdir: parent={}, name={}", parent, name);
 
+        if snapshots::is_synthetic(parent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         match self.remove_directory(parent, name) {
             Ok(_) => reply.ok(),
             Err(e) => {
@@ -708,6 +1579,11 @@ impl Filesystem for TgCryptFs {
             parent, name, newparent, newname
         );
 
+        if snapshots::is_synthetic(parent) || snapshots::is_synthetic(newparent) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
         // Get source inode
         let mut inode = match self.metadata.lookup(parent, name) {
             Ok(Some(i)) => i,
@@ -764,6 +1640,129 @@ impl Filesystem for TgCryptFs {
         }
     }
 
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+
+        debug!("getxattr: ino={}, name={}", ino, name);
+
+        let value = match self.xattrs.get(ino, name) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                reply.error(Error::XattrNotFound(name.to_string()).to_errno());
+                return;
+            }
+            Err(e) => {
+                reply.error(e.to_errno());
+                return;
+            }
+        };
+
+        if size == 0 {
+            reply.size(value.len() as u32);
+        } else if (size as usize) < value.len() {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(&value);
+        }
+    }
+
+    fn setxattr(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+
+        debug!("setxattr: ino={}, name={}, len={}", ino, name, value.len());
+
+        if snapshots::is_synthetic(ino) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        let ctx = match self.xattr_context(req, ino) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                reply.error(e.to_errno());
+                return;
+            }
+        };
+
+        match self.xattrs.set(ino, name, value, &ctx, InodeFlags::default()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.to_errno()),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        debug!("listxattr: ino={}", ino);
+
+        match self.xattrs.list_buffer(ino, size as usize) {
+            Ok(result) if size == 0 => reply.size(result.total_len() as u32),
+            Ok(result) => reply.data(result.as_bytes()),
+            Err(e) => reply.error(e.to_errno()),
+        }
+    }
+
+    fn removexattr(&mut self, req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(libc::EINVAL);
+                return;
+            }
+        };
+
+        debug!("removexattr: ino={}, name={}", ino, name);
+
+        if snapshots::is_synthetic(ino) {
+            reply.error(libc::EROFS);
+            return;
+        }
+
+        match self.xattrs.get(ino, name) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                reply.error(Error::XattrNotFound(name.to_string()).to_errno());
+                return;
+            }
+            Err(e) => {
+                reply.error(e.to_errno());
+                return;
+            }
+        }
+
+        let ctx = match self.xattr_context(req, ino) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                reply.error(e.to_errno());
+                return;
+            }
+        };
+
+        match self.xattrs.remove(ino, name, &ctx, InodeFlags::default()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.to_errno()),
+        }
+    }
+
     fn statfs(&mut self, _req: &Request, _ino: u64, reply: fuser::ReplyStatfs) {
         // Return some reasonable values
         // In a full implementation, you'd track actual usage
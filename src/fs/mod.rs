@@ -1,10 +1,15 @@
-//! FUSE filesystem implementation
+//! Filesystem implementation
 //!
-//! Implements the FUSE filesystem interface, translating
-//! filesystem operations to our encrypted cloud backend.
+//! [`TgCryptFs`] implements the FUSE filesystem interface (behind the
+//! `mount` feature), translating filesystem operations to our encrypted
+//! cloud backend. [`EncryptedFilesystem`] exposes the same operations as a
+//! stable async trait for frontends that don't want a FUSE dependency at
+//! all - see [`TgCryptFs::into_encrypted_filesystem`].
 
+mod encrypted_filesystem;
 mod filesystem;
 mod handle;
 
+pub use encrypted_filesystem::{DirEntry, EncryptedFilesystem, EncryptedFs, SetAttrRequest};
 pub use filesystem::TgCryptFs;
 pub use handle::FileHandle;
@@ -12,8 +12,14 @@ pub struct OverlayConfig {
     /// Path to the upper (writable) layer
     pub upper_path: PathBuf,
 
-    /// Path to whiteout database
-    pub whiteout_db_path: PathBuf,
+    /// Which xattr marks a directory opaque - see [`OpaqueXattr`].
+    pub opaque_xattr: OpaqueXattr,
+
+    /// Path to the persisted inode table - see
+    /// [`super::inode_table::InodeTable`]. Lives outside `upper_path`
+    /// itself so it never shows up as a file in the merged directory
+    /// listing.
+    pub inode_table_path: PathBuf,
 
     /// Behavior when file exists in both layers
     pub conflict_behavior: ConflictBehavior,
@@ -43,6 +49,31 @@ pub enum ConflictBehavior {
     MergeDirectories,
 }
 
+/// Extended attribute an opaque directory carries on itself, so the upper
+/// directory can also be mounted by an overlay implementation other than
+/// this one. `fuse-overlayfs` looks for `user.fuseoverlayfs.opaque`; the
+/// in-kernel `overlay` driver looks for `trusted.overlay.opaque` instead,
+/// which requires `CAP_SYS_ADMIN` to set - pick whichever this upper
+/// directory needs to interoperate with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum OpaqueXattr {
+    /// `user.fuseoverlayfs.opaque` (writable by an unprivileged user)
+    #[default]
+    FuseOverlayfs,
+    /// `trusted.overlay.opaque` (what the kernel's `overlay` driver reads)
+    Kernel,
+}
+
+impl OpaqueXattr {
+    /// The xattr name to set on an opaque directory.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpaqueXattr::FuseOverlayfs => "user.fuseoverlayfs.opaque",
+            OpaqueXattr::Kernel => "trusted.overlay.opaque",
+        }
+    }
+}
+
 impl Default for OverlayConfig {
     fn default() -> Self {
         let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
@@ -53,7 +84,8 @@ impl Default for OverlayConfig {
         OverlayConfig {
             lower_path: home,
             upper_path: data_dir.join("overlay_upper"),
-            whiteout_db_path: data_dir.join("overlay_whiteout.db"),
+            opaque_xattr: OpaqueXattr::default(),
+            inode_table_path: data_dir.join("overlay_inodes.zst"),
             conflict_behavior: ConflictBehavior::UpperWins,
             follow_symlinks: true,
             exclude_patterns: vec![
@@ -0,0 +1,353 @@
+//! Fast copy-up: reflink, then `copy_file_range(2)`, then a plain loop
+//!
+//! [`OverlayFs::copy_up`](super::filesystem::OverlayFs::copy_up) used to
+//! materialize a lower file into the upper layer with `std::fs::copy`, a
+//! userspace byte-for-byte copy - slow, and wasteful of disk space when
+//! both layers happen to sit on the same copy-on-write filesystem.
+//! [`copy_file_fast`] instead tries, in order: a `FICLONE` reflink (an
+//! instant CoW clone sharing blocks with the source, on btrfs/XFS), then
+//! `copy_file_range(2)` (an in-kernel copy that skips the userspace
+//! bounce buffer `std::fs::copy` uses), and only falls back to a
+//! read/write loop if both are unavailable - e.g. the two layers are on
+//! different filesystems or a filesystem that doesn't support either.
+//! Whichever path copies the bytes, mode, ownership, timestamps and
+//! extended attributes are applied afterward so the upper file is
+//! metadata-identical to the lower one.
+
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use tracing::warn;
+
+/// `_IOW(0x94, 9, int)` - the `FICLONE` reflink ioctl. Not exposed by the
+/// `libc` crate, so the request code is spelled out the way the kernel
+/// header (`<linux/fs.h>`) computes it.
+#[cfg(target_os = "linux")]
+const FICLONE: libc::c_ulong = 0x4004_9409;
+
+/// Copy `src`'s full contents and metadata to `dst`, trying the fastest
+/// method the underlying filesystems support.
+pub fn copy_file_fast(src: &Path, dst: &Path) -> io::Result<()> {
+    let meta = fs::metadata(src)?;
+
+    let src_file = File::open(src)?;
+    let dst_file =
+        fs::OpenOptions::new().create(true).write(true).truncate(true).mode(meta.mode()).open(dst)?;
+
+    if !try_reflink(&src_file, &dst_file) {
+        copy_contents(&src_file, &dst_file, meta.len())?;
+    }
+
+    drop(src_file);
+    drop(dst_file);
+
+    copy_metadata(src, dst, &meta)?;
+    Ok(())
+}
+
+/// Attempt a `FICLONE` reflink. Returns `true` on success; any failure
+/// (cross-filesystem, unsupported filesystem, etc.) is left for
+/// `copy_contents` to handle with a slower method instead.
+#[cfg(target_os = "linux")]
+fn try_reflink(src: &File, dst: &File) -> bool {
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), FICLONE, src.as_raw_fd()) };
+    ret == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn try_reflink(_src: &File, _dst: &File) -> bool {
+    false
+}
+
+/// Copy `len` bytes from `src` to `dst`, via `copy_file_range(2)` if the
+/// platform has it, falling back to a plain read/write loop otherwise.
+fn copy_contents(src: &File, dst: &File, len: u64) -> io::Result<()> {
+    if copy_file_range_loop(src, dst, len).is_ok() {
+        return Ok(());
+    }
+    reset_for_retry(src, dst)?;
+    io::copy(&mut &*src, &mut &*dst)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn copy_file_range_loop(src: &File, dst: &File, len: u64) -> io::Result<()> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                std::ptr::null_mut(),
+                dst.as_raw_fd(),
+                std::ptr::null_mut(),
+                remaining as usize,
+                0,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if n == 0 {
+            // Source exhausted early (shouldn't happen given `len` came
+            // from its own metadata, but don't loop forever if it does).
+            break;
+        }
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file_range_loop(_src: &File, _dst: &File, _len: u64) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+/// Rewind both descriptors and truncate `dst` before falling back to a
+/// different copy method, in case the previous attempt copied a partial
+/// chunk before failing.
+fn reset_for_retry(src: &File, dst: &File) -> io::Result<()> {
+    unsafe {
+        if libc::lseek(src.as_raw_fd(), 0, libc::SEEK_SET) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::lseek(dst.as_raw_fd(), 0, libc::SEEK_SET) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    dst.set_len(0)
+}
+
+fn copy_metadata(src: &Path, dst: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    fs::set_permissions(dst, meta.permissions())?;
+    chown(dst, meta.uid(), meta.gid())?;
+    copy_timestamps(dst, meta)?;
+    copy_xattrs(src, dst);
+    Ok(())
+}
+
+fn chown(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let ret = unsafe { libc::chown(c_path.as_ptr(), uid, gid) };
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        // An unprivileged copy-up can't chown to an arbitrary owner -
+        // expected when we're not running as root, not a reason to fail
+        // the whole copy-up.
+        if err.raw_os_error() == Some(libc::EPERM) {
+            return Ok(());
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn copy_timestamps(path: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let times = [
+        libc::timespec { tv_sec: meta.atime() as _, tv_nsec: meta.atime_nsec() as _ },
+        libc::timespec { tv_sec: meta.mtime() as _, tv_nsec: meta.mtime_nsec() as _ },
+    ];
+    let ret = unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), 0) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Best-effort: copy every extended attribute from `src` to `dst`,
+/// logging (rather than failing the copy-up over) any one that can't be
+/// read or re-applied.
+fn copy_xattrs(src: &Path, dst: &Path) {
+    let names = match listxattr_names(src) {
+        Ok(names) => names,
+        Err(e) => {
+            warn!("Failed to list xattrs on {:?} for copy-up: {}", src, e);
+            return;
+        }
+    };
+
+    for name in names {
+        match getxattr_value(src, &name) {
+            Ok(value) => {
+                if let Err(e) = setxattr_value(dst, &name, &value) {
+                    warn!("Failed to copy xattr {:?} to {:?}: {}", name, dst, e);
+                }
+            }
+            Err(e) => warn!("Failed to read xattr {:?} on {:?} for copy-up: {}", name, src, e),
+        }
+    }
+}
+
+fn cpath(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn split_nul_names(buf: &[u8]) -> Vec<CString> {
+    buf.split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| CString::new(s).ok())
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn listxattr_names(path: &Path) -> io::Result<Vec<CString>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::listxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written =
+            unsafe { libc::listxattr(c_path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(split_nul_names(&buf))
+}
+
+#[cfg(target_os = "macos")]
+fn listxattr_names(path: &Path) -> io::Result<Vec<CString>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::listxattr(c_path.as_ptr(), std::ptr::null_mut(), 0, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe {
+            libc::listxattr(c_path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len(), 0)
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(split_nul_names(&buf))
+}
+
+#[cfg(target_os = "linux")]
+fn getxattr_value(path: &Path, name: &CString) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::getxattr(c_path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe {
+            libc::getxattr(c_path.as_ptr(), name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "macos")]
+fn getxattr_value(path: &Path, name: &CString) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::getxattr(c_path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0, 0, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe {
+            libc::getxattr(
+                c_path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                0,
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "linux")]
+fn setxattr_value(path: &Path, name: &CString, value: &[u8]) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let ret = unsafe {
+        libc::setxattr(c_path.as_ptr(), name.as_ptr(), value.as_ptr() as *const libc::c_void, value.len(), 0)
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn setxattr_value(path: &Path, name: &CString, value: &[u8]) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let ret = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_file_fast_preserves_contents_and_mode() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src.txt");
+        let dst = dir.path().join("dst.txt");
+
+        fs::write(&src, b"hello, overlay").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o640)).unwrap();
+
+        copy_file_fast(&src, &dst).unwrap();
+
+        assert_eq!(fs::read(&dst).unwrap(), b"hello, overlay");
+        assert_eq!(fs::metadata(&dst).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_copy_file_fast_copies_xattrs() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src.txt");
+        let dst = dir.path().join("dst.txt");
+        fs::write(&src, b"data").unwrap();
+
+        if setxattr_value(&src, &CString::new("user.overlay_test").unwrap(), b"value").is_err() {
+            // The test filesystem (e.g. tmpfs without xattr support in
+            // some sandboxes) may not support user xattrs at all; the
+            // copy path is still exercised by the other test.
+            return;
+        }
+
+        copy_file_fast(&src, &dst).unwrap();
+
+        let value = getxattr_value(&dst, &CString::new("user.overlay_test").unwrap()).unwrap();
+        assert_eq!(value, b"value");
+    }
+}
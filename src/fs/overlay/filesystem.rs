@@ -5,22 +5,26 @@
 
 use fuser::{
     Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
-    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, ReplyXattr, Request,
 };
 use libc::{ENOENT, ENOTDIR, ENOTEMPTY};
 use std::ffi::OsStr;
-use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::fs::{self, OpenOptions};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 use tracing::{debug, error, info, warn};
 
 use super::{
+    fastcopy,
     handle::OverlayHandleManager,
     inode::{InodeSource, OverlayAttributes, OverlayFileType, OverlayInode, OverlayInodeManager},
+    inode_table::InodeTable,
     lower::LowerLayer,
+    openfiles::OpenFileTable,
     whiteout::WhiteoutStore,
+    xattr,
     OverlayConfig,
 };
 
@@ -38,8 +42,14 @@ pub struct OverlayFs {
     whiteouts: WhiteoutStore,
     /// Virtual inode management
     inodes: OverlayInodeManager,
+    /// Durable mirror of `inodes`, so path-to-ino assignments survive a
+    /// remount - see [`super::inode_table::InodeTable`].
+    inode_table: InodeTable,
     /// File handle manager
     handles: OverlayHandleManager,
+    /// Real, already-opened `File` behind each FUSE `fh` - see
+    /// [`super::openfiles::OpenFileTable`].
+    open_files: OpenFileTable,
     /// UID (reserved for future chown support)
     #[allow(dead_code)]
     uid: u32,
@@ -52,7 +62,7 @@ impl OverlayFs {
     /// Create a new overlay filesystem
     pub fn new(config: OverlayConfig) -> crate::error::Result<Self> {
         let lower = LowerLayer::new(config.lower_path.clone(), config.clone())?;
-        let whiteouts = WhiteoutStore::open(&config.whiteout_db_path)?;
+        let whiteouts = WhiteoutStore::open(&config.upper_path, config.opaque_xattr)?;
 
         // Create upper directory if it doesn't exist
         fs::create_dir_all(&config.upper_path)?;
@@ -62,16 +72,96 @@ impl OverlayFs {
             config.lower_path, config.upper_path
         );
 
-        Ok(Self {
+        let inode_table = InodeTable::open(&config.inode_table_path)?;
+        let inodes = OverlayInodeManager::new();
+
+        let mut overlay = Self {
             upper_path: config.upper_path.clone(),
             config,
             lower,
             whiteouts,
-            inodes: OverlayInodeManager::new(),
+            inodes,
+            inode_table,
             handles: OverlayHandleManager::new(),
+            open_files: OpenFileTable::new(),
             uid: unsafe { libc::getuid() },
             gid: unsafe { libc::getgid() },
-        })
+        };
+        overlay.reload_persisted_inodes();
+        Ok(overlay)
+    }
+
+    /// Re-stat every entry the durable inode table remembers from a prior
+    /// mount, re-registering the ones whose backing file still exists
+    /// under its original ino so it doesn't change across a remount.
+    /// Entries whose file is gone are dropped (invalidated) instead.
+    ///
+    /// `OverlayInodeManager` doesn't expose a way to seed its `alloc_ino`
+    /// counter directly, so afterwards we burn allocations until it
+    /// passes the highest ino we just restored - wasteful by at most one
+    /// inode number, but guarantees no collision with a restored entry.
+    fn reload_persisted_inodes(&mut self) {
+        let persisted = self.inode_table.entries();
+        if persisted.is_empty() {
+            return;
+        }
+
+        let mut restored = 0;
+        for entry in &persisted {
+            let upper_path = self.upper_path_for(&entry.path);
+            let inode = if let Ok(meta) = fs::metadata(&upper_path) {
+                let mut inode =
+                    OverlayInode::from_lower(entry.ino, entry.parent, entry.name.clone(), entry.path.clone(), &meta);
+                inode.source = InodeSource::Upper;
+                inode.lower_path = None;
+                Some(inode)
+            } else if self.lower.exists(&entry.path) {
+                self.lower.metadata(&entry.path).ok().map(|meta| {
+                    OverlayInode::from_lower(entry.ino, entry.parent, entry.name.clone(), entry.path.clone(), &meta)
+                })
+            } else {
+                None
+            };
+
+            match inode {
+                Some(inode) => {
+                    self.inodes.register(inode);
+                    restored += 1;
+                }
+                None => self.inode_table.remove(&entry.path),
+            }
+        }
+
+        if let Some(max_ino) = persisted.iter().map(|e| e.ino).max() {
+            while self.inodes.alloc_ino() <= max_ino {}
+        }
+
+        debug!(
+            "Restored {}/{} inode entries from {:?}",
+            restored,
+            persisted.len(),
+            self.config.inode_table_path
+        );
+    }
+
+    /// Register a newly looked-up or created inode, mirroring its
+    /// stable fields into [`InodeTable`] so the path->ino mapping
+    /// survives a remount.
+    fn register_inode(&self, inode: OverlayInode) {
+        let name = inode
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.inode_table.record(inode.ino, inode.parent, &name, &inode.path);
+        self.inodes.register(inode);
+    }
+
+    /// Drop `path` (and anything persisted under it) from both the
+    /// in-memory inode cache and its durable mirror.
+    fn invalidate_inode_path(&self, path: &PathBuf) {
+        self.inode_table.remove(path);
+        self.inodes.invalidate_path(path);
     }
 
     /// Get the upper layer path for a virtual path
@@ -83,11 +173,31 @@ impl OverlayFs {
     }
 
     /// Check if path exists in upper layer
-    #[allow(dead_code)]
     fn exists_in_upper(&self, virtual_path: &PathBuf) -> bool {
         self.upper_path_for(virtual_path).exists()
     }
 
+    /// Check whether `virtual_path` currently resolves to anything in
+    /// the overlay - upper layer, or lower layer not hidden by a
+    /// whiteout. Used by `rename`'s `RENAME_NOREPLACE` handling, which
+    /// must fail if the destination exists in either layer.
+    fn exists_virtual(&self, virtual_path: &PathBuf) -> bool {
+        if self.whiteouts.is_whiteout(virtual_path) {
+            return false;
+        }
+        self.exists_in_upper(virtual_path) || self.lower.exists(virtual_path)
+    }
+
+    /// Resolve the real file an inode's xattrs should be read from,
+    /// mirroring `read`'s upper-or-lower resolution.
+    fn real_path_for_xattr(&self, inode: &OverlayInode) -> Option<PathBuf> {
+        match inode.source {
+            InodeSource::Upper => Some(self.upper_path_for(&inode.path)),
+            InodeSource::Lower => inode.lower_path.as_ref().map(|lp| self.lower.resolve(lp)),
+            _ => None,
+        }
+    }
+
     /// Get virtual path from parent inode and name
     fn get_path(&self, parent: u64, name: &OsStr) -> Option<PathBuf> {
         let parent_inode = self.inodes.get(parent)?;
@@ -116,10 +226,10 @@ impl OverlayFs {
             let target = fs::read_link(&lower_path)?;
             std::os::unix::fs::symlink(&target, &upper_path)?;
         } else {
-            // Regular file - copy contents
-            fs::copy(&lower_path, &upper_path)?;
-            // Preserve permissions
-            fs::set_permissions(&upper_path, meta.permissions())?;
+            // Regular file - reflink/copy_file_range/read-write, in that
+            // order of preference, preserving mode, ownership,
+            // timestamps and xattrs along the way.
+            fastcopy::copy_file_fast(&lower_path, &upper_path)?;
         }
 
         info!("Copied up: {:?} -> {:?}", virtual_path, upper_path);
@@ -135,6 +245,55 @@ impl OverlayFs {
         Ok(())
     }
 
+    /// Handle `rename`'s `RENAME_EXCHANGE`: copy up both sides if
+    /// needed, then atomically swap them with `renameat2(2)` so neither
+    /// path is ever briefly missing. Whiteouts and the inode cache are
+    /// updated for both sides afterward, since each one's lower/upper
+    /// provenance has just swapped too.
+    fn rename_exchange(&mut self, old_path: &PathBuf, new_path: &PathBuf, reply: ReplyEmpty) {
+        for path in [old_path, new_path] {
+            let upper = self.upper_path_for(path);
+            if !upper.exists() && self.lower.exists(path) {
+                if let Err(e) = self.copy_up(path) {
+                    error!("Copy-up failed for rename exchange: {}", e);
+                    reply.error(libc::EIO);
+                    return;
+                }
+            }
+        }
+
+        let old_upper = self.upper_path_for(old_path);
+        let new_upper = self.upper_path_for(new_path);
+
+        if let Err(e) = renameat2(&old_upper, &new_upper, libc::RENAME_EXCHANGE) {
+            error!("Atomic rename exchange failed: {}", e);
+            reply.error(e.raw_os_error().unwrap_or(libc::EIO));
+            return;
+        }
+
+        // Each side's lower-layer whiteout state now belongs to the
+        // other path - swap them too.
+        let old_was_lower = self.lower.exists(old_path);
+        let new_was_lower = self.lower.exists(new_path);
+        if old_was_lower {
+            let _ = self.whiteouts.remove_whiteout(old_path);
+        }
+        if new_was_lower {
+            let _ = self.whiteouts.remove_whiteout(new_path);
+        }
+        if new_was_lower {
+            let _ = self.whiteouts.add_whiteout(old_path);
+        }
+        if old_was_lower {
+            let _ = self.whiteouts.add_whiteout(new_path);
+        }
+
+        self.invalidate_inode_path(old_path);
+        self.invalidate_inode_path(new_path);
+
+        reply.ok();
+    }
+
     /// Lookup or create inode for a path
     fn lookup_inode(&self, parent: u64, name: &OsStr) -> Option<OverlayInode> {
         let path = self.get_path(parent, name)?;
@@ -163,7 +322,7 @@ impl OverlayFs {
                 );
                 inode.source = InodeSource::Upper;
                 inode.lower_path = None;
-                self.inodes.register(inode.clone());
+                self.register_inode(inode.clone());
                 return Some(inode);
             }
         }
@@ -183,7 +342,7 @@ impl OverlayFs {
                 path.clone(),
                 &meta,
             );
-            self.inodes.register(inode.clone());
+            self.register_inode(inode.clone());
             return Some(inode);
         }
 
@@ -214,16 +373,19 @@ impl OverlayFs {
             if let Ok(read_dir) = fs::read_dir(&upper_dir) {
                 for entry in read_dir.flatten() {
                     let name = entry.file_name().to_string_lossy().to_string();
+                    let entry_path = dir_path.join(&entry.file_name());
 
-                    // Skip whiteout marker files
-                    if name.starts_with(".wh.") {
+                    // Skip whiteout marker files - a character device of
+                    // rdev 0/0 stands in for the deleted entry, so don't
+                    // surface it, and don't let the lower-layer pass
+                    // below reveal it either.
+                    if self.whiteouts.is_whiteout_device(&entry.path()) {
+                        seen.insert(name);
                         continue;
                     }
 
                     seen.insert(name.clone());
 
-                    let entry_path = dir_path.join(&entry.file_name());
-
                     // Get or create inode
                     let child_ino = if let Some(child) = self.inodes.get_by_path(&entry_path) {
                         child.ino
@@ -239,7 +401,7 @@ impl OverlayFs {
                             );
                             child.source = InodeSource::Upper;
                             child.lower_path = None;
-                            self.inodes.register(child);
+                            self.register_inode(child);
                         }
                         ino
                     };
@@ -287,7 +449,7 @@ impl OverlayFs {
                                     entry_path,
                                     &meta,
                                 );
-                                self.inodes.register(child);
+                                self.register_inode(child);
                             }
                             ino
                         };
@@ -508,22 +670,17 @@ impl Filesystem for OverlayFs {
         let upper_path = self.upper_path_for(&virtual_path);
 
         // Create file
-        let file = match OpenOptions::new()
+        if let Err(e) = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
             .mode(mode)
             .open(&upper_path)
         {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Failed to create file: {}", e);
-                reply.error(libc::EIO);
-                return;
-            }
-        };
-
-        drop(file);
+            error!("Failed to create file: {}", e);
+            reply.error(libc::EIO);
+            return;
+        }
 
         // Get metadata
         let meta = match fs::metadata(&upper_path) {
@@ -546,10 +703,15 @@ impl Filesystem for OverlayFs {
         );
         inode.source = InodeSource::Upper;
         inode.lower_path = None;
-        self.inodes.register(inode.clone());
+        self.register_inode(inode.clone());
 
         // Open file handle
         let fh = self.handles.open(ino, InodeSource::Upper, flags);
+        if let Err(e) = self.open_files.insert(fh, &upper_path, flags) {
+            error!("Failed to open cached handle for {:?}: {}", upper_path, e);
+            reply.error(libc::EIO);
+            return;
+        }
 
         reply.created(&TTL, &inode.to_fuser_attr(), 0, fh, 0);
     }
@@ -576,7 +738,7 @@ impl Filesystem for OverlayFs {
         let virtual_path = parent_inode.path.join(name);
 
         // Remove whiteout if exists
-        let _ = self.whiteouts.remove_whiteout(&virtual_path);
+        let replaced_whiteout = self.whiteouts.remove_whiteout(&virtual_path).unwrap_or(false);
 
         let upper_path = self.upper_path_for(&virtual_path);
 
@@ -592,6 +754,15 @@ impl Filesystem for OverlayFs {
             warn!("Failed to set permissions: {}", e);
         }
 
+        // A directory created in place of a whiteout must start opaque,
+        // or the old directory's lower-layer contents would reappear
+        // underneath it.
+        if replaced_whiteout {
+            if let Err(e) = self.whiteouts.mark_opaque(&virtual_path) {
+                error!("Failed to mark recreated directory opaque: {}", e);
+            }
+        }
+
         // Get metadata
         let meta = match fs::metadata(&upper_path) {
             Ok(m) => m,
@@ -614,7 +785,7 @@ impl Filesystem for OverlayFs {
         inode.source = InodeSource::Upper;
         inode.lower_path = None;
         inode.file_type = OverlayFileType::Directory;
-        self.inodes.register(inode.clone());
+        self.register_inode(inode.clone());
 
         reply.entry(&TTL, &inode.to_fuser_attr(), 0);
     }
@@ -651,13 +822,29 @@ impl Filesystem for OverlayFs {
             }
         }
 
+        // Re-read the inode - copy-up above may have updated its source
+        let inode = self.inodes.get(ino).unwrap_or(inode);
+
         let fh = self.handles.open(ino, inode.source, flags);
 
-        // Set path info
-        if inode.source == InodeSource::Lower {
-            if let Some(ref path) = inode.lower_path {
+        // Set path info and cache the real file handle backing `fh`
+        let real_path = if inode.source == InodeSource::Lower {
+            let path = inode.lower_path.clone();
+            if let Some(ref path) = path {
                 self.handles.set_lower_path(fh, path.clone());
             }
+            path.map(|p| self.lower.resolve(&p))
+        } else {
+            Some(self.upper_path_for(&inode.path))
+        };
+
+        if let Some(path) = real_path {
+            if let Err(e) = self.open_files.insert(fh, &path, flags) {
+                error!("Failed to open cached handle for {:?}: {}", path, e);
+                self.handles.close(fh);
+                reply.error(libc::EIO);
+                return;
+            }
         }
 
         reply.opened(fh, 0);
@@ -679,54 +866,20 @@ impl Filesystem for OverlayFs {
             ino, fh, offset, size
         );
 
-        let inode = match self.inodes.get(ino) {
-            Some(i) => i,
-            None => {
-                reply.error(ENOENT);
-                return;
-            }
-        };
-
-        let path = match inode.source {
-            InodeSource::Upper => self.upper_path_for(&inode.path),
-            InodeSource::Lower => {
-                if let Some(ref lp) = inode.lower_path {
-                    self.lower.resolve(lp)
-                } else {
-                    reply.error(ENOENT);
-                    return;
-                }
-            }
-            _ => {
-                reply.error(ENOENT);
-                return;
-            }
-        };
-
-        let mut file = match File::open(&path) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Failed to open file for read: {}", e);
-                reply.error(libc::EIO);
-                return;
-            }
-        };
-
-        if let Err(e) = file.seek(SeekFrom::Start(offset as u64)) {
-            error!("Failed to seek: {}", e);
-            reply.error(libc::EIO);
+        if self.inodes.get(ino).is_none() {
+            reply.error(ENOENT);
             return;
         }
 
         let mut buffer = vec![0u8; size as usize];
-        match file.read(&mut buffer) {
+        match self.open_files.read_at(fh, offset as u64, &mut buffer) {
             Ok(n) => {
                 buffer.truncate(n);
                 reply.data(&buffer);
             }
             Err(e) => {
                 error!("Failed to read: {}", e);
-                reply.error(libc::EIO);
+                reply.error(e.raw_os_error().unwrap_or(libc::EIO));
             }
         }
     }
@@ -759,8 +912,9 @@ impl Filesystem for OverlayFs {
             }
         };
 
-        // Ensure file is in upper layer
-        let upper_path = if inode.source == InodeSource::Lower {
+        // Ensure file is in upper layer, retargeting the cached handle
+        // at the new path if this write is what triggers the copy-up
+        if inode.source == InodeSource::Lower {
             match self.copy_up(&inode.path) {
                 Ok(p) => {
                     // Update inode
@@ -768,7 +922,12 @@ impl Filesystem for OverlayFs {
                     updated.source = InodeSource::Upper;
                     updated.lower_path = None;
                     self.inodes.update(ino, updated);
-                    p
+
+                    if let Err(e) = self.open_files.retarget(fh, &p, libc::O_RDWR) {
+                        error!("Failed to retarget cached handle to {:?}: {}", p, e);
+                        reply.error(libc::EIO);
+                        return;
+                    }
                 }
                 Err(e) => {
                     error!("Copy-up failed: {}", e);
@@ -776,32 +935,15 @@ impl Filesystem for OverlayFs {
                     return;
                 }
             }
-        } else {
-            self.upper_path_for(&inode.path)
-        };
-
-        let mut file = match OpenOptions::new().write(true).open(&upper_path) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Failed to open file for write: {}", e);
-                reply.error(libc::EIO);
-                return;
-            }
-        };
-
-        if let Err(e) = file.seek(SeekFrom::Start(offset as u64)) {
-            error!("Failed to seek: {}", e);
-            reply.error(libc::EIO);
-            return;
         }
 
-        match file.write(data) {
+        match self.open_files.write_at(fh, offset as u64, data) {
             Ok(n) => {
                 reply.written(n as u32);
             }
             Err(e) => {
                 error!("Failed to write: {}", e);
-                reply.error(libc::EIO);
+                reply.error(e.raw_os_error().unwrap_or(libc::EIO));
             }
         }
     }
@@ -837,7 +979,7 @@ impl Filesystem for OverlayFs {
         }
 
         // Remove from inode cache
-        self.inodes.invalidate_path(&virtual_path);
+        self.invalidate_inode_path(&virtual_path);
 
         reply.ok();
     }
@@ -869,19 +1011,18 @@ impl Filesystem for OverlayFs {
             }
         }
 
-        // Add whiteout if exists in lower
+        // Add whiteout if exists in lower - the whiteout device alone
+        // hides the whole subtree, since there's no longer an upper
+        // directory left to mark opaque. If this name is later
+        // recreated as a directory, `mkdir` marks *that* opaque instead.
         if self.lower.exists(&virtual_path) {
             if let Err(e) = self.whiteouts.add_whiteout(&virtual_path) {
                 error!("Failed to add whiteout: {}", e);
             }
-            // Mark as opaque to hide all lower contents
-            if let Err(e) = self.whiteouts.mark_opaque(&virtual_path) {
-                error!("Failed to mark opaque: {}", e);
-            }
         }
 
         // Remove from inode cache
-        self.inodes.invalidate_path(&virtual_path);
+        self.invalidate_inode_path(&virtual_path);
 
         reply.ok();
     }
@@ -893,14 +1034,24 @@ impl Filesystem for OverlayFs {
         name: &OsStr,
         newparent: u64,
         newname: &OsStr,
-        _flags: u32,
+        flags: u32,
         reply: ReplyEmpty,
     ) {
         debug!(
-            "rename(parent={}, name={:?}, newparent={}, newname={:?})",
-            parent, name, newparent, newname
+            "rename(parent={}, name={:?}, newparent={}, newname={:?}, flags={:#x})",
+            parent, name, newparent, newname, flags
         );
 
+        let known_flags = libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE | libc::RENAME_WHITEOUT;
+        if flags & !known_flags != 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if flags & libc::RENAME_NOREPLACE != 0 && flags & libc::RENAME_EXCHANGE != 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+
         let parent_inode = match self.inodes.get(parent) {
             Some(i) => i,
             None => {
@@ -920,6 +1071,16 @@ impl Filesystem for OverlayFs {
         let old_path = parent_inode.path.join(name);
         let new_path = newparent_inode.path.join(newname);
 
+        if flags & libc::RENAME_NOREPLACE != 0 && self.exists_virtual(&new_path) {
+            reply.error(libc::EEXIST);
+            return;
+        }
+
+        if flags & libc::RENAME_EXCHANGE != 0 {
+            self.rename_exchange(&old_path, &new_path, reply);
+            return;
+        }
+
         // Ensure source is in upper (copy-up if needed)
         let old_upper = self.upper_path_for(&old_path);
         if !old_upper.exists() && self.lower.exists(&old_path) {
@@ -955,8 +1116,8 @@ impl Filesystem for OverlayFs {
         let _ = self.whiteouts.remove_whiteout(&new_path);
 
         // Update inode cache
-        self.inodes.invalidate_path(&old_path);
-        self.inodes.invalidate_path(&new_path);
+        self.invalidate_inode_path(&old_path);
+        self.invalidate_inode_path(&new_path);
 
         reply.ok();
     }
@@ -972,6 +1133,7 @@ impl Filesystem for OverlayFs {
         reply: ReplyEmpty,
     ) {
         debug!("release(ino={}, fh={})", ino, fh);
+        self.open_files.remove(fh);
         self.handles.close(fh);
         reply.ok();
     }
@@ -1075,11 +1237,162 @@ impl Filesystem for OverlayFs {
         inode.source = InodeSource::Upper;
         inode.lower_path = None;
         inode.file_type = OverlayFileType::Symlink;
-        self.inodes.register(inode.clone());
+        self.register_inode(inode.clone());
 
         reply.entry(&TTL, &inode.to_fuser_attr(), 0);
     }
 
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        debug!("getxattr(ino={}, name={:?}, size={})", ino, name, size);
+
+        let inode = match self.inodes.get(ino) {
+            Some(i) => i,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        if name.as_bytes() == self.config.opaque_xattr.name().as_bytes() {
+            reply.error(libc::ENODATA);
+            return;
+        }
+
+        let path = match self.real_path_for_xattr(&inode) {
+            Some(p) => p,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match xattr::get(&path, name) {
+            Ok(value) => reply_xattr_payload(reply, &value, size),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        debug!("setxattr(ino={}, name={:?}, size={})", ino, name, value.len());
+
+        let inode = match self.inodes.get(ino) {
+            Some(i) => i,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        if name.as_bytes() == self.config.opaque_xattr.name().as_bytes() {
+            reply.error(libc::EPERM);
+            return;
+        }
+
+        // Ensure the file is in the upper layer before mutating its
+        // xattrs, the same way `write` forces copy-up before a write.
+        let upper_path = if inode.source == InodeSource::Lower {
+            match self.copy_up(&inode.path) {
+                Ok(p) => {
+                    let mut updated = inode.clone();
+                    updated.source = InodeSource::Upper;
+                    updated.lower_path = None;
+                    self.inodes.update(ino, updated);
+                    p
+                }
+                Err(e) => {
+                    error!("Copy-up failed: {}", e);
+                    reply.error(libc::EIO);
+                    return;
+                }
+            }
+        } else {
+            self.upper_path_for(&inode.path)
+        };
+
+        match xattr::set(&upper_path, name, value, flags) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        debug!("listxattr(ino={}, size={})", ino, size);
+
+        let inode = match self.inodes.get(ino) {
+            Some(i) => i,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let path = match self.real_path_for_xattr(&inode) {
+            Some(p) => p,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match xattr::list(&path) {
+            Ok(buf) => {
+                let buf = xattr::strip_name(&buf, self.config.opaque_xattr.name());
+                reply_xattr_payload(reply, &buf, size);
+            }
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("removexattr(ino={}, name={:?})", ino, name);
+
+        let inode = match self.inodes.get(ino) {
+            Some(i) => i,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        if name.as_bytes() == self.config.opaque_xattr.name().as_bytes() {
+            reply.error(libc::EPERM);
+            return;
+        }
+
+        let upper_path = if inode.source == InodeSource::Lower {
+            match self.copy_up(&inode.path) {
+                Ok(p) => {
+                    let mut updated = inode.clone();
+                    updated.source = InodeSource::Upper;
+                    updated.lower_path = None;
+                    self.inodes.update(ino, updated);
+                    p
+                }
+                Err(e) => {
+                    error!("Copy-up failed: {}", e);
+                    reply.error(libc::EIO);
+                    return;
+                }
+            }
+        } else {
+            self.upper_path_for(&inode.path)
+        };
+
+        match xattr::remove(&upper_path, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
     fn access(&mut self, _req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
         debug!("access(ino={}, mask={})", ino, mask);
 
@@ -1113,6 +1426,9 @@ impl Filesystem for OverlayFs {
         reply: ReplyEmpty,
     ) {
         debug!("flush(ino={}, fh={})", ino, fh);
+        if let Err(e) = self.open_files.sync(fh, false) {
+            warn!("Failed to flush cached handle for fh={}: {}", fh, e);
+        }
         reply.ok();
     }
 
@@ -1125,6 +1441,60 @@ impl Filesystem for OverlayFs {
         reply: ReplyEmpty,
     ) {
         debug!("fsync(ino={}, fh={}, datasync={})", ino, fh, datasync);
+        if let Err(e) = self.open_files.sync(fh, datasync) {
+            warn!("Failed to sync cached handle for fh={}: {}", fh, e);
+        }
+        if let Err(e) = self.inode_table.flush() {
+            warn!("Failed to persist inode table on fsync: {}", e);
+        }
         reply.ok();
     }
+
+    fn destroy(&mut self) {
+        if let Err(e) = self.inode_table.flush() {
+            warn!("Failed to persist inode table on unmount: {}", e);
+        }
+    }
+}
+
+/// Reply to a `getxattr`/`listxattr` request following the FUSE
+/// convention: `size == 0` means the caller only wants the value's
+/// length, any other size must be large enough to hold it or the call
+/// fails with `ERANGE`.
+fn reply_xattr_payload(reply: ReplyXattr, data: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(data);
+    }
+}
+
+/// `renameat2(2)` - like `std::fs::rename`, but takes the `RENAME_*`
+/// flag bits (`RENAME_NOREPLACE`/`RENAME_EXCHANGE`/`RENAME_WHITEOUT`)
+/// that the plain `rename(2)` syscall `std::fs::rename` wraps has no way
+/// to pass. Not exposed as a safe wrapper by the `libc` crate, so it's
+/// invoked directly via `libc::syscall`.
+fn renameat2(old: &std::path::Path, new: &std::path::Path, flags: u32) -> std::io::Result<()> {
+    let c_old = std::ffi::CString::new(old.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let c_new = std::ffi::CString::new(new.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            c_old.as_ptr(),
+            libc::AT_FDCWD,
+            c_new.as_ptr(),
+            flags,
+        )
+    };
+
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
 }
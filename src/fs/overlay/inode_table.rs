@@ -0,0 +1,166 @@
+//! Durable persistence for the overlay's virtual inode table
+//!
+//! `OverlayInodeManager` allocates inode numbers with `alloc_ino` and
+//! keeps its path-to-ino map only in memory, so every remount hands out
+//! fresh numbers for the same virtual paths - breaking any `open` file
+//! handle, NFS export handle, or cached `st_ino` that survived the
+//! remount. [`InodeTable`] mirrors the stable half of that map (ino,
+//! parent, name, virtual path) to a zstd-compressed state file next to
+//! the upper layer, updated incrementally as inodes are registered and
+//! invalidated, and reloaded at mount so the same path maps to the same
+//! inode number across restarts.
+
+use crate::error::{Error, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// zstd compression level for the state file - this is small and can be
+/// written on every fsync, so favor speed over ratio.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The stable fields of one virtual inode, kept separate from
+/// `OverlayInode` itself so this on-disk format doesn't change shape
+/// every time that struct gains an in-memory-only field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedInode {
+    pub ino: u64,
+    pub parent: u64,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Durable mirror of the path -> ino assignments `OverlayInodeManager`
+/// holds in memory.
+pub struct InodeTable {
+    path: PathBuf,
+    entries: Mutex<HashMap<u64, PersistedInode>>,
+}
+
+impl InodeTable {
+    /// Open the table backed by `path`, loading whatever was persisted
+    /// there (or starting empty if nothing was).
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = Self::load(&path)?.unwrap_or_default();
+        debug!("Loaded {} persisted inode entries from {:?}", entries.len(), path);
+        Ok(Self { path, entries: Mutex::new(entries) })
+    }
+
+    fn load(path: &Path) -> Result<Option<HashMap<u64, PersistedInode>>> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let decompressed = zstd::decode_all(BufReader::new(file))
+            .map_err(|e| Error::Internal(format!("zstd decompression failed: {}", e)))?;
+        let list: Vec<PersistedInode> = bincode::deserialize(&decompressed)?;
+        Ok(Some(list.into_iter().map(|e| (e.ino, e)).collect()))
+    }
+
+    /// Every persisted entry, ascending by ino - used at mount to
+    /// re-register inodes before `alloc_ino` resumes past the highest
+    /// one.
+    pub fn entries(&self) -> Vec<PersistedInode> {
+        let mut entries: Vec<_> = self.entries.lock().values().cloned().collect();
+        entries.sort_by_key(|e| e.ino);
+        entries
+    }
+
+    /// The highest persisted ino, if any - `alloc_ino` must resume above
+    /// this.
+    pub fn max_ino(&self) -> Option<u64> {
+        self.entries.lock().keys().copied().max()
+    }
+
+    /// Record (or update) one inode's stable fields.
+    pub fn record(&self, ino: u64, parent: u64, name: &str, path: &Path) {
+        self.entries
+            .lock()
+            .insert(ino, PersistedInode { ino, parent, name: name.to_string(), path: path.to_path_buf() });
+    }
+
+    /// Drop the entry at `virtual_path`, and any entries still recorded
+    /// under it (a whole subtree disappearing under a directory delete).
+    pub fn remove(&self, virtual_path: &Path) {
+        self.entries.lock().retain(|_, e| e.path != virtual_path && !e.path.starts_with(virtual_path));
+    }
+
+    /// Write the current table to `path`, compressed with zstd. Writes
+    /// to a temp file first and renames over the target so a crash
+    /// mid-write can't leave a truncated, undecodable state file.
+    pub fn flush(&self) -> Result<()> {
+        let list = self.entries();
+        let bytes = bincode::serialize(&list)?;
+        let compressed = zstd::encode_all(bytes.as_slice(), COMPRESSION_LEVEL)
+            .map_err(|e| Error::Internal(format!("zstd compression failed: {}", e)))?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("zst.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(&compressed)?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        debug!("Persisted {} inode entries to {:?}", list.len(), self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_flush_and_reopen_roundtrips_entries() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("inodes.zst");
+
+        {
+            let table = InodeTable::open(&state_path).unwrap();
+            table.record(2, 1, "docs", Path::new("/docs"));
+            table.record(3, 2, "readme.txt", Path::new("/docs/readme.txt"));
+            table.flush().unwrap();
+        }
+
+        let reopened = InodeTable::open(&state_path).unwrap();
+        assert_eq!(reopened.max_ino(), Some(3));
+        let entries = reopened.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, Path::new("/docs"));
+        assert_eq!(entries[1].path, Path::new("/docs/readme.txt"));
+    }
+
+    #[test]
+    fn test_remove_drops_subtree() {
+        let dir = tempdir().unwrap();
+        let table = InodeTable::open(dir.path().join("inodes.zst")).unwrap();
+
+        table.record(2, 1, "docs", Path::new("/docs"));
+        table.record(3, 2, "readme.txt", Path::new("/docs/readme.txt"));
+        table.record(4, 1, "other.txt", Path::new("/other.txt"));
+
+        table.remove(Path::new("/docs"));
+
+        let paths: Vec<_> = table.entries().into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, vec![PathBuf::from("/other.txt")]);
+    }
+
+    #[test]
+    fn test_open_with_no_existing_file_starts_empty() {
+        let dir = tempdir().unwrap();
+        let table = InodeTable::open(dir.path().join("missing.zst")).unwrap();
+        assert!(table.entries().is_empty());
+        assert_eq!(table.max_ino(), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Cached real file handles for the overlay's open files
+//!
+//! `OverlayHandleManager` (see `handle.rs`) hands out the FUSE `fh` and
+//! tracks which layer backs it, but `read`/`write` used to reopen
+//! `&upper_path` and seek to the right offset on *every* call - a
+//! syscall-heavy hot path for large sequential I/O, and it throws away
+//! any `O_APPEND`/`O_DIRECT` semantics requested at `open` time.
+//! [`OpenFileTable`] caches the actual `std::fs::File` opened for each
+//! `fh`, so `read`/`write` issue a single positional `pread`/`pwrite`
+//! (via [`FileExt`]) instead of an open-seek-read/write per call.
+
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+
+/// Caches the open `File` backing each FUSE file handle.
+pub struct OpenFileTable {
+    files: RwLock<HashMap<u64, File>>,
+}
+
+impl OpenFileTable {
+    pub fn new() -> Self {
+        Self { files: RwLock::new(HashMap::new()) }
+    }
+
+    /// Open `path` with the access mode encoded in `flags` (as passed
+    /// to FUSE's `open`/`create`) and cache it under `fh`.
+    pub fn insert(&self, fh: u64, path: &Path, flags: i32) -> io::Result<()> {
+        let file = open_with_flags(path, flags)?;
+        self.files.write().insert(fh, file);
+        Ok(())
+    }
+
+    /// Re-open `path` for an `fh` that already has a cached handle,
+    /// replacing it - used when `copy_up` retargets a handle from the
+    /// lower layer to the freshly materialized upper file.
+    pub fn retarget(&self, fh: u64, path: &Path, flags: i32) -> io::Result<()> {
+        self.insert(fh, path, flags)
+    }
+
+    pub fn read_at(&self, fh: u64, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let files = self.files.read();
+        let file = files.get(&fh).ok_or_else(missing_handle)?;
+        file.read_at(buf, offset)
+    }
+
+    pub fn write_at(&self, fh: u64, offset: u64, data: &[u8]) -> io::Result<usize> {
+        let files = self.files.read();
+        let file = files.get(&fh).ok_or_else(missing_handle)?;
+        file.write_at(data, offset)
+    }
+
+    /// Flush the cached handle's data (and, if `datasync` is false,
+    /// metadata) to disk.
+    pub fn sync(&self, fh: u64, datasync: bool) -> io::Result<()> {
+        let files = self.files.read();
+        let file = files.get(&fh).ok_or_else(missing_handle)?;
+        if datasync {
+            file.sync_data()
+        } else {
+            file.sync_all()
+        }
+    }
+
+    /// Drop the cached handle for `fh`, e.g. on `release`.
+    pub fn remove(&self, fh: u64) {
+        self.files.write().remove(&fh);
+    }
+}
+
+fn missing_handle() -> io::Error {
+    io::Error::from_raw_os_error(libc::EBADF)
+}
+
+/// Open `path` honoring the access-mode and behavior bits already
+/// present in a FUSE `flags` argument, rather than re-deriving them
+/// (`O_WRONLY`/`O_RDWR`/`O_APPEND`/etc. are exactly the bits the kernel
+/// validated when it first asked to open this file). `O_CREAT`/
+/// `O_EXCL`/`O_TRUNC` are stripped - the file this table opens always
+/// already exists with the right contents by the time it gets here, so
+/// those creation-time bits would only risk an unwanted truncation or a
+/// spurious `EEXIST`.
+fn open_with_flags(path: &Path, flags: i32) -> io::Result<File> {
+    let accmode = flags & libc::O_ACCMODE;
+    let behavior_flags = flags & !libc::O_ACCMODE & !libc::O_CREAT & !libc::O_EXCL & !libc::O_TRUNC;
+    let mut options = OpenOptions::new();
+    options.read(accmode == libc::O_RDONLY || accmode == libc::O_RDWR);
+    options.write(accmode == libc::O_WRONLY || accmode == libc::O_RDWR);
+    options.custom_flags(behavior_flags);
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_then_read_at_offset_without_reopening() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let table = OpenFileTable::new();
+        table.insert(1, &path, libc::O_RDWR).unwrap();
+
+        table.write_at(1, 2, b"XX").unwrap();
+
+        let mut buf = [0u8; 4];
+        let n = table.read_at(1, 1, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"1XX4");
+
+        table.remove(1);
+        assert!(table.read_at(1, 0, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_retarget_points_at_new_file() {
+        let dir = tempdir().unwrap();
+        let old_path = dir.path().join("old.txt");
+        let new_path = dir.path().join("new.txt");
+        std::fs::write(&old_path, b"old").unwrap();
+        std::fs::write(&new_path, b"new").unwrap();
+
+        let table = OpenFileTable::new();
+        table.insert(1, &old_path, libc::O_RDONLY).unwrap();
+        table.retarget(1, &new_path, libc::O_RDWR).unwrap();
+
+        let mut buf = [0u8; 3];
+        table.read_at(1, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"new");
+    }
+}
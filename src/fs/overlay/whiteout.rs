@@ -1,196 +1,512 @@
 //! Whiteout tracking for overlay filesystem
 //!
-//! Tracks deleted files and opaque directories to hide lower layer entries.
-
+//! Deleted paths and opaque directories used to live in a sidecar sled
+//! database, keyed by path string - which meant the upper directory this
+//! produced was only usable through this crate's own lookup/readdir
+//! logic, not as a real overlay upper layer. This store instead marks
+//! deletions and opacity directly on the upper directory, using the same
+//! on-disk conventions as the kernel's `overlay` filesystem and
+//! `fuse-overlayfs`: a deleted path is a character device (`mknod`, mode
+//! `S_IFCHR`, rdev 0/0) of the same name, and an opaque directory carries
+//! an extended attribute on itself. That makes the upper directory a
+//! portable overlay upper layer that either of those can mount directly.
+//!
+//! Lookups are scoped to a single directory rather than kept in one
+//! flat, ever-growing set: `dir_entry` reads (or lazily `readdir`s) just
+//! the directory a query is about, and a bounded [`LruCache`] of recently
+//! touched directories caps how many of those per-directory results stay
+//! resident, so memory stays flat regardless of how many whiteouts exist
+//! overall.
+
+use crate::cache::LruCache;
 use crate::error::Result;
 use parking_lot::RwLock;
-use std::collections::HashSet;
-use std::ffi::OsString;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::{Path, PathBuf};
 use tracing::debug;
 
-/// Prefix for whiteout entries in sled (reserved for future prefix-based keys)
-#[allow(dead_code)]
-const WHITEOUT_PREFIX: &[u8] = b"wo:";
-/// Prefix for opaque directory markers (reserved for future prefix-based keys)
-#[allow(dead_code)]
-const OPAQUE_PREFIX: &[u8] = b"op:";
+use super::OpaqueXattr;
 
-/// Tracks deleted files and opaque directories
+/// How many directories' scan results [`WhiteoutStore`] keeps resident
+/// before evicting the least recently used one.
+const DEFAULT_MAX_CACHED_DIRS: usize = 4096;
+
+/// Tracks deleted files and opaque directories directly on the upper
+/// directory's own entries.
 pub struct WhiteoutStore {
-    /// Sled database for persistence
-    db: sled::Db,
-    /// Whiteout entries tree (deleted files)
-    whiteouts: sled::Tree,
-    /// Opaque directories tree
-    opaque_dirs: sled::Tree,
-    /// In-memory cache for fast lookups
-    cache: RwLock<WhiteoutCache>,
-}
-
-/// In-memory cache for whiteout lookups
-struct WhiteoutCache {
-    /// Set of whiteout paths (normalized, relative to overlay root)
+    /// Root of the upper layer; whiteouts and opaque markers are written
+    /// here, not to a sidecar database.
+    upper_path: PathBuf,
+    /// Which xattr name marks a directory opaque - see [`OpaqueXattr`].
+    opaque_xattr: OpaqueXattr,
+    /// Bounded cache of per-directory scan results.
+    cache: RwLock<DirCache>,
+    /// Cap on how many directories `cache` keeps resident at once.
+    max_cached_dirs: usize,
+}
+
+/// Cached `readdir` result for a single directory: which of its children
+/// are whiteout devices, and whether the directory itself is opaque.
+#[derive(Debug, Clone, Default)]
+struct DirEntry {
+    whiteout_names: HashSet<OsString>,
+    opaque: bool,
+}
+
+/// Bounded, per-directory cache backing [`WhiteoutStore`] lookups.
+/// `order` tracks recency so the oldest directory's entry is evicted from
+/// `dirs` once `max_cached_dirs` is exceeded.
+#[derive(Default)]
+struct DirCache {
+    dirs: HashMap<PathBuf, DirEntry>,
+    order: LruCache<PathBuf>,
+}
+
+/// Full-tree scan result - every whiteout and opaque directory found
+/// under a root, virtual-pathed. Only used by the bulk
+/// [`WhiteoutStore::export_overlay`]/[`WhiteoutStore::import_overlay`]
+/// operations, which genuinely need the complete set; everyday lookups go
+/// through the bounded [`DirCache`] instead.
+#[derive(Default)]
+struct FullTreeScan {
     whiteouts: HashSet<PathBuf>,
-    /// Set of opaque directory paths
     opaque_dirs: HashSet<PathBuf>,
-    /// Whether cache is fully loaded
-    loaded: bool,
 }
 
 impl WhiteoutStore {
-    /// Create/open whiteout store at the given path
-    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let db = sled::open(path.as_ref())?;
-
-        let whiteouts = db.open_tree("whiteouts")?;
-
-        let opaque_dirs = db.open_tree("opaque_dirs")?;
-
-        let store = Self {
-            db,
-            whiteouts,
-            opaque_dirs,
-            cache: RwLock::new(WhiteoutCache {
-                whiteouts: HashSet::new(),
-                opaque_dirs: HashSet::new(),
-                loaded: false,
-            }),
-        };
+    /// Open the whiteout store backed by `upper_path`. Unlike a full
+    /// upfront walk, directory state is discovered lazily on first query
+    /// and cached up to [`DEFAULT_MAX_CACHED_DIRS`] entries - see
+    /// [`Self::with_cache_capacity`] to override that.
+    pub fn open(upper_path: impl AsRef<Path>, opaque_xattr: OpaqueXattr) -> Result<Self> {
+        Self::with_cache_capacity(upper_path, opaque_xattr, DEFAULT_MAX_CACHED_DIRS)
+    }
 
-        store.load_cache()?;
-        Ok(store)
+    /// Like [`Self::open`], but with an explicit cap on how many
+    /// directories' scan results stay cached at once.
+    pub fn with_cache_capacity(
+        upper_path: impl AsRef<Path>,
+        opaque_xattr: OpaqueXattr,
+        max_cached_dirs: usize,
+    ) -> Result<Self> {
+        let upper_path = upper_path.as_ref().to_path_buf();
+        fs::create_dir_all(&upper_path)?;
+
+        Ok(Self {
+            upper_path,
+            opaque_xattr,
+            cache: RwLock::new(DirCache::default()),
+            max_cached_dirs,
+        })
     }
 
-    /// Load all whiteouts into cache
-    fn load_cache(&self) -> Result<()> {
-        let mut cache = self.cache.write();
+    /// Translate a virtual path (rooted at `/`) to its location under the
+    /// upper directory - mirrors `OverlayFs::upper_path_for`.
+    fn real_path(&self, virtual_path: &Path) -> PathBuf {
+        real_path_under(&self.upper_path, virtual_path)
+    }
+
+    /// Read `dir`'s cached scan result, scanning it from disk on a cache
+    /// miss and evicting the least recently used directory if the cache
+    /// is now over capacity.
+    fn dir_entry(&self, dir: &Path) -> Result<DirEntry> {
+        {
+            let mut cache = self.cache.write();
+            if let Some(entry) = cache.dirs.get(dir).cloned() {
+                cache.order.touch(&dir.to_path_buf());
+                return Ok(entry);
+            }
+        }
+
+        let entry = self.scan_one_dir(&self.real_path(dir))?;
 
-        for entry in self.whiteouts.iter() {
-            let (key, _) = entry?;
-            if let Ok(path_str) = std::str::from_utf8(&key) {
-                cache.whiteouts.insert(PathBuf::from(path_str));
+        let mut cache = self.cache.write();
+        cache.dirs.insert(dir.to_path_buf(), entry.clone());
+        cache.order.insert(dir.to_path_buf());
+        while cache.dirs.len() > self.max_cached_dirs {
+            match cache.order.pop_oldest() {
+                Some(evicted) => {
+                    cache.dirs.remove(&evicted);
+                }
+                None => break,
             }
         }
 
-        for entry in self.opaque_dirs.iter() {
-            let (key, _) = entry?;
-            if let Ok(path_str) = std::str::from_utf8(&key) {
-                cache.opaque_dirs.insert(PathBuf::from(path_str));
+        Ok(entry)
+    }
+
+    /// `readdir` a single real directory, recording its whiteout device
+    /// children and whether it's itself marked opaque.
+    fn scan_one_dir(&self, real_dir: &Path) -> Result<DirEntry> {
+        let mut entry = DirEntry::default();
+
+        let read_dir = match fs::read_dir(real_dir) {
+            Ok(rd) => rd,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entry),
+            Err(e) => return Err(e.into()),
+        };
+
+        for child in read_dir {
+            let child = child?;
+            if child.file_type()?.is_char_device() {
+                if let Ok(meta) = child.metadata() {
+                    if meta.rdev() == 0 {
+                        entry.whiteout_names.insert(child.file_name());
+                    }
+                }
             }
         }
 
-        cache.loaded = true;
-        debug!(
-            "Loaded {} whiteouts and {} opaque dirs into cache",
-            cache.whiteouts.len(),
-            cache.opaque_dirs.len()
-        );
-        Ok(())
+        entry.opaque = has_xattr(real_dir, self.opaque_xattr.name());
+        Ok(entry)
+    }
+
+    /// Invalidate (or update in place) `dir`'s cached entry. Used after a
+    /// write so the next read doesn't serve stale cached state without
+    /// requiring a full rescan.
+    fn with_cached_entry_mut(&self, dir: &Path, f: impl FnOnce(&mut DirEntry)) {
+        if let Some(entry) = self.cache.write().dirs.get_mut(dir) {
+            f(entry);
+        }
     }
 
     /// Check if a path is whited-out (deleted)
     pub fn is_whiteout(&self, path: &Path) -> bool {
-        let cache = self.cache.read();
-        cache.whiteouts.contains(path)
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return false;
+        };
+        self.dir_entry(parent)
+            .map(|entry| entry.whiteout_names.contains(name))
+            .unwrap_or(false)
     }
 
     /// Check if a path is under an opaque directory
     pub fn is_under_opaque(&self, path: &Path) -> bool {
-        let cache = self.cache.read();
-        for ancestor in path.ancestors().skip(1) {
-            if cache.opaque_dirs.contains(ancestor) {
-                return true;
-            }
-        }
-        false
+        path.ancestors()
+            .skip(1)
+            .any(|ancestor| self.dir_entry(ancestor).map(|e| e.opaque).unwrap_or(false))
     }
 
-    /// Add a whiteout (mark as deleted)
+    /// Add a whiteout (mark as deleted): replace `path` in the upper
+    /// directory with a character device of rdev 0/0.
     pub fn add_whiteout(&self, path: &Path) -> Result<()> {
-        let path_str = path.to_string_lossy();
-        self.whiteouts.insert(path_str.as_bytes(), b"1")?;
-
-        let mut cache = self.cache.write();
-        cache.whiteouts.insert(path.to_path_buf());
+        let real = self.real_path(path);
+        if let Some(parent) = real.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A whiteout takes the deleted entry's place, so clear anything
+        // left there first (callers already remove upper files/dirs
+        // before calling this, but a stale whiteout from a prior delete
+        // would otherwise make mknod fail with EEXIST).
+        let _ = fs::remove_file(&real);
+        mknod_whiteout(&real)?;
+
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            let name = name.to_os_string();
+            self.with_cached_entry_mut(parent, |entry| {
+                entry.whiteout_names.insert(name);
+            });
+        }
         debug!("Added whiteout for: {:?}", path);
         Ok(())
     }
 
-    /// Remove a whiteout (file re-created)
-    pub fn remove_whiteout(&self, path: &Path) -> Result<()> {
-        let path_str = path.to_string_lossy();
-        self.whiteouts.remove(path_str.as_bytes())?;
+    /// Remove a whiteout (file or directory re-created at `path`).
+    /// Returns whether a whiteout was actually present.
+    pub fn remove_whiteout(&self, path: &Path) -> Result<bool> {
+        let real = self.real_path(path);
+        let existed = self.is_whiteout(path);
 
-        let mut cache = self.cache.write();
-        cache.whiteouts.remove(path);
-        debug!("Removed whiteout for: {:?}", path);
-        Ok(())
+        if existed {
+            fs::remove_file(&real)?;
+        }
+
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            self.with_cached_entry_mut(parent, |entry| {
+                entry.whiteout_names.remove(name);
+            });
+        }
+        if existed {
+            debug!("Removed whiteout for: {:?}", path);
+        }
+        Ok(existed)
     }
 
-    /// Mark directory as opaque (hide lower contents)
+    /// Mark directory as opaque (hide lower contents) by setting the
+    /// configured xattr on the upper directory itself.
     pub fn mark_opaque(&self, path: &Path) -> Result<()> {
-        let path_str = path.to_string_lossy();
-        self.opaque_dirs.insert(path_str.as_bytes(), b"1")?;
+        let real = self.real_path(path);
+        set_xattr(&real, self.opaque_xattr.name())?;
 
-        let mut cache = self.cache.write();
-        cache.opaque_dirs.insert(path.to_path_buf());
+        self.with_cached_entry_mut(path, |entry| entry.opaque = true);
         debug!("Marked directory as opaque: {:?}", path);
         Ok(())
     }
 
     /// Unmark directory as opaque
     pub fn unmark_opaque(&self, path: &Path) -> Result<()> {
-        let path_str = path.to_string_lossy();
-        self.opaque_dirs.remove(path_str.as_bytes())?;
+        let real = self.real_path(path);
+        remove_xattr(&real, self.opaque_xattr.name())?;
 
-        let mut cache = self.cache.write();
-        cache.opaque_dirs.remove(path);
+        self.with_cached_entry_mut(path, |entry| entry.opaque = false);
         Ok(())
     }
 
     /// Check if directory is opaque
     pub fn is_opaque(&self, path: &Path) -> bool {
-        let cache = self.cache.read();
-        cache.opaque_dirs.contains(path)
+        self.dir_entry(path).map(|e| e.opaque).unwrap_or(false)
     }
 
-    /// Get all whiteouts under a directory (for readdir filtering)
+    /// Get all whiteouts directly under a directory (for readdir filtering)
     pub fn whiteouts_in_dir(&self, dir: &Path) -> HashSet<OsString> {
-        let cache = self.cache.read();
-        let mut result = HashSet::new();
-
-        for whiteout_path in &cache.whiteouts {
-            if let Some(parent) = whiteout_path.parent() {
-                if parent == dir {
-                    if let Some(name) = whiteout_path.file_name() {
-                        result.insert(name.to_os_string());
-                    }
+        self.dir_entry(dir).map(|e| e.whiteout_names).unwrap_or_default()
+    }
+
+    /// Is the real upper-directory entry at `path` a whiteout device?
+    /// Used by readdir to recognize whiteouts it hasn't cached yet (e.g.
+    /// created by another overlay implementation sharing this upper dir).
+    pub fn is_whiteout_device(&self, real_path: &Path) -> bool {
+        match fs::symlink_metadata(real_path) {
+            Ok(meta) => meta.file_type().is_char_device() && meta.rdev() == 0,
+            Err(_) => false,
+        }
+    }
+
+    /// Materialize every whiteout and opaque-directory marker this store
+    /// currently knows about onto `dest`, using the same overlayfs/OCI
+    /// on-disk conventions `upper_path` itself already uses. Lets the
+    /// layer this crate produces be packed into a container image layer
+    /// (or handed to a standalone overlayfs mount) living somewhere other
+    /// than our own upper directory.
+    ///
+    /// Returns the number of markers written.
+    pub fn export_overlay(&self, dest: impl AsRef<Path>) -> Result<usize> {
+        let dest = dest.as_ref();
+        fs::create_dir_all(dest)?;
+
+        // Export needs every marker in the tree at once, so it does a
+        // one-off full walk rather than going through the bounded
+        // per-directory lookup cache used by everyday queries.
+        let mut found = FullTreeScan::default();
+        scan_overlay_tree(&self.upper_path, &self.upper_path, self.opaque_xattr, &mut found)?;
+        let mut written = 0;
+
+        for path in &found.whiteouts {
+            let target = real_path_under(dest, path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let _ = fs::remove_file(&target);
+            mknod_whiteout(&target)?;
+            written += 1;
+        }
+
+        for path in &found.opaque_dirs {
+            let target = real_path_under(dest, path);
+            fs::create_dir_all(&target)?;
+            set_xattr(&target, self.opaque_xattr.name())?;
+            written += 1;
+        }
+
+        debug!("Exported {} overlay markers to {:?}", written, dest);
+        Ok(written)
+    }
+
+    /// Scan an existing overlay tree at `src` (conventionally laid out the
+    /// same way `upper_path` is - whiteout devices and opaque xattrs) and
+    /// adopt every marker it finds into this store, mirroring each onto
+    /// our own `upper_path` as well so the two stay consistent. Lets a
+    /// layer produced by Docker/containerd/overlayfs - or unpacked from an
+    /// OCI image - be adopted as-is.
+    ///
+    /// Returns the number of markers adopted.
+    pub fn import_overlay(&self, src: impl AsRef<Path>) -> Result<usize> {
+        let src = src.as_ref();
+        let mut found = FullTreeScan::default();
+        scan_overlay_tree(src, src, self.opaque_xattr, &mut found)?;
+
+        let mut imported = 0;
+        for path in &found.whiteouts {
+            self.add_whiteout(path)?;
+            imported += 1;
+        }
+        for path in &found.opaque_dirs {
+            fs::create_dir_all(self.real_path(path))?;
+            self.mark_opaque(path)?;
+            imported += 1;
+        }
+
+        debug!("Imported {} overlay markers from {:?}", imported, src);
+        Ok(imported)
+    }
+}
+
+/// Translate an upper-directory path back to its virtual path (rooted at
+/// `/`), relative to `root`.
+fn virtual_path_under(root: &Path, real_path: &Path) -> PathBuf {
+    match real_path.strip_prefix(root) {
+        Ok(relative) => Path::new("/").join(relative),
+        Err(_) => real_path.to_path_buf(),
+    }
+}
+
+/// Translate a virtual path (rooted at `/`) to its location under `root`.
+fn real_path_under(root: &Path, virtual_path: &Path) -> PathBuf {
+    let relative = virtual_path.strip_prefix("/").unwrap_or(virtual_path);
+    root.join(relative)
+}
+
+/// Walk `dir` (part of an overlay tree rooted at `root`), recording every
+/// whiteout device and opaque directory found, virtual-pathed relative to
+/// `root`. Used for the full-tree bulk operations
+/// ([`WhiteoutStore::export_overlay`], [`WhiteoutStore::import_overlay`])
+/// rather than everyday lookups, which go through the bounded
+/// [`DirCache`] instead.
+fn scan_overlay_tree(
+    dir: &Path,
+    root: &Path,
+    opaque_xattr: OpaqueXattr,
+    cache: &mut FullTreeScan,
+) -> Result<()> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_char_device() {
+            if let Ok(meta) = entry.metadata() {
+                if meta.rdev() == 0 {
+                    cache.whiteouts.insert(virtual_path_under(root, &path));
+                    continue;
                 }
             }
         }
 
-        result
+        if file_type.is_dir() {
+            if has_xattr(&path, opaque_xattr.name()) {
+                cache.opaque_dirs.insert(virtual_path_under(root, &path));
+            }
+            scan_overlay_tree(&path, root, opaque_xattr, cache)?;
+        }
     }
 
-    /// Clear all whiteouts (for sync operations)
-    pub fn clear(&self) -> Result<()> {
-        self.whiteouts.clear()?;
-        self.opaque_dirs.clear()?;
+    Ok(())
+}
 
-        let mut cache = self.cache.write();
-        cache.whiteouts.clear();
-        cache.opaque_dirs.clear();
+/// Create a character-device whiteout (mode `S_IFCHR`, rdev 0/0) at `path`.
+fn mknod_whiteout(path: &Path) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| crate::error::Error::InvalidConfig(e.to_string()))?;
+    let rdev = unsafe { libc::makedev(0, 0) };
+    let ret = unsafe { libc::mknod(c_path.as_ptr(), libc::S_IFCHR as libc::mode_t, rdev) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
 
-        debug!("Cleared all whiteouts and opaque directories");
-        Ok(())
+#[cfg(target_os = "linux")]
+fn set_xattr(path: &Path, name: &str) -> Result<()> {
+    let c_path = cstring(path)?;
+    let c_name = cstring_str(name)?;
+    let value = b"y";
+    let ret = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
     }
+    Ok(())
+}
 
-    /// Flush to disk
-    pub fn flush(&self) -> Result<()> {
-        self.db.flush()?;
-        Ok(())
+#[cfg(target_os = "macos")]
+fn set_xattr(path: &Path, name: &str) -> Result<()> {
+    let c_path = cstring(path)?;
+    let c_name = cstring_str(name)?;
+    let value = b"y";
+    let ret = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn get_xattr_len(path: &Path, name: &str) -> isize {
+    let (Ok(c_path), Ok(c_name)) = (cstring(path), cstring_str(name)) else { return -1 };
+    unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0) }
+}
+
+#[cfg(target_os = "macos")]
+fn get_xattr_len(path: &Path, name: &str) -> isize {
+    let (Ok(c_path), Ok(c_name)) = (cstring(path), cstring_str(name)) else { return -1 };
+    unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0, 0, 0) }
+}
+
+fn has_xattr(path: &Path, name: &str) -> bool {
+    get_xattr_len(path, name) > 0
+}
+
+#[cfg(target_os = "linux")]
+fn remove_xattr(path: &Path, name: &str) -> Result<()> {
+    let c_path = cstring(path)?;
+    let c_name = cstring_str(name)?;
+    let ret = unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr()) };
+    ignore_missing_xattr(ret)
+}
+
+#[cfg(target_os = "macos")]
+fn remove_xattr(path: &Path, name: &str) -> Result<()> {
+    let c_path = cstring(path)?;
+    let c_name = cstring_str(name)?;
+    let ret = unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr(), 0) };
+    ignore_missing_xattr(ret)
+}
+
+/// `removexattr` returning ENODATA just means the directory was never
+/// marked opaque - not an error our callers need to react to.
+fn ignore_missing_xattr(ret: i32) -> Result<()> {
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENODATA) {
+            return Err(err.into());
+        }
     }
+    Ok(())
+}
+
+fn cstring(path: &Path) -> std::result::Result<CString, std::ffi::NulError> {
+    CString::new(path.as_os_str().as_bytes())
+}
+
+fn cstring_str(s: &str) -> std::result::Result<CString, std::ffi::NulError> {
+    CString::new(s)
 }
 
 #[cfg(test)]
@@ -201,24 +517,36 @@ mod tests {
     #[test]
     fn test_whiteout_store() {
         let dir = tempdir().unwrap();
-        let store = WhiteoutStore::open(dir.path().join("whiteout.db")).unwrap();
+        let store = WhiteoutStore::open(dir.path(), OpaqueXattr::default()).unwrap();
 
-        let path = PathBuf::from("test/file.txt");
+        let path = PathBuf::from("/test/file.txt");
+        fs::create_dir_all(dir.path().join("test")).unwrap();
+        fs::write(dir.path().join("test/file.txt"), b"hi").unwrap();
 
         assert!(!store.is_whiteout(&path));
+        fs::remove_file(dir.path().join("test/file.txt")).unwrap();
         store.add_whiteout(&path).unwrap();
         assert!(store.is_whiteout(&path));
-        store.remove_whiteout(&path).unwrap();
+
+        // The deleted entry is a real character-device whiteout on disk.
+        let meta = fs::symlink_metadata(dir.path().join("test/file.txt")).unwrap();
+        assert!(meta.file_type().is_char_device());
+        assert_eq!(meta.rdev(), 0);
+
+        let removed = store.remove_whiteout(&path).unwrap();
+        assert!(removed);
         assert!(!store.is_whiteout(&path));
+        assert!(!dir.path().join("test/file.txt").exists());
     }
 
     #[test]
     fn test_opaque_dirs() {
         let dir = tempdir().unwrap();
-        let store = WhiteoutStore::open(dir.path().join("whiteout.db")).unwrap();
+        let store = WhiteoutStore::open(dir.path(), OpaqueXattr::default()).unwrap();
 
-        let dir_path = PathBuf::from("test/dir");
-        let child_path = PathBuf::from("test/dir/child.txt");
+        let dir_path = PathBuf::from("/test/dir");
+        let child_path = PathBuf::from("/test/dir/child.txt");
+        fs::create_dir_all(dir.path().join("test/dir")).unwrap();
 
         assert!(!store.is_opaque(&dir_path));
         assert!(!store.is_under_opaque(&child_path));
@@ -226,5 +554,92 @@ mod tests {
         store.mark_opaque(&dir_path).unwrap();
         assert!(store.is_opaque(&dir_path));
         assert!(store.is_under_opaque(&child_path));
+        assert!(has_xattr(&dir.path().join("test/dir"), OpaqueXattr::default().name()));
+
+        store.unmark_opaque(&dir_path).unwrap();
+        assert!(!store.is_opaque(&dir_path));
+        assert!(!has_xattr(&dir.path().join("test/dir"), OpaqueXattr::default().name()));
+    }
+
+    #[test]
+    fn test_open_rediscovers_existing_markers() {
+        let dir = tempdir().unwrap();
+        {
+            let store = WhiteoutStore::open(dir.path(), OpaqueXattr::default()).unwrap();
+            fs::create_dir_all(dir.path().join("a/b")).unwrap();
+            store.mark_opaque(&PathBuf::from("/a/b")).unwrap();
+            store.add_whiteout(&PathBuf::from("/a/gone.txt")).unwrap();
+        }
+
+        // A fresh store over the same upper directory rediscovers both
+        // markers straight from the filesystem, with no sidecar state.
+        let reopened = WhiteoutStore::open(dir.path(), OpaqueXattr::default()).unwrap();
+        assert!(reopened.is_opaque(&PathBuf::from("/a/b")));
+        assert!(reopened.is_whiteout(&PathBuf::from("/a/gone.txt")));
+    }
+
+    #[test]
+    fn test_with_cache_capacity_evicts_least_recently_used_directory() {
+        let dir = tempdir().unwrap();
+        let store = WhiteoutStore::with_cache_capacity(dir.path(), OpaqueXattr::default(), 2).unwrap();
+
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        fs::create_dir_all(dir.path().join("b")).unwrap();
+        fs::create_dir_all(dir.path().join("c")).unwrap();
+        store.add_whiteout(&PathBuf::from("/a/gone.txt")).unwrap();
+        store.add_whiteout(&PathBuf::from("/b/gone.txt")).unwrap();
+
+        // Querying "/a" and "/b" fills the two-entry cache; "/c" evicts the
+        // least recently touched of them ("/a", since "/b" was added last).
+        assert!(store.is_whiteout(&PathBuf::from("/a/gone.txt")));
+        assert!(store.is_whiteout(&PathBuf::from("/b/gone.txt")));
+        assert!(!store.is_whiteout(&PathBuf::from("/c/gone.txt")));
+        assert_eq!(store.cache.read().dirs.len(), 2);
+
+        // A whiteout added on disk after "/a" was evicted is still found -
+        // the miss just triggers a fresh scan rather than serving stale
+        // (absent) state.
+        fs::remove_file(dir.path().join("a")).ok();
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        store.add_whiteout(&PathBuf::from("/a/new.txt")).unwrap();
+        assert!(store.is_whiteout(&PathBuf::from("/a/new.txt")));
+    }
+
+    #[test]
+    fn test_export_overlay_materializes_markers_at_destination() {
+        let dir = tempdir().unwrap();
+        let store = WhiteoutStore::open(dir.path(), OpaqueXattr::default()).unwrap();
+
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        store.mark_opaque(&PathBuf::from("/a/b")).unwrap();
+        store.add_whiteout(&PathBuf::from("/a/gone.txt")).unwrap();
+
+        let dest = tempdir().unwrap();
+        let written = store.export_overlay(dest.path()).unwrap();
+        assert_eq!(written, 2);
+
+        let gone_meta = fs::symlink_metadata(dest.path().join("a/gone.txt")).unwrap();
+        assert!(gone_meta.file_type().is_char_device());
+        assert_eq!(gone_meta.rdev(), 0);
+        assert!(has_xattr(&dest.path().join("a/b"), OpaqueXattr::default().name()));
+    }
+
+    #[test]
+    fn test_import_overlay_adopts_markers_from_an_external_tree() {
+        let external = tempdir().unwrap();
+        fs::create_dir_all(external.path().join("a/b")).unwrap();
+        set_xattr(&external.path().join("a/b"), OpaqueXattr::default().name()).unwrap();
+        mknod_whiteout(&external.path().join("a/gone.txt")).unwrap();
+
+        let upper = tempdir().unwrap();
+        let store = WhiteoutStore::open(upper.path(), OpaqueXattr::default()).unwrap();
+        let imported = store.import_overlay(external.path()).unwrap();
+        assert_eq!(imported, 2);
+
+        assert!(store.is_opaque(&PathBuf::from("/a/b")));
+        assert!(store.is_whiteout(&PathBuf::from("/a/gone.txt")));
+        // Markers are mirrored onto our own upper directory too.
+        assert!(upper.path().join("a/gone.txt").exists());
+        assert!(has_xattr(&upper.path().join("a/b"), OpaqueXattr::default().name()));
     }
 }
@@ -0,0 +1,213 @@
+//! Raw extended-attribute syscalls for the merged overlay view
+//!
+//! [`super::filesystem::OverlayFs`]'s `getxattr`/`setxattr`/`listxattr`/
+//! `removexattr` handlers resolve an inode to whichever real file
+//! currently backs it (upper or lower) and call straight through to
+//! these wrappers. Kept separate from [`super::fastcopy`]'s xattr
+//! helpers because those return names already split out of the raw
+//! `listxattr(2)` buffer for copy-up, while FUSE's `listxattr` wants
+//! that buffer handed back untouched.
+
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+fn cpath(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn cname(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// The raw, null-separated attribute-name buffer `listxattr(2)` returns.
+#[cfg(target_os = "linux")]
+pub fn list(path: &Path) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::listxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe { libc::listxattr(c_path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "macos")]
+pub fn list(path: &Path) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let len = unsafe { libc::listxattr(c_path.as_ptr(), std::ptr::null_mut(), 0, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written =
+            unsafe { libc::listxattr(c_path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len(), 0) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "linux")]
+pub fn get(path: &Path, name: &OsStr) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let len = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe {
+            libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "macos")]
+pub fn get(path: &Path, name: &OsStr) -> io::Result<Vec<u8>> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let len = unsafe { libc::getxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0, 0, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buf = vec![0u8; len as usize];
+    if len > 0 {
+        let written = unsafe {
+            libc::getxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                0,
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(written as usize);
+    }
+    Ok(buf)
+}
+
+#[cfg(target_os = "linux")]
+pub fn set(path: &Path, name: &OsStr, value: &[u8], flags: i32) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let ret = unsafe {
+        libc::setxattr(c_path.as_ptr(), c_name.as_ptr(), value.as_ptr() as *const libc::c_void, value.len(), flags)
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn set(path: &Path, name: &OsStr, value: &[u8], flags: i32) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let ret = unsafe {
+        libc::setxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+            flags,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn remove(path: &Path, name: &OsStr) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let ret = unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr()) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn remove(path: &Path, name: &OsStr) -> io::Result<()> {
+    let c_path = cpath(path)?;
+    let c_name = cname(name)?;
+    let ret = unsafe { libc::removexattr(c_path.as_ptr(), c_name.as_ptr(), 0) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Strip one name out of a raw `listxattr(2)`-style null-separated
+/// buffer, so internal markers (e.g. [`super::config::OpaqueXattr`]'s
+/// attribute) never show up to a FUSE caller.
+pub fn strip_name(buf: &[u8], name: &str) -> Vec<u8> {
+    let name = name.as_bytes();
+    let mut out = Vec::with_capacity(buf.len());
+    for segment in buf.split(|&b| b == 0) {
+        if segment.is_empty() || segment == name {
+            continue;
+        }
+        out.extend_from_slice(segment);
+        out.push(0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_set_get_remove_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, b"data").unwrap();
+
+        if set(&path, OsStr::new("user.overlay_test"), b"value", 0).is_err() {
+            // Test filesystem doesn't support user xattrs (e.g. some
+            // sandboxed tmpfs) - nothing further to exercise here.
+            return;
+        }
+
+        assert_eq!(get(&path, OsStr::new("user.overlay_test")).unwrap(), b"value");
+        assert!(list(&path).unwrap().windows(17).any(|w| w == b"user.overlay_test"));
+
+        remove(&path, OsStr::new("user.overlay_test")).unwrap();
+        assert!(get(&path, OsStr::new("user.overlay_test")).is_err());
+    }
+
+    #[test]
+    fn test_strip_name_removes_only_matching_segment() {
+        let buf = b"user.a\0user.overlay_opaque\0user.b\0";
+        let stripped = strip_name(buf, "user.overlay_opaque");
+        assert_eq!(stripped, b"user.a\0user.b\0");
+    }
+}
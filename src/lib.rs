@@ -2,10 +2,19 @@
 //!
 //! This library provides a FUSE-based filesystem that stores all data
 //! encrypted in the cloud, with local caching for performance.
+//!
+//! The `mount` feature (default-on) controls whether the `fuser`-based
+//! FUSE mount path is compiled in. Disable it to use the encryption,
+//! chunking, and metadata pipeline as a plain library - e.g. server-side
+//! backup tooling or CI - through [`fs::EncryptedFilesystem`] without
+//! depending on libfuse.
 
+pub mod backend;
 pub mod cache;
 pub mod chunk;
 pub mod config;
+pub mod control;
+pub mod crash_report;
 pub mod crypto;
 pub mod distributed;
 pub mod error;
@@ -13,8 +22,11 @@ pub mod fs;
 pub mod metadata;
 pub mod migration;
 pub mod raid;
+pub mod rate_limit;
+pub mod service;
 pub mod snapshot;
 pub mod telegram;
+pub mod updater;
 
 pub use config::Config;
 pub use error::{Error, Result};
@@ -23,5 +35,6 @@ pub use error::{Error, Result};
 pub mod prelude {
     pub use crate::config::Config;
     pub use crate::error::{Error, Result};
+    pub use crate::fs::EncryptedFilesystem;
     pub use crate::metadata::Inode;
 }
@@ -4,15 +4,58 @@
 //! proper hard link semantics for backup systems like Time Machine.
 
 use crate::error::{Error, Result};
-use sled::{Db, Tree};
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, Transactional, Tree};
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use tracing::{debug, warn};
 
+/// Filter applied by [`HardLinkStore::create_link`] to keep cache/temp
+/// directories and cross-mount paths out of the dedup database, mirroring
+/// the `same_device`/`excludes` options real backup tools expose.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeConfig {
+    /// Path patterns to exclude; a path matching any pattern is skipped.
+    pub excludes: Option<RegexSet>,
+    /// When set, links are only tracked for inodes on this device.
+    pub same_device: Option<u64>,
+}
+
+/// Compact, truncated-timestamp metadata record used to decide whether a
+/// path's content has changed since it was last captured under an inode,
+/// without re-reading or re-hashing the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathMetadata {
+    /// Modification time, whole seconds
+    pub mtime_secs: i64,
+    /// Modification time, sub-second nanoseconds
+    pub mtime_nanos: u32,
+    /// File size in bytes
+    pub size: u64,
+}
+
+/// A single difference between two snapshots taken with
+/// [`HardLinkStore::snapshot`], as computed by [`HardLinkStore::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// `inode` gained a new path that wasn't present in the earlier snapshot.
+    Added(u64, PathBuf),
+    /// `inode` lost a path that was present in the earlier snapshot.
+    Removed(u64, PathBuf),
+    /// `inode`'s link count changed between snapshots (paths unchanged, or
+    /// already reported individually via `Added`/`Removed`).
+    Modified(u64, u64, u64),
+}
+
 /// Hard link tracker using sled database
 ///
 /// Tracks the relationship between inodes and paths, maintaining:
 /// - Link count per inode
 /// - Multiple paths pointing to the same inode
+/// - A reverse path -> inode index for `lookup_inode`
 pub struct HardLinkStore {
     /// Sled database
     db: Db,
@@ -20,6 +63,14 @@ pub struct HardLinkStore {
     link_counts: Tree,
     /// Inode -> paths mapping tree
     inode_paths: Tree,
+    /// Path -> inode tree (reverse of `inode_paths`), keyed by the path string
+    path_inode: Tree,
+    /// Snapshot name -> serialized `(inode -> (paths, link_count))` map
+    snapshots: Tree,
+    /// Exclude-pattern/same-device filter applied by `create_link`
+    exclude: ExcludeConfig,
+    /// `(inode, path)` -> serialized [`PathMetadata`], for change detection
+    path_metadata: Tree,
 }
 
 impl HardLinkStore {
@@ -34,9 +85,25 @@ impl HardLinkStore {
     /// # Errors
     /// Returns an error if the database cannot be opened
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_options(path, ExcludeConfig::default())
+    }
+
+    /// Open or create a hard link store with an exclude-pattern/same-device
+    /// filter applied to every [`Self::create_link`] call.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the database directory
+    /// * `exclude` - The filter to apply
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened
+    pub fn with_options<P: AsRef<Path>>(path: P, exclude: ExcludeConfig) -> Result<Self> {
         let db = sled::open(path.as_ref())?;
         let link_counts = db.open_tree("link_counts")?;
         let inode_paths = db.open_tree("inode_paths")?;
+        let path_inode = db.open_tree("path_inode")?;
+        let snapshots = db.open_tree("snapshots")?;
+        let path_metadata = db.open_tree("path_metadata")?;
 
         debug!("Opened hard link store at {:?}", path.as_ref());
 
@@ -44,6 +111,10 @@ impl HardLinkStore {
             db,
             link_counts,
             inode_paths,
+            path_inode,
+            snapshots,
+            exclude,
+            path_metadata,
         })
     }
 
@@ -59,6 +130,34 @@ impl HardLinkStore {
     /// # Errors
     /// Returns an error if the database operation fails
     pub fn create_link(&self, inode: u64, path: &Path) -> Result<u64> {
+        self.create_link_on_device(inode, path, None)
+    }
+
+    /// Like [`Self::create_link`], but also enforces the configured
+    /// `same_device` filter against `device`, when both are set.
+    ///
+    /// # Arguments
+    /// * `inode` - The inode number
+    /// * `path` - The path to associate with this inode
+    /// * `device` - The device the path's inode lives on, if known
+    ///
+    /// # Returns
+    /// The link count after this call - unchanged if the path was skipped
+    /// by the exclude filter
+    ///
+    /// # Errors
+    /// Returns an error if the database operation fails
+    pub fn create_link_on_device(
+        &self,
+        inode: u64,
+        path: &Path,
+        device: Option<u64>,
+    ) -> Result<u64> {
+        if self.is_excluded(path, device) {
+            debug!("Skipping excluded path for hard link tracking: {:?}", path);
+            return Ok(self.get_link_count(inode));
+        }
+
         let inode_key = inode.to_be_bytes();
 
         // Get current paths for this inode
@@ -82,6 +181,10 @@ impl HardLinkStore {
         self.link_counts
             .insert(&inode_key, &new_count.to_be_bytes())?;
 
+        // Keep the reverse path -> inode index in sync
+        self.path_inode
+            .insert(path_key(path), &inode_key)?;
+
         debug!(
             "Created hard link: inode={}, path={:?}, count={}",
             inode, path, new_count
@@ -142,9 +245,88 @@ impl HardLinkStore {
             );
         }
 
+        self.path_inode.remove(path_key(path))?;
+        self.path_metadata.remove(metadata_key(inode, path))?;
+
         Ok(new_count)
     }
 
+    /// Look up which inode backs a path, without scanning every tracked
+    /// inode's path list.
+    ///
+    /// # Arguments
+    /// * `path` - The path to resolve
+    ///
+    /// # Returns
+    /// The inode backing this path, or `None` if it isn't tracked
+    pub fn lookup_inode(&self, path: &Path) -> Option<u64> {
+        self.path_inode
+            .get(path_key(path))
+            .ok()
+            .flatten()
+            .and_then(|bytes| {
+                let inode_bytes: [u8; 8] = bytes.as_ref().try_into().ok()?;
+                Some(u64::from_be_bytes(inode_bytes))
+            })
+    }
+
+    /// Atomically rename a tracked hard link from `old` to `new`, updating
+    /// both the forward (`inode_paths`) and reverse (`path_inode`) trees in
+    /// a single sled transaction so `rename(2)` of a linked file never
+    /// leaves the two out of sync.
+    ///
+    /// # Arguments
+    /// * `inode` - The inode the link belongs to
+    /// * `old` - The link's current path
+    /// * `new` - The link's new path
+    ///
+    /// # Errors
+    /// Returns an error if `old` isn't one of `inode`'s tracked paths, or
+    /// if the database transaction fails
+    pub fn rename_link(&self, inode: u64, old: &Path, new: &Path) -> Result<()> {
+        let inode_key = inode.to_be_bytes();
+        let old_key = path_key(old);
+        let new_key = path_key(new);
+
+        let result = (&self.inode_paths, &self.path_inode).transaction(
+            |(inode_paths, path_inode)| {
+                let mut paths: Vec<PathBuf> = match inode_paths.get(&inode_key)? {
+                    Some(bytes) => bincode::deserialize(&bytes)
+                        .map_err(|e| ConflictableTransactionError::Abort(e.to_string()))?,
+                    None => Vec::new(),
+                };
+
+                let Some(slot) = paths.iter_mut().find(|p| p.as_path() == old) else {
+                    return Err(ConflictableTransactionError::Abort(
+                        old.to_string_lossy().to_string(),
+                    ));
+                };
+                *slot = new.to_path_buf();
+
+                let paths_bytes = bincode::serialize(&paths)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.to_string()))?;
+                inode_paths.insert(&inode_key, paths_bytes)?;
+
+                path_inode.remove(old_key.as_slice())?;
+                path_inode.insert(new_key.as_slice(), &inode_key)?;
+
+                Ok(())
+            },
+        );
+
+        match result {
+            Ok(()) => {
+                debug!(
+                    "Renamed hard link: inode={}, old={:?}, new={:?}",
+                    inode, old, new
+                );
+                Ok(())
+            }
+            Err(TransactionError::Abort(path_str)) => Err(Error::PathNotFound(path_str)),
+            Err(TransactionError::Storage(e)) => Err(Error::Database(e)),
+        }
+    }
+
     /// Get the link count for an inode
     ///
     /// # Arguments
@@ -234,11 +416,209 @@ impl HardLinkStore {
     /// Returns an error if the database operation fails
     pub fn remove_inode(&self, inode: u64) -> Result<()> {
         let inode_key = inode.to_be_bytes();
+        for path in self.get_paths_internal(inode)? {
+            self.path_inode.remove(path_key(&path))?;
+            self.path_metadata.remove(metadata_key(inode, &path))?;
+        }
         self.inode_paths.remove(&inode_key)?;
         self.link_counts.remove(&inode_key)?;
         debug!("Removed all hard link data for inode={}", inode);
         Ok(())
     }
+
+    /// Capture the current `inode -> (paths, link_count)` state under a
+    /// named snapshot, so a later [`Self::diff`] can report what changed
+    /// since this point.
+    ///
+    /// # Arguments
+    /// * `name` - The snapshot's name; re-using a name overwrites it
+    ///
+    /// # Errors
+    /// Returns an error if the database operation fails
+    pub fn snapshot(&self, name: &str) -> Result<()> {
+        let state = self.current_state()?;
+        let bytes = bincode::serialize(&state)?;
+        self.snapshots.insert(name.as_bytes(), bytes)?;
+        debug!("Captured hard link snapshot '{}' ({} inodes)", name, state.len());
+        Ok(())
+    }
+
+    /// Compute what changed between two snapshots previously captured with
+    /// [`Self::snapshot`].
+    ///
+    /// # Arguments
+    /// * `from` - The earlier snapshot's name
+    /// * `to` - The later snapshot's name
+    ///
+    /// # Errors
+    /// Returns an error if either snapshot doesn't exist or the database
+    /// operation fails
+    pub fn diff(&self, from: &str, to: &str) -> Result<Vec<Change>> {
+        let from_state = self.load_snapshot(from)?;
+        let to_state = self.load_snapshot(to)?;
+
+        let inodes: HashSet<u64> = from_state.keys().chain(to_state.keys()).copied().collect();
+        let mut changes = Vec::new();
+
+        for inode in inodes {
+            let (from_paths, from_count) = from_state
+                .get(&inode)
+                .cloned()
+                .unwrap_or_else(|| (HashSet::new(), 0));
+            let (to_paths, to_count) = to_state
+                .get(&inode)
+                .cloned()
+                .unwrap_or_else(|| (HashSet::new(), 0));
+
+            for added in to_paths.difference(&from_paths) {
+                changes.push(Change::Added(inode, added.clone()));
+            }
+            for removed in from_paths.difference(&to_paths) {
+                changes.push(Change::Removed(inode, removed.clone()));
+            }
+            if from_count != to_count {
+                changes.push(Change::Modified(inode, from_count, to_count));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Load a named snapshot as `inode -> (path set, link count)`, keyed
+    /// for efficient set comparison in [`Self::diff`].
+    fn load_snapshot(&self, name: &str) -> Result<HashMap<u64, (HashSet<PathBuf>, u64)>> {
+        let bytes = self
+            .snapshots
+            .get(name.as_bytes())?
+            .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?;
+        let state: HashMap<u64, Vec<PathBuf>> = bincode::deserialize(&bytes)?;
+        Ok(state
+            .into_iter()
+            .map(|(inode, paths)| {
+                let count = paths.len() as u64;
+                (inode, (paths.into_iter().collect(), count))
+            })
+            .collect())
+    }
+
+    /// Enumerate every inode with at least one tracked path under `prefix`,
+    /// without scanning the full `inode_paths` tree. Because `path_inode`
+    /// is keyed by the path string, every path under `prefix` sorts
+    /// contiguously right after it, so this is a single bounded prefix
+    /// scan rather than an O(all inodes) walk.
+    ///
+    /// # Arguments
+    /// * `prefix` - The directory to enumerate tracked inodes under
+    pub fn inodes_under(&self, prefix: &Path) -> Vec<u64> {
+        let mut scan_prefix = path_key(prefix);
+        if !scan_prefix.ends_with(b"/") {
+            scan_prefix.push(b'/');
+        }
+
+        self.path_inode
+            .scan_prefix(&scan_prefix)
+            .filter_map(|entry| {
+                let (_, value) = entry.ok()?;
+                let inode_bytes: [u8; 8] = value.as_ref().try_into().ok()?;
+                Some(u64::from_be_bytes(inode_bytes))
+            })
+            .collect()
+    }
+
+    /// Drop all hard link tracking for every path under `prefix` (and the
+    /// inodes that become untracked as a result), in one traversal rather
+    /// than a full-store scan. Used to invalidate a whole directory's
+    /// worth of link records in one shot, e.g. when it's deleted.
+    ///
+    /// # Arguments
+    /// * `prefix` - The directory whose descendants should be dropped
+    ///
+    /// # Returns
+    /// The number of distinct inodes removed
+    ///
+    /// # Errors
+    /// Returns an error if the database operation fails
+    pub fn remove_subtree(&self, prefix: &Path) -> Result<usize> {
+        let inodes: HashSet<u64> = self.inodes_under(prefix).into_iter().collect();
+        for inode in &inodes {
+            self.remove_inode(*inode)?;
+        }
+        debug!(
+            "Removed {} inode(s) under subtree {:?}",
+            inodes.len(),
+            prefix
+        );
+        Ok(inodes.len())
+    }
+
+    /// Record `meta` as the last-seen metadata for `path` under `inode`, so
+    /// a later [`Self::is_unchanged`] call can tell whether a backup pass
+    /// needs to re-hash it.
+    ///
+    /// # Errors
+    /// Returns an error if the database operation fails
+    pub fn record_metadata(&self, inode: u64, path: &Path, meta: PathMetadata) -> Result<()> {
+        let bytes = bincode::serialize(&meta)?;
+        self.path_metadata.insert(metadata_key(inode, path), bytes)?;
+        Ok(())
+    }
+
+    /// Whether `path` (tracked under `inode`) has changed since the
+    /// metadata last recorded for it with [`Self::record_metadata`]. A
+    /// path with no recorded metadata is always considered changed.
+    pub fn is_unchanged(&self, inode: u64, path: &Path, current: PathMetadata) -> bool {
+        self.path_metadata
+            .get(metadata_key(inode, path))
+            .ok()
+            .flatten()
+            .and_then(|bytes| bincode::deserialize::<PathMetadata>(&bytes).ok())
+            .is_some_and(|stored| stored == current)
+    }
+
+    /// Whether `path`/`device` should be skipped per the configured
+    /// [`ExcludeConfig`].
+    fn is_excluded(&self, path: &Path, device: Option<u64>) -> bool {
+        if let Some(excludes) = &self.exclude.excludes {
+            if excludes.is_match(&path.to_string_lossy()) {
+                return true;
+            }
+        }
+        if let (Some(configured), Some(actual)) = (self.exclude.same_device, device) {
+            if configured != actual {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Snapshot-able view of the store's live state: every tracked inode's
+    /// current path list.
+    fn current_state(&self) -> Result<HashMap<u64, Vec<PathBuf>>> {
+        let mut state = HashMap::new();
+        for entry in self.inode_paths.iter() {
+            let (key, value) = entry?;
+            let inode = u64::from_be_bytes(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| Error::Internal("corrupt inode key in inode_paths".into()))?,
+            );
+            let paths: Vec<PathBuf> = bincode::deserialize(&value)?;
+            state.insert(inode, paths);
+        }
+        Ok(state)
+    }
+}
+
+/// Key the reverse path -> inode tree by the path's UTF-8 (lossy) bytes.
+fn path_key(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Key the `(inode, path)` -> [`PathMetadata`] tree.
+fn metadata_key(inode: u64, path: &Path) -> Vec<u8> {
+    let mut key = inode.to_be_bytes().to_vec();
+    key.extend_from_slice(&path_key(path));
+    key
 }
 
 #[cfg(test)]
@@ -433,4 +813,260 @@ mod tests {
         assert!(store.get_paths(inode).is_empty());
         assert_eq!(store.inode_count(), 0);
     }
+
+    #[test]
+    fn test_lookup_inode() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 900;
+        let path1 = PathBuf::from("/test/path1");
+        let path2 = PathBuf::from("/test/path2");
+
+        assert_eq!(store.lookup_inode(&path1), None);
+
+        store.create_link(inode, &path1).unwrap();
+        store.create_link(inode, &path2).unwrap();
+        assert_eq!(store.lookup_inode(&path1), Some(inode));
+        assert_eq!(store.lookup_inode(&path2), Some(inode));
+
+        store.remove_link(inode, &path1).unwrap();
+        assert_eq!(store.lookup_inode(&path1), None);
+        assert_eq!(store.lookup_inode(&path2), Some(inode));
+    }
+
+    #[test]
+    fn test_lookup_inode_cleared_by_remove_inode() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 1000;
+        let path = PathBuf::from("/test/path");
+        store.create_link(inode, &path).unwrap();
+
+        store.remove_inode(inode).unwrap();
+        assert_eq!(store.lookup_inode(&path), None);
+    }
+
+    #[test]
+    fn test_rename_link() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 1100;
+        let old_path = PathBuf::from("/test/old");
+        let other_path = PathBuf::from("/test/other");
+        let new_path = PathBuf::from("/test/new");
+
+        store.create_link(inode, &old_path).unwrap();
+        store.create_link(inode, &other_path).unwrap();
+
+        store.rename_link(inode, &old_path, &new_path).unwrap();
+
+        // The forward mapping now has the new path in place of the old one.
+        let paths = store.get_paths(inode);
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&new_path));
+        assert!(paths.contains(&other_path));
+        assert!(!paths.contains(&old_path));
+
+        // The reverse index is updated in lockstep.
+        assert_eq!(store.lookup_inode(&old_path), None);
+        assert_eq!(store.lookup_inode(&new_path), Some(inode));
+        assert_eq!(store.lookup_inode(&other_path), Some(inode));
+    }
+
+    #[test]
+    fn test_rename_link_unknown_path_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 1200;
+        let tracked = PathBuf::from("/test/tracked");
+        let untracked = PathBuf::from("/test/untracked");
+        store.create_link(inode, &tracked).unwrap();
+
+        let result = store.rename_link(inode, &untracked, &PathBuf::from("/test/new"));
+        assert!(result.is_err());
+
+        // Nothing changed.
+        assert_eq!(store.get_paths(inode), vec![tracked]);
+    }
+
+    #[test]
+    fn test_snapshot_diff_detects_add_remove_and_modify() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let kept_inode = 2000;
+        let removed_inode = 2001;
+        let added_inode = 2002;
+        let kept_path = PathBuf::from("/test/kept");
+        let removed_path = PathBuf::from("/test/removed");
+        let new_path_for_kept = PathBuf::from("/test/kept-extra");
+        let added_path = PathBuf::from("/test/added");
+
+        store.create_link(kept_inode, &kept_path).unwrap();
+        store.create_link(removed_inode, &removed_path).unwrap();
+        store.snapshot("before").unwrap();
+
+        store.create_link(kept_inode, &new_path_for_kept).unwrap();
+        store.remove_inode(removed_inode).unwrap();
+        store.create_link(added_inode, &added_path).unwrap();
+        store.snapshot("after").unwrap();
+
+        let mut changes = store.diff("before", "after").unwrap();
+        changes.sort_by_key(|c| format!("{:?}", c));
+
+        assert!(changes.contains(&Change::Added(kept_inode, new_path_for_kept.clone())));
+        assert!(changes.contains(&Change::Modified(kept_inode, 1, 2)));
+        assert!(changes.contains(&Change::Removed(removed_inode, removed_path.clone())));
+        assert!(changes.contains(&Change::Modified(removed_inode, 1, 0)));
+        assert!(changes.contains(&Change::Added(added_inode, added_path.clone())));
+        assert!(changes.contains(&Change::Modified(added_inode, 0, 1)));
+        assert_eq!(changes.len(), 6);
+    }
+
+    #[test]
+    fn test_diff_unknown_snapshot_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+        store.snapshot("only").unwrap();
+
+        assert!(store.diff("missing", "only").is_err());
+        assert!(store.diff("only", "missing").is_err());
+    }
+
+    #[test]
+    fn test_create_link_skips_excluded_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let exclude = ExcludeConfig {
+            excludes: Some(RegexSet::new([r"^/tmp/", r"\.cache/"]).unwrap()),
+            same_device: None,
+        };
+        let store = HardLinkStore::with_options(temp_dir.path(), exclude).unwrap();
+
+        let inode = 3000;
+        let count = store.create_link(inode, &PathBuf::from("/tmp/scratch")).unwrap();
+        assert_eq!(count, 0);
+        assert!(store.lookup_inode(&PathBuf::from("/tmp/scratch")).is_none());
+
+        let count = store
+            .create_link(inode, &PathBuf::from("/home/user/.cache/thing"))
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let count = store.create_link(inode, &PathBuf::from("/home/user/keep")).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_create_link_skips_cross_device_inodes() {
+        let temp_dir = TempDir::new().unwrap();
+        let exclude = ExcludeConfig {
+            excludes: None,
+            same_device: Some(1),
+        };
+        let store = HardLinkStore::with_options(temp_dir.path(), exclude).unwrap();
+
+        let inode = 3100;
+        let count = store
+            .create_link_on_device(inode, &PathBuf::from("/mnt/other/file"), Some(2))
+            .unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(store.lookup_inode(&PathBuf::from("/mnt/other/file")), None);
+
+        let count = store
+            .create_link_on_device(inode, &PathBuf::from("/data/file"), Some(1))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_inodes_under_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        store.create_link(4000, &PathBuf::from("/a/b/file1")).unwrap();
+        store.create_link(4001, &PathBuf::from("/a/b/c/file2")).unwrap();
+        store.create_link(4002, &PathBuf::from("/a/other")).unwrap();
+        // A sibling whose name merely shares the prefix as a substring
+        // must not be included.
+        store.create_link(4003, &PathBuf::from("/a/bogus")).unwrap();
+
+        let mut under_b: Vec<u64> = store.inodes_under(&PathBuf::from("/a/b"));
+        under_b.sort();
+        assert_eq!(under_b, vec![4000, 4001]);
+
+        let mut under_a: Vec<u64> = store.inodes_under(&PathBuf::from("/a"));
+        under_a.sort();
+        assert_eq!(under_a, vec![4000, 4001, 4002, 4003]);
+    }
+
+    #[test]
+    fn test_remove_subtree_drops_all_descendant_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        store.create_link(5000, &PathBuf::from("/dir/file1")).unwrap();
+        store.create_link(5001, &PathBuf::from("/dir/sub/file2")).unwrap();
+        store.create_link(5002, &PathBuf::from("/other/file3")).unwrap();
+
+        let removed = store.remove_subtree(&PathBuf::from("/dir")).unwrap();
+        assert_eq!(removed, 2);
+
+        assert!(store.get_paths(5000).is_empty());
+        assert!(store.get_paths(5001).is_empty());
+        assert_eq!(store.get_paths(5002), vec![PathBuf::from("/other/file3")]);
+        assert!(store.inodes_under(&PathBuf::from("/dir")).is_empty());
+    }
+
+    #[test]
+    fn test_is_unchanged() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 6000;
+        let path = PathBuf::from("/test/file");
+        store.create_link(inode, &path).unwrap();
+
+        let meta = PathMetadata {
+            mtime_secs: 1_700_000_000,
+            mtime_nanos: 123,
+            size: 4096,
+        };
+
+        // No metadata recorded yet - always considered changed.
+        assert!(!store.is_unchanged(inode, &path, meta));
+
+        store.record_metadata(inode, &path, meta).unwrap();
+        assert!(store.is_unchanged(inode, &path, meta));
+
+        let changed = PathMetadata { size: 4097, ..meta };
+        assert!(!store.is_unchanged(inode, &path, changed));
+
+        let changed_mtime = PathMetadata { mtime_nanos: 124, ..meta };
+        assert!(!store.is_unchanged(inode, &path, changed_mtime));
+    }
+
+    #[test]
+    fn test_metadata_cleared_on_remove_link_and_remove_inode() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HardLinkStore::open(temp_dir.path()).unwrap();
+
+        let inode = 6100;
+        let path = PathBuf::from("/test/file");
+        let meta = PathMetadata { mtime_secs: 1, mtime_nanos: 0, size: 1 };
+        store.create_link(inode, &path).unwrap();
+        store.record_metadata(inode, &path, meta).unwrap();
+
+        store.remove_link(inode, &path).unwrap();
+        assert!(!store.is_unchanged(inode, &path, meta));
+
+        let path2 = PathBuf::from("/test/file2");
+        store.create_link(inode, &path2).unwrap();
+        store.record_metadata(inode, &path2, meta).unwrap();
+        store.remove_inode(inode).unwrap();
+        assert!(!store.is_unchanged(inode, &path2, meta));
+    }
 }
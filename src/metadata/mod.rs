@@ -3,14 +3,16 @@
 //! Stores encrypted filesystem metadata in SQLite.
 //! All metadata is encrypted before storage using the metadata key.
 
+pub mod backend;
 mod hardlinks;
 mod inode;
 mod store;
 mod version;
 mod xattr;
 
+pub use backend::{Backend, BackendKind, ConvertReport, LmdbBackend, SledBackend, SqliteBackend, Tree};
 pub use hardlinks::HardLinkStore;
 pub use inode::{FileType, Inode, InodeAttributes};
 pub use store::MetadataStore;
 pub use version::{FileVersion, VersionManager};
-pub use xattr::XattrStore;
+pub use xattr::{InodeFlags, ListResult, XattrContext, XattrNamespace, XattrStats, XattrStore};
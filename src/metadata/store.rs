@@ -3,12 +3,16 @@
 //! All metadata is encrypted before storage. The database contains
 //! encrypted blobs that can only be read with the correct key.
 
-use crate::crypto::{decrypt, encrypt, EncryptedData, KEY_SIZE};
+use crate::cache::LruCache;
+use crate::chunk::ChunkId;
+use crate::crypto::{
+    decrypt, encrypt_with_nonce, Algorithm, EncryptedData, NonceSource, WrappedRootSecret, KEY_SIZE,
+};
 use crate::error::{Error, Result};
 use crate::metadata::Inode;
 use parking_lot::RwLock;
 use sled::{Db, Tree};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::{debug, info};
@@ -23,6 +27,25 @@ const CHUNK_PREFIX: &[u8] = b"chk:";
 #[allow(dead_code)]
 const META_PREFIX: &[u8] = b"meta:";
 
+/// Raw (unencrypted) sled key the per-key nonce counter is persisted
+/// under. One key encrypts every inode and metadata value this store will
+/// ever hold, so its nonces are deterministic counter values rather than
+/// random - see [`MetadataStore::next_nonce_counter`].
+const NONCE_COUNTER_KEY: &[u8] = b"__nonce_counter__";
+
+/// Raw sled key a store's [`WrappedRootSecret`] is persisted under, if it
+/// has been migrated to root-secret envelope wrapping. Like
+/// [`NONCE_COUNTER_KEY`], stored outside [`MetadataStore::save_metadata`]'s
+/// encryption path - unwrapping it is how the caller derives the key that
+/// path needs, so loading it can't depend on already having that key.
+const ROOT_SECRET_KEY: &[u8] = b"__wrapped_root_secret__";
+
+/// Default inode cache capacity (item count) until
+/// [`MetadataStore::with_inode_cache_capacity`] overrides it - enough to
+/// keep a typical hot working set resident without growing unbounded on
+/// a long-running mount over millions of inodes.
+const DEFAULT_INODE_CACHE_CAPACITY: u64 = 10_000;
+
 /// Encrypted metadata store using sled
 pub struct MetadataStore {
     /// Sled database
@@ -33,16 +56,51 @@ pub struct MetadataStore {
     parent_index: Tree,
     /// Chunk references tree
     chunks: Tree,
+    /// Erasure-coded block references tree, keyed by (account id, content
+    /// hash) - see [`Self::save_erasure_block_ref`].
+    erasure_blocks: Tree,
     /// General metadata tree
     metadata: Tree,
     /// Encryption key for metadata
     key: [u8; KEY_SIZE],
+    /// AEAD cipher new inodes and metadata values are encrypted with.
+    /// Existing records stay readable regardless - [`EncryptedData`] tags
+    /// each blob with the algorithm that wrote it.
+    algorithm: Algorithm,
+    /// Monotonic counter this store's single long-lived `key` derives its
+    /// GCM nonces from, so nonces never collide no matter how many
+    /// inodes/metadata values get encrypted under it over its lifetime.
+    nonce_counter: AtomicU64,
     /// Next available inode number
     next_ino: AtomicU64,
-    /// In-memory inode cache
+    /// In-memory inode cache values. Bounded by `cache_lru` - every key
+    /// here is tracked there and vice versa; see [`Self::cache_insert`].
     cache: RwLock<HashMap<u64, Inode>>,
+    /// Recency order and bounded eviction for `cache` - see
+    /// [`Self::with_inode_cache_capacity`].
+    cache_lru: RwLock<LruCache<u64>>,
+    /// Cache hit count, exposed via [`Self::get_stats`]'s [`FsStats`].
+    cache_hits: AtomicU64,
+    /// Cache miss count, exposed via [`Self::get_stats`]'s [`FsStats`].
+    cache_misses: AtomicU64,
     /// Optional namespace prefix for storage keys
     namespace_prefix: Option<String>,
+    /// Per-inode prewrite locks held by in-flight
+    /// [`crate::distributed::transaction::Transaction`]s, keyed by inode
+    /// number
+    txn_locks: RwLock<HashMap<u64, TxnLock>>,
+}
+
+/// A prewrite lock staked by [`MetadataStore::prewrite_lock`], released by
+/// either [`MetadataStore::commit_locked_inode`] or
+/// [`MetadataStore::release_lock`].
+#[derive(Debug, Clone)]
+struct TxnLock {
+    /// The transaction's client id, so a lock can only be committed or
+    /// released by the transaction that staked it
+    client_id: String,
+    /// The transaction's start timestamp
+    start_ts: u64,
 }
 
 impl MetadataStore {
@@ -56,21 +114,37 @@ impl MetadataStore {
         path: P,
         key: [u8; KEY_SIZE],
         namespace_prefix: Option<String>,
+    ) -> Result<Self> {
+        Self::open_with_namespace_and_algorithm(path, key, namespace_prefix, Algorithm::default())
+    }
+
+    /// Open or create a metadata store with a namespace prefix, encrypting
+    /// new records with `algorithm` instead of the default
+    /// [`Algorithm::Aes256Gcm`] - see
+    /// [`crate::config::EncryptionConfig::algorithm`]. Existing records
+    /// stay readable no matter which algorithm wrote them.
+    pub fn open_with_namespace_and_algorithm<P: AsRef<Path>>(
+        path: P,
+        key: [u8; KEY_SIZE],
+        namespace_prefix: Option<String>,
+        algorithm: Algorithm,
     ) -> Result<Self> {
         let db = sled::open(path.as_ref())?;
 
         // Use namespace-prefixed tree names if namespace is provided
-        let (inodes_name, parent_name, chunks_name, metadata_name) = match &namespace_prefix {
+        let (inodes_name, parent_name, chunks_name, erasure_blocks_name, metadata_name) = match &namespace_prefix {
             Some(prefix) => (
                 format!("{}:inodes", prefix),
                 format!("{}:parent_index", prefix),
                 format!("{}:chunks", prefix),
+                format!("{}:erasure_blocks", prefix),
                 format!("{}:metadata", prefix),
             ),
             None => (
                 "inodes".to_string(),
                 "parent_index".to_string(),
                 "chunks".to_string(),
+                "erasure_blocks".to_string(),
                 "metadata".to_string(),
             ),
         };
@@ -78,6 +152,7 @@ impl MetadataStore {
         let inodes = db.open_tree(&inodes_name)?;
         let parent_index = db.open_tree(&parent_name)?;
         let chunks = db.open_tree(&chunks_name)?;
+        let erasure_blocks = db.open_tree(&erasure_blocks_name)?;
         let metadata = db.open_tree(&metadata_name)?;
 
         // Get max inode number
@@ -95,16 +170,25 @@ impl MetadataStore {
             .max()
             .unwrap_or(0);
 
+        let nonce_counter = AtomicU64::new(Self::load_nonce_counter(&metadata)?);
+
         let store = MetadataStore {
             db,
             inodes,
             parent_index,
             chunks,
+            erasure_blocks,
             metadata,
             key,
+            algorithm,
+            nonce_counter,
             next_ino: AtomicU64::new(max_ino + 1),
             cache: RwLock::new(HashMap::new()),
+            cache_lru: RwLock::new(LruCache::with_capacity(DEFAULT_INODE_CACHE_CAPACITY)),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
             namespace_prefix,
+            txn_locks: RwLock::new(HashMap::new()),
         };
 
         // Initialize root if needed
@@ -128,21 +212,33 @@ impl MetadataStore {
     pub fn in_memory_with_namespace(
         key: [u8; KEY_SIZE],
         namespace_prefix: Option<String>,
+    ) -> Result<Self> {
+        Self::in_memory_with_algorithm(key, namespace_prefix, Algorithm::default())
+    }
+
+    /// Create an in-memory store with namespace prefix and a specific AEAD
+    /// cipher (for testing algorithm-specific behavior)
+    pub fn in_memory_with_algorithm(
+        key: [u8; KEY_SIZE],
+        namespace_prefix: Option<String>,
+        algorithm: Algorithm,
     ) -> Result<Self> {
         let db = sled::Config::new().temporary(true).open()?;
 
         // Use namespace-prefixed tree names if namespace is provided
-        let (inodes_name, parent_name, chunks_name, metadata_name) = match &namespace_prefix {
+        let (inodes_name, parent_name, chunks_name, erasure_blocks_name, metadata_name) = match &namespace_prefix {
             Some(prefix) => (
                 format!("{}:inodes", prefix),
                 format!("{}:parent_index", prefix),
                 format!("{}:chunks", prefix),
+                format!("{}:erasure_blocks", prefix),
                 format!("{}:metadata", prefix),
             ),
             None => (
                 "inodes".to_string(),
                 "parent_index".to_string(),
                 "chunks".to_string(),
+                "erasure_blocks".to_string(),
                 "metadata".to_string(),
             ),
         };
@@ -150,18 +246,27 @@ impl MetadataStore {
         let inodes = db.open_tree(&inodes_name)?;
         let parent_index = db.open_tree(&parent_name)?;
         let chunks = db.open_tree(&chunks_name)?;
+        let erasure_blocks = db.open_tree(&erasure_blocks_name)?;
         let metadata = db.open_tree(&metadata_name)?;
+        let nonce_counter = AtomicU64::new(Self::load_nonce_counter(&metadata)?);
 
         let store = MetadataStore {
             db,
             inodes,
             parent_index,
             chunks,
+            erasure_blocks,
             metadata,
             key,
+            algorithm,
+            nonce_counter,
             next_ino: AtomicU64::new(1),
             cache: RwLock::new(HashMap::new()),
+            cache_lru: RwLock::new(LruCache::with_capacity(DEFAULT_INODE_CACHE_CAPACITY)),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
             namespace_prefix,
+            txn_locks: RwLock::new(HashMap::new()),
         };
 
         store.init_root()?;
@@ -184,6 +289,47 @@ impl MetadataStore {
         self.next_ino.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// Read the nonce counter persisted under [`NONCE_COUNTER_KEY`], or 0
+    /// for a fresh store. Stored raw (not through [`Self::save_metadata`]):
+    /// encrypting the counter would itself need a nonce.
+    fn load_nonce_counter(metadata: &Tree) -> Result<u64> {
+        Ok(metadata
+            .get(NONCE_COUNTER_KEY)?
+            .and_then(|bytes| bytes.as_ref().try_into().ok())
+            .map(u64::from_be_bytes)
+            .unwrap_or(0))
+    }
+
+    /// Hand out the next value of this store's nonce counter, persisting
+    /// the new high-water mark before returning so a restart never reuses
+    /// a value - every encryption under `self.key` gets a nonce that's
+    /// never been used before, without relying on randomness.
+    fn next_nonce_counter(&self) -> Result<u64> {
+        let value = self.nonce_counter.fetch_add(1, Ordering::SeqCst);
+        self.metadata.insert(NONCE_COUNTER_KEY, &(value + 1).to_be_bytes())?;
+        Ok(value)
+    }
+
+    /// Load this store's wrapped root secret, if it has been migrated to
+    /// root-secret envelope wrapping (see [`crate::crypto::KeyManager::unlock`]).
+    /// Returns `None` for a store that predates the scheme, which the
+    /// caller should treat as "generate and save one now".
+    pub fn load_wrapped_root_secret(&self) -> Result<Option<WrappedRootSecret>> {
+        match self.metadata.get(ROOT_SECRET_KEY)? {
+            Some(bytes) => Ok(Some(WrappedRootSecret::from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Persist `wrapped` - a freshly generated or freshly rewrapped root
+    /// secret - directly to the metadata tree, bypassing the usual
+    /// encryption path for the same bootstrapping reason as
+    /// [`Self::load_wrapped_root_secret`].
+    pub fn save_wrapped_root_secret(&self, wrapped: &WrappedRootSecret) -> Result<()> {
+        self.metadata.insert(ROOT_SECRET_KEY, wrapped.to_bytes())?;
+        Ok(())
+    }
+
     /// Create inode key from ino
     fn inode_key(ino: u64) -> [u8; 8] {
         ino.to_be_bytes()
@@ -197,10 +343,13 @@ impl MetadataStore {
         key
     }
 
-    /// Encrypt an inode for storage
+    /// Encrypt an inode for storage. Uses a counter-derived nonce rather
+    /// than a random one: this one key encrypts every inode the store
+    /// will ever hold, far more than the ~2^32 encryptions after which a
+    /// random 96-bit nonce starts risking collision.
     fn encrypt_inode(&self, inode: &Inode) -> Result<Vec<u8>> {
         let data = bincode::serialize(inode)?;
-        let encrypted = encrypt(&self.key, &data, &[])?;
+        let encrypted = encrypt_with_nonce(self.algorithm, &self.key, &data, &[], NonceSource::Counter(self.next_nonce_counter()?))?;
         Ok(encrypted.to_bytes())
     }
 
@@ -212,6 +361,39 @@ impl MetadataStore {
         Ok(inode)
     }
 
+    /// Insert or refresh `inode` in the bounded inode cache, evicting the
+    /// least-recently-used entry if this pushes it over
+    /// [`Self::with_inode_cache_capacity`]'s limit. The single place
+    /// `cache` and `cache_lru` are written together, so they can never
+    /// drift out of sync.
+    fn cache_insert(&self, inode: &Inode) {
+        let evicted = self.cache_lru.write().insert(inode.ino);
+        let mut cache = self.cache.write();
+        cache.insert(inode.ino, inode.clone());
+        for ino in evicted {
+            cache.remove(&ino);
+        }
+    }
+
+    /// Look up `ino` in the cache, touching its recency on a hit and
+    /// counting the result toward [`Self::get_stats`]'s [`FsStats`].
+    fn cache_get(&self, ino: u64) -> Option<Inode> {
+        let hit = self.cache.read().get(&ino).cloned();
+        if hit.is_some() {
+            self.cache_lru.write().touch(&ino);
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    /// Drop `ino` from the cache, e.g. on delete.
+    fn cache_remove(&self, ino: u64) {
+        self.cache.write().remove(&ino);
+        self.cache_lru.write().remove(&ino);
+    }
+
     /// Save an inode to the database
     pub fn save_inode(&self, inode: &Inode) -> Result<()> {
         let encrypted = self.encrypt_inode(inode)?;
@@ -225,7 +407,7 @@ impl MetadataStore {
         self.parent_index.insert(parent_key, &key[..])?;
 
         // Update cache
-        self.cache.write().insert(inode.ino, inode.clone());
+        self.cache_insert(inode);
 
         debug!("Saved inode {} ({})", inode.ino, inode.name);
         Ok(())
@@ -234,15 +416,15 @@ impl MetadataStore {
     /// Get an inode by number
     pub fn get_inode(&self, ino: u64) -> Result<Option<Inode>> {
         // Check cache first
-        if let Some(inode) = self.cache.read().get(&ino) {
-            return Ok(Some(inode.clone()));
+        if let Some(inode) = self.cache_get(ino) {
+            return Ok(Some(inode));
         }
 
         let key = Self::inode_key(ino);
         match self.inodes.get(key)? {
             Some(data) => {
                 let inode = self.decrypt_inode(&data)?;
-                self.cache.write().insert(ino, inode.clone());
+                self.cache_insert(&inode);
                 Ok(Some(inode))
             }
             None => Ok(None),
@@ -282,11 +464,24 @@ impl MetadataStore {
 
         let key = Self::inode_key(ino);
         self.inodes.remove(key)?;
-        self.cache.write().remove(&ino);
+        self.cache_remove(ino);
         debug!("Deleted inode {}", ino);
         Ok(())
     }
 
+    /// Every inode currently stored, in no particular order. Used by
+    /// passes that must walk the whole filesystem regardless of directory
+    /// structure - e.g. `raid migrate-to-erasure` finding every
+    /// single-account `ChunkManifest` left to convert.
+    pub fn list_all_inodes(&self) -> Result<Vec<Inode>> {
+        let mut inodes = Vec::new();
+        for result in self.inodes.iter() {
+            let (_, data) = result?;
+            inodes.push(self.decrypt_inode(&data)?);
+        }
+        Ok(inodes)
+    }
+
     /// Get all children of a directory
     pub fn get_children(&self, parent: u64) -> Result<Vec<Inode>> {
         let prefix = parent.to_be_bytes();
@@ -344,6 +539,37 @@ impl MetadataStore {
         }
     }
 
+    /// Whether a chunk with this content-addressed id is already stored.
+    /// Just a presence check on [`Self::get_chunk_ref`] - kept as its own
+    /// method since "does this chunk exist" reads clearer at call sites
+    /// than `get_chunk_ref(..).is_some()`. Note that checking this and
+    /// then calling [`Self::get_chunk_ref`] separately is not atomic; the
+    /// write path's dedup check uses a single `get_chunk_ref` call instead
+    /// of composing the two.
+    pub fn chunk_exists(&self, chunk_id: &str) -> Result<bool> {
+        Ok(self.get_chunk_ref(chunk_id)?.is_some())
+    }
+
+    /// List every chunk id this store currently holds a reference for.
+    pub fn list_chunk_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for item in self.chunks.iter() {
+            let (key, _) = item?;
+            let id = String::from_utf8(key.to_vec())
+                .map_err(|e| Error::Deserialization(format!("non-UTF8 chunk id: {e}")))?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Remove a chunk reference outright, regardless of ref count. Used
+    /// when garbage-collecting chunks a snapshot's live-chunk filter
+    /// identifies as no longer referenced by anything.
+    pub fn remove_chunk_ref(&self, chunk_id: &str) -> Result<()> {
+        self.chunks.remove(chunk_id.as_bytes())?;
+        Ok(())
+    }
+
     /// Decrement chunk reference count
     pub fn decrement_chunk_ref(&self, chunk_id: &str) -> Result<Option<i32>> {
         let key = chunk_id.as_bytes();
@@ -370,9 +596,131 @@ impl MetadataStore {
         }
     }
 
+    /// Recompute every chunk's true refcount from what inodes actually
+    /// reference, rewrite the `chunks` tree to match, and return the
+    /// `message_id`s whose refcount dropped to zero so the caller can
+    /// delete them remotely. Repairs drift left behind by a crash
+    /// mid-delete, where [`Self::decrement_chunk_ref`] never ran and a
+    /// chunk's stored count overstates how many inodes still point at it.
+    ///
+    /// Takes its read of the inode tree and the `chunks` tree as two
+    /// separate passes rather than one sled transaction spanning both -
+    /// sled's own snapshot isolation per-tree is enough here, since the
+    /// worst a concurrent write can do is make vacuum conservative (an
+    /// inode saved mid-vacuum is counted either way, never missed), and a
+    /// chunk is only ever rewritten to the count just computed for it, so
+    /// a chunk still referenced by anything this pass saw is never
+    /// deleted.
+    pub fn vacuum(&self) -> Result<Vec<i32>> {
+        let mut true_refs: HashMap<String, (i32, u32)> = HashMap::new();
+        for inode in self.list_all_inodes()? {
+            let Some(manifest) = &inode.manifest else { continue };
+            for chunk in &manifest.chunks {
+                let Some(message_id) = chunk.message_id() else { continue };
+                true_refs.entry(chunk.id.to_string()).or_insert((message_id, 0)).1 += 1;
+            }
+        }
+
+        let mut freed = Vec::new();
+        for chunk_id in self.list_chunk_ids()? {
+            match true_refs.get(&chunk_id) {
+                Some((message_id, count)) => {
+                    let mut value = Vec::with_capacity(8);
+                    value.extend_from_slice(&message_id.to_be_bytes());
+                    value.extend_from_slice(&count.to_be_bytes());
+                    self.chunks.insert(chunk_id.as_bytes(), value)?;
+                }
+                None => {
+                    if let Some(message_id) = self.get_chunk_ref(&chunk_id)? {
+                        self.remove_chunk_ref(&chunk_id)?;
+                        freed.push(message_id);
+                    }
+                }
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Key an erasure block ref by the account it's stored on plus its
+    /// content hash - the same shard bytes can legitimately live under
+    /// different hashes on different accounts only if re-encoded, but
+    /// never need to be looked up cross-account since `raid-migrate`
+    /// always re-checks the specific account a block is about to land on.
+    fn erasure_block_key(account_id: u8, hash: &str) -> Vec<u8> {
+        let mut key = Vec::with_capacity(1 + hash.len());
+        key.push(account_id);
+        key.extend_from_slice(hash.as_bytes());
+        key
+    }
+
+    /// Record that an erasure-coded block with the given content hash is
+    /// stored as `message_id` on `account_id`, bumping its reference
+    /// count if one is already on file. Mirrors [`Self::save_chunk_ref`]
+    /// but scoped per-account, since each account is a distinct Telegram
+    /// chat a block can only be deleted from by whoever uploaded it.
+    pub fn save_erasure_block_ref(&self, account_id: u8, hash: &str, message_id: i32) -> Result<()> {
+        let key = Self::erasure_block_key(account_id, hash);
+
+        let ref_count = match self.erasure_blocks.get(&key)? {
+            Some(data) if data.len() >= 8 => {
+                let count = u32::from_be_bytes(data[4..8].try_into().unwrap());
+                count + 1
+            }
+            _ => 1,
+        };
+
+        let mut value = Vec::with_capacity(8);
+        value.extend_from_slice(&message_id.to_be_bytes());
+        value.extend_from_slice(&ref_count.to_be_bytes());
+
+        self.erasure_blocks.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Look up the message a previously-uploaded block with this content
+    /// hash lives at on `account_id`, without touching its refcount.
+    /// `raid-migrate` calls this before encoding/uploading a chunk so a
+    /// duplicate block can point at the existing message instead.
+    pub fn get_erasure_block_ref(&self, account_id: u8, hash: &str) -> Result<Option<i32>> {
+        let key = Self::erasure_block_key(account_id, hash);
+
+        match self.erasure_blocks.get(key)? {
+            Some(data) if data.len() >= 4 => Ok(Some(i32::from_be_bytes(data[..4].try_into().unwrap()))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Release one reference to a previously deduplicated erasure block.
+    /// Returns the message id to delete from Telegram once the refcount
+    /// reaches zero, or `None` if other stripes still reference it.
+    /// Called from `cmd_raid_scrub`'s repair path and from manifest
+    /// deletion once either exists.
+    pub fn decrement_erasure_block_ref(&self, account_id: u8, hash: &str) -> Result<Option<i32>> {
+        let key = Self::erasure_block_key(account_id, hash);
+
+        match self.erasure_blocks.get(&key)? {
+            Some(data) if data.len() >= 8 => {
+                let msg_id = i32::from_be_bytes(data[..4].try_into().unwrap());
+                let ref_count = u32::from_be_bytes(data[4..8].try_into().unwrap());
+
+                if ref_count <= 1 {
+                    self.erasure_blocks.remove(key)?;
+                    Ok(Some(msg_id))
+                } else {
+                    let mut value = Vec::with_capacity(8);
+                    value.extend_from_slice(&msg_id.to_be_bytes());
+                    value.extend_from_slice(&(ref_count - 1).to_be_bytes());
+                    self.erasure_blocks.insert(key, value)?;
+                    Ok(None)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Save general metadata
     pub fn save_metadata(&self, key: &str, value: &[u8]) -> Result<()> {
-        let encrypted = encrypt(&self.key, value, &[])?;
+        let encrypted = encrypt_with_nonce(self.algorithm, &self.key, value, &[], NonceSource::Counter(self.next_nonce_counter()?))?;
         self.metadata.insert(key.as_bytes(), encrypted.to_bytes())?;
         Ok(())
     }
@@ -389,20 +737,84 @@ impl MetadataStore {
         }
     }
 
-    /// Get filesystem statistics
+    /// Delete a general metadata entry
+    pub fn delete_metadata(&self, key: &str) -> Result<()> {
+        self.metadata.remove(key.as_bytes())?;
+        Ok(())
+    }
+
+    /// List every metadata key (and decrypted value) whose key starts with
+    /// `prefix`, e.g. scanning `snapshot_meta:` to enumerate every
+    /// registered snapshot without knowing its id ahead of time.
+    pub fn scan_metadata_prefix(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        for item in self.metadata.scan_prefix(prefix.as_bytes()) {
+            let (key, data) = item?;
+            let key = String::from_utf8(key.to_vec())
+                .map_err(|e| Error::Deserialization(format!("non-UTF8 metadata key: {e}")))?;
+            let encrypted = EncryptedData::from_bytes(&data)?;
+            let decrypted = decrypt(&self.key, &encrypted, &[])?;
+            entries.push((key, decrypted));
+        }
+        Ok(entries)
+    }
+
+    /// Get filesystem statistics, including dedup/space-reclamation
+    /// effectiveness: how much of the `chunks` tree [`Self::vacuum`] would
+    /// reclaim, and how much deduplication is buying back from the
+    /// logical (uncompressed, per-inode) size actually stored.
     pub fn get_stats(&self) -> Result<FsStats> {
         let inode_count = self.inodes.len() as u64;
         let chunk_count = self.chunks.len() as u64;
 
+        let mut referenced: HashSet<ChunkId> = HashSet::new();
+        let mut physical_sizes: HashMap<ChunkId, u64> = HashMap::new();
+        let mut total_logical_bytes = 0u64;
+
+        for inode in self.list_all_inodes()? {
+            total_logical_bytes += inode.attrs.size;
+            let Some(manifest) = &inode.manifest else { continue };
+            for chunk in &manifest.chunks {
+                referenced.insert(chunk.id.clone());
+                physical_sizes.insert(chunk.id.clone(), chunk.size);
+            }
+        }
+
+        let orphaned_chunks = self
+            .list_chunk_ids()?
+            .into_iter()
+            .filter(|id| !referenced.contains(&ChunkId::from(id.clone())))
+            .count() as u64;
+        let referenced_chunks = chunk_count.saturating_sub(orphaned_chunks);
+        let total_physical_bytes = physical_sizes.values().sum();
+
         Ok(FsStats {
             inode_count,
             chunk_count,
+            referenced_chunks,
+            orphaned_chunks,
+            total_logical_bytes,
+            total_physical_bytes,
+            inode_cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            inode_cache_misses: self.cache_misses.load(Ordering::Relaxed),
         })
     }
 
     /// Clear the cache
     pub fn clear_cache(&self) {
         self.cache.write().clear();
+        self.cache_lru.write().clear();
+    }
+
+    /// Override the inode cache's capacity (item count) from the
+    /// [`DEFAULT_INODE_CACHE_CAPACITY`] every constructor otherwise uses -
+    /// e.g. a mount expecting a much larger or smaller working set than
+    /// the default assumes. Resets the cache, so call this right after
+    /// construction rather than mid-flight.
+    pub fn with_inode_cache_capacity(mut self, capacity: u64) -> Self {
+        self.cache.write().clear();
+        self.cache_lru = RwLock::new(LruCache::with_capacity(capacity));
+        self
     }
 
     /// Flush to disk
@@ -420,19 +832,104 @@ impl MetadataStore {
     pub fn is_namespaced(&self) -> bool {
         self.namespace_prefix.is_some()
     }
+
+    /// Prewrite phase of a [`crate::distributed::transaction::Transaction`]
+    /// commit: stake a lock on `ino` under `client_id`/`start_ts`, failing
+    /// with [`Error::TransactionConflict`] if another transaction already
+    /// holds a lock on it, or if `ino`'s current version has moved past
+    /// `expected_version` since the transaction read it.
+    pub(crate) fn prewrite_lock(
+        &self,
+        ino: u64,
+        expected_version: u64,
+        client_id: &str,
+        start_ts: u64,
+    ) -> Result<()> {
+        let current = self.get_inode_required(ino)?;
+        if current.version != expected_version {
+            return Err(Error::TransactionConflict(format!(
+                "inode {} is at version {}, expected {}",
+                ino, current.version, expected_version
+            )));
+        }
+
+        let mut locks = self.txn_locks.write();
+        if let Some(held) = locks.get(&ino) {
+            if held.client_id != client_id {
+                return Err(Error::TransactionConflict(format!(
+                    "inode {} is locked by transaction '{}' (started at {})",
+                    ino, held.client_id, held.start_ts
+                )));
+            }
+        }
+        locks.insert(ino, TxnLock { client_id: client_id.to_string(), start_ts });
+        Ok(())
+    }
+
+    /// Commit phase: save `inode`, provided `client_id` still holds its
+    /// prewrite lock, then release the lock. A lock can go missing if the
+    /// caller's own retry loop released it first; treat that as a
+    /// conflict rather than saving over a lock nobody verified is still
+    /// ours.
+    pub(crate) fn commit_locked_inode(&self, inode: &Inode, client_id: &str) -> Result<()> {
+        {
+            let mut locks = self.txn_locks.write();
+            match locks.get(&inode.ino) {
+                Some(held) if held.client_id == client_id => {
+                    locks.remove(&inode.ino);
+                }
+                _ => {
+                    return Err(Error::TransactionConflict(format!(
+                        "lost prewrite lock on inode {} before commit",
+                        inode.ino
+                    )))
+                }
+            }
+        }
+        self.save_inode(inode)
+    }
+
+    /// Release a prewrite lock without committing, e.g. when a
+    /// transaction aborts after partially prewriting its keys.
+    pub(crate) fn release_lock(&self, ino: u64, client_id: &str) {
+        let mut locks = self.txn_locks.write();
+        if locks.get(&ino).map(|held| held.client_id.as_str()) == Some(client_id) {
+            locks.remove(&ino);
+        }
+    }
 }
 
 /// Filesystem statistics
 #[derive(Debug, Clone)]
 pub struct FsStats {
     pub inode_count: u64,
+    /// Total entries in the `chunks` tree, including orphans.
     pub chunk_count: u64,
+    /// Chunks in `chunks` that at least one inode's manifest still points
+    /// at, as of this call.
+    pub referenced_chunks: u64,
+    /// Chunks in `chunks` no inode references any more - what
+    /// [`MetadataStore::vacuum`] would reclaim if run now.
+    pub orphaned_chunks: u64,
+    /// Sum of every inode's logical (uncompressed) size. Compared against
+    /// `total_physical_bytes`, this is how much space compression and
+    /// deduplication are buying back.
+    pub total_logical_bytes: u64,
+    /// Sum of every distinct chunk's stored (encrypted, compressed) size,
+    /// deduplicated by content id - the actual bytes occupying storage.
+    pub total_physical_bytes: u64,
+    /// Inode lookups served from the in-memory cache since this store was
+    /// opened. See [`MetadataStore::with_inode_cache_capacity`].
+    pub inode_cache_hits: u64,
+    /// Inode lookups that missed the cache and fell through to sled.
+    pub inode_cache_misses: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use rand::RngCore;
+    use tempfile::TempDir;
 
     fn test_key() -> [u8; KEY_SIZE] {
         let mut key = [0u8; KEY_SIZE];
@@ -507,6 +1004,68 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_inode_cache_evicts_least_recently_used_beyond_capacity() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap().with_inode_cache_capacity(2);
+
+        for i in 2..5 {
+            let file = Inode::new_file(i, 1, format!("file{}.txt", i), 1000, 1000, 0o644);
+            store.save_inode(&file).unwrap();
+        }
+
+        // Capacity 2: inode 2 (least recently touched) should have been
+        // evicted from the cache by inode 4's insert, forcing a miss that
+        // falls through to sled and re-populates it.
+        let misses_before = store.get_stats().unwrap().inode_cache_misses;
+        store.get_inode(2).unwrap();
+        let misses_after = store.get_stats().unwrap().inode_cache_misses;
+        assert_eq!(misses_after, misses_before + 1);
+    }
+
+    #[test]
+    fn test_inode_cache_get_touches_recency_on_hit() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap().with_inode_cache_capacity(2);
+
+        let a = Inode::new_file(2, 1, "a.txt".to_string(), 1000, 1000, 0o644);
+        let b = Inode::new_file(3, 1, "b.txt".to_string(), 1000, 1000, 0o644);
+        store.save_inode(&a).unwrap();
+        store.save_inode(&b).unwrap();
+
+        // Touch inode 2 so it's the most recently used, then insert a third
+        // inode - inode 3, not 2, should be evicted.
+        store.get_inode(2).unwrap();
+        let c = Inode::new_file(4, 1, "c.txt".to_string(), 1000, 1000, 0o644);
+        store.save_inode(&c).unwrap();
+
+        let misses_before = store.get_stats().unwrap().inode_cache_misses;
+        store.get_inode(2).unwrap();
+        assert_eq!(store.get_stats().unwrap().inode_cache_misses, misses_before, "inode 2 should still be cached");
+
+        let misses_before = store.get_stats().unwrap().inode_cache_misses;
+        store.get_inode(3).unwrap();
+        assert_eq!(store.get_stats().unwrap().inode_cache_misses, misses_before + 1, "inode 3 should have been evicted");
+    }
+
+    #[test]
+    fn test_inode_cache_hit_and_miss_counters() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        let file = Inode::new_file(2, 1, "test.txt".to_string(), 1000, 1000, 0o644);
+        store.save_inode(&file).unwrap();
+        store.clear_cache();
+
+        let before = store.get_stats().unwrap();
+        store.get_inode(2).unwrap(); // miss, repopulates cache
+        store.get_inode(2).unwrap(); // hit
+        let after = store.get_stats().unwrap();
+
+        assert_eq!(after.inode_cache_misses, before.inode_cache_misses + 1);
+        assert_eq!(after.inode_cache_hits, before.inode_cache_hits + 1);
+    }
+
     #[test]
     fn test_chunk_refs() {
         let key = test_key();
@@ -527,6 +1086,113 @@ mod tests {
         assert!(store.get_chunk_ref("chunk1").unwrap().is_none());
     }
 
+    #[test]
+    fn test_chunk_exists_reflects_whether_a_ref_is_on_file() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        assert!(!store.chunk_exists("chunk1").unwrap());
+
+        store.save_chunk_ref("chunk1", 100).unwrap();
+        assert!(store.chunk_exists("chunk1").unwrap());
+
+        store.decrement_chunk_ref("chunk1").unwrap();
+        assert!(!store.chunk_exists("chunk1").unwrap());
+    }
+
+    fn file_with_chunk(ino: u64, name: &str, chunk_id: &str, message_id: i32, size: u64) -> Inode {
+        let mut file = Inode::new_file(ino, 1, name.to_string(), 1000, 1000, 0o644);
+        file.attrs.size = size;
+        let mut manifest = crate::chunk::ChunkManifest::new(1);
+        manifest.chunks.push(crate::chunk::ChunkRef {
+            id: ChunkId::from(chunk_id.to_string()),
+            size,
+            payload: crate::chunk::ChunkPayload::Remote { message_id },
+            offset: 0,
+            original_size: size,
+            compression: crate::chunk::CompressionAlgo::None,
+        });
+        file.manifest = Some(manifest);
+        file
+    }
+
+    #[test]
+    fn test_vacuum_frees_chunks_no_inode_references_any_more() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        // Overstate "orphaned"'s refcount the way a crash mid-delete
+        // would: save_chunk_ref was called twice, but only one inode
+        // (which we then delete) ever actually pointed at it.
+        store.save_chunk_ref("orphaned", 1).unwrap();
+        store.save_chunk_ref("orphaned", 1).unwrap();
+        store.save_chunk_ref("still-referenced", 2).unwrap();
+
+        let kept = file_with_chunk(2, "kept.txt", "still-referenced", 2, 10);
+        store.save_inode(&kept).unwrap();
+
+        let freed = store.vacuum().unwrap();
+        assert_eq!(freed, vec![1]);
+        assert!(store.get_chunk_ref("orphaned").unwrap().is_none());
+        assert_eq!(store.get_chunk_ref("still-referenced").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_vacuum_corrects_an_overstated_refcount_without_freeing_it() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        // Two inodes used to share this chunk, inflating its refcount to
+        // 2, but only one remains - vacuum should bring the count down to
+        // 1 rather than freeing it outright.
+        store.save_chunk_ref("shared", 5).unwrap();
+        store.save_chunk_ref("shared", 5).unwrap();
+        let kept = file_with_chunk(2, "kept.txt", "shared", 5, 10);
+        store.save_inode(&kept).unwrap();
+
+        assert!(store.vacuum().unwrap().is_empty());
+        // Refcount is now exactly 1, so one decrement frees it.
+        assert_eq!(store.decrement_chunk_ref("shared").unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_stats_report_dedup_and_orphan_effectiveness() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        store.save_chunk_ref("orphaned", 1).unwrap();
+        store.save_chunk_ref("shared", 2).unwrap();
+
+        let a = file_with_chunk(2, "a.txt", "shared", 2, 100);
+        let b = file_with_chunk(3, "b.txt", "shared", 2, 100);
+        store.save_inode(&a).unwrap();
+        store.save_inode(&b).unwrap();
+
+        let stats = store.get_stats().unwrap();
+        assert_eq!(stats.chunk_count, 2); // "orphaned" + "shared"
+        assert_eq!(stats.referenced_chunks, 1); // only "shared"
+        assert_eq!(stats.orphaned_chunks, 1); // "orphaned"
+        assert_eq!(stats.total_logical_bytes, 200); // 100 + 100, not deduplicated
+        assert_eq!(stats.total_physical_bytes, 100); // "shared" counted once
+    }
+
+    #[test]
+    fn test_erasure_block_refs_are_scoped_per_account() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        store.save_erasure_block_ref(0, "shard-hash", 100).unwrap();
+        store.save_erasure_block_ref(0, "shard-hash", 100).unwrap(); // Add reference
+
+        assert_eq!(store.get_erasure_block_ref(0, "shard-hash").unwrap(), Some(100));
+        // Same hash on a different account is untracked until recorded there.
+        assert!(store.get_erasure_block_ref(1, "shard-hash").unwrap().is_none());
+
+        assert!(store.decrement_erasure_block_ref(0, "shard-hash").unwrap().is_none());
+        assert_eq!(store.decrement_erasure_block_ref(0, "shard-hash").unwrap(), Some(100));
+        assert!(store.get_erasure_block_ref(0, "shard-hash").unwrap().is_none());
+    }
+
     #[test]
     fn test_metadata() {
         let key = test_key();
@@ -537,4 +1203,130 @@ mod tests {
         let value = store.get_metadata("test_key").unwrap().unwrap();
         assert_eq!(value, b"test_value");
     }
+
+    #[test]
+    fn test_delete_metadata() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        store.save_metadata("test_key", b"test_value").unwrap();
+        store.delete_metadata("test_key").unwrap();
+
+        assert!(store.get_metadata("test_key").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_scan_metadata_prefix_finds_only_matching_keys() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        store.save_metadata("snapshot_meta:a", b"one").unwrap();
+        store.save_metadata("snapshot_meta:b", b"two").unwrap();
+        store.save_metadata("other:c", b"three").unwrap();
+
+        let mut entries = store.scan_metadata_prefix("snapshot_meta:").unwrap();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], ("snapshot_meta:a".to_string(), b"one".to_vec()));
+        assert_eq!(entries[1], ("snapshot_meta:b".to_string(), b"two".to_vec()));
+    }
+
+    #[test]
+    fn test_nonce_counter_survives_reopen() {
+        let key = test_key();
+        let dir = TempDir::new().unwrap();
+
+        {
+            let store = MetadataStore::open(dir.path(), key).unwrap();
+            store.save_metadata("a", b"one").unwrap();
+            store.save_metadata("b", b"two").unwrap();
+        }
+
+        // Reopening must resume the counter rather than restart it at zero,
+        // or a reused nonce would let an attacker break semantic security.
+        let store = MetadataStore::open(dir.path(), key).unwrap();
+        store.save_metadata("c", b"three").unwrap();
+        assert_eq!(store.get_metadata("c").unwrap().unwrap(), b"three");
+        assert_eq!(store.nonce_counter.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_open_defaults_to_aes256_gcm() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+        assert_eq!(store.algorithm, Algorithm::Aes256Gcm);
+    }
+
+    #[test]
+    fn test_store_reads_and_writes_under_configured_algorithm() {
+        let key = test_key();
+        let store =
+            MetadataStore::in_memory_with_algorithm(key, None, Algorithm::ChaCha20Poly1305)
+                .unwrap();
+
+        let file = Inode::new_file(2, 1, "test.txt".to_string(), 1000, 1000, 0o644);
+        store.save_inode(&file).unwrap();
+
+        let retrieved = store.get_inode(2).unwrap().unwrap();
+        assert_eq!(retrieved.name, "test.txt");
+    }
+
+    #[test]
+    fn test_records_written_under_one_algorithm_survive_a_reopen_under_another() {
+        let key = test_key();
+        let dir = TempDir::new().unwrap();
+
+        {
+            let store = MetadataStore::open_with_namespace_and_algorithm(
+                dir.path(),
+                key,
+                None,
+                Algorithm::ChaCha20Poly1305,
+            )
+            .unwrap();
+            store.save_metadata("a", b"one").unwrap();
+        }
+
+        // Reopening under a different algorithm must still be able to
+        // decrypt records the previous algorithm wrote - EncryptedData
+        // tags each blob, so a mixed-cipher store never loses data.
+        let store = MetadataStore::open_with_namespace_and_algorithm(
+            dir.path(),
+            key,
+            None,
+            Algorithm::Aes256Gcm,
+        )
+        .unwrap();
+        assert_eq!(store.get_metadata("a").unwrap().unwrap(), b"one");
+
+        store.save_metadata("b", b"two").unwrap();
+        assert_eq!(store.get_metadata("b").unwrap().unwrap(), b"two");
+    }
+
+    #[test]
+    fn test_fresh_store_has_no_wrapped_root_secret() {
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+        assert!(store.load_wrapped_root_secret().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_wrapped_root_secret_round_trips_through_the_store() {
+        use crate::crypto::{MasterKey, RootSecret};
+
+        let key = test_key();
+        let store = MetadataStore::in_memory(key).unwrap();
+
+        let encryption_config = crate::config::EncryptionConfig::default();
+        let kek = MasterKey::from_password(b"password", &encryption_config).unwrap();
+        let secret = RootSecret::generate();
+        let wrapped = WrappedRootSecret::wrap(&secret, &kek).unwrap();
+
+        store.save_wrapped_root_secret(&wrapped).unwrap();
+
+        let loaded = store.load_wrapped_root_secret().unwrap().unwrap();
+        let unwrapped = loaded.unwrap_with(&kek).unwrap();
+        assert_eq!(secret.metadata_key().unwrap(), unwrapped.metadata_key().unwrap());
+    }
 }
@@ -0,0 +1,168 @@
+//! Per-inode file version history
+//!
+//! Retains the [`ChunkManifest`] a write supersedes, keyed by `(inode,
+//! version)`, so old file content stays reachable after a later write
+//! replaces it. This is the backing store for the FUSE layer's read-only
+//! `.snapshots/<timestamp>` browsing.
+//!
+//! A manifest is already just a list of content-addressed [`ChunkRef`](crate::chunk::ChunkRef)s,
+//! so two versions of a file that happen to share a chunk already share
+//! its storage and its [`MetadataStore`](crate::metadata::MetadataStore)
+//! refcount - nothing here needs to change for that. What determines
+//! *how much* gets shared is whether the chunk boundaries upstream of
+//! this module are stable across an edit: fixed-size chunking shifts
+//! every boundary after an insertion or deletion, while
+//! [`crate::chunk::ContentChunker`]'s rolling-hash boundaries don't, so a
+//! small edit to a large file only changes the one or two chunks it
+//! actually touched.
+
+use crate::chunk::ChunkManifest;
+use crate::error::Result;
+use sled::{Db, Tree};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, trace};
+
+/// One superseded version of a file's content.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FileVersion {
+    /// Inode this version belonged to
+    pub ino: u64,
+    /// The manifest's own version number
+    pub version: u64,
+    /// Wall-clock time the version was superseded
+    pub timestamp: SystemTime,
+    /// The manifest as it stood at that version
+    pub manifest: ChunkManifest,
+}
+
+fn epoch_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Version history store using sled
+///
+/// Stores [`FileVersion`]s keyed by `(inode, version)` big-endian bytes so a
+/// prefix scan over the inode yields every retained version in order.
+pub struct VersionManager {
+    /// Sled database reference
+    #[allow(dead_code)]
+    db: Db,
+    /// Version history tree
+    versions: Tree,
+}
+
+impl VersionManager {
+    /// Open or create a version history store at the given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let db = sled::open(path.as_ref())?;
+        let versions = db.open_tree("versions")?;
+
+        debug!("VersionManager opened at {:?}", path.as_ref());
+        Ok(Self { db, versions })
+    }
+
+    /// Create an in-memory version history store (primarily for testing)
+    #[allow(dead_code)]
+    pub fn in_memory() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let versions = db.open_tree("versions")?;
+
+        debug!("In-memory VersionManager created");
+        Ok(Self { db, versions })
+    }
+
+    fn make_key(ino: u64, version: u64) -> [u8; 16] {
+        let mut key = [0u8; 16];
+        key[..8].copy_from_slice(&ino.to_be_bytes());
+        key[8..].copy_from_slice(&version.to_be_bytes());
+        key
+    }
+
+    fn make_prefix(ino: u64) -> [u8; 8] {
+        ino.to_be_bytes()
+    }
+
+    /// Retain `manifest` as a superseded version of `ino`, as of `timestamp`.
+    pub fn record(&self, ino: u64, manifest: &ChunkManifest, timestamp: SystemTime) -> Result<()> {
+        let key = Self::make_key(ino, manifest.version);
+        let version =
+            FileVersion { ino, version: manifest.version, timestamp, manifest: manifest.clone() };
+        let bytes = bincode::serialize(&version)?;
+        self.versions.insert(key, bytes)?;
+
+        trace!("Recorded version {} for inode {}", manifest.version, ino);
+        Ok(())
+    }
+
+    /// Look up one retained version by its exact version number.
+    pub fn get(&self, ino: u64, version: u64) -> Result<Option<FileVersion>> {
+        let key = Self::make_key(ino, version);
+        match self.versions.get(key)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All retained versions of `ino`, oldest first.
+    pub fn list(&self, ino: u64) -> Result<Vec<FileVersion>> {
+        let prefix = Self::make_prefix(ino);
+        let mut versions = Vec::new();
+
+        for result in self.versions.scan_prefix(prefix) {
+            let (_, bytes) = result?;
+            versions.push(bincode::deserialize(&bytes)?);
+        }
+
+        versions.sort_by_key(|v| v.version);
+        Ok(versions)
+    }
+
+    /// The most recent retained version of `ino` at or before `timestamp`
+    /// (unix seconds), if any.
+    pub fn latest_at_or_before(&self, ino: u64, timestamp_secs: u64) -> Result<Option<FileVersion>> {
+        let versions = self.list(ino)?;
+        Ok(versions.into_iter().filter(|v| epoch_secs(v.timestamp) <= timestamp_secs).last())
+    }
+
+    /// Every retained version recorded at exactly `timestamp_secs` (unix
+    /// seconds), across all inodes - the contents of one
+    /// `.snapshots/<timestamp>` directory.
+    pub fn versions_at(&self, timestamp_secs: u64) -> Result<Vec<FileVersion>> {
+        let mut versions = Vec::new();
+        for result in self.versions.iter() {
+            let (_, bytes) = result?;
+            let version: FileVersion = bincode::deserialize(&bytes)?;
+            if epoch_secs(version.timestamp) == timestamp_secs {
+                versions.push(version);
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Every distinct timestamp (unix seconds) a version was recorded at,
+    /// across all inodes, ascending.
+    pub fn all_timestamps(&self) -> Result<Vec<u64>> {
+        let mut seen = std::collections::BTreeSet::new();
+        for result in self.versions.iter() {
+            let (_, bytes) = result?;
+            let version: FileVersion = bincode::deserialize(&bytes)?;
+            seen.insert(epoch_secs(version.timestamp));
+        }
+        Ok(seen.into_iter().collect())
+    }
+
+    /// Every inode with at least one retained version at or before
+    /// `timestamp` (unix seconds).
+    pub fn inodes_with_version_at_or_before(&self, timestamp_secs: u64) -> Result<Vec<u64>> {
+        let mut seen = std::collections::BTreeSet::new();
+        for result in self.versions.iter() {
+            let (_, bytes) = result?;
+            let version: FileVersion = bincode::deserialize(&bytes)?;
+            if epoch_secs(version.timestamp) <= timestamp_secs {
+                seen.insert(version.ino);
+            }
+        }
+        Ok(seen.into_iter().collect())
+    }
+}
@@ -0,0 +1,232 @@
+//! Crash-resumable tracking for long-running migration passes.
+//!
+//! `cmd_migrate` (HKDF re-keying) and `raid migrate-to-erasure` are both
+//! long, destructive, effectively all-or-nothing passes over every
+//! inode/chunk a mount has ever written - dangerous to restart from
+//! scratch if the process dies halfway, and especially dangerous for
+//! `--delete-old`, which must never re-delete or double-encode a unit of
+//! work it already committed. [`Journal`] gives either pass a narrow,
+//! reusable way to record per-unit progress: each activity (an inode, a
+//! chunk, whatever unit the caller defines) is an append-only,
+//! content-keyed entry that moves from [`ActivityState::Pending`] to
+//! [`ActivityState::Done`], persisted in the same [`MetadataStore`] the
+//! migration itself is running against. Restarting a migration with
+//! `--resume` checks [`Journal::is_done`] before redoing a unit's work and
+//! skips it if an earlier run already finished it; `--restart` calls
+//! [`Journal::clear`] first to discard that history and start over.
+//!
+//! Callers are responsible for only calling [`Journal::mark_done`] *after*
+//! a unit's output has actually been durably committed (the re-keyed value
+//! written, or the erasure blocks uploaded and the manifest saved) - the
+//! journal only remembers what it's told, it doesn't verify anything
+//! itself.
+
+use crate::error::{Error, Result};
+use crate::metadata::MetadataStore;
+use serde::{Deserialize, Serialize};
+
+/// Progress of one activity tracked by a [`Journal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityState {
+    /// Work has started (or is about to) but isn't durably committed yet -
+    /// a resumed run must redo it.
+    Pending,
+    /// Work is durably committed; a resumed run skips straight past it.
+    Done,
+}
+
+/// `MetadataStore` key prefix every activity is filed under, namespaced by
+/// the migration id so unrelated passes (HKDF re-keying vs. raid
+/// migration) never collide.
+const JOURNAL_PREFIX: &str = "migration_journal:";
+
+fn activity_key(migration_id: &str, activity_id: &str) -> String {
+    format!("{JOURNAL_PREFIX}{migration_id}:{activity_id}")
+}
+
+fn scan_prefix(migration_id: &str) -> String {
+    format!("{JOURNAL_PREFIX}{migration_id}:")
+}
+
+/// Append-only, content-keyed progress log for one migration pass,
+/// scoped by `migration_id` within a shared [`MetadataStore`]. See the
+/// module docs for how `--resume`/`--restart` callers are expected to use
+/// it.
+pub struct Journal<'a> {
+    metadata: &'a MetadataStore,
+    migration_id: String,
+}
+
+impl<'a> Journal<'a> {
+    /// Open the journal for `migration_id` within `metadata`. Cheap -
+    /// doesn't read anything until a method below is called.
+    pub fn new(metadata: &'a MetadataStore, migration_id: impl Into<String>) -> Self {
+        Journal { metadata, migration_id: migration_id.into() }
+    }
+
+    /// Whether `activity_id` was recorded `Done` by an earlier run.
+    pub fn is_done(&self, activity_id: &str) -> Result<bool> {
+        Ok(matches!(self.get(activity_id)?, Some(ActivityState::Done)))
+    }
+
+    fn get(&self, activity_id: &str) -> Result<Option<ActivityState>> {
+        match self.metadata.get_metadata(&activity_key(&self.migration_id, activity_id))? {
+            Some(bytes) => Ok(Some(
+                bincode::deserialize(&bytes).map_err(|e| Error::Deserialization(e.to_string()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Record `activity_id` as pending - about to start, or retryable
+    /// after a prior attempt failed.
+    pub fn mark_pending(&self, activity_id: &str) -> Result<()> {
+        self.put(activity_id, ActivityState::Pending)
+    }
+
+    /// Record `activity_id` as done. Only call this once the activity's
+    /// output is durably committed; see the module docs.
+    pub fn mark_done(&self, activity_id: &str) -> Result<()> {
+        self.put(activity_id, ActivityState::Done)
+    }
+
+    fn put(&self, activity_id: &str, state: ActivityState) -> Result<()> {
+        let bytes = bincode::serialize(&state).map_err(Error::from)?;
+        self.metadata.save_metadata(&activity_key(&self.migration_id, activity_id), &bytes)
+    }
+
+    /// Erase every recorded activity for this migration, for `--restart`.
+    pub fn clear(&self) -> Result<()> {
+        for (key, _) in self.metadata.scan_metadata_prefix(&scan_prefix(&self.migration_id))? {
+            self.metadata.delete_metadata(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Count of activities in each state, for `migration status`.
+    pub fn report(&self) -> Result<JournalReport> {
+        let mut report = JournalReport::default();
+        for (_, bytes) in self.metadata.scan_metadata_prefix(&scan_prefix(&self.migration_id))? {
+            let state: ActivityState =
+                bincode::deserialize(&bytes).map_err(|e| Error::Deserialization(e.to_string()))?;
+            match state {
+                ActivityState::Pending => report.pending += 1,
+                ActivityState::Done => report.done += 1,
+            }
+        }
+        Ok(report)
+    }
+
+    /// Every migration id with at least one recorded activity in
+    /// `metadata`, for `migration status` to report on without the caller
+    /// needing to know which migrations have ever run.
+    pub fn known_migrations(metadata: &MetadataStore) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = metadata
+            .scan_metadata_prefix(JOURNAL_PREFIX)?
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let rest = key.strip_prefix(JOURNAL_PREFIX)?;
+                Some(rest.split_once(':')?.0.to_string())
+            })
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+}
+
+/// Activity counts for one migration, as reported by [`Journal::report`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JournalReport {
+    /// Activities started but not yet durably committed.
+    pub pending: usize,
+    /// Activities durably committed; skipped on resume.
+    pub done: usize,
+}
+
+impl JournalReport {
+    /// Total activities recorded, pending or done.
+    pub fn total(&self) -> usize {
+        self.pending + self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_metadata() -> MetadataStore {
+        MetadataStore::in_memory([0u8; crate::crypto::KEY_SIZE]).unwrap()
+    }
+
+    #[test]
+    fn test_unrecorded_activity_is_not_done() {
+        let metadata = test_metadata();
+        let journal = Journal::new(&metadata, "hkdf-migration");
+        assert!(!journal.is_done("42").unwrap());
+    }
+
+    #[test]
+    fn test_mark_done_persists_across_journal_handles() {
+        let metadata = test_metadata();
+        Journal::new(&metadata, "hkdf-migration").mark_done("42").unwrap();
+
+        let reopened = Journal::new(&metadata, "hkdf-migration");
+        assert!(reopened.is_done("42").unwrap());
+    }
+
+    #[test]
+    fn test_pending_activity_is_not_done() {
+        let metadata = test_metadata();
+        let journal = Journal::new(&metadata, "hkdf-migration");
+        journal.mark_pending("42").unwrap();
+        assert!(!journal.is_done("42").unwrap());
+    }
+
+    #[test]
+    fn test_report_counts_each_state() {
+        let metadata = test_metadata();
+        let journal = Journal::new(&metadata, "raid-migrate-to-erasure");
+        journal.mark_done("1:0").unwrap();
+        journal.mark_done("1:1").unwrap();
+        journal.mark_pending("2:0").unwrap();
+
+        let report = journal.report().unwrap();
+        assert_eq!(report.done, 2);
+        assert_eq!(report.pending, 1);
+        assert_eq!(report.total(), 3);
+    }
+
+    #[test]
+    fn test_clear_removes_recorded_activities() {
+        let metadata = test_metadata();
+        let journal = Journal::new(&metadata, "hkdf-migration");
+        journal.mark_done("1").unwrap();
+        journal.clear().unwrap();
+
+        assert!(!journal.is_done("1").unwrap());
+        assert_eq!(journal.report().unwrap().total(), 0);
+    }
+
+    #[test]
+    fn test_journals_for_different_migrations_are_isolated() {
+        let metadata = test_metadata();
+        Journal::new(&metadata, "hkdf-migration").mark_done("1").unwrap();
+        Journal::new(&metadata, "raid-migrate-to-erasure").mark_pending("1:0").unwrap();
+
+        assert!(Journal::new(&metadata, "hkdf-migration").is_done("1").unwrap());
+        assert!(!Journal::new(&metadata, "raid-migrate-to-erasure").is_done("1:0").unwrap());
+    }
+
+    #[test]
+    fn test_known_migrations_lists_every_namespace_with_activity() {
+        let metadata = test_metadata();
+        Journal::new(&metadata, "hkdf-migration").mark_done("1").unwrap();
+        Journal::new(&metadata, "raid-migrate-to-erasure").mark_pending("1:0").unwrap();
+
+        assert_eq!(
+            Journal::known_migrations(&metadata).unwrap(),
+            vec!["hkdf-migration".to_string(), "raid-migrate-to-erasure".to_string()]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Versioned (de)serialization with an explicit migration chain, plus
+//! [`journal`]'s crash-resumable tracking for long-running, destructive
+//! migration *passes* (HKDF re-keying, single-account to erasure-coded).
+//!
+//! Every persisted type pins its current on-disk schema version and names
+//! the immediately preceding format it can be upgraded from via the
+//! [`Migrate`] trait. Serializing a value should prepend its `VERSION` as a
+//! tag; [`load_and_migrate`] reads that tag back and walks `migrate` calls
+//! from the stored version up to the current one before handing back the
+//! latest struct. This lets on-disk/on-Telegram formats evolve field by
+//! field without a flag-day.
+
+pub mod journal;
+
+pub use journal::{ActivityState, Journal, JournalReport};
+
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A persisted schema format that can be produced from its immediate
+/// predecessor. The very first format of a type is its own `Previous`,
+/// with `migrate` acting as the identity - this terminates the chain.
+pub trait Migrate: Serialize + DeserializeOwned {
+    /// The schema version this format identifies itself with on disk.
+    const VERSION: u16;
+
+    /// The format this version is upgraded from.
+    type Previous: Migrate;
+
+    /// Upgrade from the previous format to this one.
+    fn migrate(previous: Self::Previous) -> Self;
+}
+
+/// Deserialize `bytes` that were stored under `stored_version` into the
+/// current format `T`, walking the migration chain as needed.
+pub fn load_and_migrate<T: Migrate>(stored_version: u16, bytes: &[u8]) -> Result<T> {
+    if stored_version == T::VERSION {
+        return bincode::deserialize(bytes).map_err(Error::from);
+    }
+
+    if T::VERSION == <T::Previous as Migrate>::VERSION {
+        // Reached the bottom of the chain without finding a matching version.
+        return Err(Error::Deserialization(format!(
+            "no known format for stored version {}",
+            stored_version
+        )));
+    }
+
+    let previous = load_and_migrate::<T::Previous>(stored_version, bytes)?;
+    Ok(T::migrate(previous))
+}
+
+/// Serialize `value` prefixed with its format version tag: a little-endian
+/// `u16` followed by the bincode-encoded bytes.
+pub fn save_versioned<T: Migrate>(value: &T) -> Result<Vec<u8>> {
+    let mut out = T::VERSION.to_le_bytes().to_vec();
+    out.extend(bincode::serialize(value).map_err(Error::from)?);
+    Ok(out)
+}
+
+/// Split a version-tagged blob produced by [`save_versioned`] into its
+/// version tag and payload, then migrate it up to the current format.
+pub fn load_versioned<T: Migrate>(blob: &[u8]) -> Result<T> {
+    if blob.len() < 2 {
+        return Err(Error::Deserialization(
+            "versioned blob shorter than the version tag".to_string(),
+        ));
+    }
+    let stored_version = u16::from_le_bytes([blob[0], blob[1]]);
+    load_and_migrate(stored_version, &blob[2..])
+}
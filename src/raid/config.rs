@@ -6,6 +6,7 @@
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use tracing::warn;
 
 /// Erasure coding preset configurations
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -159,6 +160,93 @@ impl ErasureConfig {
     pub fn fault_tolerance(&self) -> usize {
         self.parity_chunks()
     }
+
+    /// Estimate how much object data `raw_pool_bytes` of raw account
+    /// storage can actually hold under this K/N split - each stripe
+    /// writes N blocks to store K blocks' worth of data, so only a
+    /// `data_chunks / total_chunks` fraction of raw capacity is usable.
+    /// Meant for `from_preset` callers sizing a pool against the
+    /// accounts' combined quotas.
+    pub fn usable_capacity(&self, raw_pool_bytes: u64) -> u64 {
+        if self.total_chunks == 0 {
+            return 0;
+        }
+        raw_pool_bytes * self.data_chunks as u64 / self.total_chunks as u64
+    }
+}
+
+/// Digest algorithm used to detect silent corruption of a chunk fetched
+/// back from a Telegram account, before it's handed to the Reed-Solomon
+/// decoder alongside its siblings.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    /// CRC32C - cheapest to compute, adequate for catching bit-rot and
+    /// truncation; not collision-resistant.
+    Crc32c,
+
+    /// BLAKE3 - the hash already used elsewhere in this crate for
+    /// content addressing (see `BlockLocation::content_hash`).
+    Blake3,
+
+    /// SHA-256 - slower than BLAKE3 but sometimes required for
+    /// compliance reasons.
+    Sha256,
+}
+
+impl Default for ChecksumAlgorithm {
+    fn default() -> Self {
+        ChecksumAlgorithm::Blake3
+    }
+}
+
+/// Per-chunk integrity checking configuration
+///
+/// When enabled, every one of a stripe's N chunks is hashed at upload
+/// time and the digest is persisted alongside its `BlockLocation`. On
+/// download, each chunk is re-hashed and compared before it's handed to
+/// the decoder; a mismatch is treated as a missing shard rather than
+/// valid data, so K-of-N reconstruction routes around the corruption
+/// automatically instead of decoding garbage.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChecksumConfig {
+    /// Digest algorithm to use
+    #[serde(default)]
+    pub algorithm: ChecksumAlgorithm,
+
+    /// Whether per-chunk checksums are computed and verified
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for ChecksumConfig {
+    fn default() -> Self {
+        ChecksumConfig {
+            algorithm: ChecksumAlgorithm::default(),
+            enabled: true,
+        }
+    }
+}
+
+impl ChecksumConfig {
+    /// Hex-encoded digest of `data` under this config's algorithm
+    pub fn digest(&self, data: &[u8]) -> String {
+        match self.algorithm {
+            ChecksumAlgorithm::Crc32c => format!("{:08x}", crc32c::crc32c(data)),
+            ChecksumAlgorithm::Blake3 => blake3::hash(data).to_hex().to_string(),
+            ChecksumAlgorithm::Sha256 => {
+                use sha2::{Digest, Sha256};
+                let mut hasher = Sha256::new();
+                hasher.update(data);
+                hex::encode(hasher.finalize())
+            }
+        }
+    }
+
+    /// Whether `data` matches a previously-recorded `digest`
+    pub fn verify(&self, data: &[u8], digest: &str) -> bool {
+        self.digest(data) == digest
+    }
 }
 
 /// Configuration for a single Telegram account in the pool
@@ -183,6 +271,37 @@ pub struct AccountConfig {
     #[serde(default = "default_priority")]
     pub priority: u8,
 
+    /// Failure domain this account belongs to (e.g. accounts sharing a
+    /// phone-number region or owner). Accounts with no zone set are each
+    /// treated as their own singleton zone. Used by `raid::layout` to
+    /// keep any one failure domain from holding more of a stripe's
+    /// blocks than the array's parity count can absorb.
+    #[serde(default)]
+    pub zone: Option<String>,
+
+    /// Relative storage capacity weight used by `raid::layout` to spread
+    /// blocks proportionally to how much room an account actually has,
+    /// rather than splitting them evenly. Plain round-robin and
+    /// priority-based placement (`AssignmentStrategy`) ignore this.
+    #[serde(default = "default_capacity_weight")]
+    pub capacity_weight: u32,
+
+    /// Absolute storage quota for this account, in bytes, if known.
+    /// `None` means unbounded (or simply untracked) - `free_bytes`/
+    /// `remaining_ratio` treat that as always having room. Unlike
+    /// `capacity_weight`, which only sets a relative share of traffic,
+    /// this is what [`PoolConfig::placement_candidates`] checks before
+    /// ever considering an account for a new chunk.
+    #[serde(default)]
+    pub capacity_bytes: Option<u64>,
+
+    /// Running total of bytes this account is believed to hold. Callers
+    /// that place chunks are responsible for keeping this updated; it's
+    /// bookkeeping the pool trusts rather than something it measures
+    /// itself.
+    #[serde(default)]
+    pub used_bytes: u64,
+
     /// Whether this account is enabled
     #[serde(default = "default_enabled")]
     pub enabled: bool,
@@ -192,6 +311,10 @@ fn default_priority() -> u8 {
     100
 }
 
+fn default_capacity_weight() -> u32 {
+    1
+}
+
 impl AccountConfig {
     /// Create a new account configuration
     pub fn new(
@@ -207,6 +330,10 @@ impl AccountConfig {
             phone: None,
             session_file,
             priority: default_priority(),
+            zone: None,
+            capacity_weight: default_capacity_weight(),
+            capacity_bytes: None,
+            used_bytes: 0,
             enabled: true,
         }
     }
@@ -223,11 +350,102 @@ impl AccountConfig {
         self
     }
 
+    /// Set the failure-domain zone
+    pub fn with_zone(mut self, zone: String) -> Self {
+        self.zone = Some(zone);
+        self
+    }
+
+    /// Set the storage capacity weight
+    pub fn with_capacity_weight(mut self, capacity_weight: u32) -> Self {
+        self.capacity_weight = capacity_weight;
+        self
+    }
+
+    /// This account's failure-domain zone, defaulting to a singleton
+    /// zone of its own if none was configured.
+    pub fn zone_key(&self) -> String {
+        self.zone.clone().unwrap_or_else(|| format!("account-{}", self.account_id))
+    }
+
     /// Disable this account
     pub fn disabled(mut self) -> Self {
         self.enabled = false;
         self
     }
+
+    /// Set the storage quota and seed the running usage counter
+    pub fn with_capacity(mut self, capacity_bytes: u64) -> Self {
+        self.capacity_bytes = Some(capacity_bytes);
+        self
+    }
+
+    /// Remaining free space, or `None` if this account has no configured
+    /// quota. Saturates at zero rather than underflowing if `used_bytes`
+    /// has drifted past `capacity_bytes`.
+    pub fn free_bytes(&self) -> Option<u64> {
+        self.capacity_bytes.map(|cap| cap.saturating_sub(self.used_bytes))
+    }
+
+    /// Fraction of this account's quota that's still free, in `[0.0,
+    /// 1.0]`. An account with no configured quota is treated as
+    /// entirely free, so it competes on `priority` alone.
+    pub fn remaining_ratio(&self) -> f64 {
+        match self.capacity_bytes {
+            Some(0) => 0.0,
+            Some(cap) => self.free_bytes().unwrap_or(0) as f64 / cap as f64,
+            None => 1.0,
+        }
+    }
+
+    /// Whether this account currently has room for another chunk of
+    /// `chunk_size` bytes. An account with no configured quota always
+    /// has room.
+    pub fn has_room_for(&self, chunk_size: u64) -> bool {
+        self.free_bytes().map(|free| free >= chunk_size).unwrap_or(true)
+    }
+}
+
+/// A block-index instruction produced by [`PoolConfig::plan_reshard`] for
+/// migrating every existing stripe from one `ErasureConfig` to another
+/// without a full re-upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReshardPlan {
+    /// Block indices whose on-disk bytes are unaffected by the resize and
+    /// can be left exactly where they are.
+    pub retained_blocks: Vec<usize>,
+
+    /// Block indices that must be (re)encoded under `new_erasure` - every
+    /// parity index at minimum, or every index at all if `data_chunks`
+    /// itself changed and the old data/parity split no longer applies.
+    pub regenerate_blocks: Vec<usize>,
+
+    /// The erasure parameters `regenerate_blocks` should be encoded under.
+    pub new_erasure: ErasureConfig,
+}
+
+/// One block to reconstruct and re-upload as part of a [`RebuildPlan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildChunkAssignment {
+    /// Identifier of the chunk whose block on the failed account needs
+    /// reconstructing (the same ID used to key its manifest entry).
+    pub chunk_id: String,
+
+    /// Account the reconstructed block should be uploaded to.
+    pub target_account: u8,
+}
+
+/// Ordered, concurrency-bounded plan for replacing one failed account with
+/// a freshly-added one, produced by [`PoolConfig::plan_rebuild`].
+///
+/// `batches[i]` is the i-th wave of reconstructions that may run
+/// concurrently; waves run strictly in order but chunks within a wave do
+/// not depend on each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildPlan {
+    pub failed_account: u8,
+    pub replacement_account: u8,
+    pub batches: Vec<Vec<RebuildChunkAssignment>>,
 }
 
 /// Pool configuration for managing multiple Telegram accounts
@@ -239,6 +457,10 @@ pub struct PoolConfig {
     /// Erasure coding configuration
     pub erasure: ErasureConfig,
 
+    /// Per-chunk integrity checksum configuration
+    #[serde(default)]
+    pub checksum: ChecksumConfig,
+
     /// Maximum concurrent uploads across all accounts
     #[serde(default = "default_max_concurrent_uploads")]
     pub max_concurrent_uploads: usize,
@@ -254,6 +476,13 @@ pub struct PoolConfig {
     /// Health check interval in seconds
     #[serde(default = "default_health_check_interval")]
     pub health_check_interval_secs: u64,
+
+    /// Opt-in read-repair: when `AccountPool::download_blocks` comes up
+    /// short of a full set of blocks, automatically re-materialize the
+    /// missing ones via `repair_stripe` instead of leaving the stripe
+    /// degraded until an explicit rebuild.
+    #[serde(default)]
+    pub repair_on_read: bool,
 }
 
 fn default_max_concurrent_uploads() -> usize {
@@ -277,10 +506,12 @@ impl Default for PoolConfig {
         PoolConfig {
             accounts: Vec::new(),
             erasure: ErasureConfig::default(),
+            checksum: ChecksumConfig::default(),
             max_concurrent_uploads: default_max_concurrent_uploads(),
             max_concurrent_downloads: default_max_concurrent_downloads(),
             retry_attempts: default_retry_attempts(),
             health_check_interval_secs: default_health_check_interval(),
+            repair_on_read: false,
         }
     }
 }
@@ -302,10 +533,24 @@ impl PoolConfig {
     /// - Must have enough enabled accounts for total_chunks (N)
     /// - Account IDs must be unique
     /// - All enabled accounts must have valid API credentials
+    ///
+    /// Disabling `checksum` is not an error - it's a valid (if riskier)
+    /// configuration - but it logs a warning, since it silently widens
+    /// what counts as an undetectable failure from "more than
+    /// `parity_chunks()` accounts lost" to "any bit-rot at all".
     pub fn validate(&self) -> Result<()> {
         // Validate erasure config first
         self.erasure.validate()?;
 
+        if !self.checksum.enabled {
+            warn!(
+                "per-chunk checksums are disabled: corruption of more than {} of {} shards \
+                 will go undetected and may be reconstructed as valid data",
+                self.erasure.parity_chunks(),
+                self.erasure.total_chunks
+            );
+        }
+
         // Count enabled accounts
         let enabled_accounts: Vec<_> = self.accounts.iter().filter(|a| a.enabled).collect();
         let enabled_count = enabled_accounts.len();
@@ -319,6 +564,23 @@ impl PoolConfig {
             )));
         }
 
+        // An account whose quota is already exhausted can't actually
+        // take a placement, even though it's enabled - so "enough
+        // accounts for N" above isn't the whole story once capacities
+        // are tracked.
+        let placeable_count = enabled_accounts
+            .iter()
+            .filter(|a| a.has_room_for(1))
+            .count();
+        if placeable_count < self.erasure.total_chunks {
+            return Err(Error::InvalidConfig(format!(
+                "Not enough account capacity for placement: {} account(s) have free space, need {} for N={}",
+                placeable_count,
+                self.erasure.total_chunks,
+                self.erasure.total_chunks
+            )));
+        }
+
         // Check for unique account IDs
         let mut seen_ids = std::collections::HashSet::new();
         for account in &self.accounts {
@@ -356,6 +618,199 @@ impl PoolConfig {
         accounts
     }
 
+    /// The best `count` enabled accounts to place a new stripe's chunks
+    /// on, given chunks of `chunk_size` bytes each.
+    ///
+    /// Accounts without room for another `chunk_size`-byte chunk are
+    /// dropped outright; the rest are ranked by a score blending
+    /// `priority` (operator preference) with `remaining_ratio` (how
+    /// empty the account still is), so placement gravitates toward
+    /// accounts with headroom instead of piling onto whichever one
+    /// happens to have the highest priority until it's full. Returns
+    /// fewer than `count` accounts if fewer qualify.
+    pub fn placement_candidates(&self, chunk_size: u64, count: usize) -> Vec<&AccountConfig> {
+        const PRIORITY_WEIGHT: f64 = 0.5;
+        const HEADROOM_WEIGHT: f64 = 0.5;
+
+        let mut candidates: Vec<&AccountConfig> = self
+            .accounts
+            .iter()
+            .filter(|a| a.enabled && a.has_room_for(chunk_size))
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            let score = |account: &AccountConfig| {
+                PRIORITY_WEIGHT * (account.priority as f64 / u8::MAX as f64)
+                    + HEADROOM_WEIGHT * account.remaining_ratio()
+            };
+            score(b)
+                .partial_cmp(&score(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        candidates.truncate(count);
+        candidates
+    }
+
+    /// Enabled accounts sorted by priority, with any account ID present
+    /// in `excluded` left out entirely - e.g. ones `raid::health::HealthTracker`
+    /// currently reports `Unavailable`, so the distribution planner stops
+    /// targeting dead shards until a rebuild brings them back.
+    pub fn enabled_accounts_excluding(
+        &self,
+        excluded: &std::collections::HashSet<u8>,
+    ) -> Vec<&AccountConfig> {
+        let mut accounts: Vec<_> = self
+            .accounts
+            .iter()
+            .filter(|a| a.enabled && !excluded.contains(&a.account_id))
+            .collect();
+        accounts.sort_by(|a, b| b.priority.cmp(&a.priority));
+        accounts
+    }
+
+    /// Whether a read/reconstruction can be attempted with `healthy_count`
+    /// accounts currently available - true as soon as at least
+    /// `data_chunks` (K) of them are up, since Reed-Solomon can
+    /// reconstruct from any K of N.
+    pub fn can_read(&self, healthy_count: usize) -> bool {
+        healthy_count >= self.erasure.data_chunks
+    }
+
+    /// Whether a new stripe can be written with `healthy_count` accounts
+    /// currently available. Unlike reads, writes need the full N so every
+    /// block lands somewhere - a write against fewer accounts would start
+    /// the stripe already degraded.
+    pub fn can_write(&self, healthy_count: usize) -> bool {
+        healthy_count >= self.erasure.total_chunks
+    }
+
+    /// How many more accounts need to come back healthy to restore full
+    /// N-of-N redundancy, given `healthy_count` currently available. Zero
+    /// once the array is fully healthy.
+    pub fn missing_for_full_redundancy(&self, healthy_count: usize) -> usize {
+        self.erasure.total_chunks.saturating_sub(healthy_count)
+    }
+
+    /// The erasure configuration actually achievable with only
+    /// `healthy_count` accounts available, capping `total_chunks` down to
+    /// whatever's healthy while holding `data_chunks` fixed. Returns
+    /// `None` if even reconstruction isn't possible (`healthy_count` below
+    /// K) - the pool can't operate at all, not just in a reduced mode.
+    ///
+    /// A result with `total_chunks < self.erasure.total_chunks` means the
+    /// pool is in read-only degraded mode: [`Self::can_read`] against it
+    /// is true, but it no longer has enough accounts to accept new writes
+    /// at the configured redundancy.
+    pub fn effective_erasure(&self, healthy_count: usize) -> Option<ErasureConfig> {
+        if !self.can_read(healthy_count) {
+            return None;
+        }
+        Some(ErasureConfig {
+            data_chunks: self.erasure.data_chunks,
+            total_chunks: healthy_count.min(self.erasure.total_chunks),
+            preset: ErasurePreset::Custom,
+            enabled: self.erasure.enabled,
+        })
+    }
+
+    /// Plan how to migrate every existing stripe from `old` to `new`
+    /// erasure parameters (e.g. RAID5 -> RAID6 after adding a spare)
+    /// without re-encoding data that doesn't need it.
+    ///
+    /// When only `total_chunks` (N) changes, the K existing data shards
+    /// are byte-identical under the new layout and are reported as
+    /// retained - only the delta parity shards need recomputing, since
+    /// the Reed-Solomon matrix is dimensioned by N. When `data_chunks`
+    /// (K) changes too, the data/parity split itself is different, so
+    /// nothing survives and every shard is regenerated from the original
+    /// object data.
+    ///
+    /// Refuses to shrink K: a parity shard still encoded under the old,
+    /// larger K would decode incorrectly if read with the new K before
+    /// every stripe has actually been regenerated, so that transition is
+    /// rejected outright rather than left to race against the rebuild.
+    pub fn plan_reshard(old: &ErasureConfig, new: &ErasureConfig) -> Result<ReshardPlan> {
+        old.validate()?;
+        new.validate()?;
+
+        if new.data_chunks < old.data_chunks {
+            return Err(Error::InvalidConfig(format!(
+                "Cannot reshard from K={} down to K={}: stripes not yet regenerated would \
+                 still be encoded for the old, larger K and would misdecode if read under \
+                 the new one mid-operation",
+                old.data_chunks, new.data_chunks
+            )));
+        }
+
+        let (retained_blocks, regenerate_blocks) = if new.data_chunks == old.data_chunks {
+            (
+                (0..old.data_chunks).collect(),
+                (old.data_chunks..new.total_chunks).collect(),
+            )
+        } else {
+            (Vec::new(), (0..new.total_chunks).collect())
+        };
+
+        Ok(ReshardPlan {
+            retained_blocks,
+            regenerate_blocks,
+            new_erasure: new.clone(),
+        })
+    }
+
+    /// Plan the reconstruction of every block `failed_account` held,
+    /// retargeting each one to `replacement_account`, in waves of at most
+    /// `max_concurrent_uploads` concurrent reconstructions.
+    ///
+    /// `chunk_ids` is caller-supplied (e.g. from a manifest scan like
+    /// [`crate::raid::rebuild::rebuild_account`] already performs) since
+    /// `PoolConfig` itself holds no per-stripe block assignments.
+    pub fn plan_rebuild(
+        &self,
+        failed_account: u8,
+        replacement_account: u8,
+        chunk_ids: Vec<String>,
+    ) -> Result<RebuildPlan> {
+        self.erasure.validate()?;
+
+        if self.get_account(replacement_account).is_none() {
+            return Err(Error::InvalidConfig(format!(
+                "Replacement account {} is not part of this pool",
+                replacement_account
+            )));
+        }
+
+        let excluded = std::collections::HashSet::from([failed_account]);
+        let healthy_count = self.enabled_accounts_excluding(&excluded).len();
+        if !self.can_read(healthy_count) {
+            return Err(Error::InvalidConfig(format!(
+                "Cannot rebuild account {}: only {} other account(s) remain, need {} to \
+                 reconstruct a block",
+                failed_account, healthy_count, self.erasure.data_chunks
+            )));
+        }
+
+        let batch_size = self.max_concurrent_uploads.max(1);
+        let assignments: Vec<RebuildChunkAssignment> = chunk_ids
+            .into_iter()
+            .map(|chunk_id| RebuildChunkAssignment {
+                chunk_id,
+                target_account: replacement_account,
+            })
+            .collect();
+        let batches = assignments
+            .chunks(batch_size)
+            .map(|batch| batch.to_vec())
+            .collect();
+
+        Ok(RebuildPlan {
+            failed_account,
+            replacement_account,
+            batches,
+        })
+    }
+
     /// Get an account by ID
     pub fn get_account(&self, account_id: u8) -> Option<&AccountConfig> {
         self.accounts.iter().find(|a| a.account_id == account_id)
@@ -502,6 +957,145 @@ mod tests {
         assert_eq!(enabled[2].account_id, 0); // Priority 50
     }
 
+    #[test]
+    fn test_checksum_digest_round_trips_per_algorithm() {
+        let data = b"stripe block contents";
+        for algorithm in [
+            ChecksumAlgorithm::Crc32c,
+            ChecksumAlgorithm::Blake3,
+            ChecksumAlgorithm::Sha256,
+        ] {
+            let config = ChecksumConfig { algorithm, enabled: true };
+            let digest = config.digest(data);
+            assert!(config.verify(data, &digest));
+            assert!(!config.verify(b"tampered contents", &digest));
+        }
+    }
+
+    #[test]
+    fn test_pool_config_validate_warns_but_succeeds_with_checksums_disabled() {
+        let accounts = vec![
+            AccountConfig::new(0, 12345, "hash1".to_string(), PathBuf::from("session0")),
+            AccountConfig::new(1, 12346, "hash2".to_string(), PathBuf::from("session1")),
+            AccountConfig::new(2, 12347, "hash3".to_string(), PathBuf::from("session2")),
+        ];
+
+        let erasure = ErasureConfig::new(2, 3);
+        let mut pool = PoolConfig::new(accounts, erasure);
+        pool.checksum.enabled = false;
+        assert!(pool.validate().is_ok());
+    }
+
+    #[test]
+    fn test_usable_capacity_reflects_parity_overhead() {
+        let erasure = ErasureConfig::new(2, 3);
+        assert_eq!(erasure.usable_capacity(300), 200);
+    }
+
+    #[test]
+    fn test_placement_candidates_excludes_full_accounts() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0"))
+                .with_capacity(100),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1"))
+                .with_capacity(100),
+        ];
+        let mut pool = PoolConfig::new(accounts, ErasureConfig::new(1, 2));
+        pool.get_account_mut(1).unwrap().used_bytes = 100; // full
+
+        let candidates = pool.placement_candidates(10, 2);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].account_id, 0);
+    }
+
+    #[test]
+    fn test_placement_candidates_prefers_more_headroom_over_lower_priority() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0"))
+                .with_priority(100)
+                .with_capacity(1000),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1"))
+                .with_priority(100)
+                .with_capacity(1000),
+        ];
+        let mut pool = PoolConfig::new(accounts, ErasureConfig::new(1, 2));
+        pool.get_account_mut(0).unwrap().used_bytes = 900; // almost full
+
+        let candidates = pool.placement_candidates(10, 1);
+        assert_eq!(candidates[0].account_id, 1);
+    }
+
+    #[test]
+    fn test_pool_config_validate_rejects_exhausted_capacity() {
+        let accounts = vec![
+            AccountConfig::new(0, 12345, "hash1".to_string(), PathBuf::from("session0"))
+                .with_capacity(100),
+            AccountConfig::new(1, 12346, "hash2".to_string(), PathBuf::from("session1"))
+                .with_capacity(100),
+            AccountConfig::new(2, 12347, "hash3".to_string(), PathBuf::from("session2"))
+                .with_capacity(100),
+        ];
+        let mut pool = PoolConfig::new(accounts, ErasureConfig::new(2, 3));
+        pool.get_account_mut(2).unwrap().used_bytes = 100; // no room left
+
+        let err = pool.validate().unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_can_read_and_write_thresholds() {
+        let erasure = ErasureConfig::new(3, 5);
+        let pool = PoolConfig::new(Vec::new(), erasure);
+
+        assert!(!pool.can_read(2));
+        assert!(pool.can_read(3));
+        assert!(pool.can_read(5));
+
+        assert!(!pool.can_write(4));
+        assert!(pool.can_write(5));
+    }
+
+    #[test]
+    fn test_missing_for_full_redundancy() {
+        let erasure = ErasureConfig::new(3, 5);
+        let pool = PoolConfig::new(Vec::new(), erasure);
+
+        assert_eq!(pool.missing_for_full_redundancy(5), 0);
+        assert_eq!(pool.missing_for_full_redundancy(4), 1);
+        assert_eq!(pool.missing_for_full_redundancy(0), 5);
+    }
+
+    #[test]
+    fn test_effective_erasure_degrades_total_chunks_but_keeps_k() {
+        let erasure = ErasureConfig::new(3, 5);
+        let pool = PoolConfig::new(Vec::new(), erasure);
+
+        let effective = pool.effective_erasure(4).unwrap();
+        assert_eq!(effective.data_chunks, 3);
+        assert_eq!(effective.total_chunks, 4);
+
+        // Below K: can't even reconstruct, not just degraded.
+        assert!(pool.effective_erasure(2).is_none());
+    }
+
+    #[test]
+    fn test_enabled_accounts_excluding_skips_listed_ids() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0")),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1")),
+            AccountConfig::new(2, 1, "hash2".to_string(), PathBuf::from("session2")),
+        ];
+        let pool = PoolConfig::new(accounts, ErasureConfig::new(1, 2));
+
+        let excluded: std::collections::HashSet<u8> = [1].into_iter().collect();
+        let remaining: Vec<u8> = pool
+            .enabled_accounts_excluding(&excluded)
+            .into_iter()
+            .map(|a| a.account_id)
+            .collect();
+        assert_eq!(remaining, vec![0, 2]);
+    }
+
     #[test]
     fn test_disabled_accounts_not_counted() {
         let accounts = vec![
@@ -515,4 +1109,84 @@ mod tests {
         let pool = PoolConfig::new(accounts, erasure);
         assert!(pool.validate().is_err());
     }
+
+    #[test]
+    fn test_plan_reshard_retains_data_shards_when_only_n_grows() {
+        let old = ErasureConfig::new(2, 3); // RAID5-ish, 1 parity
+        let new = ErasureConfig::new(2, 4); // add a spare -> RAID6-ish, 2 parity
+
+        let plan = PoolConfig::plan_reshard(&old, &new).unwrap();
+        assert_eq!(plan.retained_blocks, vec![0, 1]);
+        assert_eq!(plan.regenerate_blocks, vec![2, 3]);
+        assert_eq!(plan.new_erasure.total_chunks, 4);
+    }
+
+    #[test]
+    fn test_plan_reshard_regenerates_everything_when_k_grows() {
+        let old = ErasureConfig::new(2, 3);
+        let new = ErasureConfig::new(3, 4);
+
+        let plan = PoolConfig::plan_reshard(&old, &new).unwrap();
+        assert!(plan.retained_blocks.is_empty());
+        assert_eq!(plan.regenerate_blocks, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_plan_reshard_rejects_shrinking_k() {
+        let old = ErasureConfig::new(3, 4);
+        let new = ErasureConfig::new(2, 4);
+
+        assert!(PoolConfig::plan_reshard(&old, &new).is_err());
+    }
+
+    #[test]
+    fn test_plan_rebuild_batches_by_max_concurrent_uploads() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0")),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1")),
+            AccountConfig::new(2, 1, "hash2".to_string(), PathBuf::from("session2")),
+            AccountConfig::new(3, 1, "hash3".to_string(), PathBuf::from("session3")),
+        ];
+        let mut pool = PoolConfig::new(accounts, ErasureConfig::new(2, 3));
+        pool.max_concurrent_uploads = 2;
+
+        let chunk_ids: Vec<String> = (0..5).map(|i| format!("chunk{i}")).collect();
+        let plan = pool.plan_rebuild(1, 3, chunk_ids).unwrap();
+
+        assert_eq!(plan.failed_account, 1);
+        assert_eq!(plan.replacement_account, 3);
+        assert_eq!(plan.batches.len(), 3); // 5 chunks at 2 per wave
+        assert_eq!(plan.batches[0].len(), 2);
+        assert_eq!(plan.batches[2].len(), 1);
+        assert!(plan
+            .batches
+            .iter()
+            .flatten()
+            .all(|a| a.target_account == 3));
+    }
+
+    #[test]
+    fn test_plan_rebuild_rejects_unknown_replacement_account() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0")),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1")),
+        ];
+        let pool = PoolConfig::new(accounts, ErasureConfig::new(1, 2));
+
+        assert!(pool.plan_rebuild(0, 99, vec!["chunk0".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_plan_rebuild_rejects_when_too_few_accounts_survive() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "hash0".to_string(), PathBuf::from("session0")),
+            AccountConfig::new(1, 1, "hash1".to_string(), PathBuf::from("session1")),
+            AccountConfig::new(2, 1, "hash2".to_string(), PathBuf::from("session2")),
+        ];
+        // K=3 of N=4, but the pool only has 3 accounts registered -
+        // excluding the failed one leaves 2, which can't reconstruct K=3.
+        let pool = PoolConfig::new(accounts, ErasureConfig::new(3, 4));
+
+        assert!(pool.plan_rebuild(0, 1, vec!["chunk0".to_string()]).is_err());
+    }
 }
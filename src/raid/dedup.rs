@@ -0,0 +1,126 @@
+//! Content-addressed deduplication across stripes
+//!
+//! Many chunks end up byte-identical (sparse-file zero blocks, repeated
+//! headers, etc). `ChunkIndex` lets the stripe uploader recognize a chunk
+//! it has already stored by hash and reuse the existing Telegram message
+//! instead of uploading a duplicate copy.
+
+use std::collections::HashMap;
+
+/// An already-stored chunk that new uploads with the same hash can reuse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredChunk {
+    pub account_id: u8,
+    pub message_id: i32,
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    chunk: StoredChunk,
+    ref_count: u64,
+}
+
+/// Maps chunk content hash to the account/message already holding it,
+/// reference-counted so the underlying message is only freed once no
+/// stripe references it anymore.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkIndex {
+    entries: HashMap<String, IndexEntry>,
+}
+
+impl ChunkIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        ChunkIndex {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Look up `hash`. If it's already stored, bump its refcount and
+    /// return the existing location (the caller should reuse it instead
+    /// of uploading). Otherwise register `chunk` as the first copy and
+    /// return `None`, signaling the caller must upload it.
+    pub fn insert_or_get(&mut self, hash: &str, chunk: StoredChunk) -> Option<StoredChunk> {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.ref_count += 1;
+            return Some(entry.chunk);
+        }
+
+        self.entries.insert(
+            hash.to_string(),
+            IndexEntry {
+                chunk,
+                ref_count: 1,
+            },
+        );
+        None
+    }
+
+    /// Release one reference to `hash`. Returns `true` once the refcount
+    /// reaches zero and the caller is free to delete the underlying
+    /// Telegram message.
+    pub fn release(&mut self, hash: &str) -> bool {
+        let Some(entry) = self.entries.get_mut(hash) else {
+            return false;
+        };
+
+        entry.ref_count = entry.ref_count.saturating_sub(1);
+        if entry.ref_count == 0 {
+            self.entries.remove(hash);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Current reference count for a hash, or 0 if it isn't tracked.
+    pub fn ref_count(&self, hash: &str) -> u64 {
+        self.entries.get(hash).map_or(0, |e| e.ref_count)
+    }
+
+    /// Number of distinct chunks currently tracked.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the index has no tracked chunks.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_insert_signals_upload_needed() {
+        let mut index = ChunkIndex::new();
+        let result = index.insert_or_get("hash-a", StoredChunk { account_id: 0, message_id: 100 });
+        assert_eq!(result, None);
+        assert_eq!(index.ref_count("hash-a"), 1);
+    }
+
+    #[test]
+    fn test_second_insert_with_same_hash_reuses_existing_message() {
+        let mut index = ChunkIndex::new();
+        index.insert_or_get("hash-a", StoredChunk { account_id: 0, message_id: 100 });
+
+        let reused = index.insert_or_get("hash-a", StoredChunk { account_id: 1, message_id: 999 });
+        assert_eq!(reused, Some(StoredChunk { account_id: 0, message_id: 100 }));
+        assert_eq!(index.ref_count("hash-a"), 2);
+    }
+
+    #[test]
+    fn test_release_only_frees_at_zero_refcount() {
+        let mut index = ChunkIndex::new();
+        index.insert_or_get("hash-a", StoredChunk { account_id: 0, message_id: 100 });
+        index.insert_or_get("hash-a", StoredChunk { account_id: 1, message_id: 999 });
+
+        assert!(!index.release("hash-a"));
+        assert_eq!(index.ref_count("hash-a"), 1);
+        assert!(index.release("hash-a"));
+        assert_eq!(index.ref_count("hash-a"), 0);
+        assert!(index.is_empty());
+    }
+}
@@ -3,13 +3,118 @@
 //! Provides encode/decode operations for K-of-N erasure coding.
 //! Any K shards can reconstruct the original data.
 
-use reed_solomon_erasure::galois_8::ReedSolomon;
+use reed_solomon_erasure::{galois_16, galois_8};
+use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
 
+/// Which Galois field the underlying Reed-Solomon backend operates over.
+/// GF(2^8) symbols are a single byte, which caps total shards at 255; a
+/// pool wanting wider fan-out needs GF(2^16), whose two-byte symbols push
+/// that ceiling out to 65535 at the cost of requiring even shard lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GaloisField {
+    /// GF(2^8): one-byte symbols, supports up to 255 total shards.
+    Gf8,
+    /// GF(2^16): two-byte symbols, supports up to 65535 total shards.
+    Gf16,
+}
+
+impl GaloisField {
+    /// The smallest field able to represent `total_shards`.
+    pub fn for_total_shards(total_shards: usize) -> Self {
+        if total_shards <= 255 {
+            GaloisField::Gf8
+        } else {
+            GaloisField::Gf16
+        }
+    }
+
+    /// Maximum total shards this field can represent.
+    fn max_total_shards(self) -> usize {
+        match self {
+            GaloisField::Gf8 => 255,
+            GaloisField::Gf16 => 65535,
+        }
+    }
+}
+
+/// Dispatches `encode`/`reconstruct` to the Reed-Solomon instance backing
+/// the selected [`GaloisField`]. Both backends expose the same shape of
+/// API over `Vec<u8>` shards, so this is a thin enum rather than a trait
+/// object.
+enum RsBackend {
+    Gf8(galois_8::ReedSolomon),
+    Gf16(galois_16::ReedSolomon),
+}
+
+impl RsBackend {
+    fn new(field: GaloisField, data_shards: usize, parity_shards: usize) -> Result<Self> {
+        match field {
+            GaloisField::Gf8 => galois_8::ReedSolomon::new(data_shards, parity_shards)
+                .map(RsBackend::Gf8)
+                .map_err(|e| Error::Internal(format!("Failed to create Reed-Solomon encoder: {}", e))),
+            GaloisField::Gf16 => galois_16::ReedSolomon::new(data_shards, parity_shards)
+                .map(RsBackend::Gf16)
+                .map_err(|e| Error::Internal(format!("Failed to create Reed-Solomon encoder: {}", e))),
+        }
+    }
+
+    fn encode(&self, shards: &mut [Vec<u8>]) -> Result<()> {
+        match self {
+            RsBackend::Gf8(rs) => rs.encode(shards),
+            RsBackend::Gf16(rs) => rs.encode(shards),
+        }
+        .map_err(|e| Error::Internal(format!("Reed-Solomon encoding failed: {}", e)))
+    }
+
+    fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> Result<()> {
+        match self {
+            RsBackend::Gf8(rs) => rs.reconstruct(shards),
+            RsBackend::Gf16(rs) => rs.reconstruct(shards),
+        }
+        .map_err(|e| Error::Internal(format!("Reed-Solomon reconstruction failed: {}", e)))
+    }
+
+    fn verify(&self, shards: &[Vec<u8>]) -> Result<bool> {
+        match self {
+            RsBackend::Gf8(rs) => rs.verify(shards),
+            RsBackend::Gf16(rs) => rs.verify(shards),
+        }
+        .map_err(|e| Error::Internal(format!("Reed-Solomon parity verification failed: {}", e)))
+    }
+}
+
+/// Bytes of BLAKE3 digest prepended to each shard by
+/// [`Encoder::encode_checksummed`], truncated the same way
+/// [`crate::chunk::compression`]'s checksum footer is - enough to catch
+/// corruption without the overhead of a full 32-byte hash per shard.
+const SHARD_CHECKSUM_LEN: usize = 4;
+
+fn shard_checksum(payload: &[u8]) -> [u8; SHARD_CHECKSUM_LEN] {
+    let hash = blake3::hash(payload);
+    let bytes = hash.as_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3]]
+}
+
+/// Strip and verify a shard framed by [`Encoder::encode_checksummed`],
+/// returning `None` if the shard is too short or its checksum doesn't
+/// match - both treated as corruption by [`Encoder::decode_verified`].
+fn verify_and_unframe_shard(framed: &[u8]) -> Option<Vec<u8>> {
+    if framed.len() < SHARD_CHECKSUM_LEN {
+        return None;
+    }
+    let (header, payload) = framed.split_at(SHARD_CHECKSUM_LEN);
+    if shard_checksum(payload) != header {
+        return None;
+    }
+    Some(payload.to_vec())
+}
+
 /// Reed-Solomon encoder/decoder
 pub struct Encoder {
-    rs: ReedSolomon,
+    rs: RsBackend,
+    field: GaloisField,
     data_shards: usize,   // K
     parity_shards: usize, // N-K
 }
@@ -17,6 +122,9 @@ pub struct Encoder {
 impl Encoder {
     /// Create new encoder with K data shards and N total shards
     ///
+    /// Picks GF(2^8) or GF(2^16) automatically based on `total_shards` -
+    /// use [`Encoder::with_field`] to force a specific field.
+    ///
     /// # Arguments
     /// * `data_shards` - K, the number of data shards
     /// * `total_shards` - N, the total number of shards (data + parity)
@@ -24,6 +132,16 @@ impl Encoder {
     /// # Errors
     /// Returns error if parameters are invalid (K must be > 0, N must be > K)
     pub fn new(data_shards: usize, total_shards: usize) -> Result<Self> {
+        Self::with_field(data_shards, total_shards, GaloisField::for_total_shards(total_shards))
+    }
+
+    /// Create a new encoder with an explicit [`GaloisField`] backend
+    /// rather than the one [`Encoder::new`] would pick automatically.
+    ///
+    /// # Errors
+    /// Returns error if parameters are invalid, or if `total_shards`
+    /// exceeds what `field` can represent
+    pub fn with_field(data_shards: usize, total_shards: usize, field: GaloisField) -> Result<Self> {
         if data_shards == 0 {
             return Err(Error::Internal(
                 "data_shards must be greater than 0".to_string(),
@@ -34,20 +152,31 @@ impl Encoder {
                 "total_shards must be greater than data_shards".to_string(),
             ));
         }
+        if total_shards > field.max_total_shards() {
+            return Err(Error::Internal(format!(
+                "total_shards {} exceeds the {:?} field's limit of {}",
+                total_shards,
+                field,
+                field.max_total_shards()
+            )));
+        }
 
         let parity_shards = total_shards - data_shards;
-
-        let rs = ReedSolomon::new(data_shards, parity_shards).map_err(|e| {
-            Error::Internal(format!("Failed to create Reed-Solomon encoder: {}", e))
-        })?;
+        let rs = RsBackend::new(field, data_shards, parity_shards)?;
 
         Ok(Self {
             rs,
+            field,
             data_shards,
             parity_shards,
         })
     }
 
+    /// Which Galois field this encoder's backend operates over.
+    pub fn field(&self) -> GaloisField {
+        self.field
+    }
+
     /// Encode data into N shards (K data + parity)
     ///
     /// The original data length is stored as the first 8 bytes (u64 big-endian)
@@ -84,9 +213,7 @@ impl Encoder {
         }
 
         // Encode parity
-        self.rs.encode(&mut shards).map_err(|e| {
-            Error::Internal(format!("Reed-Solomon encoding failed: {}", e))
-        })?;
+        self.rs.encode(&mut shards)?;
 
         Ok(shards)
     }
@@ -121,9 +248,7 @@ impl Encoder {
         }
 
         // Reconstruct missing shards
-        self.rs.reconstruct(shards).map_err(|e| {
-            Error::Internal(format!("Reed-Solomon reconstruction failed: {}", e))
-        })?;
+        self.rs.reconstruct(shards)?;
 
         // Combine data shards
         let mut reconstructed = Vec::new();
@@ -164,11 +289,17 @@ impl Encoder {
 
     /// Get the required shard size for given data length
     ///
-    /// Each shard will be ceil((data_len + 8) / data_shards) bytes,
-    /// where 8 bytes are for the length header.
+    /// Each shard will be ceil((data_len + 8) / data_shards) bytes, where
+    /// 8 bytes are for the length header, additionally rounded up to an
+    /// even number of bytes under [`GaloisField::Gf16`] since each symbol
+    /// there is 2 bytes wide.
     pub fn shard_size(&self, data_len: usize) -> usize {
         let total_len = data_len + 8; // Include length header
-        (total_len + self.data_shards - 1) / self.data_shards
+        let size = (total_len + self.data_shards - 1) / self.data_shards;
+        match self.field {
+            GaloisField::Gf8 => size,
+            GaloisField::Gf16 => size + (size % 2),
+        }
     }
 
     /// Check if we have enough shards to reconstruct
@@ -188,6 +319,431 @@ impl Encoder {
     pub fn total_shards(&self) -> usize {
         self.data_shards + self.parity_shards
     }
+
+    /// Reconstruct a single missing shard's raw bytes from whatever
+    /// shards are present, without decoding the whole stripe back into
+    /// plaintext. Used by single-block repair paths (rebuild, scrub) that
+    /// only need one shard back rather than the reassembled original data.
+    pub fn reconstruct_shard(
+        &self,
+        mut shards: Vec<Option<Vec<u8>>>,
+        missing_index: usize,
+    ) -> Result<Vec<u8>> {
+        let total_shards = self.data_shards + self.parity_shards;
+        if shards.len() != total_shards {
+            return Err(Error::Internal(format!(
+                "Expected {} shards, got {}",
+                total_shards,
+                shards.len()
+            )));
+        }
+        if !self.can_reconstruct(&shards) {
+            return Err(Error::Internal(format!(
+                "Not enough shards to reconstruct: need {}, have {}",
+                self.data_shards,
+                shards.iter().filter(|s| s.is_some()).count()
+            )));
+        }
+
+        self.rs.reconstruct(&mut shards)?;
+
+        shards
+            .get(missing_index)
+            .and_then(|s| s.clone())
+            .ok_or_else(|| Error::Internal("Reconstruction succeeded but shard is still missing".to_string()))
+    }
+
+    /// Reconstruct every missing shard in place, unlike
+    /// [`reconstruct_shard`](Self::reconstruct_shard)'s single-slot
+    /// shortcut. Used by multi-block repair paths (e.g.
+    /// `AccountPool::repair_stripe`) that need the full N-shard set back
+    /// at once rather than one shard at a time.
+    pub fn reconstruct_all(&self, shards: &mut Vec<Option<Vec<u8>>>) -> Result<()> {
+        let total_shards = self.data_shards + self.parity_shards;
+        if shards.len() != total_shards {
+            return Err(Error::Internal(format!(
+                "Expected {} shards, got {}",
+                total_shards,
+                shards.len()
+            )));
+        }
+        if !self.can_reconstruct(shards) {
+            return Err(Error::Internal(format!(
+                "Not enough shards to reconstruct: need {}, have {}",
+                self.data_shards,
+                shards.iter().filter(|s| s.is_some()).count()
+            )));
+        }
+
+        self.rs.reconstruct(shards)
+    }
+
+    /// Like [`encode`](Self::encode), but prepends each shard with a
+    /// 4-byte BLAKE3 checksum of its payload so
+    /// [`decode_verified`](Self::decode_verified) can tell a
+    /// present-but-corrupted shard from a trustworthy one instead of
+    /// feeding corruption straight into reconstruction.
+    pub fn encode_checksummed(&self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let shards = self.encode(data)?;
+        Ok(shards
+            .into_iter()
+            .map(|shard| {
+                let mut framed = Vec::with_capacity(SHARD_CHECKSUM_LEN + shard.len());
+                framed.extend_from_slice(&shard_checksum(&shard));
+                framed.extend_from_slice(&shard);
+                framed
+            })
+            .collect())
+    }
+
+    /// Reverse of [`encode_checksummed`](Self::encode_checksummed):
+    /// verify each present shard's checksum, demoting any mismatch to a
+    /// missing shard, then reconstruct and decode as usual.
+    ///
+    /// # Errors
+    /// Returns [`Error::ShardCorruption`] when corrupted shards (not mere
+    /// absence) are what pushed the stripe below `data_shards` trustworthy
+    /// shards, so callers can distinguish "some accounts were offline"
+    /// from "data came back silently wrong".
+    pub fn decode_verified(&self, shards: &mut [Option<Vec<u8>>]) -> Result<Vec<u8>> {
+        let total_shards = self.data_shards + self.parity_shards;
+        if shards.len() != total_shards {
+            return Err(Error::Internal(format!(
+                "Expected {} shards, got {}",
+                total_shards,
+                shards.len()
+            )));
+        }
+
+        let mut corrupted = 0;
+        let mut verified: Vec<Option<Vec<u8>>> = Vec::with_capacity(shards.len());
+        for shard in shards.iter() {
+            match shard {
+                None => verified.push(None),
+                Some(framed) => match verify_and_unframe_shard(framed) {
+                    Some(payload) => verified.push(Some(payload)),
+                    None => {
+                        corrupted += 1;
+                        verified.push(None);
+                    }
+                },
+            }
+        }
+
+        if corrupted > 0 && !self.can_reconstruct(&verified) {
+            return Err(Error::ShardCorruption {
+                corrupted,
+                available: verified.iter().filter(|s| s.is_some()).count(),
+                required: self.data_shards,
+            });
+        }
+
+        self.decode(&mut verified)
+    }
+
+    /// Check whether checksummed shards are parity-consistent without
+    /// reconstructing - a cheap pre-flight before committing to a full
+    /// [`decode_verified`](Self::decode_verified) call. Requires every
+    /// shard to be present (unframes and verifies each) since parity
+    /// verification, unlike reconstruction, can't route around gaps.
+    pub fn verify(&self, shards: &[Vec<u8>]) -> Result<bool> {
+        let total_shards = self.data_shards + self.parity_shards;
+        if shards.len() != total_shards {
+            return Err(Error::Internal(format!(
+                "Expected {} shards, got {}",
+                total_shards,
+                shards.len()
+            )));
+        }
+
+        let mut unframed = Vec::with_capacity(shards.len());
+        for framed in shards {
+            match verify_and_unframe_shard(framed) {
+                Some(payload) => unframed.push(payload),
+                None => return Ok(false),
+            }
+        }
+
+        self.rs.verify(&unframed)
+    }
+}
+
+/// Describes how a [`StripedEncoder`] split an object into independently
+/// encoded stripes, so decode doesn't need the original object length
+/// passed back in separately and every stripe but the last can stay at a
+/// uniform `stripe_bytes` size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StripeManifest {
+    /// K, the number of data shards per stripe.
+    pub data_shards: usize,
+    /// N, the total shards (data + parity) per stripe.
+    pub total_shards: usize,
+    /// Plaintext bytes per stripe, except possibly the last.
+    pub stripe_bytes: usize,
+    /// Number of stripes the object was split into.
+    pub stripe_count: usize,
+    /// Plaintext length of the final stripe (`<= stripe_bytes`).
+    pub final_stripe_len: usize,
+}
+
+impl StripeManifest {
+    /// Total plaintext length across all stripes.
+    pub fn total_len(&self) -> usize {
+        if self.stripe_count == 0 {
+            return 0;
+        }
+        (self.stripe_count - 1) * self.stripe_bytes + self.final_stripe_len
+    }
+}
+
+/// Splits large objects into fixed-size stripes and Reed-Solomon encodes
+/// each one independently, so peak memory is bounded by a single stripe
+/// rather than the whole object plus all of its shards. Mirrors the
+/// "erasure set" model of grouping data into fixed-size sets keyed by
+/// `(stripe_index, shard_index)` rather than producing one giant shard set.
+pub struct StripedEncoder {
+    encoder: Encoder,
+    stripe_bytes: usize,
+}
+
+impl StripedEncoder {
+    /// Create a striped encoder with K data shards, N total shards, and a
+    /// target stripe size in plaintext bytes. `stripe_bytes` is rounded up
+    /// to the next multiple of `data_shards` so every stripe divides
+    /// evenly across its data shards.
+    pub fn new(data_shards: usize, total_shards: usize, stripe_bytes: usize) -> Result<Self> {
+        if stripe_bytes == 0 {
+            return Err(Error::Internal(
+                "stripe_bytes must be greater than 0".to_string(),
+            ));
+        }
+        let encoder = Encoder::new(data_shards, total_shards)?;
+        let remainder = stripe_bytes % data_shards;
+        let stripe_bytes = if remainder == 0 {
+            stripe_bytes
+        } else {
+            stripe_bytes + (data_shards - remainder)
+        };
+
+        Ok(Self {
+            encoder,
+            stripe_bytes,
+        })
+    }
+
+    /// The underlying per-stripe [`Encoder`].
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// Plaintext bytes per stripe (except possibly the last).
+    pub fn stripe_bytes(&self) -> usize {
+        self.stripe_bytes
+    }
+
+    /// Split `data` into stripes and encode each independently, returning
+    /// the manifest needed to decode plus one shard set per stripe.
+    pub fn encode(&self, data: &[u8]) -> Result<(StripeManifest, Vec<Vec<Vec<u8>>>)> {
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![&[]]
+        } else {
+            data.chunks(self.stripe_bytes).collect()
+        };
+        let final_stripe_len = chunks.last().map(|c| c.len()).unwrap_or(0);
+
+        let stripes = chunks
+            .into_iter()
+            .map(|chunk| self.encoder.encode(chunk))
+            .collect::<Result<Vec<_>>>()?;
+
+        let manifest = StripeManifest {
+            data_shards: self.encoder.data_shards(),
+            total_shards: self.encoder.total_shards(),
+            stripe_bytes: self.stripe_bytes,
+            stripe_count: stripes.len(),
+            final_stripe_len,
+        };
+
+        Ok((manifest, stripes))
+    }
+
+    /// Reconstruct the original object from per-stripe shard sets,
+    /// decoding and concatenating stripes in order.
+    ///
+    /// # Errors
+    /// Returns an error if `stripes` doesn't match `manifest.stripe_count`,
+    /// or if any stripe lacks enough shards to reconstruct.
+    pub fn decode(
+        &self,
+        manifest: &StripeManifest,
+        stripes: Vec<Vec<Option<Vec<u8>>>>,
+    ) -> Result<Vec<u8>> {
+        if stripes.len() != manifest.stripe_count {
+            return Err(Error::Internal(format!(
+                "Expected {} stripes, got {}",
+                manifest.stripe_count,
+                stripes.len()
+            )));
+        }
+
+        let mut data = Vec::with_capacity(manifest.total_len());
+        for mut stripe_shards in stripes {
+            data.extend_from_slice(&self.encoder.decode(&mut stripe_shards)?);
+        }
+
+        Ok(data)
+    }
+}
+
+const SHARD_TAG_MAGIC: [u8; 4] = *b"TGRS";
+const SHARD_TAG_VERSION: u8 = 1;
+const SHARD_TAG_HEADER_LEN: usize = 4 + 1 + 4 + 4 + 4 + 8 + 8;
+
+/// Fixed-size self-describing header prepended to every shard emitted by
+/// [`Encoder::encode_tagged`]. Unlike the length header [`Encoder::encode`]
+/// embeds inside the shard payload (which only tells you the plaintext
+/// size), this identifies K, N, this shard's own index, and which stripe
+/// it belongs to, so [`decode_tagged`] can reassemble a stripe from
+/// whatever tagged shards it's handed without any side-channel config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ShardTag {
+    data_shards: u32,
+    total_shards: u32,
+    shard_index: u32,
+    original_len: u64,
+    stripe_id: u64,
+}
+
+impl ShardTag {
+    fn to_bytes(self) -> [u8; SHARD_TAG_HEADER_LEN] {
+        let mut out = [0u8; SHARD_TAG_HEADER_LEN];
+        let mut pos = 0;
+        out[pos..pos + 4].copy_from_slice(&SHARD_TAG_MAGIC);
+        pos += 4;
+        out[pos] = SHARD_TAG_VERSION;
+        pos += 1;
+        out[pos..pos + 4].copy_from_slice(&self.data_shards.to_be_bytes());
+        pos += 4;
+        out[pos..pos + 4].copy_from_slice(&self.total_shards.to_be_bytes());
+        pos += 4;
+        out[pos..pos + 4].copy_from_slice(&self.shard_index.to_be_bytes());
+        pos += 4;
+        out[pos..pos + 8].copy_from_slice(&self.original_len.to_be_bytes());
+        pos += 8;
+        out[pos..pos + 8].copy_from_slice(&self.stripe_id.to_be_bytes());
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < SHARD_TAG_HEADER_LEN {
+            return Err(Error::Internal(
+                "Tagged shard shorter than its header".to_string(),
+            ));
+        }
+        if bytes[0..4] != SHARD_TAG_MAGIC {
+            return Err(Error::Internal("Tagged shard has bad magic".to_string()));
+        }
+        if bytes[4] != SHARD_TAG_VERSION {
+            return Err(Error::Internal(format!(
+                "Unsupported tagged shard header version {}",
+                bytes[4]
+            )));
+        }
+        Ok(Self {
+            data_shards: u32::from_be_bytes(bytes[5..9].try_into().unwrap()),
+            total_shards: u32::from_be_bytes(bytes[9..13].try_into().unwrap()),
+            shard_index: u32::from_be_bytes(bytes[13..17].try_into().unwrap()),
+            original_len: u64::from_be_bytes(bytes[17..25].try_into().unwrap()),
+            stripe_id: u64::from_be_bytes(bytes[25..33].try_into().unwrap()),
+        })
+    }
+}
+
+impl Encoder {
+    /// Like [`encode`](Self::encode), but prepends each shard with a
+    /// [`ShardTag`] header identifying K, N, its own index, and
+    /// `stripe_id`, so [`decode_tagged`] can reconstruct without the
+    /// caller tracking K/N/ordering out of band. Useful for shards that
+    /// outlive the metadata describing them (moved across machines/
+    /// backends, or recovered from a backend that lost its index).
+    pub fn encode_tagged(&self, data: &[u8], stripe_id: u64) -> Result<Vec<Vec<u8>>> {
+        let shards = self.encode(data)?;
+        let total_shards = shards.len() as u32;
+        let original_len = data.len() as u64;
+
+        Ok(shards
+            .into_iter()
+            .enumerate()
+            .map(|(index, shard)| {
+                let tag = ShardTag {
+                    data_shards: self.data_shards as u32,
+                    total_shards,
+                    shard_index: index as u32,
+                    original_len,
+                    stripe_id,
+                };
+                let mut tagged = Vec::with_capacity(SHARD_TAG_HEADER_LEN + shard.len());
+                tagged.extend_from_slice(&tag.to_bytes());
+                tagged.extend_from_slice(&shard);
+                tagged
+            })
+            .collect())
+    }
+}
+
+/// Reconstruct the plaintext a set of [`Encoder::encode_tagged`] shards
+/// was encoded from, without the caller supplying K or N: every shard's
+/// header already carries them. Shards may arrive in any order and with
+/// gaps (missing indices become erasures); every present shard must agree
+/// on K, N, original length, and stripe id, or this rejects the set as
+/// not belonging to the same stripe.
+///
+/// # Errors
+/// Returns an error if `shards` is empty, shards disagree with each
+/// other, a shard's index is out of range for its own declared N, or
+/// there aren't enough agreeing shards to reconstruct.
+pub fn decode_tagged(shards: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if shards.is_empty() {
+        return Err(Error::Internal(
+            "decode_tagged requires at least one shard".to_string(),
+        ));
+    }
+
+    let parsed = shards
+        .iter()
+        .map(|tagged| {
+            let tag = ShardTag::from_bytes(tagged)?;
+            Ok((tag, tagged[SHARD_TAG_HEADER_LEN..].to_vec()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let first = parsed[0].0;
+    for (tag, _) in &parsed {
+        if tag.data_shards != first.data_shards
+            || tag.total_shards != first.total_shards
+            || tag.original_len != first.original_len
+            || tag.stripe_id != first.stripe_id
+        {
+            return Err(Error::Internal(
+                "Tagged shards disagree on K/N/original_len/stripe_id".to_string(),
+            ));
+        }
+    }
+
+    let mut slots: Vec<Option<Vec<u8>>> = vec![None; first.total_shards as usize];
+    for (tag, payload) in parsed {
+        let index = tag.shard_index as usize;
+        if index >= slots.len() {
+            return Err(Error::Internal(format!(
+                "Tagged shard index {} out of range for {} total shards",
+                index, first.total_shards
+            )));
+        }
+        slots[index] = Some(payload);
+    }
+
+    let encoder = Encoder::new(first.data_shards as usize, first.total_shards as usize)?;
+    encoder.decode(&mut slots)
 }
 
 #[cfg(test)]
@@ -497,4 +1053,278 @@ mod tests {
             assert_eq!(decoded, data.as_slice(), "Failed for combination {:?}", combo);
         }
     }
+
+    #[test]
+    fn test_reconstruct_shard_returns_only_missing_shard() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Rebuild a single missing shard without full decode";
+
+        let shards = encoder.encode(data).unwrap();
+        let missing_index = 2;
+        let shards_opt: Vec<Option<Vec<u8>>> = shards
+            .iter()
+            .enumerate()
+            .map(|(i, s)| if i == missing_index { None } else { Some(s.clone()) })
+            .collect();
+
+        let rebuilt = encoder.reconstruct_shard(shards_opt, missing_index).unwrap();
+        assert_eq!(rebuilt, shards[missing_index]);
+    }
+
+    #[test]
+    fn test_reconstruct_shard_fails_below_threshold() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let shards_opt: Vec<Option<Vec<u8>>> = vec![Some(vec![0u8; 8]), None, None, None, None];
+        assert!(encoder.reconstruct_shard(shards_opt, 1).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_all_fills_in_every_missing_shard() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Rebuild every missing shard of a stripe in one pass";
+
+        let shards = encoder.encode(data).unwrap();
+        let mut shards_opt: Vec<Option<Vec<u8>>> = shards
+            .iter()
+            .enumerate()
+            .map(|(i, s)| if i == 1 || i == 4 { None } else { Some(s.clone()) })
+            .collect();
+
+        encoder.reconstruct_all(&mut shards_opt).unwrap();
+        let rebuilt: Vec<Vec<u8>> = shards_opt.into_iter().map(|s| s.unwrap()).collect();
+        assert_eq!(rebuilt, shards);
+    }
+
+    #[test]
+    fn test_reconstruct_all_fails_below_threshold() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let mut shards_opt: Vec<Option<Vec<u8>>> = vec![Some(vec![0u8; 8]), None, None, None, None];
+        assert!(encoder.reconstruct_all(&mut shards_opt).is_err());
+    }
+
+    #[test]
+    fn test_new_picks_gf8_for_small_total_shards() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        assert_eq!(encoder.field(), GaloisField::Gf8);
+    }
+
+    #[test]
+    fn test_new_picks_gf16_above_255_total_shards() {
+        let encoder = Encoder::new(100, 300).unwrap();
+        assert_eq!(encoder.field(), GaloisField::Gf16);
+        assert_eq!(encoder.total_shards(), 300);
+    }
+
+    #[test]
+    fn test_gf8_rejects_total_shards_above_255() {
+        let result = Encoder::with_field(100, 300, GaloisField::Gf8);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_gf16_round_trip_with_wide_fan_out() {
+        let encoder = Encoder::with_field(100, 300, GaloisField::Gf16).unwrap();
+        let data = b"Wide fan-out erasure coding beyond the GF(2^8) 255-shard ceiling.";
+
+        let shards = encoder.encode(data).unwrap();
+        assert_eq!(shards.len(), 300);
+        assert_eq!(shards[0].len() % 2, 0, "GF(2^16) shards must be an even number of bytes");
+
+        let mut shards_opt: Vec<Option<Vec<u8>>> = shards.iter().map(|s| Some(s.clone())).collect();
+        // Drop some parity shards to exercise reconstruction, not just the happy path.
+        for slot in shards_opt.iter_mut().skip(100).take(50) {
+            *slot = None;
+        }
+        let decoded = encoder.decode(&mut shards_opt).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_striped_encoder_rounds_stripe_bytes_to_multiple_of_k() {
+        let striped = StripedEncoder::new(3, 5, 10).unwrap();
+        assert_eq!(striped.stripe_bytes(), 12);
+    }
+
+    #[test]
+    fn test_striped_encoder_round_trip_multiple_stripes() {
+        let striped = StripedEncoder::new(3, 5, 16).unwrap();
+        let data: Vec<u8> = (0..100u32).map(|i| (i % 256) as u8).collect();
+
+        let (manifest, stripes) = striped.encode(&data).unwrap();
+        assert_eq!(manifest.data_shards, 3);
+        assert_eq!(manifest.total_shards, 5);
+        assert_eq!(manifest.total_len(), data.len());
+        assert!(manifest.stripe_count > 1, "100 bytes should span multiple 16-byte-ish stripes");
+
+        let shards_opt: Vec<Vec<Option<Vec<u8>>>> = stripes
+            .into_iter()
+            .map(|stripe| stripe.into_iter().map(Some).collect())
+            .collect();
+        let decoded = striped.decode(&manifest, shards_opt).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_striped_encoder_round_trip_with_missing_shards_per_stripe() {
+        let striped = StripedEncoder::new(2, 4, 8).unwrap();
+        let data = b"Pipelined stripe-based erasure coding for large objects end to end.";
+
+        let (manifest, stripes) = striped.encode(data).unwrap();
+
+        let shards_opt: Vec<Vec<Option<Vec<u8>>>> = stripes
+            .into_iter()
+            .map(|stripe| {
+                stripe
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, s)| if i == 1 { None } else { Some(s) })
+                    .collect()
+            })
+            .collect();
+        let decoded = striped.decode(&manifest, shards_opt).unwrap();
+        assert_eq!(decoded, data.as_slice());
+    }
+
+    #[test]
+    fn test_striped_encoder_round_trip_empty_data() {
+        let striped = StripedEncoder::new(2, 3, 8).unwrap();
+        let (manifest, stripes) = striped.encode(b"").unwrap();
+        assert_eq!(manifest.stripe_count, 1);
+        assert_eq!(manifest.total_len(), 0);
+
+        let shards_opt: Vec<Vec<Option<Vec<u8>>>> = stripes
+            .into_iter()
+            .map(|stripe| stripe.into_iter().map(Some).collect())
+            .collect();
+        let decoded = striped.decode(&manifest, shards_opt).unwrap();
+        assert_eq!(decoded, b"");
+    }
+
+    #[test]
+    fn test_striped_encoder_decode_rejects_stripe_count_mismatch() {
+        let striped = StripedEncoder::new(2, 3, 8).unwrap();
+        let (manifest, stripes) = striped.encode(b"some test data here").unwrap();
+        let mut shards_opt: Vec<Vec<Option<Vec<u8>>>> = stripes
+            .into_iter()
+            .map(|stripe| stripe.into_iter().map(Some).collect())
+            .collect();
+        shards_opt.pop();
+
+        assert!(striped.decode(&manifest, shards_opt).is_err());
+    }
+
+    #[test]
+    fn test_decode_verified_round_trips_with_all_shards_intact() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Checksummed shards should round-trip like plain ones";
+
+        let shards = encoder.encode_checksummed(data).unwrap();
+        let mut shards_opt: Vec<Option<Vec<u8>>> =
+            shards.iter().map(|s| Some(s.clone())).collect();
+        let decoded = encoder.decode_verified(&mut shards_opt).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_decode_verified_routes_around_corrupted_shard() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"A corrupted-but-present shard must not poison reconstruction";
+
+        let shards = encoder.encode_checksummed(data).unwrap();
+        let mut shards_opt: Vec<Option<Vec<u8>>> =
+            shards.iter().map(|s| Some(s.clone())).collect();
+
+        // Flip a byte in shard 1's payload (past the checksum header) so
+        // it's present but fails verification - parity should route
+        // around it exactly as if it had been missing.
+        let corrupted = shards_opt[1].as_mut().unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+
+        let decoded = encoder.decode_verified(&mut shards_opt).unwrap();
+        assert_eq!(decoded, data.as_slice());
+    }
+
+    #[test]
+    fn test_decode_verified_fails_distinctly_when_corruption_exceeds_parity() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Too much corruption to route around";
+
+        let shards = encoder.encode_checksummed(data).unwrap();
+        let mut shards_opt: Vec<Option<Vec<u8>>> =
+            shards.iter().map(|s| Some(s.clone())).collect();
+
+        // Corrupt 3 of 5 shards - only 2 parity shards, so this exceeds
+        // what reconstruction can route around.
+        for slot in shards_opt.iter_mut().take(3) {
+            let bytes = slot.as_mut().unwrap();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0xFF;
+        }
+
+        let err = encoder.decode_verified(&mut shards_opt).unwrap_err();
+        assert!(matches!(err, Error::ShardCorruption { .. }));
+    }
+
+    #[test]
+    fn test_verify_detects_consistent_and_corrupted_shard_sets() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Verify parity without reconstructing";
+
+        let shards = encoder.encode_checksummed(data).unwrap();
+        assert!(encoder.verify(&shards).unwrap());
+
+        let mut tampered = shards.clone();
+        let last = tampered[0].len() - 1;
+        tampered[0][last] ^= 0xFF;
+        assert!(!encoder.verify(&tampered).unwrap());
+    }
+
+    #[test]
+    fn test_encode_tagged_decode_tagged_round_trip() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Self-describing shards need no external K/N config to decode";
+
+        let shards = encoder.encode_tagged(data, 42).unwrap();
+        assert_eq!(shards.len(), 5);
+
+        let decoded = decode_tagged(&shards).unwrap();
+        assert_eq!(decoded, data.as_slice());
+    }
+
+    #[test]
+    fn test_decode_tagged_reconstructs_from_a_subset_in_any_order() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let data = b"Shards may arrive out of order and with gaps";
+
+        let mut shards = encoder.encode_tagged(data, 7).unwrap();
+        // Drop two shards and shuffle the rest - decode_tagged must not
+        // rely on array position, only each shard's own header.
+        shards.remove(4);
+        shards.remove(1);
+        shards.swap(0, 2);
+
+        let decoded = decode_tagged(&shards).unwrap();
+        assert_eq!(decoded, data.as_slice());
+    }
+
+    #[test]
+    fn test_decode_tagged_rejects_shards_from_different_stripes() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let mut shards_a = encoder.encode_tagged(b"stripe a data here", 1).unwrap();
+        let shards_b = encoder.encode_tagged(b"stripe b data here", 2).unwrap();
+
+        shards_a[0] = shards_b[0].clone();
+
+        assert!(decode_tagged(&shards_a).is_err());
+    }
+
+    #[test]
+    fn test_decode_tagged_fails_below_reconstruction_threshold() {
+        let encoder = Encoder::new(3, 5).unwrap();
+        let mut shards = encoder.encode_tagged(b"not enough shards survive", 9).unwrap();
+        shards.truncate(2);
+
+        assert!(decode_tagged(&shards).is_err());
+    }
 }
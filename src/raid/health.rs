@@ -2,9 +2,16 @@
 //!
 //! Tracks the health status of each Telegram account and the overall array.
 
+use crate::error::{Error, Result};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
 
 /// Default number of consecutive failures before marking account unavailable
 const DEFAULT_MAX_FAILURES: u32 = 3;
@@ -12,6 +19,56 @@ const DEFAULT_MAX_FAILURES: u32 = 3;
 /// Error rate threshold for degraded status (10%)
 const DEGRADED_ERROR_RATE_THRESHOLD: f64 = 0.10;
 
+/// Base backoff before the first retry of a quarantined account (seconds)
+const BASE_BACKOFF_SECS: i64 = 30;
+
+/// Upper bound on backoff between retries of a quarantined account (seconds)
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// Jitter applied to the computed backoff, as a fraction of it (+/- 20%)
+const BACKOFF_JITTER_FRACTION: f64 = 0.2;
+
+/// Default smoothing factor for the error-rate EWMA
+const DEFAULT_EWMA_ALPHA: f64 = 0.1;
+
+/// Minimum number of recorded operations before the EWMA is trusted to
+/// drive status decisions, so a single early failure can't pin a fresh
+/// account to `Degraded`
+const MIN_OPERATIONS_FOR_EWMA: u64 = 5;
+
+/// Default staleness window (seconds): an account with no successful
+/// operation in this long gets demoted from `Healthy` to `Degraded` by
+/// [`HealthTracker::tick`], even if nothing has actually failed
+const DEFAULT_STALENESS_SECS: i64 = 300;
+
+/// An account idle beyond `staleness_secs * PROBE_STALENESS_MULTIPLIER` is
+/// additionally queued for an active liveness probe, not just demoted
+const PROBE_STALENESS_MULTIPLIER: i64 = 3;
+
+/// Maximum number of liveness probes `tick` will queue in a single call, so
+/// many accounts going stale at once doesn't thundering-herd probe traffic
+const MAX_PROBES_PER_INTERVAL: usize = 2;
+
+/// Upper bound (inclusive) of each fixed latency histogram bucket, in
+/// milliseconds. An observation is sorted into the first bucket whose
+/// bound it doesn't exceed; anything above the last bound falls into an
+/// implicit overflow bucket. Fixed, pre-allocated buckets keep
+/// [`LatencyHistogram::record`] a few array increments under the existing
+/// `RwLock`, instead of a data structure that grows or sorts per sample.
+const LATENCY_BUCKET_BOUNDS_MS: [f64; 12] = [
+    1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Number of buckets in a [`LatencyHistogram`], including the overflow
+/// bucket above the largest bound in [`LATENCY_BUCKET_BOUNDS_MS`]
+const LATENCY_BUCKET_COUNT: usize = LATENCY_BUCKET_BOUNDS_MS.len() + 1;
+
+/// Smoothing factor for [`RebuildProgress`]'s throughput estimate. Higher
+/// than [`DEFAULT_EWMA_ALPHA`] because a rebuild is a single short-lived
+/// operation, not a lifetime error rate - the ETA should track the last
+/// few progress updates closely rather than the whole rebuild's history.
+const REBUILD_THROUGHPUT_EWMA_ALPHA: f64 = 0.3;
+
 /// Status of a single account
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AccountStatus {
@@ -42,6 +99,17 @@ pub struct AccountHealth {
     pub total_operations: u64,
     /// Total failed operations
     pub failed_operations: u64,
+    /// Unix timestamp at or after which an unavailable account may be
+    /// retried, set when the account is quarantined and cleared on success
+    pub next_retry_at: Option<i64>,
+    /// Exponentially-weighted moving average of recent outcomes (1.0 =
+    /// failure, 0.0 = success), tracking error rate over roughly the last
+    /// `1 / alpha` operations instead of the account's full lifetime
+    pub ewma_error: f64,
+    /// Progress of an in-flight rebuild, set by [`HealthTracker::set_rebuilding`]
+    /// and cleared by [`HealthTracker::set_healthy`]. `None` whenever the
+    /// account isn't `Rebuilding`.
+    pub rebuild: Option<RebuildProgress>,
 }
 
 impl AccountHealth {
@@ -55,16 +123,25 @@ impl AccountHealth {
             failure_count: 0,
             total_operations: 0,
             failed_operations: 0,
+            next_retry_at: None,
+            ewma_error: 0.0,
+            rebuild: None,
         }
     }
 
-    /// Calculate error rate
+    /// Calculate error rate (lifetime-cumulative)
     pub fn error_rate(&self) -> f64 {
         if self.total_operations == 0 {
             return 0.0;
         }
         self.failed_operations as f64 / self.total_operations as f64
     }
+
+    /// Whether enough operations have been observed for `ewma_error` to be
+    /// trusted to drive status decisions
+    fn ewma_is_warm(&self) -> bool {
+        self.total_operations >= MIN_OPERATIONS_FOR_EWMA
+    }
 }
 
 impl Default for AccountHealth {
@@ -77,6 +154,233 @@ impl Default for AccountHealth {
             failure_count: 0,
             total_operations: 0,
             failed_operations: 0,
+            next_retry_at: None,
+            ewma_error: 0.0,
+            rebuild: None,
+        }
+    }
+}
+
+/// Live progress of a single account's rebuild, tracking real byte counts
+/// instead of the fixed placeholder `array_health()` used to report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebuildProgress {
+    /// Bytes reconstructed and re-uploaded so far
+    pub bytes_done: u64,
+    /// Total bytes this account is expected to receive before the rebuild
+    /// is complete
+    pub bytes_total: u64,
+    /// Unix timestamp the rebuild started at
+    pub started_at: i64,
+    /// Exponentially-smoothed throughput estimate in bytes/sec, derived
+    /// from the gap between successive [`HealthTracker::update_rebuild_progress`]
+    /// calls
+    throughput_bps: f64,
+    /// Timestamp of the last progress update, used to compute the
+    /// instantaneous throughput between updates
+    last_update_at: i64,
+}
+
+impl RebuildProgress {
+    fn new(bytes_total: u64, now: i64) -> Self {
+        Self {
+            bytes_done: 0,
+            bytes_total,
+            started_at: now,
+            throughput_bps: 0.0,
+            last_update_at: now,
+        }
+    }
+
+    /// Record that `bytes_done` bytes have now been reconstructed in total,
+    /// updating the smoothed throughput estimate from the bytes made
+    /// progress since the last update.
+    fn advance(&mut self, bytes_done: u64, now: i64) {
+        let delta = bytes_done.saturating_sub(self.bytes_done);
+        if delta > 0 {
+            let elapsed_secs = (now - self.last_update_at).max(1) as f64;
+            let instantaneous_bps = delta as f64 / elapsed_secs;
+            self.throughput_bps = if self.bytes_done == 0 {
+                instantaneous_bps
+            } else {
+                REBUILD_THROUGHPUT_EWMA_ALPHA * instantaneous_bps
+                    + (1.0 - REBUILD_THROUGHPUT_EWMA_ALPHA) * self.throughput_bps
+            };
+        }
+        self.bytes_done = bytes_done.min(self.bytes_total);
+        self.last_update_at = now;
+    }
+
+    /// Fraction of this account's rebuild completed, in `[0.0, 1.0]`
+    pub fn fraction(&self) -> f32 {
+        if self.bytes_total == 0 {
+            1.0
+        } else {
+            (self.bytes_done as f64 / self.bytes_total as f64) as f32
+        }
+    }
+
+    /// Estimated time remaining in seconds, or `None` if throughput hasn't
+    /// been observed yet (e.g. no progress reported since the rebuild
+    /// started)
+    pub fn eta_secs(&self) -> Option<i64> {
+        if self.throughput_bps <= 0.0 {
+            return None;
+        }
+        let remaining = self.bytes_total.saturating_sub(self.bytes_done);
+        Some((remaining as f64 / self.throughput_bps).ceil() as i64)
+    }
+}
+
+/// Fixed-bucket latency histogram for a single account/operation-kind
+/// pair. Bucket boundaries are the same for every account (see
+/// [`LATENCY_BUCKET_BOUNDS_MS`]), so recording a sample is just finding
+/// which bucket it falls in and incrementing a counter - O(buckets), no
+/// allocation, cheap enough to do on every `record_*` call under the
+/// tracker's `RwLock`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// Count of observations per bucket; `bucket_counts[i]` holds samples
+    /// `<= LATENCY_BUCKET_BOUNDS_MS[i]`, and the last slot catches
+    /// everything above the largest bound
+    bucket_counts: [u64; LATENCY_BUCKET_COUNT],
+    /// Total observations recorded
+    count: u64,
+    /// Running sum, for the mean
+    sum_ms: f64,
+    /// Smallest observation recorded
+    min_ms: f64,
+    /// Largest observation recorded
+    max_ms: f64,
+}
+
+impl LatencyHistogram {
+    fn new() -> Self {
+        Self {
+            bucket_counts: [0; LATENCY_BUCKET_COUNT],
+            count: 0,
+            sum_ms: 0.0,
+            min_ms: 0.0,
+            max_ms: 0.0,
+        }
+    }
+
+    /// Record one observed operation duration
+    fn record(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+
+        if self.count == 0 {
+            self.min_ms = ms;
+            self.max_ms = ms;
+        } else {
+            self.min_ms = self.min_ms.min(ms);
+            self.max_ms = self.max_ms.max(ms);
+        }
+        self.count += 1;
+        self.sum_ms += ms;
+
+        let bucket = LATENCY_BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len());
+        self.bucket_counts[bucket] += 1;
+    }
+
+    /// Mean latency in milliseconds
+    pub fn mean_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum_ms / self.count as f64
+        }
+    }
+
+    /// Smallest recorded latency in milliseconds
+    pub fn min_ms(&self) -> f64 {
+        self.min_ms
+    }
+
+    /// Largest recorded latency in milliseconds
+    pub fn max_ms(&self) -> f64 {
+        self.max_ms
+    }
+
+    /// Estimate the `p`-th quantile (e.g. `0.99` for p99) in milliseconds
+    /// from the bucket counts. The estimate is the upper bound of whichever
+    /// bucket the target rank falls in, so it's biased high by at most one
+    /// bucket width - acceptable for the "is this account getting slow"
+    /// signal this is built for, in exchange for O(1) space per sample.
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let target = ((p * self.count as f64).ceil() as u64).max(1);
+        let mut cumulative = 0u64;
+        for (i, &bucket_count) in self.bucket_counts.iter().enumerate() {
+            cumulative += bucket_count;
+            if cumulative >= target {
+                return *LATENCY_BUCKET_BOUNDS_MS.get(i).unwrap_or(&self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+
+    /// Estimated 50th percentile latency in milliseconds
+    pub fn p50_ms(&self) -> f64 {
+        self.percentile(0.50)
+    }
+
+    /// Estimated 90th percentile latency in milliseconds
+    pub fn p90_ms(&self) -> f64 {
+        self.percentile(0.90)
+    }
+
+    /// Estimated 99th percentile latency in milliseconds
+    pub fn p99_ms(&self) -> f64 {
+        self.percentile(0.99)
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Success/failure counts and latency distribution for one kind of
+/// operation (reads or writes) against one account
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OperationMetrics {
+    /// Number of successful operations of this kind
+    pub successes: u64,
+    /// Number of failed operations of this kind
+    pub failures: u64,
+    /// Latency distribution across both successes and failures
+    pub histogram: LatencyHistogram,
+}
+
+/// Read and write metrics for a single account, as exposed by
+/// [`HealthTracker::account_metrics`] and [`HealthTracker::export_metrics`].
+/// Kept separate from [`AccountHealth`] because it's purely observational -
+/// unlike status and failure counts, it isn't needed to make availability
+/// decisions and doesn't need to survive a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountMetrics {
+    /// Account ID
+    pub account_id: u8,
+    /// Read (download) metrics
+    pub read: OperationMetrics,
+    /// Write (upload) metrics
+    pub write: OperationMetrics,
+}
+
+impl AccountMetrics {
+    fn new(account_id: u8) -> Self {
+        Self {
+            account_id,
+            read: OperationMetrics::default(),
+            write: OperationMetrics::default(),
         }
     }
 }
@@ -105,18 +409,139 @@ pub struct ArrayHealth {
     pub required_accounts: usize,
     /// Total accounts configured (N)
     pub total_accounts: usize,
-    /// Rebuild progress (0.0 - 1.0) if rebuilding
+    /// Rebuild progress (0.0 - 1.0) if rebuilding, aggregated across every
+    /// rebuilding account weighted by `bytes_total`
     pub rebuild_progress: Option<f32>,
+    /// Estimated time remaining for the rebuild, in seconds, derived from
+    /// recent per-account throughput. `None` while rebuilding if no
+    /// account has reported progress yet.
+    pub rebuild_eta_secs: Option<i64>,
+}
+
+/// Current on-disk format of [`HealthSnapshot`]
+const HEALTH_SNAPSHOT_VERSION: u32 = 3;
+
+/// A versioned, self-describing serialization of [`HealthTracker`] state,
+/// so it can survive a process restart instead of resetting every account
+/// to healthy (and re-probing known-dead ones) on every boot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthSnapshot {
+    /// Format version, bumped whenever a field is added or changed so old
+    /// snapshots can still be read
+    pub version: u32,
+    /// Per-account health as of the snapshot
+    pub accounts: Vec<AccountHealth>,
+    /// `max_failures_before_unavailable` as of the snapshot
+    pub max_failures_before_unavailable: u32,
+    /// `ewma_alpha` as of the snapshot
+    pub ewma_alpha: f64,
+    /// `staleness_secs` as of the snapshot (added in version 2; snapshots
+    /// from version 1 fall back to [`DEFAULT_STALENESS_SECS`])
+    pub staleness_secs: i64,
+}
+
+/// On-disk shape of [`AccountHealth`] prior to rebuild-progress tracking
+/// (snapshot versions 1 and 2), kept only to migrate older snapshots.
+#[derive(Debug, Clone, Deserialize)]
+struct AccountHealthV2 {
+    account_id: u8,
+    status: AccountStatus,
+    last_success: Option<i64>,
+    last_error: Option<String>,
+    failure_count: u32,
+    total_operations: u64,
+    failed_operations: u64,
+    next_retry_at: Option<i64>,
+    ewma_error: f64,
+}
+
+impl From<AccountHealthV2> for AccountHealth {
+    fn from(v2: AccountHealthV2) -> Self {
+        AccountHealth {
+            account_id: v2.account_id,
+            status: v2.status,
+            last_success: v2.last_success,
+            last_error: v2.last_error,
+            failure_count: v2.failure_count,
+            total_operations: v2.total_operations,
+            failed_operations: v2.failed_operations,
+            next_retry_at: v2.next_retry_at,
+            ewma_error: v2.ewma_error,
+            rebuild: None,
+        }
+    }
+}
+
+/// Pre-v2 on-disk shape of [`HealthSnapshot`], kept only to migrate
+/// snapshots written before `staleness_secs` existed.
+#[derive(Debug, Clone, Deserialize)]
+struct HealthSnapshotV1 {
+    #[allow(dead_code)]
+    version: u32,
+    accounts: Vec<AccountHealthV2>,
+    max_failures_before_unavailable: u32,
+    ewma_alpha: f64,
+}
+
+impl From<HealthSnapshotV1> for HealthSnapshot {
+    fn from(v1: HealthSnapshotV1) -> Self {
+        HealthSnapshot {
+            version: HEALTH_SNAPSHOT_VERSION,
+            accounts: v1.accounts.into_iter().map(Into::into).collect(),
+            max_failures_before_unavailable: v1.max_failures_before_unavailable,
+            ewma_alpha: v1.ewma_alpha,
+            staleness_secs: DEFAULT_STALENESS_SECS,
+        }
+    }
+}
+
+/// Pre-v3 on-disk shape of [`HealthSnapshot`], kept only to migrate
+/// snapshots written before rebuild-progress tracking existed.
+#[derive(Debug, Clone, Deserialize)]
+struct HealthSnapshotV2 {
+    #[allow(dead_code)]
+    version: u32,
+    accounts: Vec<AccountHealthV2>,
+    max_failures_before_unavailable: u32,
+    ewma_alpha: f64,
+    staleness_secs: i64,
+}
+
+impl From<HealthSnapshotV2> for HealthSnapshot {
+    fn from(v2: HealthSnapshotV2) -> Self {
+        HealthSnapshot {
+            version: HEALTH_SNAPSHOT_VERSION,
+            accounts: v2.accounts.into_iter().map(Into::into).collect(),
+            max_failures_before_unavailable: v2.max_failures_before_unavailable,
+            ewma_alpha: v2.ewma_alpha,
+            staleness_secs: v2.staleness_secs,
+        }
+    }
 }
 
 /// Health tracker for the account pool
 pub struct HealthTracker {
     accounts: RwLock<Vec<AccountHealth>>,
+    /// Per-account latency/throughput metrics. Purely observational (see
+    /// [`AccountMetrics`]) - not part of [`HealthSnapshot`], so it resets
+    /// to empty on restart the same way a freshly-started process's
+    /// Prometheus counters would.
+    metrics: RwLock<Vec<AccountMetrics>>,
     required_accounts: usize,
     max_failures_before_unavailable: u32,
+    ewma_alpha: f64,
+    staleness_secs: i64,
 }
 
 impl HealthTracker {
+    fn fresh_metrics(num_accounts: usize) -> RwLock<Vec<AccountMetrics>> {
+        RwLock::new(
+            (0..num_accounts)
+                .map(|i| AccountMetrics::new(i as u8))
+                .collect(),
+        )
+    }
+
     /// Create a new health tracker
     pub fn new(num_accounts: usize, required_accounts: usize) -> Self {
         let accounts: Vec<AccountHealth> = (0..num_accounts)
@@ -125,8 +550,11 @@ impl HealthTracker {
 
         Self {
             accounts: RwLock::new(accounts),
+            metrics: Self::fresh_metrics(num_accounts),
             required_accounts,
             max_failures_before_unavailable: DEFAULT_MAX_FAILURES,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
+            staleness_secs: DEFAULT_STALENESS_SECS,
         }
     }
 
@@ -142,8 +570,48 @@ impl HealthTracker {
 
         Self {
             accounts: RwLock::new(accounts),
+            metrics: Self::fresh_metrics(num_accounts),
             required_accounts,
             max_failures_before_unavailable: max_failures,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
+            staleness_secs: DEFAULT_STALENESS_SECS,
+        }
+    }
+
+    /// Create a new health tracker with a custom EWMA smoothing factor
+    pub fn with_ewma_alpha(num_accounts: usize, required_accounts: usize, ewma_alpha: f64) -> Self {
+        let accounts: Vec<AccountHealth> = (0..num_accounts)
+            .map(|i| AccountHealth::new(i as u8))
+            .collect();
+
+        Self {
+            accounts: RwLock::new(accounts),
+            metrics: Self::fresh_metrics(num_accounts),
+            required_accounts,
+            max_failures_before_unavailable: DEFAULT_MAX_FAILURES,
+            ewma_alpha,
+            staleness_secs: DEFAULT_STALENESS_SECS,
+        }
+    }
+
+    /// Create a new health tracker with a custom staleness window for
+    /// [`Self::tick`]
+    pub fn with_staleness_secs(
+        num_accounts: usize,
+        required_accounts: usize,
+        staleness_secs: i64,
+    ) -> Self {
+        let accounts: Vec<AccountHealth> = (0..num_accounts)
+            .map(|i| AccountHealth::new(i as u8))
+            .collect();
+
+        Self {
+            accounts: RwLock::new(accounts),
+            metrics: Self::fresh_metrics(num_accounts),
+            required_accounts,
+            max_failures_before_unavailable: DEFAULT_MAX_FAILURES,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
+            staleness_secs,
         }
     }
 
@@ -162,12 +630,16 @@ impl HealthTracker {
             account.total_operations += 1;
             account.last_success = Some(Self::now_unix_secs());
             account.failure_count = 0;
+            account.next_retry_at = None;
+            account.ewma_error =
+                self.ewma_alpha * 0.0 + (1.0 - self.ewma_alpha) * account.ewma_error;
 
-            // Update status based on error rate
+            // Update status based on recent error rate
             if account.status == AccountStatus::Unavailable {
-                // Recovery from unavailable - need explicit reset or rebuild
+                // A probe succeeded - clear the backoff and return to service
+                account.status = AccountStatus::Healthy;
             } else if account.status != AccountStatus::Rebuilding {
-                if account.error_rate() < DEGRADED_ERROR_RATE_THRESHOLD {
+                if !account.ewma_is_warm() || account.ewma_error < DEGRADED_ERROR_RATE_THRESHOLD {
                     account.status = AccountStatus::Healthy;
                 } else {
                     account.status = AccountStatus::Degraded;
@@ -184,18 +656,153 @@ impl HealthTracker {
             account.failed_operations += 1;
             account.failure_count += 1;
             account.last_error = Some(error.to_string());
+            account.ewma_error =
+                self.ewma_alpha * 1.0 + (1.0 - self.ewma_alpha) * account.ewma_error;
 
-            // Update status based on failure count
+            // Update status based on failure count and recent error rate
             if account.status != AccountStatus::Rebuilding {
-                if account.failure_count >= self.max_failures_before_unavailable {
+                if account.status == AccountStatus::Unavailable {
+                    // A retry probe failed while quarantined - double the backoff
+                    account.next_retry_at = Some(Self::next_retry_at(
+                        account.failure_count,
+                        self.max_failures_before_unavailable,
+                    ));
+                } else if account.failure_count >= self.max_failures_before_unavailable {
                     account.status = AccountStatus::Unavailable;
-                } else if account.error_rate() >= DEGRADED_ERROR_RATE_THRESHOLD {
+                    account.next_retry_at = Some(Self::next_retry_at(
+                        account.failure_count,
+                        self.max_failures_before_unavailable,
+                    ));
+                } else if account.ewma_is_warm()
+                    && account.ewma_error >= DEGRADED_ERROR_RATE_THRESHOLD
+                {
                     account.status = AccountStatus::Degraded;
                 }
             }
         }
     }
 
+    /// Compute the next retry time for an account that just went (or
+    /// remains) `Unavailable`, given its current consecutive failure count.
+    ///
+    /// Backoff is `BASE_BACKOFF_SECS * 2^(failure_count - threshold)`, capped
+    /// at `MAX_BACKOFF_SECS` and jittered by +/- `BACKOFF_JITTER_FRACTION`.
+    fn next_retry_at(failure_count: u32, threshold: u32) -> i64 {
+        let exponent = failure_count.saturating_sub(threshold);
+        let backoff = BASE_BACKOFF_SECS
+            .saturating_mul(1i64 << exponent.min(32))
+            .min(MAX_BACKOFF_SECS);
+
+        let jitter_range = (backoff as f64 * BACKOFF_JITTER_FRACTION) as i64;
+        let jitter = if jitter_range > 0 {
+            rand::random::<i64>().rem_euclid(2 * jitter_range + 1) - jitter_range
+        } else {
+            0
+        };
+
+        Self::now_unix_secs() + (backoff + jitter).max(1)
+    }
+
+    /// Record a successful read (download) operation, tracking how long it
+    /// took in addition to the ordinary status bookkeeping done by
+    /// [`Self::record_success`].
+    pub fn record_read_success(&self, account_id: u8, duration: Duration) {
+        self.record_success(account_id);
+        self.record_latency(account_id, duration, true, false);
+    }
+
+    /// Record a failed read (download) operation, tracking how long it took
+    /// in addition to the ordinary status bookkeeping done by
+    /// [`Self::record_failure`].
+    pub fn record_read_failure(&self, account_id: u8, error: &str, duration: Duration) {
+        self.record_failure(account_id, error);
+        self.record_latency(account_id, duration, false, false);
+    }
+
+    /// Record a successful write (upload) operation, tracking how long it
+    /// took in addition to the ordinary status bookkeeping done by
+    /// [`Self::record_success`].
+    pub fn record_write_success(&self, account_id: u8, duration: Duration) {
+        self.record_success(account_id);
+        self.record_latency(account_id, duration, true, true);
+    }
+
+    /// Record a failed write (upload) operation, tracking how long it took
+    /// in addition to the ordinary status bookkeeping done by
+    /// [`Self::record_failure`].
+    pub fn record_write_failure(&self, account_id: u8, error: &str, duration: Duration) {
+        self.record_failure(account_id, error);
+        self.record_latency(account_id, duration, false, true);
+    }
+
+    fn record_latency(&self, account_id: u8, duration: Duration, success: bool, is_write: bool) {
+        let mut metrics = self.metrics.write();
+        if let Some(account) = metrics.get_mut(account_id as usize) {
+            let op = if is_write {
+                &mut account.write
+            } else {
+                &mut account.read
+            };
+            if success {
+                op.successes += 1;
+            } else {
+                op.failures += 1;
+            }
+            op.histogram.record(duration);
+        }
+    }
+
+    /// Get latency/throughput metrics for a specific account
+    pub fn account_metrics(&self, account_id: u8) -> AccountMetrics {
+        self.metrics
+            .read()
+            .get(account_id as usize)
+            .cloned()
+            .unwrap_or_else(|| AccountMetrics::new(account_id))
+    }
+
+    /// Render every account's metrics in a scrape-friendly text format (one
+    /// metric line per account/operation/label set), so an operator can
+    /// chart which account is slow or erroring before it trips the
+    /// unavailability threshold.
+    pub fn export_metrics(&self) -> String {
+        use std::fmt::Write as _;
+
+        let metrics = self.metrics.read();
+        let mut out = String::new();
+        for account in metrics.iter() {
+            for (op_label, op) in [("read", &account.read), ("write", &account.write)] {
+                let account_id = account.account_id;
+                let _ = writeln!(
+                    out,
+                    "tgcryptfs_account_operations_total{{account=\"{account_id}\",op=\"{op_label}\",result=\"success\"}} {}",
+                    op.successes
+                );
+                let _ = writeln!(
+                    out,
+                    "tgcryptfs_account_operations_total{{account=\"{account_id}\",op=\"{op_label}\",result=\"failure\"}} {}",
+                    op.failures
+                );
+                let _ = writeln!(
+                    out,
+                    "tgcryptfs_account_latency_ms{{account=\"{account_id}\",op=\"{op_label}\",stat=\"mean\"}} {:.3}",
+                    op.histogram.mean_ms()
+                );
+                for (quantile, value) in [
+                    ("0.5", op.histogram.p50_ms()),
+                    ("0.9", op.histogram.p90_ms()),
+                    ("0.99", op.histogram.p99_ms()),
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "tgcryptfs_account_latency_ms{{account=\"{account_id}\",op=\"{op_label}\",quantile=\"{quantile}\"}} {value:.3}"
+                    );
+                }
+            }
+        }
+        out
+    }
+
     /// Get health status of a specific account
     pub fn account_health(&self, account_id: u8) -> AccountHealth {
         let accounts = self.accounts.read();
@@ -217,12 +824,27 @@ impl HealthTracker {
             .iter()
             .any(|a| a.status == AccountStatus::Rebuilding);
 
-        let rebuild_progress = if rebuilding {
-            // Calculate average rebuild progress (placeholder - actual implementation
-            // would track real progress)
-            Some(0.0)
+        // Aggregate per-account rebuild progress weighted by bytes_total,
+        // so a mostly-empty account finishing doesn't make the array look
+        // further along than it is.
+        let (rebuild_progress, rebuild_eta_secs) = if rebuilding {
+            let in_progress: Vec<&RebuildProgress> =
+                accounts.iter().filter_map(|a| a.rebuild.as_ref()).collect();
+            let bytes_total: u64 = in_progress.iter().map(|r| r.bytes_total).sum();
+            let bytes_done: u64 = in_progress.iter().map(|r| r.bytes_done).sum();
+
+            let progress = if bytes_total == 0 {
+                0.0
+            } else {
+                bytes_done as f32 / bytes_total as f32
+            };
+            // The array isn't done rebuilding until every account's rebuild
+            // is, so the array-wide ETA is the slowest one.
+            let eta = in_progress.iter().filter_map(|r| r.eta_secs()).max();
+
+            (Some(progress), eta)
         } else {
-            None
+            (None, None)
         };
 
         let status = if rebuilding {
@@ -245,6 +867,7 @@ impl HealthTracker {
             required_accounts: self.required_accounts,
             total_accounts: accounts.len(),
             rebuild_progress,
+            rebuild_eta_secs,
         }
     }
 
@@ -281,21 +904,27 @@ impl HealthTracker {
             .count()
     }
 
-    /// Mark account as rebuilding
-    pub fn set_rebuilding(&self, account_id: u8) {
+    /// Mark account as rebuilding, starting a [`RebuildProgress`] against
+    /// `bytes_total` so [`Self::update_rebuild_progress`] and
+    /// [`Self::array_health`] have a real size to measure against.
+    pub fn set_rebuilding(&self, account_id: u8, bytes_total: u64) {
+        let now = Self::now_unix_secs();
         let mut accounts = self.accounts.write();
         if let Some(account) = accounts.get_mut(account_id as usize) {
             account.status = AccountStatus::Rebuilding;
+            account.rebuild = Some(RebuildProgress::new(bytes_total, now));
         }
     }
 
-    /// Mark account as healthy after rebuild
+    /// Mark account as healthy after rebuild, clearing its rebuild record
     pub fn set_healthy(&self, account_id: u8) {
         let mut accounts = self.accounts.write();
         if let Some(account) = accounts.get_mut(account_id as usize) {
             account.status = AccountStatus::Healthy;
             account.failure_count = 0;
             account.last_error = None;
+            account.next_retry_at = None;
+            account.rebuild = None;
         }
     }
 
@@ -306,6 +935,7 @@ impl HealthTracker {
             account.failure_count = 0;
             account.failed_operations = 0;
             account.last_error = None;
+            account.next_retry_at = None;
 
             // If was unavailable, move back to healthy
             if account.status == AccountStatus::Unavailable {
@@ -314,13 +944,209 @@ impl HealthTracker {
         }
     }
 
-    /// Update rebuild progress for an account
-    pub fn update_rebuild_progress(&self, _account_id: u8, _progress: f32) {
-        // This would update internal tracking of rebuild progress
-        // For now, the ArrayHealth calculates it on demand
+    /// Unavailable accounts whose quarantine backoff has elapsed and that
+    /// the pool should probe again, rather than exclude permanently.
+    pub fn accounts_due_for_retry(&self) -> Vec<u8> {
+        let now = Self::now_unix_secs();
+        let accounts = self.accounts.read();
+        accounts
+            .iter()
+            .filter(|a| a.status == AccountStatus::Unavailable)
+            .filter(|a| a.next_retry_at.map(|t| t <= now).unwrap_or(false))
+            .map(|a| a.account_id)
+            .collect()
+    }
+
+    /// Report that `bytes_done` bytes have now been reconstructed in total
+    /// for `account_id`'s rebuild. A no-op if the account isn't currently
+    /// `Rebuilding` (e.g. the rebuild already finished or was never
+    /// started via [`Self::set_rebuilding`]).
+    pub fn update_rebuild_progress(&self, account_id: u8, bytes_done: u64) {
+        let now = Self::now_unix_secs();
+        let mut accounts = self.accounts.write();
+        if let Some(account) = accounts.get_mut(account_id as usize) {
+            if let Some(rebuild) = account.rebuild.as_mut() {
+                rebuild.advance(bytes_done, now);
+            }
+        }
+    }
+
+    /// Capture the tracker's current state as a versioned, serializable
+    /// snapshot.
+    pub fn snapshot(&self) -> HealthSnapshot {
+        HealthSnapshot {
+            version: HEALTH_SNAPSHOT_VERSION,
+            accounts: self.accounts.read().clone(),
+            max_failures_before_unavailable: self.max_failures_before_unavailable,
+            ewma_alpha: self.ewma_alpha,
+            staleness_secs: self.staleness_secs,
+        }
+    }
+
+    /// Rebuild a tracker from a previously captured snapshot.
+    ///
+    /// Reconciles a changed `num_accounts` against the snapshot rather than
+    /// panicking: accounts beyond the snapshot start out `Healthy`, and
+    /// accounts the snapshot has but the pool no longer does are discarded.
+    pub fn restore(
+        snapshot: &HealthSnapshot,
+        num_accounts: usize,
+        required_accounts: usize,
+    ) -> Self {
+        let mut accounts: Vec<AccountHealth> = (0..num_accounts)
+            .map(|i| {
+                snapshot
+                    .accounts
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| AccountHealth::new(i as u8))
+            })
+            .collect();
+        for (i, account) in accounts.iter_mut().enumerate() {
+            account.account_id = i as u8;
+        }
+
+        Self {
+            accounts: RwLock::new(accounts),
+            metrics: Self::fresh_metrics(num_accounts),
+            required_accounts,
+            max_failures_before_unavailable: snapshot.max_failures_before_unavailable,
+            ewma_alpha: snapshot.ewma_alpha,
+            staleness_secs: snapshot.staleness_secs,
+        }
+    }
+
+    /// Serialize the current state and atomically write it to `path`, so a
+    /// crash mid-write never leaves a truncated snapshot behind.
+    pub fn persist_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        let bytes = bincode::serialize(&self.snapshot())?;
+        let mut staged = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|e| Error::Internal(format!("failed to stage health snapshot: {e}")))?;
+        staged.write_all(&bytes)?;
+        staged.as_file().sync_all()?;
+        staged
+            .persist(path)
+            .map_err(|e| Error::Internal(format!("failed to persist health snapshot: {e}")))?;
+
+        Ok(())
+    }
+
+    /// Load a snapshot previously written by [`Self::persist_to`] and
+    /// reconstruct a tracker from it. Returns `Ok(None)` if no snapshot
+    /// exists yet at `path` (e.g. first boot).
+    pub fn load_from<P: AsRef<Path>>(
+        path: P,
+        num_accounts: usize,
+        required_accounts: usize,
+    ) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = std::fs::read(path)?;
+        let snapshot: HealthSnapshot = match bincode::deserialize(&bytes) {
+            Ok(snapshot) => snapshot,
+            Err(_) => match bincode::deserialize::<HealthSnapshotV2>(&bytes) {
+                Ok(v2) => v2.into(),
+                Err(_) => bincode::deserialize::<HealthSnapshotV1>(&bytes)?.into(),
+            },
+        };
+        Ok(Some(Self::restore(
+            &snapshot,
+            num_accounts,
+            required_accounts,
+        )))
+    }
+
+    /// Pure state-transition step for the background health monitor: act on
+    /// `last_success` staleness without performing any I/O, so it is
+    /// unit-testable with a synthetic `now` instead of real time.
+    ///
+    /// An account idle (no successful operation) for longer than
+    /// `staleness_secs` is demoted from `Healthy` to `Degraded`, even though
+    /// nothing has actually failed - this catches a silently-dead session
+    /// that just hasn't been exercised by a real read/write. An account
+    /// idle for `staleness_secs * PROBE_STALENESS_MULTIPLIER` is further
+    /// queued for an active liveness probe, capped at
+    /// `MAX_PROBES_PER_INTERVAL` per call to avoid a thundering herd of
+    /// probes when many accounts go stale at once.
+    pub fn tick(&self, now: i64) -> Vec<u8> {
+        let mut accounts = self.accounts.write();
+        let mut due_for_probe = Vec::new();
+
+        for account in accounts.iter_mut() {
+            if matches!(
+                account.status,
+                AccountStatus::Unavailable | AccountStatus::Rebuilding
+            ) {
+                continue;
+            }
+            let Some(last_success) = account.last_success else {
+                continue;
+            };
+
+            let idle = now - last_success;
+            if idle <= self.staleness_secs {
+                continue;
+            }
+
+            if account.status == AccountStatus::Healthy {
+                account.status = AccountStatus::Degraded;
+            }
+
+            if idle > self.staleness_secs * PROBE_STALENESS_MULTIPLIER
+                && due_for_probe.len() < MAX_PROBES_PER_INTERVAL
+            {
+                due_for_probe.push(account.account_id);
+            }
+        }
+
+        due_for_probe
+    }
+
+    /// Spawn a background thread that calls [`Self::tick`] on a fixed
+    /// interval and runs `prober` against whatever accounts it returns,
+    /// recording the outcome as an ordinary success/failure. Detached: it
+    /// runs for the lifetime of the process, the same as the cache's
+    /// write-back flush workers.
+    pub fn spawn_monitor(
+        self: &Arc<Self>,
+        interval: Duration,
+        prober: Arc<dyn LivenessProber>,
+    ) -> JoinHandle<()> {
+        let tracker = Arc::clone(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            for account_id in tracker.tick(Self::now_unix_secs()) {
+                let started = Instant::now();
+                if prober.probe(account_id) {
+                    tracker.record_read_success(account_id, started.elapsed());
+                } else {
+                    warn!("Liveness probe failed for account {}", account_id);
+                    tracker.record_read_failure(account_id, "liveness probe failed", started.elapsed());
+                }
+            }
+        })
     }
 }
 
+/// Performs an out-of-band liveness check against a single account,
+/// independent of whatever real read/write traffic it may or may not be
+/// seeing. Used by [`HealthTracker::spawn_monitor`] to probe accounts that
+/// [`HealthTracker::tick`] has flagged as stale.
+pub trait LivenessProber: Send + Sync {
+    /// Probe `account_id` and report whether it responded
+    fn probe(&self, account_id: u8) -> bool;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -490,7 +1316,7 @@ mod tests {
         for _ in 0..3 {
             tracker.record_failure(0, "Error");
         }
-        tracker.set_rebuilding(0);
+        tracker.set_rebuilding(0, 1000);
 
         let health = tracker.array_health();
         assert_eq!(health.status, ArrayStatus::Rebuilding);
@@ -511,13 +1337,14 @@ mod tests {
         for _ in 0..3 {
             tracker.record_failure(0, "Error");
         }
-        tracker.set_rebuilding(0);
+        tracker.set_rebuilding(0, 1000);
         tracker.set_healthy(0);
 
         let health = tracker.account_health(0);
         assert_eq!(health.status, AccountStatus::Healthy);
         assert_eq!(health.failure_count, 0);
         assert!(health.last_error.is_none());
+        assert!(health.rebuild.is_none());
     }
 
     #[test]
@@ -623,4 +1450,452 @@ mod tests {
         tracker.record_success(255);
         tracker.record_failure(255, "Error");
     }
+
+    #[test]
+    fn test_unavailable_account_gets_retry_schedule() {
+        let tracker = HealthTracker::new(3, 2);
+
+        for _ in 0..3 {
+            tracker.record_failure(0, "Error");
+        }
+
+        let health = tracker.account_health(0);
+        assert_eq!(health.status, AccountStatus::Unavailable);
+        assert!(health.next_retry_at.is_some());
+        assert!(health.next_retry_at.unwrap() > HealthTracker::now_unix_secs());
+    }
+
+    #[test]
+    fn test_accounts_due_for_retry_respects_schedule() {
+        let tracker = HealthTracker::new(3, 2);
+
+        for _ in 0..3 {
+            tracker.record_failure(0, "Error");
+        }
+
+        // Backoff hasn't elapsed yet
+        assert!(tracker.accounts_due_for_retry().is_empty());
+
+        // Force the schedule into the past as if the backoff had elapsed
+        {
+            let mut accounts = tracker.accounts.write();
+            accounts[0].next_retry_at = Some(HealthTracker::now_unix_secs() - 1);
+        }
+        assert_eq!(tracker.accounts_due_for_retry(), vec![0]);
+    }
+
+    #[test]
+    fn test_failed_probe_doubles_backoff() {
+        let tracker = HealthTracker::new(3, 2);
+
+        for _ in 0..3 {
+            tracker.record_failure(0, "Error");
+        }
+        let first_deadline = tracker.account_health(0).next_retry_at.unwrap();
+
+        // A failed retry probe should push the deadline further out
+        tracker.record_failure(0, "Still down");
+        let second_deadline = tracker.account_health(0).next_retry_at.unwrap();
+
+        assert!(second_deadline >= first_deadline);
+    }
+
+    #[test]
+    fn test_successful_probe_clears_backoff() {
+        let tracker = HealthTracker::new(3, 2);
+
+        for _ in 0..3 {
+            tracker.record_failure(0, "Error");
+        }
+        assert_eq!(
+            tracker.account_health(0).status,
+            AccountStatus::Unavailable
+        );
+
+        tracker.record_success(0);
+        let health = tracker.account_health(0);
+        assert_eq!(health.status, AccountStatus::Healthy);
+        assert!(health.next_retry_at.is_none());
+    }
+
+    #[test]
+    fn test_ewma_not_trusted_below_minimum_operations() {
+        let tracker = HealthTracker::new(3, 2);
+
+        // A single early failure shouldn't be enough to degrade the account
+        tracker.record_failure(0, "Error");
+        assert_eq!(tracker.account_health(0).status, AccountStatus::Healthy);
+    }
+
+    #[test]
+    fn test_ewma_degrades_and_recovers_on_recent_behavior() {
+        let tracker = HealthTracker::with_ewma_alpha(3, 2, 0.5);
+
+        // Warm up past the minimum, then drive the EWMA high with failures
+        for _ in 0..5 {
+            tracker.record_failure(0, "Error");
+        }
+        assert_eq!(
+            tracker.account_health(0).status,
+            AccountStatus::Unavailable
+        );
+
+        // A successful probe clears the backoff; further successes should
+        // pull the EWMA back down below the degraded threshold
+        tracker.record_success(0);
+        for _ in 0..5 {
+            tracker.record_success(0);
+        }
+        let health = tracker.account_health(0);
+        assert_eq!(health.status, AccountStatus::Healthy);
+        assert!(health.ewma_error < DEGRADED_ERROR_RATE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_snapshot_restore_round_trip() {
+        let tracker = HealthTracker::new(3, 2);
+        tracker.record_success(0);
+        for _ in 0..3 {
+            tracker.record_failure(1, "Error");
+        }
+
+        let snapshot = tracker.snapshot();
+        let restored = HealthTracker::restore(&snapshot, 3, 2);
+
+        assert_eq!(
+            restored.account_health(0).total_operations,
+            tracker.account_health(0).total_operations
+        );
+        assert_eq!(
+            restored.account_health(1).status,
+            AccountStatus::Unavailable
+        );
+        assert_eq!(
+            restored.account_health(1).next_retry_at,
+            tracker.account_health(1).next_retry_at
+        );
+    }
+
+    #[test]
+    fn test_restore_reconciles_grown_account_count() {
+        let tracker = HealthTracker::new(2, 1);
+        tracker.record_success(0);
+
+        let snapshot = tracker.snapshot();
+        let restored = HealthTracker::restore(&snapshot, 4, 2);
+
+        assert_eq!(restored.accounts.read().len(), 4);
+        assert_eq!(restored.account_health(3).status, AccountStatus::Healthy);
+        assert_eq!(restored.account_health(3).total_operations, 0);
+    }
+
+    #[test]
+    fn test_restore_reconciles_shrunk_account_count() {
+        let tracker = HealthTracker::new(4, 2);
+        for _ in 0..3 {
+            tracker.record_failure(3, "Error");
+        }
+
+        let snapshot = tracker.snapshot();
+        let restored = HealthTracker::restore(&snapshot, 2, 2);
+
+        assert_eq!(restored.accounts.read().len(), 2);
+    }
+
+    #[test]
+    fn test_persist_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("health.bin");
+
+        let tracker = HealthTracker::new(3, 2);
+        for _ in 0..3 {
+            tracker.record_failure(0, "Error");
+        }
+        tracker.persist_to(&path).unwrap();
+
+        let restored = HealthTracker::load_from(&path, 3, 2).unwrap().unwrap();
+        assert_eq!(
+            restored.account_health(0).status,
+            AccountStatus::Unavailable
+        );
+    }
+
+    #[test]
+    fn test_load_from_missing_path_returns_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("does-not-exist.bin");
+
+        assert!(HealthTracker::load_from(&path, 3, 2).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tick_ignores_fresh_accounts() {
+        let tracker = HealthTracker::with_staleness_secs(3, 2, 60);
+        tracker.record_success(0);
+
+        let now = HealthTracker::now_unix_secs();
+        assert!(tracker.tick(now).is_empty());
+        assert_eq!(tracker.account_health(0).status, AccountStatus::Healthy);
+    }
+
+    #[test]
+    fn test_tick_demotes_stale_healthy_account() {
+        let tracker = HealthTracker::with_staleness_secs(3, 2, 60);
+        tracker.record_success(0);
+
+        let stale_now = HealthTracker::now_unix_secs() + 61;
+        let probes = tracker.tick(stale_now);
+
+        assert!(probes.is_empty());
+        assert_eq!(tracker.account_health(0).status, AccountStatus::Degraded);
+    }
+
+    #[test]
+    fn test_tick_queues_liveness_probe_once_very_stale() {
+        let tracker = HealthTracker::with_staleness_secs(3, 2, 60);
+        tracker.record_success(0);
+
+        let very_stale_now = HealthTracker::now_unix_secs() + 60 * PROBE_STALENESS_MULTIPLIER + 1;
+        let probes = tracker.tick(very_stale_now);
+
+        assert_eq!(probes, vec![0]);
+    }
+
+    #[test]
+    fn test_tick_caps_probes_per_interval() {
+        let tracker = HealthTracker::with_staleness_secs(5, 2, 60);
+        for id in 0..5 {
+            tracker.record_success(id);
+        }
+
+        let very_stale_now = HealthTracker::now_unix_secs() + 60 * PROBE_STALENESS_MULTIPLIER + 1;
+        let probes = tracker.tick(very_stale_now);
+
+        assert_eq!(probes.len(), MAX_PROBES_PER_INTERVAL);
+    }
+
+    #[test]
+    fn test_tick_skips_unavailable_and_rebuilding_accounts() {
+        let tracker = HealthTracker::with_staleness_secs(3, 2, 60);
+        tracker.record_success(0);
+        tracker.record_success(1);
+        tracker.set_rebuilding(1, 1000);
+        for _ in 0..3 {
+            tracker.record_failure(2, "Error");
+        }
+
+        let very_stale_now = HealthTracker::now_unix_secs() + 60 * PROBE_STALENESS_MULTIPLIER + 1;
+        let probes = tracker.tick(very_stale_now);
+
+        assert_eq!(probes, vec![0]);
+        assert_eq!(tracker.account_health(1).status, AccountStatus::Rebuilding);
+        assert_eq!(
+            tracker.account_health(2).status,
+            AccountStatus::Unavailable
+        );
+    }
+
+    struct AlwaysUpProber;
+    impl LivenessProber for AlwaysUpProber {
+        fn probe(&self, _account_id: u8) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_spawn_monitor_recovers_stale_account_via_probe() {
+        let tracker = Arc::new(HealthTracker::with_staleness_secs(1, 1, 0));
+        tracker.record_success(0);
+        // Force immediate staleness so the first tick queues a probe
+        {
+            let mut accounts = tracker.accounts.write();
+            accounts[0].last_success = Some(HealthTracker::now_unix_secs() - 1000);
+        }
+
+        let handle = tracker.spawn_monitor(Duration::from_millis(10), Arc::new(AlwaysUpProber));
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(tracker.account_health(0).status, AccountStatus::Healthy);
+        drop(handle);
+    }
+
+    #[test]
+    fn test_snapshot_v1_migrates_with_default_staleness() {
+        let v1 = HealthSnapshotV1 {
+            version: 1,
+            accounts: vec![AccountHealthV2 {
+                account_id: 0,
+                status: AccountStatus::Healthy,
+                last_success: None,
+                last_error: None,
+                failure_count: 0,
+                total_operations: 0,
+                failed_operations: 0,
+                next_retry_at: None,
+                ewma_error: 0.0,
+            }],
+            max_failures_before_unavailable: DEFAULT_MAX_FAILURES,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
+        };
+        let migrated: HealthSnapshot = v1.into();
+
+        assert_eq!(migrated.version, HEALTH_SNAPSHOT_VERSION);
+        assert_eq!(migrated.staleness_secs, DEFAULT_STALENESS_SECS);
+        assert!(migrated.accounts[0].rebuild.is_none());
+    }
+
+    #[test]
+    fn test_snapshot_v2_migrates_with_no_rebuild_progress() {
+        let v2 = HealthSnapshotV2 {
+            version: 2,
+            accounts: vec![AccountHealthV2 {
+                account_id: 0,
+                status: AccountStatus::Unavailable,
+                last_success: None,
+                last_error: Some("boom".to_string()),
+                failure_count: 3,
+                total_operations: 5,
+                failed_operations: 3,
+                next_retry_at: Some(100),
+                ewma_error: 0.6,
+            }],
+            max_failures_before_unavailable: DEFAULT_MAX_FAILURES,
+            ewma_alpha: DEFAULT_EWMA_ALPHA,
+            staleness_secs: 120,
+        };
+        let migrated: HealthSnapshot = v2.into();
+
+        assert_eq!(migrated.version, HEALTH_SNAPSHOT_VERSION);
+        assert_eq!(migrated.staleness_secs, 120);
+        assert!(migrated.accounts[0].rebuild.is_none());
+        assert_eq!(migrated.accounts[0].failure_count, 3);
+    }
+
+    #[test]
+    fn test_latency_histogram_tracks_min_max_mean() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_millis(10));
+        histogram.record(Duration::from_millis(20));
+        histogram.record(Duration::from_millis(30));
+
+        assert!((histogram.min_ms() - 10.0).abs() < 0.5);
+        assert!((histogram.max_ms() - 30.0).abs() < 0.5);
+        assert!((histogram.mean_ms() - 20.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_latency_histogram_percentile_is_monotonic() {
+        let mut histogram = LatencyHistogram::new();
+        for ms in [1, 5, 10, 50, 100, 500, 1000, 5000] {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        assert!(histogram.p50_ms() <= histogram.p90_ms());
+        assert!(histogram.p90_ms() <= histogram.p99_ms());
+    }
+
+    #[test]
+    fn test_latency_histogram_empty_is_zero() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.mean_ms(), 0.0);
+        assert_eq!(histogram.p99_ms(), 0.0);
+    }
+
+    #[test]
+    fn test_record_read_write_success_updates_separate_metrics() {
+        let tracker = HealthTracker::new(3, 2);
+
+        tracker.record_read_success(0, Duration::from_millis(5));
+        tracker.record_write_success(0, Duration::from_millis(15));
+
+        let metrics = tracker.account_metrics(0);
+        assert_eq!(metrics.read.successes, 1);
+        assert_eq!(metrics.write.successes, 1);
+        assert_eq!(metrics.read.failures, 0);
+        assert_eq!(metrics.write.failures, 0);
+    }
+
+    #[test]
+    fn test_record_read_write_failure_updates_separate_metrics() {
+        let tracker = HealthTracker::new(3, 2);
+
+        tracker.record_read_failure(0, "timeout", Duration::from_millis(5));
+        tracker.record_write_failure(0, "timeout", Duration::from_millis(5));
+
+        let metrics = tracker.account_metrics(0);
+        assert_eq!(metrics.read.failures, 1);
+        assert_eq!(metrics.write.failures, 1);
+        // Ordinary status bookkeeping still applies
+        assert_eq!(tracker.account_health(0).failed_operations, 2);
+    }
+
+    #[test]
+    fn test_account_metrics_unknown_account_is_default() {
+        let tracker = HealthTracker::new(3, 2);
+        let metrics = tracker.account_metrics(255);
+        assert_eq!(metrics.account_id, 255);
+        assert_eq!(metrics.read.successes, 0);
+        assert_eq!(metrics.write.successes, 0);
+    }
+
+    #[test]
+    fn test_export_metrics_contains_all_accounts() {
+        let tracker = HealthTracker::new(2, 1);
+        tracker.record_read_success(0, Duration::from_millis(10));
+        tracker.record_write_failure(1, "error", Duration::from_millis(20));
+
+        let exported = tracker.export_metrics();
+        assert!(exported.contains("account=\"0\",op=\"read\",result=\"success\"} 1"));
+        assert!(exported.contains("account=\"1\",op=\"write\",result=\"failure\"} 1"));
+    }
+
+    #[test]
+    fn test_rebuild_progress_fraction_and_eta() {
+        let tracker = HealthTracker::new(3, 2);
+        tracker.set_rebuilding(0, 1000);
+
+        // Force the last update into the past so advance() sees elapsed time
+        {
+            let mut accounts = tracker.accounts.write();
+            accounts[0].rebuild.as_mut().unwrap().last_update_at -= 10;
+        }
+        tracker.update_rebuild_progress(0, 400);
+
+        let rebuild = tracker.account_health(0).rebuild.unwrap();
+        assert_eq!(rebuild.bytes_done, 400);
+        assert!((rebuild.fraction() - 0.4).abs() < f32::EPSILON);
+        // 600 bytes remaining at ~40 bytes/sec should take a while
+        assert!(rebuild.eta_secs().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_update_rebuild_progress_is_noop_when_not_rebuilding() {
+        let tracker = HealthTracker::new(3, 2);
+        tracker.update_rebuild_progress(0, 500);
+        assert!(tracker.account_health(0).rebuild.is_none());
+    }
+
+    #[test]
+    fn test_rebuild_progress_caps_at_bytes_total() {
+        let tracker = HealthTracker::new(3, 2);
+        tracker.set_rebuilding(0, 1000);
+        tracker.update_rebuild_progress(0, 5000);
+
+        let rebuild = tracker.account_health(0).rebuild.unwrap();
+        assert_eq!(rebuild.bytes_done, 1000);
+        assert!((rebuild.fraction() - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_array_health_aggregates_weighted_rebuild_progress() {
+        let tracker = HealthTracker::new(3, 2);
+        tracker.set_rebuilding(0, 1000);
+        tracker.set_rebuilding(1, 3000);
+        tracker.update_rebuild_progress(0, 1000);
+        tracker.update_rebuild_progress(1, 0);
+
+        // 1000 of 4000 total bytes done, weighted by bytes_total
+        let health = tracker.array_health();
+        assert!((health.rebuild_progress.unwrap() - 0.25).abs() < 0.01);
+    }
 }
@@ -0,0 +1,233 @@
+//! Capacity- and zone-aware block placement planning
+//!
+//! [`StripeManager::plan_placement`](super::stripe::StripeManager::plan_placement)
+//! decides, per stripe, which of the accounts *currently available* gets
+//! each block - it treats every account as its own one-block fault domain
+//! and has no notion of accounts sharing a failure domain (the same
+//! phone-number region, the same owner) or of accounts having different
+//! storage capacity. This module computes the pool-wide *target* layout
+//! instead: how many of every stripe's N blocks an account should end up
+//! holding in steady state, so operators can see the intended
+//! distribution up front (`tgcryptfs raid add-account`, `tgcryptfs raid
+//! status`) and compare it against what placement is actually doing.
+//!
+//! The target is modeled as a min-cost max-flow over a small bipartite
+//! graph: a source feeds one node per zone (capacity = that zone's
+//! accounts' combined weight, capped so a single zone can never hold more
+//! than the array's parity count - losing the whole zone must still
+//! leave a stripe reconstructable), each zone node feeds its accounts
+//! (capacity = the account's weight), and every account drains into a
+//! sink with demand N. Since the graphs involved are tiny (at most 256
+//! accounts), we don't need a general-purpose max-flow solver: pushing
+//! the N units of flow one at a time, always along the cheapest
+//! still-open path, is exactly the divisor-based apportionment loop
+//! below - at each step it hands the next block to whichever eligible
+//! account is furthest behind its proportional share.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::config::{AccountConfig, PoolConfig};
+
+/// How many of a stripe's blocks a single account is targeted to hold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountTarget {
+    /// The account this target applies to
+    pub account_id: u8,
+    /// The account's failure-domain zone (see [`AccountConfig::zone_key`])
+    pub zone: String,
+    /// Capacity weight the target was computed from
+    pub weight: u32,
+    /// Target number of blocks, out of every stripe's N, this account
+    /// should hold in steady state
+    pub target_blocks: usize,
+}
+
+/// A pool-wide block placement target, computed by [`plan_layout`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutPlan {
+    /// Total blocks per stripe (N)
+    pub total_chunks: usize,
+    /// Most blocks a single zone may hold per stripe without breaking
+    /// the K-of-N reconstruction guarantee if that zone is lost
+    pub zone_cap: usize,
+    /// One entry per enabled account, sorted by account id
+    pub targets: Vec<AccountTarget>,
+}
+
+impl LayoutPlan {
+    /// Target block count for a specific account, if it was part of this plan
+    pub fn target_for(&self, account_id: u8) -> Option<usize> {
+        self.targets
+            .iter()
+            .find(|t| t.account_id == account_id)
+            .map(|t| t.target_blocks)
+    }
+
+    /// Total targeted blocks, grouped by zone
+    pub fn zone_totals(&self) -> HashMap<String, usize> {
+        let mut totals: HashMap<String, usize> = HashMap::new();
+        for target in &self.targets {
+            *totals.entry(target.zone.clone()).or_default() += target.target_blocks;
+        }
+        totals
+    }
+}
+
+/// Compute the target block distribution for every enabled account in
+/// `pool_config`, honoring per-account capacity weight and per-zone
+/// parity caps.
+///
+/// Returns [`Error::InvalidConfig`] if no assignment can place all N
+/// blocks of a stripe without some zone exceeding the array's parity
+/// count (e.g. too few zones, or their combined weight is too small).
+pub fn plan_layout(pool_config: &PoolConfig) -> Result<LayoutPlan> {
+    pool_config.erasure.validate()?;
+
+    let accounts: Vec<&AccountConfig> = pool_config.accounts.iter().filter(|a| a.enabled).collect();
+    if accounts.is_empty() {
+        return Err(Error::InvalidConfig(
+            "no enabled accounts to plan a layout for".to_string(),
+        ));
+    }
+
+    let total_chunks = pool_config.erasure.total_chunks;
+    let zone_cap = pool_config.erasure.parity_chunks();
+
+    let mut zone_weight: HashMap<String, u32> = HashMap::new();
+    for account in &accounts {
+        *zone_weight.entry(account.zone_key()).or_default() += account.capacity_weight;
+    }
+
+    // Capacity actually reachable per zone, bounded by the parity cap.
+    let zone_capacity: HashMap<String, usize> = zone_weight
+        .iter()
+        .map(|(zone, &weight)| (zone.clone(), (weight as usize).min(zone_cap)))
+        .collect();
+
+    let total_capacity: usize = zone_capacity.values().sum();
+    if total_capacity < total_chunks {
+        return Err(Error::InvalidConfig(format!(
+            "cannot place {} block(s) per stripe: accounts offer only {} block(s) of \
+             capacity once every zone is capped at {} block(s) (the array's parity count) - \
+             add more accounts, raise their capacity weight, or spread them across more zones",
+            total_chunks, total_capacity, zone_cap
+        )));
+    }
+
+    // Push one unit of flow at a time to the account that is currently
+    // furthest behind its proportional share (lowest (count + 1) / weight),
+    // skipping any account or zone that has already hit its cap. Ties are
+    // broken by account id so the plan is deterministic.
+    let mut zone_count: HashMap<String, usize> = zone_weight.keys().map(|z| (z.clone(), 0)).collect();
+    let mut account_count: HashMap<u8, usize> = accounts.iter().map(|a| (a.account_id, 0)).collect();
+
+    for _ in 0..total_chunks {
+        let pick = accounts
+            .iter()
+            .filter(|a| {
+                account_count[&a.account_id] < a.capacity_weight as usize
+                    && zone_count[&a.zone_key()] < zone_capacity[&a.zone_key()]
+            })
+            .min_by(|a, b| {
+                let score_a = (account_count[&a.account_id] + 1) as f64 / a.capacity_weight as f64;
+                let score_b = (account_count[&b.account_id] + 1) as f64 / b.capacity_weight as f64;
+                score_a
+                    .partial_cmp(&score_b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.account_id.cmp(&b.account_id))
+            })
+            .ok_or_else(|| {
+                Error::InvalidConfig(
+                    "no feasible placement: ran out of accounts under zone/capacity caps".to_string(),
+                )
+            })?;
+
+        *account_count.get_mut(&pick.account_id).unwrap() += 1;
+        *zone_count.get_mut(&pick.zone_key()).unwrap() += 1;
+    }
+
+    let mut targets: Vec<AccountTarget> = accounts
+        .iter()
+        .map(|a| AccountTarget {
+            account_id: a.account_id,
+            zone: a.zone_key(),
+            weight: a.capacity_weight,
+            target_blocks: account_count[&a.account_id],
+        })
+        .collect();
+    targets.sort_by_key(|t| t.account_id);
+
+    Ok(LayoutPlan {
+        total_chunks,
+        zone_cap,
+        targets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::config::ErasureConfig;
+    use std::path::PathBuf;
+
+    fn account(id: u8, zone: &str, weight: u32) -> AccountConfig {
+        AccountConfig::new(id, 1, "hash".to_string(), PathBuf::from(format!("session-{id}")))
+            .with_zone(zone.to_string())
+            .with_capacity_weight(weight)
+    }
+
+    fn pool(accounts: Vec<AccountConfig>, data_chunks: usize, total_chunks: usize) -> PoolConfig {
+        PoolConfig::new(accounts, ErasureConfig::new(data_chunks, total_chunks))
+    }
+
+    #[test]
+    fn test_plan_layout_caps_zone_at_parity_count() {
+        // K=2, N=4 => 2 parity blocks tolerated. Zone "a" has 3 equally
+        // weighted accounts; it must not receive more than 2 of the 4 blocks.
+        let accounts = vec![
+            account(0, "a", 1),
+            account(1, "a", 1),
+            account(2, "a", 1),
+            account(3, "b", 1),
+        ];
+        let plan = plan_layout(&pool(accounts, 2, 4)).unwrap();
+        let zone_totals = plan.zone_totals();
+        assert!(zone_totals["a"] <= 2);
+        assert_eq!(zone_totals.values().sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_plan_layout_balances_by_weight() {
+        // K=1, N=3, single zone wide enough to hold everything: a 2x
+        // weighted account should get roughly twice the blocks.
+        let accounts = vec![account(0, "x", 2), account(1, "y", 1)];
+        let plan = plan_layout(&pool(accounts, 1, 3)).unwrap();
+        assert_eq!(plan.target_for(0), Some(2));
+        assert_eq!(plan.target_for(1), Some(1));
+    }
+
+    #[test]
+    fn test_plan_layout_rejects_infeasible_zone_caps() {
+        // K=3, N=4 => only 1 parity block tolerated, but every account
+        // shares the same zone, so no more than 1 of the 4 blocks can
+        // legally be placed at all.
+        let accounts = vec![account(0, "solo", 1), account(1, "solo", 1), account(2, "solo", 1), account(3, "solo", 1)];
+        let err = plan_layout(&pool(accounts, 3, 4)).unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_plan_layout_defaults_unzoned_accounts_to_singleton_zones() {
+        let accounts = vec![
+            AccountConfig::new(0, 1, "h".to_string(), PathBuf::from("s0")),
+            AccountConfig::new(1, 1, "h".to_string(), PathBuf::from("s1")),
+            AccountConfig::new(2, 1, "h".to_string(), PathBuf::from("s2")),
+        ];
+        let plan = plan_layout(&pool(accounts, 2, 3)).unwrap();
+        assert_eq!(plan.zone_totals().len(), 3);
+    }
+}
@@ -0,0 +1,255 @@
+//! Exportable/importable pool manifest for disaster recovery
+//!
+//! If the local metadata describing where every stripe lives is lost, the
+//! data already sitting on Telegram becomes unrecoverable even though
+//! every message id is still intact - nothing ties them back together
+//! without the stripe/block layout. A [`Manifest`] is that layout,
+//! snapshotted: the erasure parameters, the account layout (from
+//! [`plan_layout`]), and every [`StripeInfo`] it was asked to cover,
+//! serialized and split into fixed-size compressed chunks the same way
+//! [`crate::snapshot`] content-addresses its object bodies. Each chunk
+//! carries its own BLAKE3 hash, and a root hash over those hashes lets
+//! [`AccountPool::import_manifest`] detect a truncated or tampered
+//! manifest before trusting a single byte of it.
+//!
+//! Because the manifest is itself just data, [`AccountPool::upload_manifest`]
+//! can erasure-code and upload it like any other stripe, so a pool can be
+//! bootstrapped from nothing but account credentials and the handful of
+//! message ids the manifest stripe lives at.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::{compress, decompress, CompressionAlgo, StripeInfo};
+use crate::error::{Error, Result};
+
+use super::layout::{plan_layout, LayoutPlan};
+use super::pool::AccountPool;
+
+/// Plaintext bytes are split into chunks of this size (pre-compression)
+/// before each is compressed and hashed independently - small enough that
+/// a single corrupt chunk only costs re-fetching a small slice of the
+/// manifest, not the whole thing.
+pub const MANIFEST_CHUNK_SIZE: usize = 256 * 1024;
+
+/// zstd level used for manifest chunks - the manifest is metadata, not a
+/// hot path, so we favor ratio over speed.
+const MANIFEST_COMPRESSION_LEVEL: i32 = 9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestBody {
+    layout: LayoutPlan,
+    stripes: Vec<StripeInfo>,
+}
+
+/// One content-addressed, independently verifiable slice of a [`Manifest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestChunk {
+    /// BLAKE3 hex digest of `data`.
+    pub hash: String,
+    /// Compression applied to this chunk's plaintext slice.
+    pub compression: CompressionAlgo,
+    /// Compressed (or passed-through) bytes, plus the checksum footer
+    /// `compress`/`decompress` already append.
+    pub data: Vec<u8>,
+}
+
+/// A self-verifying, exportable snapshot of everything needed to
+/// reconstruct an [`AccountPool`]'s stripe index from scratch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The manifest body, split into fixed-size compressed chunks.
+    pub chunks: Vec<ManifestChunk>,
+    /// BLAKE3 hex digest over the concatenation of every chunk's `hash`,
+    /// checked before any chunk is trusted.
+    pub root_hash: String,
+}
+
+fn root_hash(chunks: &[ManifestChunk]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for chunk in chunks {
+        hasher.update(chunk.hash.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Verify every chunk's hash and the manifest's root hash, then
+/// decompress and reassemble the plaintext body.
+fn verify_and_reassemble(manifest: &Manifest) -> Result<Vec<u8>> {
+    let expected_root = root_hash(&manifest.chunks);
+    if expected_root != manifest.root_hash {
+        return Err(Error::ChunkVerificationFailed {
+            expected: manifest.root_hash.clone(),
+            got: expected_root,
+        });
+    }
+
+    let mut plaintext = Vec::new();
+    for chunk in &manifest.chunks {
+        let actual_hash = blake3::hash(&chunk.data).to_hex().to_string();
+        if actual_hash != chunk.hash {
+            return Err(Error::ChunkVerificationFailed {
+                expected: chunk.hash.clone(),
+                got: actual_hash,
+            });
+        }
+        plaintext.extend_from_slice(&decompress(&chunk.data, chunk.compression)?);
+    }
+    Ok(plaintext)
+}
+
+impl AccountPool {
+    /// Build a [`Manifest`] covering `stripes` and this pool's current
+    /// account layout. Pure and synchronous - nothing is uploaded here,
+    /// see [`upload_manifest`](Self::upload_manifest) for that.
+    pub fn export_manifest(&self, stripes: &[StripeInfo]) -> Result<Manifest> {
+        let layout = plan_layout(&self.config)?;
+        let body = ManifestBody {
+            layout,
+            stripes: stripes.to_vec(),
+        };
+        let plaintext = bincode::serialize(&body).map_err(|e| Error::Serialization(e.to_string()))?;
+
+        let chunks: Result<Vec<ManifestChunk>> = plaintext
+            .chunks(MANIFEST_CHUNK_SIZE)
+            .map(|slice| {
+                let (data, compression) = compress(slice, MANIFEST_COMPRESSION_LEVEL)?;
+                let hash = blake3::hash(&data).to_hex().to_string();
+                Ok(ManifestChunk { hash, compression, data })
+            })
+            .collect();
+        let chunks = chunks?;
+
+        let root_hash = root_hash(&chunks);
+        Ok(Manifest { chunks, root_hash })
+    }
+
+    /// Verify `manifest` end to end and rebuild the pool it describes.
+    /// `config` supplies the account credentials - the manifest itself
+    /// never carries secrets, only the layout and stripe index - so a
+    /// cold-start rebuild only needs account credentials plus a manifest
+    /// fetched (or downloaded via [`download_manifest`](Self::download_manifest))
+    /// from wherever it was kept.
+    pub async fn import_manifest(config: super::config::PoolConfig, manifest: &Manifest) -> Result<(Self, Vec<StripeInfo>)> {
+        let plaintext = verify_and_reassemble(manifest)?;
+        let body: ManifestBody =
+            bincode::deserialize(&plaintext).map_err(|e| Error::Deserialization(e.to_string()))?;
+
+        let pool = Self::new(config)?;
+        Ok((pool, body.stripes))
+    }
+
+    /// Erasure-code `manifest` itself and upload it as a dedicated
+    /// "manifest stripe" across this pool's accounts, so the pool can be
+    /// bootstrapped from nothing but account credentials and this
+    /// stripe's block locations.
+    pub async fn upload_manifest(&self, manifest: &Manifest) -> Result<StripeInfo> {
+        let plaintext = bincode::serialize(manifest).map_err(|e| Error::Serialization(e.to_string()))?;
+
+        let data_count = self.data_chunks() as u8;
+        let total_count = self.total_chunks() as u8;
+        let encoder = super::erasure::Encoder::new(data_count as usize, total_count as usize)?;
+        let shards = encoder.encode(&plaintext)?;
+        let block_size = shards.first().map(|s| s.len() as u64).unwrap_or(0);
+
+        let accounts = self.healthy_accounts();
+        if accounts.len() < total_count as usize {
+            return Err(Error::ErasureFailed {
+                available: accounts.len(),
+                required: total_count as usize,
+            });
+        }
+
+        let mut stripe_info = StripeInfo::new(data_count, total_count - data_count, block_size);
+        for (block_index, shard) in shards.iter().enumerate() {
+            let account_id = accounts[block_index % accounts.len()];
+            let backend = self
+                .get_backend(account_id)
+                .ok_or_else(|| Error::AccountUnavailable(account_id, "Backend not found".to_string()))?;
+
+            let block_chunk_id = format!("manifest_{}_{}", &manifest.root_hash[..16], block_index);
+            let message_id = backend.upload_chunk(&block_chunk_id, shard).await?;
+
+            stripe_info.blocks.push(crate::chunk::BlockLocation {
+                account_id,
+                message_id: Some(message_id),
+                block_index: block_index as u8,
+                uploaded_at: Some(super::pool::now_unix()),
+                content_hash: self.config().checksum.enabled.then(|| self.config().checksum.digest(shard)),
+            });
+        }
+
+        Ok(stripe_info)
+    }
+
+    /// Download and reconstruct a [`Manifest`] previously uploaded with
+    /// [`upload_manifest`](Self::upload_manifest).
+    pub async fn download_manifest(&self, stripe_info: &StripeInfo) -> Result<Manifest> {
+        let blocks = self.download_blocks(stripe_info).await?;
+
+        let total = stripe_info.total_blocks() as usize;
+        let mut shards: Vec<Option<Vec<u8>>> = vec![None; total];
+        for (block_index, data) in blocks {
+            shards[block_index as usize] = Some(data);
+        }
+
+        let encoder = super::erasure::Encoder::new(stripe_info.data_count as usize, total)?;
+        let plaintext = encoder.decode(&mut shards)?;
+
+        bincode::deserialize(&plaintext).map_err(|e| Error::Deserialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::config::{AccountConfig, ErasureConfig, PoolConfig};
+    use std::path::PathBuf;
+
+    fn make_test_pool() -> AccountPool {
+        let accounts: Vec<AccountConfig> = (0..5)
+            .map(|i| {
+                AccountConfig::new(
+                    i as u8,
+                    12345,
+                    "test_hash".to_string(),
+                    PathBuf::from(format!("/tmp/test_session_{}", i)),
+                )
+                .with_phone(format!("+1234567890{}", i))
+            })
+            .collect();
+        let erasure = ErasureConfig::new(3, 5);
+        AccountPool::new(PoolConfig::new(accounts, erasure)).unwrap()
+    }
+
+    #[test]
+    fn test_export_manifest_round_trips_via_root_hash() {
+        let pool = make_test_pool();
+        let stripes = vec![StripeInfo::new(3, 2, 1024)];
+
+        let manifest = pool.export_manifest(&stripes).unwrap();
+        assert!(!manifest.chunks.is_empty());
+        assert_eq!(root_hash(&manifest.chunks), manifest.root_hash);
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_chunk() {
+        let pool = make_test_pool();
+        let stripes = vec![StripeInfo::new(3, 2, 1024)];
+
+        let mut manifest = pool.export_manifest(&stripes).unwrap();
+        manifest.chunks[0].data[0] ^= 0xFF;
+
+        assert!(verify_and_reassemble(&manifest).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_root_hash() {
+        let pool = make_test_pool();
+        let stripes = vec![StripeInfo::new(3, 2, 1024)];
+
+        let mut manifest = pool.export_manifest(&stripes).unwrap();
+        manifest.root_hash = "not-the-real-root".to_string();
+
+        assert!(verify_and_reassemble(&manifest).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Single-account -> erasure-coded migration driver
+//!
+//! `rebuild.rs` repairs blocks of files that are already erasure-coded;
+//! this module does the one-time conversion that gets a file *into* that
+//! shape in the first place. For every inode still holding a plain
+//! single-account [`ChunkManifest`], each of its chunks is downloaded from
+//! the source account, Reed-Solomon encoded into N blocks, and those
+//! blocks uploaded across the pool; the resulting [`ErasureChunkRef`]s are
+//! assembled into an [`ErasureChunkManifest`] and filed the same way
+//! `rebuild::save_manifest` files one for `rebuild_account` to find later.
+//!
+//! Each chunk is its own [`Journal`] activity, keyed `"<ino>:<chunk
+//! index>"` and marked `Done` only once its manifest has been durably
+//! saved - never on "blocks uploaded" alone, so a crash between those two
+//! steps safely redoes just that chunk on the next `--resume` instead of
+//! leaving it half-migrated or silently dropped. When `delete_old` is
+//! set, an inode's original single-account messages are only deleted once
+//! every one of its chunks has actually made it into the new manifest -
+//! never on a partial migration, so a retry always has the original data
+//! to fall back on.
+//!
+//! Before uploading a shard, its BLAKE3 content hash is checked against
+//! [`MetadataStore::get_erasure_block_ref`] for the destination account;
+//! a hit (sparse-file zero blocks and other repeated content are common)
+//! reuses the existing message and bumps its refcount instead of storing
+//! a duplicate copy. The refcount is released the same way
+//! [`MetadataStore::decrement_chunk_ref`] already is for single-account
+//! chunks, so a block's message is only deleted once nothing references
+//! it anymore.
+
+use std::sync::Arc;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use crate::chunk::{BlockLocation, ChunkRef, ErasureChunkManifest, ErasureChunkRef, StripeInfo};
+use crate::error::{Error, Result};
+use crate::metadata::MetadataStore;
+use crate::migration::Journal;
+use crate::telegram::TelegramBackend;
+
+use super::erasure::Encoder;
+use super::pool::AccountPool;
+use super::rebuild::save_manifest;
+use super::stripe::StripeManager;
+
+/// Migration id this driver's [`Journal`] activities are filed under.
+pub const MIGRATION_ID: &str = "raid-migrate-to-erasure";
+
+/// How many chunks this driver encodes/uploads concurrently.
+const MAX_CONCURRENT_CHUNKS: usize = 4;
+
+/// Progress counters for one [`migrate_to_erasure`] run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrateReport {
+    /// Inodes examined that had at least one non-inline chunk to migrate.
+    pub inodes_migrated: u64,
+    /// Chunks newly encoded and uploaded this run.
+    pub chunks_migrated: u64,
+    /// Chunks an earlier, interrupted run had already finished - skipped.
+    pub chunks_already_done: u64,
+    /// Chunks that failed to migrate this pass (left `Pending`, retried
+    /// on the next run).
+    pub failures: u64,
+}
+
+/// Convert every single-account chunk this mount has written into
+/// erasure-coded storage, resuming from whatever an earlier interrupted
+/// run already journaled `Done`. See the module docs for the per-chunk
+/// resumability contract.
+pub async fn migrate_to_erasure(
+    pool: &AccountPool,
+    source: &TelegramBackend,
+    metadata: &MetadataStore,
+    encoder: &Encoder,
+    data_chunks: u8,
+    total_chunks: u8,
+    delete_old: bool,
+) -> Result<MigrateReport> {
+    let journal = Journal::new(metadata, MIGRATION_ID);
+    let stripe_manager = StripeManager::new(data_chunks as usize, total_chunks as usize);
+
+    let mut report = MigrateReport::default();
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+
+    for inode in metadata.list_all_inodes()? {
+        let Some(manifest) = inode.manifest.clone() else { continue };
+        if manifest.chunks.iter().all(|c| c.is_inline()) {
+            continue;
+        }
+
+        let available = pool.healthy_accounts();
+        let assignments = stripe_manager
+            .plan_placement(&available)
+            .map_err(|e| Error::Internal(format!("cannot place blocks for inode {}: {e}", inode.ino)))?;
+
+        let mut tasks = FuturesUnordered::new();
+        for (chunk_index, chunk) in manifest.chunks.iter().enumerate() {
+            if chunk.is_inline() {
+                continue;
+            }
+
+            let activity_id = format!("{}:{chunk_index}", inode.ino);
+            if journal.is_done(&activity_id)? {
+                report.chunks_already_done += 1;
+                continue;
+            }
+            journal.mark_pending(&activity_id)?;
+
+            let chunk = chunk.clone();
+            let assignments = assignments.clone();
+            let semaphore = Arc::clone(&semaphore);
+            tasks.push(async move {
+                let _permit = semaphore.acquire().await.expect("semaphore is never closed");
+                let result = migrate_one_chunk(
+                    pool,
+                    source,
+                    metadata,
+                    encoder,
+                    &chunk,
+                    &assignments,
+                    data_chunks,
+                    total_chunks - data_chunks,
+                )
+                .await;
+                (activity_id, chunk_index, chunk, result)
+            });
+        }
+
+        let mut erasure_chunks: Vec<(usize, ErasureChunkRef)> = Vec::new();
+        while let Some((activity_id, chunk_index, chunk, result)) = tasks.next().await {
+            match result {
+                Ok(stripe) => {
+                    erasure_chunks.push((
+                        chunk_index,
+                        ErasureChunkRef {
+                            id: chunk.id.clone(),
+                            offset: chunk.offset,
+                            original_size: chunk.original_size,
+                            compression: chunk.compression,
+                            stripe,
+                            version: 1,
+                        },
+                    ));
+                    journal.mark_done(&activity_id)?;
+                    report.chunks_migrated += 1;
+                }
+                Err(e) => {
+                    warn!("Could not migrate inode {} chunk {}: {}", inode.ino, chunk_index, e);
+                    report.failures += 1;
+                }
+            }
+        }
+
+        if erasure_chunks.is_empty() {
+            continue;
+        }
+        erasure_chunks.sort_by_key(|(idx, _)| *idx);
+
+        let mut erasure_manifest = ErasureChunkManifest::new(manifest.version, data_chunks, total_chunks);
+        erasure_manifest.total_size = manifest.total_size;
+        erasure_manifest.file_hash = manifest.file_hash.clone();
+        erasure_manifest.chunks = erasure_chunks.into_iter().map(|(_, c)| c).collect();
+
+        save_manifest(metadata, &inode.ino.to_string(), &erasure_manifest)?;
+        report.inodes_migrated += 1;
+        info!("Migrated inode {} to erasure coding ({} chunk(s))", inode.ino, erasure_manifest.chunks.len());
+
+        // Only purge the old single-account messages once every one of
+        // this inode's chunks is journaled `Done` - a partial migration
+        // (some chunks still failing) must leave the original data alone
+        // so a retry has something to fall back on.
+        if delete_old && erasure_manifest.chunks.len() == manifest.chunks.iter().filter(|c| !c.is_inline()).count() {
+            for chunk in manifest.chunks.iter().filter(|c| !c.is_inline()) {
+                if let Some(message_id) = chunk.message_id() {
+                    if let Err(e) = source.delete_message(message_id).await {
+                        warn!("Could not delete old message {} for inode {}: {}", message_id, inode.ino, e);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Download one single-account chunk, encode it into N shards, and upload
+/// each shard to the account `assignments` picked for its block index -
+/// deduplicating against any block already stored for that account under
+/// the same content hash rather than uploading it again.
+async fn migrate_one_chunk(
+    pool: &AccountPool,
+    source: &TelegramBackend,
+    metadata: &MetadataStore,
+    encoder: &Encoder,
+    chunk: &ChunkRef,
+    assignments: &[u8],
+    data_chunks: u8,
+    parity_chunks: u8,
+) -> Result<StripeInfo> {
+    let message_id = chunk
+        .message_id()
+        .ok_or_else(|| Error::Internal(format!("chunk {} has no source message id", chunk.id)))?;
+    let data = source.download_chunk(message_id).await?;
+    let shards = encoder.encode(&data)?;
+
+    let mut stripe = StripeInfo::new(data_chunks, parity_chunks, shards.first().map(|s| s.len() as u64).unwrap_or(0));
+
+    for (block_index, (shard, &account_id)) in shards.iter().zip(assignments.iter()).enumerate() {
+        let hash = pool.config().checksum.digest(shard);
+
+        // Dedup: a block with this content already stored on this account
+        // can be pointed at directly, saving both bandwidth and the
+        // account's message quota.
+        let message_id = if let Some(existing) = metadata.get_erasure_block_ref(account_id, &hash)? {
+            metadata.save_erasure_block_ref(account_id, &hash, existing)?;
+            existing
+        } else {
+            let backend = pool
+                .get_backend(account_id)
+                .ok_or_else(|| Error::AccountUnavailable(account_id, "Backend not found".to_string()))?;
+            let block_chunk_id = format!("{}_{block_index}", chunk.id);
+            let uploaded = backend.upload_chunk(&block_chunk_id, shard).await?;
+            metadata.save_erasure_block_ref(account_id, &hash, uploaded)?;
+            uploaded
+        };
+
+        stripe.blocks.push(BlockLocation {
+            account_id,
+            message_id: Some(message_id),
+            block_index: block_index as u8,
+            uploaded_at: Some(now_unix()),
+            content_hash: pool.config().checksum.enabled.then(|| hash.clone()),
+        });
+    }
+
+    Ok(stripe)
+}
+
+fn now_unix() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
@@ -4,11 +4,34 @@
 //! Presets: RAID5 (N-1 of N), RAID6 (N-2 of N), or custom K/N.
 
 pub mod config;
+pub mod dedup;
 pub mod erasure;
+pub mod layout;
+pub mod manifest;
 pub mod stripe;
+pub mod stripe_index;
 pub mod health;
+pub mod migrate;
+pub mod pool;
+pub mod rebuild;
+pub mod scrub;
 
-pub use config::{ErasureConfig, ErasurePreset, AccountConfig, PoolConfig};
-pub use erasure::Encoder;
-pub use stripe::{Stripe, StripeManager};
-pub use health::{AccountHealth, AccountStatus, ArrayStatus};
+pub use config::{
+    AccountConfig, ChecksumAlgorithm, ChecksumConfig, ErasureConfig, ErasurePreset, PoolConfig,
+    RebuildChunkAssignment, RebuildPlan, ReshardPlan,
+};
+pub use dedup::{ChunkIndex, StoredChunk};
+pub use erasure::{decode_tagged, Encoder, GaloisField, StripeManifest, StripedEncoder};
+pub use layout::{plan_layout, AccountTarget, LayoutPlan};
+pub use manifest::{Manifest, ManifestChunk, MANIFEST_CHUNK_SIZE};
+pub use stripe::{
+    AccountStats, AssignmentStrategy, CompleteChunkError, PlacementError, Stripe, StripeManager,
+};
+pub use stripe_index::{AccountMessageScanner, ScannedChunk, StripeIndex};
+pub use health::{AccountHealth, AccountStatus, ArrayHealth, ArrayStatus};
+pub use migrate::{migrate_to_erasure, MigrateReport};
+pub use pool::{resolve_latest, AccountPool, RepairReport};
+pub use rebuild::{rebuild_account, RebuildReport};
+pub use scrub::{
+    BlockAvailabilityOracle, HoldsBlockQuery, RebuiltBlock, ScrubState, ScrubWorker,
+};
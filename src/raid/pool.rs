@@ -2,10 +2,13 @@
 //!
 //! Provides unified interface for uploading/downloading across multiple accounts.
 
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use dashmap::DashMap;
 use futures::future::join_all;
+use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
 
 use crate::chunk::StripeInfo;
@@ -14,9 +17,85 @@ use crate::error::{Error, Result};
 use crate::telegram::TelegramBackend;
 
 use super::config::{AccountConfig, PoolConfig};
+use super::erasure::Encoder;
 use super::health::{AccountStatus, ArrayHealth, ArrayStatus, HealthTracker};
 use super::stripe::Stripe;
 
+pub(super) fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Outcome of one [`AccountPool::repair_stripe`] pass.
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// Block indices that were missing or unhealthy and got
+    /// re-materialized onto a spare account.
+    pub repaired: Vec<u8>,
+    /// Block indices that are still bad after this pass - no healthy
+    /// spare account was available, or the re-upload itself failed.
+    pub unrepaired: Vec<u8>,
+}
+
+/// Per-block outcome of one [`AccountPool::scrub_stripe`] verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubVerdict {
+    /// Downloaded and its hash matched `BlockLocation::content_hash`.
+    Ok,
+    /// Downloaded but its hash did not match - the account returned
+    /// truncated or altered bytes.
+    Corrupt,
+    /// Has no `message_id`, or the download itself failed.
+    Missing,
+}
+
+/// Outcome of one [`AccountPool::scrub_stripe`] pass.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubResult {
+    /// Verdict for every block that was checked, in block-index order.
+    pub verdicts: Vec<(u8, ScrubVerdict)>,
+    /// Set once a repair was attempted because at least one block came
+    /// back `Corrupt` or `Missing`.
+    pub repair: Option<RepairReport>,
+}
+
+impl ScrubResult {
+    /// Whether every block verified clean - no repair was needed.
+    pub fn is_clean(&self) -> bool {
+        self.verdicts.iter().all(|(_, v)| *v == ScrubVerdict::Ok)
+    }
+}
+
+/// Aggregate counts from one [`AccountPool::scrub_all`] sweep.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScrubSummary {
+    /// Stripes where every block verified clean.
+    pub clean: usize,
+    /// Stripes with at least one bad block that repair fully fixed.
+    pub repaired: usize,
+    /// Stripes with at least one bad block repair could not fix (no
+    /// spare account, or too few healthy blocks to reconstruct).
+    pub unrecoverable: usize,
+}
+
+/// Pick the newest of several `StripeInfo`s committed for the same chunk,
+/// e.g. when a crash retry or two racing writers both called
+/// `AccountPool::upload_stripe`. Ties (including the all-zero
+/// `write_version` of pre-versioning manifests) keep whichever comes
+/// first in `infos`.
+///
+/// # Panics
+/// Panics if `infos` is empty - callers always have at least one manifest
+/// on hand, since that's what they're trying to disambiguate.
+pub fn resolve_latest(infos: &[StripeInfo]) -> &StripeInfo {
+    infos
+        .iter()
+        .max_by_key(|s| s.write_version)
+        .expect("resolve_latest: infos must not be empty")
+}
+
 /// Pool of Telegram account backends
 pub struct AccountPool {
     /// Individual backends (one per account)
@@ -25,6 +104,24 @@ pub struct AccountPool {
     health: Arc<HealthTracker>,
     /// Configuration
     config: PoolConfig,
+    /// Monotonic counter stamped onto every `StripeInfo` committed by
+    /// `upload_stripe`, so a re-upload of the same `chunk_id` (crash
+    /// retry, or two racing writers) can be told apart from whatever
+    /// commit preceded it - see [`resolve_latest`].
+    write_version: AtomicU64,
+    /// Persistent record of which account actually holds each
+    /// `(chunk_id, block_index)` - see [`assign_blocks`](Self::assign_blocks).
+    /// Decouples the erasure geometry (block index) from a fixed account
+    /// numbering, so placement can be rebalanced as accounts are added,
+    /// removed, or degrade.
+    placement: DashMap<(String, u8), u8>,
+    /// Approximate number of blocks currently placed on each account,
+    /// used by [`assign_blocks`](Self::assign_blocks) to prefer the
+    /// least-loaded healthy accounts.
+    account_load: DashMap<u8, u64>,
+    /// Round-robin cursor used to break load ties fairly across
+    /// successive `assign_blocks` calls.
+    placement_cursor: AtomicUsize,
 }
 
 impl AccountPool {
@@ -68,6 +165,10 @@ impl AccountPool {
             backends,
             health,
             config,
+            write_version: AtomicU64::new(0),
+            placement: DashMap::new(),
+            account_load: DashMap::new(),
+            placement_cursor: AtomicUsize::new(0),
         })
     }
 
@@ -82,6 +183,8 @@ impl AccountPool {
             max_concurrent_downloads: 5,
             retry_attempts: 3,
             retry_base_delay_ms: 1000,
+            targets: Vec::new(),
+            default_target: None,
         }
     }
 
@@ -197,15 +300,25 @@ impl AccountPool {
             );
         }
 
-        // Create upload futures for each block
+        // Create upload futures for each block. Target accounts come from
+        // `assign_blocks` rather than `all_blocks()`'s own account_id, so
+        // placement can route around a degraded account and spread load
+        // instead of pinning block i to a fixed account i.
         let chunk_id = stripe.chunk_id.clone();
+        let assigned_accounts = self.assign_blocks(&chunk_id, block_count)?;
         let upload_futures: Vec<_> = all_blocks
             .into_iter()
-            .map(|(block_idx, account_id, data)| {
+            .map(|(block_idx, _original_account_id, data)| {
+                let account_id = assigned_accounts[block_idx as usize];
                 let backend = self.get_backend(account_id);
                 let health = Arc::clone(&self.health);
                 let block_chunk_id = format!("{}_{}", chunk_id, block_idx);
                 let data_owned = data.to_vec();
+                let content_hash = self
+                    .config
+                    .checksum
+                    .enabled
+                    .then(|| self.config.checksum.digest(&data_owned));
 
                 async move {
                     // Check if this account is unavailable
@@ -236,17 +349,18 @@ impl AccountPool {
                         }
                     };
 
+                    let started = Instant::now();
                     match backend.upload_chunk(&block_chunk_id, &data_owned).await {
                         Ok(msg_id) => {
-                            health.record_success(account_id);
+                            health.record_write_success(account_id, started.elapsed());
                             debug!(
                                 "Block {} uploaded to account {} as message {}",
                                 block_idx, account_id, msg_id
                             );
-                            Ok((block_idx, account_id, msg_id))
+                            Ok((block_idx, account_id, msg_id, content_hash))
                         }
                         Err(e) => {
-                            health.record_failure(account_id, &e.to_string());
+                            health.record_write_failure(account_id, &e.to_string(), started.elapsed());
                             error!(
                                 "Failed to upload block {} to account {}: {}",
                                 block_idx, account_id, e
@@ -271,7 +385,7 @@ impl AccountPool {
 
         for result in results {
             match result {
-                Ok((block_idx, account_id, msg_id)) => {
+                Ok((block_idx, account_id, msg_id, content_hash)) => {
                     let now = SystemTime::now()
                         .duration_since(UNIX_EPOCH)
                         .unwrap_or_default()
@@ -282,6 +396,7 @@ impl AccountPool {
                         message_id: Some(msg_id),
                         block_index: block_idx,
                         uploaded_at: Some(now),
+                        content_hash,
                     });
                     success_count += 1;
                 }
@@ -292,6 +407,7 @@ impl AccountPool {
                         message_id: None,
                         block_index: block_idx,
                         uploaded_at: None,
+                        content_hash: None,
                     });
                     failures.push((block_idx, account_id, e));
                 }
@@ -303,6 +419,8 @@ impl AccountPool {
             .blocks
             .sort_by_key(|b| (b.block_index, b.account_id));
 
+        stripe_info.write_version = self.write_version.fetch_add(1, Ordering::SeqCst) + 1;
+
         // Check if we have enough successful uploads
         let required = self.config.erasure.data_chunks;
         if success_count < required {
@@ -334,9 +452,92 @@ impl AccountPool {
         Ok(stripe_info)
     }
 
-    /// Download blocks for a stripe from available accounts
+    /// Pick a target account for each of a stripe's `block_count` blocks
+    /// from [`healthy_accounts`](Self::healthy_accounts), preferring the
+    /// least-loaded accounts and guaranteeing every block of the stripe
+    /// lands on a distinct account (required so losing one account never
+    /// costs more than one block of the stripe). Ties in load are broken
+    /// round-robin via `placement_cursor` so repeated calls spread evenly
+    /// rather than always picking the same least-loaded account first.
+    ///
+    /// Records the chosen placement into `self.placement`, keyed by
+    /// `(chunk_id, block_index)`, so it survives to inform later
+    /// `placement_for` lookups even after `healthy_accounts()` changes.
+    fn assign_blocks(&self, chunk_id: &str, block_count: usize) -> Result<Vec<u8>> {
+        let mut candidates = self.healthy_accounts();
+        if candidates.len() < block_count {
+            return Err(Error::ErasureFailed {
+                available: candidates.len(),
+                required: block_count,
+            });
+        }
+
+        let cursor = self.placement_cursor.fetch_add(1, Ordering::Relaxed);
+        let rotate_by = cursor % candidates.len();
+        candidates.rotate_left(rotate_by);
+        candidates.sort_by_key(|id| self.account_load.get(id).map(|load| *load).unwrap_or(0));
+
+        let assigned: Vec<u8> = candidates.into_iter().take(block_count).collect();
+        for (block_index, &account_id) in assigned.iter().enumerate() {
+            *self.account_load.entry(account_id).or_insert(0) += 1;
+            self.placement
+                .insert((chunk_id.to_string(), block_index as u8), account_id);
+        }
+        Ok(assigned)
+    }
+
+    /// The account currently recorded as holding `(chunk_id, block_index)`,
+    /// if [`assign_blocks`](Self::assign_blocks) has ever placed it. This
+    /// is the pool's own bookkeeping, kept independent of whatever
+    /// `BlockLocation::account_id` a particular `StripeInfo` snapshot
+    /// records, so placement survives rebalancing even across stripes
+    /// that haven't been re-uploaded.
+    pub fn placement_for(&self, chunk_id: &str, block_index: u8) -> Option<u8> {
+        self.placement
+            .get(&(chunk_id.to_string(), block_index))
+            .map(|entry| *entry)
+    }
+
+    /// Download blocks for a stripe from available accounts, triggering
+    /// [`repair_stripe`](Self::repair_stripe) as a best-effort side
+    /// effect when [`PoolConfig::repair_on_read`] is enabled and this
+    /// pass came up short of the full block set. The repair runs against
+    /// a clone of `stripe_info` - its new `BlockLocation`s aren't
+    /// threaded back through this call's return value, so a caller that
+    /// wants them should call `repair_stripe` directly on the manifest
+    /// it owns instead of relying on this opt-in side effect alone.
     /// Returns Vec of (block_index, data) for successfully downloaded blocks
     pub async fn download_blocks(&self, stripe_info: &StripeInfo) -> Result<Vec<(u8, Vec<u8>)>> {
+        let blocks = self.download_blocks_raw(stripe_info).await?;
+
+        if self.config.repair_on_read && blocks.len() < stripe_info.total_blocks() as usize {
+            let mut repair_copy = stripe_info.clone();
+            match self.repair_stripe(&mut repair_copy).await {
+                Ok(report) if !report.repaired.is_empty() => {
+                    info!(
+                        "repair-on-read: repaired {} block(s) of a degraded stripe",
+                        report.repaired.len()
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => warn!("repair-on-read attempt failed: {}", e),
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    /// The actual download logic behind [`download_blocks`](Self::download_blocks),
+    /// without the repair-on-read side effect - used directly by
+    /// [`repair_stripe`](Self::repair_stripe) so a repair's own download
+    /// never recursively triggers another repair attempt.
+    ///
+    /// When [`PoolConfig::checksum`] is enabled, every block is re-hashed
+    /// against its recorded `content_hash` as it comes back; a mismatch
+    /// is dropped from the result exactly like a download failure, so it
+    /// counts toward `required` the same way a missing block would
+    /// rather than being handed to the decoder as if it were good data.
+    async fn download_blocks_raw(&self, stripe_info: &StripeInfo) -> Result<Vec<(u8, Vec<u8>)>> {
         debug!(
             "Downloading {} blocks from stripe",
             stripe_info.blocks.len()
@@ -365,6 +566,8 @@ impl AccountPool {
                 let block_idx = block.block_index;
                 let account_id = block.account_id;
                 let message_id = block.message_id.unwrap(); // Safe: filtered above
+                let expected_hash = block.content_hash.clone();
+                let checksum = self.config.checksum;
 
                 async move {
                     // Check if this account is healthy
@@ -389,9 +592,35 @@ impl AccountPool {
                         }
                     };
 
+                    let started = Instant::now();
                     match backend.download_chunk(message_id).await {
                         Ok(data) => {
-                            health.record_success(account_id);
+                            // A chunk that fails its recorded checksum is no
+                            // more trustworthy than one that never arrived -
+                            // treat it as a missing shard so the K-of-N
+                            // decoder reconstructs around it instead of
+                            // being handed silently corrupted bytes.
+                            let verified = expected_hash
+                                .as_ref()
+                                .map(|h| checksum.verify(&data, h))
+                                .unwrap_or(true);
+                            if !verified {
+                                health.record_read_failure(
+                                    account_id,
+                                    "content hash mismatch on download",
+                                    started.elapsed(),
+                                );
+                                error!(
+                                    "Block {} from account {} failed checksum verification on download",
+                                    block_idx, account_id
+                                );
+                                return Err((
+                                    block_idx,
+                                    Error::ChecksumMismatch { block_index: block_idx, account_id },
+                                ));
+                            }
+
+                            health.record_read_success(account_id, started.elapsed());
                             debug!(
                                 "Block {} downloaded from account {} ({} bytes)",
                                 block_idx,
@@ -401,7 +630,7 @@ impl AccountPool {
                             Ok((block_idx, data))
                         }
                         Err(e) => {
-                            health.record_failure(account_id, &e.to_string());
+                            health.record_read_failure(account_id, &e.to_string(), started.elapsed());
                             error!(
                                 "Failed to download block {} from account {}: {}",
                                 block_idx, account_id, e
@@ -465,6 +694,286 @@ impl AccountPool {
         Ok(blocks)
     }
 
+    /// Re-materialize whatever blocks of `stripe_info` are currently bad
+    /// - missing a `message_id`, or sitting on an account the health
+    /// tracker reports `Unavailable` - so a stripe that dropped below
+    /// full redundancy on a read doesn't stay degraded until an operator
+    /// runs an explicit `raid rebuild`.
+    ///
+    /// Downloads at least `data_count` good blocks, Reed-Solomon decodes
+    /// the full N-block set, then re-uploads each bad slot to a spare
+    /// healthy account that doesn't already hold a block for this
+    /// stripe. `stripe_info` is updated in place with the new
+    /// `BlockLocation`s; a slot with no healthy spare available is left
+    /// as-is and reported in `RepairReport::unrepaired`.
+    pub async fn repair_stripe(&self, stripe_info: &mut StripeInfo) -> Result<RepairReport> {
+        let downloaded = self.download_blocks_raw(stripe_info).await?;
+
+        let total = stripe_info.total_blocks() as usize;
+        let mut shards: Vec<Option<Vec<u8>>> = vec![None; total];
+        for (block_index, data) in downloaded {
+            shards[block_index as usize] = Some(data);
+        }
+
+        let encoder = Encoder::new(stripe_info.data_count as usize, total)?;
+        encoder.reconstruct_all(&mut shards)?;
+
+        // Spares must avoid every account this stripe already occupies,
+        // not just the bad slots - otherwise a repair could park two
+        // blocks of the same stripe on one account.
+        let mut occupied: std::collections::HashSet<u8> =
+            stripe_info.blocks.iter().map(|b| b.account_id).collect();
+        let mut spares: std::collections::VecDeque<u8> = self
+            .healthy_accounts()
+            .into_iter()
+            .filter(|id| !occupied.contains(id))
+            .collect();
+
+        let bad_slots: Vec<usize> = stripe_info
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| {
+                b.message_id.is_none()
+                    || self.health.account_health(b.account_id).status == AccountStatus::Unavailable
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut report = RepairReport::default();
+
+        for slot in bad_slots {
+            let block_index = stripe_info.blocks[slot].block_index;
+
+            let data = match shards.get(block_index as usize).and_then(|s| s.as_ref()) {
+                Some(data) => data.clone(),
+                None => {
+                    warn!("repair_stripe: block {} missing from reconstructed shard set", block_index);
+                    report.unrepaired.push(block_index);
+                    continue;
+                }
+            };
+
+            let spare = match spares.pop_front() {
+                Some(id) => id,
+                None => {
+                    warn!("repair_stripe: no healthy spare account for block {}", block_index);
+                    report.unrepaired.push(block_index);
+                    continue;
+                }
+            };
+
+            let backend = match self.get_backend(spare) {
+                Some(b) => b,
+                None => {
+                    report.unrepaired.push(block_index);
+                    continue;
+                }
+            };
+
+            let block_chunk_id = format!("repair_{}_{}", block_index, now_unix());
+            let started = Instant::now();
+            match backend.upload_chunk(&block_chunk_id, &data).await {
+                Ok(message_id) => {
+                    self.health.record_write_success(spare, started.elapsed());
+                    occupied.insert(spare);
+                    stripe_info.blocks[slot] = crate::chunk::BlockLocation {
+                        account_id: spare,
+                        message_id: Some(message_id),
+                        block_index,
+                        uploaded_at: Some(now_unix()),
+                        content_hash: self.config.checksum.enabled.then(|| self.config.checksum.digest(&data)),
+                    };
+                    report.repaired.push(block_index);
+                }
+                Err(e) => {
+                    self.health.record_write_failure(spare, &e.to_string(), started.elapsed());
+                    error!("repair_stripe: failed to re-upload block {} to account {}: {}", block_index, spare, e);
+                    // Give the slot back up for the next bad block - this
+                    // spare never actually ended up holding anything.
+                    spares.push_front(spare);
+                    report.unrepaired.push(block_index);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Delete every block of a `StripeInfo` that a newer write superseded.
+    ///
+    /// Callers must have already confirmed, via [`resolve_latest`], that a
+    /// different `StripeInfo` for the same chunk with a higher
+    /// `write_version` is durably committed (at least `data_count` blocks
+    /// uploaded) before passing the old one here - this method does not
+    /// re-check that itself. Deletes are best-effort and run concurrently;
+    /// a failure to delete one block is logged and does not stop the rest.
+    pub async fn garbage_collect(&self, superseded: &StripeInfo) {
+        let delete_futures: Vec<_> = superseded
+            .blocks
+            .iter()
+            .filter_map(|block| {
+                let message_id = block.message_id?;
+                let backend = self.get_backend(block.account_id)?;
+                let account_id = block.account_id;
+                let block_index = block.block_index;
+                Some(async move {
+                    if let Err(e) = backend.delete_message(message_id).await {
+                        warn!(
+                            "garbage_collect: failed to delete block {} (message {}) on account {}: {}",
+                            block_index, message_id, account_id, e
+                        );
+                    }
+                })
+            })
+            .collect();
+
+        join_all(delete_futures).await;
+    }
+
+    /// Verify every uploaded block of a stripe still matches the content
+    /// hash recorded at upload time, so a byte-for-byte truncation or
+    /// alteration by an account is caught instead of silently trusted.
+    /// Blocks without a recorded `content_hash` (pre-dating this field)
+    /// are treated as `Ok` if they download at all - there's nothing to
+    /// compare them against.
+    ///
+    /// Every verification outcome is fed back into the `HealthTracker`
+    /// via `record_read_success`/`record_read_failure`, so an account
+    /// that keeps returning corrupt bytes is demoted the same way one
+    /// that keeps failing reads would be. Any `Corrupt` or `Missing`
+    /// block then drives a [`repair_stripe`](Self::repair_stripe) pass,
+    /// whose outcome is returned as `ScrubResult::repair`.
+    pub async fn scrub_stripe(&self, stripe_info: &mut StripeInfo) -> Result<ScrubResult> {
+        let verify_futures: Vec<_> = stripe_info
+            .blocks
+            .iter()
+            .map(|block| {
+                let block_index = block.block_index;
+                let message_id = block.message_id;
+                let expected_hash = block.content_hash.clone();
+                let account_id = block.account_id;
+                let backend = self.get_backend(account_id);
+                let health = Arc::clone(&self.health);
+                let checksum = self.config.checksum;
+
+                async move {
+                    let message_id = match message_id {
+                        Some(id) => id,
+                        None => return (block_index, ScrubVerdict::Missing),
+                    };
+
+                    let backend = match backend {
+                        Some(b) => b,
+                        None => return (block_index, ScrubVerdict::Missing),
+                    };
+
+                    let started = Instant::now();
+                    match backend.download_chunk(message_id).await {
+                        Ok(data) => {
+                            let matches = expected_hash
+                                .as_ref()
+                                .map(|h| checksum.verify(&data, h))
+                                .unwrap_or(true);
+
+                            if matches {
+                                health.record_read_success(account_id, started.elapsed());
+                                (block_index, ScrubVerdict::Ok)
+                            } else {
+                                health.record_read_failure(
+                                    account_id,
+                                    "content hash mismatch on scrub",
+                                    started.elapsed(),
+                                );
+                                error!(
+                                    "scrub_stripe: block {} on account {} failed hash verification",
+                                    block_index, account_id
+                                );
+                                (block_index, ScrubVerdict::Corrupt)
+                            }
+                        }
+                        Err(e) => {
+                            health.record_read_failure(account_id, &e.to_string(), started.elapsed());
+                            (block_index, ScrubVerdict::Missing)
+                        }
+                    }
+                }
+            })
+            .collect();
+
+        let mut verdicts = join_all(verify_futures).await;
+        verdicts.sort_by_key(|(idx, _)| *idx);
+
+        let any_bad = verdicts.iter().any(|(_, v)| *v != ScrubVerdict::Ok);
+
+        // A block that scrub found Corrupt is no better than one that was
+        // never uploaded - clear it so `repair_stripe` treats it as a bad
+        // slot needing a spare, rather than trusting its (wrong) bytes.
+        for (block_index, verdict) in &verdicts {
+            if *verdict == ScrubVerdict::Corrupt {
+                if let Some(block) = stripe_info
+                    .blocks
+                    .iter_mut()
+                    .find(|b| b.block_index == *block_index)
+                {
+                    block.message_id = None;
+                    block.uploaded_at = None;
+                    block.content_hash = None;
+                }
+            }
+        }
+
+        let repair = if any_bad {
+            Some(self.repair_stripe(stripe_info).await?)
+        } else {
+            None
+        };
+
+        Ok(ScrubResult { verdicts, repair })
+    }
+
+    /// Run [`scrub_stripe`](Self::scrub_stripe) over a batch of stripes,
+    /// bounding how many are verified concurrently so a sweep doesn't
+    /// compete with every healthy account's normal read/write traffic at
+    /// once. Intended for a periodic integrity pass an operator (or a
+    /// scheduled job) runs across the whole array.
+    pub async fn scrub_all(
+        &self,
+        stripes: impl IntoIterator<Item = StripeInfo>,
+        parallelism: usize,
+    ) -> ScrubSummary {
+        let semaphore = Arc::new(Semaphore::new(parallelism.max(1)));
+        let scrub_futures: Vec<_> = stripes
+            .into_iter()
+            .map(|mut stripe_info| {
+                let semaphore = Arc::clone(&semaphore);
+                async move {
+                    let _permit = semaphore.acquire().await;
+                    self.scrub_stripe(&mut stripe_info).await
+                }
+            })
+            .collect();
+
+        let results = join_all(scrub_futures).await;
+
+        let mut summary = ScrubSummary::default();
+        for result in results {
+            match result {
+                Ok(scrub) if scrub.is_clean() => summary.clean += 1,
+                Ok(scrub) => match scrub.repair {
+                    Some(report) if report.unrepaired.is_empty() => summary.repaired += 1,
+                    _ => summary.unrecoverable += 1,
+                },
+                Err(e) => {
+                    warn!("scrub_all: failed to scrub a stripe: {}", e);
+                    summary.unrecoverable += 1;
+                }
+            }
+        }
+
+        summary
+    }
+
     /// Get current array health
     pub fn health(&self) -> ArrayHealth {
         self.health.array_health()
@@ -495,6 +1004,12 @@ impl AccountPool {
         self.health.healthy_accounts()
     }
 
+    /// Get unavailable account IDs whose backoff has elapsed and are due to
+    /// be probed again
+    pub fn accounts_due_for_retry(&self) -> Vec<u8> {
+        self.health.accounts_due_for_retry()
+    }
+
     /// Get total number of accounts in pool
     pub fn account_count(&self) -> usize {
         self.backends.len()
@@ -616,4 +1131,63 @@ mod tests {
 
         assert_eq!(pool.parity_chunks(), 2); // N=5, K=3, parity=2
     }
+
+    #[test]
+    fn test_assign_blocks_uses_distinct_accounts_and_records_placement() {
+        let config = make_test_config(5);
+        let pool = AccountPool::new(config).unwrap();
+
+        let assigned = pool.assign_blocks("chunk-a", 5).unwrap();
+        assert_eq!(assigned.len(), 5);
+
+        let unique: std::collections::HashSet<u8> = assigned.iter().copied().collect();
+        assert_eq!(unique.len(), 5, "no two blocks of one stripe may share an account");
+
+        for (block_index, &account_id) in assigned.iter().enumerate() {
+            assert_eq!(pool.placement_for("chunk-a", block_index as u8), Some(account_id));
+        }
+    }
+
+    #[test]
+    fn test_assign_blocks_rejects_too_few_healthy_accounts() {
+        let config = make_test_config(3);
+        let pool = AccountPool::new(config).unwrap();
+
+        assert!(pool.assign_blocks("chunk-a", 5).is_err());
+    }
+
+    #[test]
+    fn test_assign_blocks_prefers_least_loaded_account() {
+        let config = make_test_config(5);
+        let pool = AccountPool::new(config).unwrap();
+
+        // Load every account except 4 up front; the next single-block
+        // assignment should land on the one account still at zero load.
+        for account_id in [0u8, 1, 2, 3] {
+            *pool.account_load.entry(account_id).or_insert(0) += 10;
+        }
+
+        let assigned = pool.assign_blocks("chunk-b", 1).unwrap();
+        assert_eq!(assigned, vec![4]);
+    }
+
+    #[test]
+    fn test_resolve_latest_picks_highest_write_version() {
+        let mut older = StripeInfo::new(3, 2, 1024);
+        older.write_version = 1;
+        let mut newer = StripeInfo::new(3, 2, 1024);
+        newer.write_version = 2;
+
+        let infos = vec![older, newer.clone()];
+        assert_eq!(resolve_latest(&infos).write_version, newer.write_version);
+    }
+
+    #[test]
+    fn test_resolve_latest_breaks_ties_on_first() {
+        let a = StripeInfo::new(3, 2, 1024);
+        let b = StripeInfo::new(3, 2, 1024);
+
+        let infos = vec![a, b];
+        assert_eq!(resolve_latest(&infos).write_version, 0);
+    }
 }
@@ -0,0 +1,398 @@
+//! Persistent, crash-recoverable catalog of `Stripe` records
+//!
+//! `StripeManager` only ever hands back `Stripe`s; nothing keeps them once
+//! the process holding them exits. `StripeIndex` is the durable catalog
+//! that sits in front of it, modeled after how Solana's AccountsDB
+//! bootstraps its in-memory index: every write is appended to an on-disk
+//! journal, and replaying that journal while keeping only the
+//! highest-`write_version` record per key reconstructs the latest state.
+//! If the journal itself is lost, [`StripeIndex::recover_from_accounts`]
+//! rebuilds the catalog from nothing but the surviving chunks: it scans
+//! each account's message history, matches what it finds back to the
+//! chunk hashes a stripe was expected to contain, and reconstructs every
+//! `ChunkLocation`'s `message_id` and `verified` flag from scratch.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::error::Result;
+
+use super::stripe::Stripe;
+
+/// One journal entry: the full state of a stripe as of one `StripeIndex::put`.
+/// Replaying the journal in order and keeping only the highest
+/// `write_version` per `stripe_id` reconstructs the latest catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalRecord {
+    write_version: u64,
+    stripe: Stripe,
+}
+
+/// Durable, crash-recoverable catalog of every `Stripe` a `StripeManager`
+/// has produced.
+pub struct StripeIndex {
+    stripes: HashMap<u64, Stripe>,
+    stripe_versions: HashMap<u64, u64>,
+    next_write_version: u64,
+    journal: Option<File>,
+}
+
+impl Default for StripeIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StripeIndex {
+    /// An empty, in-memory-only index with no journal backing it.
+    pub fn new() -> Self {
+        StripeIndex {
+            stripes: HashMap::new(),
+            stripe_versions: HashMap::new(),
+            next_write_version: 1,
+            journal: None,
+        }
+    }
+
+    /// Open (or create) a journal file at `path`: replay any records it
+    /// already contains, then keep it open so further [`Self::put`] calls
+    /// append to it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut index = Self::new();
+
+        if path.exists() {
+            let mut bytes = Vec::new();
+            File::open(path)?.read_to_end(&mut bytes)?;
+            for record in Self::decode_records(&bytes)? {
+                index.apply(record);
+            }
+        }
+
+        index.journal = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(index)
+    }
+
+    /// Decode a sequence of `[u64 length][bincode record]` entries,
+    /// stopping at the first truncated trailing entry instead of erroring -
+    /// a process can crash mid-append, and the journal up to that point is
+    /// still valid.
+    fn decode_records(bytes: &[u8]) -> Result<Vec<JournalRecord>> {
+        let mut records = Vec::new();
+        let mut cursor = 0usize;
+        while cursor + 8 <= bytes.len() {
+            let len = u64::from_le_bytes(bytes[cursor..cursor + 8].try_into().unwrap()) as usize;
+            cursor += 8;
+            if cursor + len > bytes.len() {
+                break;
+            }
+            records.push(bincode::deserialize(&bytes[cursor..cursor + len])?);
+            cursor += len;
+        }
+        Ok(records)
+    }
+
+    /// Apply a replayed record, keeping it only if it's at least as new as
+    /// whatever this stripe id currently holds.
+    fn apply(&mut self, record: JournalRecord) {
+        self.next_write_version = self.next_write_version.max(record.write_version + 1);
+
+        let is_newer = match self.stripe_versions.get(&record.stripe.stripe_id) {
+            Some(&current) => record.write_version >= current,
+            None => true,
+        };
+        if is_newer {
+            self.stripe_versions
+                .insert(record.stripe.stripe_id, record.write_version);
+            self.stripes.insert(record.stripe.stripe_id, record.stripe);
+        }
+    }
+
+    /// Record (or update) a stripe, appending it to the journal if one is open.
+    pub fn put(&mut self, stripe: Stripe) -> Result<()> {
+        let write_version = self.next_write_version;
+        self.next_write_version += 1;
+        let record = JournalRecord {
+            write_version,
+            stripe,
+        };
+
+        if let Some(journal) = self.journal.as_mut() {
+            let bytes = bincode::serialize(&record)?;
+            journal.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            journal.write_all(&bytes)?;
+            journal.flush()?;
+        }
+
+        self.apply(record);
+        Ok(())
+    }
+
+    /// Look up a stripe by id.
+    pub fn get(&self, stripe_id: u64) -> Option<&Stripe> {
+        self.stripes.get(&stripe_id)
+    }
+
+    /// Iterate over every stripe currently in the catalog.
+    pub fn stripes(&self) -> impl Iterator<Item = &Stripe> {
+        self.stripes.values()
+    }
+
+    /// Number of stripes in the catalog.
+    pub fn len(&self) -> usize {
+        self.stripes.len()
+    }
+
+    /// Whether the catalog holds no stripes.
+    pub fn is_empty(&self) -> bool {
+        self.stripes.is_empty()
+    }
+
+    /// Stripe ids that have dropped below their `data_chunks` threshold and
+    /// can no longer be reconstructed from what's left.
+    pub fn unrecoverable_stripes(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .stripes
+            .values()
+            .filter(|s| !s.can_reconstruct())
+            .map(|s| s.stripe_id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// For every stripe that's still reconstructable but missing chunks,
+    /// the chunk indices that need to be re-derived and re-uploaded.
+    pub fn stripes_needing_reencode(&self) -> HashMap<u64, Vec<u8>> {
+        self.stripes
+            .values()
+            .filter(|s| s.can_reconstruct() && !s.is_complete())
+            .map(|s| (s.stripe_id, s.missing_chunks()))
+            .collect()
+    }
+
+    /// Rebuild a catalog from nothing but surviving chunks, for when the
+    /// journal itself is gone.
+    ///
+    /// `expected` is the blueprint of stripes as they were meant to look -
+    /// normally recovered from the file-level manifests that reference
+    /// these stripes by content hash, which are backed up independently of
+    /// this journal. Every `ChunkLocation::hash` in `expected` is looked up
+    /// against what `scanner` finds on each of `account_ids`; a match
+    /// restores that location's `account_id`, `message_id` and `verified`
+    /// flag, while anything not found is left without a message id. Chunks
+    /// without a recorded hash can't be matched and are left untouched.
+    pub fn recover_from_accounts(
+        expected: &[Stripe],
+        account_ids: &[u8],
+        scanner: &impl AccountMessageScanner,
+    ) -> StripeIndex {
+        let mut found: HashMap<String, (u8, i32)> = HashMap::new();
+        for &account_id in account_ids {
+            for chunk in scanner.scan(account_id) {
+                found.insert(chunk.hash, (account_id, chunk.message_id));
+            }
+        }
+
+        let mut index = StripeIndex::new();
+        for template in expected {
+            let mut stripe = template.clone();
+            for location in stripe.chunks.iter_mut() {
+                let Some(hash) = location.hash.clone() else {
+                    continue;
+                };
+                match found.get(&hash) {
+                    Some(&(account_id, message_id)) => {
+                        location.account_id = account_id;
+                        location.message_id = Some(message_id);
+                        location.verified = true;
+                    }
+                    None => {
+                        location.message_id = None;
+                        location.verified = false;
+                    }
+                }
+            }
+            index.stripes.insert(stripe.stripe_id, stripe);
+        }
+        index
+    }
+}
+
+/// One message found while scanning an account's surviving message history
+/// during crash recovery.
+#[derive(Debug, Clone)]
+pub struct ScannedChunk {
+    /// Message id the chunk still lives at.
+    pub message_id: i32,
+    /// Content hash recovered from the message (e.g. parsed back out of its
+    /// filename), used to match it to the `ChunkLocation` that expected it.
+    pub hash: String,
+}
+
+/// Where [`StripeIndex::recover_from_accounts`] gets each account's
+/// surviving message history from - a live scan of Telegram Saved Messages
+/// in production, a fixture in tests.
+pub trait AccountMessageScanner {
+    /// List every candidate chunk message still present on `account_id`.
+    fn scan(&self, account_id: u8) -> Vec<ScannedChunk>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raid::stripe::ChunkLocation;
+    use std::collections::HashMap as StdHashMap;
+    use tempfile::tempdir;
+
+    fn sample_stripe(stripe_id: u64) -> Stripe {
+        let mut stripe = Stripe::new(stripe_id, 1024, 512, 2, 3);
+        stripe.add_chunk(ChunkLocation::new(0, 0, true).with_message_id(1));
+        stripe.add_chunk(ChunkLocation::new(1, 1, true).with_message_id(2));
+        stripe.add_chunk(ChunkLocation::new(2, 2, false).with_message_id(3));
+        stripe
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let mut index = StripeIndex::new();
+        index.put(sample_stripe(1)).unwrap();
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get(1).unwrap().stripe_id, 1);
+        assert!(index.get(2).is_none());
+    }
+
+    #[test]
+    fn test_journal_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("stripes.journal");
+
+        {
+            let mut index = StripeIndex::open(&path).unwrap();
+            index.put(sample_stripe(1)).unwrap();
+            index.put(sample_stripe(2)).unwrap();
+        }
+
+        let reopened = StripeIndex::open(&path).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.get(1).unwrap().stripe_id, 1);
+        assert_eq!(reopened.get(2).unwrap().stripe_id, 2);
+    }
+
+    #[test]
+    fn test_journal_keeps_latest_write_per_stripe() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("stripes.journal");
+
+        let mut index = StripeIndex::open(&path).unwrap();
+        let mut stripe = sample_stripe(1);
+        index.put(stripe.clone()).unwrap();
+
+        // Rewrite the same stripe id with chunk 0 now uploaded elsewhere.
+        stripe.chunks[0].account_id = 9;
+        index.put(stripe).unwrap();
+
+        drop(index);
+        let reopened = StripeIndex::open(&path).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.get(1).unwrap().chunks[0].account_id, 9);
+    }
+
+    #[test]
+    fn test_unrecoverable_and_reencode_reports() {
+        let mut index = StripeIndex::new();
+
+        // Stripe 1: fully intact.
+        index.put(sample_stripe(1)).unwrap();
+
+        // Stripe 2: missing only the parity chunk - still reconstructable.
+        let mut degraded = sample_stripe(2);
+        degraded.chunks[2].message_id = None;
+        index.put(degraded).unwrap();
+
+        // Stripe 3: missing a data chunk - below K=2, unrecoverable.
+        let mut dead = sample_stripe(3);
+        dead.chunks[0].message_id = None;
+        dead.chunks[1].message_id = None;
+        index.put(dead).unwrap();
+
+        assert_eq!(index.unrecoverable_stripes(), vec![3]);
+
+        let reencode = index.stripes_needing_reencode();
+        assert_eq!(reencode.len(), 1);
+        assert_eq!(reencode[&2], vec![2]);
+    }
+
+    struct FixtureScanner {
+        messages: StdHashMap<u8, Vec<ScannedChunk>>,
+    }
+
+    impl AccountMessageScanner for FixtureScanner {
+        fn scan(&self, account_id: u8) -> Vec<ScannedChunk> {
+            self.messages.get(&account_id).cloned().unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_recover_from_accounts_restores_surviving_chunks() {
+        // Expected blueprint: stripe 1 should have 3 chunks at known hashes.
+        let mut expected = Stripe::new(1, 1024, 512, 2, 3);
+        expected.add_chunk(ChunkLocation::new(0, 0, true).with_hash("hash-a".into()));
+        expected.add_chunk(ChunkLocation::new(1, 1, true).with_hash("hash-b".into()));
+        expected.add_chunk(ChunkLocation::new(2, 2, false).with_hash("hash-c".into()));
+
+        // Only chunks a and b actually survived, and chunk b moved to a
+        // different account than the blueprint expected.
+        let mut messages = StdHashMap::new();
+        messages.insert(
+            0u8,
+            vec![ScannedChunk {
+                message_id: 101,
+                hash: "hash-a".to_string(),
+            }],
+        );
+        messages.insert(
+            9u8,
+            vec![ScannedChunk {
+                message_id: 202,
+                hash: "hash-b".to_string(),
+            }],
+        );
+        let scanner = FixtureScanner { messages };
+
+        let index =
+            StripeIndex::recover_from_accounts(&[expected], &[0, 1, 2, 9], &scanner);
+
+        let stripe = index.get(1).unwrap();
+        assert_eq!(stripe.get_chunk(0).unwrap().message_id, Some(101));
+        assert_eq!(stripe.get_chunk(1).unwrap().message_id, Some(202));
+        assert_eq!(stripe.get_chunk(1).unwrap().account_id, 9);
+        assert!(stripe.get_chunk(2).unwrap().message_id.is_none());
+
+        // 2 of 3 chunks recovered, K=2, so still reconstructable...
+        assert!(stripe.can_reconstruct());
+        // ...but chunk 2 (parity) is gone and needs re-encoding.
+        assert_eq!(index.stripes_needing_reencode()[&1], vec![2]);
+        assert!(index.unrecoverable_stripes().is_empty());
+    }
+
+    #[test]
+    fn test_recover_from_accounts_flags_stripe_below_k_unrecoverable() {
+        let mut expected = Stripe::new(1, 1024, 512, 2, 3);
+        expected.add_chunk(ChunkLocation::new(0, 0, true).with_hash("hash-a".into()));
+        expected.add_chunk(ChunkLocation::new(1, 1, true).with_hash("hash-b".into()));
+        expected.add_chunk(ChunkLocation::new(2, 2, false).with_hash("hash-c".into()));
+
+        // Nothing survived anywhere.
+        let scanner = FixtureScanner {
+            messages: StdHashMap::new(),
+        };
+
+        let index = StripeIndex::recover_from_accounts(&[expected], &[0, 1, 2], &scanner);
+        assert_eq!(index.unrecoverable_stripes(), vec![1]);
+    }
+}
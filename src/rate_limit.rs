@@ -0,0 +1,95 @@
+//! Token-bucket rate limiting.
+//!
+//! Used to throttle outgoing Telegram API calls ahead of time rather than
+//! discovering a `FLOOD_WAIT` after the fact. See `RateLimitConfig` for
+//! the configuration surface.
+
+use crate::config::RateLimitConfig;
+use std::time::{Duration, Instant};
+
+/// Classic token bucket: `capacity` tokens refill at `refill_rate`
+/// tokens/sec, and `take` either spends tokens immediately or reports how
+/// long the caller must wait for enough to accumulate.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a bucket with the given capacity (burst size) and refill
+    /// rate (tokens/sec), starting full.
+    pub fn new(capacity: u64, refill_rate: f64) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            refill_rate,
+            tokens: capacity as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Build a bucket from a `RateLimitConfig`.
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        TokenBucket::new(config.burst, config.requests_per_second)
+    }
+
+    /// Refill based on elapsed time since the last refill, clamped to
+    /// `capacity`.
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Try to spend `n` tokens. Returns `Ok(())` if there were enough,
+    /// otherwise `Err(duration)` with how long the caller must wait before
+    /// `n` tokens would be available.
+    pub fn take(&mut self, n: u64) -> Result<(), Duration> {
+        self.refill();
+
+        let n = n as f64;
+        if self.tokens >= n {
+            self.tokens -= n;
+            Ok(())
+        } else {
+            let wait_secs = (n - self.tokens) / self.refill_rate;
+            Err(Duration::from_secs_f64(wait_secs))
+        }
+    }
+}
+
+/// A `TokenBucket` shared across tasks, behind a `Mutex` so concurrent
+/// callers serialize on `take` rather than racing its internal state.
+#[derive(Debug, Clone)]
+pub struct SharedTokenBucket {
+    inner: std::sync::Arc<std::sync::Mutex<TokenBucket>>,
+}
+
+impl SharedTokenBucket {
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        SharedTokenBucket {
+            inner: std::sync::Arc::new(std::sync::Mutex::new(TokenBucket::from_config(config))),
+        }
+    }
+
+    /// Try to spend `n` tokens, waking a poisoned lock by recovering its
+    /// inner value rather than propagating the panic.
+    pub fn take(&self, n: u64) -> Result<(), Duration> {
+        let mut bucket = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.take(n)
+    }
+
+    /// Spend `n` tokens, sleeping (via `tokio::time::sleep`) for as long
+    /// as `take` reports is needed, then spending them.
+    pub async fn acquire(&self, n: u64) {
+        loop {
+            match self.take(n) {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+}
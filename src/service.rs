@@ -0,0 +1,346 @@
+//! OS service unit generation and (un)installation.
+//!
+//! `tgcryptfs service install <mount_point>` wires a mount up to survive
+//! reboots: a per-user systemd unit on Linux, a launchd agent plist on
+//! macOS. Both just re-invoke `tgcryptfs mount --foreground` with the
+//! options the operator chose, so `service uninstall`/`service status`
+//! only ever have to manage the one generated file - named
+//! deterministically from the mount point so repeat installs overwrite
+//! rather than accumulate.
+
+use crate::error::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Everything the generated unit needs to reproduce one `tgcryptfs mount`
+/// invocation.
+pub struct ServiceSpec {
+    pub config_path: PathBuf,
+    pub mount_point: PathBuf,
+    pub allow_other: bool,
+    pub password_file: Option<PathBuf>,
+    pub overlay: bool,
+    pub lower_path: Option<PathBuf>,
+    pub backend: Option<String>,
+}
+
+impl ServiceSpec {
+    /// Path to the currently running `tgcryptfs` binary, so the generated
+    /// unit keeps working even if it's not on `$PATH` under a service
+    /// manager's minimal environment.
+    fn binary_path() -> Result<PathBuf> {
+        std::env::current_exe()
+            .map_err(|e| Error::Internal(format!("Could not resolve tgcryptfs's own binary path: {e}")))
+    }
+
+    /// `tgcryptfs --config ... mount ... --foreground ...` arguments
+    /// reproducing this spec.
+    fn mount_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--config".to_string(),
+            self.config_path.display().to_string(),
+            "mount".to_string(),
+            self.mount_point.display().to_string(),
+            "--foreground".to_string(),
+        ];
+
+        if self.allow_other {
+            args.push("--allow-other".to_string());
+        }
+        if let Some(password_file) = &self.password_file {
+            args.push("--password-file".to_string());
+            args.push(password_file.display().to_string());
+        }
+        if self.overlay {
+            args.push("--overlay".to_string());
+        }
+        if let Some(lower_path) = &self.lower_path {
+            args.push("--lower-path".to_string());
+            args.push(lower_path.display().to_string());
+        }
+        if let Some(backend) = &self.backend {
+            args.push("--backend".to_string());
+            args.push(backend.clone());
+        }
+
+        args
+    }
+
+    /// `tgcryptfs --config ... unmount ...` arguments, so the generated
+    /// unit can cleanly unmount on stop instead of leaving a dangling
+    /// FUSE mount behind.
+    fn unmount_args(&self) -> Vec<String> {
+        vec![
+            "--config".to_string(),
+            self.config_path.display().to_string(),
+            "unmount".to_string(),
+            self.mount_point.display().to_string(),
+        ]
+    }
+}
+
+/// Deterministic service identifier for `mount_point`, so repeat installs
+/// overwrite the same unit instead of accumulating one per attempt.
+fn service_name(mount_point: &Path) -> String {
+    let sanitized: String = mount_point
+        .display()
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("tgcryptfs-{}", sanitized.trim_matches('-'))
+}
+
+/// Quote `arg` for inclusion in a generated shell-invoked `ExecStart`/
+/// `ExecStop` line.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+fn exec_line(binary: &Path, args: &[String]) -> String {
+    std::iter::once(shell_quote(&binary.display().to_string()))
+        .chain(args.iter().map(|a| shell_quote(a)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(target_os = "linux")]
+mod systemd {
+    use super::*;
+
+    fn unit_dir() -> Result<PathBuf> {
+        let home = dirs::home_dir()
+            .ok_or_else(|| Error::Internal("Could not resolve home directory".to_string()))?;
+        Ok(home.join(".config/systemd/user"))
+    }
+
+    fn unit_path(mount_point: &Path) -> Result<PathBuf> {
+        Ok(unit_dir()?.join(format!("{}.service", service_name(mount_point))))
+    }
+
+    fn render(spec: &ServiceSpec) -> Result<String> {
+        let binary = ServiceSpec::binary_path()?;
+        Ok(format!(
+            "[Unit]\n\
+             Description=tgcryptfs mount at {mount_point}\n\
+             After=network-online.target\n\
+             Wants=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart={exec_start}\n\
+             ExecStop={exec_stop}\n\
+             Restart=on-failure\n\
+             RestartSec=5\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            mount_point = spec.mount_point.display(),
+            exec_start = exec_line(&binary, &spec.mount_args()),
+            exec_stop = exec_line(&binary, &spec.unmount_args()),
+        ))
+    }
+
+    fn run_systemctl(args: &[&str]) -> Result<()> {
+        let mut full_args = vec!["--user"];
+        full_args.extend_from_slice(args);
+        let status = Command::new("systemctl")
+            .args(&full_args)
+            .status()
+            .map_err(|e| Error::Internal(format!("Failed to run systemctl: {e}")))?;
+        if !status.success() {
+            return Err(Error::Internal(format!("systemctl {:?} failed", full_args)));
+        }
+        Ok(())
+    }
+
+    pub fn install(spec: &ServiceSpec) -> Result<PathBuf> {
+        let path = unit_path(&spec.mount_point)?;
+        std::fs::create_dir_all(unit_dir()?)?;
+        std::fs::write(&path, render(spec)?)?;
+
+        run_systemctl(&["daemon-reload"])?;
+        run_systemctl(&["enable", "--now", &format!("{}.service", service_name(&spec.mount_point))])?;
+        Ok(path)
+    }
+
+    pub fn uninstall(mount_point: &Path) -> Result<()> {
+        let name = format!("{}.service", service_name(mount_point));
+        let _ = run_systemctl(&["disable", "--now", &name]);
+
+        let path = unit_path(mount_point)?;
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        run_systemctl(&["daemon-reload"])
+    }
+
+    pub fn status(mount_point: &Path) -> Result<String> {
+        let name = format!("{}.service", service_name(mount_point));
+        let output = Command::new("systemctl")
+            .args(["--user", "status", &name])
+            .output()
+            .map_err(|e| Error::Internal(format!("Failed to run systemctl: {e}")))?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod launchd {
+    use super::*;
+
+    fn label(mount_point: &Path) -> String {
+        format!("com.{}", service_name(mount_point))
+    }
+
+    fn plist_path(mount_point: &Path) -> Result<PathBuf> {
+        let home = dirs::home_dir()
+            .ok_or_else(|| Error::Internal("Could not resolve home directory".to_string()))?;
+        Ok(home.join("Library/LaunchAgents").join(format!("{}.plist", label(mount_point))))
+    }
+
+    fn xml_escape(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    fn render(spec: &ServiceSpec) -> Result<String> {
+        let binary = ServiceSpec::binary_path()?;
+        let mut program_args = String::new();
+        for arg in std::iter::once(binary.display().to_string()).chain(spec.mount_args()) {
+            program_args.push_str(&format!("        <string>{}</string>\n", xml_escape(&arg)));
+        }
+
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>{label}</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {program_args}\
+             \x20   </array>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             \x20   <key>KeepAlive</key>\n\
+             \x20   <true/>\n\
+             </dict>\n\
+             </plist>\n",
+            label = label(&spec.mount_point),
+        ))
+    }
+
+    fn run_launchctl(args: &[&str]) -> Result<()> {
+        let status = Command::new("launchctl")
+            .args(args)
+            .status()
+            .map_err(|e| Error::Internal(format!("Failed to run launchctl: {e}")))?;
+        if !status.success() {
+            return Err(Error::Internal(format!("launchctl {:?} failed", args)));
+        }
+        Ok(())
+    }
+
+    pub fn install(spec: &ServiceSpec) -> Result<PathBuf> {
+        let path = plist_path(&spec.mount_point)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, render(spec)?)?;
+
+        run_launchctl(&["load", "-w", &path.display().to_string()])?;
+        Ok(path)
+    }
+
+    pub fn uninstall(mount_point: &Path) -> Result<()> {
+        let path = plist_path(mount_point)?;
+        if path.exists() {
+            let _ = run_launchctl(&["unload", "-w", &path.display().to_string()]);
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn status(mount_point: &Path) -> Result<String> {
+        let output = Command::new("launchctl")
+            .args(["list", &label(mount_point)])
+            .output()
+            .map_err(|e| Error::Internal(format!("Failed to run launchctl: {e}")))?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Generate and install the service unit for `spec`, enabling and
+/// starting it immediately. Returns the path of the file written.
+#[cfg(target_os = "linux")]
+pub fn install(spec: &ServiceSpec) -> Result<PathBuf> {
+    systemd::install(spec)
+}
+
+#[cfg(target_os = "macos")]
+pub fn install(spec: &ServiceSpec) -> Result<PathBuf> {
+    launchd::install(spec)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn install(_spec: &ServiceSpec) -> Result<PathBuf> {
+    Err(Error::NotImplemented(
+        "service install is only supported on Linux (systemd) and macOS (launchd)".to_string(),
+    ))
+}
+
+/// Stop and remove the service unit previously installed for `mount_point`.
+#[cfg(target_os = "linux")]
+pub fn uninstall(mount_point: &Path) -> Result<()> {
+    systemd::uninstall(mount_point)
+}
+
+#[cfg(target_os = "macos")]
+pub fn uninstall(mount_point: &Path) -> Result<()> {
+    launchd::uninstall(mount_point)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn uninstall(_mount_point: &Path) -> Result<()> {
+    Err(Error::NotImplemented(
+        "service uninstall is only supported on Linux (systemd) and macOS (launchd)".to_string(),
+    ))
+}
+
+/// Raw status output (`systemctl --user status` / `launchctl list`) for
+/// the service installed for `mount_point`.
+#[cfg(target_os = "linux")]
+pub fn status(mount_point: &Path) -> Result<String> {
+    systemd::status(mount_point)
+}
+
+#[cfg(target_os = "macos")]
+pub fn status(mount_point: &Path) -> Result<String> {
+    launchd::status(mount_point)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn status(_mount_point: &Path) -> Result<String> {
+    Err(Error::NotImplemented(
+        "service status is only supported on Linux (systemd) and macOS (launchd)".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_name_sanitizes_path() {
+        assert_eq!(service_name(Path::new("/mnt/tg-crypt fs")), "tgcryptfs-mnt-tg-crypt-fs");
+    }
+
+    #[test]
+    fn test_shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("it's here"), r"'it'\''s here'");
+    }
+}
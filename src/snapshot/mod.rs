@@ -0,0 +1,23 @@
+//! Filesystem snapshot subsystem
+//!
+//! Provides point-in-time export/import of inode metadata (see
+//! [`Snapshot`]/[`SnapshotManager`]) plus the lower-level object writers
+//! used to back up and restore the chunks those inodes reference,
+//! [`SnapshotStore`] tying the two together into durable, on-disk,
+//! content-addressed whole-tree snapshots, and [`SnapshotStorageRebuilder`]
+//! for rebuilding a snapshot's inode tree back into a live metadata store.
+
+mod archive;
+mod restore;
+mod snapshot;
+mod store;
+mod writer;
+
+pub use archive::ArchiveFormat;
+pub use restore::{MetadataSink, RestoreReport, SnapshotStorageRebuilder};
+pub use snapshot::{RetentionPolicy, Snapshot, SnapshotManager, SnapshotType};
+pub use store::{DiffType, SnapshotInfo, SnapshotStore};
+pub use writer::{
+    bound_manifest_versions, ChunkAssembler, LooseSnapshotReader, LooseSnapshotWriter,
+    PackedSnapshotReader, PackedSnapshotWriter, SnapshotReader, SnapshotWriter,
+};
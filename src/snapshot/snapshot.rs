@@ -3,13 +3,45 @@
 //! A snapshot captures the state of all inodes at a point in time.
 //! Since chunk data is immutable and content-addressed, snapshots
 //! only need to store inode metadata.
-
-use crate::crypto::{decrypt, encrypt, EncryptedData, KEY_SIZE};
+//!
+//! Snapshots can be [`SnapshotType::Full`] (every inode) or
+//! [`SnapshotType::Incremental`] (only the inodes that changed since a
+//! `parent_id` snapshot, plus a `deleted` set) - see
+//! [`SnapshotManager::create_incremental_snapshot`] and
+//! [`SnapshotManager::materialize`].
+
+use crate::chunk::ChunkId;
+use crate::crypto::{decrypt, encrypt, Algorithm, EncryptedData, KEY_SIZE};
 use crate::error::{Error, Result};
 use crate::metadata::Inode;
-use chrono::{DateTime, Utc};
+use crate::snapshot::ArchiveFormat;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Read, Write};
+use tracing::warn;
+
+/// Magic bytes identifying a [`SnapshotManager::export_to`] archive.
+const ARCHIVE_MAGIC: &[u8; 4] = b"SNAP";
+/// Archive header layout version - bump if the header itself changes
+/// shape, independent of `ArchiveFormat`.
+const ARCHIVE_VERSION: u8 = 1;
+
+/// Whether a [`Snapshot`] stands alone or is a delta against a parent.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SnapshotType {
+    /// Contains every inode at the time it was taken.
+    Full,
+    /// Contains only the inodes added or changed since `parent_id`, plus
+    /// `deleted` for inodes removed since then.
+    Incremental,
+}
+
+impl Default for SnapshotType {
+    fn default() -> Self {
+        SnapshotType::Full
+    }
+}
 
 /// A filesystem snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,22 +54,61 @@ pub struct Snapshot {
     pub created: DateTime<Utc>,
     /// Optional description
     pub description: Option<String>,
-    /// Snapshot of all inodes (ino -> serialized inode)
+    /// Full or incremental
+    #[serde(default)]
+    pub snapshot_type: SnapshotType,
+    /// For an incremental snapshot, the ID of the snapshot it's a delta
+    /// against. `None` for full snapshots.
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Inodes removed since `parent_id` - only meaningful for incremental
+    /// snapshots, since a full snapshot is a complete inode set on its
+    /// own.
+    #[serde(default)]
+    pub deleted: HashSet<u64>,
+    /// Inodes added or changed since `parent_id` (ino -> serialized
+    /// inode), or every inode for a full snapshot.
     pub inodes: HashMap<u64, Vec<u8>>,
     /// Root inode number
     pub root_ino: u64,
+    /// Per-inode BLAKE3 content hash, keyed by ino - the Merkle tree's
+    /// leaves behind `content_hash`, persisted alongside the root so
+    /// [`Self::verify`] can point at exactly which inode(s) don't match
+    /// rather than only reporting that the snapshot as a whole is
+    /// corrupt.
+    #[serde(default)]
+    pub leaf_hashes: BTreeMap<u64, String>,
+    /// Merkle root over `leaf_hashes`, computed by [`Self::seal`] once
+    /// the snapshot's inode set is final. `None` for snapshots created
+    /// before this field existed, or not yet sealed.
+    #[serde(default)]
+    pub content_hash: Option<String>,
 }
 
 impl Snapshot {
-    /// Create a new snapshot
+    /// Create a new full snapshot
     pub fn new(name: String, description: Option<String>) -> Self {
         Snapshot {
             id: uuid::Uuid::new_v4().to_string(),
             name,
             created: Utc::now(),
             description,
+            snapshot_type: SnapshotType::Full,
+            parent_id: None,
+            deleted: HashSet::new(),
             inodes: HashMap::new(),
             root_ino: 1,
+            leaf_hashes: BTreeMap::new(),
+            content_hash: None,
+        }
+    }
+
+    /// Create a new incremental snapshot, a delta against `parent_id`
+    pub fn new_incremental(name: String, description: Option<String>, parent_id: String) -> Self {
+        Snapshot {
+            snapshot_type: SnapshotType::Incremental,
+            parent_id: Some(parent_id),
+            ..Snapshot::new(name, description)
         }
     }
 
@@ -74,6 +145,103 @@ impl Snapshot {
         self.inodes.len()
     }
 
+    /// Every chunk id any inode in this snapshot references, across all
+    /// stored manifest versions - the snapshot's side of a mark-and-sweep
+    /// GC's "mark" phase (see `SnapshotStore::gc`).
+    pub fn referenced_chunk_ids(&self) -> Result<HashSet<ChunkId>> {
+        let mut ids = HashSet::new();
+        for inode in self.all_inodes()? {
+            if let Some(manifest) = &inode.manifest {
+                ids.extend(manifest.chunks.iter().map(|chunk| chunk.id.clone()));
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Recompute `leaf_hashes` and `content_hash` over the snapshot's
+    /// current inode set. Call once the inode set is final - any
+    /// `add_inode` after sealing leaves `content_hash` stale until
+    /// `seal` runs again.
+    pub fn seal(&mut self) {
+        self.leaf_hashes = self
+            .inodes
+            .iter()
+            .map(|(ino, data)| (*ino, blake3::hash(data).to_hex().to_string()))
+            .collect();
+        self.content_hash = Some(Self::merkle_root(&self.leaf_hashes));
+    }
+
+    /// Merkle root over `leaves`' `(ino, hash)` pairs in ascending ino
+    /// order (guaranteed by `BTreeMap`'s iteration order) - each leaf is
+    /// `BLAKE3(ino || inode_hash)`, combined pairwise up the tree,
+    /// duplicating a level's last node when it's odd-sized.
+    fn merkle_root(leaves: &BTreeMap<u64, String>) -> String {
+        if leaves.is_empty() {
+            return blake3::hash(b"empty-snapshot").to_hex().to_string();
+        }
+
+        let mut level: Vec<[u8; 32]> = leaves
+            .iter()
+            .map(|(ino, hash)| {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(&ino.to_le_bytes());
+                hasher.update(hash.as_bytes());
+                *hasher.finalize().as_bytes()
+            })
+            .collect();
+
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity((level.len() + 1) / 2);
+            for pair in level.chunks(2) {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(&pair[0]);
+                hasher.update(pair.get(1).unwrap_or(&pair[0]));
+                next.push(*hasher.finalize().as_bytes());
+            }
+            level = next;
+        }
+        hex::encode(level[0])
+    }
+
+    /// Verify every inode's bytes against its recorded leaf hash and the
+    /// snapshot's Merkle root.
+    ///
+    /// Returns `Ok(())` if everything matches (including an unsealed
+    /// snapshot with no `content_hash` to check). Otherwise returns the
+    /// inode numbers whose hash no longer matches `leaf_hashes` - an
+    /// empty list alongside a mismatched root means the tree/root itself
+    /// was corrupted rather than any one inode's bytes.
+    pub fn verify(&self) -> std::result::Result<(), Vec<u64>> {
+        let mut mismatched: Vec<u64> = self
+            .inodes
+            .iter()
+            .filter(|(ino, data)| {
+                let actual = blake3::hash(data).to_hex().to_string();
+                self.leaf_hashes.get(ino) != Some(&actual)
+            })
+            .map(|(ino, _)| *ino)
+            .collect();
+
+        for ino in self.leaf_hashes.keys() {
+            if !self.inodes.contains_key(ino) && !mismatched.contains(ino) {
+                mismatched.push(*ino);
+            }
+        }
+
+        if !mismatched.is_empty() {
+            mismatched.sort_unstable();
+            return Err(mismatched);
+        }
+
+        if let Some(content_hash) = &self.content_hash {
+            if &Self::merkle_root(&self.leaf_hashes) != content_hash {
+                return Err(self.inodes.keys().copied().collect());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Serialize the snapshot for storage
     pub fn serialize(&self) -> Result<Vec<u8>> {
         bincode::serialize(self).map_err(|e| Error::Serialization(e.to_string()))
@@ -85,6 +253,25 @@ impl Snapshot {
     }
 }
 
+/// GFS-style ("grandfather-father-son") snapshot retention: keep the
+/// newest snapshot in each of the most recent `hourly` one-hour windows,
+/// `daily` one-day windows, and `weekly` one-week windows, evaluated
+/// against [`Snapshot::created`]. A snapshot that lands in more than one
+/// tier's window (e.g. the latest snapshot overall) is only kept once.
+///
+/// Lets [`SnapshotManager::apply_retention`] hold a sparse but long
+/// history instead of [`SnapshotManager::max_snapshots`]'s flat cap,
+/// which only ever keeps the most recent few.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Number of most-recent one-hour windows to keep a snapshot from.
+    pub hourly: usize,
+    /// Number of most-recent one-day windows to keep a snapshot from.
+    pub daily: usize,
+    /// Number of most-recent one-week windows to keep a snapshot from.
+    pub weekly: usize,
+}
+
 /// Manages snapshots
 pub struct SnapshotManager {
     /// Encryption key
@@ -122,16 +309,198 @@ impl SnapshotManager {
         for inode in inodes {
             snapshot.add_inode(&inode)?;
         }
+        snapshot.seal();
 
-        // Prune old snapshots if needed
-        if self.max_snapshots > 0 && self.snapshots.len() >= self.max_snapshots {
-            self.snapshots.remove(0);
+        self.prune_if_needed();
+        self.snapshots.push(snapshot);
+        Ok(self.snapshots.last().unwrap())
+    }
+
+    /// Create an incremental snapshot: a delta against the existing
+    /// snapshot `base_id`, storing only the inodes `iter_changes` reports
+    /// as added/changed plus the set it reports as deleted.
+    ///
+    /// Rejects the call if `base_id` doesn't name an existing snapshot -
+    /// an incremental with a dangling parent can never be materialized.
+    pub fn create_incremental_snapshot<F>(
+        &mut self,
+        name: String,
+        description: Option<String>,
+        base_id: &str,
+        iter_changes: F,
+    ) -> Result<&Snapshot>
+    where
+        F: FnOnce() -> Result<(Vec<Inode>, HashSet<u64>)>,
+    {
+        if self.get(base_id).is_none() {
+            return Err(Error::SnapshotNotFound(base_id.to_string()));
+        }
+
+        let mut snapshot = Snapshot::new_incremental(name, description, base_id.to_string());
+        let (changed, deleted) = iter_changes()?;
+        for inode in changed {
+            snapshot.add_inode(&inode)?;
         }
+        snapshot.deleted = deleted;
+        snapshot.seal();
 
+        self.prune_if_needed();
         self.snapshots.push(snapshot);
         Ok(self.snapshots.last().unwrap())
     }
 
+    /// Replay the full/incremental chain rooted at `id`'s eventual full
+    /// ancestor, oldest-to-newest, into a single point-in-time inode map.
+    pub fn materialize(&self, id: &str) -> Result<HashMap<u64, Inode>> {
+        let snapshot = self
+            .get(id)
+            .ok_or_else(|| Error::SnapshotNotFound(id.to_string()))?;
+
+        let mut chain = vec![snapshot];
+        let mut current = snapshot;
+        while current.snapshot_type == SnapshotType::Incremental {
+            let parent_id = current.parent_id.as_ref().ok_or_else(|| {
+                Error::SnapshotNotFound(format!(
+                    "incremental snapshot '{}' has no parent_id",
+                    current.id
+                ))
+            })?;
+            let parent = self.get(parent_id).ok_or_else(|| {
+                Error::SnapshotNotFound(format!(
+                    "base snapshot '{}' for incremental '{}' not found",
+                    parent_id, current.id
+                ))
+            })?;
+            chain.push(parent);
+            current = parent;
+        }
+        chain.reverse(); // full ancestor first, id's own snapshot last
+
+        let mut materialized = HashMap::new();
+        for snap in chain {
+            for ino in &snap.deleted {
+                materialized.remove(ino);
+            }
+            for (ino, data) in &snap.inodes {
+                let inode: Inode = bincode::deserialize(data)?;
+                materialized.insert(*ino, inode);
+            }
+        }
+        Ok(materialized)
+    }
+
+    /// Drop the oldest snapshot that nothing else still depends on, if
+    /// we're at `max_snapshots` capacity. A full snapshot that a
+    /// surviving incremental was taken against is never a candidate -
+    /// pruning it would leave that incremental impossible to
+    /// materialize - so if every kept snapshot is depended on, nothing
+    /// is pruned this round.
+    fn prune_if_needed(&mut self) {
+        if self.max_snapshots == 0 || self.snapshots.len() < self.max_snapshots {
+            return;
+        }
+
+        let depended_on: HashSet<&str> = self
+            .snapshots
+            .iter()
+            .filter_map(|s| s.parent_id.as_deref())
+            .collect();
+
+        match self
+            .snapshots
+            .iter()
+            .position(|s| !depended_on.contains(s.id.as_str()))
+        {
+            Some(pos) => {
+                self.snapshots.remove(pos);
+            }
+            None => {
+                warn!(
+                    "Not pruning any snapshot: every one of the {} kept snapshots is a base \
+                     a surviving incremental still depends on",
+                    self.snapshots.len()
+                );
+            }
+        }
+    }
+
+    /// Apply a GFS-style [`RetentionPolicy`], deleting every snapshot not
+    /// in the computed keep-set, and return how many were deleted.
+    ///
+    /// `now` anchors the policy's time windows - pass `Utc::now()` for
+    /// real use; tests pass a fixed instant so bucket boundaries are
+    /// deterministic. A full snapshot that a kept incremental still
+    /// depends on (transitively, through however long its chain) is
+    /// always retained regardless of its own age, same as
+    /// [`Self::prune_if_needed`].
+    pub fn apply_retention(&mut self, policy: RetentionPolicy, now: DateTime<Utc>) -> usize {
+        let mut keep: HashSet<String> = HashSet::new();
+        Self::keep_newest_per_window(&self.snapshots, now, Duration::hours(1), policy.hourly, &mut keep);
+        Self::keep_newest_per_window(&self.snapshots, now, Duration::days(1), policy.daily, &mut keep);
+        Self::keep_newest_per_window(&self.snapshots, now, Duration::weeks(1), policy.weekly, &mut keep);
+
+        self.extend_keep_set_with_dependencies(&mut keep);
+
+        let before = self.snapshots.len();
+        self.snapshots.retain(|s| keep.contains(&s.id));
+        before - self.snapshots.len()
+    }
+
+    /// Bucket `snapshots` into consecutive `window`-wide windows counting
+    /// back from `now`, keep the newest snapshot in each of the first
+    /// `window_count` windows, and add its id to `keep`. A snapshot older
+    /// than `window_count` windows, or created after `now`, falls outside
+    /// this tier and is left alone.
+    fn keep_newest_per_window(
+        snapshots: &[Snapshot],
+        now: DateTime<Utc>,
+        window: Duration,
+        window_count: usize,
+        keep: &mut HashSet<String>,
+    ) {
+        if window_count == 0 {
+            return;
+        }
+
+        let window_secs = window.num_seconds().max(1);
+        let mut newest_per_window: HashMap<i64, &Snapshot> = HashMap::new();
+        for snapshot in snapshots {
+            let age_secs = (now - snapshot.created).num_seconds();
+            if age_secs < 0 {
+                continue;
+            }
+            let window_index = age_secs / window_secs;
+            if window_index as usize >= window_count {
+                continue;
+            }
+            newest_per_window
+                .entry(window_index)
+                .and_modify(|newest| {
+                    if snapshot.created > newest.created {
+                        *newest = snapshot;
+                    }
+                })
+                .or_insert(snapshot);
+        }
+
+        keep.extend(newest_per_window.into_values().map(|s| s.id.clone()));
+    }
+
+    /// Pull in every full snapshot transitively depended on by a snapshot
+    /// already in `keep` - pruning a base out from under a surviving
+    /// incremental would make it impossible to materialize.
+    fn extend_keep_set_with_dependencies(&self, keep: &mut HashSet<String>) {
+        let mut frontier: Vec<String> = keep.iter().cloned().collect();
+        while let Some(id) = frontier.pop() {
+            let parent_id = self.get(&id).and_then(|s| s.parent_id.clone());
+            if let Some(parent_id) = parent_id {
+                if keep.insert(parent_id.clone()) {
+                    frontier.push(parent_id);
+                }
+            }
+        }
+    }
+
     /// List all snapshots
     pub fn list(&self) -> &[Snapshot] {
         &self.snapshots
@@ -160,15 +529,123 @@ impl SnapshotManager {
     /// Encrypt and serialize all snapshots for storage
     pub fn export(&self) -> Result<Vec<u8>> {
         let data = bincode::serialize(&self.snapshots)?;
-        let encrypted = encrypt(&self.key, &data, b"snapshots")?;
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &self.key, &data, b"snapshots")?;
         Ok(encrypted.to_bytes())
     }
 
     /// Import snapshots from encrypted data
+    ///
+    /// Rejects the whole import, leaving the current snapshots untouched,
+    /// if any incremental snapshot's `parent_id` doesn't resolve within
+    /// the imported set - a partial chain can never be materialized.
     pub fn import(&mut self, data: &[u8]) -> Result<()> {
         let encrypted = EncryptedData::from_bytes(data)?;
         let decrypted = decrypt(&self.key, &encrypted, b"snapshots")?;
-        self.snapshots = bincode::deserialize(&decrypted)?;
+        let snapshots: Vec<Snapshot> = bincode::deserialize(&decrypted)?;
+        Self::validate_chain(&snapshots)?;
+        Self::verify_chain(&snapshots)?;
+        self.snapshots = snapshots;
+        Ok(())
+    }
+
+    /// Checks that every incremental snapshot's `parent_id` resolves to
+    /// another snapshot in `snapshots`, so a chain is never left with a
+    /// dangling base.
+    fn validate_chain(snapshots: &[Snapshot]) -> Result<()> {
+        let ids: HashSet<&str> = snapshots.iter().map(|s| s.id.as_str()).collect();
+        for snapshot in snapshots {
+            if snapshot.snapshot_type != SnapshotType::Incremental {
+                continue;
+            }
+            let parent_id = snapshot.parent_id.as_deref().ok_or_else(|| {
+                Error::SnapshotNotFound(format!(
+                    "incremental snapshot '{}' has no parent_id",
+                    snapshot.id
+                ))
+            })?;
+            if !ids.contains(parent_id) {
+                return Err(Error::SnapshotNotFound(format!(
+                    "incremental snapshot '{}' references missing base '{}'",
+                    snapshot.id, parent_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs [`Snapshot::verify`] over every snapshot in the chain,
+    /// rejecting the whole import if any one of them fails - a partially
+    /// corrupt chain can't materialize correctly anyway, so there's no
+    /// value in quarantining just the bad snapshot and keeping the rest.
+    fn verify_chain(snapshots: &[Snapshot]) -> Result<()> {
+        for snapshot in snapshots {
+            if let Err(mismatched) = snapshot.verify() {
+                return Err(Error::SnapshotCorruption(mismatched.len(), mismatched));
+            }
+        }
+        Ok(())
+    }
+
+    /// Export every snapshot as a single self-describing archive:
+    /// `"SNAP"` magic, a header-layout version byte, an [`ArchiveFormat`]
+    /// tag byte, an 8-byte little-endian payload length, then the
+    /// compressed-then-encrypted payload - in that order, so
+    /// [`Self::import_from`] never has to be told out of band which
+    /// compressor was used.
+    pub fn export_to<W: Write>(&self, format: ArchiveFormat, mut writer: W) -> Result<()> {
+        let serialized = bincode::serialize(&self.snapshots)?;
+        let compressed = format.compress(&serialized)?;
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &self.key, &compressed, b"snapshots")?;
+        let payload = encrypted.to_bytes();
+
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&[ARCHIVE_VERSION])?;
+        writer.write_all(&[format.tag()])?;
+        writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        Ok(())
+    }
+
+    /// Import snapshots from an archive written by [`Self::export_to`].
+    /// Same chain-validation guarantee as [`Self::import`]: a bad or
+    /// incomplete archive never partially overwrites the current
+    /// snapshots.
+    pub fn import_from<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(Error::Deserialization(
+                "not a snapshot archive: bad magic bytes".to_string(),
+            ));
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != ARCHIVE_VERSION {
+            return Err(Error::Deserialization(format!(
+                "unsupported snapshot archive version {}",
+                version[0]
+            )));
+        }
+
+        let mut format_tag = [0u8; 1];
+        reader.read_exact(&mut format_tag)?;
+        let format = ArchiveFormat::from_tag(format_tag[0])?;
+
+        let mut len_bytes = [0u8; 8];
+        reader.read_exact(&mut len_bytes)?;
+        let len = u64::from_le_bytes(len_bytes) as usize;
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload)?;
+
+        let encrypted = EncryptedData::from_bytes(&payload)?;
+        let decrypted = decrypt(&self.key, &encrypted, b"snapshots")?;
+        let serialized = format.decompress(&decrypted)?;
+        let snapshots: Vec<Snapshot> = bincode::deserialize(&serialized)?;
+        Self::validate_chain(&snapshots)?;
+        Self::verify_chain(&snapshots)?;
+
+        self.snapshots = snapshots;
         Ok(())
     }
 
@@ -278,4 +755,267 @@ mod tests {
         assert_eq!(manager2.list().len(), 1);
         assert_eq!(manager2.list()[0].name, "test");
     }
+
+    #[test]
+    fn test_materialize_replays_incremental_chain() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 10);
+
+        let base_id = manager
+            .create_snapshot("base".to_string(), None, || {
+                Ok(vec![test_inode(1, "a.txt"), test_inode(2, "b.txt")])
+            })
+            .unwrap()
+            .id
+            .clone();
+
+        let delta_id = manager
+            .create_incremental_snapshot("delta".to_string(), None, &base_id, || {
+                let deleted: HashSet<u64> = [2].into_iter().collect();
+                Ok((vec![test_inode(3, "c.txt")], deleted))
+            })
+            .unwrap()
+            .id
+            .clone();
+
+        let materialized = manager.materialize(&delta_id).unwrap();
+        assert_eq!(materialized.len(), 2);
+        assert!(materialized.contains_key(&1));
+        assert!(!materialized.contains_key(&2));
+        assert!(materialized.contains_key(&3));
+    }
+
+    #[test]
+    fn test_create_incremental_snapshot_rejects_unknown_base() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 10);
+
+        let result = manager.create_incremental_snapshot(
+            "delta".to_string(),
+            None,
+            "does-not-exist",
+            || Ok((vec![], HashSet::new())),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pruning_never_drops_a_base_still_depended_on() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 2);
+
+        let base_id = manager
+            .create_snapshot("base".to_string(), None, || {
+                Ok(vec![test_inode(1, "a.txt")])
+            })
+            .unwrap()
+            .id
+            .clone();
+
+        manager
+            .create_incremental_snapshot("delta1".to_string(), None, &base_id, || {
+                Ok((vec![test_inode(2, "b.txt")], HashSet::new()))
+            })
+            .unwrap();
+
+        // At capacity (2): a normal flat policy would now evict "base",
+        // but "delta1" still depends on it, so "base" must survive.
+        manager
+            .create_incremental_snapshot("delta2".to_string(), None, &base_id, || {
+                Ok((vec![test_inode(3, "c.txt")], HashSet::new()))
+            })
+            .unwrap();
+
+        assert!(manager.get_by_name("base").is_some());
+    }
+
+    #[test]
+    fn test_apply_retention_keeps_newest_per_window_and_drops_the_rest() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 100);
+        let now = Utc::now();
+
+        // Two snapshots in the same hourly window: only the newer
+        // survives the hourly tier. Neither has a daily/weekly tier to
+        // fall back on, so with hourly=1/daily=0/weekly=0 only it keeps.
+        manager
+            .create_snapshot("older-in-window".to_string(), None, || Ok(vec![]))
+            .unwrap();
+        manager.snapshots[0].created = now - Duration::minutes(40);
+
+        manager
+            .create_snapshot("newer-in-window".to_string(), None, || Ok(vec![]))
+            .unwrap();
+        manager.snapshots[1].created = now - Duration::minutes(10);
+
+        let policy = RetentionPolicy { hourly: 1, daily: 0, weekly: 0 };
+        let deleted = manager.apply_retention(policy, now);
+
+        assert_eq!(deleted, 1);
+        assert!(manager.get_by_name("older-in-window").is_none());
+        assert!(manager.get_by_name("newer-in-window").is_some());
+    }
+
+    #[test]
+    fn test_apply_retention_keeps_one_per_tier_across_wide_gaps() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 100);
+        let now = Utc::now();
+
+        manager
+            .create_snapshot("three-weeks-old".to_string(), None, || Ok(vec![]))
+            .unwrap();
+        manager.snapshots[0].created = now - Duration::weeks(3);
+
+        manager
+            .create_snapshot("two-days-old".to_string(), None, || Ok(vec![]))
+            .unwrap();
+        manager.snapshots[1].created = now - Duration::days(2);
+
+        manager
+            .create_snapshot("just-now".to_string(), None, || Ok(vec![]))
+            .unwrap();
+
+        // hourly covers "just-now", daily covers "two-days-old", weekly
+        // covers "three-weeks-old" - all three survive despite the gaps.
+        let policy = RetentionPolicy { hourly: 1, daily: 3, weekly: 4 };
+        let deleted = manager.apply_retention(policy, now);
+
+        assert_eq!(deleted, 0);
+        assert_eq!(manager.list().len(), 3);
+    }
+
+    #[test]
+    fn test_apply_retention_never_drops_a_base_a_kept_incremental_depends_on() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 100);
+        let now = Utc::now();
+
+        let base_id = manager
+            .create_snapshot("base".to_string(), None, || {
+                Ok(vec![test_inode(1, "a.txt")])
+            })
+            .unwrap()
+            .id
+            .clone();
+        let base_pos = manager.snapshots.iter().position(|s| s.id == base_id).unwrap();
+        manager.snapshots[base_pos].created = now - Duration::weeks(10);
+
+        manager
+            .create_incremental_snapshot("delta".to_string(), None, &base_id, || {
+                Ok((vec![test_inode(2, "b.txt")], HashSet::new()))
+            })
+            .unwrap();
+
+        // "base" is far outside every tier's window, but "delta" (just
+        // created) is kept by the hourly tier and still depends on it.
+        let policy = RetentionPolicy { hourly: 1, daily: 0, weekly: 0 };
+        let deleted = manager.apply_retention(policy, now);
+
+        assert_eq!(deleted, 0);
+        assert!(manager.get_by_name("base").is_some());
+        assert!(manager.get_by_name("delta").is_some());
+    }
+
+    #[test]
+    fn test_import_rejects_incremental_with_missing_parent() {
+        let key = test_key();
+
+        let mut orphan = Snapshot::new_incremental(
+            "orphan".to_string(),
+            None,
+            "does-not-exist".to_string(),
+        );
+        orphan.add_inode(&test_inode(1, "a.txt")).unwrap();
+        let data = bincode::serialize(&vec![orphan]).unwrap();
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &key, &data, b"snapshots").unwrap();
+
+        let mut manager2 = SnapshotManager::new(key, 10);
+        assert!(manager2.import(&encrypted.to_bytes()).is_err());
+        assert_eq!(manager2.list().len(), 0);
+    }
+
+    #[test]
+    fn test_export_to_import_from_round_trips_per_format() {
+        for format in [
+            ArchiveFormat::None,
+            ArchiveFormat::Gzip,
+            ArchiveFormat::Bzip2,
+            ArchiveFormat::Zstd,
+        ] {
+            let key = test_key();
+            let mut manager = SnapshotManager::new(key, 10);
+            manager
+                .create_snapshot("test".to_string(), None, || {
+                    Ok(vec![test_inode(1, "file.txt")])
+                })
+                .unwrap();
+
+            let mut archive = Vec::new();
+            manager.export_to(format, &mut archive).unwrap();
+
+            let mut manager2 = SnapshotManager::new(key, 10);
+            manager2.import_from(archive.as_slice()).unwrap();
+
+            assert_eq!(manager2.list().len(), 1, "format {:?}", format);
+            assert_eq!(manager2.list()[0].name, "test");
+        }
+    }
+
+    #[test]
+    fn test_import_from_rejects_bad_magic() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 10);
+        assert!(manager.import_from([0u8; 16].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_seal_then_verify_passes_on_untouched_snapshot() {
+        let mut snapshot = Snapshot::new("test".to_string(), None);
+        snapshot.add_inode(&test_inode(1, "file1.txt")).unwrap();
+        snapshot.add_inode(&test_inode(2, "file2.txt")).unwrap();
+        snapshot.seal();
+
+        assert!(snapshot.content_hash.is_some());
+        assert_eq!(snapshot.leaf_hashes.len(), 2);
+        assert!(snapshot.verify().is_ok());
+    }
+
+    #[test]
+    fn test_verify_reports_the_tampered_inode() {
+        let mut snapshot = Snapshot::new("test".to_string(), None);
+        snapshot.add_inode(&test_inode(1, "file1.txt")).unwrap();
+        snapshot.add_inode(&test_inode(2, "file2.txt")).unwrap();
+        snapshot.seal();
+
+        snapshot.inodes.get_mut(&2).unwrap().push(0xff);
+
+        assert_eq!(snapshot.verify(), Err(vec![2]));
+    }
+
+    #[test]
+    fn test_unsealed_snapshot_verifies_ok() {
+        let mut snapshot = Snapshot::new("test".to_string(), None);
+        snapshot.add_inode(&test_inode(1, "file1.txt")).unwrap();
+        assert!(snapshot.verify().is_ok());
+    }
+
+    #[test]
+    fn test_import_rejects_snapshot_with_corrupted_inode() {
+        let key = test_key();
+        let mut manager = SnapshotManager::new(key, 10);
+
+        manager
+            .create_snapshot("test".to_string(), None, || {
+                Ok(vec![test_inode(1, "file.txt")])
+            })
+            .unwrap();
+        manager.snapshots[0].inodes.get_mut(&1).unwrap().push(0xff);
+
+        let exported = manager.export().unwrap();
+
+        let mut manager2 = SnapshotManager::new(key, 10);
+        assert!(manager2.import(&exported).is_err());
+        assert_eq!(manager2.list().len(), 0);
+    }
 }
@@ -0,0 +1,521 @@
+//! On-disk persistence for whole-tree snapshots.
+//!
+//! A snapshot's body (the serialized, encrypted [`Snapshot`] - the full
+//! inode hierarchy, each file's manifest carrying its ordered
+//! content-addressed chunk hashes) is written as a single object under
+//! `<data_dir>/snapshots/objects`, named by the BLAKE3 hash of its
+//! ciphertext via [`LooseSnapshotWriter`]/[`LooseSnapshotReader`]. Taking a
+//! snapshot never copies chunk data - chunks are already content-addressed
+//! on the backend, so the manifest is the only new bytes written, and two
+//! snapshots that share unchanged files share the same chunk ids too.
+//!
+//! Cheap listing metadata (id, name, timestamps, size) is kept separately
+//! in [`MetadataStore`] under `snapshot_meta:<id>` so `tgcryptfs snapshots`
+//! doesn't have to load and decrypt every snapshot body just to print a
+//! summary.
+//!
+//! Chunks a snapshot references are protected from the live tree's own
+//! [`MetadataStore::decrement_chunk_ref`] deletions by the same ref-count
+//! [`MetadataStore`] already keeps for dedup: [`SnapshotStore::create`]
+//! bumps the count for every chunk the new snapshot points at (the
+//! snapshot itself now holds a reference), and [`SnapshotStore::delete`]
+//! drops those references back down, freeing anything that reaches zero.
+//! [`SnapshotStore::gc`] is the mark-and-sweep backstop for drift between
+//! those counts and reality (e.g. after a crash mid-operation).
+
+use super::{LooseSnapshotReader, LooseSnapshotWriter, Snapshot, SnapshotReader, SnapshotWriter};
+use crate::chunk::ChunkId;
+use crate::crypto::{decrypt, encrypt, Algorithm, EncryptedData, KEY_SIZE};
+use crate::error::{Error, Result};
+use crate::metadata::{Inode, MetadataStore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Root inode every snapshot is walked from - the same inode number
+/// [`MetadataStore::init_root`] assigns the filesystem root.
+const ROOT_INO: u64 = 1;
+
+/// `MetadataStore` key prefix a snapshot's [`SnapshotInfo`] is filed
+/// under, so every registered snapshot can be enumerated with
+/// [`MetadataStore::scan_metadata_prefix`] without touching its body.
+const SNAPSHOT_META_PREFIX: &str = "snapshot_meta:";
+
+/// Listing metadata for one snapshot - cheap to load, since it's the only
+/// thing `tgcryptfs snapshots` needs to print a summary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created: DateTime<Utc>,
+    /// Total encrypted size of the chunks this snapshot's inodes
+    /// reference, deduplicated by content id.
+    pub size: u64,
+    /// Content id of the encrypted snapshot body under `objects/`.
+    object_id: ChunkId,
+}
+
+/// How a path differs between two [`Snapshot`]s, as computed by
+/// [`SnapshotStore::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffType {
+    /// Present in the later snapshot but not the earlier one.
+    Add,
+    /// Present in both, but its size, mtime, or chunk list differs.
+    Mod,
+    /// Present in the earlier snapshot but not the later one.
+    Del,
+}
+
+/// Every inode in `snapshot`, keyed by ino - lets [`path_of`] walk a
+/// `(parent, name)` chain back to the root without re-touching the live
+/// [`MetadataStore`].
+fn inode_map(snapshot: &Snapshot) -> Result<HashMap<u64, Inode>> {
+    Ok(snapshot.all_inodes()?.into_iter().map(|inode| (inode.ino, inode)).collect())
+}
+
+/// Reconstruct `ino`'s full path by walking `map`'s parent chain up to the
+/// root (an inode that is its own parent).
+fn path_of(map: &HashMap<u64, Inode>, mut ino: u64) -> PathBuf {
+    let mut components = Vec::new();
+    while let Some(inode) = map.get(&ino) {
+        if inode.parent == ino {
+            break;
+        }
+        components.push(inode.name.clone());
+        ino = inode.parent;
+    }
+    components.reverse();
+    components.into_iter().collect()
+}
+
+/// Whether two inodes known to share a `(parent, name)` key still describe
+/// the same content - same size, mtime, and ordered chunk list. Anything
+/// else (permissions, uid/gid, ...) isn't a content change as far as
+/// [`SnapshotStore::diff`] is concerned.
+fn content_differs(a: &Inode, b: &Inode) -> bool {
+    let chunk_ids = |inode: &Inode| -> Vec<ChunkId> {
+        inode
+            .manifest
+            .as_ref()
+            .map(|m| m.chunks.iter().map(|c| c.id.clone()).collect())
+            .unwrap_or_default()
+    };
+
+    a.attrs.size != b.attrs.size || a.attrs.mtime != b.attrs.mtime || chunk_ids(a) != chunk_ids(b)
+}
+
+/// Recursively collects every inode reachable from `root`, depth-first.
+fn walk_tree(metadata: &MetadataStore, root: u64) -> Result<Vec<Inode>> {
+    let mut collected = Vec::new();
+    let mut stack = vec![root];
+    while let Some(ino) = stack.pop() {
+        let inode = match metadata.get_inode(ino)? {
+            Some(inode) => inode,
+            None => continue,
+        };
+        if inode.is_dir() {
+            for child in metadata.get_children(ino)? {
+                stack.push(child.ino);
+            }
+        }
+        collected.push(inode);
+    }
+    Ok(collected)
+}
+
+/// Persists and restores whole-tree [`Snapshot`]s for one mount's
+/// `data_dir`.
+pub struct SnapshotStore {
+    objects_dir: PathBuf,
+    key: [u8; KEY_SIZE],
+}
+
+impl SnapshotStore {
+    /// Open (creating if necessary) the snapshot object store under
+    /// `data_dir`, encrypting/decrypting bodies with `key` - callers pass
+    /// the same metadata key the rest of the mount's metadata is
+    /// encrypted with, since a snapshot is just a backup of that metadata.
+    pub fn new(data_dir: &Path, key: [u8; KEY_SIZE]) -> Result<Self> {
+        Ok(SnapshotStore { objects_dir: data_dir.join("snapshots").join("objects"), key })
+    }
+
+    /// Walk the live tree from the root inode, build a [`Snapshot`] from
+    /// it, and persist it: the encrypted body as a content-addressed
+    /// object, and its [`SnapshotInfo`] under `snapshot_meta:<id>` in
+    /// `metadata`.
+    pub fn create(
+        &self,
+        metadata: &MetadataStore,
+        name: String,
+        description: Option<String>,
+    ) -> Result<SnapshotInfo> {
+        let mut snapshot = Snapshot::new(name, description.clone());
+        for inode in walk_tree(metadata, ROOT_INO)? {
+            snapshot.add_inode(&inode)?;
+        }
+
+        let size = self.stored_size(&snapshot)?;
+        for (chunk_id, message_id) in self.remote_chunks(&snapshot)? {
+            metadata.save_chunk_ref(&chunk_id, message_id)?;
+        }
+        let object_id = self.write_body(&snapshot)?;
+
+        let info = SnapshotInfo {
+            id: snapshot.id.clone(),
+            name: snapshot.name.clone(),
+            description,
+            created: snapshot.created,
+            size,
+            object_id,
+        };
+        metadata.save_metadata(&Self::meta_key(&info.id), &bincode::serialize(&info)?)?;
+        Ok(info)
+    }
+
+    /// Every registered snapshot's listing metadata, newest first.
+    pub fn list(&self, metadata: &MetadataStore) -> Result<Vec<SnapshotInfo>> {
+        let mut infos = Vec::new();
+        for (_, data) in metadata.scan_metadata_prefix(SNAPSHOT_META_PREFIX)? {
+            infos.push(bincode::deserialize::<SnapshotInfo>(&data)?);
+        }
+        infos.sort_by(|a, b| b.created.cmp(&a.created));
+        Ok(infos)
+    }
+
+    /// Look up a snapshot's listing metadata by id or name.
+    pub fn find(&self, metadata: &MetadataStore, id_or_name: &str) -> Result<Option<SnapshotInfo>> {
+        Ok(self.list(metadata)?.into_iter().find(|s| s.id == id_or_name || s.name == id_or_name))
+    }
+
+    /// Load the full snapshot body (inode hierarchy) `info` points at.
+    pub fn load(&self, info: &SnapshotInfo) -> Result<Snapshot> {
+        let reader = LooseSnapshotReader::new(&self.objects_dir);
+        let body = reader.read_object(&info.object_id)?;
+        let encrypted = EncryptedData::from_bytes(&body)?;
+        let plaintext = decrypt(&self.key, &encrypted, b"snapshot")?;
+        Snapshot::deserialize(&plaintext)
+    }
+
+    /// Remove a snapshot's listing entry and drop the chunk references it
+    /// was holding, returning the Telegram message ids that reached zero
+    /// references and should be deleted from the backend. Its object body
+    /// is left in place under `objects/` - it's unreachable once the
+    /// listing entry is gone, and [`Self::gc`] sweeps it up along with any
+    /// chunks this snapshot was the last to reference.
+    pub fn delete(&self, metadata: &MetadataStore, id: &str) -> Result<Vec<i32>> {
+        let info = match self.find(metadata, id)? {
+            Some(info) => info,
+            None => return Ok(Vec::new()),
+        };
+        let snapshot = self.load(&info)?;
+
+        let mut freed = Vec::new();
+        for (chunk_id, _) in self.remote_chunks(&snapshot)? {
+            if let Some(message_id) = metadata.decrement_chunk_ref(&chunk_id)? {
+                freed.push(message_id);
+            }
+        }
+
+        metadata.delete_metadata(&Self::meta_key(&info.id))?;
+        Ok(freed)
+    }
+
+    /// Mark-and-sweep garbage collection: the "mark" phase is the union of
+    /// `live_chunk_ids` (the current, live tree's referenced chunks) and
+    /// every remaining snapshot's referenced chunks; the "sweep" phase
+    /// reclaims every chunk reference in `metadata` outside that set.
+    /// Returns the Telegram message ids freed, so the caller can delete
+    /// them from the backend.
+    pub fn gc(&self, metadata: &MetadataStore, mut live_chunk_ids: HashSet<ChunkId>) -> Result<Vec<i32>> {
+        for info in self.list(metadata)? {
+            live_chunk_ids.extend(self.load(&info)?.referenced_chunk_ids()?);
+        }
+
+        let mut freed = Vec::new();
+        for chunk_id in metadata.list_chunk_ids()? {
+            if !live_chunk_ids.contains(&ChunkId::from(chunk_id.clone())) {
+                if let Some(message_id) = metadata.get_chunk_ref(&chunk_id)? {
+                    metadata.remove_chunk_ref(&chunk_id)?;
+                    freed.push(message_id);
+                }
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Structural diff between two previously taken snapshots: every path
+    /// that was added, removed, or changed content going from `a` to `b`.
+    /// Paths are matched by `(parent, name)` rather than `ino`, so a path
+    /// that was deleted and a different file later created at the same
+    /// name is still reported as a single `Mod` rather than a `Del`+`Add`
+    /// pair - the same identity rule [`HardLinkStore::diff`](crate::metadata::HardLinkStore::diff)
+    /// uses for paths, applied here to whole inodes.
+    pub fn diff(
+        &self,
+        metadata: &MetadataStore,
+        a: &str,
+        b: &str,
+    ) -> Result<Vec<(PathBuf, DiffType)>> {
+        let info_a = self.find(metadata, a)?.ok_or_else(|| Error::SnapshotNotFound(a.to_string()))?;
+        let info_b = self.find(metadata, b)?.ok_or_else(|| Error::SnapshotNotFound(b.to_string()))?;
+        let snapshot_a = self.load(&info_a)?;
+        let snapshot_b = self.load(&info_b)?;
+
+        let map_a = inode_map(&snapshot_a)?;
+        let map_b = inode_map(&snapshot_b)?;
+
+        let keyed = |map: &HashMap<u64, Inode>| -> HashMap<(u64, String), u64> {
+            map.values().map(|inode| ((inode.parent, inode.name.clone()), inode.ino)).collect()
+        };
+        let keys_a = keyed(&map_a);
+        let keys_b = keyed(&map_b);
+
+        let mut diffs = Vec::new();
+        for (key, &ino_a) in &keys_a {
+            match keys_b.get(key) {
+                Some(&ino_b) => {
+                    if content_differs(&map_a[&ino_a], &map_b[&ino_b]) {
+                        diffs.push((path_of(&map_b, ino_b), DiffType::Mod));
+                    }
+                }
+                None => diffs.push((path_of(&map_a, ino_a), DiffType::Del)),
+            }
+        }
+        for (key, &ino_b) in &keys_b {
+            if !keys_a.contains_key(key) {
+                diffs.push((path_of(&map_b, ino_b), DiffType::Add));
+            }
+        }
+
+        Ok(diffs)
+    }
+
+    fn meta_key(id: &str) -> String {
+        format!("{SNAPSHOT_META_PREFIX}{id}")
+    }
+
+    fn write_body(&self, snapshot: &Snapshot) -> Result<ChunkId> {
+        let plaintext = snapshot.serialize()?;
+        let encrypted = encrypt(Algorithm::Aes256Gcm, &self.key, &plaintext, b"snapshot")?;
+        let body = encrypted.to_bytes();
+        let object_id = ChunkId::from(blake3::hash(&body).to_hex().to_string());
+
+        let mut writer = LooseSnapshotWriter::new(&self.objects_dir)?;
+        writer.write_object(&object_id, &body)?;
+        writer.finalize()?;
+        Ok(object_id)
+    }
+
+    /// Total encrypted size of `snapshot`'s referenced chunks, deduplicated
+    /// by content id so a file shared across versions is only counted once.
+    fn stored_size(&self, snapshot: &Snapshot) -> Result<u64> {
+        let mut sizes = std::collections::HashMap::new();
+        for inode in snapshot.all_inodes()? {
+            if let Some(manifest) = &inode.manifest {
+                for chunk in &manifest.chunks {
+                    sizes.insert(chunk.id.clone(), chunk.size);
+                }
+            }
+        }
+        Ok(sizes.values().sum())
+    }
+
+    /// `snapshot`'s referenced chunks that are backed by a Telegram
+    /// message (inline chunks carry their own bytes and never have a
+    /// ref-counted entry in `metadata`), deduplicated by content id.
+    fn remote_chunks(&self, snapshot: &Snapshot) -> Result<Vec<(ChunkId, i32)>> {
+        let mut chunks = std::collections::HashMap::new();
+        for inode in snapshot.all_inodes()? {
+            if let Some(manifest) = &inode.manifest {
+                for chunk in &manifest.chunks {
+                    if let Some(message_id) = chunk.message_id() {
+                        chunks.insert(chunk.id.clone(), message_id);
+                    }
+                }
+            }
+        }
+        Ok(chunks.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Inode;
+    use rand::RngCore;
+    use tempfile::tempdir;
+
+    fn test_key() -> [u8; KEY_SIZE] {
+        let mut key = [0u8; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut key);
+        key
+    }
+
+    #[test]
+    fn test_create_list_find_and_load_round_trip() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        let file = Inode::new_file(2, ROOT_INO, "file.txt".to_string(), 1000, 1000, 0o644);
+        metadata.save_inode(&file).unwrap();
+
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+
+        let info = store.create(&metadata, "snap1".to_string(), Some("first".to_string())).unwrap();
+
+        let listed = store.list(&metadata).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, info.id);
+
+        let found = store.find(&metadata, "snap1").unwrap().unwrap();
+        assert_eq!(found.id, info.id);
+        let found_by_id = store.find(&metadata, &info.id).unwrap().unwrap();
+        assert_eq!(found_by_id.id, info.id);
+
+        let loaded = store.load(&info).unwrap();
+        let inos: Vec<u64> = loaded.all_inodes().unwrap().iter().map(|i| i.ino).collect();
+        assert!(inos.contains(&2));
+        assert!(inos.contains(&ROOT_INO));
+    }
+
+    #[test]
+    fn test_delete_removes_from_listing() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+
+        let info = store.create(&metadata, "snap1".to_string(), None).unwrap();
+        store.delete(&metadata, &info.id).unwrap();
+
+        assert!(store.list(&metadata).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_create_and_delete_manage_chunk_ref_counts() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        metadata.save_chunk_ref("shared-chunk", 7).unwrap();
+
+        let mut file = Inode::new_file(2, ROOT_INO, "file.txt".to_string(), 1000, 1000, 0o644);
+        let mut manifest = crate::chunk::ChunkManifest::new(1);
+        manifest.chunks.push(crate::chunk::ChunkRef {
+            id: ChunkId::from("shared-chunk".to_string()),
+            size: 10,
+            payload: crate::chunk::ChunkPayload::Remote { message_id: 7 },
+            offset: 0,
+            original_size: 10,
+            compression: crate::chunk::CompressionAlgo::None,
+        });
+        file.manifest = Some(manifest);
+        metadata.save_inode(&file).unwrap();
+
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+        let info = store.create(&metadata, "snap1".to_string(), None).unwrap();
+
+        // The live tree's own decrement (e.g. the file got overwritten)
+        // must not delete a chunk the snapshot still references.
+        assert!(metadata.decrement_chunk_ref("shared-chunk").unwrap().is_none());
+        assert!(metadata.get_chunk_ref("shared-chunk").unwrap().is_some());
+
+        // Once the snapshot is deleted too, the last reference goes with it.
+        let freed = store.delete(&metadata, &info.id).unwrap();
+        assert_eq!(freed, vec![7]);
+        assert!(metadata.get_chunk_ref("shared-chunk").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_gc_sweeps_chunks_outside_live_set_and_snapshots() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        metadata.save_chunk_ref("kept-by-live-tree", 1).unwrap();
+        metadata.save_chunk_ref("kept-by-snapshot", 2).unwrap();
+        metadata.save_chunk_ref("orphaned", 3).unwrap();
+
+        let mut file = Inode::new_file(2, ROOT_INO, "file.txt".to_string(), 1000, 1000, 0o644);
+        let mut manifest = crate::chunk::ChunkManifest::new(1);
+        manifest.chunks.push(crate::chunk::ChunkRef {
+            id: ChunkId::from("kept-by-snapshot".to_string()),
+            size: 10,
+            payload: crate::chunk::ChunkPayload::Remote { message_id: 2 },
+            offset: 0,
+            original_size: 10,
+            compression: crate::chunk::CompressionAlgo::None,
+        });
+        file.manifest = Some(manifest);
+        metadata.save_inode(&file).unwrap();
+
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+        store.create(&metadata, "snap1".to_string(), None).unwrap();
+
+        // The live file is now overwritten with no chunks, so only the
+        // snapshot still remembers "kept-by-snapshot".
+        let mut live_file = Inode::new_file(2, ROOT_INO, "file.txt".to_string(), 1000, 1000, 0o644);
+        live_file.manifest = Some(crate::chunk::ChunkManifest::new(2));
+        metadata.save_inode(&live_file).unwrap();
+
+        let mut live_ids = HashSet::new();
+        live_ids.insert(ChunkId::from("kept-by-live-tree".to_string()));
+
+        let freed = store.gc(&metadata, live_ids).unwrap();
+        assert_eq!(freed, vec![3]);
+        assert!(metadata.get_chunk_ref("orphaned").unwrap().is_none());
+        assert!(metadata.get_chunk_ref("kept-by-live-tree").unwrap().is_some());
+        assert!(metadata.get_chunk_ref("kept-by-snapshot").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_diff_reports_add_mod_and_del() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+
+        let mut unchanged = Inode::new_file(2, ROOT_INO, "unchanged.txt".to_string(), 1000, 1000, 0o644);
+        unchanged.attrs.size = 10;
+        let mut to_modify = Inode::new_file(3, ROOT_INO, "modified.txt".to_string(), 1000, 1000, 0o644);
+        to_modify.attrs.size = 10;
+        let to_delete = Inode::new_file(4, ROOT_INO, "deleted.txt".to_string(), 1000, 1000, 0o644);
+        metadata.save_inode(&unchanged).unwrap();
+        metadata.save_inode(&to_modify).unwrap();
+        metadata.save_inode(&to_delete).unwrap();
+
+        let before = store.create(&metadata, "before".to_string(), None).unwrap();
+
+        metadata.delete_inode(4).unwrap();
+        to_modify.attrs.size = 20;
+        metadata.save_inode(&to_modify).unwrap();
+        let added = Inode::new_file(5, ROOT_INO, "added.txt".to_string(), 1000, 1000, 0o644);
+        metadata.save_inode(&added).unwrap();
+
+        let after = store.create(&metadata, "after".to_string(), None).unwrap();
+
+        let mut diffs = store.diff(&metadata, &before.id, &after.id).unwrap();
+        diffs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            diffs,
+            vec![
+                (PathBuf::from("added.txt"), DiffType::Add),
+                (PathBuf::from("deleted.txt"), DiffType::Del),
+                (PathBuf::from("modified.txt"), DiffType::Mod),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_rejects_unknown_snapshot() {
+        let key = test_key();
+        let metadata = MetadataStore::in_memory(key).unwrap();
+        let dir = tempdir().unwrap();
+        let store = SnapshotStore::new(dir.path(), key).unwrap();
+
+        let info = store.create(&metadata, "snap1".to_string(), None).unwrap();
+        assert!(store.diff(&metadata, &info.id, "does-not-exist").is_err());
+    }
+}
@@ -0,0 +1,390 @@
+//! Object writers/readers backing the snapshot subsystem
+//!
+//! A snapshot is a bag of content-addressed objects - chunk bytes and
+//! encoded manifests - plus enough of an index to find them again. Two
+//! backends are provided: a "loose" one that writes each object as its own
+//! file (simple, good for incremental backups), and a "packed" one that
+//! concatenates everything into a single file with a trailing offset
+//! table (fewer files, good for shipping a snapshot as one blob).
+
+use crate::chunk::{ChunkId, ChunkManifest};
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes content-addressed snapshot objects to a backing store.
+pub trait SnapshotWriter {
+    /// Write (or overwrite) a single object, keyed by its content id.
+    fn write_object(&mut self, id: &ChunkId, data: &[u8]) -> Result<()>;
+
+    /// Flush any trailing index and finish writing.
+    fn finalize(&mut self) -> Result<()>;
+}
+
+/// Reads content-addressed snapshot objects back out of a backing store.
+pub trait SnapshotReader {
+    /// Read a single object by its content id.
+    fn read_object(&self, id: &ChunkId) -> Result<Vec<u8>>;
+}
+
+/// Writes each object as its own file, named by its content id, under a
+/// directory.
+pub struct LooseSnapshotWriter {
+    dir: PathBuf,
+}
+
+impl LooseSnapshotWriter {
+    /// Create a writer rooted at `dir`, creating it if necessary.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(LooseSnapshotWriter { dir })
+    }
+
+    fn object_path(&self, id: &ChunkId) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+}
+
+impl SnapshotWriter for LooseSnapshotWriter {
+    fn write_object(&mut self, id: &ChunkId, data: &[u8]) -> Result<()> {
+        fs::write(self.object_path(id), data)?;
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        // Nothing to flush: every object is already its own file.
+        Ok(())
+    }
+}
+
+/// Reads objects previously written by [`LooseSnapshotWriter`].
+pub struct LooseSnapshotReader {
+    dir: PathBuf,
+}
+
+impl LooseSnapshotReader {
+    /// Open a loose snapshot directory for reading.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LooseSnapshotReader { dir: dir.into() }
+    }
+}
+
+impl SnapshotReader for LooseSnapshotReader {
+    fn read_object(&self, id: &ChunkId) -> Result<Vec<u8>> {
+        let path = self.dir.join(id.to_string());
+        fs::read(&path).map_err(|_| Error::ChunkNotFound(id.to_string()))
+    }
+}
+
+/// One entry in a packed snapshot's trailing offset table.
+#[derive(Debug, Clone)]
+struct PackedEntry {
+    id: ChunkId,
+    offset: u64,
+    len: u64,
+}
+
+/// Concatenates objects into a single file, followed by a `[(ChunkId,
+/// offset, len)]` table and an 8-byte little-endian table length footer so
+/// readers can seek directly to any object without scanning the whole
+/// file.
+pub struct PackedSnapshotWriter {
+    file: File,
+    offset: u64,
+    entries: Vec<PackedEntry>,
+}
+
+impl PackedSnapshotWriter {
+    /// Create a new packed snapshot at `path`, truncating any existing file.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(PackedSnapshotWriter {
+            file,
+            offset: 0,
+            entries: Vec::new(),
+        })
+    }
+}
+
+impl SnapshotWriter for PackedSnapshotWriter {
+    fn write_object(&mut self, id: &ChunkId, data: &[u8]) -> Result<()> {
+        self.file.write_all(data)?;
+        self.entries.push(PackedEntry {
+            id: id.clone(),
+            offset: self.offset,
+            len: data.len() as u64,
+        });
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        let table_start = self.file.stream_position()?;
+        for entry in &self.entries {
+            let id_bytes = entry.id.to_string().into_bytes();
+            self.file.write_all(&(id_bytes.len() as u32).to_le_bytes())?;
+            self.file.write_all(&id_bytes)?;
+            self.file.write_all(&entry.offset.to_le_bytes())?;
+            self.file.write_all(&entry.len.to_le_bytes())?;
+        }
+        let table_len = self.file.stream_position()? - table_start;
+        self.file.write_all(&table_len.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads objects back out of a file written by [`PackedSnapshotWriter`] by
+/// loading the trailing offset table once, then seeking directly to each
+/// requested object.
+pub struct PackedSnapshotReader {
+    file: std::sync::Mutex<File>,
+    index: HashMap<ChunkId, (u64, u64)>,
+}
+
+impl PackedSnapshotReader {
+    /// Open a packed snapshot file and load its offset table.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let end = file.seek(SeekFrom::End(0))?;
+
+        if end < 8 {
+            return Err(Error::Deserialization(
+                "packed snapshot too small to contain an offset table".to_string(),
+            ));
+        }
+
+        file.seek(SeekFrom::End(-8))?;
+        let mut footer = [0u8; 8];
+        file.read_exact(&mut footer)?;
+        let table_len = u64::from_le_bytes(footer);
+
+        let table_start = end - 8 - table_len;
+        file.seek(SeekFrom::Start(table_start))?;
+        let mut table_bytes = vec![0u8; table_len as usize];
+        file.read_exact(&mut table_bytes)?;
+
+        let mut index = HashMap::new();
+        let mut cursor = 0usize;
+        while cursor < table_bytes.len() {
+            let id_len =
+                u32::from_le_bytes(table_bytes[cursor..cursor + 4].try_into().unwrap()) as usize;
+            cursor += 4;
+            let id_str = String::from_utf8_lossy(&table_bytes[cursor..cursor + id_len]).into_owned();
+            cursor += id_len;
+            let offset =
+                u64::from_le_bytes(table_bytes[cursor..cursor + 8].try_into().unwrap());
+            cursor += 8;
+            let len = u64::from_le_bytes(table_bytes[cursor..cursor + 8].try_into().unwrap());
+            cursor += 8;
+            index.insert(ChunkId::from(id_str), (offset, len));
+        }
+
+        Ok(PackedSnapshotReader {
+            file: std::sync::Mutex::new(file),
+            index,
+        })
+    }
+}
+
+impl SnapshotReader for PackedSnapshotReader {
+    fn read_object(&self, id: &ChunkId) -> Result<Vec<u8>> {
+        let (offset, len) = self
+            .index
+            .get(id)
+            .ok_or_else(|| Error::ChunkNotFound(id.to_string()))?;
+
+        let mut file = self.file.lock().map_err(|_| {
+            Error::Internal("packed snapshot reader mutex poisoned".to_string())
+        })?;
+        file.seek(SeekFrom::Start(*offset))?;
+        let mut buf = vec![0u8; *len as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Keep only the most recent `keep` manifest versions per file so a
+/// snapshot doesn't grow unbounded when a file has been rewritten many
+/// times. `manifests` is mutated in place; each file's `Vec` is assumed to
+/// be sorted ascending by `ChunkManifest::version`.
+pub fn bound_manifest_versions(manifests: &mut HashMap<String, Vec<ChunkManifest>>, keep: usize) {
+    if keep == 0 {
+        return;
+    }
+    for versions in manifests.values_mut() {
+        if versions.len() > keep {
+            let excess = versions.len() - keep;
+            versions.drain(0..excess);
+        }
+    }
+}
+
+/// Reassembles a file's chunks as they arrive in arbitrary order,
+/// verifying each one against its BLAKE3 content hash and only yielding
+/// the reconstructed file once every chunk in the manifest has arrived.
+pub struct ChunkAssembler {
+    manifest: ChunkManifest,
+    received: HashMap<ChunkId, Vec<u8>>,
+}
+
+impl ChunkAssembler {
+    /// Start assembling the file described by `manifest`.
+    pub fn new(manifest: ChunkManifest) -> Self {
+        ChunkAssembler {
+            manifest,
+            received: HashMap::new(),
+        }
+    }
+
+    /// Accept a chunk's bytes, verifying them against the content id
+    /// before buffering them. Chunks may arrive in any order.
+    pub fn accept(&mut self, id: ChunkId, data: Vec<u8>) -> Result<()> {
+        let expected = blake3::hash(&data).to_hex().to_string();
+        if expected != id.to_string() {
+            return Err(Error::ChunkVerificationFailed {
+                expected: id.to_string(),
+                got: expected,
+            });
+        }
+        self.received.insert(id, data);
+        Ok(())
+    }
+
+    /// Whether every chunk referenced by the manifest has arrived.
+    pub fn is_complete(&self) -> bool {
+        self.manifest
+            .chunks
+            .iter()
+            .all(|chunk| self.received.contains_key(&chunk.id))
+    }
+
+    /// Reconstruct the full file content in manifest order. Fails if any
+    /// chunk hasn't arrived yet.
+    pub fn finalize(&self) -> Result<Vec<u8>> {
+        if !self.is_complete() {
+            return Err(Error::Internal(
+                "cannot finalize manifest: missing chunks".to_string(),
+            ));
+        }
+
+        let mut out = Vec::with_capacity(self.manifest.total_size as usize);
+        for chunk in &self.manifest.chunks {
+            out.extend_from_slice(&self.received[&chunk.id]);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{ChunkPayload, ChunkRef, CompressionAlgo};
+    use tempfile::tempdir;
+
+    fn chunk_id_for(data: &[u8]) -> ChunkId {
+        ChunkId::from(blake3::hash(data).to_hex().to_string())
+    }
+
+    #[test]
+    fn test_loose_writer_reader_round_trip() {
+        let dir = tempdir().unwrap();
+        let mut writer = LooseSnapshotWriter::new(dir.path()).unwrap();
+        let id = chunk_id_for(b"hello world");
+        writer.write_object(&id, b"hello world").unwrap();
+        writer.finalize().unwrap();
+
+        let reader = LooseSnapshotReader::new(dir.path());
+        assert_eq!(reader.read_object(&id).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_packed_writer_reader_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("snapshot.pack");
+
+        let id_a = chunk_id_for(b"chunk-a");
+        let id_b = chunk_id_for(b"chunk-b-longer");
+
+        let mut writer = PackedSnapshotWriter::new(&path).unwrap();
+        writer.write_object(&id_a, b"chunk-a").unwrap();
+        writer.write_object(&id_b, b"chunk-b-longer").unwrap();
+        writer.finalize().unwrap();
+
+        let reader = PackedSnapshotReader::new(&path).unwrap();
+        assert_eq!(reader.read_object(&id_a).unwrap(), b"chunk-a");
+        assert_eq!(reader.read_object(&id_b).unwrap(), b"chunk-b-longer");
+    }
+
+    #[test]
+    fn test_chunk_assembler_out_of_order() {
+        let data_a = b"first-chunk".to_vec();
+        let data_b = b"second-chunk".to_vec();
+        let id_a = chunk_id_for(&data_a);
+        let id_b = chunk_id_for(&data_b);
+
+        let mut manifest = ChunkManifest::new(1);
+        manifest.total_size = (data_a.len() + data_b.len()) as u64;
+        manifest.chunks = vec![
+            ChunkRef {
+                id: id_a.clone(),
+                size: data_a.len() as u64,
+                payload: ChunkPayload::Remote { message_id: 1 },
+                offset: 0,
+                original_size: data_a.len() as u64,
+                compression: CompressionAlgo::None,
+            },
+            ChunkRef {
+                id: id_b.clone(),
+                size: data_b.len() as u64,
+                payload: ChunkPayload::Remote { message_id: 2 },
+                offset: data_a.len() as u64,
+                original_size: data_b.len() as u64,
+                compression: CompressionAlgo::None,
+            },
+        ];
+
+        let mut assembler = ChunkAssembler::new(manifest);
+        assert!(!assembler.is_complete());
+
+        // Arrives out of order: second chunk first.
+        assembler.accept(id_b, data_b.clone()).unwrap();
+        assert!(!assembler.is_complete());
+        assembler.accept(id_a, data_a.clone()).unwrap();
+        assert!(assembler.is_complete());
+
+        let mut expected = data_a;
+        expected.extend(data_b);
+        assert_eq!(assembler.finalize().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bound_manifest_versions_keeps_most_recent() {
+        let mut manifests = HashMap::new();
+        manifests.insert(
+            "file.txt".to_string(),
+            vec![
+                ChunkManifest::new(1),
+                ChunkManifest::new(2),
+                ChunkManifest::new(3),
+            ],
+        );
+
+        bound_manifest_versions(&mut manifests, 2);
+
+        let versions = &manifests["file.txt"];
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, 2);
+        assert_eq!(versions[1].version, 3);
+    }
+
+    #[test]
+    fn test_chunk_assembler_rejects_corrupt_chunk() {
+        let manifest = ChunkManifest::new(1);
+        let mut assembler = ChunkAssembler::new(manifest);
+        let bogus_id = ChunkId::from("not-the-real-hash".to_string());
+        assert!(assembler.accept(bogus_id, b"data".to_vec()).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! Telegram client implementation
 //!
 //! Uses grammers library to interact with Telegram API.
-//! All data is uploaded to "Saved Messages" for private storage.
+//! Data is uploaded to "Saved Messages" by default, or to a configured
+//! `TelegramConfig::default_target` channel/chat.
 
 use crate::config::TelegramConfig;
 use crate::error::{Error, Result};
@@ -11,11 +12,13 @@ use crate::telegram::{CHUNK_FILE_PREFIX, METADATA_FILE_PREFIX};
 use grammers_client::{Client, InputMessage, SignInError};
 use grammers_mtsender::{SenderPool, SenderPoolHandle};
 use grammers_session::storages::SqliteSession;
-use grammers_session::defs::PeerRef;
+use grammers_session::defs::{PackedChat, PeerRef};
 
+use futures::stream::{FuturesUnordered, StreamExt};
 use std::io::{BufRead, Cursor, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
@@ -50,13 +53,196 @@ impl PasswordToken {
     }
 }
 
+/// Session key the packed self-peer is stashed under, so a reconnect can
+/// reuse it instead of resolving `get_me()` again.
+const SELF_PEER_SESSION_KEY: &str = "tgcryptfs_self_peer";
+
+/// Session key the packed storage-target peer is stashed under, so a
+/// reconnect doesn't have to re-resolve the configured channel/chat.
+const STORAGE_PEER_SESSION_KEY: &str = "tgcryptfs_storage_peer";
+
+/// Classify a failed Telegram RPC call, turning `FLOOD_WAIT_X`/
+/// `SLOWMODE_WAIT_X` into [`Error::TelegramRateLimited`] (so the retry
+/// loop can sleep for exactly the server-mandated duration instead of
+/// burning a backoff attempt on it) and revoked-auth/stale-reference
+/// errors into their own non-retryable variants. Anything else falls back
+/// to `fallback`, which lets each call site keep its own error context
+/// (`TelegramUpload` vs. `TelegramDownload` vs. `TelegramClient`).
+fn classify_rpc_error(e: grammers_client::InvocationError, fallback: impl FnOnce(String) -> Error) -> Error {
+    if let grammers_client::InvocationError::Rpc(rpc) = &e {
+        if (rpc.name.starts_with("FLOOD_WAIT") || rpc.name.starts_with("SLOWMODE_WAIT"))
+            && rpc.value.is_some()
+        {
+            return Error::TelegramRateLimited {
+                seconds: rpc.value.unwrap(),
+            };
+        }
+
+        match rpc.name.as_str() {
+            "AUTH_KEY_UNREGISTERED" | "SESSION_REVOKED" | "USER_DEACTIVATED" => {
+                return Error::TelegramAuthRequired;
+            }
+            "FILE_REFERENCE_EXPIRED" => {
+                return Error::TelegramFileReferenceExpired;
+            }
+            _ => {}
+        }
+    }
+
+    fallback(e.to_string())
+}
+
+/// Issue one `upload.getFile` range request and hand back its offset
+/// alongside the bytes received, so the caller can slot the result back
+/// into place once every in-flight segment of a [`TelegramBackend::download_segmented`]
+/// call has resolved in whatever order the `SenderPool` finishes them.
+async fn fetch_segment(
+    client: &Client,
+    location: grammers_tl_types::enums::InputFileLocation,
+    offset: u64,
+    segment_size: u64,
+) -> std::result::Result<(u64, Vec<u8>), grammers_client::InvocationError> {
+    let result = client
+        .invoke(&grammers_tl_types::functions::upload::GetFile {
+            precise: false,
+            cdn_supported: false,
+            location,
+            offset: offset as i64,
+            limit: segment_size as i32,
+        })
+        .await?;
+
+    let bytes = match result {
+        grammers_tl_types::enums::upload::File::File(f) => f.bytes,
+        grammers_tl_types::enums::upload::File::CdnRedirect(_) => Vec::new(),
+    };
+
+    Ok((offset, bytes))
+}
+
+/// Local SQLite index mapping a chunk/metadata filename to the message it
+/// was last uploaded as, so [`TelegramBackend::list_chunks`] can serve a
+/// local lookup instead of walking the entire remote history on every
+/// call. Lives in a file adjacent to the session file, independent of any
+/// one connection, so it survives reconnects untouched.
+struct ChunkIndex {
+    conn: std::sync::Mutex<rusqlite::Connection>,
+}
+
+impl ChunkIndex {
+    fn open(path: &std::path::Path) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path)
+            .map_err(|e| Error::TelegramClient(format!("Failed to open chunk index: {}", e)))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS chunks (
+                filename TEXT PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                date INTEGER NOT NULL
+            )",
+            [],
+        )
+        .map_err(|e| Error::TelegramClient(format!("Failed to create chunk index table: {}", e)))?;
+
+        Ok(ChunkIndex { conn: std::sync::Mutex::new(conn) })
+    }
+
+    /// Record (or update) the message a filename now lives at.
+    fn upsert(&self, message: &TelegramMessage) -> Result<()> {
+        let filename = message.filename.as_deref().ok_or_else(|| {
+            Error::TelegramClient("Cannot index a message with no filename".to_string())
+        })?;
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "INSERT INTO chunks (filename, message_id, size, date) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(filename) DO UPDATE SET message_id = ?2, size = ?3, date = ?4",
+            rusqlite::params![filename, message.id, message.size as i64, message.date],
+        )
+        .map_err(|e| Error::TelegramClient(format!("Failed to index chunk '{}': {}", filename, e)))?;
+        Ok(())
+    }
+
+    /// Drop whatever entry currently points at `message_id`, e.g. after
+    /// `delete_message` removes it from Telegram.
+    fn remove_by_message_id(&self, message_id: i32) -> Result<()> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "DELETE FROM chunks WHERE message_id = ?1",
+            rusqlite::params![message_id],
+        )
+        .map_err(|e| Error::TelegramClient(format!("Failed to remove message {} from chunk index: {}", message_id, e)))?;
+        Ok(())
+    }
+
+    /// All indexed chunks, in local storage order.
+    fn list(&self) -> Result<Vec<TelegramMessage>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn
+            .prepare("SELECT message_id, filename, size, date FROM chunks ORDER BY message_id")
+            .map_err(|e| Error::TelegramClient(format!("Failed to query chunk index: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(TelegramMessage {
+                    id: row.get(0)?,
+                    filename: row.get(1)?,
+                    size: row.get::<_, i64>(2)? as u64,
+                    date: row.get(3)?,
+                })
+            })
+            .map_err(|e| Error::TelegramClient(format!("Failed to query chunk index: {}", e)))?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(row.map_err(|e| {
+                Error::TelegramClient(format!("Failed to read chunk index row: {}", e))
+            })?);
+        }
+        Ok(messages)
+    }
+
+    /// Replace the entire index with `messages` inside one transaction,
+    /// used by [`TelegramBackend::resync`] to repair drift after
+    /// out-of-band changes.
+    fn replace_all(&self, messages: &[TelegramMessage]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let txn = conn
+            .transaction()
+            .map_err(|e| Error::TelegramClient(format!("Failed to start resync transaction: {}", e)))?;
+
+        txn.execute("DELETE FROM chunks", [])
+            .map_err(|e| Error::TelegramClient(format!("Failed to clear chunk index: {}", e)))?;
+
+        for message in messages {
+            let Some(filename) = message.filename.as_deref() else {
+                continue;
+            };
+            txn.execute(
+                "INSERT INTO chunks (filename, message_id, size, date) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![filename, message.id, message.size as i64, message.date],
+            )
+            .map_err(|e| Error::TelegramClient(format!("Failed to index chunk '{}': {}", filename, e)))?;
+        }
+
+        txn.commit()
+            .map_err(|e| Error::TelegramClient(format!("Failed to commit resync: {}", e)))?;
+        Ok(())
+    }
+}
+
 /// Internal client state
 struct ClientState {
     client: Client,
-    #[allow(dead_code)]
     session: Arc<SqliteSession>,
     pool_handle: SenderPoolHandle,
     _pool_task: JoinHandle<()>,
+    /// `PeerRef` for "Saved Messages" (self), resolved once per connection
+    /// (from the session if cached, otherwise via `get_me()`) instead of
+    /// re-resolving it on every upload/download/list call.
+    self_peer: PeerRef,
+    /// `PeerRef` chunks are actually uploaded to/downloaded from/listed
+    /// from: `TelegramConfig::default_target` resolved once at connect
+    /// time, or `self_peer` when no target is configured.
+    storage_peer: PeerRef,
 }
 
 /// Telegram backend for storing and retrieving chunks
@@ -71,6 +257,13 @@ pub struct TelegramBackend {
     download_limiter: RateLimiter,
     /// Client state (when connected)
     client_state: Arc<RwLock<Option<ClientState>>>,
+    /// Background task pinging the connection on `TelegramConfig::keepalive_interval_secs`
+    /// and rebuilding `client_state` if it goes dead. `None` until `connect()` runs.
+    keepalive_task: RwLock<Option<JoinHandle<()>>>,
+    /// Local index of chunk/metadata filename -> message, opened on first
+    /// `connect()` and kept across reconnects since it's independent of
+    /// the remote connection.
+    chunk_index: RwLock<Option<Arc<ChunkIndex>>>,
 }
 
 impl TelegramBackend {
@@ -90,6 +283,8 @@ impl TelegramBackend {
             upload_limiter,
             download_limiter,
             client_state: Arc::new(RwLock::new(None)),
+            keepalive_task: RwLock::new(None),
+            chunk_index: RwLock::new(None),
         }
     }
 
@@ -112,6 +307,17 @@ impl TelegramBackend {
         }
     }
 
+    /// Path to the local chunk index, a SQLite file adjacent to the
+    /// session file.
+    fn index_path(&self) -> PathBuf {
+        self.session_path().with_extension("chunks.sqlite")
+    }
+
+    /// The open chunk index, if `connect()` has run.
+    async fn chunk_index(&self) -> Option<Arc<ChunkIndex>> {
+        self.chunk_index.read().await.clone()
+    }
+
     /// Connect to Telegram
     pub async fn connect(&self) -> Result<()> {
         let session_path = self.session_path();
@@ -135,18 +341,207 @@ impl TelegramBackend {
 
         let pool_task = tokio::spawn(runner.run());
 
+        let self_peer = Self::resolve_self_peer(&client, &session).await?;
+        let storage_peer =
+            Self::resolve_storage_peer(&client, &session, &self_peer, &self.config).await?;
+
         let state = ClientState {
             client,
             session,
             pool_handle: handle,
             _pool_task: pool_task,
+            self_peer,
+            storage_peer,
         };
 
         *self.client_state.write().await = Some(state);
         info!("Connected to Telegram");
+
+        let mut chunk_index = self.chunk_index.write().await;
+        if chunk_index.is_none() {
+            *chunk_index = Some(Arc::new(ChunkIndex::open(&self.index_path())?));
+        }
+        drop(chunk_index);
+
+        let mut keepalive_task = self.keepalive_task.write().await;
+        if let Some(old_task) = keepalive_task.take() {
+            old_task.abort();
+        }
+        *keepalive_task = Some(tokio::spawn(Self::run_keepalive(
+            Arc::clone(&self.client_state),
+            self.config.clone(),
+        )));
+
         Ok(())
     }
 
+    /// Background loop: every `keepalive_interval_secs`, ping the
+    /// connection while it's idle so a dropped `SenderPool` is noticed
+    /// quickly rather than on the next user-initiated operation. On a
+    /// failed ping, rebuild the connection from the existing `SqliteSession`
+    /// so no re-auth is needed; operations that call in concurrently just
+    /// await the write lock `rebuild_client_state` holds instead of seeing
+    /// "Not connected".
+    async fn run_keepalive(client_state: Arc<RwLock<Option<ClientState>>>, config: TelegramConfig) {
+        let interval = Duration::from_secs(config.keepalive_interval_secs.max(1));
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let ping = {
+                let state = client_state.read().await;
+                match state.as_ref() {
+                    Some(client_state) => {
+                        client_state
+                            .client
+                            .invoke(&grammers_tl_types::functions::Ping { ping_id: 0 })
+                            .await
+                    }
+                    // Backend was disconnected; nothing left to keep alive.
+                    None => return,
+                }
+            };
+
+            if let Err(e) = ping {
+                warn!("Keepalive ping failed, rebuilding Telegram connection: {}", e);
+                if let Err(e) = Self::rebuild_client_state(&client_state, &config).await {
+                    error!("Automatic reconnect failed: {}", e);
+                }
+            } else {
+                debug!("Keepalive ping ok");
+            }
+        }
+    }
+
+    /// Tear down and rebuild the `SenderPool`/`Client` in place, reusing the
+    /// existing `SqliteSession` so the new connection comes back already
+    /// authorized. Holds the write lock for the whole rebuild so concurrent
+    /// callers of `do_upload`/`do_download`/etc. block until it's done
+    /// rather than observing a torn-down state.
+    async fn rebuild_client_state(
+        client_state: &Arc<RwLock<Option<ClientState>>>,
+        config: &TelegramConfig,
+    ) -> Result<()> {
+        let mut guard = client_state.write().await;
+        let session = match guard.as_ref() {
+            Some(state) => Arc::clone(&state.session),
+            None => return Err(Error::TelegramClient("Not connected".to_string())),
+        };
+
+        // Drop the old pool/client first so the stale connection is closed
+        // before we open a new one against the same session file.
+        if let Some(old_state) = guard.take() {
+            old_state.pool_handle.quit();
+        }
+
+        let pool = SenderPool::new(Arc::clone(&session), config.api_id);
+        let client = Client::new(&pool);
+        let SenderPool { runner, handle, .. } = pool;
+        let pool_task = tokio::spawn(runner.run());
+
+        let self_peer = Self::resolve_self_peer(&client, &session).await?;
+        let storage_peer = Self::resolve_storage_peer(&client, &session, &self_peer, config).await?;
+
+        *guard = Some(ClientState {
+            client,
+            session,
+            pool_handle: handle,
+            _pool_task: pool_task,
+            self_peer,
+            storage_peer,
+        });
+
+        info!("Reconnected to Telegram");
+        Ok(())
+    }
+
+    /// Resolve the packed self-peer, reusing whatever `session` already has
+    /// cached under [`SELF_PEER_SESSION_KEY`] rather than calling
+    /// `get_me()` again once it has been resolved once.
+    async fn resolve_self_peer(client: &Client, session: &SqliteSession) -> Result<PeerRef> {
+        if let Some(bytes) = session.get_value(SELF_PEER_SESSION_KEY).map_err(|e| {
+            Error::TelegramClient(format!("Failed to read cached self peer: {}", e))
+        })? {
+            if let Ok(packed) = PackedChat::from_bytes(&bytes) {
+                debug!("Reusing cached self peer from session");
+                return Ok(PeerRef::from(packed));
+            }
+            warn!("Cached self peer in session was unreadable, re-resolving");
+        }
+
+        let me = client.get_me().await.map_err(|e| {
+            Error::TelegramClient(format!("Failed to get self: {}", e))
+        })?;
+        let packed = me.pack();
+
+        session
+            .set_value(SELF_PEER_SESSION_KEY, &packed.to_bytes())
+            .map_err(|e| Error::TelegramClient(format!("Failed to cache self peer: {}", e)))?;
+
+        Ok(PeerRef::from(packed))
+    }
+
+    /// Resolve the peer chunks should be stored against: the destination of
+    /// `config.default_target` (looked up in `config.targets`) if one is
+    /// configured, otherwise `self_peer` ("Saved Messages"). Like
+    /// `resolve_self_peer`, the packed result is cached in `session` under
+    /// [`STORAGE_PEER_SESSION_KEY`] so a reconnect doesn't re-resolve the
+    /// username.
+    async fn resolve_storage_peer(
+        client: &Client,
+        session: &SqliteSession,
+        self_peer: &PeerRef,
+        config: &TelegramConfig,
+    ) -> Result<PeerRef> {
+        let target = match config
+            .default_target
+            .as_deref()
+            .and_then(|name| config.targets.iter().find(|t| t.name == name))
+        {
+            Some(target) => target,
+            None => return Ok(self_peer.clone()),
+        };
+
+        if target.destination == "me" {
+            return Ok(self_peer.clone());
+        }
+
+        if let Some(bytes) = session.get_value(STORAGE_PEER_SESSION_KEY).map_err(|e| {
+            Error::TelegramClient(format!("Failed to read cached storage peer: {}", e))
+        })? {
+            if let Ok(packed) = PackedChat::from_bytes(&bytes) {
+                debug!("Reusing cached storage peer '{}' from session", target.name);
+                return Ok(PeerRef::from(packed));
+            }
+            warn!("Cached storage peer in session was unreadable, re-resolving");
+        }
+
+        let username = target.destination.trim_start_matches('@');
+        let chat = client
+            .resolve_username(username)
+            .await
+            .map_err(|e| {
+                Error::TelegramClient(format!(
+                    "Failed to resolve storage target '{}': {}",
+                    target.name, e
+                ))
+            })?
+            .ok_or_else(|| {
+                Error::TelegramClient(format!(
+                    "Storage target '{}' destination '{}' could not be found",
+                    target.name, target.destination
+                ))
+            })?;
+        let packed = chat.pack();
+
+        session
+            .set_value(STORAGE_PEER_SESSION_KEY, &packed.to_bytes())
+            .map_err(|e| Error::TelegramClient(format!("Failed to cache storage peer: {}", e)))?;
+
+        info!("Storing chunks in target '{}' ({})", target.name, target.destination);
+        Ok(PeerRef::from(packed))
+    }
+
     /// Check if authorized
     pub async fn is_authorized(&self) -> Result<bool> {
         let state = self.client_state.read().await;
@@ -225,23 +620,7 @@ impl TelegramBackend {
         Ok(())
     }
 
-    /// Get PeerRef for "Saved Messages" (self)
-    #[allow(dead_code)]
-    async fn get_self_peer(&self) -> Result<PeerRef> {
-        let state = self.client_state.read().await;
-        let client_state = state.as_ref().ok_or_else(|| {
-            Error::TelegramClient("Not connected".to_string())
-        })?;
-
-        let me = client_state.client.get_me().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to get self: {}", e))
-        })?;
-
-        // Convert to PeerRef via the raw tl type
-        Ok(PeerRef::from(me.raw))
-    }
-
-    /// Upload a chunk to Saved Messages
+    /// Upload a chunk to the configured storage target
     pub async fn upload_chunk(&self, chunk_id: &str, data: &[u8]) -> Result<i32> {
         let _permit = self.upload_limiter.acquire().await;
 
@@ -259,6 +638,14 @@ impl TelegramBackend {
                     debug!("Chunk {} uploaded as message {}", chunk_id, msg_id);
                     return Ok(msg_id);
                 }
+                Err(Error::TelegramRateLimited { seconds }) => {
+                    warn!("Upload flood-waited, sleeping {}s as instructed by Telegram", seconds);
+                    tokio::time::sleep(Duration::from_secs(seconds as u64)).await;
+                }
+                Err(e) if !e.is_retryable() => {
+                    error!("Upload failed with non-retryable error: {}", e);
+                    return Err(e);
+                }
                 Err(e) => {
                     if let Some(delay) = backoff.next_delay() {
                         warn!("Upload failed, retrying in {:?}: {}", delay, e);
@@ -279,17 +666,14 @@ impl TelegramBackend {
             Error::TelegramClient("Not connected".to_string())
         })?;
 
-        let me = client_state.client.get_me().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to get self: {}", e))
-        })?;
-        let peer = PeerRef::from(me.raw);
+        let peer = client_state.storage_peer.clone();
 
         // Upload file from memory using upload_stream
         let mut cursor = Cursor::new(data);
         let uploaded = client_state.client
             .upload_stream(&mut cursor, data.len(), filename.to_string())
             .await
-            .map_err(|e| Error::TelegramUpload(format!("Failed to upload file: {}", e)))?;
+            .map_err(|e| classify_rpc_error(e, |s| Error::TelegramUpload(format!("Failed to upload file: {}", s))))?;
 
         let message = InputMessage::new()
             .document(uploaded);
@@ -297,9 +681,24 @@ impl TelegramBackend {
         let sent = client_state.client
             .send_message(peer, message)
             .await
-            .map_err(|e| Error::TelegramUpload(format!("Failed to send message: {}", e)))?;
+            .map_err(|e| classify_rpc_error(e, |s| Error::TelegramUpload(format!("Failed to send message: {}", s))))?;
+
+        let message_id = sent.id();
+        drop(state);
+
+        if let Some(index) = self.chunk_index().await {
+            let entry = TelegramMessage {
+                id: message_id,
+                filename: Some(filename.to_string()),
+                size: data.len() as u64,
+                date: sent.date().timestamp(),
+            };
+            if let Err(e) = index.upsert(&entry) {
+                warn!("Failed to update chunk index for '{}': {}", filename, e);
+            }
+        }
 
-        Ok(sent.id())
+        Ok(message_id)
     }
 
     /// Download a chunk by message ID
@@ -319,6 +718,14 @@ impl TelegramBackend {
                     debug!("Downloaded {} bytes from message {}", data.len(), message_id);
                     return Ok(data);
                 }
+                Err(Error::TelegramRateLimited { seconds }) => {
+                    warn!("Download flood-waited, sleeping {}s as instructed by Telegram", seconds);
+                    tokio::time::sleep(Duration::from_secs(seconds as u64)).await;
+                }
+                Err(e) if !e.is_retryable() => {
+                    error!("Download failed with non-retryable error: {}", e);
+                    return Err(e);
+                }
                 Err(e) => {
                     if let Some(delay) = backoff.next_delay() {
                         warn!("Download failed, retrying in {:?}: {}", delay, e);
@@ -339,16 +746,13 @@ impl TelegramBackend {
             Error::TelegramClient("Not connected".to_string())
         })?;
 
-        let me = client_state.client.get_me().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to get self: {}", e))
-        })?;
-        let peer = PeerRef::from(me.raw);
+        let peer = client_state.storage_peer.clone();
 
         // Get the message
         let messages = client_state.client
             .get_messages_by_id(peer, &[message_id])
             .await
-            .map_err(|e| Error::TelegramDownload(format!("Failed to get message: {}", e)))?;
+            .map_err(|e| classify_rpc_error(e, |s| Error::TelegramDownload(format!("Failed to get message: {}", s))))?;
 
         let message = messages.into_iter().next().flatten().ok_or_else(|| {
             Error::TelegramDownload(format!("Message {} not found", message_id))
@@ -358,12 +762,18 @@ impl TelegramBackend {
             Error::TelegramDownload(format!("Message {} has no media", message_id))
         })?;
 
+        if let grammers_client::types::Media::Document(doc) = &media {
+            if doc.size() as u64 > self.config.download_segment_size {
+                return self.download_segmented(&client_state.client, doc).await;
+            }
+        }
+
         // Download to memory
         let mut data = Vec::new();
         let mut download = client_state.client.iter_download(&media);
 
         while let Some(chunk) = download.next().await.map_err(|e| {
-            Error::TelegramDownload(format!("Failed to download chunk: {}", e))
+            classify_rpc_error(e, |s| Error::TelegramDownload(format!("Failed to download chunk: {}", s)))
         })? {
             data.extend_from_slice(&chunk);
         }
@@ -371,6 +781,70 @@ impl TelegramBackend {
         Ok(data)
     }
 
+    /// Fetch a large document's bytes as concurrent `upload.getFile` range
+    /// requests instead of one sequential stream, so a multi-megabyte
+    /// chunk can put every connection in the `SenderPool` to work instead
+    /// of sitting on just one. Only called for documents bigger than
+    /// `download_segment_size`; smaller ones take the sequential
+    /// `iter_download` path in `do_download` above.
+    async fn download_segmented(
+        &self,
+        client: &Client,
+        doc: &grammers_client::types::Document,
+    ) -> Result<Vec<u8>> {
+        let total_size = doc.size() as u64;
+        let segment_size = self.config.download_segment_size.max(4096);
+        let location = doc.input_location();
+
+        let segment_count = total_size.div_ceil(segment_size) as usize;
+        let mut offsets = (0..segment_count).map(|i| i as u64 * segment_size);
+        let parallelism = self.config.download_segment_parallelism.max(1);
+
+        // `download_chunk` already holds a `download_limiter` permit for
+        // the whole call; concurrency here is bounded purely by
+        // `download_segment_parallelism` so it doesn't compete with that
+        // permit for the same budget.
+        let mut segments: Vec<Option<Vec<u8>>> = vec![None; segment_count];
+        let mut tasks = FuturesUnordered::new();
+
+        for offset in offsets.by_ref().take(parallelism) {
+            tasks.push(fetch_segment(client, location.clone(), offset, segment_size));
+        }
+
+        while let Some(result) = tasks.next().await {
+            let (offset, bytes) = result.map_err(|e| {
+                classify_rpc_error(e, |s| Error::TelegramDownload(format!("Failed to download segment: {}", s)))
+            })?;
+            segments[(offset / segment_size) as usize] = Some(bytes);
+
+            if let Some(next_offset) = offsets.next() {
+                tasks.push(fetch_segment(client, location.clone(), next_offset, segment_size));
+            }
+        }
+
+        let mut data = Vec::with_capacity(total_size as usize);
+        for segment in segments {
+            data.extend(segment.ok_or_else(|| {
+                Error::TelegramDownload("Segmented download left a gap".to_string())
+            })?);
+        }
+
+        if data.len() as u64 != total_size {
+            return Err(Error::TelegramDownload(format!(
+                "Segmented download size mismatch: expected {} bytes, got {}",
+                total_size,
+                data.len()
+            )));
+        }
+
+        debug!(
+            "Segmented download of {} bytes across {} ranges (parallelism {})",
+            total_size, segment_count, parallelism
+        );
+
+        Ok(data)
+    }
+
     /// Delete a message by ID
     pub async fn delete_message(&self, message_id: i32) -> Result<()> {
         let state = self.client_state.read().await;
@@ -378,37 +852,63 @@ impl TelegramBackend {
             Error::TelegramClient("Not connected".to_string())
         })?;
 
-        let me = client_state.client.get_me().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to get self: {}", e))
-        })?;
-        let peer = PeerRef::from(me.raw);
+        let peer = client_state.storage_peer.clone();
 
         client_state.client
             .delete_messages(peer, &[message_id])
             .await
-            .map_err(|e| Error::TelegramClient(format!("Failed to delete message: {}", e)))?;
+            .map_err(|e| classify_rpc_error(e, |s| Error::TelegramClient(format!("Failed to delete message: {}", s))))?;
+
+        drop(state);
+        if let Some(index) = self.chunk_index().await {
+            if let Err(e) = index.remove_by_message_id(message_id) {
+                warn!("Failed to remove message {} from chunk index: {}", message_id, e);
+            }
+        }
 
         debug!("Deleted message {}", message_id);
         Ok(())
     }
 
-    /// List all chunk messages in Saved Messages
+    /// List all chunk messages in the configured storage target, served
+    /// from the local chunk index when one is open (the common case once
+    /// `connect()` has run) instead of a full remote history scan.
     pub async fn list_chunks(&self) -> Result<Vec<TelegramMessage>> {
+        if let Some(index) = self.chunk_index().await {
+            return index.list();
+        }
+        self.scan_remote_messages().await
+    }
+
+    /// Rebuild the local chunk index from a full remote history scan.
+    /// Repairs drift from out-of-band changes (e.g. chunks uploaded or
+    /// deleted from another machine, or a corrupted/deleted index file).
+    pub async fn resync(&self) -> Result<()> {
+        let messages = self.scan_remote_messages().await?;
+        if let Some(index) = self.chunk_index().await {
+            index.replace_all(&messages)?;
+            info!("Resynced chunk index: {} entries", messages.len());
+        }
+        Ok(())
+    }
+
+    /// Walk the entire remote message history in the configured storage
+    /// target, collecting every chunk/metadata document. O(n) in the
+    /// number of messages - only meant to be used to populate/repair the
+    /// local chunk index, not on every `list_chunks` call.
+    async fn scan_remote_messages(&self) -> Result<Vec<TelegramMessage>> {
         let state = self.client_state.read().await;
         let client_state = state.as_ref().ok_or_else(|| {
             Error::TelegramClient("Not connected".to_string())
         })?;
 
-        let me = client_state.client.get_me().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to get self: {}", e))
-        })?;
-        let peer = PeerRef::from(me.raw);
+        let peer = client_state.storage_peer.clone();
 
         let mut messages = Vec::new();
         let mut iter = client_state.client.iter_messages(peer);
 
         while let Some(msg) = iter.next().await.map_err(|e| {
-            Error::TelegramClient(format!("Failed to iterate messages: {}", e))
+            classify_rpc_error(e, |s| Error::TelegramClient(format!("Failed to iterate messages: {}", s)))
         })? {
             if let Some(media) = msg.media() {
                 // Check if it's a document with our prefix
@@ -429,14 +929,53 @@ impl TelegramBackend {
         Ok(messages)
     }
 
-    /// Upload metadata to Saved Messages
+    /// Upload metadata to the configured storage target
     pub async fn upload_metadata(&self, name: &str, data: &[u8]) -> Result<i32> {
         let filename = format!("{}{}", METADATA_FILE_PREFIX, name);
         self.do_upload(&filename, data).await
     }
 
+    /// Send a one-off text message to `destination` - `"me"` for Saved
+    /// Messages, or an `@username` for a channel/chat. Used for crash
+    /// reports and other out-of-band sends outside the chunk upload path.
+    pub async fn send_text(&self, destination: &str, text: &str) -> Result<i32> {
+        let state = self.client_state.read().await;
+        let client_state = state.as_ref().ok_or_else(|| {
+            Error::TelegramClient("Not connected".to_string())
+        })?;
+
+        let peer = if destination == "me" {
+            client_state.self_peer.clone()
+        } else {
+            let username = destination.trim_start_matches('@');
+            let chat = client_state
+                .client
+                .resolve_username(username)
+                .await
+                .map_err(|e| {
+                    Error::TelegramClient(format!("Failed to resolve {}: {}", destination, e))
+                })?
+                .ok_or_else(|| {
+                    Error::TelegramClient(format!("Unknown destination: {}", destination))
+                })?;
+            PeerRef::from(chat.pack())
+        };
+
+        let message = InputMessage::text(text.to_string());
+        let sent = client_state.client
+            .send_message(peer, message)
+            .await
+            .map_err(|e| Error::TelegramUpload(format!("Failed to send message: {}", e)))?;
+
+        Ok(sent.id())
+    }
+
     /// Disconnect from Telegram
     pub async fn disconnect(&self) {
+        if let Some(task) = self.keepalive_task.write().await.take() {
+            task.abort();
+        }
+
         let mut state = self.client_state.write().await;
         if let Some(client_state) = state.take() {
             client_state.pool_handle.quit();
@@ -0,0 +1,110 @@
+//! Self-update support.
+//!
+//! When `UpdaterConfig::enabled` is set, `tgcryptfs update` checks
+//! `check_url` for a newer release on the configured channel, downloads
+//! its binary, verifies it against the published SHA-256 checksum, and
+//! atomically swaps it in for the currently running executable. This
+//! lets embedded-credential release builds stay current without relying
+//! on a package manager.
+
+use crate::config::UpdaterConfig;
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// Release metadata served at `UpdaterConfig::check_url`.
+#[derive(Debug, Deserialize)]
+pub struct ReleaseInfo {
+    /// Released version, e.g. "0.4.0"
+    pub version: String,
+
+    /// URL of the release binary for the running platform
+    pub binary_url: String,
+
+    /// Hex-encoded SHA-256 checksum of the binary at `binary_url`
+    pub sha256: String,
+}
+
+/// Fetch the latest `ReleaseInfo` for `config.channel` from
+/// `config.check_url`.
+pub async fn check_for_update(config: &UpdaterConfig) -> Result<ReleaseInfo> {
+    if config.check_url.is_empty() {
+        return Err(Error::InvalidConfig(
+            "updater.check_url is not configured".to_string(),
+        ));
+    }
+
+    let url = format!("{}?channel={}", config.check_url, config.channel.as_str());
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to check for updates: {}", e)))?;
+
+    response
+        .json::<ReleaseInfo>()
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to parse release info: {}", e)))
+}
+
+/// Download `release`'s binary, verify it against `release.sha256`, and
+/// atomically replace the currently running executable with it.
+pub async fn apply_update(release: &ReleaseInfo) -> Result<()> {
+    let response = reqwest::get(&release.binary_url)
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to download update: {}", e)))?;
+
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to read downloaded update: {}", e)))?;
+
+    verify_checksum(&bytes, &release.sha256)?;
+
+    let current_exe = std::env::current_exe()
+        .map_err(|e| Error::Internal(format!("Failed to locate running binary: {}", e)))?;
+
+    let staged_path = current_exe.with_extension("new");
+    write_staged_binary(&staged_path, &bytes)?;
+
+    // Rename is atomic within a filesystem, so there's no window where
+    // `current_exe` is missing or only partially written.
+    std::fs::rename(&staged_path, &current_exe)
+        .map_err(|e| Error::Internal(format!("Failed to swap in updated binary: {}", e)))?;
+
+    Ok(())
+}
+
+/// Compare the SHA-256 of `bytes` against `expected_hex`, case-insensitively.
+fn verify_checksum(bytes: &[u8], expected_hex: &str) -> Result<()> {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    let actual_hex = hex::encode(hasher.finalize());
+
+    if !actual_hex.eq_ignore_ascii_case(expected_hex) {
+        return Err(Error::InvalidConfig(format!(
+            "Checksum mismatch: expected {}, got {}",
+            expected_hex, actual_hex
+        )));
+    }
+
+    Ok(())
+}
+
+/// Write `bytes` to `staged_path`, marking it executable on Unix.
+fn write_staged_binary(staged_path: &std::path::Path, bytes: &[u8]) -> Result<()> {
+    let mut staged = std::fs::File::create(staged_path)
+        .map_err(|e| Error::Internal(format!("Failed to create staged binary: {}", e)))?;
+    staged
+        .write_all(bytes)
+        .map_err(|e| Error::Internal(format!("Failed to write staged binary: {}", e)))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(staged_path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| Error::Internal(format!("Failed to make staged binary executable: {}", e)))?;
+    }
+
+    Ok(())
+}